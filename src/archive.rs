@@ -0,0 +1,98 @@
+//! Keeping every run's results, for `--archive`.
+//!
+//! Each run is written to `DIR/<command hash>/<timestamp>.json`, the hash
+//! being of the command line and the timestamp the run's end in UTC, so
+//! the runs of a command are in one directory in the order they finished.
+//! The files hold the full results, the timeline too when one was
+//! collected, as `--json` prints them.
+
+use crate::fingerprint;
+use crate::types::{MonitorResult, Result, Timestamp};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Writes `result` to the archive in `dir`, creating the directories
+/// needed.
+///
+/// # Returns
+/// * The path of the file written
+///
+/// # Errors
+/// * `PeakMemError::Io` - The directory or file couldn't be created
+/// * `PeakMemError::Json` - The result couldn't be serialized
+pub fn archive(dir: &Path, result: &MonitorResult) -> Result<PathBuf> {
+    let dir = dir.join(command_hash(&result.command));
+    std::fs::create_dir_all(&dir)?;
+    let json = serde_json::to_string_pretty(result)?;
+
+    // Two runs ending in the same microsecond are told apart
+    let stem = file_stem(result.timestamp);
+    let mut attempt = 0;
+    loop {
+        let path = match attempt {
+            0 => dir.join(format!("{stem}.json")),
+            n => dir.join(format!("{stem}-{n}.json")),
+        };
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(mut file) => {
+                file.write_all(json.as_bytes())?;
+                return Ok(path);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Names the directory of a command's runs.
+pub fn command_hash(command: &str) -> String {
+    fingerprint::hex(fingerprint::fnv1a(command.as_bytes()))
+}
+
+/// Names a run's file after its timestamp, in a form that sorts in time
+/// order and is valid on every filesystem, e.g. `20260101T093000.000000Z`.
+fn file_stem(timestamp: Timestamp) -> String {
+    let rfc3339 = timestamp.to_rfc3339();
+    let utc = rfc3339.trim_end_matches("+00:00");
+    format!("{}Z", utc.replace(['-', ':'], ""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = MonitorResult {
+            timestamp: Timestamp::parse_rfc3339("2026-01-01T09:30:00Z").unwrap(),
+            ..MonitorResult::fixture("make all", 1024)
+        };
+
+        let path = archive(dir.path(), &first).unwrap();
+        let expected = dir
+            .path()
+            .join(command_hash("make all"))
+            .join("20260101T093000.000000Z.json");
+        assert_eq!(path, expected);
+        let saved: MonitorResult =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved.peak_rss_bytes, 1024);
+
+        // The same moment again doesn't overwrite the first
+        let again = archive(dir.path(), &first).unwrap();
+        assert_eq!(again.file_name().unwrap(), "20260101T093000.000000Z-1.json");
+
+        let other = MonitorResult {
+            command: "make test".to_string(),
+            ..first.clone()
+        };
+        let other = archive(dir.path(), &other).unwrap();
+        assert_ne!(other.parent(), path.parent());
+        assert_eq!(command_hash("make all").len(), 16);
+    }
+}
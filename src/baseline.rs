@@ -2,13 +2,41 @@
 //!
 //! This module provides functionality to save memory usage snapshots as
 //! baselines and compare new measurements against them to detect regressions.
+//!
+//! A baseline saved with `--append-to-baseline` accumulates runs instead:
+//! it keeps the most recent ones, and its values are their means. One
+//! saved from `--runs` keeps each of its runs the same way. Once it holds
+//! two runs or more that vary, peak RSS only regresses when it is also more
+//! standard deviations above their mean than `--noise-sigmas` (by default
+//! [`NOISE_SIGMAS`]), as the noise of the machine trips a percentage as
+//! readily as the code does. The percentage stays a floor, so that runs
+//! which barely vary don't turn every small increase into a regression.
 
-use crate::types::{MonitorResult, PeakMemError, Result, Timestamp};
+use crate::fingerprint::{EnvFingerprint, FingerprintChange};
+use crate::git::GitInfo;
+use crate::monitor::pressure::MemoryPressure;
+use crate::monitor::thermal::ThermalContext;
+use crate::types::{MemoryUsage, MonitorResult, PeakMemError, Result, Timestamp};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::env;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// Runs an accumulated baseline keeps unless `--baseline-window` says
+/// otherwise.
+pub const DEFAULT_WINDOW: usize = 20;
+
+/// Saves of a baseline `--keep-history` keeps.
+pub const HISTORY_LIMIT: usize = 100;
+
+/// Saves `--trend` shows unless told otherwise.
+pub const DEFAULT_TREND_RUNS: usize = 10;
+
+/// Standard deviations of an accumulated baseline's peak RSS above its
+/// mean that count as more than noise, unless `--noise-sigmas` says
+/// otherwise.
+pub const NOISE_SIGMAS: f64 = 3.0;
 
 /// Represents a saved baseline measurement for comparison.
 ///
@@ -28,10 +56,124 @@ pub struct Baseline {
     pub peak_vsz_bytes: u64,
     /// Duration of execution in milliseconds.
     pub duration_ms: u64,
+    /// Total minor page faults of the process tree.
+    #[serde(default)]
+    pub minor_faults: u64,
+    /// Total major page faults of the process tree.
+    #[serde(default)]
+    pub major_faults: u64,
+    /// User-mode CPU time of the process tree in milliseconds.
+    #[serde(default)]
+    pub user_time_ms: u64,
+    /// Kernel-mode CPU time of the process tree in milliseconds.
+    #[serde(default)]
+    pub system_time_ms: u64,
+    /// Environment the baseline was recorded in, if fingerprinted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<EnvFingerprint>,
+    /// Highest system memory pressure seen while recording, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peak_memory_pressure: Option<MemoryPressure>,
+    /// CPU frequency, throttling and energy while recording, if recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thermal: Option<ThermalContext>,
+    /// Peak GPU memory in bytes, if GPU tracking was enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peak_gpu_bytes: Option<u64>,
+    /// The timeline, if one was recorded with `--timeline`, which
+    /// `--report` draws next to that of a run compared against it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeline: Option<Vec<MemoryUsage>>,
+    /// The runs accumulated by `--append-to-baseline`, oldest first; the
+    /// values above are their means.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub runs: Vec<BaselineRun>,
+    /// Every save of the baseline kept by `--keep-history`, oldest first
+    /// and ending with this one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<BaselineRun>,
     /// Additional metadata (platform, architecture, etc.).
     pub metadata: HashMap<String, String>,
 }
 
+/// What `--list-baselines` shows of a baseline.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BaselineSummary {
+    pub name: String,
+    pub command: String,
+    pub peak_rss_bytes: u64,
+    pub duration_ms: u64,
+    /// Operating system it was recorded on, if known.
+    pub platform: Option<String>,
+    pub created_at: Timestamp,
+}
+
+/// What `--trend` shows of a baseline.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Trend {
+    pub name: String,
+    pub command: String,
+    /// Saves or runs kept in all, of which the most recent are shown.
+    pub total_runs: usize,
+    /// Oldest first.
+    pub runs: Vec<BaselineRun>,
+}
+
+/// One run of an accumulated baseline.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BaselineRun {
+    /// When the run was added.
+    pub recorded_at: Timestamp,
+    /// Peak RSS value in bytes.
+    pub peak_rss_bytes: u64,
+    /// Peak VSZ value in bytes.
+    pub peak_vsz_bytes: u64,
+    /// Duration of execution in milliseconds.
+    pub duration_ms: u64,
+    /// Total minor page faults of the process tree.
+    pub minor_faults: u64,
+    /// Total major page faults of the process tree.
+    pub major_faults: u64,
+    /// User-mode CPU time of the process tree in milliseconds.
+    pub user_time_ms: u64,
+    /// Kernel-mode CPU time of the process tree in milliseconds.
+    pub system_time_ms: u64,
+    /// Peak GPU memory in bytes, if GPU tracking was enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peak_gpu_bytes: Option<u64>,
+}
+
+impl From<&Baseline> for BaselineRun {
+    fn from(baseline: &Baseline) -> Self {
+        Self {
+            recorded_at: baseline.created_at,
+            peak_rss_bytes: baseline.peak_rss_bytes,
+            peak_vsz_bytes: baseline.peak_vsz_bytes,
+            duration_ms: baseline.duration_ms,
+            minor_faults: baseline.minor_faults,
+            major_faults: baseline.major_faults,
+            user_time_ms: baseline.user_time_ms,
+            system_time_ms: baseline.system_time_ms,
+            peak_gpu_bytes: baseline.peak_gpu_bytes,
+        }
+    }
+}
+
+/// How the peak RSS of an accumulated baseline's runs is distributed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RssDistribution {
+    /// Number of runs.
+    pub runs: usize,
+    /// Mean peak RSS in bytes.
+    pub mean_bytes: u64,
+    /// Sample standard deviation of the peak RSS in bytes.
+    pub stddev_bytes: u64,
+    /// Lowest peak RSS in bytes.
+    pub min_bytes: u64,
+    /// Highest peak RSS in bytes.
+    pub max_bytes: u64,
+}
+
 impl From<&MonitorResult> for Baseline {
     fn from(result: &MonitorResult) -> Self {
         let mut metadata = HashMap::new();
@@ -41,15 +183,238 @@ impl From<&MonitorResult> for Baseline {
         if let Some(pid) = result.main_pid {
             metadata.insert("main_pid".to_string(), pid.to_string());
         }
+        if let Some(git) = &result.git {
+            git.insert_into(&mut metadata);
+        }
+        metadata.extend(result.metadata.clone());
 
-        Self {
+        let mut baseline = Self {
             version: env!("CARGO_PKG_VERSION").to_string(),
             created_at: Timestamp::now(),
             command: result.command.clone(),
             peak_rss_bytes: result.peak_rss_bytes,
             peak_vsz_bytes: result.peak_vsz_bytes,
             duration_ms: result.duration_ms,
+            minor_faults: result.minor_faults,
+            major_faults: result.major_faults,
+            user_time_ms: result.user_time_ms,
+            system_time_ms: result.system_time_ms,
+            fingerprint: result.fingerprint.clone(),
+            peak_memory_pressure: result.peak_memory_pressure,
+            thermal: result.thermal,
+            peak_gpu_bytes: result.peak_gpu_bytes,
+            timeline: result.timeline.clone(),
+            runs: Vec::new(),
+            history: Vec::new(),
             metadata,
+        };
+        // Only peaks and duration are kept per run of --runs; the rest
+        // is that of the run reported
+        if result.runs.len() > 1 {
+            let reported = BaselineRun::from(&baseline);
+            baseline.runs = result
+                .runs
+                .iter()
+                .map(|run| BaselineRun {
+                    peak_rss_bytes: run.peak_rss_bytes,
+                    peak_vsz_bytes: run.peak_vsz_bytes,
+                    duration_ms: run.duration_ms,
+                    ..reported
+                })
+                .collect();
+            baseline.average_runs();
+        }
+        baseline
+    }
+}
+
+impl Baseline {
+    /// Returns the total (user + system) CPU time in milliseconds.
+    pub fn cpu_time_ms(&self) -> u64 {
+        self.user_time_ms + self.system_time_ms
+    }
+
+    /// Returns the platform the baseline was recorded on, as `os-arch`,
+    /// if recorded.
+    pub fn platform(&self) -> Option<String> {
+        Some(format!(
+            "{}-{}",
+            self.metadata.get("platform")?,
+            self.metadata.get("arch")?
+        ))
+    }
+
+    /// Returns the commit the baseline was measured at, if recorded.
+    pub fn git(&self) -> Option<GitInfo> {
+        GitInfo::from_metadata(&self.metadata)
+    }
+
+    /// Adds a run to a baseline's runs, keeping the most recent `window`.
+    ///
+    /// The baseline is that of the run, but with the means of the runs.
+    /// A baseline saved before it accumulated counts as one run, and a
+    /// result of `--runs` as each of its runs.
+    pub fn accumulate(previous: Option<Baseline>, result: &MonitorResult, window: usize) -> Self {
+        let mut baseline = Baseline::from(result);
+        // An earlier --keep-history is carried along
+        baseline.history = previous
+            .as_ref()
+            .map(|previous| previous.history.clone())
+            .unwrap_or_default();
+        let mut runs = match previous {
+            Some(previous) if previous.runs.is_empty() => vec![BaselineRun::from(&previous)],
+            Some(previous) => previous.runs,
+            None => Vec::new(),
+        };
+        if baseline.runs.is_empty() {
+            runs.push(BaselineRun::from(&baseline));
+        } else {
+            runs.append(&mut baseline.runs);
+        }
+        let excess = runs.len().saturating_sub(window.max(1));
+        runs.drain(..excess);
+        baseline.runs = runs;
+        baseline.average_runs();
+        baseline
+    }
+
+    /// Sets the baseline's values to the means of its runs.
+    fn average_runs(&mut self) {
+        let runs = &self.runs;
+        let mean = |value: fn(&BaselineRun) -> u64| {
+            (runs.iter().map(|run| value(run) as f64).sum::<f64>() / runs.len() as f64).round()
+                as u64
+        };
+        let gpu: Vec<u64> = runs.iter().filter_map(|run| run.peak_gpu_bytes).collect();
+        self.peak_rss_bytes = mean(|run| run.peak_rss_bytes);
+        self.peak_vsz_bytes = mean(|run| run.peak_vsz_bytes);
+        self.duration_ms = mean(|run| run.duration_ms);
+        self.minor_faults = mean(|run| run.minor_faults);
+        self.major_faults = mean(|run| run.major_faults);
+        self.user_time_ms = mean(|run| run.user_time_ms);
+        self.system_time_ms = mean(|run| run.system_time_ms);
+        self.peak_gpu_bytes = (!gpu.is_empty()).then(|| {
+            (gpu.iter().map(|&bytes| bytes as f64).sum::<f64>() / gpu.len() as f64).round() as u64
+        });
+    }
+
+    /// Replaces a saved baseline, adding it to the saves before it.
+    ///
+    /// A baseline saved before it kept a history counts as one save.
+    pub fn with_history(mut self, previous: Option<Baseline>) -> Self {
+        let mut history = match previous {
+            Some(previous) if previous.history.is_empty() => vec![BaselineRun::from(&previous)],
+            Some(previous) => previous.history,
+            None => Vec::new(),
+        };
+        history.push(BaselineRun::from(&self));
+        let excess = history.len().saturating_sub(HISTORY_LIMIT);
+        history.drain(..excess);
+        self.history = history;
+        self
+    }
+
+    /// The most recent `count` saves of the baseline, or of its
+    /// accumulated runs if it kept no history.
+    ///
+    /// # Returns
+    /// * `None` - Neither saves nor runs were kept
+    pub fn trend(&self, name: &str, count: usize) -> Option<Trend> {
+        let runs = if self.history.is_empty() {
+            &self.runs
+        } else {
+            &self.history
+        };
+        if runs.is_empty() {
+            return None;
+        }
+        Some(Trend {
+            name: name.to_string(),
+            command: self.command.clone(),
+            total_runs: runs.len(),
+            runs: runs[runs.len().saturating_sub(count)..].to_vec(),
+        })
+    }
+
+    /// Sums the baseline up for a list of baselines.
+    pub fn summary(&self, name: &str) -> BaselineSummary {
+        BaselineSummary {
+            name: name.to_string(),
+            command: self.command.clone(),
+            peak_rss_bytes: self.peak_rss_bytes,
+            duration_ms: self.duration_ms,
+            platform: self.metadata.get("platform").cloned(),
+            created_at: self.created_at,
+        }
+    }
+
+    /// How the peak RSS of the runs is distributed, once there are two
+    /// or more.
+    pub fn rss_distribution(&self) -> Option<RssDistribution> {
+        if self.runs.len() < 2 {
+            return None;
+        }
+        let peaks: Vec<f64> = self
+            .runs
+            .iter()
+            .map(|run| run.peak_rss_bytes as f64)
+            .collect();
+        let mean = peaks.iter().sum::<f64>() / peaks.len() as f64;
+        let variance =
+            peaks.iter().map(|peak| (peak - mean).powi(2)).sum::<f64>() / (peaks.len() - 1) as f64;
+        let rss = self.runs.iter().map(|run| run.peak_rss_bytes);
+        Some(RssDistribution {
+            runs: self.runs.len(),
+            mean_bytes: mean.round() as u64,
+            stddev_bytes: variance.sqrt().round() as u64,
+            min_bytes: rss.clone().min().unwrap_or(0),
+            max_bytes: rss.max().unwrap_or(0),
+        })
+    }
+}
+
+/// Percentage increases over a baseline that count as a regression.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegressionThresholds {
+    /// Peak RSS increase that triggers regression detection.
+    pub rss_percent: f64,
+    /// Major page fault increase that also triggers regression detection,
+    /// if set.
+    pub major_faults_percent: Option<f64>,
+    /// Total CPU time increase that also triggers regression detection, if
+    /// set.
+    pub cpu_time_percent: Option<f64>,
+    /// Peak GPU memory increase that also triggers regression detection,
+    /// if set.
+    pub gpu_percent: Option<f64>,
+    /// Memory differences smaller than this many bytes count as no change,
+    /// if set.
+    pub ignore_below_bytes: Option<u64>,
+    /// Standard deviations above the mean of a baseline's runs that count
+    /// as a peak RSS regression, instead of `rss_percent`, against runs
+    /// that vary.
+    pub noise_sigmas: f64,
+}
+
+impl RegressionThresholds {
+    /// Thresholds that only check peak RSS.
+    #[cfg(test)]
+    pub fn rss(rss_percent: f64) -> Self {
+        Self {
+            rss_percent,
+            major_faults_percent: None,
+            cpu_time_percent: None,
+            gpu_percent: None,
+            ignore_below_bytes: None,
+            noise_sigmas: NOISE_SIGMAS,
+        }
+    }
+
+    /// Rounds a memory difference to zero if it is below the floor.
+    fn round_memory_diff(&self, (diff, percent): (i64, f64)) -> (i64, f64) {
+        match self.ignore_below_bytes {
+            Some(floor) if diff.unsigned_abs() < floor => (0, 0.0),
+            _ => (diff, percent),
         }
     }
 }
@@ -76,8 +441,79 @@ pub struct ComparisonResult {
     pub duration_diff_ms: i64,
     /// Percentage change in duration.
     pub duration_diff_percent: f64,
-    /// Whether memory usage exceeded the regression threshold.
+    /// Difference in minor page faults.
+    #[serde(default)]
+    pub minor_faults_diff: i64,
+    /// Percentage change in minor page faults.
+    #[serde(default)]
+    pub minor_faults_diff_percent: f64,
+    /// Difference in major page faults.
+    #[serde(default)]
+    pub major_faults_diff: i64,
+    /// Percentage change in major page faults.
+    #[serde(default)]
+    pub major_faults_diff_percent: f64,
+    /// Difference in total CPU time milliseconds.
+    #[serde(default)]
+    pub cpu_time_diff_ms: i64,
+    /// Percentage change in total CPU time.
+    #[serde(default)]
+    pub cpu_time_diff_percent: f64,
+    /// Whether peak RSS exceeded the regression threshold.
+    #[serde(default)]
+    pub memory_regression_detected: bool,
+    /// Whether major page faults exceeded the fault regression threshold.
+    #[serde(default)]
+    pub fault_regression_detected: bool,
+    /// Whether CPU time exceeded the CPU regression threshold.
+    #[serde(default)]
+    pub cpu_regression_detected: bool,
+    /// Difference in peak GPU memory bytes, if both runs tracked it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gpu_diff_bytes: Option<i64>,
+    /// Percentage change in peak GPU memory, if both runs tracked it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gpu_diff_percent: Option<f64>,
+    /// Whether peak GPU memory exceeded the GPU regression threshold.
+    #[serde(default)]
+    pub gpu_regression_detected: bool,
+    /// Whether any checked metric exceeded its regression threshold.
     pub regression_detected: bool,
+    /// Environment differences from the baseline, if both runs were
+    /// fingerprinted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint_changes: Option<Vec<FingerprintChange>>,
+    /// Whether the baseline was fingerprinted with another key, such as on
+    /// another machine, so that changed values can't be told.
+    #[serde(default)]
+    pub fingerprint_key_changed: bool,
+    /// Whether either run saw high system memory pressure, making the
+    /// comparison less trustworthy.
+    #[serde(default)]
+    pub noisy_environment: bool,
+    /// Whether the CPUs were throttled during either run, making the
+    /// comparison of duration and CPU time less trustworthy.
+    #[serde(default)]
+    pub throttled: bool,
+    /// Memory differences below this many bytes were rounded to zero, if
+    /// set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ignored_below_bytes: Option<u64>,
+    /// The peak RSS of the baseline's runs, if it accumulated two or more.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rss_distribution: Option<RssDistribution>,
+    /// Standard deviations of the runs' peak RSS that the current one is
+    /// above their mean, if they vary at all.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rss_z_score: Option<f64>,
+    /// The z-score above which peak RSS regressed, if there was one to
+    /// decide by.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub noise_sigmas: Option<f64>,
+    /// The platform the baseline was recorded on, if other than this one,
+    /// in which case its numbers measure the platform as much as the code.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub other_platform: Option<String>,
 }
 
 impl ComparisonResult {
@@ -86,31 +522,81 @@ impl ComparisonResult {
     /// # Arguments
     /// * `baseline` - The baseline to compare against
     /// * `current` - Current measurement results
-    /// * `threshold_percent` - Percentage increase that triggers regression
-    ///   detection
-    pub fn new(baseline: Baseline, current: MonitorResult, threshold_percent: f64) -> Self {
-        let rss_diff_bytes = current.peak_rss_bytes as i64 - baseline.peak_rss_bytes as i64;
-        let rss_diff_percent = if baseline.peak_rss_bytes > 0 {
-            (rss_diff_bytes as f64 / baseline.peak_rss_bytes as f64) * 100.0
-        } else {
-            0.0
-        };
+    /// * `thresholds` - Percentage increases that trigger regression detection
+    pub fn new(
+        baseline: Baseline,
+        current: MonitorResult,
+        thresholds: RegressionThresholds,
+    ) -> Self {
+        // Small memory differences are mostly ASLR and allocator jitter
+        let (rss_diff_bytes, rss_diff_percent) = thresholds.round_memory_diff(diff_with_percent(
+            current.peak_rss_bytes,
+            baseline.peak_rss_bytes,
+        ));
+        let (vsz_diff_bytes, vsz_diff_percent) = thresholds.round_memory_diff(diff_with_percent(
+            current.peak_vsz_bytes,
+            baseline.peak_vsz_bytes,
+        ));
+        let (duration_diff_ms, duration_diff_percent) =
+            diff_with_percent(current.duration_ms, baseline.duration_ms);
+        let (minor_faults_diff, minor_faults_diff_percent) =
+            diff_with_percent(current.minor_faults, baseline.minor_faults);
+        let (major_faults_diff, major_faults_diff_percent) =
+            diff_with_percent(current.major_faults, baseline.major_faults);
 
-        let vsz_diff_bytes = current.peak_vsz_bytes as i64 - baseline.peak_vsz_bytes as i64;
-        let vsz_diff_percent = if baseline.peak_vsz_bytes > 0 {
-            (vsz_diff_bytes as f64 / baseline.peak_vsz_bytes as f64) * 100.0
-        } else {
-            0.0
+        let (cpu_time_diff_ms, cpu_time_diff_percent) =
+            diff_with_percent(current.cpu_time_ms(), baseline.cpu_time_ms());
+
+        let gpu_diff = match (current.peak_gpu_bytes, baseline.peak_gpu_bytes) {
+            (Some(current), Some(baseline)) => {
+                Some(thresholds.round_memory_diff(diff_with_percent(current, baseline)))
+            }
+            _ => None,
         };
 
-        let duration_diff_ms = current.duration_ms as i64 - baseline.duration_ms as i64;
-        let duration_diff_percent = if baseline.duration_ms > 0 {
-            (duration_diff_ms as f64 / baseline.duration_ms as f64) * 100.0
-        } else {
-            0.0
+        // Against runs that vary, an increase must also be beyond their
+        // noise
+        let rss_distribution = baseline.rss_distribution();
+        let rss_z_score = rss_distribution
+            .filter(|distribution| distribution.stddev_bytes > 0)
+            .map(|distribution| {
+                (current.peak_rss_bytes as f64 - distribution.mean_bytes as f64)
+                    / distribution.stddev_bytes as f64
+            });
+        let memory_regression_detected = rss_diff_percent > thresholds.rss_percent
+            && rss_z_score.is_none_or(|z| z > thresholds.noise_sigmas);
+        let fault_regression_detected = thresholds
+            .major_faults_percent
+            .is_some_and(|threshold| major_faults_diff_percent > threshold);
+        let cpu_regression_detected = thresholds
+            .cpu_time_percent
+            .is_some_and(|threshold| cpu_time_diff_percent > threshold);
+        let gpu_regression_detected = match (gpu_diff, thresholds.gpu_percent) {
+            (Some((_, percent)), Some(threshold)) => percent > threshold,
+            _ => false,
         };
+        let regression_detected = memory_regression_detected
+            || fault_regression_detected
+            || cpu_regression_detected
+            || gpu_regression_detected;
+
+        let fingerprints = current
+            .fingerprint
+            .as_ref()
+            .zip(baseline.fingerprint.as_ref());
+        let fingerprint_changes = fingerprints.map(|(current, baseline)| current.diff(baseline));
+        let fingerprint_key_changed =
+            fingerprints.is_some_and(|(current, baseline)| current.key_id != baseline.key_id);
 
-        let regression_detected = rss_diff_percent > threshold_percent;
+        let noisy_environment = [current.peak_memory_pressure, baseline.peak_memory_pressure]
+            .iter()
+            .flatten()
+            .any(MemoryPressure::is_high);
+        let throttled = [current.thermal, baseline.thermal]
+            .iter()
+            .flatten()
+            .any(ThermalContext::throttled);
+        let other_platform = baseline.platform().filter(|other| *other != platform());
 
         Self {
             baseline,
@@ -121,17 +607,70 @@ impl ComparisonResult {
             vsz_diff_percent,
             duration_diff_ms,
             duration_diff_percent,
+            minor_faults_diff,
+            minor_faults_diff_percent,
+            major_faults_diff,
+            major_faults_diff_percent,
+            cpu_time_diff_ms,
+            cpu_time_diff_percent,
+            memory_regression_detected,
+            fault_regression_detected,
+            cpu_regression_detected,
+            gpu_diff_bytes: gpu_diff.map(|(bytes, _)| bytes),
+            gpu_diff_percent: gpu_diff.map(|(_, percent)| percent),
+            gpu_regression_detected,
             regression_detected,
+            fingerprint_changes,
+            fingerprint_key_changed,
+            noisy_environment,
+            throttled,
+            ignored_below_bytes: thresholds.ignore_below_bytes,
+            rss_distribution,
+            rss_z_score,
+            noise_sigmas: rss_z_score.map(|_| thresholds.noise_sigmas),
+            other_platform,
         }
     }
 }
 
+impl ComparisonResult {
+    /// Whether the current run used less peak RSS than the baseline
+    /// without regressing on any other checked metric.
+    pub fn is_improvement(&self) -> bool {
+        self.rss_diff_bytes < 0 && !self.regression_detected
+    }
+
+    /// Whether the current run used at most `tolerance_percent` more peak
+    /// RSS than the baseline, or less, without regressing on any checked
+    /// metric.
+    pub fn is_within(&self, tolerance_percent: f64) -> bool {
+        self.rss_diff_percent <= tolerance_percent && !self.regression_detected
+    }
+}
+
+/// Returns the signed difference `current - baseline` and its percentage
+/// of the baseline (0.0 when the baseline is zero).
+fn diff_with_percent(current: u64, baseline: u64) -> (i64, f64) {
+    let diff = current as i64 - baseline as i64;
+    let percent = if baseline > 0 {
+        (diff as f64 / baseline as f64) * 100.0
+    } else {
+        0.0
+    };
+    (diff, percent)
+}
+
 /// Manages baseline storage and retrieval.
 ///
 /// Handles saving baselines to disk, loading them for comparison,
 /// and managing the baseline directory.
 pub struct BaselineManager {
     baselines_dir: PathBuf,
+    /// Whether names are kept apart per platform, and comparing against
+    /// another platform's baseline is an error.
+    platform_scoped: bool,
+    /// The file every name stands for, with `--baseline-file`.
+    file: Option<PathBuf>,
 }
 
 impl BaselineManager {
@@ -146,7 +685,28 @@ impl BaselineManager {
         if !baselines_dir.exists() {
             fs::create_dir_all(&baselines_dir)?;
         }
-        Ok(Self { baselines_dir })
+        Ok(Self {
+            baselines_dir,
+            platform_scoped: true,
+            file: None,
+        })
+    }
+
+    /// Keeps the baseline in `file` instead of the baseline directory,
+    /// whatever its name, so that a project can commit it next to the
+    /// code it measures.
+    pub fn with_file(mut self, file: Option<PathBuf>) -> Self {
+        self.file = file;
+        self
+    }
+
+    /// Sets whether baselines are kept per platform, as
+    /// `NAME.os-arch.json`, so that one saved on macOS isn't compared
+    /// against on Linux (default: true). Unscoped baselines, such as those
+    /// saved before scoping, are still found by their plain name.
+    pub fn with_platform_scope(mut self, scoped: bool) -> Self {
+        self.platform_scoped = scoped;
+        self
     }
 
     /// Returns the default baseline directory path.
@@ -196,26 +756,123 @@ impl BaselineManager {
     /// # Returns
     /// * Path to the saved baseline file
     pub fn save_baseline(&self, name: &str, result: &MonitorResult) -> Result<PathBuf> {
-        let baseline = Baseline::from(result);
-        let filename = format!("{}.json", sanitize_filename(name)?);
-        let path = self.baselines_dir.join(&filename);
+        self.write(name, &Baseline::from(result))
+    }
 
-        let json = serde_json::to_string_pretty(&baseline)?;
+    /// Saves a monitoring result as a baseline, keeping the saves before
+    /// it in its history.
+    ///
+    /// # Returns
+    /// * Path to the saved baseline file and the number of saves it holds
+    pub fn save_with_history(
+        &self,
+        name: &str,
+        result: &MonitorResult,
+    ) -> Result<(PathBuf, usize)> {
+        let previous = if self.exists(name) {
+            Some(self.load_baseline(name)?)
+        } else {
+            None
+        };
+        let baseline = Baseline::from(result).with_history(previous);
+        Ok((self.write(name, &baseline)?, baseline.history.len()))
+    }
 
-        fs::write(&path, json)?;
+    /// Adds a monitoring result to a baseline's runs, creating the
+    /// baseline if there is none.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the baseline (will be sanitized)
+    /// * `result` - Monitoring results to add
+    /// * `window` - Most recent runs to keep
+    ///
+    /// # Returns
+    /// * Path to the saved baseline file and the number of runs it holds
+    pub fn append_to_baseline(
+        &self,
+        name: &str,
+        result: &MonitorResult,
+        window: usize,
+    ) -> Result<(PathBuf, usize)> {
+        let previous = if self.exists(name) {
+            Some(self.load_baseline(name)?)
+        } else {
+            None
+        };
+        let baseline = Baseline::accumulate(previous, result, window);
+        Ok((self.write(name, &baseline)?, baseline.runs.len()))
+    }
+
+    /// Writes a baseline under a name, refusing to replace a file that
+    /// isn't one.
+    ///
+    /// # Returns
+    /// * Path to the baseline file
+    fn write(&self, name: &str, baseline: &Baseline) -> Result<PathBuf> {
+        let path = self.baseline_path(name)?;
+        ensure_baseline(&path)?;
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(baseline)?)?;
         Ok(path)
     }
 
     pub fn load_baseline(&self, name: &str) -> Result<Baseline> {
-        let filename = format!("{}.json", sanitize_filename(name)?);
-        let path = self.baselines_dir.join(&filename);
-
-        let json = fs::read_to_string(&path)?;
+        let json = fs::read_to_string(self.saved_path(name)?)?;
         let baseline: Baseline = serde_json::from_str(&json)?;
 
         Ok(baseline)
     }
 
+    /// Whether a baseline with this name has been saved.
+    pub fn exists(&self, name: &str) -> bool {
+        self.saved_path(name)
+            .map(|path| path.exists())
+            .unwrap_or(false)
+    }
+
+    /// Where the baseline of a name is kept: the `--baseline-file` if
+    /// given, or else the name sanitized into a file name in the baseline
+    /// directory, scoped to this platform unless turned off.
+    ///
+    /// # Errors
+    /// * `PeakMemError::InvalidArgument` - An invalid name, or one ending in
+    ///   `.json`, which would look like a path
+    fn baseline_path(&self, name: &str) -> Result<PathBuf> {
+        if let Some(file) = &self.file {
+            return Ok(file.clone());
+        }
+        if name.ends_with(".json") {
+            return Err(PeakMemError::InvalidArgument(format!(
+                "Invalid baseline name '{name}': names can't end in .json; \
+                 use --baseline-file PATH to keep a baseline in a file of its own"
+            )));
+        }
+        let name = sanitize_filename(name)?;
+        Ok(self.baselines_dir.join(if self.platform_scoped {
+            format!("{name}.{}.json", platform())
+        } else {
+            format!("{name}.json")
+        }))
+    }
+
+    /// Where a saved baseline of a name is read from: its
+    /// [`Self::baseline_path`], or an unscoped file by that name.
+    fn saved_path(&self, name: &str) -> Result<PathBuf> {
+        let path = self.baseline_path(name)?;
+        if path.exists() || !self.platform_scoped || self.file.is_some() {
+            return Ok(path);
+        }
+        let unscoped = self
+            .baselines_dir
+            .join(format!("{}.json", sanitize_filename(name)?));
+        Ok(if unscoped.exists() { unscoped } else { path })
+    }
+
     pub fn list_baselines(&self) -> Result<Vec<String>> {
         let mut baselines = Vec::new();
 
@@ -225,36 +882,254 @@ impl BaselineManager {
 
             if path.extension().and_then(|s| s.to_str()) == Some("json") {
                 if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
-                    baselines.push(stem.to_string());
+                    // This platform's baselines go by their plain name;
+                    // other platforms' keep theirs
+                    let name = stem
+                        .strip_suffix(&format!(".{}", platform()))
+                        .filter(|_| self.platform_scoped)
+                        .unwrap_or(stem);
+                    baselines.push(name.to_string());
                 }
             }
         }
 
         baselines.sort();
+        baselines.dedup();
         Ok(baselines)
     }
 
+    /// Renames a saved baseline, refusing to replace another.
+    ///
+    /// # Returns
+    /// * Path to the renamed baseline file
+    ///
+    /// # Errors
+    /// * `PeakMemError::InvalidArgument` - No baseline named `old`, one named
+    ///   `new` already, or an invalid name
+    pub fn rename_baseline(&self, old: &str, new: &str) -> Result<PathBuf> {
+        let (from, to) = (self.saved_path(old)?, self.baseline_path(new)?);
+        if !from.exists() {
+            return Err(PeakMemError::InvalidArgument(format!(
+                "No baseline named '{old}'"
+            )));
+        }
+        if to.exists() {
+            return Err(PeakMemError::InvalidArgument(format!(
+                "A baseline named '{new}' already exists; delete it first"
+            )));
+        }
+        ensure_baseline(&from)?;
+        fs::rename(&from, &to)?;
+        Ok(to)
+    }
+
+    /// Writes a saved baseline to a file, to commit to a repository or
+    /// copy to another machine.
+    pub fn export_baseline(&self, name: &str, file: &Path) -> Result<()> {
+        let baseline = self.load_baseline(name)?;
+        fs::write(file, serde_json::to_string_pretty(&baseline)?)?;
+        Ok(())
+    }
+
+    /// Saves a baseline exported with [`Self::export_baseline`], under
+    /// `name` or else the file's name, refusing to replace another.
+    ///
+    /// # Returns
+    /// * The name it was saved under, and warnings about using it here: a
+    ///   baseline recorded on another platform, or by a newer peak-mem
+    ///
+    /// # Errors
+    /// * `PeakMemError::Io` / `PeakMemError::Json` - File unreadable or not
+    ///   JSON
+    /// * `PeakMemError::InvalidArgument` - Not a baseline, or one by that name
+    ///   exists already
+    pub fn import_baseline(
+        &self,
+        file: &Path,
+        name: Option<&str>,
+    ) -> Result<(String, Vec<String>)> {
+        let json = fs::read_to_string(file)?;
+        let baseline: Baseline = serde_json::from_str(&json).map_err(|e| {
+            PeakMemError::InvalidArgument(format!(
+                "{} isn't an exported baseline ({e})",
+                file.display()
+            ))
+        })?;
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => file
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .ok_or_else(|| {
+                    PeakMemError::InvalidArgument(format!(
+                        "Can't name a baseline after {}",
+                        file.display()
+                    ))
+                })?,
+        };
+        if self.exists(&name) {
+            return Err(PeakMemError::InvalidArgument(format!(
+                "A baseline named '{name}' already exists; delete it first"
+            )));
+        }
+
+        let mut warnings = Vec::new();
+        if let Some(platform) = baseline.metadata.get("platform") {
+            if platform != env::consts::OS {
+                warnings.push(format!(
+                    "Baseline '{name}' was recorded on {platform}; comparing runs on {} \
+                     against it measures the platform as much as the code",
+                    env::consts::OS
+                ));
+            }
+        }
+        let ours = env!("CARGO_PKG_VERSION");
+        match (parse_version(&baseline.version), parse_version(ours)) {
+            (Some(theirs), Some(current)) if theirs > current => warnings.push(format!(
+                "Baseline '{name}' was saved by peak-mem {}, newer than this one ({ours}); \
+                 what this version doesn't know of it is dropped",
+                baseline.version
+            )),
+            (None, _) => {
+                return Err(PeakMemError::InvalidArgument(format!(
+                    "{} has an invalid peak-mem version '{}'",
+                    file.display(),
+                    baseline.version
+                )))
+            }
+            _ => {}
+        }
+
+        self.write(&name, &baseline)?;
+        Ok((name, warnings))
+    }
+
+    /// Deletes a saved baseline.
+    ///
+    /// # Errors
+    /// * `PeakMemError::InvalidArgument` - The file isn't a baseline
     pub fn delete_baseline(&self, name: &str) -> Result<()> {
-        let filename = format!("{}.json", sanitize_filename(name)?);
-        let path = self.baselines_dir.join(&filename);
-        fs::remove_file(&path)?;
+        let path = self.saved_path(name)?;
+        ensure_baseline(&path)?;
+        fs::remove_file(path)?;
         Ok(())
     }
 
+    /// Compares a run against a saved baseline.
+    ///
+    /// # Errors
+    /// * `PeakMemError::InvalidArgument` - With platform scoping, the baseline
+    ///   was recorded on another platform
     pub fn compare(
         &self,
         baseline_name: &str,
         current: &MonitorResult,
-        threshold_percent: f64,
+        thresholds: RegressionThresholds,
     ) -> Result<ComparisonResult> {
         let baseline = self.load_baseline(baseline_name)?;
         // Clone is necessary here because ComparisonResult needs to own the
         // MonitorResult for serialization and output formatting purposes
-        Ok(ComparisonResult::new(
-            baseline,
-            current.clone(),
-            threshold_percent,
-        ))
+        let comparison = ComparisonResult::new(baseline, current.clone(), thresholds);
+        match &comparison.other_platform {
+            Some(other) if self.platform_scoped => Err(PeakMemError::InvalidArgument(format!(
+                "Baseline '{baseline_name}' was recorded on {other}, not {}, so its numbers \
+                 aren't comparable; use --no-platform-scope to compare anyway",
+                platform()
+            ))),
+            _ => Ok(comparison),
+        }
+    }
+}
+
+/// This platform, as `os-arch`, e.g. `linux-x86_64`.
+pub fn platform() -> String {
+    format!("{}-{}", env::consts::OS, env::consts::ARCH)
+}
+
+/// Results saved with `--json`, read back.
+#[derive(Debug, Clone)]
+pub enum SavedResults {
+    Run(Box<MonitorResult>),
+    Comparison(Box<ComparisonResult>),
+}
+
+/// Reads the results of a run, or of a comparison, saved with `--json`.
+///
+/// # Errors
+/// * `PeakMemError::Io` / `PeakMemError::Json` - File unreadable or not JSON
+/// * `PeakMemError::InvalidArgument` - Neither the results of a run nor a
+///   comparison
+pub fn load_saved(path: &Path) -> Result<SavedResults> {
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let invalid = |e: serde_json::Error| {
+        PeakMemError::InvalidArgument(format!("{} isn't a --json result ({e})", path.display()))
+    };
+    if json.get("baseline").is_some() {
+        let comparison = serde_json::from_value(json).map_err(invalid)?;
+        return Ok(SavedResults::Comparison(Box::new(comparison)));
+    }
+    Ok(SavedResults::Run(Box::new(
+        serde_json::from_value(json).map_err(invalid)?,
+    )))
+}
+
+/// Reads the results of a run saved with `--json`, to compare with
+/// another without saving a baseline.
+///
+/// Comparison output is accepted too, in which case its current run is
+/// used.
+///
+/// # Errors
+/// See [`load_saved`].
+pub fn load_result(path: &Path) -> Result<MonitorResult> {
+    Ok(match load_saved(path)? {
+        SavedResults::Run(result) => *result,
+        SavedResults::Comparison(comparison) => comparison.current,
+    })
+}
+
+/// Compares two runs as if the first were a saved baseline.
+pub fn compare_results(
+    before: &MonitorResult,
+    after: &MonitorResult,
+    thresholds: RegressionThresholds,
+) -> ComparisonResult {
+    let baseline = Baseline {
+        created_at: before.timestamp,
+        ..Baseline::from(before)
+    };
+    ComparisonResult::new(baseline, after.clone(), thresholds)
+}
+
+/// Parses a `MAJOR.MINOR.PATCH` version, ignoring any pre-release or
+/// build suffix.
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let core = version.split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(|part| part.parse::<u64>().ok());
+    let version = (parts.next()??, parts.next()??, parts.next()??);
+    parts.next().is_none().then_some(version)
+}
+
+/// Refuses to go on if a file exists at `path` that doesn't parse as a
+/// baseline, so that replacing, renaming or deleting a baseline can't
+/// destroy some other file.
+fn ensure_baseline(path: &Path) -> Result<()> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return if path.exists() {
+            Err(PeakMemError::InvalidArgument(format!(
+                "{} exists and is not a baseline; refusing to touch it",
+                path.display()
+            )))
+        } else {
+            Ok(())
+        };
+    };
+    match serde_json::from_str::<Baseline>(&content) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(PeakMemError::InvalidArgument(format!(
+            "{} is not a baseline ({e}); refusing to touch it",
+            path.display()
+        ))),
     }
 }
 
@@ -285,23 +1160,16 @@ fn sanitize_filename(name: &str) -> Result<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::RunSummary;
     use tempfile::TempDir;
 
     #[test]
     fn test_baseline_conversion() {
         let result = MonitorResult {
-            command: "test".to_string(),
-            peak_rss_bytes: 100 * 1024 * 1024,
             peak_vsz_bytes: 200 * 1024 * 1024,
             duration_ms: 5000,
-            exit_code: Some(0),
-            threshold_exceeded: false,
-            timestamp: Timestamp::now(),
-            process_tree: None,
-            timeline: None,
-            start_time: None,
-            sample_count: None,
             main_pid: Some(1234),
+            ..MonitorResult::fixture("test", 100 * 1024 * 1024)
         };
 
         let baseline = Baseline::from(&result);
@@ -320,18 +1188,9 @@ mod tests {
         let manager = BaselineManager::new(temp_dir.path().to_path_buf()).unwrap();
 
         let result = MonitorResult {
-            command: "test".to_string(),
-            peak_rss_bytes: 100 * 1024 * 1024,
             peak_vsz_bytes: 200 * 1024 * 1024,
             duration_ms: 5000,
-            exit_code: Some(0),
-            threshold_exceeded: false,
-            timestamp: Timestamp::now(),
-            process_tree: None,
-            timeline: None,
-            start_time: None,
-            sample_count: None,
-            main_pid: None,
+            ..MonitorResult::fixture("test", 100 * 1024 * 1024)
         };
 
         // Save baseline
@@ -347,10 +1206,293 @@ mod tests {
         let baselines = manager.list_baselines().unwrap();
         assert_eq!(baselines, vec!["test_baseline"]);
 
+        // Rename baseline
+        manager.save_baseline("other", &result).unwrap();
+        assert!(manager.rename_baseline("test_baseline", "other").is_err());
+        assert!(manager.rename_baseline("missing", "new").is_err());
+        manager.delete_baseline("other").unwrap();
+        manager
+            .rename_baseline("test_baseline", "main/test")
+            .unwrap();
+        assert_eq!(manager.list_baselines().unwrap(), vec!["main_test"]);
+        assert_eq!(manager.load_baseline("main/test").unwrap().command, "test");
+
         // Delete baseline
-        manager.delete_baseline("test_baseline").unwrap();
+        manager.delete_baseline("main/test").unwrap();
+
+        // A name can't be a path
+        assert!(manager.save_baseline("package.json", &result).is_err());
+
+        // A file outside the baseline directory
+        let file = temp_dir.path().join("bench").join("baseline.json");
+        let in_file = BaselineManager::new(temp_dir.path().to_path_buf())
+            .unwrap()
+            .with_file(Some(file.clone()));
+        assert_eq!(in_file.save_baseline("any", &result).unwrap(), file);
+        assert!(in_file.exists("other"));
+        assert_eq!(in_file.load_baseline("any").unwrap().command, "test");
         let baselines = manager.list_baselines().unwrap();
         assert!(baselines.is_empty());
+
+        // Nor is a file that isn't a baseline replaced or deleted
+        fs::write(&file, "{\"name\": \"app\"}").unwrap();
+        assert!(in_file.save_baseline("any", &result).is_err());
+        assert!(in_file.delete_baseline("any").is_err());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "{\"name\": \"app\"}");
+    }
+
+    #[test]
+    fn test_fault_regression_detection() {
+        let mut result = MonitorResult {
+            peak_vsz_bytes: 200 * 1024 * 1024,
+            duration_ms: 5000,
+            minor_faults: 1000,
+            major_faults: 10,
+            ..MonitorResult::fixture("test", 100 * 1024 * 1024)
+        };
+        let baseline = Baseline::from(&result);
+
+        // Same peak RSS, 100x the major faults
+        result.major_faults = 1000;
+
+        let ignored = ComparisonResult::new(
+            baseline.clone(),
+            result.clone(),
+            RegressionThresholds::rss(10.0),
+        );
+        assert_eq!(ignored.major_faults_diff, 990);
+        assert_eq!(ignored.major_faults_diff_percent, 9900.0);
+        assert!(!ignored.regression_detected);
+
+        let thresholds = RegressionThresholds {
+            major_faults_percent: Some(50.0),
+            ..RegressionThresholds::rss(10.0)
+        };
+        let gated = ComparisonResult::new(baseline, result, thresholds);
+        assert!(gated.fault_regression_detected);
+        assert!(!gated.memory_regression_detected);
+        assert!(gated.regression_detected);
+    }
+
+    #[test]
+    fn test_cpu_regression_detection() {
+        let mut result = MonitorResult {
+            peak_vsz_bytes: 200 * 1024 * 1024,
+            duration_ms: 5000,
+            user_time_ms: 3000,
+            system_time_ms: 1000,
+            cpu_utilization_percent: 80.0,
+            ..MonitorResult::fixture("test", 100 * 1024 * 1024)
+        };
+        let baseline = Baseline::from(&result);
+        assert_eq!(baseline.cpu_time_ms(), 4000);
+
+        result.user_time_ms = 5000;
+
+        let thresholds = RegressionThresholds {
+            cpu_time_percent: Some(20.0),
+            ..RegressionThresholds::rss(10.0)
+        };
+        let comparison = ComparisonResult::new(baseline, result, thresholds);
+        assert_eq!(comparison.cpu_time_diff_ms, 2000);
+        assert_eq!(comparison.cpu_time_diff_percent, 50.0);
+        assert!(comparison.cpu_regression_detected);
+        assert!(comparison.regression_detected);
+        assert!(!comparison.noisy_environment);
+    }
+
+    #[test]
+    fn test_gpu_regression_detection() {
+        let mut result = MonitorResult {
+            peak_vsz_bytes: 200 * 1024 * 1024,
+            duration_ms: 5000,
+            peak_gpu_bytes: Some(1024 * 1024 * 1024),
+            ..MonitorResult::fixture("train", 100 * 1024 * 1024)
+        };
+        let baseline = Baseline::from(&result);
+
+        result.peak_gpu_bytes = Some(1536 * 1024 * 1024);
+        let thresholds = RegressionThresholds {
+            gpu_percent: Some(25.0),
+            ..RegressionThresholds::rss(10.0)
+        };
+        let comparison = ComparisonResult::new(baseline.clone(), result.clone(), thresholds);
+        assert_eq!(comparison.gpu_diff_bytes, Some(512 * 1024 * 1024));
+        assert_eq!(comparison.gpu_diff_percent, Some(50.0));
+        assert!(comparison.gpu_regression_detected);
+        assert!(comparison.regression_detected);
+
+        // Without GPU data on both sides there is nothing to compare
+        result.peak_gpu_bytes = None;
+        let comparison = ComparisonResult::new(baseline, result, thresholds);
+        assert_eq!(comparison.gpu_diff_percent, None);
+        assert!(!comparison.regression_detected);
+    }
+
+    #[test]
+    fn test_ignore_below() {
+        let mut result = MonitorResult {
+            peak_vsz_bytes: 8 * 1024 * 1024,
+            duration_ms: 5,
+            ..MonitorResult::fixture("true", 1024 * 1024)
+        };
+        let baseline = Baseline::from(&result);
+        let thresholds = RegressionThresholds {
+            ignore_below_bytes: Some(2 * 1024 * 1024),
+            ..RegressionThresholds::rss(10.0)
+        };
+
+        // +50% on a tiny utility is jitter, not a regression
+        result.peak_rss_bytes = 1536 * 1024;
+        let comparison = ComparisonResult::new(baseline.clone(), result.clone(), thresholds);
+        assert_eq!(comparison.rss_diff_bytes, 0);
+        assert_eq!(comparison.rss_diff_percent, 0.0);
+        assert!(!comparison.regression_detected);
+        assert!(!comparison.is_improvement());
+        assert!(comparison.is_within(0.0));
+
+        // Differences at or above the floor are reported as usual
+        result.peak_rss_bytes = 3 * 1024 * 1024;
+        let comparison = ComparisonResult::new(baseline.clone(), result.clone(), thresholds);
+        assert_eq!(comparison.rss_diff_bytes, 2 * 1024 * 1024);
+        assert!(comparison.regression_detected);
+        assert!(!comparison.is_within(1000.0));
+
+        // An increase within tolerance, but short of a regression
+        let lenient = RegressionThresholds {
+            rss_percent: 500.0,
+            ..thresholds
+        };
+        let comparison = ComparisonResult::new(baseline, result, lenient);
+        assert!(!comparison.is_within(100.0));
+        assert!(comparison.is_within(200.0));
+    }
+
+    #[test]
+    fn test_noisy_environment_detection() {
+        let mut result = MonitorResult {
+            peak_vsz_bytes: 200 * 1024 * 1024,
+            duration_ms: 5000,
+            peak_memory_pressure: Some(MemoryPressure {
+                some_avg10: 25.0,
+                full_avg10: 8.0,
+            }),
+            ..MonitorResult::fixture("test", 100 * 1024 * 1024)
+        };
+        let baseline = Baseline::from(&result);
+        assert_eq!(baseline.peak_memory_pressure, result.peak_memory_pressure);
+
+        // Pressure in the baseline alone is enough to flag the comparison
+        result.peak_memory_pressure = None;
+        let comparison = ComparisonResult::new(
+            baseline.clone(),
+            result.clone(),
+            RegressionThresholds::rss(10.0),
+        );
+        assert!(comparison.noisy_environment);
+        assert!(!comparison.regression_detected);
+
+        let mut calm = baseline;
+        calm.peak_memory_pressure = Some(MemoryPressure {
+            some_avg10: 0.5,
+            full_avg10: 0.0,
+        });
+        let comparison = ComparisonResult::new(calm, result, RegressionThresholds::rss(10.0));
+        assert!(!comparison.noisy_environment);
+    }
+
+    #[test]
+    fn test_accumulated_baseline() {
+        let mut result = MonitorResult {
+            peak_vsz_bytes: 200 * 1024 * 1024,
+            duration_ms: 5000,
+            minor_faults: 1000,
+            major_faults: 10,
+            ..MonitorResult::fixture("test", 100 * 1024 * 1024)
+        };
+        let mib = |n: u64| n * 1024 * 1024;
+
+        // Runs of 100, 104, 96 and 100 MiB, from a baseline saved before
+        let mut baseline = Baseline::from(&result);
+        for peak in [104, 96, 100] {
+            result.peak_rss_bytes = mib(peak);
+            baseline = Baseline::accumulate(Some(baseline), &result, 3);
+        }
+        let peaks: Vec<u64> = baseline.runs.iter().map(|run| run.peak_rss_bytes).collect();
+        assert_eq!(peaks, vec![mib(104), mib(96), mib(100)]);
+        assert_eq!(baseline.peak_rss_bytes, mib(100));
+        let distribution = baseline.rss_distribution().unwrap();
+        assert_eq!(distribution.runs, 3);
+        assert_eq!(distribution.stddev_bytes, mib(4));
+        assert_eq!(
+            (distribution.min_bytes, distribution.max_bytes),
+            (mib(96), mib(104))
+        );
+
+        // 11% above the mean, but within three standard deviations
+        result.peak_rss_bytes = mib(111);
+        let comparison = ComparisonResult::new(
+            baseline.clone(),
+            result.clone(),
+            RegressionThresholds::rss(10.0),
+        );
+        assert!(!comparison.memory_regression_detected);
+        assert!((comparison.rss_z_score.unwrap() - 2.75).abs() < 1e-9);
+
+        result.peak_rss_bytes = mib(113);
+        let comparison = ComparisonResult::new(
+            baseline.clone(),
+            result.clone(),
+            RegressionThresholds::rss(10.0),
+        );
+        assert!(comparison.memory_regression_detected);
+        assert_eq!(comparison.noise_sigmas, Some(NOISE_SIGMAS));
+
+        // Not within a looser noise allowance
+        let loose = RegressionThresholds {
+            noise_sigmas: 4.0,
+            ..RegressionThresholds::rss(10.0)
+        };
+        let comparison = ComparisonResult::new(baseline.clone(), result.clone(), loose);
+        assert!(!comparison.memory_regression_detected);
+        let steady = [100, 101, 100, 101]
+            .into_iter()
+            .fold(None, |baseline, peak| {
+                result.peak_rss_bytes = mib(peak);
+                Some(Baseline::accumulate(baseline, &result, 4))
+            })
+            .unwrap();
+        // Against runs that barely vary, the percentage is still a floor
+        result.peak_rss_bytes = mib(105);
+        let comparison = ComparisonResult::new(
+            steady.clone(),
+            result.clone(),
+            RegressionThresholds::rss(10.0),
+        );
+        assert!(comparison.rss_z_score.unwrap() > NOISE_SIGMAS);
+        assert!(!comparison.memory_regression_detected);
+        let comparison =
+            ComparisonResult::new(steady, result.clone(), RegressionThresholds::rss(4.0));
+        assert!(comparison.memory_regression_detected);
+
+        // A result of --runs is saved as each of its runs
+        result.runs = [96, 100, 104]
+            .into_iter()
+            .map(|peak| RunSummary {
+                peak_rss_bytes: mib(peak),
+                peak_vsz_bytes: 0,
+                duration_ms: 5000,
+                exit_code: Some(0),
+            })
+            .collect();
+        let repeated = Baseline::from(&result);
+        assert_eq!(repeated.runs.len(), 3);
+        assert_eq!(repeated.peak_rss_bytes, mib(100));
+        assert_eq!(repeated.rss_distribution().unwrap().stddev_bytes, mib(4));
+        assert_eq!(
+            Baseline::accumulate(Some(repeated), &result, 10).runs.len(),
+            6
+        );
     }
 
     #[test]
@@ -367,4 +1509,152 @@ mod tests {
         assert!(sanitize_filename("..").is_err());
         assert!(sanitize_filename("...").is_err());
     }
+
+    #[test]
+    fn test_export_import() {
+        let dir = TempDir::new().unwrap();
+        let manager = BaselineManager::new(dir.path().join("baselines")).unwrap();
+        let result = MonitorResult::fixture("./app", 2048);
+        manager.save_baseline("main", &result).unwrap();
+
+        let file = dir.path().join("release.json");
+        manager.export_baseline("main", &file).unwrap();
+        assert_eq!(
+            manager.import_baseline(&file, None).unwrap(),
+            ("release".to_string(), Vec::new())
+        );
+        assert_eq!(manager.load_baseline("release").unwrap().command, "./app");
+        // Not over an existing one
+        assert!(manager.import_baseline(&file, Some("main")).is_err());
+
+        let mut foreign = manager.load_baseline("main").unwrap();
+        foreign.version = "999.0.0".to_string();
+        foreign
+            .metadata
+            .insert("platform".to_string(), "plan9".to_string());
+        fs::write(&file, serde_json::to_string(&foreign).unwrap()).unwrap();
+        let (_, warnings) = manager.import_baseline(&file, Some("foreign")).unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("recorded on plan9"));
+        assert!(warnings[1].contains("peak-mem 999.0.0, newer than this one"));
+
+        foreign.version = "one".to_string();
+        fs::write(&file, serde_json::to_string(&foreign).unwrap()).unwrap();
+        assert!(manager.import_baseline(&file, Some("invalid")).is_err());
+        fs::write(&file, r#"{"command": "./app"}"#).unwrap();
+        assert!(manager.import_baseline(&file, Some("invalid")).is_err());
+    }
+
+    #[test]
+    fn test_platform_scope() {
+        let dir = TempDir::new().unwrap();
+        let manager = BaselineManager::new(dir.path().to_path_buf()).unwrap();
+        let result = MonitorResult::fixture("./app", 2048);
+        let path = manager.save_baseline("main", &result).unwrap();
+        assert_eq!(path, dir.path().join(format!("main.{}.json", platform())));
+
+        // An unscoped baseline from another platform
+        let mut foreign = manager.load_baseline("main").unwrap();
+        foreign
+            .metadata
+            .insert("platform".to_string(), "plan9".to_string());
+        foreign
+            .metadata
+            .insert("arch".to_string(), "mips".to_string());
+        fs::write(
+            dir.path().join("foreign.json"),
+            serde_json::to_string(&foreign).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(manager.list_baselines().unwrap(), vec!["foreign", "main"]);
+        assert!(manager
+            .compare("main", &result, RegressionThresholds::rss(10.0))
+            .is_ok());
+        let error = manager
+            .compare("foreign", &result, RegressionThresholds::rss(10.0))
+            .unwrap_err();
+        assert!(error.to_string().contains("recorded on plan9-mips"));
+
+        let unscoped = manager.with_platform_scope(false);
+        let comparison = unscoped
+            .compare("foreign", &result, RegressionThresholds::rss(10.0))
+            .unwrap();
+        assert_eq!(comparison.other_platform.as_deref(), Some("plan9-mips"));
+        assert!(!unscoped.exists("main"));
+        assert_eq!(
+            unscoped.list_baselines().unwrap(),
+            vec!["foreign".to_string(), format!("main.{}", platform())]
+        );
+    }
+
+    #[test]
+    fn test_history_and_trend() {
+        let result = |rss: u64| MonitorResult::fixture("./app", rss);
+        let dir = TempDir::new().unwrap();
+        let manager = BaselineManager::new(dir.path().to_path_buf()).unwrap();
+
+        // A baseline saved without a history starts it
+        manager.save_baseline("main", &result(100)).unwrap();
+        assert_eq!(
+            manager.load_baseline("main").unwrap().trend("main", 10),
+            None
+        );
+        for rss in [200, 300] {
+            manager.save_with_history("main", &result(rss)).unwrap();
+        }
+        let baseline = manager.load_baseline("main").unwrap();
+        assert_eq!(baseline.peak_rss_bytes, 300);
+        let trend = baseline.trend("main", 2).unwrap();
+        assert_eq!(trend.total_runs, 3);
+        assert_eq!(
+            trend
+                .runs
+                .iter()
+                .map(|run| run.peak_rss_bytes)
+                .collect::<Vec<_>>(),
+            vec![200, 300]
+        );
+
+        // Appending keeps the history, and without one the runs are shown
+        manager.append_to_baseline("main", &result(400), 5).unwrap();
+        assert_eq!(manager.load_baseline("main").unwrap().history.len(), 3);
+        manager.append_to_baseline("runs", &result(1), 5).unwrap();
+        manager.append_to_baseline("runs", &result(2), 5).unwrap();
+        let runs = manager.load_baseline("runs").unwrap();
+        assert_eq!(runs.trend("runs", 10).unwrap().total_runs, 2);
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("0.1.4"), Some((0, 1, 4)));
+        assert_eq!(parse_version("1.2.3-rc.1"), Some((1, 2, 3)));
+        assert_eq!(parse_version("1.2"), None);
+        assert_eq!(parse_version("1.2.3.4"), None);
+    }
+
+    #[test]
+    fn test_load_result() {
+        let dir = TempDir::new().unwrap();
+        let run = r#"{"command": "./app", "peak_rss_bytes": 2048, "peak_vsz_bytes": 0,
+                      "duration_ms": 10, "exit_code": 0, "threshold_exceeded": false,
+                      "timestamp": "2026-01-01T00:00:00Z"}"#;
+        let result = dir.path().join("result.json");
+        fs::write(&result, run).unwrap();
+        let before = load_result(&result).unwrap();
+        assert_eq!(before.peak_rss_bytes, 2048);
+
+        let compared = compare_results(&before, &before, RegressionThresholds::rss(10.0));
+        assert_eq!(compared.baseline.created_at, before.timestamp);
+        assert!(!compared.regression_detected);
+        let comparison = dir.path().join("comparison.json");
+        fs::write(&comparison, serde_json::to_string(&compared).unwrap()).unwrap();
+        assert!(matches!(
+            load_saved(&comparison).unwrap(),
+            SavedResults::Comparison(_)
+        ));
+        assert_eq!(load_result(&comparison).unwrap().command, "./app");
+
+        fs::write(&result, r#"{"peak_rss_bytes": 2048}"#).unwrap();
+        assert!(load_result(&result).is_err());
+    }
 }
@@ -0,0 +1,186 @@
+//! Measuring the cost of sampling on this machine, for `--bench-self`.
+//!
+//! Each sample reads the memory of every process in the tree, so its
+//! cost grows with the tree and the overhead of monitoring is that cost
+//! times the sampling rate. Synthetic trees of idle `sleep` processes are
+//! sampled back to back to measure the cost per sample, from which the
+//! CPU share of each interval follows. The trees are killed afterwards.
+
+use crate::monitor::MemoryMonitor;
+use crate::types::{PeakMemError, ProcessMemoryInfo, Result};
+use serde::Serialize;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Sizes of the process trees sampled.
+pub const TREE_SIZES: [usize; 3] = [1, 10, 100];
+/// Intervals the overhead is reported for, in milliseconds.
+pub const INTERVALS_MS: [u64; 5] = [10, 50, 100, 500, 1000];
+/// Samples taken of each tree, unless that takes longer than
+/// [`MAX_SAMPLING_TIME`].
+const SAMPLES: u32 = 50;
+/// How long a tree is sampled for at most.
+const MAX_SAMPLING_TIME: Duration = Duration::from_secs(1);
+/// How long a tree may take to start all its processes.
+const SPAWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The cost of sampling a tree of one size.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BenchResult {
+    /// Processes in the tree.
+    pub processes: usize,
+    /// Samples taken.
+    pub samples: u32,
+    /// Mean wall time of a sample (in microseconds).
+    pub wall_us_per_sample: f64,
+    /// Mean CPU time of a sample (in microseconds).
+    pub cpu_us_per_sample: f64,
+    /// Share of one CPU sampling takes at each interval.
+    pub overhead: Vec<IntervalOverhead>,
+}
+
+/// The CPU share of sampling at one interval.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct IntervalOverhead {
+    /// Sampling interval (in milliseconds).
+    pub interval_ms: u64,
+    /// Percent of one CPU spent sampling.
+    pub cpu_percent: f64,
+}
+
+impl BenchResult {
+    fn new(processes: usize, samples: u32, wall: Duration, cpu: Duration) -> Self {
+        let per_sample = |total: Duration| total.as_secs_f64() * 1e6 / f64::from(samples);
+        let cpu_us_per_sample = per_sample(cpu);
+        Self {
+            processes,
+            samples,
+            wall_us_per_sample: per_sample(wall),
+            cpu_us_per_sample,
+            overhead: INTERVALS_MS
+                .iter()
+                .map(|&interval_ms| IntervalOverhead {
+                    interval_ms,
+                    cpu_percent: cpu_us_per_sample / (interval_ms as f64 * 1000.0) * 100.0,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Samples a synthetic tree of each of [`TREE_SIZES`].
+///
+/// # Errors
+/// * `PeakMemError::ProcessSpawn` - A tree couldn't be started
+/// * Errors from sampling the tree
+pub async fn bench_self(monitor: &dyn MemoryMonitor) -> Result<Vec<BenchResult>> {
+    let mut results = Vec::new();
+    for processes in TREE_SIZES {
+        let tree = SyntheticTree::spawn(processes)?;
+        let result = bench_tree(monitor, tree.pid(), processes).await;
+        drop(tree);
+        results.push(result?);
+    }
+    Ok(results)
+}
+
+async fn bench_tree(
+    monitor: &dyn MemoryMonitor,
+    pid: u32,
+    processes: usize,
+) -> Result<BenchResult> {
+    // Wait for the shell to start all of its children
+    let started = Instant::now();
+    while count_processes(&monitor.get_process_tree(pid).await?) < processes {
+        if started.elapsed() > SPAWN_TIMEOUT {
+            return Err(PeakMemError::ProcessSpawn(format!(
+                "Benchmark tree of {processes} processes didn't start in time"
+            )));
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let (wall, cpu) = (Instant::now(), cpu_time());
+    let mut samples = 0;
+    while samples < SAMPLES && (samples == 0 || wall.elapsed() < MAX_SAMPLING_TIME) {
+        monitor.get_process_tree(pid).await?;
+        samples += 1;
+    }
+    Ok(BenchResult::new(
+        processes,
+        samples,
+        wall.elapsed(),
+        cpu_time().saturating_sub(cpu),
+    ))
+}
+
+fn count_processes(tree: &ProcessMemoryInfo) -> usize {
+    1 + tree.children.iter().map(count_processes).sum::<usize>()
+}
+
+/// CPU time used by this process so far.
+fn cpu_time() -> Duration {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return Duration::ZERO;
+    }
+    let duration = |tv: libc::timeval| {
+        Duration::from_secs(tv.tv_sec.max(0) as u64)
+            + Duration::from_micros(tv.tv_usec.max(0) as u64)
+    };
+    duration(usage.ru_utime) + duration(usage.ru_stime)
+}
+
+/// A shell with idle children, in a process group of its own so that the
+/// whole tree can be killed at once.
+struct SyntheticTree {
+    child: Child,
+}
+
+impl SyntheticTree {
+    /// Starts a tree of `processes` processes: the shell and its
+    /// children.
+    fn spawn(processes: usize) -> Result<Self> {
+        let script =
+            format!("i=1; while [ $i -lt {processes} ]; do sleep 600 & i=$((i + 1)); done; wait");
+        let child = Command::new("sh")
+            .args(["-c", &script])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .process_group(0)
+            .spawn()
+            .map_err(|e| {
+                PeakMemError::ProcessSpawn(format!("Failed to start benchmark tree: {e}"))
+            })?;
+        Ok(Self { child })
+    }
+
+    fn pid(&self) -> u32 {
+        self.child.id()
+    }
+}
+
+impl Drop for SyntheticTree {
+    fn drop(&mut self) {
+        let group = nix::unistd::Pid::from_raw(self.child.id() as i32);
+        let _ = nix::sys::signal::killpg(group, nix::sys::signal::Signal::SIGKILL);
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overhead() {
+        let result = BenchResult::new(10, 50, Duration::from_millis(10), Duration::from_millis(5));
+        assert_eq!(result.wall_us_per_sample, 200.0);
+        assert_eq!(result.cpu_us_per_sample, 100.0);
+        // 100us of every 10ms
+        assert_eq!(result.overhead[0].interval_ms, 10);
+        assert!((result.overhead[0].cpu_percent - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,70 @@
+//! Detecting CI environments.
+//!
+//! A pipeline wants the human summary in its log and the JSON results as
+//! an artifact to keep. When one of the variables CI providers set is
+//! present, the JSON results are written to [`DEFAULT_JSON_FILE`] in
+//! addition to the human output, so the pipeline only has to upload it.
+
+use std::ffi::OsString;
+
+/// Where the JSON results are written in CI unless `--json-file` says
+/// otherwise.
+pub const DEFAULT_JSON_FILE: &str = "peak-mem.json";
+
+/// Variables set by CI providers and the providers' names, most specific
+/// first. The generic `CI`, which most providers set too, comes last.
+const PROVIDERS: [(&str, &str); 9] = [
+    ("GITHUB_ACTIONS", "GitHub Actions"),
+    ("GITLAB_CI", "GitLab CI"),
+    ("BUILDKITE", "Buildkite"),
+    ("CIRCLECI", "CircleCI"),
+    ("TF_BUILD", "Azure Pipelines"),
+    ("TRAVIS", "Travis CI"),
+    ("JENKINS_URL", "Jenkins"),
+    ("TEAMCITY_VERSION", "TeamCity"),
+    ("CI", "CI"),
+];
+
+/// Names the CI provider this runs under.
+///
+/// # Returns
+/// * `None` outside CI, or where a variable is set to `false` or `0`
+pub fn detect() -> Option<&'static str> {
+    detect_from(|name| std::env::var_os(name))
+}
+
+fn detect_from(var: impl Fn(&str) -> Option<OsString>) -> Option<&'static str> {
+    PROVIDERS.iter().find_map(|&(name, provider)| {
+        let value = var(name)?;
+        let disabled = value.is_empty() || value == "false" || value == "0";
+        (!disabled).then_some(provider)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect_with(vars: &[(&str, &str)]) -> Option<&'static str> {
+        detect_from(|name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| OsString::from(value))
+        })
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect_with(&[]), None);
+        assert_eq!(
+            detect_with(&[("CI", "true"), ("GITHUB_ACTIONS", "true")]),
+            Some("GitHub Actions")
+        );
+        assert_eq!(
+            detect_with(&[("JENKINS_URL", "http://ci:8080/")]),
+            Some("Jenkins")
+        );
+        assert_eq!(detect_with(&[("CI", "1")]), Some("CI"));
+        assert_eq!(detect_with(&[("CI", "false")]), None);
+    }
+}
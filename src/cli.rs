@@ -1,6 +1,20 @@
-use crate::types::{ByteSize, PeakMemError, Result};
-use clap::{ArgAction, Parser};
+use crate::baseline::{self, RegressionThresholds};
+use crate::git;
+use crate::http::HttpUrl;
+use crate::monitor::aggregate::Aggregate;
+use crate::monitor::gpu::GpuBackend;
+use crate::monitor::retention::RetentionPolicy;
+use crate::monitor::runtime::Runtime;
+use crate::monitor::store::StoreKind;
+use crate::output::{self, ColorChoice, ExecFormat, Field, Template};
+use crate::threshold::ThresholdAction;
+use crate::types::{ByteSize, PeakMemError, Result, Timestamp, TimestampFormat};
+use clap::{ArgAction, CommandFactory, Parser};
+use regex::Regex;
+use std::ffi::OsString;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MemoryUnit {
@@ -27,6 +41,122 @@ impl MemoryUnit {
     }
 }
 
+/// Subcommands, each a shorthand for the flags of a mode, for those who
+/// look for one. The wrapper form stays the default: any other first
+/// argument is the command to run.
+const SUBCOMMANDS: [(&str, &str); 10] = [
+    ("run", ""),
+    ("attach", "--pid"),
+    ("sample", "--pid"),
+    ("baseline", ""),
+    ("report", "--render"),
+    ("compare", "--diff"),
+    ("merge", "--merge"),
+    ("plot", "--plot"),
+    ("bench", "--bench-self"),
+    ("history", ""),
+];
+
+const SUBCOMMANDS_HELP: &str = "\
+Subcommands (shorthands for the flags on the right):
+  run [--] COMMAND...         Same as peak-mem [--] COMMAND...
+  attach PID...               --pid PID...
+  sample PID...               --once --pid PID...
+  baseline list               --list-baselines
+  baseline show NAME          --show-baseline NAME
+  baseline mv OLD NEW         --rename-baseline OLD NEW
+  baseline export NAME FILE   --export-baseline NAME FILE
+  baseline import FILE [NAME] --import-baseline FILE [NAME]
+  baseline trend NAME [N]     --trend NAME [N]
+  baseline rm NAME            --delete-baseline NAME
+  report RESULT               --render RESULT
+  compare A B                 --diff A B
+  compare --trees A B         --diff-trees A B
+  merge RESULT...             --merge RESULT...
+  plot TIMELINE [OTHER]       --plot TIMELINE [OTHER]
+  bench                       --bench-self
+  history query               --query-history
+Options follow the subcommand's arguments. A program named like a
+subcommand is run with peak-mem -- NAME.";
+
+/// Replaces a subcommand at the start of the arguments with the flags it
+/// stands for, leaving the arguments of the wrapper form as they are.
+///
+/// # Errors
+/// * A message for an unknown or missing `baseline` action
+pub fn expand_subcommand(args: Vec<OsString>) -> std::result::Result<Vec<OsString>, String> {
+    let Some((name, flag)) = args.get(1).and_then(|first| {
+        SUBCOMMANDS
+            .iter()
+            .find(|(name, _)| first.as_os_str() == *name)
+    }) else {
+        return Ok(args);
+    };
+    let mut rest = args[2..].to_vec();
+    // The subcommand's arguments are those before the first option
+    let operands = rest
+        .iter()
+        .take_while(|arg| !arg.to_string_lossy().starts_with('-'))
+        .count();
+    let mut expanded = vec![args[0].clone()];
+    match *name {
+        "run" => {}
+        "attach" | "sample" => {
+            if *name == "sample" {
+                expanded.push("--once".into());
+            }
+            for pid in rest.drain(..operands) {
+                expanded.extend([OsString::from(flag), pid]);
+            }
+        }
+        "baseline" => {
+            let action = (operands > 0).then(|| rest.remove(0));
+            let flag = match action.as_ref().and_then(|action| action.to_str()) {
+                Some("list") => "--list-baselines",
+                Some("show") => "--show-baseline",
+                Some("mv") => "--rename-baseline",
+                Some("export") => "--export-baseline",
+                Some("import") => "--import-baseline",
+                Some("trend") => "--trend",
+                Some("rm") => "--delete-baseline",
+                Some(other) => {
+                    return Err(format!(
+                        "unknown baseline action '{other}' (expected list, show, mv, export, import, trend or rm)"
+                    ))
+                }
+                None => {
+                    return Err(
+                        "baseline needs an action: list, show, mv, export, import, trend or rm"
+                            .to_string(),
+                    )
+                }
+            };
+            expanded.push(flag.into());
+        }
+        "compare" => {
+            if rest.first().is_some_and(|arg| arg == "--trees") {
+                rest.remove(0);
+                expanded.push("--diff-trees".into());
+            } else {
+                expanded.push(flag.into());
+            }
+        }
+        "history" => {
+            let action = (operands > 0).then(|| rest.remove(0));
+            match action.as_ref().and_then(|action| action.to_str()) {
+                Some("query") => expanded.push("--query-history".into()),
+                Some(other) => {
+                    return Err(format!("unknown history action '{other}' (expected query)"))
+                }
+                None => return Err("history needs an action: query".to_string()),
+            }
+        }
+        _ => expanded.push(flag.into()),
+    }
+    expanded.extend(rest);
+    Ok(expanded)
+}
+
 #[derive(Parser, Debug)]
 #[command(
     name = "peak-mem",
@@ -34,22 +164,24 @@ impl MemoryUnit {
     about = "Lightweight memory usage monitor for any process",
     long_about = "Peak-mem monitors and reports the peak memory usage of any program during its execution.\n\n\
                   It tracks both resident set size (RSS) and virtual memory size (VSZ) with minimal overhead.",
-    disable_version_flag = true
+    disable_version_flag = true,
+    after_help = SUBCOMMANDS_HELP
 )]
 pub struct Cli {
     #[arg(
         trailing_var_arg = true,
         value_name = "COMMAND",
         help = "Command to execute and monitor",
-        required_unless_present_any = &["list_baselines", "delete_baseline", "short_version", "long_version"]
+        value_parser = clap::value_parser!(OsString),
+        required_unless_present_any = &["pids", "container", "unit", "system", "list_baselines", "show_baseline", "rename_baseline", "export_baseline", "import_baseline", "trend", "delete_baseline", "diff_trees", "bench_self", "plot", "diff", "render", "merge", "query_history", "short_version", "long_version"]
     )]
-    pub command: Vec<String>,
+    pub command: Vec<OsString>,
 
     #[arg(
         short = 'j',
         long = "json",
         help = "Output in JSON format",
-        conflicts_with_all = &["csv", "quiet"]
+        conflicts_with_all = &["csv", "quiet", "influx", "tap"]
     )]
     pub json: bool,
 
@@ -57,7 +189,7 @@ pub struct Cli {
         short = 'c',
         long = "csv",
         help = "Output in CSV format",
-        conflicts_with_all = &["json", "quiet"]
+        conflicts_with_all = &["json", "quiet", "influx", "tap"]
     )]
     pub csv: bool,
 
@@ -65,10 +197,60 @@ pub struct Cli {
         short = 'q',
         long = "quiet",
         help = "Only output peak RSS value",
-        conflicts_with_all = &["json", "csv", "verbose"]
+        conflicts_with_all = &["json", "csv", "influx", "tap", "verbose"]
     )]
     pub quiet: bool,
 
+    #[arg(
+        long = "influx",
+        help = "Output the timeline and peak as InfluxDB line protocol"
+    )]
+    pub influx: bool,
+
+    #[arg(
+        long = "tap",
+        help = "Output the exit status, threshold and regression checks as TAP test points",
+        conflicts_with_all = &["influx", "verbose"]
+    )]
+    pub tap: bool,
+
+    #[arg(
+        long = "fields",
+        value_name = "LIST",
+        help = "Only output these comma-separated fields, tab-separated (e.g., peak_rss,duration_ms,exit_code)",
+        value_delimiter = ',',
+        value_parser = parse_field,
+        conflicts_with_all = &["json", "csv", "quiet", "influx", "tap", "verbose"]
+    )]
+    pub fields: Vec<Field>,
+
+    #[arg(
+        long = "template",
+        value_name = "TEMPLATE|FILE",
+        help = "Output the results as TEMPLATE, or the template in FILE, with {{field}} replaced by the JSON field (e.g., '{{peak_rss_mib}} MiB in {{duration_s}}s')",
+        value_parser = parse_template,
+        conflicts_with_all = &["json", "csv", "quiet", "influx", "tap", "fields"]
+    )]
+    pub template: Option<Template>,
+
+    #[arg(
+        long = "format",
+        value_name = "FORMAT",
+        help = "Output format: human, json, csv, quiet, influx, tap, prometheus, or exec:COMMAND for what COMMAND, run with sh, prints when given the JSON results on stdin",
+        value_parser = parse_format,
+        conflicts_with_all = &["json", "csv", "quiet", "influx", "tap", "fields", "template"]
+    )]
+    pub format: Option<Format>,
+
+    #[arg(
+        short = 'o',
+        long = "output",
+        value_name = "PATH",
+        help = "Write the results to PATH instead of stdout, which is left to the command; .json, .csv, .md and .html pick the format",
+        conflicts_with_all = &["list_baselines", "delete_baseline", "diff_trees", "bench_self", "plot"]
+    )]
+    pub output: Option<PathBuf>,
+
     #[arg(
         short = 'v',
         long = "verbose",
@@ -80,11 +262,29 @@ pub struct Cli {
     #[arg(
         short = 'w',
         long = "watch",
-        help = "Show real-time memory usage",
-        conflicts_with_all = &["json", "csv", "quiet"]
+        help = "Show real-time memory usage (with --json, as JSON lines on stderr)",
+        conflicts_with_all = &["csv", "quiet", "influx", "tap"]
     )]
     pub watch: bool,
 
+    #[arg(
+        long = "tui",
+        help = "With --watch, take over the terminal with live graphs and the top processes",
+        requires = "watch",
+        conflicts_with = "json"
+    )]
+    pub tui: bool,
+
+    #[arg(
+        long = "progress-every",
+        value_name = "SECS",
+        default_value_t = 10,
+        help = "With --watch and stdout not a terminal, print a progress line to stderr every SECS seconds",
+        requires = "watch",
+        value_parser = parse_duration
+    )]
+    pub progress_every: u64,
+
     #[arg(
         short = 't',
         long = "threshold",
@@ -94,6 +294,79 @@ pub struct Cli {
     )]
     pub threshold: Option<ByteSize>,
 
+    #[arg(
+        long = "on-threshold",
+        value_name = "ACTION",
+        help = "Action to run as soon as the threshold is crossed (repeatable): warn, annotate, kill, exec:CMD, signal:NAME, webhook:URL",
+        requires = "threshold",
+        action = ArgAction::Append,
+        value_parser = parse_threshold_action
+    )]
+    pub on_threshold: Vec<ThresholdAction>,
+
+    #[arg(
+        long = "strict",
+        help = "Fail with a distinct exit code instead of reporting incomplete data: unreadable processes, no samples, skipped ticks or degraded sampling",
+        conflicts_with = "system"
+    )]
+    pub strict: bool,
+
+    #[arg(
+        long = "max-skipped-ticks",
+        value_name = "PERCENT",
+        default_value = "10.0",
+        help = "With --strict, the share of sampling ticks that may be skipped",
+        requires = "strict"
+    )]
+    pub max_skipped_ticks: f64,
+
+    #[arg(
+        long = "pid",
+        value_name = "PID",
+        help = "Also monitor an already running process, reporting its peak and the combined total (repeatable)",
+        action = ArgAction::Append
+    )]
+    pub pids: Vec<u32>,
+
+    #[arg(
+        long = "container",
+        value_name = "NAME",
+        help = "Monitor a running Docker or Podman container by name or ID, measuring its cgroup's memory (Linux)",
+        conflicts_with_all = &["command", "pids"]
+    )]
+    pub container: Option<String>,
+
+    #[arg(
+        long = "unit",
+        value_name = "NAME",
+        help = "Monitor a running systemd unit, measuring its cgroup's memory (Linux)",
+        conflicts_with_all = &["command", "pids", "container"]
+    )]
+    pub unit: Option<String>,
+
+    #[arg(
+        long = "system",
+        help = "Monitor the whole machine's memory (used, available, swap) instead of a command (Linux)",
+        conflicts_with_all = &["command", "pids", "container", "unit", "watch", "on_threshold"]
+    )]
+    pub system: bool,
+
+    #[arg(
+        long = "once",
+        help = "Take a single sample of the --pid, --container or --unit processes, report it and exit",
+        conflicts_with_all = &["command", "system", "runs", "watch", "on_threshold", "serve_metrics"]
+    )]
+    pub once: bool,
+
+    #[arg(
+        long = "duration",
+        value_name = "SECS",
+        help = "With --system, stop after SECS seconds instead of at Ctrl-C",
+        requires = "system",
+        value_parser = parse_duration
+    )]
+    pub duration: Option<u64>,
+
     #[arg(
         long = "no-children",
         help = "Don't track child processes",
@@ -101,6 +374,68 @@ pub struct Cli {
     )]
     pub no_children: bool,
 
+    #[arg(
+        long = "cargo",
+        help = "Report the peak RSS of each crate compiled by a cargo command (Linux)",
+        requires = "command",
+        conflicts_with = "no_children",
+        action = ArgAction::SetTrue
+    )]
+    pub cargo: bool,
+
+    #[arg(
+        long = "compile-units",
+        help = "Report the peak RSS of each C/C++ source file compiled by a make, Ninja or other build (Linux)",
+        requires = "command",
+        conflicts_with = "no_children",
+        action = ArgAction::SetTrue
+    )]
+    pub compile_units: bool,
+
+    #[arg(
+        long = "aggregate",
+        value_name = "HOW",
+        default_value = "sum",
+        help = "How to combine the tree's memory: sum, max-of-processes or root-only-plus-named:NAMES",
+        conflicts_with_all = &["no_children", "container", "unit", "system"],
+        value_parser = parse_aggregate
+    )]
+    pub aggregate: Aggregate,
+
+    #[arg(
+        long = "pool-process",
+        value_name = "REGEX",
+        help = "Report the steady-state footprint of the worker pool whose process names match REGEX, e.g. gunicorn",
+        conflicts_with_all = &["no_children", "system"],
+        value_parser = parse_regex
+    )]
+    pub pool_process: Option<Regex>,
+
+    #[arg(
+        long = "pool-steady-after",
+        value_name = "DURATION",
+        default_value = "30s",
+        help = "Time after the start from which the pool counts as settled (e.g. 500ms, 30s, 2m)",
+        requires = "pool_process",
+        value_parser = parse_steady_after
+    )]
+    pub pool_steady_after: Duration,
+
+    #[arg(
+        long = "fd-summary",
+        help = "Count open file descriptors by type (sockets, pipes, files) at peak (Linux)",
+        action = ArgAction::SetTrue
+    )]
+    pub fd_summary: bool,
+
+    #[arg(
+        long = "expect-processes-from",
+        value_name = "FILE",
+        help = "Warn if the processes seen differ from those in a previous --json result",
+        conflicts_with = "no_children"
+    )]
+    pub expect_processes_from: Option<PathBuf>,
+
     #[arg(
         long = "timeline",
         value_name = "FILE",
@@ -108,6 +443,101 @@ pub struct Cli {
     )]
     pub timeline: Option<PathBuf>,
 
+    #[arg(
+        long = "timeline-normalized",
+        help = "Write the timeline as percent of peak and percent of duration",
+        requires = "timeline",
+        action = ArgAction::SetTrue
+    )]
+    pub timeline_normalized: bool,
+
+    #[arg(
+        long = "timeline-max-samples",
+        value_name = "N",
+        help = "Keep at most N timeline samples, dropping the rest by --timeline-retention",
+        required_if_eq("sample_store", "ring-file"),
+        value_parser = parse_max_samples
+    )]
+    pub timeline_max_samples: Option<usize>,
+
+    #[arg(
+        long = "timeline-retention",
+        value_name = "POLICY",
+        default_value = "downsample",
+        help = "What to drop beyond --timeline-max-samples: downsample (the whole run at a lower resolution), ring (the most recent samples) or truncate (the first samples)",
+        requires = "timeline_max_samples",
+        value_parser = parse_retention_policy
+    )]
+    pub timeline_retention: RetentionPolicy,
+
+    #[arg(
+        long = "timeline-delta",
+        value_name = "SIZE",
+        help = "Only keep timeline samples whose RSS moved by more than SIZE (e.g., 1M) since the last one kept; peaks still count every sample",
+        conflicts_with = "system",
+        value_parser = parse_threshold
+    )]
+    pub timeline_delta: Option<ByteSize>,
+
+    #[arg(
+        long = "sample-store",
+        value_name = "STORE",
+        default_value = "memory",
+        help = "Where to keep the timeline while running: memory, ring-file (the most recent --timeline-max-samples samples, in the memory-mapped --sample-store-file) or sqlite (every sample, in the --sample-store-file database; needs the sqlite feature)",
+        value_parser = parse_sample_store
+    )]
+    pub sample_store: StoreKind,
+
+    #[arg(
+        long = "sample-store-file",
+        value_name = "FILE",
+        help = "File of --sample-store ring-file, replaced if it exists, or database of --sample-store sqlite, whose samples table is replaced",
+        required_if_eq_any([("sample_store", "ring-file"), ("sample_store", "sqlite")])
+    )]
+    pub sample_store_file: Option<PathBuf>,
+
+    #[arg(
+        long = "self-memory-cap",
+        value_name = "SIZE",
+        help = "Keep peak-mem's own RSS under SIZE (e.g., 64M) by moving the timeline to disk and forgetting exited processes, warning when over",
+        conflicts_with = "system",
+        value_parser = parse_threshold
+    )]
+    pub self_memory_cap: Option<ByteSize>,
+
+    #[arg(
+        long = "stream",
+        value_name = "FILE|-",
+        num_args = 0..=1,
+        default_missing_value = "-",
+        help = "Write each sample as a line of JSON as it is taken, to FILE or standard output (the default)"
+    )]
+    pub stream: Option<PathBuf>,
+
+    #[arg(
+        long = "stream-socket",
+        value_name = "PATH",
+        help = "Send each sample as a line of JSON to the clients of a Unix domain socket at PATH, removed when monitoring ends"
+    )]
+    pub stream_socket: Option<PathBuf>,
+
+    #[arg(
+        long = "runs",
+        value_name = "N",
+        default_value = "1",
+        help = "Run the command N times and report the run with the highest peak",
+        conflicts_with_all = &["pids", "container", "unit", "system"],
+        value_parser = parse_runs
+    )]
+    pub runs: u32,
+
+    #[arg(
+        long = "timeline-envelope",
+        value_name = "FILE",
+        help = "Write the min/median/max RSS across --runs at each point of their duration to FILE"
+    )]
+    pub timeline_envelope: Option<PathBuf>,
+
     #[arg(
         long = "interval",
         value_name = "MS",
@@ -117,6 +547,60 @@ pub struct Cli {
     )]
     pub interval: u64,
 
+    #[arg(
+        long = "pressure-backoff",
+        help = "Sample less often while system memory pressure is high (Linux PSI)",
+        action = ArgAction::SetTrue
+    )]
+    pub pressure_backoff: bool,
+
+    #[arg(
+        long = "thermal",
+        help = "Record CPU frequency, thermal throttling, temperature and RAPL energy during the run (Linux)",
+        action = ArgAction::SetTrue
+    )]
+    pub thermal: bool,
+
+    #[arg(
+        long = "gpu",
+        help = "Also track per-process GPU memory through NVML (NVIDIA, Linux)",
+        action = ArgAction::SetTrue
+    )]
+    pub gpu: bool,
+
+    #[cfg(all(feature = "perf", target_os = "linux"))]
+    #[arg(
+        long = "perf",
+        help = "Count page faults and mmap/brk calls with perf counters",
+        action = ArgAction::SetTrue
+    )]
+    pub perf: bool,
+
+    #[arg(
+        long = "gpu-backend",
+        value_name = "BACKEND",
+        help = "GPU library to use: auto, nvidia (NVML) or rocm (ROCm SMI); implies --gpu",
+        value_parser = parse_gpu_backend
+    )]
+    pub gpu_backend: Option<GpuBackend>,
+
+    #[arg(
+        long = "runtime-heap",
+        value_name = "RUNTIME",
+        help = "Also record the managed heap of a runtime in the tree: jvm, go or python (repeatable)",
+        action = ArgAction::Append,
+        value_parser = parse_runtime
+    )]
+    pub runtime_heap: Vec<Runtime>,
+
+    #[arg(
+        long = "sandbox",
+        help = "Run the command without network, on a read-only filesystem but for the working directory and /tmp, under a seccomp filter (Linux)",
+        requires = "command",
+        action = ArgAction::SetTrue
+    )]
+    pub sandbox: bool,
+
     #[arg(
         long = "units",
         value_name = "UNIT",
@@ -125,22 +609,124 @@ pub struct Cli {
     )]
     pub units: Option<MemoryUnit>,
 
+    #[arg(
+        long = "color",
+        value_name = "WHEN",
+        help = "Color the human output: auto (on a terminal, unless NO_COLOR is set), always or never",
+        default_value = "auto",
+        value_parser = parse_color
+    )]
+    pub color: ColorChoice,
+
+    #[arg(
+        long = "no-color",
+        help = "Don't color the output, as --color never",
+        conflicts_with = "color",
+        action = ArgAction::SetTrue
+    )]
+    pub no_color: bool,
+
+    #[arg(
+        long = "serve-metrics",
+        value_name = "ADDR",
+        help = "Serve current and peak RSS/VSZ for Prometheus at http://ADDR/metrics during the run (e.g., 127.0.0.1:9100)",
+        conflicts_with = "system"
+    )]
+    pub serve_metrics: Option<String>,
+
+    #[arg(
+        long = "timestamp-format",
+        value_name = "FORMAT",
+        help = "Write timestamps in human, CSV, --fields and timeline output as rfc3339, unix or relative (seconds since start)",
+        value_parser = parse_timestamp_format
+    )]
+    pub timestamp_format: Option<TimestampFormat>,
+
     #[arg(
         long = "save-baseline",
         value_name = "NAME",
-        help = "Save the result as a baseline with the given name",
-        conflicts_with = "compare_baseline"
+        help = "Save the result as a baseline with the given name ('auto' names it from git)"
     )]
     pub save_baseline: Option<String>,
 
     #[arg(
-        long = "compare-baseline",
+        long = "append-to-baseline",
         value_name = "NAME",
-        help = "Compare results against a saved baseline",
+        help = "Add the result to the runs of a baseline, compared against by their mean and variance ('auto' names it from git)",
         conflicts_with = "save_baseline"
     )]
+    pub append_to_baseline: Option<String>,
+
+    #[arg(
+        long = "baseline-window",
+        value_name = "N",
+        default_value_t = baseline::DEFAULT_WINDOW,
+        help = "Keep the N most recent runs of --append-to-baseline",
+        requires = "append_to_baseline",
+        value_parser = parse_window
+    )]
+    pub baseline_window: usize,
+
+    #[arg(
+        long = "keep-history",
+        help = "Add each save of a baseline to its history instead of overwriting it, for --trend",
+        conflicts_with = "append_to_baseline"
+    )]
+    pub keep_history: bool,
+
+    #[arg(
+        long = "compare-baseline",
+        value_name = "NAME",
+        help = "Compare results against a saved baseline ('auto' picks it from git, see --baseline-scheme)",
+        group = "comparison"
+    )]
     pub compare_baseline: Option<String>,
 
+    #[arg(
+        long = "compare-result",
+        value_name = "FILE",
+        help = "Compare results against a run saved with --json, such as another CI job's artifact, as if it were a baseline",
+        group = "comparison",
+        conflicts_with_all = &["list_baselines", "show_baseline", "rename_baseline", "export_baseline", "import_baseline", "delete_baseline", "diff_trees", "bench_self", "plot", "diff", "render", "merge"]
+    )]
+    pub compare_result: Option<PathBuf>,
+
+    #[arg(
+        long = "baseline-scheme",
+        value_name = "TEMPLATE",
+        default_value = git::DEFAULT_SCHEME,
+        help = "How 'auto' baseline names are built from git: {branch} and {commit} are replaced"
+    )]
+    pub baseline_scheme: String,
+
+    #[arg(
+        long = "save-if-improved",
+        help = "After comparing, save the baseline only if peak RSS decreased without a regression",
+        requires = "compare_baseline",
+        conflicts_with = "save_if_no_regression"
+    )]
+    pub save_if_improved: bool,
+
+    #[arg(
+        long = "save-if-no-regression",
+        help = "After comparing, save the baseline only if no regression was detected",
+        requires = "compare_baseline",
+        conflicts_with = "save_if_improved"
+    )]
+    pub save_if_no_regression: bool,
+
+    #[arg(
+        long = "update-baseline-if-improved",
+        value_name = "PERCENT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "0",
+        help = "After comparing, replace the baseline if peak RSS decreased, or rose by at most PERCENT (default: 0), without a regression",
+        requires = "compare_baseline",
+        conflicts_with_all = &["save_if_improved", "save_if_no_regression"]
+    )]
+    pub update_baseline_if_improved: Option<f64>,
+
     #[arg(
         long = "regression-threshold",
         value_name = "PERCENT",
@@ -149,6 +735,184 @@ pub struct Cli {
     )]
     pub regression_threshold: f64,
 
+    #[arg(
+        long = "noise-sigmas",
+        value_name = "K",
+        default_value_t = baseline::NOISE_SIGMAS,
+        help = "Against a baseline of several runs that vary, only consider peak RSS a regression above their mean plus K standard deviations as well as by --regression-threshold",
+        value_parser = parse_noise_sigmas
+    )]
+    pub noise_sigmas: f64,
+
+    #[arg(
+        long = "fault-regression-threshold",
+        value_name = "PERCENT",
+        help = "Major page fault increase percentage to also consider as regression"
+    )]
+    pub fault_regression_threshold: Option<f64>,
+
+    #[arg(
+        long = "cpu-regression-threshold",
+        value_name = "PERCENT",
+        help = "CPU time increase percentage to also consider as regression"
+    )]
+    pub cpu_regression_threshold: Option<f64>,
+
+    #[arg(
+        long = "gpu-regression-threshold",
+        value_name = "PERCENT",
+        help = "Peak GPU memory increase percentage to also consider as regression"
+    )]
+    pub gpu_regression_threshold: Option<f64>,
+
+    #[arg(
+        long = "ignore-below",
+        value_name = "SIZE",
+        help = "Treat memory differences smaller than SIZE (e.g., 2M) as no change when comparing",
+        value_parser = parse_threshold
+    )]
+    pub ignore_below: Option<ByteSize>,
+
+    #[arg(
+        long = "regression-report",
+        value_name = "FILE",
+        help = "If a regression is detected, write a Markdown report for filing an issue to FILE",
+        requires = "comparison"
+    )]
+    pub regression_report: Option<PathBuf>,
+
+    #[arg(
+        long = "report",
+        value_name = "FILE",
+        help = "Write a self-contained HTML report with the timeline chart and process tree to FILE"
+    )]
+    pub report: Option<PathBuf>,
+
+    #[arg(
+        long = "chart",
+        value_name = "FILE",
+        help = "Write an SVG chart of the RSS and VSZ timeline with the peak marked to FILE"
+    )]
+    pub chart: Option<PathBuf>,
+
+    #[arg(
+        long = "chart-term",
+        help = "Draw a chart of RSS over the run in the terminal after the results"
+    )]
+    pub chart_term: bool,
+
+    #[arg(
+        long = "prom-file",
+        value_name = "FILE",
+        help = "Write the results as Prometheus metrics to FILE, for the node_exporter textfile collector"
+    )]
+    pub prom_file: Option<PathBuf>,
+
+    #[arg(
+        long = "influx-file",
+        value_name = "FILE",
+        help = "Write the timeline and peak as InfluxDB line protocol to FILE"
+    )]
+    pub influx_file: Option<PathBuf>,
+
+    #[arg(
+        long = "json-file",
+        value_name = "FILE",
+        help = "Also write the JSON results to FILE; in CI they go to peak-mem.json by default"
+    )]
+    pub json_file: Option<PathBuf>,
+
+    #[arg(
+        long = "no-json-file",
+        help = "Don't write the JSON results to peak-mem.json in CI",
+        conflicts_with = "json_file"
+    )]
+    pub no_json_file: bool,
+
+    #[arg(
+        long = "append-log",
+        value_name = "PATH",
+        help = "Append the results to PATH as one line of JSON, building a history of runs"
+    )]
+    pub append_log: Option<PathBuf>,
+
+    #[arg(
+        long = "archive",
+        value_name = "DIR",
+        help = "Keep the full results of each run, timeline included, in DIR/<command hash>/<timestamp>.json"
+    )]
+    pub archive: Option<PathBuf>,
+
+    #[arg(
+        long = "badge",
+        value_name = "FILE",
+        help = "Write an SVG badge of the peak RSS to FILE, green or red with --compare-baseline"
+    )]
+    pub badge: Option<PathBuf>,
+
+    #[arg(
+        long = "github",
+        help = "Annotate a GitHub Actions run with tripped thresholds and regressions and add the results to its job summary"
+    )]
+    pub github: bool,
+
+    #[arg(
+        long = "pushgateway",
+        value_name = "URL",
+        help = "Push the results to the Prometheus Pushgateway at URL (http:// only)",
+        value_parser = parse_http_url
+    )]
+    pub pushgateway: Option<HttpUrl>,
+
+    #[arg(
+        long = "push-job",
+        value_name = "NAME",
+        default_value = "peak-mem",
+        help = "Job label of the metrics pushed with --pushgateway",
+        requires = "pushgateway"
+    )]
+    pub push_job: String,
+
+    #[arg(
+        long = "push-instance",
+        value_name = "NAME",
+        help = "Instance label of the metrics pushed with --pushgateway [default: host name]",
+        requires = "pushgateway"
+    )]
+    pub push_instance: Option<String>,
+
+    #[arg(
+        long = "otlp-endpoint",
+        value_name = "URL",
+        help = "Export the timeline and peak as OpenTelemetry metrics to the OTLP/HTTP endpoint at URL (http:// only)",
+        value_parser = parse_http_url
+    )]
+    pub otlp_endpoint: Option<HttpUrl>,
+
+    #[arg(
+        long = "fingerprint",
+        help = "Record a fingerprint of the environment and compare it against baselines",
+        action = ArgAction::SetTrue
+    )]
+    pub fingerprint: bool,
+
+    #[arg(
+        long = "probe",
+        value_name = "COMMAND",
+        help = "Record this command's output in the fingerprint, e.g. 'rustc --version' (repeatable)",
+        action = ArgAction::Append
+    )]
+    pub probes: Vec<String>,
+
+    #[arg(
+        long = "meta",
+        value_name = "KEY=VALUE",
+        help = "Tag the run, e.g. profile=release, in --json results and saved baselines; with --list-baselines or --query-history, list only baselines or runs tagged so (repeatable)",
+        action = ArgAction::Append,
+        value_parser = parse_meta
+    )]
+    pub meta: Vec<(String, String)>,
+
     #[arg(
         long = "baseline-dir",
         value_name = "DIR",
@@ -156,21 +920,172 @@ pub struct Cli {
     )]
     pub baseline_dir: Option<PathBuf>,
 
+    #[arg(
+        long = "baseline-file",
+        value_name = "PATH",
+        help = "Keep the baseline in this file instead of the baseline directory, e.g. to commit it with the project",
+        conflicts_with_all = &["list_baselines", "rename_baseline", "import_baseline"]
+    )]
+    pub baseline_file: Option<PathBuf>,
+
+    #[arg(
+        long = "no-platform-scope",
+        help = "Don't keep baselines apart per OS and architecture, and compare against another platform's with a warning instead of an error",
+        action = ArgAction::SetTrue
+    )]
+    pub no_platform_scope: bool,
+
     #[arg(
         long = "list-baselines",
-        help = "List all saved baselines and exit",
-        conflicts_with_all = &["command", "save_baseline", "compare_baseline"]
+        help = "List all saved baselines with their command, peak RSS, duration, platform and creation time, and exit",
+        conflicts_with_all = &["command", "pids", "container", "unit", "save_baseline", "compare_baseline"]
     )]
     pub list_baselines: bool,
 
+    #[arg(
+        long = "show-baseline",
+        value_name = "NAME",
+        help = "Print the details of a saved baseline, all of them with --json, and exit",
+        conflicts_with_all = &["command", "pids", "container", "unit", "save_baseline", "compare_baseline", "list_baselines", "delete_baseline"]
+    )]
+    pub show_baseline: Option<String>,
+
+    #[arg(
+        long = "rename-baseline",
+        value_names = ["OLD", "NEW"],
+        num_args = 2,
+        help = "Rename a saved baseline and exit",
+        conflicts_with_all = &["command", "pids", "container", "unit", "save_baseline", "compare_baseline", "list_baselines", "show_baseline", "delete_baseline"]
+    )]
+    pub rename_baseline: Vec<String>,
+
+    #[arg(
+        long = "export-baseline",
+        value_names = ["NAME", "FILE"],
+        num_args = 2,
+        help = "Write a saved baseline to FILE, to commit or share, and exit",
+        conflicts_with_all = &["command", "pids", "container", "unit", "save_baseline", "compare_baseline", "list_baselines", "show_baseline", "rename_baseline", "delete_baseline"]
+    )]
+    pub export_baseline: Vec<String>,
+
+    #[arg(
+        long = "import-baseline",
+        value_names = ["FILE", "NAME"],
+        num_args = 1..=2,
+        help = "Save a baseline exported with --export-baseline, as NAME or the file's name, and exit",
+        conflicts_with_all = &["command", "pids", "container", "unit", "save_baseline", "compare_baseline", "list_baselines", "show_baseline", "rename_baseline", "export_baseline", "delete_baseline"]
+    )]
+    pub import_baseline: Vec<String>,
+
+    #[arg(
+        long = "trend",
+        value_names = ["NAME", "N"],
+        num_args = 1..=2,
+        help = "Show how peak RSS and duration of a baseline evolved over its last N saves (default: 10) and exit",
+        conflicts_with_all = &["command", "pids", "container", "unit", "save_baseline", "append_to_baseline", "compare_baseline", "compare_result", "list_baselines", "show_baseline", "rename_baseline", "export_baseline", "import_baseline", "delete_baseline"]
+    )]
+    pub trend: Vec<String>,
+
     #[arg(
         long = "delete-baseline",
         value_name = "NAME",
         help = "Delete a saved baseline and exit",
-        conflicts_with_all = &["command", "save_baseline", "compare_baseline", "list_baselines"]
+        conflicts_with_all = &["command", "pids", "container", "unit", "save_baseline", "compare_baseline", "list_baselines"]
     )]
     pub delete_baseline: Option<String>,
 
+    #[arg(
+        long = "diff-trees",
+        value_names = ["A", "B"],
+        num_args = 2,
+        help = "Compare the peak process trees of two --json --verbose results per process and exit",
+        conflicts_with_all = &["command", "pids", "container", "unit", "system", "save_baseline", "compare_baseline", "list_baselines", "delete_baseline", "influx", "tap", "fields", "template"]
+    )]
+    pub diff_trees: Vec<PathBuf>,
+
+    #[arg(
+        long = "bench-self",
+        help = "Measure the cost of sampling synthetic process trees on this machine and exit",
+        conflicts_with_all = &["command", "pids", "container", "unit", "system", "save_baseline", "compare_baseline", "list_baselines", "delete_baseline", "diff_trees", "quiet", "influx", "tap", "fields", "template"]
+    )]
+    pub bench_self: bool,
+
+    #[arg(
+        long = "plot",
+        value_names = ["TIMELINE", "OTHER"],
+        num_args = 1..=2,
+        help = "Chart a saved timeline in the terminal, or as SVG with --chart, next to OTHER if given, and exit",
+        conflicts_with_all = &["command", "pids", "container", "unit", "system", "save_baseline", "compare_baseline", "list_baselines", "delete_baseline", "diff_trees", "bench_self", "json", "csv", "quiet", "influx", "tap", "fields", "template", "format"]
+    )]
+    pub plot: Vec<PathBuf>,
+
+    #[arg(
+        long = "diff",
+        value_names = ["A", "B"],
+        num_args = 2,
+        help = "Compare two saved --json results as --compare-baseline would, with A as the baseline, and exit",
+        conflicts_with_all = &["command", "pids", "container", "unit", "system", "save_baseline", "append_to_baseline", "compare_baseline", "list_baselines", "delete_baseline", "diff_trees", "bench_self", "plot"]
+    )]
+    pub diff: Vec<PathBuf>,
+
+    #[arg(
+        long = "render",
+        value_name = "RESULT",
+        help = "Output a result or comparison saved with --json in the chosen format and reports, and exit",
+        conflicts_with_all = &["command", "pids", "container", "unit", "system", "save_baseline", "append_to_baseline", "compare_baseline", "list_baselines", "delete_baseline", "diff_trees", "bench_self", "plot", "diff"]
+    )]
+    pub render: Option<PathBuf>,
+
+    #[arg(
+        long = "merge",
+        value_name = "RESULT",
+        num_args = 1..,
+        help = "Print the min, mean, max and standard deviation of peak RSS and duration over runs saved with --json, and exit",
+        conflicts_with_all = &["command", "pids", "container", "unit", "system", "save_baseline", "append_to_baseline", "compare_baseline", "list_baselines", "delete_baseline", "diff_trees", "bench_self", "plot", "diff", "render", "output", "tap", "fields", "template"]
+    )]
+    pub merge: Vec<PathBuf>,
+
+    #[arg(
+        long = "history-db",
+        value_name = "PATH",
+        help = "Record the run in a SQLite database, created if needed, for --query-history (needs the sqlite feature)"
+    )]
+    pub history_db: Option<PathBuf>,
+
+    #[arg(
+        long = "query-history",
+        help = "List the runs recorded in --history-db that match --history-command, --since, --until and --meta, and exit",
+        requires = "history_db",
+        conflicts_with_all = &["command", "pids", "container", "unit", "system", "save_baseline", "append_to_baseline", "compare_baseline", "compare_result", "list_baselines", "show_baseline", "rename_baseline", "export_baseline", "import_baseline", "trend", "delete_baseline", "diff_trees", "bench_self", "plot", "diff", "render", "merge", "tap", "fields", "template"]
+    )]
+    pub query_history: bool,
+
+    #[arg(
+        long = "history-command",
+        value_name = "TEXT",
+        help = "With --query-history, list only runs whose command line contains TEXT",
+        requires = "query_history"
+    )]
+    pub history_command: Option<String>,
+
+    #[arg(
+        long = "since",
+        value_name = "DATE",
+        help = "With --query-history, list only runs recorded on or after DATE (YYYY-MM-DD or RFC 3339, UTC)",
+        requires = "query_history",
+        value_parser = parse_date
+    )]
+    pub since: Option<Timestamp>,
+
+    #[arg(
+        long = "until",
+        value_name = "DATE",
+        help = "With --query-history, list only runs recorded before DATE (YYYY-MM-DD or RFC 3339, UTC)",
+        requires = "query_history",
+        value_parser = parse_date
+    )]
+    pub until: Option<Timestamp>,
+
     #[arg(short = 'V', help = "Short version")]
     pub short_version: bool,
 
@@ -182,6 +1097,97 @@ fn parse_threshold(s: &str) -> Result<ByteSize> {
     s.parse::<ByteSize>()
 }
 
+fn parse_field(s: &str) -> Result<Field> {
+    s.parse()
+}
+
+fn parse_template(s: &str) -> Result<Template> {
+    Template::load(s)
+}
+
+fn parse_format(s: &str) -> Result<Format> {
+    s.parse()
+}
+
+fn parse_threshold_action(s: &str) -> Result<ThresholdAction> {
+    s.parse::<ThresholdAction>()
+}
+
+fn parse_aggregate(s: &str) -> Result<Aggregate> {
+    s.parse()
+}
+
+/// Metadata keys peak-mem records itself, which tags can't replace.
+const RESERVED_META_KEYS: [&str; 3] = ["platform", "arch", "main_pid"];
+
+fn parse_meta(s: &str) -> Result<(String, String)> {
+    let (key, value) = s
+        .split_once('=')
+        .filter(|(key, _)| !key.trim().is_empty())
+        .ok_or_else(|| {
+            PeakMemError::InvalidArgument(format!("Invalid tag '{s}'. Use KEY=VALUE"))
+        })?;
+    let key = key.trim();
+    if RESERVED_META_KEYS.contains(&key) || key.starts_with("git_") {
+        return Err(PeakMemError::InvalidArgument(format!(
+            "'{key}' is recorded by peak-mem itself and can't be used as a tag"
+        )));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Parses a date as midnight UTC, or an RFC 3339 timestamp.
+fn parse_date(s: &str) -> Result<Timestamp> {
+    let timestamp = if s.len() == 10 {
+        Timestamp::parse_rfc3339(&format!("{s}T00:00:00Z"))
+    } else {
+        Timestamp::parse_rfc3339(s)
+    };
+    timestamp.map_err(|_| {
+        PeakMemError::InvalidArgument(format!(
+            "Invalid date '{s}'. Use YYYY-MM-DD or an RFC 3339 timestamp"
+        ))
+    })
+}
+
+fn parse_regex(s: &str) -> Result<Regex> {
+    Regex::new(s).map_err(|e| PeakMemError::InvalidArgument(format!("Invalid pattern '{s}': {e}")))
+}
+
+fn parse_steady_after(s: &str) -> Result<Duration> {
+    let invalid = || {
+        PeakMemError::InvalidArgument(format!(
+            "Invalid duration '{s}'. Use a number with ms, s or m, e.g. 30s"
+        ))
+    };
+    let (number, scale) = if let Some(ms) = s.strip_suffix("ms") {
+        (ms, 0.001)
+    } else if let Some(secs) = s.strip_suffix('s') {
+        (secs, 1.0)
+    } else if let Some(mins) = s.strip_suffix('m') {
+        (mins, 60.0)
+    } else {
+        (s, 1.0)
+    };
+    let value: f64 = number.trim().parse().map_err(|_| invalid())?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs_f64(value * scale))
+}
+
+fn parse_http_url(s: &str) -> Result<HttpUrl> {
+    HttpUrl::parse(s)
+}
+
+fn parse_gpu_backend(s: &str) -> Result<GpuBackend> {
+    s.parse()
+}
+
+fn parse_runtime(s: &str) -> Result<Runtime> {
+    s.parse()
+}
+
 fn parse_interval(s: &str) -> Result<u64> {
     let interval: u64 = s.parse()?;
     if interval == 0 {
@@ -192,6 +1198,74 @@ fn parse_interval(s: &str) -> Result<u64> {
     Ok(interval)
 }
 
+fn parse_duration(s: &str) -> Result<u64> {
+    let secs: u64 = s.parse()?;
+    if secs == 0 {
+        return Err(PeakMemError::InvalidArgument(
+            "Duration must be greater than zero".to_string(),
+        ));
+    }
+    Ok(secs)
+}
+
+fn parse_max_samples(s: &str) -> Result<usize> {
+    let max: usize = s.parse()?;
+    if max == 0 {
+        return Err(PeakMemError::InvalidArgument(
+            "Max samples must be greater than zero".to_string(),
+        ));
+    }
+    Ok(max)
+}
+
+fn parse_window(s: &str) -> Result<usize> {
+    let window: usize = s.parse()?;
+    if window == 0 {
+        return Err(PeakMemError::InvalidArgument(
+            "Baseline window must be greater than zero".to_string(),
+        ));
+    }
+    Ok(window)
+}
+
+fn parse_retention_policy(s: &str) -> Result<RetentionPolicy> {
+    s.parse()
+}
+
+fn parse_sample_store(s: &str) -> Result<StoreKind> {
+    s.parse()
+}
+
+fn parse_runs(s: &str) -> Result<u32> {
+    let runs: u32 = s.parse()?;
+    if runs == 0 {
+        return Err(PeakMemError::InvalidArgument(
+            "Runs must be greater than zero".to_string(),
+        ));
+    }
+    Ok(runs)
+}
+
+fn parse_noise_sigmas(s: &str) -> Result<f64> {
+    let sigmas: f64 = s.parse().map_err(|_| {
+        PeakMemError::InvalidArgument(format!("Invalid number of standard deviations '{s}'"))
+    })?;
+    if !sigmas.is_finite() || sigmas <= 0.0 {
+        return Err(PeakMemError::InvalidArgument(
+            "Standard deviations must be a number greater than zero".to_string(),
+        ));
+    }
+    Ok(sigmas)
+}
+
+fn parse_timestamp_format(s: &str) -> Result<TimestampFormat> {
+    s.parse()
+}
+
+fn parse_color(s: &str) -> Result<ColorChoice> {
+    s.parse::<ColorChoice>()
+}
+
 fn parse_units(s: &str) -> Result<MemoryUnit> {
     match s {
         "B" => Ok(MemoryUnit::Bytes),
@@ -208,23 +1282,290 @@ fn parse_units(s: &str) -> Result<MemoryUnit> {
 }
 
 impl Cli {
+    /// Parses the command line, subcommand shorthands included, exiting
+    /// with a usage error if it is invalid.
+    pub fn parse_args() -> Self {
+        match expand_subcommand(std::env::args_os().collect()) {
+            Ok(args) => {
+                let mut cli = Self::parse_from(args);
+                if let Err(message) = cli.apply_format() {
+                    Self::command()
+                        .error(clap::error::ErrorKind::ArgumentConflict, message)
+                        .exit()
+                }
+                cli
+            }
+            Err(message) => Self::command()
+                .error(clap::error::ErrorKind::InvalidSubcommand, message)
+                .exit(),
+        }
+    }
+
+    /// Sets the flag a built-in `--format` stands for, after checking what
+    /// clap would have for the flag.
+    ///
+    /// # Errors
+    /// * A message for a format the other options can't be used with
+    fn apply_format(&mut self) -> std::result::Result<(), String> {
+        let Some(format) = &self.format else {
+            return Ok(());
+        };
+        let name = format.name();
+        if self.verbose && matches!(format, Format::Quiet | Format::Tap) {
+            return Err(format!(
+                "the argument '--format {name}' cannot be used with '--verbose'"
+            ));
+        }
+        let (mode, formats): (&str, &[&str]) = if self.bench_self {
+            ("--bench-self", &["human", "json", "csv"])
+        } else if !self.diff_trees.is_empty() {
+            ("--diff-trees", &["human", "json", "csv", "quiet"])
+        } else if !self.merge.is_empty() {
+            ("--merge", &["human", "json", "csv", "quiet", "influx"])
+        } else if self.query_history {
+            (
+                "--query-history",
+                &["human", "json", "csv", "quiet", "influx"],
+            )
+        } else {
+            ("", &[])
+        };
+        if !mode.is_empty() && !formats.contains(&name) {
+            return Err(format!(
+                "the argument '--format {name}' cannot be used with '{mode}'"
+            ));
+        }
+
+        match format {
+            Format::Json => self.json = true,
+            Format::Csv => self.csv = true,
+            Format::Quiet => self.quiet = true,
+            Format::Influx => self.influx = true,
+            Format::Tap => self.tap = true,
+            Format::Human | Format::Prometheus | Format::Exec(_) => {}
+        }
+        Ok(())
+    }
+
+    /// The formatter of `--format exec:COMMAND`.
+    pub fn exec_format(&self) -> Option<&ExecFormat> {
+        match &self.format {
+            Some(Format::Exec(format)) => Some(format),
+            _ => None,
+        }
+    }
+
+    /// Whether `--format prometheus` was given.
+    pub fn prometheus_output(&self) -> bool {
+        self.format == Some(Format::Prometheus)
+    }
+
+    /// Returns the regression thresholds for baseline comparisons.
+    pub fn regression_thresholds(&self) -> RegressionThresholds {
+        RegressionThresholds {
+            rss_percent: self.regression_threshold,
+            major_faults_percent: self.fault_regression_threshold,
+            cpu_time_percent: self.cpu_regression_threshold,
+            gpu_percent: self.gpu_regression_threshold,
+            ignore_below_bytes: self.ignore_below.map(|size| size.as_u64()),
+            noise_sigmas: self.noise_sigmas,
+        }
+    }
+
+    /// Returns the name to save a baseline under, or append to, if any.
+    ///
+    /// With a conditional save flag and no explicit `--save-baseline` or
+    /// `--append-to-baseline`, the compared baseline itself is updated.
+    pub fn save_target(&self) -> Option<&str> {
+        if let Some(name) = self
+            .save_baseline
+            .as_ref()
+            .or(self.append_to_baseline.as_ref())
+        {
+            return Some(name);
+        }
+        if self.save_if_improved
+            || self.save_if_no_regression
+            || self.update_baseline_if_improved.is_some()
+        {
+            return self.compare_baseline.as_deref();
+        }
+        None
+    }
+
+    /// Returns the GPU backend to use, if GPU tracking is enabled;
+    /// `--gpu-backend` implies `--gpu`.
+    pub fn gpu_backend(&self) -> Option<GpuBackend> {
+        self.gpu_backend.or(if self.gpu {
+            Some(GpuBackend::Auto)
+        } else {
+            None
+        })
+    }
+
+    /// Whether the timeline of a run is needed, to be saved or exported.
+    pub fn keeps_timeline(&self) -> bool {
+        self.timeline.is_some()
+            || self.html_report_wanted()
+            || self.chart.is_some()
+            || self.chart_term
+            || self.otlp_endpoint.is_some()
+            || self.influx
+            || self.influx_file.is_some()
+    }
+
+    /// Whether to fingerprint the environment; any `--probe` implies
+    /// `--fingerprint`.
+    pub fn fingerprint_enabled(&self) -> bool {
+        self.fingerprint || !self.probes.is_empty()
+    }
+
+    /// Whether an HTML report is to be written, with `--report` or as the
+    /// `--output`.
+    pub fn html_report_wanted(&self) -> bool {
+        self.report.is_some() || self.output_kind() == Some(OutputKind::Html)
+    }
+
+    /// The format the extension of the `--output` file implies, unless a
+    /// format flag chose one.
+    pub fn output_kind(&self) -> Option<OutputKind> {
+        let chosen = self.json
+            || self.csv
+            || self.quiet
+            || self.influx
+            || self.tap
+            || !self.fields.is_empty()
+            || self.template.is_some()
+            || self.format.is_some();
+        if chosen {
+            return None;
+        }
+        let extension = self.output.as_deref()?.extension()?.to_str()?;
+        match extension.to_ascii_lowercase().as_str() {
+            "json" => Some(OutputKind::Json),
+            "csv" => Some(OutputKind::Csv),
+            "md" | "markdown" => Some(OutputKind::Markdown),
+            "html" | "htm" => Some(OutputKind::Html),
+            _ => None,
+        }
+    }
+
+    /// When to color the output, by `--color` and `--no-color`.
+    pub fn color_choice(&self) -> ColorChoice {
+        if self.no_color {
+            ColorChoice::Never
+        } else {
+            self.color
+        }
+    }
+
+    /// The format of what `what` prints instead of the results of a run,
+    /// which only comes in those of [`OutputFormat`].
+    ///
+    /// # Errors
+    /// * `PeakMemError::InvalidArgument` - Another format was chosen
+    pub fn summary_format(&self, what: &str) -> Result<OutputFormat> {
+        let option = match &self.format {
+            Some(Format::Tap) => Some("--format tap"),
+            Some(Format::Prometheus) => Some("--format prometheus"),
+            Some(Format::Exec(_)) => Some("--format exec:COMMAND"),
+            _ if self.tap => Some("--tap"),
+            _ if !self.fields.is_empty() => Some("--fields"),
+            _ if self.template.is_some() => Some("--template"),
+            _ => None,
+        };
+        match option {
+            Some(option) => Err(output::unsupported_format(option, what)),
+            None => Ok(self.output_format()),
+        }
+    }
+
     pub fn output_format(&self) -> OutputFormat {
-        if self.json {
+        if self.json || self.output_kind() == Some(OutputKind::Json) {
             OutputFormat::Json
-        } else if self.csv {
+        } else if self.csv || self.output_kind() == Some(OutputKind::Csv) {
             OutputFormat::Csv
         } else if self.quiet {
             OutputFormat::Quiet
+        } else if self.influx {
+            OutputFormat::Influx
         } else {
             OutputFormat::Human
         }
     }
 }
 
+/// A format implied by the extension of the `--output` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputKind {
+    Json,
+    Csv,
+    /// A Markdown table of the results, as in a GitHub job summary.
+    Markdown,
+    /// The HTML report of `--report`.
+    Html,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OutputFormat {
     Human,
     Json,
     Csv,
     Quiet,
+    Influx,
+}
+
+/// A format for `--format`. The built-in ones but prometheus also have
+/// flags of their own, which they stand for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Format {
+    Human,
+    Json,
+    Csv,
+    Quiet,
+    Influx,
+    Tap,
+    /// The metrics of `--prom-file`.
+    Prometheus,
+    Exec(ExecFormat),
+}
+
+impl Format {
+    /// The name `--format` takes.
+    fn name(&self) -> &'static str {
+        match self {
+            Format::Human => "human",
+            Format::Json => "json",
+            Format::Csv => "csv",
+            Format::Quiet => "quiet",
+            Format::Influx => "influx",
+            Format::Tap => "tap",
+            Format::Prometheus => "prometheus",
+            Format::Exec(_) => "exec",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = PeakMemError;
+
+    /// Parses a built-in format's name, or `exec:COMMAND`.
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "human" => Format::Human,
+            "json" => Format::Json,
+            "csv" => Format::Csv,
+            "quiet" => Format::Quiet,
+            "influx" => Format::Influx,
+            "tap" => Format::Tap,
+            "prometheus" => Format::Prometheus,
+            _ if s.starts_with("exec:") => Format::Exec(s.parse()?),
+            _ => {
+                return Err(PeakMemError::InvalidArgument(format!(
+                    "Invalid format '{s}'. Use human, json, csv, quiet, influx, tap, \
+                     prometheus or exec:COMMAND"
+                )))
+            }
+        })
+    }
 }
@@ -0,0 +1,81 @@
+//! Finding the processes of a Docker or Podman container.
+//!
+//! `--container` takes a container name or ID, asks the container runtime
+//! for the PID of its init process, and monitors that process's tree and
+//! the memory charged to its cgroup. Docker is tried first, then Podman.
+
+use crate::types::{PeakMemError, Result};
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+/// Runtimes to ask, in order.
+const RUNTIMES: [&str; 2] = ["docker", "podman"];
+
+/// A running container.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Container {
+    /// The name or ID the container was given by.
+    pub name: String,
+    /// The runtime that knows the container, `docker` or `podman`.
+    pub runtime: String,
+    /// PID of the container's init process, as seen from the host.
+    pub pid: u32,
+}
+
+/// Looks up a running container by name or ID.
+///
+/// # Errors
+/// * `PeakMemError::InvalidArgument` - No runtime knows the container, or it
+///   isn't running
+pub fn resolve(name: &str) -> Result<Container> {
+    let mut failures = Vec::new();
+
+    for runtime in RUNTIMES {
+        let output = match Command::new(runtime)
+            .args([
+                "inspect",
+                "--type",
+                "container",
+                "--format",
+                "{{.State.Pid}}",
+            ])
+            .arg(name)
+            .output()
+        {
+            Ok(output) => output,
+            // Not installed
+            Err(_) => continue,
+        };
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            failures.push(format!("{runtime}: {}", stderr.trim()));
+            continue;
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        return match stdout.trim().parse::<u32>() {
+            Ok(0) => Err(PeakMemError::InvalidArgument(format!(
+                "Container '{name}' is not running"
+            ))),
+            Ok(pid) => Ok(Container {
+                name: name.to_string(),
+                runtime: runtime.to_string(),
+                pid,
+            }),
+            Err(_) => Err(PeakMemError::InvalidArgument(format!(
+                "{runtime} inspect returned no PID for '{name}': {}",
+                stdout.trim()
+            ))),
+        };
+    }
+
+    Err(PeakMemError::InvalidArgument(if failures.is_empty() {
+        format!("Cannot look up container '{name}': neither docker nor podman is installed")
+    } else {
+        format!(
+            "Cannot look up container '{name}' ({})",
+            failures.join("; ")
+        )
+    }))
+}
@@ -0,0 +1,123 @@
+//! Checking which processes a run spawned against a previous run.
+//!
+//! A build step that is silently skipped (a cached target, a missing
+//! tool) makes the tree use less memory, which looks like an improvement
+//! in a baseline comparison. `--expect-processes-from` reads the process
+//! names recorded in an earlier `--json` result and warns about any that
+//! are missing from this run, or new in it.
+
+use crate::types::{PeakMemError, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// Differences between the processes of a previous run and this one.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ProcessSetDiff {
+    /// Seen in the previous run but not in this one.
+    pub missing: Vec<String>,
+    /// Seen in this run but not in the previous one.
+    pub unexpected: Vec<String>,
+}
+
+impl ProcessSetDiff {
+    /// Compares the expected process names against those observed.
+    pub fn new(expected: &BTreeSet<String>, observed: &BTreeSet<String>) -> Self {
+        Self {
+            missing: expected.difference(observed).cloned().collect(),
+            unexpected: observed.difference(expected).cloned().collect(),
+        }
+    }
+
+    /// Whether both runs saw the same processes.
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty()
+    }
+
+    /// Prints a warning line to stderr for each kind of difference.
+    pub fn warn(&self, source: &Path) {
+        if !self.missing.is_empty() {
+            eprintln!(
+                "Warning: Processes from {} did not run: {}",
+                source.display(),
+                self.missing.join(", ")
+            );
+        }
+        if !self.unexpected.is_empty() {
+            eprintln!(
+                "Warning: Processes not seen in {}: {}",
+                source.display(),
+                self.unexpected.join(", ")
+            );
+        }
+    }
+}
+
+/// Reads the process names recorded in a previous `--json` result.
+///
+/// Comparison output is accepted too, in which case the names of its
+/// current run are used.
+///
+/// # Errors
+/// * `PeakMemError::Io` / `PeakMemError::Json` - File unreadable or not JSON
+/// * `PeakMemError::InvalidArgument` - No process names recorded, e.g. the run
+///   used `--no-children`
+pub fn load_expected_processes(path: &Path) -> Result<BTreeSet<String>> {
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    parse_expected_processes(&json).ok_or_else(|| {
+        PeakMemError::InvalidArgument(format!(
+            "{} has no observed_processes (was it written with --json?)",
+            path.display()
+        ))
+    })
+}
+
+fn parse_expected_processes(json: &serde_json::Value) -> Option<BTreeSet<String>> {
+    let names = json
+        .get("observed_processes")
+        .or_else(|| json.get("current")?.get("observed_processes"))?
+        .as_array()?;
+
+    let names: BTreeSet<String> = names
+        .iter()
+        .filter_map(|name| name.as_str().map(str::to_string))
+        .collect();
+    (!names.is_empty()).then_some(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_process_set_diff() {
+        let diff = ProcessSetDiff::new(&set(&["make", "cc1", "ld"]), &set(&["make", "ld", "sh"]));
+        assert_eq!(diff.missing, vec!["cc1"]);
+        assert_eq!(diff.unexpected, vec!["sh"]);
+        assert!(!diff.is_empty());
+
+        assert!(ProcessSetDiff::new(&set(&["make"]), &set(&["make"])).is_empty());
+    }
+
+    #[test]
+    fn test_parse_expected_processes() {
+        let result = serde_json::json!({"observed_processes": ["make", "cc1"]});
+        assert_eq!(
+            parse_expected_processes(&result),
+            Some(set(&["cc1", "make"]))
+        );
+
+        let comparison = serde_json::json!({"current": {"observed_processes": ["ld"]}});
+        assert_eq!(parse_expected_processes(&comparison), Some(set(&["ld"])));
+
+        assert_eq!(parse_expected_processes(&serde_json::json!({})), None);
+        assert_eq!(
+            parse_expected_processes(&serde_json::json!({"observed_processes": []})),
+            None
+        );
+    }
+}
@@ -0,0 +1,412 @@
+//! Fingerprints of the environment a command ran in.
+//!
+//! A fingerprint records a hash of every environment variable the command
+//! inherited, plus the output of user-supplied probe commands such as
+//! `rustc --version`. Stored in baselines, it lets a comparison point out
+//! that the toolchain or environment changed, which otherwise looks the
+//! same as a regression in the code being measured.
+//!
+//! Variable values are only stored as a keyed hash. The key is random and
+//! kept in a file of its own in the cache directory, never in a baseline,
+//! so a baseline file doesn't let anyone guess the secrets in the
+//! environment it was recorded in: without the key, a value can't be
+//! tested against its hash.
+
+use crate::baseline::BaselineManager;
+use serde::{Deserialize, Serialize};
+use siphasher::sip::SipHasher24;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::hash::Hasher;
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// Variables that differ between otherwise identical shells and would
+/// make every fingerprint look changed.
+const IGNORED_VARS: &[&str] = &["_", "OLDPWD", "SHLVL"];
+
+/// The environment and tool versions a command ran with.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvFingerprint {
+    /// Identifies the key the values were hashed with, as hashes made
+    /// with different keys can't be compared.
+    #[serde(default)]
+    pub key_id: String,
+    /// Hash over all variables, for a quick equality check.
+    pub env_hash: String,
+    /// Keyed hash of each variable's value, keyed by variable name.
+    pub env: BTreeMap<String, String>,
+    /// Output of each probe command, keyed by the command.
+    #[serde(default)]
+    pub probes: BTreeMap<String, String>,
+}
+
+/// A single difference between two fingerprints.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FingerprintChange {
+    /// A variable is set now but was not in the baseline.
+    EnvAdded { name: String },
+    /// A variable set in the baseline is no longer set.
+    EnvRemoved { name: String },
+    /// A variable has a different value than in the baseline.
+    EnvChanged { name: String },
+    /// A probe's output differs, or it was only run on one side.
+    ProbeChanged {
+        command: String,
+        baseline: Option<String>,
+        current: Option<String>,
+    },
+}
+
+impl fmt::Display for FingerprintChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_none = |output: &Option<String>| {
+            output
+                .as_deref()
+                .map_or_else(|| "(not probed)".to_string(), |s| format!("'{s}'"))
+        };
+
+        match self {
+            FingerprintChange::EnvAdded { name } => write!(f, "{name} added"),
+            FingerprintChange::EnvRemoved { name } => write!(f, "{name} removed"),
+            FingerprintChange::EnvChanged { name } => write!(f, "{name} changed"),
+            FingerprintChange::ProbeChanged {
+                command,
+                baseline,
+                current,
+            } => write!(
+                f,
+                "`{command}`: {} → {}",
+                or_none(baseline),
+                or_none(current)
+            ),
+        }
+    }
+}
+
+impl EnvFingerprint {
+    /// Fingerprints this process's environment, which the monitored
+    /// command inherits, and runs each probe through `sh -c`.
+    ///
+    /// A probe's output is its trimmed stdout, or stderr if stdout is
+    /// empty (as for `java -version`). Probes that fail record the
+    /// failure instead, so a missing tool still shows up as a change.
+    ///
+    /// If the key can't be read or created, a key only this run knows is
+    /// used with a warning, so variable changes can't be told but
+    /// additions and removals still can.
+    pub async fn capture(probes: &[String]) -> Self {
+        let path = FingerprintKey::default_path();
+        let key = FingerprintKey::load_or_create(&path).or_else(|e| {
+            eprintln!(
+                "Warning: Failed to use fingerprint key {}: {e}",
+                path.display()
+            );
+            FingerprintKey::random()
+        });
+        let key = match key {
+            Ok(key) => key,
+            Err(e) => {
+                eprintln!("Warning: Failed to create a fingerprint key: {e}");
+                return Self::default();
+            }
+        };
+        let vars = std::env::vars_os().map(|(name, value)| {
+            (
+                name.to_string_lossy().into_owned(),
+                value.to_string_lossy().into_owned(),
+            )
+        });
+        let mut fingerprint = Self::from_vars(&key, vars);
+
+        for probe in probes {
+            let output = run_probe(probe).await;
+            fingerprint.probes.insert(probe.clone(), output);
+        }
+
+        fingerprint
+    }
+
+    /// Builds a fingerprint without probes from name/value pairs.
+    fn from_vars(key: &FingerprintKey, vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        let env: BTreeMap<String, String> = vars
+            .into_iter()
+            .filter(|(name, _)| !IGNORED_VARS.contains(&name.as_str()))
+            .map(|(name, value)| (name, key.hash(value.as_bytes())))
+            .collect();
+
+        let mut all = Vec::new();
+        for (name, hash) in &env {
+            all.extend_from_slice(name.as_bytes());
+            all.push(b'=');
+            all.extend_from_slice(hash.as_bytes());
+            all.push(0);
+        }
+
+        Self {
+            key_id: key.id(),
+            env_hash: key.hash(&all),
+            env,
+            probes: BTreeMap::new(),
+        }
+    }
+
+    /// Lists the differences from a baseline fingerprint: variable
+    /// changes by name, then probe changes. A baseline fingerprinted with
+    /// another key, such as one recorded on another machine, only shows
+    /// the variables added and removed.
+    pub fn diff(&self, baseline: &EnvFingerprint) -> Vec<FingerprintChange> {
+        let mut changes = Vec::new();
+        let same_key = self.key_id == baseline.key_id;
+
+        if self.env_hash != baseline.env_hash {
+            for (name, hash) in &self.env {
+                match baseline.env.get(name) {
+                    None => changes.push(FingerprintChange::EnvAdded { name: name.clone() }),
+                    Some(old) if same_key && old != hash => {
+                        changes.push(FingerprintChange::EnvChanged { name: name.clone() })
+                    }
+                    Some(_) => {}
+                }
+            }
+            for name in baseline.env.keys() {
+                if !self.env.contains_key(name) {
+                    changes.push(FingerprintChange::EnvRemoved { name: name.clone() });
+                }
+            }
+        }
+
+        let commands: std::collections::BTreeSet<&String> =
+            self.probes.keys().chain(baseline.probes.keys()).collect();
+        for command in commands {
+            let current = self.probes.get(command);
+            let old = baseline.probes.get(command);
+            if current != old {
+                changes.push(FingerprintChange::ProbeChanged {
+                    command: command.clone(),
+                    baseline: old.cloned(),
+                    current: current.cloned(),
+                });
+            }
+        }
+
+        changes
+    }
+}
+
+/// Runs a probe command and returns its output or a description of why
+/// it failed.
+async fn run_probe(probe: &str) -> String {
+    let output = match Command::new("sh").arg("-c").arg(probe).output().await {
+        Ok(output) => output,
+        Err(e) => return format!("error: {e}"),
+    };
+
+    if !output.status.success() {
+        return format!("failed: {}", output.status);
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if stdout.is_empty() {
+        String::from_utf8_lossy(&output.stderr).trim().to_string()
+    } else {
+        stdout
+    }
+}
+
+/// The secret key variable values are hashed with.
+pub struct FingerprintKey([u8; 16]);
+
+impl FingerprintKey {
+    /// Where the key is kept: next to the default baseline directory, so
+    /// that one committed with the project or given with `--baseline-dir`
+    /// never holds it.
+    pub fn default_path() -> PathBuf {
+        match BaselineManager::default_dir().parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.join("fingerprint.key"),
+            _ => PathBuf::from(".peak-mem-fingerprint.key"),
+        }
+    }
+
+    /// Reads the key from `path`, creating it, readable only by the
+    /// user, the first time.
+    ///
+    /// A file that isn't a key is left alone and reported as invalid
+    /// data: replacing it would make every baseline fingerprinted with
+    /// the old key incomparable.
+    pub fn load_or_create(path: &Path) -> io::Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => {
+                return <[u8; 16]>::try_from(bytes.as_slice())
+                    .map(Self)
+                    .map_err(|_| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("expected a key of 16 bytes, not {}", bytes.len()),
+                        )
+                    });
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let key = Self::random()?;
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?
+            .write_all(&key.0)?;
+        Ok(key)
+    }
+
+    /// A new key, from the kernel's random number generator.
+    fn random() -> io::Result<Self> {
+        let mut key = [0; 16];
+        fs::File::open("/dev/urandom")?.read_exact(&mut key)?;
+        Ok(Self(key))
+    }
+
+    /// SipHash-2-4 of `bytes` under the key, as hex.
+    fn hash(&self, bytes: &[u8]) -> String {
+        let mut hasher = SipHasher24::new_with_key(&self.0);
+        hasher.write(bytes);
+        hex(hasher.finish())
+    }
+
+    /// A hash of a fixed string, telling keys apart without revealing
+    /// them.
+    fn id(&self) -> String {
+        self.hash(b"peak-mem fingerprint key")
+    }
+}
+
+/// 64-bit FNV-1a. Baselines outlive a single build, so the hash must not
+/// depend on the Rust version the way `DefaultHasher` may.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+pub fn hex(hash: u64) -> String {
+    format!("{hash:016x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    const KEY: FingerprintKey = FingerprintKey([7; 16]);
+
+    fn vars(pairs: &[(&str, &str)]) -> EnvFingerprint {
+        EnvFingerprint::from_vars(
+            &KEY,
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string())),
+        )
+    }
+
+    #[test]
+    fn test_fnv1a_is_stable() {
+        assert_eq!(hex(fnv1a(b"")), "cbf29ce484222325");
+        assert_eq!(hex(fnv1a(b"a")), "af63dc4c8601ec8c");
+    }
+
+    #[test]
+    fn test_fingerprint_diff() {
+        let baseline = vars(&[("PATH", "/usr/bin"), ("LANG", "C"), ("SHLVL", "1")]);
+        assert!(!baseline.env.contains_key("SHLVL"));
+        assert!(!baseline.env.values().any(|hash| hash == "/usr/bin"));
+
+        let same = vars(&[("LANG", "C"), ("PATH", "/usr/bin"), ("SHLVL", "2")]);
+        assert_eq!(same.env_hash, baseline.env_hash);
+        assert!(same.diff(&baseline).is_empty());
+
+        let mut current = vars(&[("PATH", "/opt/bin"), ("RUSTFLAGS", "-O")]);
+        current
+            .probes
+            .insert("rustc --version".to_string(), "rustc 1.88.0".to_string());
+        assert_eq!(
+            current.diff(&baseline),
+            vec![
+                FingerprintChange::EnvChanged {
+                    name: "PATH".to_string()
+                },
+                FingerprintChange::EnvAdded {
+                    name: "RUSTFLAGS".to_string()
+                },
+                FingerprintChange::EnvRemoved {
+                    name: "LANG".to_string()
+                },
+                FingerprintChange::ProbeChanged {
+                    command: "rustc --version".to_string(),
+                    baseline: None,
+                    current: Some("rustc 1.88.0".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_values_are_hashed_with_the_key() {
+        let baseline = vars(&[("TOKEN", "hunter2"), ("LANG", "C")]);
+        // An unkeyed hash of the value, which could be brute-forced
+        assert_ne!(baseline.env["TOKEN"], hex(fnv1a(b"hunter2")));
+
+        let other_key = FingerprintKey([8; 16]);
+        let elsewhere = EnvFingerprint::from_vars(
+            &other_key,
+            [("TOKEN", "hunter2"), ("PATH", "/bin")]
+                .map(|(name, value)| (name.to_string(), value.to_string())),
+        );
+        assert_ne!(elsewhere.env["TOKEN"], baseline.env["TOKEN"]);
+        assert_ne!(elsewhere.key_id, baseline.key_id);
+        // Changes can't be told across keys, only additions and removals
+        assert_eq!(
+            elsewhere.diff(&baseline),
+            vec![
+                FingerprintChange::EnvAdded {
+                    name: "PATH".to_string()
+                },
+                FingerprintChange::EnvRemoved {
+                    name: "LANG".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_key_is_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("peak-mem").join("fingerprint.key");
+        let key = FingerprintKey::load_or_create(&path).unwrap();
+        assert_eq!(FingerprintKey::load_or_create(&path).unwrap().0, key.0);
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_ne!(FingerprintKey::random().unwrap().0, key.0);
+
+        // A damaged key is reported, not replaced
+        fs::write(&path, b"short").unwrap();
+        let error = FingerprintKey::load_or_create(&path).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(&path).unwrap(), b"short");
+    }
+
+    #[tokio::test]
+    async fn test_run_probe() {
+        assert_eq!(run_probe("echo '  v1.2  '").await, "v1.2");
+        assert_eq!(run_probe("echo v2 >&2").await, "v2");
+        assert!(run_probe("exit 3").await.starts_with("failed:"));
+    }
+}
@@ -0,0 +1,216 @@
+//! Choosing baseline names from git metadata.
+//!
+//! `--compare-baseline auto` and `--save-baseline auto` name baselines
+//! after the repository state instead of a fixed string, so one CI job
+//! definition works on every branch. A naming scheme such as `{branch}` or
+//! `ci-{commit}` turns a branch and commit into a baseline name. Saving
+//! uses the current branch and `HEAD`; comparing looks for the baseline of
+//! the merge-base with the default branch, then of the default branch's
+//! tip.
+//!
+//! Runs and baselines also record the commit they were measured at, so a
+//! regression can be traced back to the commits between the two.
+
+use crate::types::{PeakMemError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::process::Command;
+
+/// Name that asks for a baseline name to be chosen from git metadata.
+pub const AUTO: &str = "auto";
+
+/// Default naming scheme: one baseline per branch.
+pub const DEFAULT_SCHEME: &str = "{branch}";
+
+/// Fills in the `{branch}` and `{commit}` placeholders of a naming scheme.
+pub fn render_scheme(scheme: &str, branch: &str, commit: &str) -> String {
+    scheme
+        .replace("{branch}", branch)
+        .replace("{commit}", commit)
+}
+
+/// The state of the repository a run was measured in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GitInfo {
+    /// Full SHA of `HEAD`.
+    pub commit: String,
+    /// Branch checked out, none on a detached `HEAD`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Whether tracked files had uncommitted changes.
+    #[serde(default)]
+    pub dirty: bool,
+}
+
+impl GitInfo {
+    /// Reads the state of the repository in the current directory, none
+    /// outside of one or without git.
+    pub fn current() -> Option<Self> {
+        let commit = git(&["rev-parse", "HEAD"]).ok()?;
+        let branch = git(&["symbolic-ref", "--quiet", "--short", "HEAD"]).ok();
+        let dirty = git(&["status", "--porcelain", "--untracked-files=no"])
+            .is_ok_and(|status| !status.is_empty());
+        Some(Self {
+            commit,
+            branch,
+            dirty,
+        })
+    }
+
+    /// Adds the state to a baseline's metadata, as `git_commit`,
+    /// `git_branch` and `git_dirty`.
+    pub fn insert_into(&self, metadata: &mut HashMap<String, String>) {
+        metadata.insert("git_commit".to_string(), self.commit.clone());
+        if let Some(branch) = &self.branch {
+            metadata.insert("git_branch".to_string(), branch.clone());
+        }
+        metadata.insert("git_dirty".to_string(), self.dirty.to_string());
+    }
+
+    /// Reads the state back from a baseline's metadata, none if the
+    /// baseline was saved outside of a repository.
+    pub fn from_metadata(metadata: &HashMap<String, String>) -> Option<Self> {
+        Some(Self {
+            commit: metadata.get("git_commit")?.clone(),
+            branch: metadata.get("git_branch").cloned(),
+            dirty: metadata
+                .get("git_dirty")
+                .is_some_and(|dirty| dirty == "true"),
+        })
+    }
+}
+
+impl fmt::Display for GitInfo {
+    /// Formats as the short SHA, then the branch and whether there were
+    /// uncommitted changes, e.g. `1a2b3c4d (main, dirty)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", &self.commit[..self.commit.len().min(8)])?;
+        match (&self.branch, self.dirty) {
+            (Some(branch), true) => write!(f, " ({branch}, dirty)"),
+            (Some(branch), false) => write!(f, " ({branch})"),
+            (None, true) => write!(f, " (dirty)"),
+            (None, false) => Ok(()),
+        }
+    }
+}
+
+/// Returns the baseline name to save the current run under.
+///
+/// A detached `HEAD` uses its commit as the branch name.
+///
+/// # Errors
+/// * `PeakMemError::InvalidArgument` - Not inside a git repository
+pub fn save_name(scheme: &str) -> Result<String> {
+    let commit = git(&["rev-parse", "HEAD"])?;
+    let branch =
+        git(&["symbolic-ref", "--quiet", "--short", "HEAD"]).unwrap_or_else(|_| commit.clone());
+    Ok(render_scheme(scheme, &branch, &commit))
+}
+
+/// Returns the baseline names to compare against, most specific first:
+/// the scheme rendered for the merge-base of `HEAD` with the default
+/// branch, then for the default branch's tip.
+///
+/// # Errors
+/// * `PeakMemError::InvalidArgument` - Not inside a git repository, or no
+///   default branch could be found
+pub fn compare_candidates(scheme: &str) -> Result<Vec<String>> {
+    let (branch, reference) = default_branch()?;
+    let tip = git(&["rev-parse", &reference])?;
+    let merge_base = git(&["merge-base", "HEAD", &reference])?;
+
+    let mut candidates = vec![render_scheme(scheme, &branch, &merge_base)];
+    let tip_name = render_scheme(scheme, &branch, &tip);
+    if !candidates.contains(&tip_name) {
+        candidates.push(tip_name);
+    }
+    Ok(candidates)
+}
+
+/// Finds the default branch: the remote's `HEAD` if known, otherwise a
+/// local `main` or `master`.
+///
+/// # Returns
+/// * The branch name for naming baselines and the ref to resolve it by
+fn default_branch() -> Result<(String, String)> {
+    if let Ok(remote_head) = git(&[
+        "symbolic-ref",
+        "--quiet",
+        "--short",
+        "refs/remotes/origin/HEAD",
+    ]) {
+        let branch = remote_head
+            .strip_prefix("origin/")
+            .unwrap_or(&remote_head)
+            .to_string();
+        return Ok((branch, remote_head));
+    }
+
+    for branch in ["main", "master"] {
+        let reference = format!("refs/heads/{branch}");
+        if git(&["rev-parse", "--verify", "--quiet", &reference]).is_ok() {
+            return Ok((branch.to_string(), reference));
+        }
+    }
+
+    Err(PeakMemError::InvalidArgument(
+        "Could not find the default branch (no origin/HEAD, main or master)".to_string(),
+    ))
+}
+
+/// Runs a git command in the current directory and returns its trimmed
+/// output.
+fn git(args: &[&str]) -> Result<String> {
+    let output = Command::new("git").args(args).output().map_err(|e| {
+        PeakMemError::InvalidArgument(format!("Baseline name 'auto' needs git: {e}"))
+    })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(PeakMemError::InvalidArgument(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            stderr.trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_scheme() {
+        assert_eq!(render_scheme("{branch}", "main", "abc123"), "main");
+        assert_eq!(
+            render_scheme("ci-{branch}-{commit}", "main", "abc123"),
+            "ci-main-abc123"
+        );
+        assert_eq!(render_scheme("release", "main", "abc123"), "release");
+    }
+
+    #[test]
+    fn test_git_info() {
+        let info = GitInfo {
+            commit: "1a2b3c4d5e6f".to_string(),
+            branch: Some("main".to_string()),
+            dirty: true,
+        };
+        assert_eq!(info.to_string(), "1a2b3c4d (main, dirty)");
+
+        let mut metadata = HashMap::new();
+        info.insert_into(&mut metadata);
+        assert_eq!(GitInfo::from_metadata(&metadata), Some(info));
+        assert_eq!(GitInfo::from_metadata(&HashMap::new()), None);
+
+        let detached = GitInfo {
+            commit: "1a2b".to_string(),
+            branch: None,
+            dirty: false,
+        };
+        assert_eq!(detached.to_string(), "1a2b");
+    }
+}
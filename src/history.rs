@@ -0,0 +1,276 @@
+//! Every run in a SQLite database, for `--history-db` and
+//! `--query-history`.
+//!
+//! A JSON file per run is fine for a handful of baselines, but thousands
+//! of CI runs want an index. Each run is a row of `runs`, with its main
+//! measurements in columns and its full `--json` results in `result`, and
+//! its `--meta` tags are rows of `tags`, so other tools can query the
+//! database too.
+//!
+//! Only built with the `sqlite` feature, which links the system's
+//! libsqlite3 through rusqlite.
+
+use crate::baseline;
+use crate::types::{MonitorResult, PeakMemError, Result, Timestamp};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, TransactionBehavior};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+/// Created on first use; kept backwards compatible, as databases outlive
+/// peak-mem versions.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS runs (
+        id INTEGER PRIMARY KEY,
+        recorded_at INTEGER NOT NULL,
+        command TEXT NOT NULL,
+        peak_rss_bytes INTEGER NOT NULL,
+        peak_vsz_bytes INTEGER NOT NULL,
+        duration_ms INTEGER NOT NULL,
+        exit_code INTEGER,
+        platform TEXT NOT NULL,
+        git_commit TEXT,
+        git_branch TEXT,
+        result TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS runs_by_command ON runs (command, recorded_at);
+    CREATE TABLE IF NOT EXISTS tags (
+        run_id INTEGER NOT NULL REFERENCES runs (id),
+        key TEXT NOT NULL,
+        value TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS tags_by_run ON tags (run_id);
+";
+
+/// How long a run waits for another one writing to the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Which recorded runs `--query-history` lists.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistoryFilter {
+    /// Text the command line contains.
+    pub command: Option<String>,
+    /// Recorded at or after.
+    pub since: Option<Timestamp>,
+    /// Recorded before.
+    pub until: Option<Timestamp>,
+    /// Tags a run must all have.
+    pub tags: Vec<(String, String)>,
+}
+
+/// A recorded run, as `--query-history` lists it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryRun {
+    pub id: i64,
+    pub recorded_at: Timestamp,
+    pub command: String,
+    pub peak_rss_bytes: u64,
+    pub duration_ms: u64,
+    pub exit_code: Option<i32>,
+    pub platform: String,
+    pub git_commit: Option<String>,
+    pub metadata: HashMap<String, String>,
+}
+
+/// An open history database.
+pub struct HistoryDb {
+    db: Connection,
+}
+
+impl HistoryDb {
+    /// Opens the database at `path`, creating it and its tables if
+    /// needed.
+    ///
+    /// # Errors
+    /// * `PeakMemError::Runtime` - The file isn't a database or can't be
+    ///   created
+    pub fn open(path: &Path) -> Result<Self> {
+        let context = format!("Can't open {}", path.display());
+        let db = Connection::open(path).map_err(error(&context))?;
+        db.busy_timeout(BUSY_TIMEOUT).map_err(error(&context))?;
+        db.execute_batch(SCHEMA).map_err(error(&context))?;
+        Ok(Self { db })
+    }
+
+    /// Records a run and its tags.
+    ///
+    /// # Returns
+    /// * The id of the run's row
+    pub fn record(&mut self, result: &MonitorResult) -> Result<i64> {
+        let json = serde_json::to_string(result)?;
+        let git = result.git.as_ref();
+
+        let failed = error("Query failed");
+        let transaction = self
+            .db
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(&failed)?;
+        transaction
+            .execute(
+                "INSERT INTO runs (recorded_at, command, peak_rss_bytes, peak_vsz_bytes,
+                     duration_ms, exit_code, platform, git_commit, git_branch, result)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    result.timestamp.unix_micros() as i64,
+                    result.command,
+                    result.peak_rss_bytes as i64,
+                    result.peak_vsz_bytes as i64,
+                    result.duration_ms as i64,
+                    result.exit_code,
+                    baseline::platform(),
+                    git.map(|git| &git.commit),
+                    git.and_then(|git| git.branch.as_ref()),
+                    json,
+                ],
+            )
+            .map_err(&failed)?;
+        let id = transaction.last_insert_rowid();
+
+        let mut tags: Vec<_> = result.metadata.iter().collect();
+        tags.sort();
+        for (key, value) in tags {
+            transaction
+                .execute(
+                    "INSERT INTO tags (run_id, key, value) VALUES (?, ?, ?)",
+                    params![id, key, value],
+                )
+                .map_err(&failed)?;
+        }
+        // Dropped uncommitted on an error, which rolls it back
+        transaction.commit().map_err(&failed)?;
+        Ok(id)
+    }
+
+    /// Lists the recorded runs that match, oldest first.
+    pub fn query(&self, filter: &HistoryFilter) -> Result<Vec<HistoryRun>> {
+        let mut sql = "SELECT id, recorded_at, command, peak_rss_bytes, duration_ms, exit_code,
+                       platform, git_commit FROM runs WHERE 1"
+            .to_string();
+        let mut params: Vec<Value> = Vec::new();
+        if let Some(command) = &filter.command {
+            sql.push_str(" AND instr(command, ?) > 0");
+            params.push(command.clone().into());
+        }
+        if let Some(since) = filter.since {
+            sql.push_str(" AND recorded_at >= ?");
+            params.push((since.unix_micros() as i64).into());
+        }
+        if let Some(until) = filter.until {
+            sql.push_str(" AND recorded_at < ?");
+            params.push((until.unix_micros() as i64).into());
+        }
+        for (key, value) in &filter.tags {
+            sql.push_str(
+                " AND EXISTS (SELECT 1 FROM tags WHERE run_id = runs.id AND key = ? AND value = ?)",
+            );
+            params.extend([key.clone().into(), value.clone().into()]);
+        }
+        sql.push_str(" ORDER BY recorded_at, id");
+
+        let failed = error("Query failed");
+        let mut select = self.db.prepare(&sql).map_err(&failed)?;
+        let mut runs = select
+            .query_map(params_from_iter(params), |row| {
+                Ok(HistoryRun {
+                    id: row.get(0)?,
+                    recorded_at: Timestamp::from_unix_micros(row.get::<_, i64>(1)? as u64),
+                    command: row.get(2)?,
+                    peak_rss_bytes: row.get::<_, i64>(3)? as u64,
+                    duration_ms: row.get::<_, i64>(4)? as u64,
+                    exit_code: row.get(5)?,
+                    platform: row.get(6)?,
+                    git_commit: row.get(7)?,
+                    metadata: HashMap::new(),
+                })
+            })
+            .and_then(Iterator::collect::<rusqlite::Result<Vec<_>>>)
+            .map_err(&failed)?;
+
+        let mut tags = self
+            .db
+            .prepare("SELECT key, value FROM tags WHERE run_id = ?")
+            .map_err(&failed)?;
+        for run in &mut runs {
+            run.metadata = tags
+                .query_map([run.id], |row| Ok((row.get(0)?, row.get(1)?)))
+                .and_then(Iterator::collect::<rusqlite::Result<_>>)
+                .map_err(&failed)?;
+        }
+        Ok(runs)
+    }
+}
+
+/// Turns the database's errors into ours, after `context`.
+fn error(context: &str) -> impl Fn(rusqlite::Error) -> PeakMemError + '_ {
+    move |e| PeakMemError::Runtime(format!("History database: {context}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(command: &str, rss: u64, timestamp: &str) -> MonitorResult {
+        MonitorResult {
+            timestamp: Timestamp::parse_rfc3339(timestamp).unwrap(),
+            ..MonitorResult::fixture(command, rss)
+        }
+    }
+
+    #[test]
+    fn test_record_and_query() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let mut history = HistoryDb::open(&path).unwrap();
+
+        let mut tagged = result("./app --fast", 100, "2026-01-01T00:00:00Z");
+        tagged
+            .metadata
+            .insert("profile".to_string(), "release".to_string());
+        history.record(&tagged).unwrap();
+        history
+            .record(&result("./app", 200, "2026-02-01T00:00:00Z"))
+            .unwrap();
+        let mut killed = result("./other", 300, "2026-03-01T00:00:00Z");
+        killed.exit_code = None;
+        history.record(&killed).unwrap();
+        drop(history);
+
+        // Runs outlive the connection
+        let history = HistoryDb::open(&path).unwrap();
+        let all = history.query(&HistoryFilter::default()).unwrap();
+        assert_eq!(
+            all.iter().map(|run| run.peak_rss_bytes).collect::<Vec<_>>(),
+            vec![100, 200, 300]
+        );
+        assert_eq!(all[0].metadata.get("profile").unwrap(), "release");
+        assert_eq!(all[0].exit_code, Some(0));
+        assert_eq!(all[2].exit_code, None);
+
+        let date = |s: &str| Some(Timestamp::parse_rfc3339(s).unwrap());
+        let filter = HistoryFilter {
+            command: Some("./app".to_string()),
+            since: date("2026-01-15T00:00:00Z"),
+            ..HistoryFilter::default()
+        };
+        let runs = history.query(&filter).unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].command, "./app");
+
+        let filter = HistoryFilter {
+            tags: vec![("profile".to_string(), "release".to_string())],
+            until: date("2026-01-02T00:00:00Z"),
+            ..HistoryFilter::default()
+        };
+        assert_eq!(history.query(&filter).unwrap()[0].command, "./app --fast");
+
+        let garbage = dir.path().join("garbage.db");
+        std::fs::write(
+            &garbage,
+            "not a database, but long enough to be read as one",
+        )
+        .unwrap();
+        assert!(HistoryDb::open(&garbage).is_err());
+    }
+}
@@ -0,0 +1,190 @@
+//! Minimal HTTP/1.1 client for pushing results to other services.
+//!
+//! peak-mem only ever sends small, one-off requests (webhooks, metric
+//! pushes), so this speaks just enough HTTP over a plain TCP stream to do
+//! that without pulling in a full client and TLS stack. Only `http://`
+//! URLs are supported.
+
+use crate::types::{PeakMemError, Result};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// How long a request may take before it is abandoned.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A parsed `http://` URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpUrl {
+    /// Host name or address, without the port, and an IPv6 address
+    /// without its brackets.
+    pub host: String,
+    /// TCP port (80 unless given in the URL).
+    pub port: u16,
+    /// Request path including any query string; always starts with '/'.
+    pub path: String,
+}
+
+impl HttpUrl {
+    /// Parses an `http://host[:port][/path]` URL, where an IPv6 address as
+    /// host is in brackets, as in `http://[::1]:9091`.
+    ///
+    /// # Errors
+    /// * `PeakMemError::InvalidArgument` - Not an `http://` URL or malformed
+    pub fn parse(url: &str) -> Result<Self> {
+        let invalid = |reason: &str| PeakMemError::InvalidArgument(format!("{reason}: '{url}'"));
+
+        let rest = match url.strip_prefix("http://") {
+            Some(rest) => rest,
+            None if url.starts_with("https://") => {
+                return Err(invalid("HTTPS is not supported, use an http:// URL"))
+            }
+            None => return Err(invalid("URL must start with http://")),
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(idx) => (&rest[..idx], &rest[idx..]),
+            None => (rest, "/"),
+        };
+
+        let parse_port = |port: &str| port.parse().map_err(|_| invalid("Invalid port in URL"));
+        let (host, port) = match authority.strip_prefix('[') {
+            // The colons of an IPv6 address aren't the port's
+            Some(bracketed) => {
+                let (host, rest) = bracketed
+                    .split_once(']')
+                    .ok_or_else(|| invalid("Unclosed '[' in URL"))?;
+                let port = match rest.strip_prefix(':') {
+                    Some(port) => parse_port(port)?,
+                    None if rest.is_empty() => 80,
+                    None => return Err(invalid("Invalid port in URL")),
+                };
+                (host, port)
+            }
+            None => match authority.rsplit_once(':') {
+                Some((host, _)) if host.contains(':') => {
+                    return Err(invalid("IPv6 address in URL must be in brackets"))
+                }
+                Some((host, port)) => (host, parse_port(port)?),
+                None => (authority, 80),
+            },
+        };
+
+        if host.is_empty() {
+            return Err(invalid("Missing host in URL"));
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// The host and port, as in the `Host` header.
+    fn authority(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/// Sends a request with the given method and body, returning the
+/// response status code.
+///
+/// # Errors
+/// * Connection, I/O or timeout failures, and non-2xx responses
+pub async fn send(method: &str, url: &HttpUrl, content_type: &str, body: &[u8]) -> Result<u16> {
+    tokio::time::timeout(REQUEST_TIMEOUT, send_inner(method, url, content_type, body))
+        .await
+        .map_err(|_| PeakMemError::Runtime(format!("HTTP request to {} timed out", url.host)))?
+}
+
+async fn send_inner(method: &str, url: &HttpUrl, content_type: &str, body: &[u8]) -> Result<u16> {
+    let mut stream = TcpStream::connect((url.host.as_str(), url.port)).await?;
+
+    let head = format!(
+        "{method} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: peak-mem/{}\r\n\
+         Content-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        url.path,
+        url.authority(),
+        env!("CARGO_PKG_VERSION"),
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.flush().await?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+
+    let status = parse_status(&response)?;
+    if !(200..300).contains(&status) {
+        return Err(PeakMemError::Runtime(format!(
+            "HTTP request to {} failed with status {status}",
+            url.host
+        )));
+    }
+    Ok(status)
+}
+
+/// Extracts the status code from an HTTP/1.x status line.
+fn parse_status(response: &[u8]) -> Result<u16> {
+    let line_end = response
+        .iter()
+        .position(|&b| b == b'\n')
+        .unwrap_or(response.len());
+    let line = String::from_utf8_lossy(&response[..line_end]);
+
+    line.split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .filter(|_| line.starts_with("HTTP/1."))
+        .ok_or_else(|| PeakMemError::Parse(format!("invalid HTTP status line: '{}'", line.trim())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_url() {
+        let url = HttpUrl::parse("http://localhost:9091/metrics/job/x").unwrap();
+        assert_eq!(url.host, "localhost");
+        assert_eq!(url.port, 9091);
+        assert_eq!(url.path, "/metrics/job/x");
+
+        let url = HttpUrl::parse("http://example.com").unwrap();
+        assert_eq!(url.port, 80);
+        assert_eq!(url.path, "/");
+
+        assert!(HttpUrl::parse("https://example.com").is_err());
+        assert!(HttpUrl::parse("ftp://example.com").is_err());
+        assert!(HttpUrl::parse("http://:80/").is_err());
+        assert!(HttpUrl::parse("http://host:port/").is_err());
+
+        let url = HttpUrl::parse("http://[::1]:9091/metrics").unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("::1", 9091));
+        assert_eq!(url.path, "/metrics");
+        assert_eq!(url.authority(), "[::1]:9091");
+        let url = HttpUrl::parse("http://[fe80::1]").unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("fe80::1", 80));
+        assert_eq!(
+            HttpUrl::parse("http://localhost:9091").unwrap().authority(),
+            "localhost:9091"
+        );
+
+        assert!(HttpUrl::parse("http://[::1/").is_err());
+        assert!(HttpUrl::parse("http://[::1]9091/").is_err());
+        assert!(HttpUrl::parse("http://[]:80/").is_err());
+        assert!(HttpUrl::parse("http://::1:9091/").is_err());
+    }
+
+    #[test]
+    fn test_parse_status() {
+        assert_eq!(parse_status(b"HTTP/1.1 202 Accepted\r\n\r\n").unwrap(), 202);
+        assert!(parse_status(b"garbage").is_err());
+    }
+}
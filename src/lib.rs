@@ -0,0 +1,2075 @@
+//! peak-mem as a library, for tools that read its results.
+//!
+//! [`types::MonitorResult`] is a run as `--json` saves it, and [`query`] has
+//! the analysis of its process tree and timeline that the formatters use, so
+//! a custom report doesn't have to redo it. Everything else is the CLI's.
+
+mod archive;
+mod baseline;
+mod bench;
+mod ci;
+mod cli;
+mod container;
+mod expectations;
+mod fingerprint;
+mod git;
+#[cfg(feature = "sqlite")]
+mod history;
+mod http;
+mod merge;
+mod metrics;
+mod monitor;
+mod otlp;
+mod output;
+mod plot;
+mod process;
+mod pushgateway;
+pub mod query;
+mod strict;
+mod systemd;
+mod threshold;
+mod treediff;
+pub mod types;
+
+use crate::types::{ByteSize, PeakMemError, Result, Timestamp};
+use baseline::{BaselineManager, ComparisonResult};
+use fingerprint::EnvFingerprint;
+use monitor::aggregate::Aggregate;
+use monitor::jobs::JobKind;
+use monitor::store::{DeltaStore, MemoryStore, RingFileStore, SampleStore, StoreKind};
+use monitor::stream::{SampleStream, SocketGuard, StreamStore};
+use monitor::tracker::MemoryTracker;
+use output::{OutputFormatter, RealtimeDisplay, TuiDisplay, WatchState};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use threshold::{ThresholdAction, ThresholdWatcher};
+use tokio::time;
+
+/// When a monitored run started, and the resource usage it started from.
+struct RunStart {
+    instant: Instant,
+    timestamp: Timestamp,
+    /// Usage of the children reaped before the run.
+    reaped: monitor::ReapedUsage,
+}
+
+/// Application state and logic handler.
+struct Application {
+    args: cli::Cli,
+    baseline_manager: BaselineManager,
+    /// Where `--stream` and `--stream-socket` send the samples of every
+    /// run.
+    streams: Vec<SampleStream>,
+    /// Removes the `--stream-socket` socket once the application is done,
+    /// however the run ended.
+    _socket_guard: Option<SocketGuard>,
+}
+
+impl Application {
+    /// Creates a new application instance.
+    fn new(args: cli::Cli) -> Result<Self> {
+        let baseline_dir = args
+            .baseline_dir
+            .clone()
+            .unwrap_or_else(BaselineManager::default_dir);
+        let baseline_manager = BaselineManager::new(baseline_dir)?
+            .with_platform_scope(!args.no_platform_scope)
+            .with_file(args.baseline_file.clone());
+        let mut streams = Vec::new();
+        if let Some(target) = &args.stream {
+            streams.push(SampleStream::open(target, args.timestamp_format)?);
+        }
+        if let Some(path) = &args.stream_socket {
+            streams.push(SampleStream::listen(path, args.timestamp_format)?);
+        }
+
+        let _socket_guard = streams.iter().find_map(SampleStream::socket_guard);
+
+        Ok(Self {
+            args,
+            baseline_manager,
+            streams,
+            _socket_guard,
+        })
+    }
+
+    /// Runs the application.
+    async fn run(self) -> Result<()> {
+        // Handle version
+        if self.handle_version() {
+            return Ok(());
+        }
+
+        #[cfg(not(feature = "sqlite"))]
+        if self.args.history_db.is_some() {
+            return Err(PeakMemError::InvalidArgument(
+                "--history-db needs peak-mem built with the sqlite feature".to_string(),
+            ));
+        }
+
+        // Handle baseline-only operations
+        if self.handle_baseline_only_operations()? {
+            return Ok(());
+        }
+
+        if let [before, after] = self.args.diff_trees.as_slice() {
+            return self.diff_trees(before, after);
+        }
+
+        if let [timeline, others @ ..] = self.args.plot.as_slice() {
+            return self.plot(timeline, others.first().map(|path| path.as_path()));
+        }
+
+        if let [before, after] = self.args.diff.as_slice() {
+            return self.diff(before, after);
+        }
+
+        if let Some(path) = &self.args.render {
+            return self.render(path);
+        }
+
+        if !self.args.merge.is_empty() {
+            let results = self
+                .args
+                .merge
+                .iter()
+                .map(|path| baseline::load_result(path))
+                .collect::<Result<Vec<_>>>()?;
+            return OutputFormatter::format_merged(
+                &merge::MergedRuns::new(&results)?,
+                self.args.summary_format("--merge")?,
+                self.args.units,
+            );
+        }
+
+        #[cfg(feature = "sqlite")]
+        if self.args.query_history {
+            return self.query_history();
+        }
+
+        if self.args.bench_self {
+            let format = self.args.summary_format("--bench-self")?;
+            let results = bench::bench_self(monitor::create_monitor()?.as_ref()).await?;
+            return OutputFormatter::format_self_bench(&results, format);
+        }
+
+        // Read expectations first so a bad file fails before the run
+        let expected_processes = match &self.args.expect_processes_from {
+            Some(path) => Some(expectations::load_expected_processes(path)?),
+            None => None,
+        };
+
+        // Record the commit before the command can change the checkout
+        let git = git::GitInfo::current();
+
+        // Resolve baseline names up front too, as `auto` depends on git,
+        // and read a result to compare against
+        let compared_result = match &self.args.compare_result {
+            Some(path) => Some(baseline::load_result(path)?),
+            None => None,
+        };
+        let compare_target = match (&self.args.compare_baseline, &self.args.compare_result) {
+            (Some(name), _) => Some(self.resolve_compare_name(name)?),
+            (None, Some(path)) => Some(path.display().to_string()),
+            (None, None) => None,
+        };
+        let save_target = match self.args.save_target() {
+            // A conditional save updates the baseline that was compared
+            Some(git::AUTO)
+                if self.args.save_baseline.is_none() && self.args.append_to_baseline.is_none() =>
+            {
+                compare_target.clone()
+            }
+            Some(git::AUTO) => Some(git::save_name(&self.args.baseline_scheme)?),
+            Some(name) => Some(name.to_string()),
+            None => None,
+        };
+
+        // Run the command and monitor memory
+        let mut result = if self.args.system {
+            self.monitor_system().await?
+        } else {
+            self.monitor_runs().await?
+        };
+        result.git = git;
+        result.metadata = self.args.meta.iter().cloned().collect();
+        for stream in &self.streams {
+            stream.close().await;
+        }
+
+        if let (Some(expected), Some(path)) =
+            (&expected_processes, &self.args.expect_processes_from)
+        {
+            let observed = result.observed_processes.iter().cloned().collect();
+            let diff = expectations::ProcessSetDiff::new(expected, &observed);
+            if !diff.is_empty() {
+                diff.warn(path);
+            }
+        }
+
+        // Handle output and exit
+        self.handle_results(result, compare_target, compared_result, save_target)
+            .await
+    }
+
+    fn handle_version(&self) -> bool {
+        if self.args.short_version {
+            println!("{}", env!("CARGO_PKG_VERSION"));
+            return true;
+        } else if self.args.long_version {
+            println!("peak-mem {}", env!("CARGO_PKG_VERSION"));
+            return true;
+        }
+
+        false
+    }
+
+    /// Handles baseline operations that don't require running a command.
+    /// Returns true if the operation was handled and the app should exit.
+    fn handle_baseline_only_operations(&self) -> Result<bool> {
+        if self.args.list_baselines {
+            self.list_baselines()?;
+            return Ok(true);
+        }
+
+        if let Some(name) = &self.args.show_baseline {
+            OutputFormatter::format_baseline(
+                name,
+                &self.baseline_manager.load_baseline(name)?,
+                self.args.summary_format("--show-baseline")?,
+                self.args.units,
+            )?;
+            return Ok(true);
+        }
+
+        if let [old, new] = self.args.rename_baseline.as_slice() {
+            self.baseline_manager.rename_baseline(old, new)?;
+            println!("Baseline '{old}' renamed to '{new}'.");
+            return Ok(true);
+        }
+
+        if let [name, file] = self.args.export_baseline.as_slice() {
+            self.baseline_manager
+                .export_baseline(name, Path::new(file))?;
+            println!("Baseline '{name}' exported to: {file}");
+            return Ok(true);
+        }
+
+        if let [file, name @ ..] = self.args.import_baseline.as_slice() {
+            let (name, warnings) = self
+                .baseline_manager
+                .import_baseline(Path::new(file), name.first().map(String::as_str))?;
+            for warning in warnings {
+                eprintln!("Warning: {warning}");
+            }
+            println!("Baseline '{name}' imported from: {file}");
+            return Ok(true);
+        }
+
+        if let [name, count @ ..] = self.args.trend.as_slice() {
+            let count = match count.first() {
+                Some(count) => count
+                    .parse()
+                    .ok()
+                    .filter(|&count| count > 0)
+                    .ok_or_else(|| {
+                        PeakMemError::InvalidArgument(format!(
+                            "Invalid number of saves '{count}' for --trend"
+                        ))
+                    })?,
+                None => baseline::DEFAULT_TREND_RUNS,
+            };
+            let trend = self
+                .baseline_manager
+                .load_baseline(name)?
+                .trend(name, count)
+                .ok_or_else(|| {
+                    PeakMemError::InvalidArgument(format!(
+                        "Baseline '{name}' has no history; save it with --keep-history or \
+                         --append-to-baseline"
+                    ))
+                })?;
+            OutputFormatter::format_trend(
+                &trend,
+                self.args.summary_format("--trend")?,
+                self.args.units,
+            )?;
+            return Ok(true);
+        }
+
+        if let Some(name) = &self.args.delete_baseline {
+            self.baseline_manager.delete_baseline(name)?;
+            println!("Baseline '{name}' deleted.");
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    /// Compares the peak process trees recorded in two results.
+    fn diff_trees(&self, before: &Path, after: &Path) -> Result<()> {
+        let diff =
+            treediff::TreeDiff::new(&treediff::load_tree(before)?, &treediff::load_tree(after)?);
+        OutputFormatter::format_tree_diff(
+            &diff,
+            self.args.summary_format("--diff-trees")?,
+            self.args.units,
+        )
+    }
+
+    /// Compares two saved results, reported as a comparison against a
+    /// baseline would be.
+    fn diff(&self, before: &Path, after: &Path) -> Result<()> {
+        let current = baseline::load_result(after)?;
+        let comparison = baseline::compare_results(
+            &baseline::load_result(before)?,
+            &current,
+            self.args.regression_thresholds(),
+        );
+        self.handle_comparison(&before.display().to_string(), &comparison, &current)?;
+        if comparison.regression_detected {
+            std::process::exit(1);
+        }
+        Ok(())
+    }
+
+    /// Outputs saved results, or a saved comparison, as if the run had
+    /// just ended: in the chosen format, and to the report, chart and
+    /// badge files asked for.
+    fn render(&self, path: &Path) -> Result<()> {
+        match baseline::load_saved(path)? {
+            baseline::SavedResults::Run(result) => {
+                self.save_html_report_if_requested(&result, None)?;
+                self.save_chart_if_requested(&result)?;
+                self.save_badge_if_requested(&result, None)?;
+                self.handle_normal_output(&result)?;
+            }
+            baseline::SavedResults::Comparison(comparison) => {
+                // The baseline's name isn't saved with it
+                let name = comparison.baseline.created_at.format_datetime();
+                let result = &comparison.current;
+                self.save_html_report_if_requested(result, Some((name.as_str(), &comparison)))?;
+                self.save_chart_if_requested(result)?;
+                self.save_badge_if_requested(result, Some(&comparison))?;
+                self.handle_comparison(&name, &comparison, result)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Charts a saved timeline, and another to compare it with.
+    fn plot(&self, path: &Path, other: Option<&Path>) -> Result<()> {
+        let timeline = plot::load_timeline(path)?;
+        let other = other.map(plot::load_timeline).transpose()?;
+        match &self.args.chart {
+            Some(chart) => {
+                let svg = output::svg_timeline(
+                    &timeline.label,
+                    &timeline.points,
+                    other
+                        .as_ref()
+                        .map(|other| (other.label.as_str(), other.points.as_slice())),
+                    self.args.threshold,
+                    self.args.units,
+                );
+                std::fs::write(chart, svg)?;
+                eprintln!("Chart written to: {}", chart.display());
+            }
+            None => {
+                let width = crossterm::terminal::size().map_or(80, |(columns, _)| columns as usize);
+                print!(
+                    "{}",
+                    plot::plot_terminal(&timeline, other.as_ref(), width, self.args.units)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Lists all saved baselines.
+    fn list_baselines(&self) -> Result<()> {
+        let mut summaries = Vec::new();
+        for name in self.baseline_manager.list_baselines()? {
+            match self.baseline_manager.load_baseline(&name) {
+                Ok(baseline) => {
+                    let tagged = self
+                        .args
+                        .meta
+                        .iter()
+                        .all(|(key, value)| baseline.metadata.get(key) == Some(value));
+                    if tagged {
+                        summaries.push(baseline.summary(&name));
+                    }
+                }
+                Err(e) => eprintln!("Warning: Can't read baseline '{name}': {e}"),
+            }
+        }
+        OutputFormatter::format_baseline_list(
+            &summaries,
+            self.args.summary_format("--list-baselines")?,
+            self.args.units,
+        )
+    }
+
+    /// Monitors the command `--runs` times.
+    ///
+    /// The run with the highest peak RSS is reported, as thresholds and
+    /// baselines are about the worst case. Repeating stops at the first
+    /// run that fails, and that run is reported so its exit code isn't
+    /// lost.
+    async fn monitor_runs(&self) -> Result<types::MonitorResult> {
+        let mut results = Vec::new();
+        for _ in 0..self.args.runs {
+            let result = self.monitor_command().await?;
+            let failed = result.exit_code != Some(0);
+            results.push(result);
+            if failed {
+                break;
+            }
+        }
+
+        if let Err(e) = self.save_envelope_if_requested(&results) {
+            eprintln!("Warning: Failed to save timeline envelope: {e}");
+        }
+
+        let runs = if results.len() > 1 {
+            results
+                .iter()
+                .map(|result| types::RunSummary {
+                    peak_rss_bytes: result.peak_rss_bytes,
+                    peak_vsz_bytes: result.peak_vsz_bytes,
+                    duration_ms: result.duration_ms,
+                    exit_code: result.exit_code,
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let failed = results.last().is_some_and(|r| r.exit_code != Some(0));
+        let reported = if failed {
+            results.pop()
+        } else {
+            results.into_iter().max_by_key(|r| r.peak_rss_bytes)
+        };
+        let mut result =
+            reported.ok_or_else(|| PeakMemError::Runtime("No runs were monitored".to_string()))?;
+        result.runs = runs;
+        // Timelines may only have been kept for the envelope
+        if !self.args.keeps_timeline() {
+            result.timeline = None;
+            result.timeline_retention = None;
+            result.timeline_delta = None;
+        }
+
+        Ok(result)
+    }
+
+    /// Samples the whole machine's memory until `--duration` has passed,
+    /// or until SIGINT or SIGTERM.
+    ///
+    /// The result's peak RSS is the most memory in use at once, so
+    /// thresholds, baselines and the timeline work as they do for a
+    /// command.
+    async fn monitor_system(&self) -> Result<types::MonitorResult> {
+        use monitor::system::{self, SystemPeaks};
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let unavailable = || {
+            PeakMemError::UnsupportedPlatform(
+                "--system needs /proc/meminfo, which is only available on Linux".to_string(),
+            )
+        };
+        let first = system::read_system_memory().ok_or_else(unavailable)?;
+        let fingerprint = if self.args.fingerprint_enabled() {
+            Some(EnvFingerprint::capture(&self.args.probes).await)
+        } else {
+            None
+        };
+
+        let mut sigint_stream = signal(SignalKind::interrupt())?;
+        let mut sigterm_stream = signal(SignalKind::terminate())?;
+        let start = RunStart {
+            instant: Instant::now(),
+            timestamp: Timestamp::now(),
+            reaped: monitor::reaped_children_usage(),
+        };
+        let deadline = self
+            .args
+            .duration
+            .map(|secs| time::Instant::from_std(start.instant) + time::Duration::from_secs(secs));
+        let mut interval = time::interval(time::Duration::from_millis(self.args.interval));
+        let mut peaks = SystemPeaks::new(&first);
+        let mut timeline = Vec::new();
+        let mut retention = self.timeline_retention();
+        let mut peak_pressure: Option<monitor::pressure::MemoryPressure> = None;
+        let thermal = self
+            .args
+            .thermal
+            .then(|| monitor::thermal::ThermalRecorder::start(self.args.interval));
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let Some(memory) = system::read_system_memory() else {
+                        continue;
+                    };
+                    peaks.record(&memory);
+                    if let Some(pressure) = monitor::pressure::read_memory_pressure() {
+                        peak_pressure = Some(peak_pressure.map_or(pressure, |peak| pressure.max(&peak)));
+                    }
+                    let mut sample = types::MemoryUsage::new(memory.used_bytes(), 0, Timestamp::now());
+                    sample.system = Some(memory);
+                    for stream in &self.streams {
+                        stream.write(&sample);
+                    }
+                    match &mut retention {
+                        Some(retention) => retention.push(&mut timeline, sample),
+                        None => timeline.push(sample),
+                    }
+                }
+                _ = time::sleep_until(deadline.unwrap_or_else(time::Instant::now)), if deadline.is_some() => break,
+                _ = sigint_stream.recv() => break,
+                _ = sigterm_stream.recv() => break,
+            }
+        }
+
+        let thermal = self.finish_thermal(thermal).await;
+        let timeline_retention = retention.map(|retention| retention.summary(timeline.len()));
+        let sample_count = timeline_retention
+            .as_ref()
+            .map_or(timeline.len() as u64, |retention| {
+                retention.original_samples
+            });
+        let verbose = self.args.verbose;
+        let wants_timeline = self.args.keeps_timeline();
+        let rss_sparkline = (self.args.output_format() == cli::OutputFormat::Human)
+            .then(|| output::sparkline(&timeline, output::SPARKLINE_WIDTH))
+            .flatten();
+        Ok(types::MonitorResult {
+            command: "system".to_string(),
+            peak_rss_bytes: peaks.peak_used_bytes,
+            peak_vsz_bytes: 0,
+            duration_ms: start.instant.elapsed().as_millis() as u64,
+            exit_code: None,
+            threshold_exceeded: self.check_threshold(peaks.peak_used_bytes),
+            timestamp: Timestamp::now(),
+            process_tree: None,
+            timeline: wants_timeline.then_some(timeline),
+            start_time: verbose.then_some(start.timestamp),
+            sample_count: verbose.then_some(sample_count),
+            main_pid: None,
+            minor_faults: 0,
+            major_faults: 0,
+            peak_thread_count: 0,
+            user_time_ms: 0,
+            system_time_ms: 0,
+            cpu_utilization_percent: 0.0,
+            disk_read_bytes: 0,
+            disk_write_bytes: 0,
+            threshold_event: None,
+            sampling_diagnostics: None,
+            fingerprint,
+            peak_memory_pressure: peak_pressure,
+            lowest_system_memory: None,
+            peak_gpu_bytes: None,
+            peak_heap_bytes: None,
+            sampling_pauses: Vec::new(),
+            observed_processes: Vec::new(),
+            degraded_mode: None,
+            allocation_counts: None,
+            targets: Vec::new(),
+            fd_summary: None,
+            container: None,
+            unit: None,
+            runs: Vec::new(),
+            system: Some(peaks),
+            crates: Vec::new(),
+            translation_units: Vec::new(),
+            aggregate: None,
+            pool: None,
+            timeline_retention: timeline_retention.filter(|_| wants_timeline),
+            timeline_delta: None,
+            git: None,
+            metadata: HashMap::new(),
+            thermal,
+            process_snapshots: None,
+            rss_sparkline,
+        })
+    }
+
+    /// Writes the envelope of all runs' timelines if requested.
+    fn save_envelope_if_requested(&self, results: &[types::MonitorResult]) -> Result<()> {
+        if let Some(path) = &self.args.timeline_envelope {
+            let timelines: Vec<_> = results.iter().filter_map(|r| r.timeline.clone()).collect();
+            let envelope = types::EnvelopeSample::envelope(&timelines);
+            std::fs::write(path, serde_json::to_string_pretty(&envelope)?)?;
+        }
+        Ok(())
+    }
+
+    /// Monitors a command's memory usage.
+    async fn monitor_command(&self) -> Result<types::MonitorResult> {
+        // The agents of --runtime-heap learn where to report from the
+        // command's environment
+        let heap = if self.args.runtime_heap.is_empty() {
+            None
+        } else {
+            Some(Arc::new(monitor::runtime::HeapProbe::new(
+                self.args.runtime_heap.clone(),
+                self.args.interval,
+            )?))
+        };
+        // Create process runner, unless only attaching to running
+        // processes
+        let runner = if self.args.command.is_empty() {
+            None
+        } else {
+            let runner = process::ProcessRunner::new(self.args.command.clone())?;
+            let runner = match &heap {
+                Some(heap) => runner.with_env(heap.environment()),
+                None => runner,
+            };
+            Some(self.sandboxed(runner)?)
+        };
+        let container = match &self.args.container {
+            Some(name) => Some(container::resolve(name)?),
+            None => None,
+        };
+        let unit = match &self.args.unit {
+            Some(name) => Some(systemd::resolve(name)?),
+            None => None,
+        };
+        let cgroup = match (&container, &unit) {
+            (Some(container), _) => Some(monitor::cgroup::CgroupMemory::for_pid(container.pid)?),
+            (None, Some(unit)) => Some(monitor::cgroup::CgroupMemory::for_path(
+                &unit.control_group,
+            )?),
+            (None, None) => None,
+        };
+        let attached: Vec<u32> = self
+            .args
+            .pids
+            .iter()
+            .copied()
+            .chain(container.as_ref().map(|container| container.pid))
+            .chain(unit.as_ref().map(|unit| unit.main_pid))
+            .collect();
+
+        let command_string = match (&runner, &container, &unit) {
+            (Some(runner), _, _) => runner.command_string(),
+            (None, Some(container), _) => format!("container {}", container.name),
+            (None, None, Some(unit)) => format!("unit {}", unit.name),
+            (None, None, None) => attached
+                .iter()
+                .map(|pid| format!("pid {pid}"))
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        for &pid in &attached {
+            process::check_attachable(pid)?;
+        }
+
+        // Fingerprint before spawning so probes don't overlap the run
+        let fingerprint = if self.args.fingerprint_enabled() {
+            Some(EnvFingerprint::capture(&self.args.probes).await)
+        } else {
+            None
+        };
+
+        // Load NVML up front so a missing driver fails before the
+        // command runs
+        let gpu = match self.args.gpu_backend() {
+            Some(backend) => Some(monitor::gpu::GpuMonitor::open(backend)?),
+            None => None,
+        };
+
+        // Listen up front too, so a taken port fails before the command
+        // runs
+        let metrics_listener = match &self.args.serve_metrics {
+            Some(addr) => Some(metrics::bind(addr).await?),
+            None => None,
+        };
+
+        // Earlier runs and fingerprint probes are reaped children too
+        let reaped_before = monitor::reaped_children_usage();
+
+        // Spawn the process
+        let handle = match &runner {
+            Some(runner) => Some(runner.spawn().await?),
+            None => None,
+        };
+        let pids: Vec<u32> = handle
+            .iter()
+            .map(process::ProcessHandle::pid)
+            .chain(attached.iter().copied())
+            .collect();
+        let pid = pids[0];
+
+        // Check before the child can be reaped whether it can be sampled
+        // at all, rather than reporting zeros later. The fallback only
+        // knows about our own children.
+        let degraded_mode = if handle.is_some() {
+            monitor::sampling_restriction(pid)
+        } else {
+            None
+        };
+        if let Some(reason) = &degraded_mode {
+            eprintln!(
+                "Warning: {reason}; only the peak RSS of the largest process can be measured"
+            );
+        }
+
+        // Counting starts once the child exists, so anything it does
+        // before this point is missed
+        #[cfg(all(feature = "perf", target_os = "linux"))]
+        let perf = if self.args.perf {
+            match monitor::perf::PerfCounters::open(pid) {
+                Ok(counters) => Some(counters),
+                Err(e) => {
+                    eprintln!("Warning: {e}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        // Set up memory tracking
+        let monitor = monitor::create_monitor()?;
+        let mut tracker = MemoryTracker::new(monitor, pid, !self.args.no_children)
+            .with_additional_pids(&pids[1..])
+            .with_pressure_backoff(self.args.pressure_backoff)
+            .with_fd_summary(self.args.fd_summary)
+            .with_aggregate(self.args.aggregate.clone());
+        if self.args.cargo {
+            tracker = tracker.with_job_attribution(JobKind::Crate);
+        }
+        if self.args.compile_units {
+            tracker = tracker.with_job_attribution(JobKind::TranslationUnit);
+        }
+        // The report's process table follows its cursor
+        if self.args.html_report_wanted() && !self.args.no_children {
+            tracker = tracker.with_process_snapshots();
+        }
+        // Watch mode shows the processes of the tree as they go
+        if self.args.watch && !self.args.no_children {
+            tracker = tracker.with_live_tree();
+        }
+        if let Some(pattern) = &self.args.pool_process {
+            tracker = tracker.with_pool(monitor::pool::PoolStats::new(
+                pattern.clone(),
+                self.args.pool_steady_after,
+            ));
+        }
+        if let Some(store) = self.sample_store()? {
+            tracker = tracker.with_sample_store(store);
+        }
+        if let Some(cap) = self.args.self_memory_cap {
+            tracker = tracker.with_self_memory_cap(cap);
+        }
+        if self.args.once {
+            tracker = tracker.with_single_sample();
+        }
+        if let Some(cgroup) = cgroup {
+            tracker = tracker.with_cgroup(cgroup);
+        }
+        if let Some(gpu) = gpu {
+            tracker = tracker.with_gpu(gpu);
+        }
+        if let Some(heap) = &heap {
+            tracker = tracker.with_heap(Arc::clone(heap));
+        }
+        let start = RunStart {
+            instant: Instant::now(),
+            timestamp: Timestamp::now(),
+            reaped: reaped_before,
+        };
+        let tracker_handle = tracker.start(self.args.interval).await;
+        let thermal = self
+            .args
+            .thermal
+            .then(|| monitor::thermal::ThermalRecorder::start(self.args.interval));
+        let metrics_server = metrics_listener.map(|listener| {
+            metrics::serve(
+                listener,
+                metrics::MetricsSource::new(command_string.clone(), &tracker),
+            )
+        });
+
+        // Watch for the threshold during the run if actions are configured
+        let watcher_stop = Arc::new(AtomicBool::new(false));
+        let child_exited = Arc::new(AtomicBool::new(false));
+        let watcher_handle = match self.args.threshold {
+            Some(threshold) if !self.args.on_threshold.is_empty() => Some(
+                ThresholdWatcher::new(
+                    threshold,
+                    self.args.on_threshold.clone(),
+                    pid,
+                    command_string.clone(),
+                )
+                .spawn(
+                    tracker.peak_rss.clone(),
+                    self.args.interval,
+                    start.instant,
+                    Arc::clone(&watcher_stop),
+                    Arc::clone(&child_exited),
+                ),
+            ),
+            _ => None,
+        };
+
+        // SIGUSR1 leaves a phase of the run out of the measurement
+        let pause_listener = monitor::pause::listen(tracker.pause_switch())?;
+
+        // Run process with optional real-time display. Without a command
+        // the session lasts until every attached process has exited, or
+        // with --once stops right away: the tracker always takes its first
+        // sample, and only that one.
+        let mut child_max_rss = 0;
+        let wait = async {
+            let exit = match handle {
+                Some(handle) => handle.wait_with_signal_forwarding().await.map(|exit| {
+                    child_max_rss = exit.max_rss_bytes;
+                    exit.code
+                }),
+                None if self.args.once => Ok(None),
+                None => {
+                    let poll = time::Duration::from_millis(self.args.interval);
+                    process::wait_for_attached(&attached, poll)
+                        .await
+                        .map(|()| None)
+                }
+            };
+            // From here on the PID may be reused, so the watcher must not
+            // signal it any more
+            child_exited.store(true, Ordering::SeqCst);
+            exit
+        };
+        let exit_code = if self.args.watch {
+            let display = WatchDisplay {
+                interval_ms: self.args.interval,
+                units: self.args.units,
+                tui: self.args.tui.then(|| command_string.clone()),
+                progress_secs: self.args.progress_every,
+                json: self.args.json,
+            };
+            run_with_realtime_display(wait, &tracker, display).await?
+        } else {
+            wait.await?
+        };
+        pause_listener.abort();
+
+        // Stop tracking and collect results. The watcher stops right after
+        // the tracker, which has taken its last sample by then.
+        tracker.stop();
+        tracker_handle.await?;
+        watcher_stop.store(true, Ordering::SeqCst);
+        let threshold_event = match watcher_handle {
+            Some(handle) => handle.await?,
+            None => None,
+        };
+        let thermal = self.finish_thermal(thermal).await;
+        if let Some(server) = metrics_server {
+            // Wait for the listener to close, so that another run can
+            // bind the address again
+            server.abort();
+            let _ = server.await;
+        }
+
+        // Build the result
+        let mut result = self
+            .build_monitor_result(command_string, &tracker, &start, exit_code, pid)
+            .await?;
+        if heap.is_some() && result.peak_heap_bytes.is_none() {
+            eprintln!(
+                "Warning: No process of the tree reported its managed heap for --runtime-heap"
+            );
+        }
+
+        if self.args.on_threshold.contains(&ThresholdAction::Annotate) {
+            result.threshold_event = threshold_event;
+        }
+        result.fingerprint = fingerprint;
+        result.thermal = thermal;
+        result.container = container;
+        result.unit = unit;
+        if !self.args.pids.is_empty() {
+            result.targets = tracker.target_peaks();
+            if runner.is_some() {
+                result.targets[0].command = Some(result.command.clone());
+            }
+        }
+        #[cfg(all(feature = "perf", target_os = "linux"))]
+        {
+            result.allocation_counts = perf.map(|counters| counters.read());
+        }
+        if self.args.strict {
+            let quality = strict::SamplingQuality {
+                samples: tracker.sample_count(),
+                skipped_ticks: tracker.skipped_ticks(),
+                unreadable_samples: tracker.unreadable_samples(),
+                degraded: degraded_mode.clone(),
+            };
+            if let Some(problem) = quality.check(self.args.max_skipped_ticks) {
+                return Err(PeakMemError::DataQuality(problem));
+            }
+        }
+        if let Some(reason) = degraded_mode {
+            self.apply_degraded_mode(&mut result, reason, child_max_rss);
+        }
+
+        Ok(result)
+    }
+
+    /// Stops recording the thermal context, warning if nothing could be
+    /// read.
+    async fn finish_thermal(
+        &self,
+        recorder: Option<monitor::thermal::ThermalRecorder>,
+    ) -> Option<monitor::thermal::ThermalContext> {
+        let thermal = recorder?.finish().await;
+        if thermal.is_none() {
+            eprintln!(
+                "Warning: --thermal found no CPU frequency, throttling, temperature or energy readings on this machine"
+            );
+        }
+        thermal
+    }
+
+    /// Replaces the sampled peaks, which are zero when sampling was
+    /// impossible, with the child's peak RSS from the rusage of its wait.
+    /// Unlike the totals of all reaped children, that doesn't include
+    /// earlier runs or fingerprint probes.
+    fn apply_degraded_mode(
+        &self,
+        result: &mut types::MonitorResult,
+        reason: String,
+        child_max_rss: u64,
+    ) {
+        result.peak_rss_bytes = child_max_rss;
+        result.peak_vsz_bytes = 0;
+        result.threshold_exceeded = self.check_threshold(result.peak_rss_bytes);
+        result.degraded_mode = Some(reason);
+    }
+
+    /// Puts the command in a sandbox, with `--sandbox`.
+    fn sandboxed(&self, runner: process::ProcessRunner) -> Result<process::ProcessRunner> {
+        if !self.args.sandbox {
+            return Ok(runner);
+        }
+        #[cfg(target_os = "linux")]
+        {
+            Ok(runner.with_sandbox(process::Sandbox::new()?))
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            Err(PeakMemError::UnsupportedPlatform(
+                "--sandbox needs Linux namespaces and seccomp, which are only available on Linux"
+                    .to_string(),
+            ))
+        }
+    }
+
+    /// Builds the monitoring result from collected data.
+    async fn build_monitor_result(
+        &self,
+        command: String,
+        tracker: &MemoryTracker,
+        start: &RunStart,
+        exit_code: Option<i32>,
+        pid: u32,
+    ) -> Result<types::MonitorResult> {
+        let duration_ms = start.instant.elapsed().as_millis() as u64;
+        let peak_rss_bytes = tracker.peak_rss();
+        let peak_vsz_bytes = tracker.peak_vsz();
+
+        // The child has been reaped by now, so rusage covers the whole
+        // tree including processes that exited between samples.
+        let reaped = monitor::reaped_children_usage().since(&start.reaped);
+        let minor_faults = tracker.minor_faults().max(reaped.minor_faults);
+        let major_faults = tracker.major_faults().max(reaped.major_faults);
+        let cpu_utilization_percent = if duration_ms > 0 {
+            (reaped.user_time_ms + reaped.system_time_ms) as f64 / duration_ms as f64 * 100.0
+        } else {
+            0.0
+        };
+
+        // Check threshold
+        let threshold_exceeded = self.check_threshold(peak_rss_bytes);
+
+        // Get optional data based on flags
+        let process_tree = self.get_process_tree_if_verbose(tracker).await;
+        let timeline = self.get_timeline_if_requested(tracker).await;
+        let (timeline_retention, timeline_delta) = match &timeline {
+            Some(_) => (
+                tracker.timeline_retention().await,
+                tracker.timeline_delta().await,
+            ),
+            None => (None, None),
+        };
+        let (start_time_opt, sample_count, main_pid) =
+            self.get_verbose_data(start.timestamp, tracker.sample_count(), pid);
+        let rss_sparkline = if self.args.output_format() == cli::OutputFormat::Human {
+            match &timeline {
+                Some(timeline) => output::sparkline(timeline, output::SPARKLINE_WIDTH),
+                None => output::sparkline(&tracker.timeline().await, output::SPARKLINE_WIDTH),
+            }
+        } else {
+            None
+        };
+
+        Ok(types::MonitorResult {
+            command,
+            peak_rss_bytes,
+            peak_vsz_bytes,
+            duration_ms,
+            exit_code,
+            threshold_exceeded,
+            timestamp: Timestamp::now(),
+            process_tree,
+            timeline,
+            start_time: start_time_opt,
+            sample_count,
+            main_pid,
+            minor_faults,
+            major_faults,
+            peak_thread_count: tracker.peak_thread_count(),
+            user_time_ms: reaped.user_time_ms,
+            system_time_ms: reaped.system_time_ms,
+            cpu_utilization_percent,
+            disk_read_bytes: tracker.disk_read_bytes().max(reaped.disk_read_bytes),
+            disk_write_bytes: tracker.disk_write_bytes().max(reaped.disk_write_bytes),
+            threshold_event: None,
+            sampling_diagnostics: tracker.sampling_diagnostics().await,
+            fingerprint: None,
+            peak_memory_pressure: tracker.peak_pressure().await,
+            lowest_system_memory: tracker.lowest_system_memory().await,
+            peak_gpu_bytes: tracker.peak_gpu(),
+            peak_heap_bytes: tracker.peak_heap().await,
+            sampling_pauses: tracker.pauses(),
+            observed_processes: tracker.observed_processes().await,
+            degraded_mode: None,
+            allocation_counts: None,
+            targets: Vec::new(),
+            fd_summary: tracker.peak_fds().await,
+            container: None,
+            unit: None,
+            runs: Vec::new(),
+            system: None,
+            crates: tracker.job_peaks(JobKind::Crate).await,
+            translation_units: tracker.job_peaks(JobKind::TranslationUnit).await,
+            aggregate: (self.args.aggregate != Aggregate::Sum)
+                .then(|| self.args.aggregate.to_string()),
+            pool: tracker.pool_summary().await,
+            timeline_retention,
+            timeline_delta,
+            git: None,
+            metadata: HashMap::new(),
+            thermal: None,
+            process_snapshots: tracker.process_snapshots().await,
+            rss_sparkline,
+        })
+    }
+
+    /// Checks if the memory usage exceeded the configured threshold.
+    fn check_threshold(&self, peak_rss_bytes: u64) -> bool {
+        self.args
+            .threshold
+            .map(|threshold| ByteSize::b(peak_rss_bytes) > threshold)
+            .unwrap_or(false)
+    }
+
+    /// Gets the process tree if verbose mode is enabled.
+    async fn get_process_tree_if_verbose(
+        &self,
+        tracker: &MemoryTracker,
+    ) -> Option<types::ProcessMemoryInfo> {
+        // The regression report lists the tree's top consumers, and the
+        // HTML report shows all of it
+        let wanted = self.args.verbose
+            || self.args.regression_report.is_some()
+            || self.args.html_report_wanted();
+        if wanted && !self.args.no_children {
+            match tracker.get_process_tree().await {
+                Ok(tree) => Some(tree),
+                Err(e) => {
+                    eprintln!("Warning: Failed to get process tree: {e}");
+                    None
+                }
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Creates the timeline cap of `--timeline-max-samples`, if given.
+    fn timeline_retention(&self) -> Option<monitor::retention::TimelineRetention> {
+        self.args.timeline_max_samples.map(|max| {
+            monitor::retention::TimelineRetention::new(self.args.timeline_retention, max)
+        })
+    }
+
+    /// Creates the tracker's store of `--sample-store`, thinned by
+    /// `--timeline-delta` and streaming to `--stream` and
+    /// `--stream-socket`, unless the default uncapped one will do.
+    ///
+    /// # Errors
+    /// * `PeakMemError::Io` - The ring file couldn't be created
+    fn sample_store(&self) -> Result<Option<Box<dyn SampleStore>>> {
+        let mut store = self.kept_sample_store()?;
+        if let Some(delta) = self.args.timeline_delta {
+            let kept = store.unwrap_or_else(|| Box::new(MemoryStore::new()));
+            store = Some(Box::new(DeltaStore::new(kept, delta.as_u64())));
+        }
+        if self.streams.is_empty() {
+            return Ok(store);
+        }
+        Ok(Some(Box::new(StreamStore::new(
+            store.unwrap_or_else(|| Box::new(MemoryStore::new())),
+            self.streams.clone(),
+        ))))
+    }
+
+    /// Creates the store of `--sample-store`.
+    fn kept_sample_store(&self) -> Result<Option<Box<dyn SampleStore>>> {
+        Ok(match self.args.sample_store {
+            StoreKind::Memory => self.timeline_retention().map(|retention| {
+                Box::new(MemoryStore::with_retention(retention)) as Box<dyn SampleStore>
+            }),
+            StoreKind::RingFile => {
+                match (&self.args.sample_store_file, self.args.timeline_max_samples) {
+                    (Some(path), Some(capacity)) => {
+                        Some(Box::new(RingFileStore::create(path, capacity)?))
+                    }
+                    // Both are required with ring-file
+                    _ => None,
+                }
+            }
+            #[cfg(feature = "sqlite")]
+            StoreKind::Sqlite => self
+                .args
+                .sample_store_file
+                .as_deref()
+                .map(|path| -> Result<Box<dyn SampleStore>> {
+                    Ok(Box::new(monitor::store::SqliteStore::create(path)?))
+                })
+                .transpose()?,
+            #[cfg(not(feature = "sqlite"))]
+            StoreKind::Sqlite => {
+                return Err(PeakMemError::InvalidArgument(
+                    "--sample-store sqlite needs peak-mem built with the sqlite feature"
+                        .to_string(),
+                ))
+            }
+        })
+    }
+
+    /// Gets the timeline if requested.
+    async fn get_timeline_if_requested(
+        &self,
+        tracker: &MemoryTracker,
+    ) -> Option<Vec<types::MemoryUsage>> {
+        if self.args.keeps_timeline() || self.args.timeline_envelope.is_some() {
+            Some(tracker.timeline().await)
+        } else {
+            None
+        }
+    }
+
+    /// Gets verbose data if verbose mode is enabled.
+    fn get_verbose_data(
+        &self,
+        start_timestamp: Timestamp,
+        sample_count: u64,
+        pid: u32,
+    ) -> (Option<Timestamp>, Option<u64>, Option<u32>) {
+        if self.args.verbose {
+            (Some(start_timestamp), Some(sample_count), Some(pid))
+        } else {
+            (None, None, None)
+        }
+    }
+
+    /// Handles the results: saves timeline, manages baselines, formats output.
+    async fn handle_results(
+        &self,
+        mut result: types::MonitorResult,
+        compare_target: Option<String>,
+        compared_result: Option<types::MonitorResult>,
+        save_target: Option<String>,
+    ) -> Result<()> {
+        // Save timeline if requested
+        if let Err(e) = self.save_timeline_if_requested(&result) {
+            eprintln!("Warning: Failed to save timeline: {e}");
+        }
+        self.export_otlp_if_requested(
+            &result,
+            compare_target.as_deref().or(save_target.as_deref()),
+        )
+        .await;
+
+        // The comparison is made against the stored baseline before any
+        // save can overwrite it.
+        let mut comparison = match (&compare_target, &compared_result) {
+            (_, Some(compared)) => Some(baseline::compare_results(
+                compared,
+                &result,
+                self.args.regression_thresholds(),
+            )),
+            (Some(name), None) => Some(self.baseline_manager.compare(
+                name,
+                &result,
+                self.args.regression_thresholds(),
+            )?),
+            (None, None) => None,
+        };
+        if let Err(e) = self.save_influx_file_if_requested(&result, comparison.as_ref()) {
+            eprintln!("Warning: Failed to save InfluxDB line protocol: {e}");
+        }
+        if let Err(e) = self.save_html_report_if_requested(
+            &result,
+            compare_target.as_deref().zip(comparison.as_ref()),
+        ) {
+            eprintln!("Warning: Failed to save HTML report: {e}");
+        }
+        if let Err(e) = self.save_chart_if_requested(&result) {
+            eprintln!("Warning: Failed to save chart: {e}");
+        }
+        let terminal_chart = self.args.chart_term.then(|| {
+            let width = crossterm::terminal::size().map_or(80, |(columns, _)| columns as usize);
+            output::terminal_chart(&result, width, self.args.units)
+        });
+        if let Some(dir) = &self.args.archive {
+            if let Err(e) = archive::archive(dir, &result) {
+                eprintln!("Warning: Failed to archive the results: {e}");
+            }
+        }
+        // It may only have been kept for export
+        let html_output = self.args.output_kind() == Some(cli::OutputKind::Html);
+        if self.args.timeline.is_none() && !self.args.influx && !html_output {
+            result.timeline = None;
+            result.timeline_retention = None;
+            result.timeline_delta = None;
+            if let Some(comparison) = &mut comparison {
+                comparison.current.timeline = None;
+                comparison.current.timeline_retention = None;
+                comparison.current.timeline_delta = None;
+            }
+        }
+        if let Err(e) = self.append_to_log_if_requested(&result) {
+            eprintln!("Warning: Failed to append to the run log: {e}");
+        }
+        #[cfg(feature = "sqlite")]
+        if let Err(e) = self.record_history_if_requested(&result) {
+            eprintln!("Warning: Failed to record the run in the history database: {e}");
+        }
+
+        // Handle comparison or normal output
+        let exit_code = if let (Some(baseline_name), Some(comparison)) =
+            (&compare_target, comparison)
+        {
+            if comparison.regression_detected {
+                if let Err(e) = self.save_regression_report_if_requested(&comparison, baseline_name)
+                {
+                    eprintln!("Warning: Failed to save regression report: {e}");
+                }
+            }
+            if let Err(e) =
+                self.save_prom_file_if_requested(&result, Some(baseline_name), Some(&comparison))
+            {
+                eprintln!("Warning: Failed to save Prometheus metrics: {e}");
+            }
+            if let Err(e) = self.save_badge_if_requested(&result, Some(&comparison)) {
+                eprintln!("Warning: Failed to save badge: {e}");
+            }
+            if let Err(e) = self.save_json_file_if_requested(&result, Some(&comparison)) {
+                eprintln!("Warning: Failed to save JSON results: {e}");
+            }
+            self.report_to_github_if_requested(&result, Some((baseline_name, &comparison)));
+            self.push_if_requested(&result, Some(baseline_name), Some(&comparison))
+                .await;
+            self.handle_baseline_operations(save_target.as_deref(), &result, Some(&comparison))?;
+            self.handle_comparison(baseline_name, &comparison, &result)?
+        } else {
+            if let Err(e) = self.save_prom_file_if_requested(&result, save_target.as_deref(), None)
+            {
+                eprintln!("Warning: Failed to save Prometheus metrics: {e}");
+            }
+            if let Err(e) = self.save_badge_if_requested(&result, None) {
+                eprintln!("Warning: Failed to save badge: {e}");
+            }
+            if let Err(e) = self.save_json_file_if_requested(&result, None) {
+                eprintln!("Warning: Failed to save JSON results: {e}");
+            }
+            self.report_to_github_if_requested(&result, None);
+            self.push_if_requested(&result, save_target.as_deref(), None)
+                .await;
+            self.handle_baseline_operations(save_target.as_deref(), &result, None)?;
+            self.handle_normal_output(&result)?
+        };
+        // On stderr, so that it doesn't get in the way of parsing the
+        // results
+        if let Some(chart) = terminal_chart {
+            eprint!("\n{chart}");
+        }
+
+        // Exit with appropriate code
+        if let Some(code) = exit_code {
+            std::process::exit(code);
+        }
+
+        Ok(())
+    }
+
+    /// Resolves the baseline to compare against. `auto` picks the first
+    /// saved baseline named after the merge-base or the default branch.
+    fn resolve_compare_name(&self, name: &str) -> Result<String> {
+        if name != git::AUTO {
+            return Ok(name.to_string());
+        }
+
+        let candidates = git::compare_candidates(&self.args.baseline_scheme)?;
+        let Some(found) = candidates
+            .iter()
+            .find(|candidate| self.baseline_manager.exists(candidate))
+        else {
+            return Err(PeakMemError::InvalidArgument(format!(
+                "No baseline found for the default branch (tried: {})",
+                candidates.join(", ")
+            )));
+        };
+        eprintln!("Comparing against baseline '{found}'");
+        Ok(found.clone())
+    }
+
+    /// Saves the timeline to a file if requested.
+    fn save_timeline_if_requested(&self, result: &types::MonitorResult) -> Result<()> {
+        if let Some(timeline_path) = &self.args.timeline {
+            if let Some(timeline) = &result.timeline {
+                let samples = if self.args.timeline_normalized {
+                    serde_json::to_value(types::NormalizedSample::normalize(timeline))?
+                } else if let Some(format) = self.args.timestamp_format {
+                    let start = result.started_at();
+                    let samples: Vec<_> = timeline
+                        .iter()
+                        .map(|sample| {
+                            let mut json = serde_json::to_value(sample)?;
+                            json["timestamp"] = format.to_json(sample.timestamp, start);
+                            Ok(json)
+                        })
+                        .collect::<Result<_>>()?;
+                    serde_json::Value::Array(samples)
+                } else {
+                    serde_json::to_value(timeline)?
+                };
+                // A capped, thinned or paused timeline says how, ahead of
+                // its samples
+                let json = if result.timeline_retention.is_some()
+                    || result.timeline_delta.is_some()
+                    || !result.sampling_pauses.is_empty()
+                {
+                    let mut json = serde_json::json!({ "samples": samples });
+                    if let Some(retention) = &result.timeline_retention {
+                        json["retention"] = serde_json::to_value(retention)?;
+                    }
+                    if let Some(delta) = &result.timeline_delta {
+                        json["delta"] = serde_json::to_value(delta)?;
+                    }
+                    if !result.sampling_pauses.is_empty() {
+                        json["pauses"] = serde_json::to_value(&result.sampling_pauses)?;
+                    }
+                    json
+                } else {
+                    samples
+                };
+                std::fs::write(timeline_path, serde_json::to_string_pretty(&json)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a Markdown report of a regressed comparison if requested.
+    fn save_regression_report_if_requested(
+        &self,
+        comparison: &ComparisonResult,
+        baseline_name: &str,
+    ) -> Result<()> {
+        if let Some(path) = &self.args.regression_report {
+            let report = output::regression_report(
+                comparison,
+                baseline_name,
+                self.args.timeline.as_deref(),
+                self.args.units,
+            );
+            std::fs::write(path, report)?;
+            eprintln!("Regression report written to: {}", path.display());
+        }
+        Ok(())
+    }
+
+    /// Appends the results to the `--append-log` file as a line of JSON,
+    /// creating it if needed.
+    fn append_to_log_if_requested(&self, result: &types::MonitorResult) -> Result<()> {
+        let Some(path) = &self.args.append_log else {
+            return Ok(());
+        };
+        let mut line = serde_json::to_string(result)?;
+        line.push('\n');
+        // In a single write, so that lines of runs appending at the same
+        // time don't interleave
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Records the results in the `--history-db` database.
+    #[cfg(feature = "sqlite")]
+    fn record_history_if_requested(&self, result: &types::MonitorResult) -> Result<()> {
+        if let Some(path) = &self.args.history_db {
+            history::HistoryDb::open(path)?.record(result)?;
+        }
+        Ok(())
+    }
+
+    /// Lists the runs recorded in the `--history-db` database that match
+    /// the filters given.
+    #[cfg(feature = "sqlite")]
+    fn query_history(&self) -> Result<()> {
+        let path = self.args.history_db.as_ref().ok_or_else(|| {
+            PeakMemError::InvalidArgument("--query-history requires --history-db".to_string())
+        })?;
+        if !path.exists() {
+            return Err(PeakMemError::InvalidArgument(format!(
+                "No history database at {}",
+                path.display()
+            )));
+        }
+        let filter = history::HistoryFilter {
+            command: self.args.history_command.clone(),
+            since: self.args.since,
+            until: self.args.until,
+            tags: self.args.meta.clone(),
+        };
+        let runs = history::HistoryDb::open(path)?.query(&filter)?;
+        OutputFormatter::format_history(
+            &runs,
+            self.args.summary_format("--query-history")?,
+            self.args.units,
+        )
+    }
+
+    /// Writes an HTML report of the run if requested.
+    fn save_html_report_if_requested(
+        &self,
+        result: &types::MonitorResult,
+        comparison: Option<(&str, &ComparisonResult)>,
+    ) -> Result<()> {
+        if let Some(path) = &self.args.report {
+            let report =
+                output::html_report(result, comparison, self.args.threshold, self.args.units);
+            std::fs::write(path, report)?;
+            eprintln!("Report written to: {}", path.display());
+        }
+        Ok(())
+    }
+
+    /// Writes an SVG chart of the timeline if requested.
+    fn save_chart_if_requested(&self, result: &types::MonitorResult) -> Result<()> {
+        if let Some(path) = &self.args.chart {
+            let chart = output::svg_chart(result, self.args.threshold, self.args.units);
+            std::fs::write(path, chart)?;
+            eprintln!("Chart written to: {}", path.display());
+        }
+        Ok(())
+    }
+
+    /// Writes the results as Prometheus metrics if requested.
+    ///
+    /// The file is written next to its final path and renamed into place,
+    /// so that the textfile collector never reads half of it; it ignores
+    /// files not ending in `.prom`.
+    fn save_prom_file_if_requested(
+        &self,
+        result: &types::MonitorResult,
+        baseline_name: Option<&str>,
+        comparison: Option<&ComparisonResult>,
+    ) -> Result<()> {
+        if let Some(path) = &self.args.prom_file {
+            let metrics = output::prometheus_textfile(result, baseline_name, comparison);
+            let mut partial = path.clone().into_os_string();
+            partial.push(".tmp");
+            std::fs::write(&partial, metrics)?;
+            std::fs::rename(&partial, path)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the timeline and peak as InfluxDB line protocol if requested.
+    fn save_influx_file_if_requested(
+        &self,
+        result: &types::MonitorResult,
+        comparison: Option<&ComparisonResult>,
+    ) -> Result<()> {
+        if let Some(path) = &self.args.influx_file {
+            std::fs::write(path, output::influx_lines(result, comparison))?;
+        }
+        Ok(())
+    }
+
+    /// Writes the JSON results, or the comparison, to a file if requested.
+    ///
+    /// In CI, while the console gets the human summary, they are written
+    /// to `peak-mem.json` unless `--no-json-file` is given.
+    fn save_json_file_if_requested(
+        &self,
+        result: &types::MonitorResult,
+        comparison: Option<&ComparisonResult>,
+    ) -> Result<()> {
+        let (path, provider) = match &self.args.json_file {
+            Some(path) => (path.clone(), None),
+            None => {
+                let human = self.args.output_format() == cli::OutputFormat::Human
+                    && !self.args.tap
+                    && self.args.fields.is_empty()
+                    && self.args.template.is_none()
+                    && self.args.exec_format().is_none()
+                    && !self.args.prometheus_output();
+                match ci::detect() {
+                    Some(provider) if human && !self.args.no_json_file => {
+                        (ci::DEFAULT_JSON_FILE.into(), Some(provider))
+                    }
+                    _ => return Ok(()),
+                }
+            }
+        };
+        let json = match comparison {
+            Some(comparison) => serde_json::to_string_pretty(comparison)?,
+            None => serde_json::to_string_pretty(result)?,
+        };
+        std::fs::write(&path, json)?;
+        if let Some(provider) = provider {
+            eprintln!(
+                "{provider} detected; results written to: {} (--no-json-file to skip)",
+                path.display()
+            );
+        }
+        Ok(())
+    }
+
+    /// Writes an SVG badge of the peak if requested.
+    fn save_badge_if_requested(
+        &self,
+        result: &types::MonitorResult,
+        comparison: Option<&ComparisonResult>,
+    ) -> Result<()> {
+        if let Some(path) = &self.args.badge {
+            std::fs::write(path, output::badge(result, comparison, self.args.units))?;
+        }
+        Ok(())
+    }
+
+    /// Reports the results to GitHub Actions if requested: annotations go
+    /// to stderr, where the runner reads workflow commands as it does on
+    /// stdout, so they don't mix with the output, and the summary is
+    /// appended to the job summary.
+    fn report_to_github_if_requested(
+        &self,
+        result: &types::MonitorResult,
+        comparison: Option<(&str, &ComparisonResult)>,
+    ) {
+        if !self.args.github {
+            return;
+        }
+        eprint!(
+            "{}",
+            output::github_annotations(result, self.args.threshold, comparison, self.args.units)
+        );
+        let Some(path) = std::env::var_os("GITHUB_STEP_SUMMARY") else {
+            eprintln!("Warning: GITHUB_STEP_SUMMARY is not set; skipping the job summary");
+            return;
+        };
+        let summary = output::github_summary(result, comparison, self.args.units);
+        let appended = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| std::io::Write::write_all(&mut file, summary.as_bytes()));
+        if let Err(e) = appended {
+            eprintln!("Warning: Failed to write the job summary: {e}");
+        }
+    }
+
+    /// Pushes the results to a Pushgateway if requested, warning if the
+    /// push fails.
+    async fn push_if_requested(
+        &self,
+        result: &types::MonitorResult,
+        baseline_name: Option<&str>,
+        comparison: Option<&ComparisonResult>,
+    ) {
+        let Some(url) = &self.args.pushgateway else {
+            return;
+        };
+        let instance = match &self.args.push_instance {
+            Some(instance) => instance.clone(),
+            None => host_name(),
+        };
+        let gateway = pushgateway::Pushgateway::new(url, &self.args.push_job, &instance);
+        let metrics = output::prometheus_textfile(result, baseline_name, comparison);
+        if let Err(e) = gateway.push(&metrics).await {
+            eprintln!("Warning: Failed to push metrics to the Pushgateway: {e}");
+        }
+    }
+
+    /// Exports the results to an OTLP endpoint if requested, warning if
+    /// the export fails.
+    async fn export_otlp_if_requested(
+        &self,
+        result: &types::MonitorResult,
+        baseline_name: Option<&str>,
+    ) {
+        let Some(endpoint) = &self.args.otlp_endpoint else {
+            return;
+        };
+        let payload = otlp::metrics_request(result, &host_name(), baseline_name);
+        if let Err(e) = otlp::OtlpExporter::new(endpoint).export(&payload).await {
+            eprintln!(
+                "Warning: Failed to export metrics to {}: {e}",
+                endpoint.host
+            );
+        }
+    }
+
+    /// Handles baseline save operations.
+    ///
+    /// Saving happens after any comparison, so `--save-baseline` may name
+    /// the compared baseline to replace it. `--save-if-improved`,
+    /// `--save-if-no-regression` and `--update-baseline-if-improved` make
+    /// the save depend on the comparison.
+    fn handle_baseline_operations(
+        &self,
+        save_target: Option<&str>,
+        result: &types::MonitorResult,
+        comparison: Option<&ComparisonResult>,
+    ) -> Result<()> {
+        let Some(baseline_name) = save_target else {
+            return Ok(());
+        };
+
+        if let Some(comparison) = comparison {
+            let save = if self.args.save_if_improved {
+                comparison.is_improvement()
+            } else if self.args.save_if_no_regression {
+                !comparison.regression_detected
+            } else if let Some(tolerance) = self.args.update_baseline_if_improved {
+                comparison.is_within(tolerance)
+            } else {
+                true
+            };
+            if !save {
+                eprintln!(
+                    "Baseline '{baseline_name}' not saved: run did not meet the save condition"
+                );
+                return Ok(());
+            }
+        }
+
+        if self.args.append_to_baseline.is_some() {
+            let (path, runs) = self.baseline_manager.append_to_baseline(
+                baseline_name,
+                result,
+                self.args.baseline_window,
+            )?;
+            eprintln!(
+                "Baseline '{baseline_name}' saved to: {} ({runs} runs)",
+                path.display()
+            );
+            return Ok(());
+        }
+        if self.args.keep_history {
+            let (path, saves) = self
+                .baseline_manager
+                .save_with_history(baseline_name, result)?;
+            eprintln!(
+                "Baseline '{baseline_name}' saved to: {} ({saves} saves in history)",
+                path.display()
+            );
+            return Ok(());
+        }
+        let path = self.baseline_manager.save_baseline(baseline_name, result)?;
+        eprintln!("Baseline '{}' saved to: {}", baseline_name, path.display());
+        Ok(())
+    }
+
+    /// Handles baseline comparison output.
+    fn handle_comparison(
+        &self,
+        baseline_name: &str,
+        comparison: &ComparisonResult,
+        result: &types::MonitorResult,
+    ) -> Result<Option<i32>> {
+        let mut stdout = self.results_output()?;
+        if self.args.tap {
+            write!(
+                stdout,
+                "{}",
+                output::tap_report(
+                    result,
+                    !self.args.command.is_empty(),
+                    self.args.threshold,
+                    Some((baseline_name, comparison)),
+                    self.args.units,
+                )
+            )?;
+        } else if let Some(document) =
+            self.output_document(result, Some((baseline_name, comparison)))
+        {
+            write!(stdout, "{document}")?;
+        } else if let Some(template) = &self.args.template {
+            OutputFormatter::format_template(&mut stdout, result, template)?;
+        } else if let Some(format) = self.args.exec_format() {
+            OutputFormatter::format_exec(&mut stdout, result, Some(comparison), format)?;
+        } else if self.args.prometheus_output() {
+            let metrics =
+                output::prometheus_textfile(result, Some(baseline_name), Some(comparison));
+            write!(stdout, "{metrics}")?;
+        } else if self.args.fields.is_empty() {
+            OutputFormatter::format_comparison(
+                &mut stdout,
+                comparison,
+                self.args.output_format(),
+                self.args.units,
+                self.output_style(),
+            )?;
+        } else {
+            OutputFormatter::format_fields(
+                &mut stdout,
+                result,
+                &self.args.fields,
+                self.args.timestamp_format,
+            )?;
+        }
+        stdout.flush()?;
+
+        if comparison.regression_detected {
+            Ok(Some(1))
+        } else {
+            Ok(result.exit_code)
+        }
+    }
+
+    /// Opens where the results go: the `--output` file, replaced if it
+    /// exists, or else stdout.
+    ///
+    /// # Errors
+    /// * `PeakMemError::Io` - The file couldn't be created
+    fn results_output(&self) -> Result<Box<dyn Write>> {
+        Ok(match &self.args.output {
+            Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+            None => Box::new(std::io::stdout()),
+        })
+    }
+
+    /// Colors the human output by `--color`, where it goes and `NO_COLOR`.
+    fn output_style(&self) -> output::Style {
+        let terminal =
+            self.args.output.is_none() && std::io::IsTerminal::is_terminal(&std::io::stdout());
+        let no_color = std::env::var_os("NO_COLOR");
+        output::Style::new(
+            self.args
+                .color_choice()
+                .enabled(terminal, no_color.as_deref()),
+        )
+    }
+
+    /// Renders the Markdown summary or HTML report that an `--output`
+    /// file's extension asks for, if it asks for one.
+    fn output_document(
+        &self,
+        result: &types::MonitorResult,
+        comparison: Option<(&str, &ComparisonResult)>,
+    ) -> Option<String> {
+        match self.args.output_kind()? {
+            cli::OutputKind::Markdown => {
+                Some(output::github_summary(result, comparison, self.args.units))
+            }
+            cli::OutputKind::Html => Some(output::html_report(
+                result,
+                comparison,
+                self.args.threshold,
+                self.args.units,
+            )),
+            cli::OutputKind::Json | cli::OutputKind::Csv => None,
+        }
+    }
+
+    /// Handles normal output (no comparison).
+    fn handle_normal_output(&self, result: &types::MonitorResult) -> Result<Option<i32>> {
+        let mut stdout = self.results_output()?;
+        if self.args.tap {
+            write!(
+                stdout,
+                "{}",
+                output::tap_report(
+                    result,
+                    !self.args.command.is_empty(),
+                    self.args.threshold,
+                    None,
+                    self.args.units,
+                )
+            )?;
+        } else if let Some(document) = self.output_document(result, None) {
+            write!(stdout, "{document}")?;
+        } else if let Some(template) = &self.args.template {
+            OutputFormatter::format_template(&mut stdout, result, template)?;
+        } else if let Some(format) = self.args.exec_format() {
+            OutputFormatter::format_exec(&mut stdout, result, None, format)?;
+        } else if self.args.prometheus_output() {
+            write!(
+                stdout,
+                "{}",
+                output::prometheus_textfile(result, None, None)
+            )?;
+        } else if self.args.fields.is_empty() {
+            OutputFormatter::format(
+                &mut stdout,
+                result,
+                self.args.output_format(),
+                self.args.verbose,
+                self.args.units,
+                self.args.timestamp_format,
+                self.output_style(),
+            )?;
+        } else {
+            OutputFormatter::format_fields(
+                &mut stdout,
+                result,
+                &self.args.fields,
+                self.args.timestamp_format,
+            )?;
+        }
+        stdout.flush()?;
+
+        if result.threshold_exceeded {
+            Ok(Some(1))
+        } else {
+            Ok(result.exit_code)
+        }
+    }
+}
+
+/// Returns this machine's host name, or an empty string if it can't be
+/// read.
+fn host_name() -> String {
+    nix::unistd::gethostname()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Runs the peak-mem command, for `src/main.rs`.
+#[doc(hidden)]
+pub fn main() -> Result<()> {
+    // Configure tokio runtime with optimized thread stack size for
+    // Linux/macOS. Based on measurements showing ~10KB actual usage
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    builder.thread_stack_size(128 * 1024); // 128KB (vs default 2MB)
+
+    let runtime = builder
+        .enable_all()
+        .build()
+        .map_err(|e| PeakMemError::Runtime(format!("Failed to build runtime: {}", e)))?;
+
+    let result = runtime.block_on(async {
+        let args = cli::Cli::parse_args();
+        let app = Application::new(args)?;
+        app.run().await
+    });
+    // Like a shell, so that wrappers can tell a missing command from one
+    // that failed, and incomplete data from either
+    if let Err(e) = &result {
+        if let Some(code) = e.exit_code() {
+            eprintln!("Error: {e:?}");
+            std::process::exit(code);
+        }
+    }
+    result
+}
+
+/// How `--watch` shows the run.
+struct WatchDisplay {
+    interval_ms: u64,
+    units: Option<cli::MemoryUnit>,
+    /// The command, for the full screen of `--tui`.
+    tui: Option<String>,
+    /// How often to print a progress line instead when stdout isn't a
+    /// terminal, in seconds.
+    progress_secs: u64,
+    /// Whether to write JSON progress events to stderr instead, for
+    /// `--json`.
+    json: bool,
+}
+
+async fn run_with_realtime_display(
+    wait: impl std::future::Future<Output = Result<Option<i32>>>,
+    tracker: &MemoryTracker,
+    display: WatchDisplay,
+) -> Result<Option<i32>> {
+    let WatchDisplay {
+        interval_ms,
+        units,
+        tui,
+        progress_secs,
+        json,
+    } = display;
+    let peak_rss_atom = tracker.peak_rss.clone();
+    let peak_vsz_atom = tracker.peak_vsz.clone();
+    // Read current values from the tracker's own samples so that
+    // "current" and "peak" agree on what is being measured (the whole
+    // process tree unless --no-children was given).
+    let timeline = tracker.timeline_handle();
+    let live_tree = tracker.live_tree_handle();
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_flag = Arc::clone(&stop);
+
+    // Stdout has the results, so the events go to stderr, whatever it is
+    if json {
+        let started = Instant::now();
+        let events_task = tokio::spawn(async move {
+            let mut interval = time::interval(time::Duration::from_millis(interval_ms));
+            loop {
+                interval.tick().await;
+                let Some(usage) = timeline.read().await.last() else {
+                    continue;
+                };
+                eprintln!(
+                    "{}",
+                    output::progress_event(
+                        started.elapsed(),
+                        &usage,
+                        peak_rss_atom.load(Ordering::SeqCst),
+                        peak_vsz_atom.load(Ordering::SeqCst),
+                    )
+                );
+            }
+        });
+
+        let exit_code = wait.await;
+        events_task.abort();
+        let _ = events_task.await;
+        return exit_code;
+    }
+
+    // Cursor movement would garble a log, so it gets plain lines
+    if !std::io::IsTerminal::is_terminal(&std::io::stdout()) {
+        let started = Instant::now();
+        let progress_task = tokio::spawn(async move {
+            let every = time::Duration::from_secs(progress_secs);
+            let mut interval = time::interval_at(time::Instant::now() + every, every);
+            loop {
+                interval.tick().await;
+                let Some(usage) = timeline.read().await.last() else {
+                    continue;
+                };
+                eprintln!(
+                    "{}",
+                    output::progress_line(
+                        started.elapsed(),
+                        &usage,
+                        peak_rss_atom.load(Ordering::SeqCst),
+                        peak_vsz_atom.load(Ordering::SeqCst),
+                        units,
+                    )
+                );
+            }
+        });
+
+        // The next line may be seconds away
+        let exit_code = wait.await;
+        progress_task.abort();
+        let _ = progress_task.await;
+        return exit_code;
+    }
+
+    if let Some(command) = tui {
+        let started = Instant::now();
+        let mut screen = TuiDisplay::enter(units)?;
+        let monitor_task = tokio::spawn(async move {
+            let mut interval = time::interval(time::Duration::from_millis(interval_ms));
+            while !stop_flag.load(Ordering::SeqCst) {
+                interval.tick().await;
+
+                // A column per sample is the most the screen can show
+                let width = crossterm::terminal::size().map_or(80, |(width, _)| width);
+                let recent = timeline.read().await.recent(width as usize);
+                let tree = match &live_tree {
+                    Some(live_tree) => live_tree.read().await.clone(),
+                    None => None,
+                };
+                let state = WatchState {
+                    command: &command,
+                    elapsed: started.elapsed(),
+                    recent: &recent,
+                    peak_rss: peak_rss_atom.load(Ordering::SeqCst),
+                    peak_vsz: peak_vsz_atom.load(Ordering::SeqCst),
+                    tree: tree.as_ref(),
+                };
+                if screen.draw(&state).is_err() {
+                    break;
+                }
+            }
+            // Back to the normal screen for the results
+            drop(screen);
+        });
+
+        let exit_code = wait.await;
+        stop.store(true, Ordering::SeqCst);
+        let _ = monitor_task.await;
+        return exit_code;
+    }
+
+    let monitor_task = tokio::spawn(async move {
+        let mut display = RealtimeDisplay::new(units);
+        let mut interval = time::interval(time::Duration::from_millis(interval_ms));
+
+        while !stop_flag.load(Ordering::SeqCst) {
+            interval.tick().await;
+
+            let latest = timeline.read().await.last();
+            if let Some(usage) = latest {
+                let current_rss = ByteSize::b(usage.rss_bytes);
+                let current_vsz = ByteSize::b(usage.vsz_bytes);
+                let peak_rss = ByteSize::b(peak_rss_atom.load(Ordering::SeqCst));
+                let peak_vsz = ByteSize::b(peak_vsz_atom.load(Ordering::SeqCst));
+                let tree = match &live_tree {
+                    Some(live_tree) => live_tree.read().await.clone(),
+                    None => None,
+                };
+
+                if display
+                    .update(current_rss, peak_rss, current_vsz, peak_vsz, tree.as_ref())
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        }
+
+        let _ = display.clear();
+    });
+
+    let exit_code = wait.await?;
+    stop.store(true, Ordering::SeqCst);
+    let _ = monitor_task.await;
+
+    Ok(exit_code)
+}
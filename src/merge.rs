@@ -0,0 +1,140 @@
+//! Statistics over saved runs, for `--merge`.
+//!
+//! Runs of a command saved one `--json` result at a time, say by separate
+//! CI jobs, are summed up as one: the lowest, mean and highest peak RSS
+//! and duration, and their sample standard deviation. A result saved with
+//! `--runs` counts as each of its runs.
+
+use crate::types::{MonitorResult, PeakMemError, Result, RunSummary};
+use serde::Serialize;
+
+/// The spread of a value over the runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    pub min: u64,
+    pub mean: u64,
+    pub max: u64,
+    /// Sample standard deviation, 0 for a single run.
+    pub stddev: u64,
+}
+
+impl Stats {
+    fn new(values: &[u64]) -> Self {
+        let count = values.len() as f64;
+        let mean = values.iter().map(|&value| value as f64).sum::<f64>() / count;
+        let stddev = if values.len() > 1 {
+            (values
+                .iter()
+                .map(|&value| (value as f64 - mean).powi(2))
+                .sum::<f64>()
+                / (count - 1.0))
+                .sqrt()
+        } else {
+            0.0
+        };
+        Self {
+            min: values.iter().copied().min().unwrap_or(0),
+            mean: mean.round() as u64,
+            max: values.iter().copied().max().unwrap_or(0),
+            stddev: stddev.round() as u64,
+        }
+    }
+}
+
+/// Saved runs of a command, merged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MergedRuns {
+    pub command: String,
+    pub runs: usize,
+    /// Runs in which the command didn't exit with 0.
+    pub failed_runs: usize,
+    pub peak_rss_bytes: Stats,
+    pub duration_ms: Stats,
+}
+
+impl MergedRuns {
+    /// Merges the runs of saved results.
+    ///
+    /// # Errors
+    /// * `PeakMemError::InvalidArgument` - No results, or results of different
+    ///   commands
+    pub fn new(results: &[MonitorResult]) -> Result<Self> {
+        let first = results
+            .first()
+            .ok_or_else(|| PeakMemError::InvalidArgument("No results to merge".to_string()))?;
+        if let Some(other) = results.iter().find(|r| r.command != first.command) {
+            return Err(PeakMemError::InvalidArgument(format!(
+                "Only runs of the same command can be merged, not '{}' and '{}'",
+                first.command, other.command
+            )));
+        }
+
+        let runs: Vec<RunSummary> = results
+            .iter()
+            .flat_map(|result| {
+                if result.runs.is_empty() {
+                    vec![RunSummary {
+                        peak_rss_bytes: result.peak_rss_bytes,
+                        peak_vsz_bytes: result.peak_vsz_bytes,
+                        duration_ms: result.duration_ms,
+                        exit_code: result.exit_code,
+                    }]
+                } else {
+                    result.runs.clone()
+                }
+            })
+            .collect();
+        let values = |value: fn(&RunSummary) -> u64| runs.iter().map(value).collect::<Vec<_>>();
+        Ok(Self {
+            command: first.command.clone(),
+            runs: runs.len(),
+            failed_runs: runs.iter().filter(|run| run.exit_code != Some(0)).count(),
+            peak_rss_bytes: Stats::new(&values(|run| run.peak_rss_bytes)),
+            duration_ms: Stats::new(&values(|run| run.duration_ms)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(command: &str, rss: u64, duration_ms: u64) -> MonitorResult {
+        MonitorResult {
+            duration_ms,
+            ..MonitorResult::fixture(command, rss)
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut repeated = result("./app", 300, 30);
+        repeated.runs = [(200, 20, Some(0)), (300, 30, Some(1))]
+            .into_iter()
+            .map(|(peak_rss_bytes, duration_ms, exit_code)| RunSummary {
+                peak_rss_bytes,
+                peak_vsz_bytes: 0,
+                duration_ms,
+                exit_code,
+            })
+            .collect();
+        let merged = MergedRuns::new(&[result("./app", 100, 10), repeated]).unwrap();
+        assert_eq!(merged.runs, 3);
+        assert_eq!(merged.failed_runs, 1);
+        assert_eq!(
+            merged.peak_rss_bytes,
+            Stats {
+                min: 100,
+                mean: 200,
+                max: 300,
+                stddev: 100
+            }
+        );
+        assert_eq!(merged.duration_ms.mean, 20);
+
+        let single = MergedRuns::new(&[result("./app", 100, 10)]).unwrap();
+        assert_eq!(single.peak_rss_bytes.stddev, 0);
+        assert!(MergedRuns::new(&[result("./app", 1, 1), result("./other", 1, 1)]).is_err());
+        assert!(MergedRuns::new(&[]).is_err());
+    }
+}
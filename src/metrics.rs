@@ -0,0 +1,215 @@
+//! Prometheus metrics endpoint for `--serve-metrics`.
+//!
+//! While a run lasts, `GET /metrics` on the given address returns the
+//! current and peak memory in the Prometheus text exposition format, so a
+//! long benchmark can be scraped and graphed live. Like the HTTP client in
+//! [`crate::http`], this speaks just enough HTTP/1.1 for the purpose: one
+//! request per connection, no keep-alive.
+
+use crate::monitor::tracker::MemoryTracker;
+use crate::output::prometheus_exposition;
+use crate::types::{PeakMemError, Result};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// How long a client may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest request head read; anything longer is rejected.
+const MAX_REQUEST_BYTES: usize = 8192;
+
+/// Content type of the text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// The tracker's live values, shared with the server.
+#[derive(Clone)]
+pub struct MetricsSource {
+    command: String,
+    peak_rss: Arc<AtomicU64>,
+    peak_vsz: Arc<AtomicU64>,
+    current_rss: Arc<AtomicU64>,
+    current_vsz: Arc<AtomicU64>,
+    sample_count: Arc<AtomicU64>,
+}
+
+impl MetricsSource {
+    /// Reads the values of a tracker, labelled with the monitored command.
+    pub fn new(command: String, tracker: &MemoryTracker) -> Self {
+        Self {
+            command,
+            peak_rss: Arc::clone(&tracker.peak_rss),
+            peak_vsz: Arc::clone(&tracker.peak_vsz),
+            current_rss: Arc::clone(&tracker.current_rss),
+            current_vsz: Arc::clone(&tracker.current_vsz),
+            sample_count: tracker.sample_count_handle(),
+        }
+    }
+
+    /// Renders the current values in the text exposition format.
+    fn render(&self) -> String {
+        prometheus_exposition(
+            &[("command", &self.command)],
+            &[
+                (
+                    "rss_bytes",
+                    "gauge",
+                    "Resident set size in the latest sample",
+                    self.current_rss.load(Ordering::SeqCst) as f64,
+                ),
+                (
+                    "vsz_bytes",
+                    "gauge",
+                    "Virtual size in the latest sample",
+                    self.current_vsz.load(Ordering::SeqCst) as f64,
+                ),
+                (
+                    "peak_rss_bytes",
+                    "gauge",
+                    "Highest resident set size so far",
+                    self.peak_rss.load(Ordering::SeqCst) as f64,
+                ),
+                (
+                    "peak_vsz_bytes",
+                    "gauge",
+                    "Peak virtual size so far",
+                    self.peak_vsz.load(Ordering::SeqCst) as f64,
+                ),
+                (
+                    "samples_total",
+                    "counter",
+                    "Samples taken so far",
+                    self.sample_count.load(Ordering::SeqCst) as f64,
+                ),
+            ],
+        )
+    }
+}
+
+/// Starts listening, so that a bad address fails before the run.
+///
+/// # Errors
+/// * `PeakMemError::InvalidArgument` - The address can't be bound
+pub async fn bind(addr: &str) -> Result<TcpListener> {
+    TcpListener::bind(addr)
+        .await
+        .map_err(|e| PeakMemError::InvalidArgument(format!("Cannot listen on {addr}: {e}")))
+}
+
+/// Serves `/metrics` until the returned task is aborted.
+pub fn serve(listener: TcpListener, source: MetricsSource) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                // E.g. out of file descriptors; try again shortly
+                Err(_) => {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            let source = source.clone();
+            tokio::spawn(async move {
+                // A client that goes away mid-request isn't our problem
+                let _ = tokio::time::timeout(REQUEST_TIMEOUT, respond(stream, &source)).await;
+            });
+        }
+    })
+}
+
+async fn respond(mut stream: TcpStream, source: &MetricsSource) -> Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 || request.len() + n > MAX_REQUEST_BYTES {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let (status, content_type, body) = match route(&request) {
+        Route::Metrics => ("200 OK", CONTENT_TYPE, source.render()),
+        Route::NotFound => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+        Route::MethodNotAllowed => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Only GET is supported\n".to_string(),
+        ),
+        Route::BadRequest => ("400 Bad Request", "text/plain", "Bad request\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// What a request asks for.
+#[derive(Debug, PartialEq, Eq)]
+enum Route {
+    Metrics,
+    NotFound,
+    MethodNotAllowed,
+    BadRequest,
+}
+
+/// Routes a request by its request line.
+fn route(request: &[u8]) -> Route {
+    let head = String::from_utf8_lossy(request);
+    let mut parts = head.lines().next().unwrap_or("").split_whitespace();
+    let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Route::BadRequest;
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Route::BadRequest;
+    }
+
+    let path = target.split('?').next().unwrap_or(target);
+    match (method, path) {
+        ("GET", "/metrics") => Route::Metrics,
+        (_, "/metrics") => Route::MethodNotAllowed,
+        _ => Route::NotFound,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route() {
+        assert_eq!(route(b"GET /metrics HTTP/1.1\r\n\r\n"), Route::Metrics);
+        assert_eq!(route(b"GET /metrics?x=1 HTTP/1.0\r\n\r\n"), Route::Metrics);
+        assert_eq!(route(b"GET / HTTP/1.1\r\n\r\n"), Route::NotFound);
+        assert_eq!(
+            route(b"POST /metrics HTTP/1.1\r\n\r\n"),
+            Route::MethodNotAllowed
+        );
+        assert_eq!(route(b"garbage"), Route::BadRequest);
+    }
+
+    #[test]
+    fn test_render() {
+        let value = |bytes: u64| Arc::new(AtomicU64::new(bytes));
+        let source = MetricsSource {
+            command: "make".to_string(),
+            peak_rss: value(4096),
+            peak_vsz: value(8192),
+            current_rss: value(1024),
+            current_vsz: value(2048),
+            sample_count: value(12),
+        };
+        let text = source.render();
+        assert!(text.contains("peak_mem_rss_bytes{command=\"make\"} 1024\n"));
+        assert!(text.contains("peak_mem_peak_rss_bytes{command=\"make\"} 4096\n"));
+        assert!(text.contains("peak_mem_samples_total{command=\"make\"} 12\n"));
+    }
+}
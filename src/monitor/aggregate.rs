@@ -0,0 +1,170 @@
+//! How the memory of a process tree is combined, for `--aggregate`.
+//!
+//! Summing RSS over a tree counts pages shared between processes (shared
+//! libraries, copy-on-write memory after fork) once per process, so the
+//! sum can exceed what the tree really uses. There is no single right
+//! answer without reading every mapping, so the definition is chosen and
+//! stated instead:
+//!
+//! * `sum` - every process added up (the default), which overcounts shared
+//!   pages but never misses memory
+//! * `max-of-processes` - the largest single process, as `getrusage()` and
+//!   `time -v` report, which never overcounts but misses concurrency
+//! * `root-only-plus-named:NAMES` - the root process plus every process in the
+//!   tree named in NAMES, e.g. the compilers of a build but not its shell
+//!   wrappers
+//!
+//! Only RSS and VSZ are affected; fault, thread and I/O counters are
+//! always totals of the whole tree.
+
+use crate::types::{PeakMemError, ProcessMemoryInfo, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// A definition of a process tree's memory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Aggregate {
+    /// Every process in the tree added up.
+    #[default]
+    Sum,
+    /// The process with the highest RSS.
+    MaxOfProcesses,
+    /// The root process plus every other process with one of these names.
+    RootPlusNamed(Vec<String>),
+}
+
+impl Aggregate {
+    /// Combines the RSS and VSZ of a tree, in bytes.
+    pub fn memory(&self, tree: &ProcessMemoryInfo) -> (u64, u64) {
+        match self {
+            Aggregate::Sum => {
+                let mut total = (0, 0);
+                visit(tree, &mut |info| {
+                    total.0 += info.memory.rss_bytes;
+                    total.1 += info.memory.vsz_bytes;
+                });
+                total
+            }
+            Aggregate::MaxOfProcesses => {
+                let mut largest = (0, 0);
+                visit(tree, &mut |info| {
+                    if info.memory.rss_bytes > largest.0 {
+                        largest = (info.memory.rss_bytes, info.memory.vsz_bytes);
+                    }
+                });
+                largest
+            }
+            Aggregate::RootPlusNamed(names) => {
+                let mut total = (tree.memory.rss_bytes, tree.memory.vsz_bytes);
+                for child in &tree.children {
+                    visit(child, &mut |info| {
+                        if names.contains(&info.name) {
+                            total.0 += info.memory.rss_bytes;
+                            total.1 += info.memory.vsz_bytes;
+                        }
+                    });
+                }
+                total
+            }
+        }
+    }
+}
+
+fn visit(info: &ProcessMemoryInfo, f: &mut dyn FnMut(&ProcessMemoryInfo)) {
+    f(info);
+    for child in &info.children {
+        visit(child, f);
+    }
+}
+
+impl FromStr for Aggregate {
+    type Err = PeakMemError;
+
+    /// Parses `sum`, `max-of-processes` or
+    /// `root-only-plus-named:NAME[,NAME...]`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None if s == "sum" => Ok(Aggregate::Sum),
+            None if s == "max-of-processes" => Ok(Aggregate::MaxOfProcesses),
+            Some(("root-only-plus-named", names)) => {
+                let names: Vec<String> = names
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(String::from)
+                    .collect();
+                if names.is_empty() {
+                    return Err(PeakMemError::InvalidArgument(
+                        "root-only-plus-named needs process names, e.g. \
+                         root-only-plus-named:cc1,ld"
+                            .to_string(),
+                    ));
+                }
+                Ok(Aggregate::RootPlusNamed(names))
+            }
+            _ => Err(PeakMemError::InvalidArgument(format!(
+                "Invalid aggregate '{s}'. Use one of: sum, max-of-processes, \
+                 root-only-plus-named:NAMES"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Aggregate::Sum => write!(f, "sum"),
+            Aggregate::MaxOfProcesses => write!(f, "max-of-processes"),
+            Aggregate::RootPlusNamed(names) => {
+                write!(f, "root-only-plus-named:{}", names.join(","))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{MemoryUsage, Timestamp};
+
+    fn process(name: &str, rss: u64, children: Vec<ProcessMemoryInfo>) -> ProcessMemoryInfo {
+        ProcessMemoryInfo {
+            pid: 1,
+            name: name.to_string(),
+            memory: MemoryUsage::new(rss, rss * 2, Timestamp::now()),
+            children,
+            namespace: None,
+            rss_stats: None,
+        }
+    }
+
+    #[test]
+    fn test_memory() {
+        let tree = process(
+            "make",
+            10,
+            vec![
+                process("sh", 5, vec![process("cc1", 300, vec![])]),
+                process("cc1", 200, vec![]),
+                process("ld", 100, vec![]),
+            ],
+        );
+
+        assert_eq!(Aggregate::Sum.memory(&tree), (615, 1230));
+        assert_eq!(Aggregate::MaxOfProcesses.memory(&tree), (300, 600));
+        let named: Aggregate = "root-only-plus-named:cc1,ld".parse().unwrap();
+        assert_eq!(named.memory(&tree), (610, 1220));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("sum".parse::<Aggregate>().unwrap(), Aggregate::Sum);
+        assert_eq!(
+            "root-only-plus-named:cc1, ld".parse::<Aggregate>().unwrap(),
+            Aggregate::RootPlusNamed(vec!["cc1".to_string(), "ld".to_string()])
+        );
+        assert!("root-only-plus-named:".parse::<Aggregate>().is_err());
+        assert!("average".parse::<Aggregate>().is_err());
+        assert_eq!(Aggregate::MaxOfProcesses.to_string(), "max-of-processes");
+    }
+}
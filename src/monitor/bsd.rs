@@ -0,0 +1,244 @@
+//! OpenBSD and NetBSD support through the `kern.proc` sysctls.
+//!
+//! sysinfo doesn't support either system, so process records are read
+//! from the kernel directly: `KERN_PROC` on OpenBSD and `KERN_PROC2` on
+//! NetBSD. Both take the record size and count as part of the MIB, so the
+//! query keeps working when a newer kernel grows the record.
+
+use crate::monitor::MemoryMonitor;
+use crate::types::{MemoryUsage, PeakMemError, ProcessMemoryInfo, Result, Timestamp};
+use std::ffi::CStr;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+
+#[cfg(target_os = "openbsd")]
+type KinfoProc = libc::kinfo_proc;
+#[cfg(target_os = "netbsd")]
+type KinfoProc = libc::kinfo_proc2;
+
+#[cfg(target_os = "openbsd")]
+const KERN_PROC: libc::c_int = libc::KERN_PROC;
+#[cfg(target_os = "netbsd")]
+const KERN_PROC: libc::c_int = libc::KERN_PROC2;
+
+pub struct BSDMonitor {
+    page_size: u64,
+}
+
+impl BSDMonitor {
+    pub fn new() -> Result<Self> {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        if page_size <= 0 {
+            return Err(PeakMemError::Monitor(
+                "Failed to determine the page size".to_string(),
+            ));
+        }
+
+        Ok(BSDMonitor {
+            page_size: page_size as u64,
+        })
+    }
+
+    fn read_usage(&self, pid: u32) -> Result<MemoryUsage> {
+        let info = read_kinfo_proc(pid)?;
+
+        let rss_bytes = info.p_vm_rssize.max(0) as u64 * self.page_size;
+        let mut usage = MemoryUsage::new(rss_bytes, self.vsz_bytes(&info), Timestamp::now());
+        usage.thread_count = thread_count(pid, &info);
+
+        // The rusage fields are only filled in for processes still
+        // holding their user area
+        if info.p_uvalid != 0 {
+            usage.minor_faults = info.p_uru_minflt;
+            usage.major_faults = info.p_uru_majflt;
+        }
+
+        Ok(usage)
+    }
+
+    /// Virtual size as ps(1) reports it: text, data and stack on OpenBSD.
+    #[cfg(target_os = "openbsd")]
+    fn vsz_bytes(&self, info: &KinfoProc) -> u64 {
+        let pages = info.p_vm_tsize.max(0) as u64
+            + info.p_vm_dsize.max(0) as u64
+            + info.p_vm_ssize.max(0) as u64;
+        pages * self.page_size
+    }
+
+    /// Virtual size as ps(1) reports it: all mapped pages on NetBSD.
+    #[cfg(target_os = "netbsd")]
+    fn vsz_bytes(&self, info: &KinfoProc) -> u64 {
+        info.p_vm_msize.max(0) as u64 * self.page_size
+    }
+
+    fn get_process_name(&self, pid: u32) -> String {
+        read_kinfo_proc(pid)
+            .map(|info| {
+                unsafe { CStr::from_ptr(info.p_comm.as_ptr()) }
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .unwrap_or_else(|_| format!("pid:{pid}"))
+    }
+}
+
+impl MemoryMonitor for BSDMonitor {
+    fn get_memory_usage(
+        &self,
+        pid: u32,
+    ) -> Pin<Box<dyn Future<Output = Result<MemoryUsage>> + Send + '_>> {
+        Box::pin(async move { self.read_usage(pid) })
+    }
+
+    fn get_process_tree(
+        &self,
+        pid: u32,
+    ) -> Pin<Box<dyn Future<Output = Result<ProcessMemoryInfo>> + Send + '_>> {
+        Box::pin(async move {
+            let memory = self.get_memory_usage(pid).await?;
+            let name = self.get_process_name(pid);
+            let child_pids = self.get_child_pids(pid).await?;
+
+            let mut children = Vec::new();
+            for child_pid in child_pids {
+                if let Ok(child_info) = self.get_process_tree(child_pid).await {
+                    children.push(child_info);
+                }
+            }
+
+            Ok(ProcessMemoryInfo {
+                pid,
+                name,
+                memory,
+                children,
+                namespace: None,
+                rss_stats: None,
+            })
+        })
+    }
+
+    fn get_child_pids(
+        &self,
+        pid: u32,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u32>>> + Send + '_>> {
+        Box::pin(async move {
+            let children = read_kinfo_procs(libc::KERN_PROC_ALL, 0)?
+                .iter()
+                .filter(|info| info.p_ppid == pid as i32)
+                .map(|info| info.p_pid as u32)
+                .collect();
+
+            Ok(children)
+        })
+    }
+}
+
+/// Counts a process's threads. OpenBSD has no thread count in the
+/// record, so its threads are listed and counted instead.
+#[cfg(target_os = "openbsd")]
+fn thread_count(pid: u32, _info: &KinfoProc) -> u64 {
+    // The listing holds one entry for the process itself (p_tid == -1)
+    // followed by one per thread
+    read_kinfo_procs(
+        libc::KERN_PROC_PID | libc::KERN_PROC_SHOW_THREADS,
+        pid as i32,
+    )
+    .map(|entries| entries.iter().filter(|info| info.p_tid != -1).count() as u64)
+    .unwrap_or(1)
+}
+
+/// Counts a process's threads (LWPs).
+#[cfg(target_os = "netbsd")]
+fn thread_count(_pid: u32, info: &KinfoProc) -> u64 {
+    info.p_nlwps
+}
+
+/// Reads the process record for a single process.
+fn read_kinfo_proc(pid: u32) -> Result<KinfoProc> {
+    read_kinfo_procs(libc::KERN_PROC_PID, pid as i32)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| PeakMemError::ProcessSpawn(format!("Process {pid} not found")))
+}
+
+/// Runs a `kern.proc` query and returns the records it yields.
+///
+/// # Arguments
+/// * `op` - Selector such as `KERN_PROC_PID` or `KERN_PROC_ALL`
+/// * `arg` - Argument to the selector, e.g. the process ID
+fn read_kinfo_procs(op: libc::c_int, arg: libc::c_int) -> Result<Vec<KinfoProc>> {
+    let record_size = mem::size_of::<KinfoProc>();
+    let mut mib = [
+        libc::CTL_KERN,
+        KERN_PROC,
+        op,
+        arg,
+        record_size as libc::c_int,
+        0,
+    ];
+
+    loop {
+        // Ask how much room the records need, then leave headroom for
+        // processes started in between
+        let mut len = 0;
+        let ret = unsafe {
+            libc::sysctl(
+                mib.as_ptr(),
+                mib.len() as libc::c_uint,
+                std::ptr::null_mut(),
+                &mut len,
+                std::ptr::null_mut(),
+                0,
+            )
+        };
+        if ret != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        let capacity = len / record_size + 8;
+        let mut records: Vec<KinfoProc> = Vec::with_capacity(capacity);
+        mib[5] = capacity as libc::c_int;
+        len = capacity * record_size;
+
+        let ret = unsafe {
+            libc::sysctl(
+                mib.as_ptr(),
+                mib.len() as libc::c_uint,
+                records.as_mut_ptr() as *mut libc::c_void,
+                &mut len,
+                std::ptr::null_mut(),
+                0,
+            )
+        };
+        if ret == 0 {
+            // The kernel wrote `len` bytes of whole records
+            unsafe { records.set_len(len / record_size) };
+            return Ok(records);
+        }
+
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ENOMEM) {
+            return Err(err.into());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_get_memory_usage_self() {
+        let monitor = BSDMonitor::new().unwrap();
+        let pid = std::process::id();
+
+        let usage = monitor.get_memory_usage(pid).await;
+        assert!(usage.is_ok());
+
+        let usage = usage.unwrap();
+        assert!(usage.rss_bytes > 0);
+        assert!(usage.vsz_bytes > 0);
+        assert!(usage.thread_count >= 1);
+    }
+}
@@ -0,0 +1,284 @@
+//! Memory accounting of a Linux cgroup.
+//!
+//! A container's or systemd unit's memory is charged to its cgroup, which
+//! counts page cache,
+//! kernel memory and processes outside the init process's tree that RSS
+//! sampling misses. Both cgroup v2 (`memory.current`, `memory.peak`) and
+//! the v1 memory controller (`memory.usage_in_bytes`,
+//! `memory.max_usage_in_bytes`) are supported. `memory.peak` needs Linux
+//! 5.19; without it the peak is the highest value sampled.
+//!
+//! Both peak files hold the cgroup's high-water mark since it was created,
+//! which for a container or service that has been up a while lies before
+//! monitoring began. So the kernel's peak only counts once it rises above
+//! what it was when the cgroup was found, which can only happen during the
+//! run; until then the sampled current charge is all there is.
+
+use crate::types::{PeakMemError, Result};
+use std::path::{Path, PathBuf};
+
+/// The memory files of one cgroup.
+#[derive(Debug, Clone)]
+pub struct CgroupMemory {
+    current: PathBuf,
+    peak: PathBuf,
+    /// The kernel's peak when the cgroup was found.
+    peak_at_start: Option<u64>,
+}
+
+impl CgroupMemory {
+    /// Finds the memory cgroup a process belongs to.
+    ///
+    /// # Errors
+    /// * `PeakMemError::Monitor` - The process's cgroup can't be read, or its
+    ///   memory controller isn't mounted where it can be seen
+    pub fn for_pid(pid: u32) -> Result<Self> {
+        let read = |path: String| {
+            std::fs::read_to_string(&path)
+                .map_err(|e| PeakMemError::Monitor(format!("Failed to read {path}: {e}")))
+        };
+        let cgroups = read(format!("/proc/{pid}/cgroup"))?;
+        let mountinfo = read("/proc/self/mountinfo".to_string())?;
+
+        Self::find(
+            &mountinfo,
+            parse_cgroup_path(&cgroups, None),
+            parse_cgroup_path(&cgroups, Some("memory")),
+        )
+        .ok_or_else(|| PeakMemError::Monitor(format!("No memory cgroup found for process {pid}")))
+    }
+
+    /// Finds a cgroup by its path in the hierarchy, such as
+    /// `/system.slice/nginx.service`, with either cgroup version.
+    ///
+    /// # Errors
+    /// * `PeakMemError::Monitor` - No cgroup with a memory controller exists at
+    ///   that path
+    pub fn for_path(path: &str) -> Result<Self> {
+        let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").map_err(|e| {
+            PeakMemError::Monitor(format!("Failed to read /proc/self/mountinfo: {e}"))
+        })?;
+
+        Self::find(&mountinfo, Some(path), Some(path))
+            .ok_or_else(|| PeakMemError::Monitor(format!("No memory cgroup found at {path}")))
+    }
+
+    /// Picks the v2 cgroup, or failing that the v1 memory cgroup, whose
+    /// memory files exist.
+    fn find(mountinfo: &str, v2_path: Option<&str>, v1_path: Option<&str>) -> Option<Self> {
+        let v2 = v2_path
+            .and_then(|path| resolve_mount(mountinfo, "cgroup2", None, path))
+            .map(|dir| (dir.join("memory.current"), dir.join("memory.peak")));
+        let v1 = v1_path
+            .and_then(|path| resolve_mount(mountinfo, "cgroup", Some("memory"), path))
+            .map(|dir| {
+                (
+                    dir.join("memory.usage_in_bytes"),
+                    dir.join("memory.max_usage_in_bytes"),
+                )
+            });
+
+        // On hybrid systems the v2 hierarchy may lack the memory controller
+        let (current, peak) = v2
+            .into_iter()
+            .chain(v1)
+            .find(|(current, _)| current.exists())?;
+        Some(Self::new(current, peak))
+    }
+
+    /// Starts watching the cgroup whose memory files these are.
+    fn new(current: PathBuf, peak: PathBuf) -> Self {
+        let peak_at_start = read_bytes(&peak);
+        Self {
+            current,
+            peak,
+            peak_at_start,
+        }
+    }
+
+    /// Returns the memory currently charged to the cgroup (in bytes), or
+    /// `None` once the cgroup has been removed.
+    pub fn current(&self) -> Option<u64> {
+        read_bytes(&self.current)
+    }
+
+    /// Returns the highest memory the kernel has charged to the cgroup (in
+    /// bytes) since it was found, if it keeps track and the peak has risen
+    /// since.
+    pub fn peak(&self) -> Option<u64> {
+        read_bytes(&self.peak).filter(|&peak| self.peak_at_start.is_none_or(|start| peak > start))
+    }
+}
+
+fn read_bytes(path: &Path) -> Option<u64> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Returns the path of a process's cgroup in the hierarchy: the v2 one,
+/// or failing that the v1 memory one.
+#[cfg(target_os = "linux")]
+pub(super) fn cgroup_path(pid: u32) -> Option<String> {
+    let cgroups = std::fs::read_to_string(format!("/proc/{pid}/cgroup")).ok()?;
+    parse_cgroup_path(&cgroups, None)
+        .or_else(|| parse_cgroup_path(&cgroups, Some("memory")))
+        .map(str::to_string)
+}
+
+/// Returns a process's cgroup path from `/proc/<pid>/cgroup`: that of the
+/// v1 hierarchy with the given controller, or of the v2 hierarchy.
+fn parse_cgroup_path<'a>(cgroups: &'a str, controller: Option<&str>) -> Option<&'a str> {
+    // 4:memory:/docker/3f2a...   (v1)
+    // 0::/system.slice/docker-3f2a....scope   (v2)
+    cgroups.lines().find_map(|line| {
+        let mut fields = line.splitn(3, ':');
+        let (_, controllers, path) = (fields.next()?, fields.next()?, fields.next()?);
+        let matches = match controller {
+            Some(controller) => controllers.split(',').any(|c| c == controller),
+            None => controllers.is_empty(),
+        };
+        matches.then_some(path)
+    })
+}
+
+/// Finds where a cgroup is visible in this mount namespace, given the
+/// hierarchy's filesystem type and, for v1, its controller.
+///
+/// A mount shows the hierarchy from its root, which inside a container is
+/// usually the container's own cgroup rather than `/`.
+fn resolve_mount(
+    mountinfo: &str,
+    fstype: &str,
+    controller: Option<&str>,
+    path: &str,
+) -> Option<PathBuf> {
+    // 30 24 0:26 / /sys/fs/cgroup/memory rw - cgroup cgroup rw,memory
+    mountinfo.lines().find_map(|line| {
+        let (mount, sb) = line.split_once(" - ")?;
+        let mut sb = sb.split(' ');
+        if sb.next() != Some(fstype) {
+            return None;
+        }
+        if let Some(controller) = controller {
+            let super_options = sb.nth(1)?;
+            if !super_options.split(',').any(|option| option == controller) {
+                return None;
+            }
+        }
+
+        let mut fields = mount.split(' ').skip(3);
+        let (root, mount_point) = (fields.next()?, fields.next()?);
+        let relative = if root == "/" {
+            path
+        } else {
+            path.strip_prefix(root)
+                .filter(|rest| rest.is_empty() || rest.starts_with('/'))?
+        };
+        Some(Path::new(mount_point).join(relative.trim_start_matches('/')))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cgroup_path() {
+        let hybrid = "\
+9:name=systemd:/docker/3f2a
+4:memory:/docker/3f2a
+2:cpu,cpuacct:/docker/3f2a
+0::/
+";
+        assert_eq!(
+            parse_cgroup_path(hybrid, Some("memory")),
+            Some("/docker/3f2a")
+        );
+        assert_eq!(parse_cgroup_path(hybrid, Some("cpu")), Some("/docker/3f2a"));
+        assert_eq!(parse_cgroup_path(hybrid, None), Some("/"));
+
+        let v2 = "0::/system.slice/docker-3f2a.scope\n";
+        assert_eq!(
+            parse_cgroup_path(v2, None),
+            Some("/system.slice/docker-3f2a.scope")
+        );
+        assert_eq!(parse_cgroup_path(v2, Some("memory")), None);
+    }
+
+    #[test]
+    fn test_resolve_mount() {
+        let mountinfo = "\
+25 20 0:22 / /sys/fs/cgroup rw,nosuid - cgroup2 cgroup2 rw
+30 24 0:26 /docker/3f2a /sys/fs/cgroup/memory ro - cgroup cgroup rw,memory
+";
+        assert_eq!(
+            resolve_mount(mountinfo, "cgroup2", None, "/system.slice/a.scope"),
+            Some(PathBuf::from("/sys/fs/cgroup/system.slice/a.scope"))
+        );
+        // Mounted from the container's own cgroup
+        assert_eq!(
+            resolve_mount(mountinfo, "cgroup", Some("memory"), "/docker/3f2a"),
+            Some(PathBuf::from("/sys/fs/cgroup/memory"))
+        );
+        assert_eq!(
+            resolve_mount(mountinfo, "cgroup", Some("memory"), "/other"),
+            None
+        );
+        assert_eq!(
+            resolve_mount(mountinfo, "cgroup", Some("memory"), "/docker/3f2abc"),
+            None
+        );
+        assert_eq!(resolve_mount(mountinfo, "cgroup", Some("cpu"), "/"), None);
+    }
+
+    #[test]
+    fn test_peak_before_start() {
+        let dir = tempfile::tempdir().unwrap();
+        let (current, peak) = (
+            dir.path().join("memory.current"),
+            dir.path().join("memory.peak"),
+        );
+        std::fs::write(&current, "1000\n").unwrap();
+        // Reached long before monitoring began
+        std::fs::write(&peak, "900000\n").unwrap();
+
+        let memory = CgroupMemory::new(current, peak.clone());
+        assert_eq!(memory.current(), Some(1000));
+        assert_eq!(memory.peak(), None);
+        std::fs::write(&peak, "1200000\n").unwrap();
+        assert_eq!(memory.peak(), Some(1_200_000));
+
+        // Without a peak file at all, as before Linux 5.19
+        let memory = CgroupMemory::new(dir.path().join("memory.current"), dir.path().join("none"));
+        assert_eq!(memory.peak(), None);
+    }
+
+    #[test]
+    fn test_unit_peak_before_start() {
+        // A v1 service cgroup, found by path as for --unit
+        let dir = tempfile::tempdir().unwrap();
+        let unit = dir.path().join("system.slice/nginx.service");
+        std::fs::create_dir_all(&unit).unwrap();
+        std::fs::write(unit.join("memory.usage_in_bytes"), "4096").unwrap();
+        std::fs::write(unit.join("memory.max_usage_in_bytes"), "800000").unwrap();
+        let mountinfo = format!(
+            "30 24 0:26 / {} rw - cgroup cgroup rw,memory\n",
+            dir.path().display()
+        );
+
+        let memory =
+            CgroupMemory::find(&mountinfo, None, Some("/system.slice/nginx.service")).unwrap();
+        assert_eq!(memory.current(), Some(4096));
+        assert_eq!(memory.peak(), None);
+        std::fs::write(unit.join("memory.max_usage_in_bytes"), "900000").unwrap();
+        assert_eq!(memory.peak(), Some(900_000));
+    }
+
+    #[test]
+    fn test_own_cgroup() {
+        // Not every sandbox exposes a memory controller
+        let Ok(memory) = CgroupMemory::for_pid(std::process::id()) else {
+            return;
+        };
+        assert!(memory.current().unwrap() > 0);
+    }
+}
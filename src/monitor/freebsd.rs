@@ -75,11 +75,7 @@ impl FreeBSDMonitor {
         self.refresh_process(pid)?;
         let (name, rss_bytes, vsz_bytes) = self.get_process_info(pid)?;
 
-        let memory = MemoryUsage {
-            rss_bytes,
-            vsz_bytes,
-            timestamp: Timestamp::now(),
-        };
+        let memory = memory_usage(pid, rss_bytes, vsz_bytes);
 
         // Get child processes
         let child_pids = self.collect_child_pids(pid);
@@ -98,6 +94,8 @@ impl FreeBSDMonitor {
             name,
             memory,
             children,
+            namespace: None,
+            rss_stats: None,
         })
     }
 }
@@ -111,11 +109,7 @@ impl MemoryMonitor for FreeBSDMonitor {
             self.refresh_process(pid)?;
             let (_name, rss_bytes, vsz_bytes) = self.get_process_info(pid)?;
 
-            Ok(MemoryUsage {
-                rss_bytes,
-                vsz_bytes,
-                timestamp: Timestamp::now(),
-            })
+            Ok(memory_usage(pid, rss_bytes, vsz_bytes))
         })
     }
 
@@ -140,3 +134,47 @@ impl MemoryMonitor for FreeBSDMonitor {
         })
     }
 }
+
+/// Builds a sample from sysinfo's memory figures, adding the thread and
+/// fault counters that sysinfo does not expose from the kernel's
+/// `kinfo_proc` record.
+fn memory_usage(pid: u32, rss_bytes: u64, vsz_bytes: u64) -> MemoryUsage {
+    let mut usage = MemoryUsage::new(rss_bytes, vsz_bytes, Timestamp::now());
+
+    if let Some(info) = read_kinfo_proc(pid) {
+        usage.thread_count = info.ki_numthreads.max(0) as u64;
+        usage.minor_faults = info.ki_rusage.ru_minflt.max(0) as u64;
+        usage.major_faults = info.ki_rusage.ru_majflt.max(0) as u64;
+    }
+
+    usage
+}
+
+/// Reads the `kern.proc.pid.<pid>` sysctl for a process.
+fn read_kinfo_proc(pid: u32) -> Option<libc::kinfo_proc> {
+    let mut mib = [
+        libc::CTL_KERN,
+        libc::KERN_PROC,
+        libc::KERN_PROC_PID,
+        pid as libc::c_int,
+    ];
+    let mut info: libc::kinfo_proc = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::kinfo_proc>();
+
+    let ret = unsafe {
+        libc::sysctl(
+            mib.as_mut_ptr(),
+            mib.len() as libc::c_uint,
+            &mut info as *mut _ as *mut libc::c_void,
+            &mut len,
+            std::ptr::null(),
+            0,
+        )
+    };
+
+    if ret != 0 || len != std::mem::size_of::<libc::kinfo_proc>() {
+        return None;
+    }
+
+    Some(info)
+}
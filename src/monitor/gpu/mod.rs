@@ -0,0 +1,188 @@
+//! Per-process GPU memory usage through vendor management libraries.
+//!
+//! NVIDIA GPUs are queried through NVML and AMD GPUs through ROCm SMI.
+//! Both libraries ship with the driver stack rather than being build
+//! dependencies, so they are loaded at runtime with `dlopen` instead of
+//! being linked. peak-mem therefore builds and runs normally on machines
+//! without a GPU; only `--gpu` fails there.
+
+use crate::types::{PeakMemError, Result};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+#[cfg(target_os = "linux")]
+mod nvml;
+#[cfg(target_os = "linux")]
+mod rocm;
+
+/// GPU memory in use by each process, in bytes, summed over all devices.
+pub type GpuUsage = HashMap<u32, u64>;
+
+/// Sums the GPU memory used by the given processes.
+pub fn usage_for_pids(usage: &GpuUsage, pids: impl IntoIterator<Item = u32>) -> u64 {
+    pids.into_iter().filter_map(|pid| usage.get(&pid)).sum()
+}
+
+/// Which vendor library to read GPU memory from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GpuBackend {
+    /// Use whichever library loads, trying NVIDIA first.
+    #[default]
+    Auto,
+    /// NVIDIA, through NVML.
+    Nvidia,
+    /// AMD, through ROCm SMI.
+    Rocm,
+}
+
+impl FromStr for GpuBackend {
+    type Err = PeakMemError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(GpuBackend::Auto),
+            "nvidia" | "nvml" => Ok(GpuBackend::Nvidia),
+            "rocm" | "amd" => Ok(GpuBackend::Rocm),
+            _ => Err(PeakMemError::InvalidArgument(format!(
+                "Invalid GPU backend '{s}'. Use one of: auto, nvidia, rocm"
+            ))),
+        }
+    }
+}
+
+/// Reads per-process GPU memory usage from a vendor library.
+pub enum GpuMonitor {
+    #[cfg(target_os = "linux")]
+    Nvidia(nvml::Nvml),
+    #[cfg(target_os = "linux")]
+    Rocm(rocm::RocmSmi),
+}
+
+impl GpuMonitor {
+    /// Loads and initializes the library for a backend.
+    ///
+    /// With [`GpuBackend::Auto`], NVML is tried first and ROCm SMI second.
+    ///
+    /// # Errors
+    /// * `PeakMemError::Monitor` - Library missing or failed to initialize
+    /// * `PeakMemError::UnsupportedPlatform` - Not Linux
+    pub fn open(backend: GpuBackend) -> Result<Self> {
+        #[cfg(target_os = "linux")]
+        {
+            match backend {
+                GpuBackend::Nvidia => nvml::Nvml::open().map(GpuMonitor::Nvidia),
+                GpuBackend::Rocm => rocm::RocmSmi::open().map(GpuMonitor::Rocm),
+                GpuBackend::Auto => nvml::Nvml::open()
+                    .map(GpuMonitor::Nvidia)
+                    .or_else(|nvidia| {
+                        rocm::RocmSmi::open().map(GpuMonitor::Rocm).map_err(|amd| {
+                            PeakMemError::Monitor(format!(
+                                "no GPU library could be loaded ({nvidia}; {amd})"
+                            ))
+                        })
+                    }),
+            }
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = backend;
+            Err(PeakMemError::UnsupportedPlatform(format!(
+                "GPU memory tracking is not supported on {}",
+                std::env::consts::OS
+            )))
+        }
+    }
+
+    /// Returns the GPU memory used by every process on any device.
+    pub fn usage_by_pid(&self) -> Result<GpuUsage> {
+        match *self {
+            #[cfg(target_os = "linux")]
+            GpuMonitor::Nvidia(ref nvml) => nvml.usage_by_pid(),
+            #[cfg(target_os = "linux")]
+            GpuMonitor::Rocm(ref smi) => smi.usage_by_pid(),
+        }
+    }
+}
+
+/// A library opened with `dlopen`, closed again on drop.
+#[cfg(target_os = "linux")]
+struct DynamicLibrary {
+    handle: *mut std::ffi::c_void,
+    name: &'static str,
+}
+
+#[cfg(target_os = "linux")]
+impl DynamicLibrary {
+    /// Opens a shared library by file name.
+    ///
+    /// # Arguments
+    /// * `file` - Library file name, e.g. `libnvidia-ml.so.1`
+    /// * `name` - Library name for error messages
+    /// * `provider` - What installs the library, for error messages
+    fn open(file: &std::ffi::CStr, name: &'static str, provider: &str) -> Result<Self> {
+        let handle = unsafe { libc::dlopen(file.as_ptr(), libc::RTLD_NOW) };
+        if handle.is_null() {
+            return Err(PeakMemError::Monitor(format!(
+                "{name} not available: {} could not be loaded (is {provider} installed?)",
+                file.to_string_lossy()
+            )));
+        }
+        Ok(Self { handle, name })
+    }
+
+    /// Looks up a function in the library.
+    ///
+    /// # Safety
+    /// `T` must be the function pointer type matching the symbol's C
+    /// signature, and must not be called after the library is dropped.
+    unsafe fn symbol<T: Copy>(&self, symbol: &std::ffi::CStr) -> Result<T> {
+        let ptr = libc::dlsym(self.handle, symbol.as_ptr());
+        if ptr.is_null() {
+            return Err(PeakMemError::Monitor(format!(
+                "{} is missing {} (driver too old?)",
+                self.name,
+                symbol.to_string_lossy()
+            )));
+        }
+        Ok(std::mem::transmute_copy(&ptr))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for DynamicLibrary {
+    fn drop(&mut self) {
+        unsafe { libc::dlclose(self.handle) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_for_pids() {
+        let usage = GpuUsage::from([(10, 1024), (11, 2048), (99, 4096)]);
+        assert_eq!(usage_for_pids(&usage, [10, 11, 12]), 3072);
+        assert_eq!(usage_for_pids(&usage, []), 0);
+    }
+
+    #[test]
+    fn test_parse_gpu_backend() {
+        assert_eq!("auto".parse::<GpuBackend>().unwrap(), GpuBackend::Auto);
+        assert_eq!("NVIDIA".parse::<GpuBackend>().unwrap(), GpuBackend::Nvidia);
+        assert_eq!("rocm".parse::<GpuBackend>().unwrap(), GpuBackend::Rocm);
+        assert!("intel".parse::<GpuBackend>().is_err());
+    }
+
+    #[test]
+    fn test_open_without_gpu_driver_fails_cleanly() {
+        // Either a library is present and works, or opening reports an
+        // error; it must never crash.
+        for backend in [GpuBackend::Auto, GpuBackend::Nvidia, GpuBackend::Rocm] {
+            if let Ok(monitor) = GpuMonitor::open(backend) {
+                assert!(monitor.usage_by_pid().is_ok());
+            }
+        }
+    }
+}
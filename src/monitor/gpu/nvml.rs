@@ -0,0 +1,147 @@
+//! NVIDIA backend using NVML, as shipped with the NVIDIA driver.
+
+use super::{DynamicLibrary, GpuUsage};
+use crate::types::{PeakMemError, Result};
+use std::ffi::{c_char, c_int, c_uint, c_ulonglong, c_void, CStr};
+
+type NvmlReturn = c_int;
+type NvmlDevice = *mut c_void;
+
+const NVML_SUCCESS: NvmlReturn = 0;
+const NVML_ERROR_INSUFFICIENT_SIZE: NvmlReturn = 7;
+/// Reported in `used_gpu_memory` when the driver can't attribute memory
+/// to the process (e.g. under some virtualization setups).
+const NVML_VALUE_NOT_AVAILABLE: c_ulonglong = c_ulonglong::MAX;
+
+/// `nvmlProcessInfo_t` as filled by the `_v3` process queries.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct ProcessInfo {
+    pid: c_uint,
+    used_gpu_memory: c_ulonglong,
+    gpu_instance_id: c_uint,
+    compute_instance_id: c_uint,
+}
+
+type InitFn = unsafe extern "C" fn() -> NvmlReturn;
+type GetCountFn = unsafe extern "C" fn(*mut c_uint) -> NvmlReturn;
+type GetHandleFn = unsafe extern "C" fn(c_uint, *mut NvmlDevice) -> NvmlReturn;
+type GetProcessesFn = unsafe extern "C" fn(NvmlDevice, *mut c_uint, *mut ProcessInfo) -> NvmlReturn;
+type ErrorStringFn = unsafe extern "C" fn(NvmlReturn) -> *const c_char;
+
+/// An initialized NVML library.
+pub struct Nvml {
+    shutdown: InitFn,
+    error_string: ErrorStringFn,
+    compute_processes: GetProcessesFn,
+    graphics_processes: GetProcessesFn,
+    devices: Vec<NvmlDevice>,
+    // Dropped last, after nvmlShutdown has run
+    _lib: DynamicLibrary,
+}
+
+// NVML is documented as thread-safe, and device handles stay valid until
+// nvmlShutdown.
+unsafe impl Send for Nvml {}
+unsafe impl Sync for Nvml {}
+
+impl Nvml {
+    pub fn open() -> Result<Self> {
+        let lib = DynamicLibrary::open(c"libnvidia-ml.so.1", "NVML", "the NVIDIA driver")?;
+
+        unsafe {
+            let init: InitFn = lib.symbol(c"nvmlInit_v2")?;
+            let mut nvml = Nvml {
+                shutdown: lib.symbol(c"nvmlShutdown")?,
+                error_string: lib.symbol(c"nvmlErrorString")?,
+                compute_processes: lib.symbol(c"nvmlDeviceGetComputeRunningProcesses_v3")?,
+                graphics_processes: lib.symbol(c"nvmlDeviceGetGraphicsRunningProcesses_v3")?,
+                devices: Vec::new(),
+                _lib: lib,
+            };
+            let get_count: GetCountFn = nvml._lib.symbol(c"nvmlDeviceGetCount_v2")?;
+            let get_handle: GetHandleFn = nvml._lib.symbol(c"nvmlDeviceGetHandleByIndex_v2")?;
+
+            // From here on, Drop shuts NVML down
+            nvml.check(init(), "initializing")?;
+
+            let mut count: c_uint = 0;
+            nvml.check(get_count(&mut count), "counting devices")?;
+            for index in 0..count {
+                let mut device: NvmlDevice = std::ptr::null_mut();
+                nvml.check(get_handle(index, &mut device), "opening a device")?;
+                nvml.devices.push(device);
+            }
+
+            Ok(nvml)
+        }
+    }
+
+    pub fn usage_by_pid(&self) -> Result<GpuUsage> {
+        let mut usage = GpuUsage::new();
+
+        for &device in &self.devices {
+            // A process with both compute and graphics contexts on the same
+            // device is listed twice with the same allocation.
+            let mut on_device = GpuUsage::new();
+            for query in [self.compute_processes, self.graphics_processes] {
+                for info in self.processes(query, device)? {
+                    if info.used_gpu_memory == NVML_VALUE_NOT_AVAILABLE {
+                        continue;
+                    }
+                    let entry = on_device.entry(info.pid).or_default();
+                    *entry = (*entry).max(info.used_gpu_memory);
+                }
+            }
+            for (pid, bytes) in on_device {
+                *usage.entry(pid).or_default() += bytes;
+            }
+        }
+
+        Ok(usage)
+    }
+
+    fn processes(&self, query: GetProcessesFn, device: NvmlDevice) -> Result<Vec<ProcessInfo>> {
+        let mut infos = vec![ProcessInfo::default(); 32];
+        loop {
+            let mut count = infos.len() as c_uint;
+            let ret = unsafe { query(device, &mut count, infos.as_mut_ptr()) };
+            match ret {
+                NVML_SUCCESS => {
+                    infos.truncate(count as usize);
+                    return Ok(infos);
+                }
+                // count now holds the number needed; leave headroom for
+                // processes starting meanwhile
+                NVML_ERROR_INSUFFICIENT_SIZE => {
+                    infos.resize(count as usize + 8, ProcessInfo::default())
+                }
+                _ => return Err(self.error(ret, "querying GPU processes")),
+            }
+        }
+    }
+
+    fn check(&self, ret: NvmlReturn, what: &str) -> Result<()> {
+        if ret == NVML_SUCCESS {
+            Ok(())
+        } else {
+            Err(self.error(ret, what))
+        }
+    }
+
+    fn error(&self, ret: NvmlReturn, what: &str) -> PeakMemError {
+        let message = unsafe { CStr::from_ptr((self.error_string)(ret)) };
+        PeakMemError::Monitor(format!(
+            "NVML error while {what}: {}",
+            message.to_string_lossy()
+        ))
+    }
+}
+
+impl Drop for Nvml {
+    fn drop(&mut self) {
+        unsafe {
+            (self.shutdown)();
+        }
+    }
+}
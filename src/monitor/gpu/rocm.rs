@@ -0,0 +1,133 @@
+//! AMD backend using the ROCm SMI library.
+
+use super::{DynamicLibrary, GpuUsage};
+use crate::types::{PeakMemError, Result};
+use std::ffi::{c_char, c_int, CStr};
+
+type RsmiStatus = c_int;
+
+const RSMI_STATUS_SUCCESS: RsmiStatus = 0;
+/// The process exited between listing and querying it.
+const RSMI_STATUS_NOT_FOUND: RsmiStatus = 10;
+const RSMI_STATUS_INSUFFICIENT_SIZE: RsmiStatus = 11;
+
+/// `rsmi_process_info_t`.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct ProcessInfo {
+    process_id: u32,
+    pasid: u32,
+    vram_usage: u64,
+    sdma_usage: u64,
+    cu_occupancy: u32,
+}
+
+type InitFn = unsafe extern "C" fn(u64) -> RsmiStatus;
+type ShutdownFn = unsafe extern "C" fn() -> RsmiStatus;
+type ListProcessesFn = unsafe extern "C" fn(*mut ProcessInfo, *mut u32) -> RsmiStatus;
+type ProcessByPidFn = unsafe extern "C" fn(u32, *mut ProcessInfo) -> RsmiStatus;
+type StatusStringFn = unsafe extern "C" fn(RsmiStatus, *mut *const c_char) -> RsmiStatus;
+
+/// An initialized ROCm SMI library.
+pub struct RocmSmi {
+    shutdown: ShutdownFn,
+    status_string: StatusStringFn,
+    list_processes: ListProcessesFn,
+    process_by_pid: ProcessByPidFn,
+    // Dropped last, after rsmi_shut_down has run
+    _lib: DynamicLibrary,
+}
+
+// ROCm SMI serializes access internally and keeps no per-thread state.
+unsafe impl Send for RocmSmi {}
+unsafe impl Sync for RocmSmi {}
+
+impl RocmSmi {
+    pub fn open() -> Result<Self> {
+        let lib = DynamicLibrary::open(c"librocm_smi64.so.1", "ROCm SMI", "ROCm")
+            .or_else(|_| DynamicLibrary::open(c"librocm_smi64.so", "ROCm SMI", "ROCm"))?;
+
+        unsafe {
+            let init: InitFn = lib.symbol(c"rsmi_init")?;
+            let smi = RocmSmi {
+                shutdown: lib.symbol(c"rsmi_shut_down")?,
+                status_string: lib.symbol(c"rsmi_status_string")?,
+                list_processes: lib.symbol(c"rsmi_compute_process_info_get")?,
+                process_by_pid: lib.symbol(c"rsmi_compute_process_info_by_pid_get")?,
+                _lib: lib,
+            };
+
+            // From here on, Drop shuts ROCm SMI down
+            smi.check(init(0), "initializing")?;
+            Ok(smi)
+        }
+    }
+
+    /// Lists the processes using any AMD GPU and their VRAM.
+    ///
+    /// The list call only fills in process IDs, so each process is then
+    /// queried for its VRAM use, summed over all GPUs.
+    pub fn usage_by_pid(&self) -> Result<GpuUsage> {
+        let mut usage = GpuUsage::new();
+
+        for pid in self.process_ids()? {
+            let mut info = ProcessInfo::default();
+            match unsafe { (self.process_by_pid)(pid, &mut info) } {
+                RSMI_STATUS_SUCCESS => {
+                    usage.insert(pid, info.vram_usage);
+                }
+                RSMI_STATUS_NOT_FOUND => {}
+                ret => return Err(self.error(ret, "querying a GPU process")),
+            }
+        }
+
+        Ok(usage)
+    }
+
+    fn process_ids(&self) -> Result<Vec<u32>> {
+        let mut infos = vec![ProcessInfo::default(); 32];
+        loop {
+            let mut count = infos.len() as u32;
+            let ret = unsafe { (self.list_processes)(infos.as_mut_ptr(), &mut count) };
+            match ret {
+                RSMI_STATUS_SUCCESS => {
+                    infos.truncate(count as usize);
+                    return Ok(infos.iter().map(|info| info.process_id).collect());
+                }
+                RSMI_STATUS_INSUFFICIENT_SIZE => {
+                    infos.resize(infos.len() * 2, ProcessInfo::default())
+                }
+                _ => return Err(self.error(ret, "listing GPU processes")),
+            }
+        }
+    }
+
+    fn check(&self, ret: RsmiStatus, what: &str) -> Result<()> {
+        if ret == RSMI_STATUS_SUCCESS {
+            Ok(())
+        } else {
+            Err(self.error(ret, what))
+        }
+    }
+
+    fn error(&self, ret: RsmiStatus, what: &str) -> PeakMemError {
+        let mut message: *const c_char = std::ptr::null();
+        let ok = unsafe { (self.status_string)(ret, &mut message) } == RSMI_STATUS_SUCCESS;
+        let message = if ok && !message.is_null() {
+            unsafe { CStr::from_ptr(message) }
+                .to_string_lossy()
+                .into_owned()
+        } else {
+            format!("status {ret}")
+        };
+        PeakMemError::Monitor(format!("ROCm SMI error while {what}: {message}"))
+    }
+}
+
+impl Drop for RocmSmi {
+    fn drop(&mut self) {
+        unsafe {
+            (self.shutdown)();
+        }
+    }
+}
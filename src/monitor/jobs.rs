@@ -0,0 +1,370 @@
+//! Attributing the memory of a build to what its compiler jobs compile.
+//!
+//! Every sample of the process tree looks for compiler processes and
+//! charges each one's memory, including its children, to what its command
+//! line says it is compiling:
+//!
+//! * With `--cargo`, `rustc` processes are charged to the crate named by their
+//!   `--crate-name` argument, and build scripts to their package. Cargo's JSON
+//!   messages only say when a crate has finished, so with parallel jobs they
+//!   can't tell which crate was compiling at a peak; the process tree can.
+//! * With `--compile-units`, C and C++ compiler processes (and wrappers such as
+//!   ccache) are charged to the source file they compile, which works the same
+//!   under make, Ninja or any other build tool.
+//!
+//! Reading command lines needs `/proc`, so this is Linux only.
+
+use crate::types::ProcessMemoryInfo;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The crate name rustc is given for build scripts.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+const BUILD_SCRIPT: &str = "build_script_build";
+
+/// Compilers and compiler wrappers whose source file is charged, by
+/// process name without any target prefix or version suffix.
+const C_COMPILERS: &[&str] = &[
+    "cc", "c++", "gcc", "g++", "cc1", "cc1plus", "clang", "clang++", "ccache", "sccache", "distcc",
+    "icecc", "nvcc",
+];
+
+/// Extensions of C-family source files.
+const SOURCE_EXTENSIONS: &[&str] = &[
+    "c", "cc", "cp", "cpp", "cxx", "c++", "C", "CPP", "m", "mm", "M", "cu", "i", "ii", "s", "S",
+];
+
+/// What jobs are attributed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobKind {
+    /// Rust crates compiled by rustc.
+    Crate,
+    /// C and C++ source files.
+    TranslationUnit,
+}
+
+impl JobKind {
+    /// Whether a process may be a compiler job of this kind, judging by
+    /// its name alone.
+    fn is_compiler(self, name: &str) -> bool {
+        match self {
+            JobKind::Crate => name == "rustc",
+            JobKind::TranslationUnit => {
+                // x86_64-linux-gnu-gcc-12, clang-17
+                let name = name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+                let name = name.strip_suffix('-').unwrap_or(name);
+                C_COMPILERS
+                    .iter()
+                    .any(|compiler| name == *compiler || name.ends_with(&format!("-{compiler}")))
+            }
+        }
+    }
+
+    /// Finds what a compiler command line compiles.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    fn parse_job(self, args: &[String]) -> Option<String> {
+        match self {
+            JobKind::Crate => parse_crate_name(args),
+            JobKind::TranslationUnit => parse_source_file(args),
+        }
+    }
+}
+
+/// The peak memory of one compiler job.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobPeak {
+    /// What was compiled: a crate's name as given to rustc, e.g.
+    /// `serde_json`, or for a build script its package's, e.g.
+    /// `openssl-sys (build script)`; or a source file as given to the
+    /// compiler.
+    pub name: String,
+    /// Highest RSS of the compiler process and its children (in bytes).
+    pub peak_rss_bytes: u64,
+}
+
+/// Peak RSS per job of one kind over a build.
+#[derive(Debug)]
+pub struct JobPeaks {
+    kind: JobKind,
+    peaks: BTreeMap<String, u64>,
+}
+
+impl JobPeaks {
+    /// Starts attributing jobs of the given kind.
+    pub fn new(kind: JobKind) -> Self {
+        Self {
+            kind,
+            peaks: BTreeMap::new(),
+        }
+    }
+
+    /// Returns what jobs are attributed to.
+    pub fn kind(&self) -> JobKind {
+        self.kind
+    }
+
+    /// Charges the compiler processes of a sampled tree to their jobs.
+    pub fn record(&mut self, tree: &ProcessMemoryInfo) {
+        let kind = self.kind;
+        self.record_with(tree, &|pid| read_job(kind, pid));
+    }
+
+    fn record_with(&mut self, info: &ProcessMemoryInfo, job_of: &dyn Fn(u32) -> Option<String>) {
+        // A driver or wrapper and the compiler it runs (rustup's proxy and
+        // rustc, gcc and cc1plus) name the same job; the outermost one's
+        // subtree covers both
+        if self.kind.is_compiler(&info.name) {
+            if let Some(name) = job_of(info.pid) {
+                let rss = subtree_rss(info);
+                let peak = self.peaks.entry(name).or_default();
+                *peak = (*peak).max(rss);
+                return;
+            }
+        }
+        for child in &info.children {
+            self.record_with(child, job_of);
+        }
+    }
+
+    /// Returns the jobs seen, highest peak first.
+    pub fn sorted(&self) -> Vec<JobPeak> {
+        let mut jobs: Vec<JobPeak> = self
+            .peaks
+            .iter()
+            .map(|(name, &peak_rss_bytes)| JobPeak {
+                name: name.clone(),
+                peak_rss_bytes,
+            })
+            .collect();
+        jobs.sort_by_key(|job| std::cmp::Reverse(job.peak_rss_bytes));
+        jobs
+    }
+}
+
+fn subtree_rss(info: &ProcessMemoryInfo) -> u64 {
+    info.memory.rss_bytes + info.children.iter().map(subtree_rss).sum::<u64>()
+}
+
+/// Reads what a compiler process is compiling from its command line.
+fn read_job(kind: JobKind, pid: u32) -> Option<String> {
+    #[cfg(target_os = "linux")]
+    {
+        let split = |bytes: &[u8]| -> Vec<String> {
+            bytes
+                .split(|&b| b == 0)
+                .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                .collect()
+        };
+        let cmdline = std::fs::read(format!("/proc/{pid}/cmdline")).ok()?;
+        let name = kind.parse_job(&split(&cmdline))?;
+        if kind != JobKind::Crate || name != BUILD_SCRIPT {
+            return Some(name);
+        }
+
+        // Every build script has the same crate name; cargo tells rustc
+        // which package it belongs to
+        let environ = std::fs::read(format!("/proc/{pid}/environ")).unwrap_or_default();
+        let package = split(&environ)
+            .into_iter()
+            .find_map(|var| var.strip_prefix("CARGO_PKG_NAME=").map(String::from));
+        Some(match package {
+            Some(package) => format!("{package} (build script)"),
+            None => name,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (kind, pid);
+        None
+    }
+}
+
+/// Finds the `--crate-name NAME` (or `--crate-name=NAME`) argument.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_crate_name(args: &[String]) -> Option<String> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--crate-name" {
+            return args.next().cloned();
+        }
+        if let Some(name) = arg.strip_prefix("--crate-name=") {
+            return Some(name.to_string());
+        }
+    }
+    None
+}
+
+/// Finds the source file among a C compiler's arguments: the last
+/// argument with a source extension that isn't an option's value.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_source_file(args: &[String]) -> Option<String> {
+    // Options whose value is a separate argument that may look like a
+    // source file
+    const TAKES_VALUE: &[&str] = &["-o", "-MF", "-MT", "-MQ", "-include", "-imacros", "-x"];
+
+    let mut source = None;
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        if TAKES_VALUE.contains(&arg.as_str()) {
+            args.next();
+            continue;
+        }
+        if arg.starts_with('-') {
+            continue;
+        }
+        let is_source = arg
+            .rsplit_once('.')
+            .is_some_and(|(_, ext)| SOURCE_EXTENSIONS.contains(&ext));
+        if is_source {
+            source = Some(arg.clone());
+        }
+    }
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{MemoryUsage, Timestamp};
+
+    fn process(
+        pid: u32,
+        name: &str,
+        rss: u64,
+        children: Vec<ProcessMemoryInfo>,
+    ) -> ProcessMemoryInfo {
+        ProcessMemoryInfo {
+            pid,
+            name: name.to_string(),
+            memory: MemoryUsage::new(rss, 0, Timestamp::now()),
+            children,
+            namespace: None,
+            rss_stats: None,
+        }
+    }
+
+    fn args(s: &str) -> Vec<String> {
+        s.split(' ').map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_crate_name() {
+        assert_eq!(
+            parse_crate_name(&args("rustc --crate-name serde --edition=2021 src/lib.rs")),
+            Some("serde".to_string())
+        );
+        assert_eq!(
+            parse_crate_name(&args("rustc --crate-name=build_script_build build.rs")),
+            Some("build_script_build".to_string())
+        );
+        assert_eq!(parse_crate_name(&args("rustc -vV")), None);
+    }
+
+    #[test]
+    fn test_parse_source_file() {
+        assert_eq!(
+            parse_source_file(&args(
+                "g++ -O2 -MD -MF obj/a.cc.d -o obj/a.cc.o -c src/a.cc"
+            )),
+            Some("src/a.cc".to_string())
+        );
+        assert_eq!(
+            parse_source_file(&args("ccache cc -include config.h -c main.c")),
+            Some("main.c".to_string())
+        );
+        // Linking compiles nothing
+        assert_eq!(parse_source_file(&args("cc -o app a.o b.o -lm")), None);
+    }
+
+    #[test]
+    fn test_is_compiler() {
+        let tu = JobKind::TranslationUnit;
+        for name in [
+            "gcc",
+            "c++",
+            "cc1plus",
+            "clang-17",
+            "x86_64-linux-gnu-gcc-12",
+            "ccache",
+        ] {
+            assert!(tu.is_compiler(name), "{name}");
+        }
+        for name in ["make", "ninja", "rustc", "ld", "sh"] {
+            assert!(!tu.is_compiler(name), "{name}");
+        }
+        assert!(JobKind::Crate.is_compiler("rustc"));
+    }
+
+    #[test]
+    fn test_crate_peaks() {
+        let names = |pid: u32| match pid {
+            10 | 11 => Some("serde".to_string()),
+            20 => Some("tokio".to_string()),
+            _ => None,
+        };
+        let sample = |serde_rss, tokio_rss| {
+            process(
+                1,
+                "cargo",
+                50,
+                vec![
+                    // A rustup proxy running the real rustc
+                    process(
+                        10,
+                        "rustc",
+                        5,
+                        vec![process(11, "rustc", serde_rss, vec![])],
+                    ),
+                    process(20, "rustc", tokio_rss, vec![process(21, "cc", 100, vec![])]),
+                    // `rustc -vV` probes name no crate
+                    process(30, "rustc", 1_000, vec![]),
+                ],
+            )
+        };
+
+        let mut peaks = JobPeaks::new(JobKind::Crate);
+        peaks.record_with(&sample(300, 200), &names);
+        peaks.record_with(&sample(100, 900), &names);
+
+        assert_eq!(
+            peaks.sorted(),
+            vec![
+                JobPeak {
+                    name: "tokio".to_string(),
+                    peak_rss_bytes: 1_000,
+                },
+                JobPeak {
+                    name: "serde".to_string(),
+                    peak_rss_bytes: 305,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_translation_unit_peaks() {
+        let sources = |pid: u32| match pid {
+            10 | 11 => Some("a.cc".to_string()),
+            20 => Some("b.c".to_string()),
+            _ => None,
+        };
+        let tree = process(
+            1,
+            "make",
+            10,
+            vec![
+                // The driver and the compiler proper
+                process(10, "g++", 2, vec![process(11, "cc1plus", 400, vec![])]),
+                process(20, "cc", 90, vec![]),
+                // The link step names no source
+                process(30, "cc", 700, vec![]),
+            ],
+        );
+
+        let mut peaks = JobPeaks::new(JobKind::TranslationUnit);
+        peaks.record_with(&tree, &sources);
+        let names: Vec<_> = peaks.sorted().into_iter().map(|job| job.name).collect();
+        assert_eq!(names, ["a.cc", "b.c"]);
+    }
+}
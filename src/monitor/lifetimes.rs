@@ -0,0 +1,123 @@
+//! The RSS of each process over its lifetime, for the verbose tree.
+//!
+//! The tree kept for the results is the one sampled at the peak of the
+//! whole tree, so it shows each process as it was at that moment: a
+//! process that peaked earlier, or had yet to grow, looks smaller than it
+//! got. Every sample's tree is folded into a running min, mean and peak
+//! per process, which the peak tree is then annotated with.
+
+use crate::types::{ProcessMemoryInfo, RssStats};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy)]
+struct Accumulator {
+    min: u64,
+    max: u64,
+    sum: u128,
+    samples: u64,
+}
+
+/// Collects the RSS of each process of a tree, by PID.
+#[derive(Debug, Default)]
+pub struct ProcessLifetimes {
+    processes: HashMap<u32, Accumulator>,
+}
+
+impl ProcessLifetimes {
+    /// Records the processes of a sampled tree.
+    pub fn record(&mut self, tree: &ProcessMemoryInfo) {
+        let rss = tree.memory.rss_bytes;
+        self.processes
+            .entry(tree.pid)
+            .and_modify(|process| {
+                process.min = process.min.min(rss);
+                process.max = process.max.max(rss);
+                process.sum += u128::from(rss);
+                process.samples += 1;
+            })
+            .or_insert(Accumulator {
+                min: rss,
+                max: rss,
+                sum: u128::from(rss),
+                samples: 1,
+            });
+        for child in &tree.children {
+            self.record(child);
+        }
+    }
+
+    /// The RSS of a process over the samples it was in.
+    pub fn stats(&self, pid: u32) -> Option<RssStats> {
+        self.processes.get(&pid).map(|process| RssStats {
+            min_bytes: process.min,
+            avg_bytes: (process.sum / u128::from(process.samples)) as u64,
+            peak_bytes: process.max,
+            samples: process.samples,
+        })
+    }
+
+    /// Forgets every process but `pids`, those still running and those of
+    /// the peak tree: the others have exited, so can't be in a later peak
+    /// tree.
+    ///
+    /// # Returns
+    /// * How many processes were forgotten
+    pub fn retain(&mut self, pids: &HashSet<u32>) -> usize {
+        let before = self.processes.len();
+        self.processes.retain(|pid, _| pids.contains(pid));
+        self.processes.shrink_to_fit();
+        before - self.processes.len()
+    }
+
+    /// Sets the stats of every process of `tree`.
+    pub fn annotate(&self, tree: &mut ProcessMemoryInfo) {
+        tree.rss_stats = self.stats(tree.pid);
+        for child in &mut tree.children {
+            self.annotate(child);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{MemoryUsage, Timestamp};
+
+    #[test]
+    fn test_lifetimes() {
+        let process = |pid, rss, children| ProcessMemoryInfo {
+            pid,
+            name: "cc".to_string(),
+            memory: MemoryUsage::new(rss, 0, Timestamp::now()),
+            children,
+            namespace: None,
+            rss_stats: None,
+        };
+        let mut lifetimes = ProcessLifetimes::default();
+        // The child peaks before the tree does, and then exits
+        lifetimes.record(&process(1, 100, vec![process(2, 900, vec![])]));
+        lifetimes.record(&process(1, 200, vec![process(2, 300, vec![])]));
+        lifetimes.record(&process(1, 1200, vec![]));
+
+        let mut tree = process(1, 1200, vec![process(2, 300, vec![])]);
+        lifetimes.annotate(&mut tree);
+        assert_eq!(
+            tree.rss_stats,
+            Some(RssStats {
+                min_bytes: 100,
+                avg_bytes: 500,
+                peak_bytes: 1200,
+                samples: 3,
+            })
+        );
+        let child = tree.children[0].rss_stats.unwrap();
+        assert_eq!((child.min_bytes, child.avg_bytes), (300, 600));
+        assert_eq!((child.peak_bytes, child.samples), (900, 2));
+        assert_eq!(lifetimes.stats(3), None);
+
+        // The child has exited and isn't in the peak tree
+        assert_eq!(lifetimes.retain(&HashSet::from([1])), 1);
+        assert!(lifetimes.stats(1).is_some());
+        assert_eq!(lifetimes.stats(2), None);
+    }
+}
@@ -1,17 +1,25 @@
+use crate::monitor::cgroup;
 use crate::monitor::MemoryMonitor;
-use crate::types::{MemoryUsage, PeakMemError, ProcessMemoryInfo, Result, Timestamp};
+use crate::types::{MemoryUsage, PeakMemError, PidNamespace, ProcessMemoryInfo, Result, Timestamp};
 use procfs::process::Process;
+use std::collections::HashSet;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 
-pub struct LinuxMonitor;
+pub struct LinuxMonitor {
+    /// Children left out of a tree because they couldn't be read.
+    unreadable: AtomicU64,
+}
 
 impl LinuxMonitor {
     pub fn new() -> Result<Self> {
-        Ok(LinuxMonitor)
+        Ok(LinuxMonitor {
+            unreadable: AtomicU64::new(0),
+        })
     }
 
-    fn read_proc_status(&self, pid: u32) -> Result<(u64, u64)> {
+    fn read_proc_usage(&self, pid: u32) -> Result<MemoryUsage> {
         let process = Process::new(pid as i32).map_err(|e| match e {
             procfs::ProcError::NotFound(_) => {
                 PeakMemError::ProcessSpawn(format!("Process {pid} not found"))
@@ -28,8 +36,25 @@ impl LinuxMonitor {
 
         let rss_bytes = status.vmrss.unwrap_or(0) * 1024;
         let vsz_bytes = status.vmsize.unwrap_or(0) * 1024;
+        let mut usage = MemoryUsage::new(rss_bytes, vsz_bytes, Timestamp::now());
+        usage.thread_count = status.threads;
+
+        // Fault counters live in /proc/<pid>/stat rather than status. A
+        // process that exits between the two reads still has valid
+        // memory figures, so a failed stat read only loses the counters.
+        if let Ok(stat) = process.stat() {
+            usage.minor_faults = stat.minflt;
+            usage.major_faults = stat.majflt;
+        }
 
-        Ok((rss_bytes, vsz_bytes))
+        // /proc/<pid>/io needs ptrace access, which may be denied even
+        // when status is readable; I/O totals are then left at zero.
+        if let Ok(io) = process.io() {
+            usage.disk_read_bytes = io.read_bytes;
+            usage.disk_write_bytes = io.write_bytes;
+        }
+
+        Ok(usage)
     }
 
     fn get_process_name(&self, pid: u32) -> String {
@@ -38,49 +63,187 @@ impl LinuxMonitor {
             .map(|stat| stat.comm)
             .unwrap_or_else(|_| format!("pid:{pid}"))
     }
-}
-
-impl MemoryMonitor for LinuxMonitor {
-    fn get_memory_usage(
-        &self,
-        pid: u32,
-    ) -> Pin<Box<dyn Future<Output = Result<MemoryUsage>> + Send + '_>> {
-        Box::pin(async move {
-            let (rss_bytes, vsz_bytes) = self.read_proc_status(pid)?;
-
-            Ok(MemoryUsage {
-                rss_bytes,
-                vsz_bytes,
-                timestamp: Timestamp::now(),
-            })
-        })
-    }
 
-    fn get_process_tree(
+    /// Builds the tree of `pid`, whose parent runs in PID namespace
+    /// `parent_namespace`.
+    ///
+    /// A process in a namespace of its own is marked as a boundary. If it
+    /// also has a cgroup of its own, as a container does, the processes
+    /// in that cgroup that aren't its descendants are followed too.
+    fn tree(
         &self,
         pid: u32,
+        parent_namespace: Option<u64>,
     ) -> Pin<Box<dyn Future<Output = Result<ProcessMemoryInfo>> + Send + '_>> {
         Box::pin(async move {
             let memory = self.get_memory_usage(pid).await?;
             let name = self.get_process_name(pid);
+            let namespace = pid_namespace(pid);
             let child_pids = self.get_child_pids(pid).await?;
 
             let mut children = Vec::new();
             for child_pid in child_pids {
-                if let Ok(child_info) = self.get_process_tree(child_pid).await {
-                    children.push(child_info);
+                // Any other failure means the child has exited meanwhile
+                match self.tree(child_pid, namespace).await {
+                    Ok(child_info) => children.push(child_info),
+                    Err(PeakMemError::PermissionDenied(_)) => {
+                        self.unreadable.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(_) => {}
                 }
             }
 
-            Ok(ProcessMemoryInfo {
+            let mut info = ProcessMemoryInfo {
                 pid,
                 name,
                 memory,
                 children,
-            })
+                namespace: None,
+                rss_stats: None,
+            };
+            // Unknown namespaces, e.g. of another user's processes, are no
+            // boundary
+            let boundary =
+                namespace.filter(|&inode| parent_namespace.is_some_and(|parent| parent != inode));
+            if let Some(inode) = boundary {
+                info.namespace = Some(PidNamespace {
+                    inode,
+                    pid: namespace_pid(pid).unwrap_or(pid),
+                    via_cgroup: false,
+                });
+                self.follow_cgroup(&mut info, inode).await;
+            }
+            Ok(info)
         })
     }
 
+    /// Adds the processes sharing the cgroup of the boundary `info` that
+    /// aren't in its tree, e.g. those a container runtime started under
+    /// a shim rather than as its children.
+    async fn follow_cgroup(&self, info: &mut ProcessMemoryInfo, inode: u64) {
+        let Some(path) = cgroup::cgroup_path(info.pid) else {
+            return;
+        };
+        // Sharing the parent's cgroup, it would take in the parent's
+        // whole session
+        let parent_path = Process::new(info.pid as i32)
+            .and_then(|p| p.stat())
+            .ok()
+            .and_then(|stat| cgroup::cgroup_path(stat.ppid as u32));
+        if parent_path.as_ref() == Some(&path) {
+            return;
+        }
+
+        let mut known = HashSet::new();
+        collect_pids(info, &mut known);
+        let Ok(processes) = procfs::process::all_processes() else {
+            return;
+        };
+        let members: Vec<(u32, u32)> = processes
+            .flatten()
+            .filter_map(|process| {
+                let stat = process.stat().ok()?;
+                let pid = stat.pid as u32;
+                (!known.contains(&pid) && cgroup::cgroup_path(pid).as_ref() == Some(&path))
+                    .then_some((pid, stat.ppid as u32))
+            })
+            .collect();
+        let member_pids: HashSet<u32> = members.iter().map(|&(pid, _)| pid).collect();
+
+        // Each member whose parent isn't one brings its own tree
+        for &(pid, ppid) in &members {
+            if member_pids.contains(&ppid) {
+                continue;
+            }
+            if let Ok(mut joined) = self.tree(pid, Some(inode)).await {
+                let namespace = pid_namespace(pid).unwrap_or(inode);
+                joined.namespace = Some(PidNamespace {
+                    inode: namespace,
+                    pid: namespace_pid(pid).unwrap_or(pid),
+                    via_cgroup: true,
+                });
+                info.children.push(joined);
+            }
+        }
+    }
+}
+
+/// Inode of the PID namespace a process runs in.
+fn pid_namespace(pid: u32) -> Option<u64> {
+    let link = std::fs::read_link(format!("/proc/{pid}/ns/pid")).ok()?;
+    parse_namespace_link(link.to_str()?)
+}
+
+/// Parses a namespace link such as `pid:[4026531836]`.
+fn parse_namespace_link(link: &str) -> Option<u64> {
+    link.strip_prefix("pid:[")?.strip_suffix(']')?.parse().ok()
+}
+
+/// PID of a process in its innermost PID namespace, from `NSpid`.
+fn namespace_pid(pid: u32) -> Option<u32> {
+    let status = Process::new(pid as i32).ok()?.status().ok()?;
+    status.nspid?.last().map(|&pid| pid as u32)
+}
+
+fn collect_pids(info: &ProcessMemoryInfo, pids: &mut HashSet<u32>) {
+    pids.insert(info.pid);
+    for child in &info.children {
+        collect_pids(child, pids);
+    }
+}
+
+/// Describes why `/proc/<pid>` can't be read, if it can't.
+pub fn procfs_restriction(pid: u32) -> Option<String> {
+    if Process::myself().and_then(|p| p.status()).is_err() {
+        return Some("/proc is not mounted or not readable".to_string());
+    }
+    if Process::new(pid as i32).and_then(|p| p.status()).is_ok() {
+        return None;
+    }
+
+    let hidepid = std::fs::read_to_string("/proc/self/mountinfo")
+        .ok()
+        .and_then(|mountinfo| parse_hidepid(&mountinfo));
+    Some(match hidepid {
+        Some(hidepid) => format!("/proc is mounted with hidepid={hidepid}"),
+        None => format!("/proc/{pid} is not readable (hardened kernel?)"),
+    })
+}
+
+/// Returns the `hidepid` option of the `/proc` mount, unless it is off.
+fn parse_hidepid(mountinfo: &str) -> Option<String> {
+    // 22 28 0:21 / /proc rw,nosuid shared:12 - proc proc rw,hidepid=2
+    mountinfo.lines().find_map(|line| {
+        let (mount, sb) = line.split_once(" - ")?;
+        if mount.split(' ').nth(4) != Some("/proc") {
+            return None;
+        }
+        let super_options = sb.split(' ').nth(2)?;
+        super_options
+            .split(',')
+            .find_map(|option| option.strip_prefix("hidepid="))
+            .filter(|value| !matches!(*value, "0" | "off"))
+            .map(str::to_string)
+    })
+}
+
+impl MemoryMonitor for LinuxMonitor {
+    fn get_memory_usage(
+        &self,
+        pid: u32,
+    ) -> Pin<Box<dyn Future<Output = Result<MemoryUsage>> + Send + '_>> {
+        Box::pin(async move { self.read_proc_usage(pid) })
+    }
+
+    fn get_process_tree(
+        &self,
+        pid: u32,
+    ) -> Pin<Box<dyn Future<Output = Result<ProcessMemoryInfo>> + Send + '_>> {
+        // The root is a boundary if it runs in another namespace than
+        // peak-mem, e.g. a container attached to with --pid
+        self.tree(pid, pid_namespace(std::process::id()))
+    }
+
     fn get_child_pids(
         &self,
         pid: u32,
@@ -102,6 +265,10 @@ impl MemoryMonitor for LinuxMonitor {
             Ok(children)
         })
     }
+
+    fn unreadable_processes(&self) -> u64 {
+        self.unreadable.load(Ordering::Relaxed)
+    }
 }
 
 #[cfg(test)]
@@ -119,5 +286,40 @@ mod tests {
         let usage = usage.unwrap();
         assert!(usage.rss_bytes > 0);
         assert!(usage.vsz_bytes >= usage.rss_bytes);
+        // Loading the test binary alone faults in pages.
+        assert!(usage.minor_faults > 0);
+        assert!(usage.thread_count >= 1);
+    }
+
+    #[tokio::test]
+    async fn test_no_namespace_boundary_in_own_tree() {
+        let monitor = LinuxMonitor::new().unwrap();
+        let tree = monitor.get_process_tree(std::process::id()).await.unwrap();
+        assert_eq!(tree.namespace, None);
+    }
+
+    #[test]
+    fn test_parse_namespace_link() {
+        assert_eq!(parse_namespace_link("pid:[4026531836]"), Some(4026531836));
+        assert_eq!(parse_namespace_link("net:[4026531840]"), None);
+    }
+
+    #[test]
+    fn test_procfs_restriction_self() {
+        assert_eq!(procfs_restriction(std::process::id()), None);
+    }
+
+    #[test]
+    fn test_parse_hidepid() {
+        let mountinfo = "\
+21 26 0:20 / /sys rw,nosuid shared:7 - sysfs sysfs rw
+22 26 0:21 / /proc rw,nosuid,nodev shared:12 - proc proc rw,hidepid=invisible
+";
+        assert_eq!(parse_hidepid(mountinfo), Some("invisible".to_string()));
+
+        let off = "22 26 0:21 / /proc rw shared:12 - proc proc rw,hidepid=0\n";
+        assert_eq!(parse_hidepid(off), None);
+        let unset = "22 26 0:21 / /proc rw shared:12 - proc proc rw\n";
+        assert_eq!(parse_hidepid(unset), None);
     }
 }
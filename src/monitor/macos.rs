@@ -11,7 +11,7 @@ impl MacOSMonitor {
         Ok(MacOSMonitor)
     }
 
-    fn get_memory_for_pid(&self, pid: u32) -> Result<(u64, u64)> {
+    fn get_memory_for_pid(&self, pid: u32) -> Result<MemoryUsage> {
         use libc::{proc_pidinfo, proc_taskinfo, PROC_PIDTASKINFO};
 
         let mut info: proc_taskinfo = unsafe { mem::zeroed() };
@@ -33,7 +33,20 @@ impl MacOSMonitor {
             )));
         }
 
-        Ok((info.pti_resident_size, info.pti_virtual_size))
+        let mut usage = MemoryUsage::new(
+            info.pti_resident_size,
+            info.pti_virtual_size,
+            Timestamp::now(),
+        );
+
+        // pti_faults counts all faults; page-ins are the ones that had to
+        // go to disk, which is what Linux reports as major faults.
+        let faults = info.pti_faults.max(0) as u64;
+        usage.major_faults = info.pti_pageins.max(0) as u64;
+        usage.minor_faults = faults.saturating_sub(usage.major_faults);
+        usage.thread_count = info.pti_threadnum.max(0) as u64;
+
+        Ok(usage)
     }
 }
 
@@ -42,15 +55,7 @@ impl MemoryMonitor for MacOSMonitor {
         &self,
         pid: u32,
     ) -> Pin<Box<dyn Future<Output = Result<MemoryUsage>> + Send + '_>> {
-        Box::pin(async move {
-            let (rss_bytes, vsz_bytes) = self.get_memory_for_pid(pid)?;
-
-            Ok(MemoryUsage {
-                rss_bytes,
-                vsz_bytes,
-                timestamp: Timestamp::now(),
-            })
-        })
+        Box::pin(async move { self.get_memory_for_pid(pid) })
     }
 
     fn get_process_tree(
@@ -74,6 +79,8 @@ impl MemoryMonitor for MacOSMonitor {
                 name,
                 memory,
                 children,
+                namespace: None,
+                rss_stats: None,
             })
         })
     }
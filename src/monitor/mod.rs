@@ -10,7 +10,25 @@ use std::pin::Pin;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+pub mod aggregate;
+pub mod cgroup;
+pub mod fds;
+pub mod gpu;
+pub mod jobs;
+pub mod lifetimes;
+pub mod pause;
+pub mod perf;
+pub mod pool;
+pub mod pressure;
+pub mod retention;
+pub mod runtime;
+pub mod snapshots;
+pub mod store;
+pub mod stream;
+pub mod system;
+pub mod thermal;
 pub mod tracker;
+pub mod warnings;
 
 #[cfg(target_os = "linux")]
 pub mod linux;
@@ -21,6 +39,9 @@ pub mod macos;
 #[cfg(target_os = "freebsd")]
 pub mod freebsd;
 
+#[cfg(any(target_os = "openbsd", target_os = "netbsd"))]
+pub mod bsd;
+
 /// Trait defining the interface for platform-specific memory monitors.
 ///
 /// Each platform must implement this trait to provide memory monitoring
@@ -63,11 +84,136 @@ pub trait MemoryMonitor: Send + Sync {
         &self,
         pid: u32,
     ) -> Pin<Box<dyn Future<Output = Result<Vec<u32>>> + Send + '_>>;
+
+    /// How many processes have been left out of the trees built so far
+    /// because they couldn't be read, for monitors that leave them out.
+    fn unreadable_processes(&self) -> u64 {
+        0
+    }
 }
 
 /// Thread-safe shared reference to a memory monitor.
 pub type SharedMonitor = Arc<Mutex<Box<dyn MemoryMonitor>>>;
 
+/// Resource usage totals of this process's reaped children.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReapedUsage {
+    /// Minor page faults.
+    pub minor_faults: u64,
+    /// Major page faults.
+    pub major_faults: u64,
+    /// CPU time spent in user mode, in milliseconds.
+    pub user_time_ms: u64,
+    /// CPU time spent in kernel mode, in milliseconds.
+    pub system_time_ms: u64,
+    /// Bytes read from storage (Linux only, zero elsewhere).
+    pub disk_read_bytes: u64,
+    /// Bytes written to storage (Linux only, zero elsewhere).
+    pub disk_write_bytes: u64,
+    /// Peak RSS of the largest single process, in bytes.
+    pub max_rss_bytes: u64,
+}
+
+impl ReapedUsage {
+    /// Returns the usage accumulated since an earlier reading, i.e. that
+    /// of the children reaped in between.
+    ///
+    /// The peak RSS is a high-water mark over all children, not a total,
+    /// so it is kept as is.
+    pub fn since(&self, earlier: &ReapedUsage) -> ReapedUsage {
+        ReapedUsage {
+            minor_faults: self.minor_faults.saturating_sub(earlier.minor_faults),
+            major_faults: self.major_faults.saturating_sub(earlier.major_faults),
+            user_time_ms: self.user_time_ms.saturating_sub(earlier.user_time_ms),
+            system_time_ms: self.system_time_ms.saturating_sub(earlier.system_time_ms),
+            disk_read_bytes: self.disk_read_bytes.saturating_sub(earlier.disk_read_bytes),
+            disk_write_bytes: self
+                .disk_write_bytes
+                .saturating_sub(earlier.disk_write_bytes),
+            max_rss_bytes: self.max_rss_bytes,
+        }
+    }
+}
+
+/// Returns the resource usage totals of this process's reaped children.
+///
+/// The kernel folds a child's counters into `RUSAGE_CHILDREN` once it
+/// has been waited for, including any grandchildren the child itself
+/// waited for. This complements sampling, which misses processes that
+/// exit between samples, and covers counters some platform monitors
+/// cannot read per process.
+#[cfg(unix)]
+pub fn reaped_children_usage() -> ReapedUsage {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    let ret = unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, &mut usage) };
+    if ret != 0 {
+        return ReapedUsage::default();
+    }
+
+    let millis =
+        |tv: libc::timeval| tv.tv_sec.max(0) as u64 * 1000 + tv.tv_usec.max(0) as u64 / 1000;
+
+    // Linux counts block I/O in 512-byte units, matching read_bytes and
+    // write_bytes in /proc/<pid>/io. Other systems count operations of
+    // varying size, which can't be turned into bytes.
+    let block_bytes = |blocks: libc::c_long| {
+        if cfg!(target_os = "linux") {
+            blocks.max(0) as u64 * 512
+        } else {
+            0
+        }
+    };
+
+    ReapedUsage {
+        minor_faults: usage.ru_minflt.max(0) as u64,
+        major_faults: usage.ru_majflt.max(0) as u64,
+        user_time_ms: millis(usage.ru_utime),
+        system_time_ms: millis(usage.ru_stime),
+        disk_read_bytes: block_bytes(usage.ru_inblock),
+        disk_write_bytes: block_bytes(usage.ru_oublock),
+        max_rss_bytes: max_rss_bytes(&usage),
+    }
+}
+
+/// Returns the peak RSS in rusage, in bytes.
+#[cfg(unix)]
+pub fn max_rss_bytes(usage: &libc::rusage) -> u64 {
+    // Reported in bytes on macOS and in kilobytes elsewhere
+    if cfg!(target_os = "macos") {
+        usage.ru_maxrss.max(0) as u64
+    } else {
+        usage.ru_maxrss.max(0) as u64 * 1024
+    }
+}
+
+/// Returns the resource usage totals of this process's reaped children.
+/// Not available on this platform.
+#[cfg(not(unix))]
+pub fn reaped_children_usage() -> ReapedUsage {
+    ReapedUsage::default()
+}
+
+/// Describes why the memory of a process can't be sampled, if it can't.
+///
+/// On Linux this catches a missing `/proc`, a `/proc` mounted with
+/// `hidepid`, and hardened kernels that restrict access to it; the run
+/// then has to fall back to rusage. Always `None` on other platforms.
+///
+/// # Arguments
+/// * `pid` - Process to check, which must not have been reaped yet
+pub fn sampling_restriction(pid: u32) -> Option<String> {
+    #[cfg(target_os = "linux")]
+    {
+        linux::procfs_restriction(pid)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = pid;
+        None
+    }
+}
+
 /// Creates a platform-specific memory monitor instance.
 ///
 /// This factory function automatically selects the appropriate monitor
@@ -94,7 +240,18 @@ pub fn create_monitor() -> Result<Box<dyn MemoryMonitor>> {
         Ok(Box::new(freebsd::FreeBSDMonitor::new()?))
     }
 
-    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+    #[cfg(any(target_os = "openbsd", target_os = "netbsd"))]
+    {
+        Ok(Box::new(bsd::BSDMonitor::new()?))
+    }
+
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd"
+    )))]
     {
         Err(crate::types::PeakMemError::UnsupportedPlatform(
             std::env::consts::OS.to_string(),
@@ -0,0 +1,147 @@
+//! Pausing sampling on request, with `SIGUSR1`.
+//!
+//! Sending peak-mem SIGUSR1 stops sampling until it is sent again, so that
+//! a known phase of the run, such as a warm-up or a data load, can be left
+//! out of the peaks and the timeline. The monitored processes are left
+//! alone and keep running. Each pause is kept with the result, and the
+//! timeline has a gap for it.
+
+use crate::types::{Result, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+
+/// A stretch of the run during which sampling was paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SamplingPause {
+    /// When sampling stopped.
+    pub paused_at: Timestamp,
+    /// When sampling started again, or monitoring ended.
+    pub resumed_at: Timestamp,
+}
+
+impl SamplingPause {
+    /// How long sampling was paused, in milliseconds.
+    pub fn duration_ms(&self) -> u64 {
+        self.resumed_at.duration_since(self.paused_at).as_millis() as u64
+    }
+}
+
+#[derive(Debug, Default)]
+struct PauseState {
+    /// When the current pause began, if sampling is paused.
+    since: Option<Timestamp>,
+    pauses: Vec<SamplingPause>,
+}
+
+/// Whether sampling is paused, and the pauses so far. Clones share the
+/// same state.
+#[derive(Debug, Clone, Default)]
+pub struct PauseSwitch {
+    state: Arc<Mutex<PauseState>>,
+}
+
+impl PauseSwitch {
+    /// Pauses sampling if it is running, or resumes it if it is paused.
+    ///
+    /// # Returns
+    /// * Whether sampling is now paused
+    pub fn toggle(&self) -> bool {
+        self.toggle_at(Timestamp::now())
+    }
+
+    fn toggle_at(&self, now: Timestamp) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.since.take() {
+            Some(paused_at) => {
+                state.pauses.push(SamplingPause {
+                    paused_at,
+                    resumed_at: now,
+                });
+                false
+            }
+            None => {
+                state.since = Some(now);
+                true
+            }
+        }
+    }
+
+    /// Whether sampling is paused.
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().since.is_some()
+    }
+
+    /// The pauses so far, in order. One still going on ends now.
+    pub fn pauses(&self) -> Vec<SamplingPause> {
+        self.pauses_at(Timestamp::now())
+    }
+
+    fn pauses_at(&self, now: Timestamp) -> Vec<SamplingPause> {
+        let state = self.state.lock().unwrap();
+        let mut pauses = state.pauses.clone();
+        if let Some(paused_at) = state.since {
+            pauses.push(SamplingPause {
+                paused_at,
+                resumed_at: now,
+            });
+        }
+        pauses
+    }
+}
+
+/// Toggles `switch` on every SIGUSR1 peak-mem receives, saying so on
+/// stderr, until the task is aborted.
+///
+/// # Errors
+/// * `PeakMemError::Io` - The signal handler couldn't be installed
+pub fn listen(switch: PauseSwitch) -> Result<JoinHandle<()>> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigusr1_stream = signal(SignalKind::user_defined1())?;
+    Ok(tokio::spawn(async move {
+        while sigusr1_stream.recv().await.is_some() {
+            if switch.toggle() {
+                eprintln!("Sampling paused (send SIGUSR1 again to resume)");
+            } else {
+                eprintln!("Sampling resumed");
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pause_switch() {
+        let at = |secs: u64| Timestamp::from_unix_micros(secs * 1_000_000);
+        let switch = PauseSwitch::default();
+        assert!(!switch.is_paused());
+
+        assert!(switch.toggle_at(at(1)));
+        assert!(switch.clone().is_paused());
+        assert!(!switch.toggle_at(at(3)));
+        assert!(!switch.is_paused());
+        assert!(switch.toggle_at(at(5)));
+
+        // The pause still going on ends when asked
+        let pauses = switch.pauses_at(at(6));
+        assert_eq!(
+            pauses,
+            vec![
+                SamplingPause {
+                    paused_at: at(1),
+                    resumed_at: at(3),
+                },
+                SamplingPause {
+                    paused_at: at(5),
+                    resumed_at: at(6),
+                },
+            ]
+        );
+        assert_eq!(pauses[0].duration_ms(), 2000);
+        assert!(switch.is_paused());
+    }
+}
@@ -0,0 +1,181 @@
+//! Allocation-related event counts from Linux perf counters.
+//!
+//! RSS shows how much memory a tree held, not how it got there. Counting
+//! page faults and `mmap`/`brk` calls tells a workload making many small
+//! allocations apart from one mapping a few large regions. The counters
+//! are opened with `perf_event_open` on the child and inherited by
+//! everything it spawns afterwards, so unlike the sampled fault counters
+//! they include children that exit between samples.
+//!
+//! The counting itself is only built with the `perf` feature; the result
+//! type is always available so results and baselines stay readable.
+
+use serde::{Deserialize, Serialize};
+
+/// Event counts for the whole process tree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AllocationCounts {
+    /// Page faults (minor and major) taken in user mode.
+    pub page_faults: u64,
+    /// `mmap` system calls, if syscall tracepoints are accessible.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mmap_calls: Option<u64>,
+    /// `brk` system calls, if syscall tracepoints are accessible.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brk_calls: Option<u64>,
+}
+
+#[cfg(all(feature = "perf", target_os = "linux"))]
+pub use counters::PerfCounters;
+
+#[cfg(all(feature = "perf", target_os = "linux"))]
+mod counters {
+    use super::AllocationCounts;
+    use crate::types::{PeakMemError, Result};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    const PERF_TYPE_SOFTWARE: u32 = 1;
+    const PERF_TYPE_TRACEPOINT: u32 = 2;
+    const PERF_COUNT_SW_PAGE_FAULTS: u64 = 2;
+
+    const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 1 << 3;
+
+    const FLAG_INHERIT: u64 = 1 << 1;
+    const FLAG_EXCLUDE_KERNEL: u64 = 1 << 5;
+    const FLAG_EXCLUDE_HV: u64 = 1 << 6;
+
+    /// Where tracefs may be mounted.
+    const TRACEFS_ROOTS: [&str; 2] = ["/sys/kernel/tracing", "/sys/kernel/debug/tracing"];
+
+    /// The first version of `struct perf_event_attr`
+    /// (`PERF_ATTR_SIZE_VER0`), which every kernel accepts.
+    #[repr(C)]
+    #[derive(Default)]
+    struct PerfEventAttr {
+        type_: u32,
+        size: u32,
+        config: u64,
+        sample_period: u64,
+        sample_type: u64,
+        read_format: u64,
+        flags: u64,
+        wakeup_events: u32,
+        bp_type: u32,
+        config1: u64,
+    }
+
+    /// Open perf counters for a process tree.
+    pub struct PerfCounters {
+        page_faults: OwnedFd,
+        mmap: Option<OwnedFd>,
+        brk: Option<OwnedFd>,
+    }
+
+    impl PerfCounters {
+        /// Starts counting for a process and the children it creates from
+        /// now on.
+        ///
+        /// The syscall counters need access to tracefs and, usually,
+        /// `perf_event_paranoid` at -1 or CAP_PERFMON; without those only
+        /// page faults are counted.
+        ///
+        /// # Errors
+        /// * `PeakMemError::Monitor` - The page fault counter couldn't be
+        ///   opened (e.g. `perf_event_paranoid` is 3, or seccomp forbids
+        ///   perf_event_open)
+        pub fn open(pid: u32) -> Result<Self> {
+            let page_faults = open_counter(pid, PERF_TYPE_SOFTWARE, PERF_COUNT_SW_PAGE_FAULTS)
+                .map_err(|e| PeakMemError::Monitor(format!("Failed to open perf counter: {e}")))?;
+            let syscall = |name: &str| {
+                let id = tracepoint_id(name)?;
+                open_counter(pid, PERF_TYPE_TRACEPOINT, id).ok()
+            };
+
+            Ok(Self {
+                page_faults,
+                mmap: syscall("sys_enter_mmap"),
+                brk: syscall("sys_enter_brk"),
+            })
+        }
+
+        /// Reads the counts so far, including those of exited children.
+        pub fn read(&self) -> AllocationCounts {
+            AllocationCounts {
+                page_faults: read_counter(&self.page_faults).unwrap_or(0),
+                mmap_calls: self.mmap.as_ref().and_then(read_counter),
+                brk_calls: self.brk.as_ref().and_then(read_counter),
+            }
+        }
+    }
+
+    fn open_counter(pid: u32, type_: u32, config: u64) -> std::io::Result<OwnedFd> {
+        let attr = PerfEventAttr {
+            type_,
+            size: std::mem::size_of::<PerfEventAttr>() as u32,
+            config,
+            flags: FLAG_INHERIT | FLAG_EXCLUDE_KERNEL | FLAG_EXCLUDE_HV,
+            ..Default::default()
+        };
+
+        // Any CPU, no group, close on exec so commands don't inherit it
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_perf_event_open,
+                &attr as *const PerfEventAttr,
+                pid as libc::pid_t,
+                -1 as libc::c_int,
+                -1 as libc::c_int,
+                PERF_FLAG_FD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(unsafe { OwnedFd::from_raw_fd(fd as libc::c_int) })
+    }
+
+    fn read_counter(fd: &OwnedFd) -> Option<u64> {
+        let mut value = 0u64;
+        let len = std::mem::size_of::<u64>();
+        let ret = unsafe {
+            libc::read(
+                fd.as_raw_fd(),
+                &mut value as *mut u64 as *mut libc::c_void,
+                len,
+            )
+        };
+        (ret == len as isize).then_some(value)
+    }
+
+    /// Looks up the id of a syscall tracepoint in tracefs.
+    fn tracepoint_id(name: &str) -> Option<u64> {
+        TRACEFS_ROOTS.iter().find_map(|root| {
+            std::fs::read_to_string(format!("{root}/events/syscalls/{name}/id"))
+                .ok()?
+                .trim()
+                .parse()
+                .ok()
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_count_own_page_faults() {
+            // perf_event_open is often unavailable in containers. Tests
+            // run on their own thread, which a counter on the process
+            // wouldn't cover.
+            let tid = unsafe { libc::gettid() } as u32;
+            let Ok(counters) = PerfCounters::open(tid) else {
+                return;
+            };
+
+            let pages = vec![1u8; 16 * 1024 * 1024];
+            assert!(pages.iter().all(|&byte| byte == 1));
+            assert!(counters.read().page_faults > 0);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Steady-state footprint of worker pools, for `--pool-process`.
+//!
+//! Pre-forking servers such as gunicorn, uWSGI or celery peak while their
+//! workers import code and fill caches, then settle. Sizing a service needs
+//! the settled footprint, which the peak of the whole run hides, so the
+//! processes matching a pattern are measured separately before and after
+//! `--pool-steady-after`.
+//!
+//! A master is told from its workers by shape: a matching process with a
+//! matching descendant (the gunicorn master above its workers) is part of
+//! the pool's memory but isn't counted as a worker. Each matching process
+//! is measured on its own, without its children, so nested matches aren't
+//! counted twice.
+
+use crate::types::ProcessMemoryInfo;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The footprint of a worker pool before and after it settled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoolSummary {
+    /// The `--pool-process` pattern pool processes are matched by.
+    pub pattern: String,
+    /// Time after the start from which the pool counts as settled.
+    pub steady_after_ms: u64,
+    /// Highest RSS of the pool during warmup (in bytes).
+    pub warmup_peak_rss_bytes: u64,
+    /// Highest RSS of the pool once settled (in bytes), if the run lasted
+    /// long enough to settle.
+    pub steady_peak_rss_bytes: Option<u64>,
+    /// Mean RSS of the pool once settled (in bytes).
+    pub steady_mean_rss_bytes: Option<u64>,
+    /// Most workers seen at once once settled, or during warmup if the
+    /// pool never settled.
+    pub workers: u64,
+    /// Settled peak RSS divided by the workers at that peak (in bytes).
+    pub steady_rss_per_worker_bytes: Option<u64>,
+}
+
+/// Accumulates pool samples over a run.
+#[derive(Debug)]
+pub struct PoolStats {
+    pattern: Regex,
+    steady_after: Duration,
+    matched: bool,
+    warmup_peak: u64,
+    warmup_workers: u64,
+    steady_peak: Option<(u64, u64)>,
+    steady_total: u128,
+    steady_samples: u64,
+    steady_workers: u64,
+}
+
+impl PoolStats {
+    /// Starts measuring the processes whose names match `pattern`.
+    pub fn new(pattern: Regex, steady_after: Duration) -> Self {
+        Self {
+            pattern,
+            steady_after,
+            matched: false,
+            warmup_peak: 0,
+            warmup_workers: 0,
+            steady_peak: None,
+            steady_total: 0,
+            steady_samples: 0,
+            steady_workers: 0,
+        }
+    }
+
+    /// Records the pool in a sampled tree, taken `elapsed` after the start.
+    pub fn record(&mut self, tree: &ProcessMemoryInfo, elapsed: Duration) {
+        let mut sample = PoolSample::default();
+        self.measure(tree, &mut sample);
+        if sample.processes == 0 {
+            return;
+        }
+        self.matched = true;
+
+        if elapsed < self.steady_after {
+            self.warmup_peak = self.warmup_peak.max(sample.rss_bytes);
+            self.warmup_workers = self.warmup_workers.max(sample.workers);
+        } else {
+            if self
+                .steady_peak
+                .is_none_or(|(peak, _)| sample.rss_bytes > peak)
+            {
+                self.steady_peak = Some((sample.rss_bytes, sample.workers));
+            }
+            self.steady_total += u128::from(sample.rss_bytes);
+            self.steady_samples += 1;
+            self.steady_workers = self.steady_workers.max(sample.workers);
+        }
+    }
+
+    /// Adds the matching processes at and below `info` to a sample.
+    fn measure(&self, info: &ProcessMemoryInfo, sample: &mut PoolSample) {
+        let before = sample.processes;
+        for child in &info.children {
+            self.measure(child, sample);
+        }
+        let has_matching_descendant = sample.processes > before;
+
+        if self.pattern.is_match(&info.name) {
+            sample.processes += 1;
+            sample.rss_bytes += info.memory.rss_bytes;
+            if !has_matching_descendant {
+                sample.workers += 1;
+            }
+        }
+    }
+
+    /// Summarizes the pool, if any process ever matched.
+    pub fn summary(&self) -> Option<PoolSummary> {
+        if !self.matched {
+            return None;
+        }
+        let settled = self.steady_samples > 0;
+        let steady_peak = self.steady_peak.map(|(peak, _)| peak);
+        Some(PoolSummary {
+            pattern: self.pattern.as_str().to_string(),
+            steady_after_ms: self.steady_after.as_millis() as u64,
+            warmup_peak_rss_bytes: self.warmup_peak,
+            steady_peak_rss_bytes: steady_peak,
+            steady_mean_rss_bytes: settled
+                .then(|| (self.steady_total / u128::from(self.steady_samples)) as u64),
+            workers: if settled {
+                self.steady_workers
+            } else {
+                self.warmup_workers
+            },
+            steady_rss_per_worker_bytes: self
+                .steady_peak
+                .filter(|&(_, workers)| workers > 0)
+                .map(|(peak, workers)| peak / workers),
+        })
+    }
+}
+
+/// The pool's processes in one sample.
+#[derive(Debug, Default)]
+struct PoolSample {
+    processes: u64,
+    workers: u64,
+    rss_bytes: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{MemoryUsage, Timestamp};
+
+    fn process(name: &str, rss: u64, children: Vec<ProcessMemoryInfo>) -> ProcessMemoryInfo {
+        ProcessMemoryInfo {
+            pid: 1,
+            name: name.to_string(),
+            memory: MemoryUsage::new(rss, 0, Timestamp::now()),
+            children,
+            namespace: None,
+            rss_stats: None,
+        }
+    }
+
+    fn pool(worker_rss: u64) -> ProcessMemoryInfo {
+        process(
+            "sh",
+            1,
+            vec![process(
+                "gunicorn",
+                20,
+                vec![
+                    process("gunicorn", worker_rss, vec![]),
+                    process("gunicorn", worker_rss, vec![]),
+                    // Not part of the pool
+                    process("bash", 500, vec![]),
+                ],
+            )],
+        )
+    }
+
+    #[test]
+    fn test_warmup_and_steady_state() {
+        let mut stats = PoolStats::new(Regex::new("^gunicorn$").unwrap(), Duration::from_secs(30));
+        stats.record(&pool(400), Duration::from_secs(1));
+        stats.record(&pool(100), Duration::from_secs(31));
+        stats.record(&pool(90), Duration::from_secs(32));
+
+        let summary = stats.summary().unwrap();
+        assert_eq!(summary.warmup_peak_rss_bytes, 820);
+        assert_eq!(summary.steady_peak_rss_bytes, Some(220));
+        assert_eq!(summary.steady_mean_rss_bytes, Some(210));
+        // The master isn't a worker
+        assert_eq!(summary.workers, 2);
+        assert_eq!(summary.steady_rss_per_worker_bytes, Some(110));
+    }
+
+    #[test]
+    fn test_unsettled_and_unmatched() {
+        let mut stats = PoolStats::new(Regex::new("gunicorn").unwrap(), Duration::from_secs(30));
+        stats.record(&pool(400), Duration::from_secs(1));
+        let summary = stats.summary().unwrap();
+        assert_eq!(summary.steady_peak_rss_bytes, None);
+        assert_eq!(summary.workers, 2);
+
+        let mut stats = PoolStats::new(Regex::new("celery").unwrap(), Duration::ZERO);
+        stats.record(&pool(400), Duration::from_secs(1));
+        assert_eq!(stats.summary(), None);
+    }
+}
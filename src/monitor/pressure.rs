@@ -0,0 +1,111 @@
+//! System memory pressure readings.
+//!
+//! On Linux, pressure stall information (PSI) in `/proc/pressure/memory`
+//! reports the share of time tasks were stalled waiting for memory. Other
+//! platforms have no equivalent, so readings are simply unavailable there.
+
+use serde::{Deserialize, Serialize};
+
+/// A snapshot of system memory pressure.
+///
+/// Percentages are the 10-second running averages reported by the kernel:
+/// `some` is the share of time at least one task was stalled on memory,
+/// `full` the share of time all non-idle tasks were.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MemoryPressure {
+    /// Share of time some tasks were stalled on memory (percent).
+    pub some_avg10: f64,
+    /// Share of time all non-idle tasks were stalled on memory (percent).
+    pub full_avg10: f64,
+}
+
+impl MemoryPressure {
+    /// Returns the higher of each percentage in two readings.
+    pub fn max(&self, other: &MemoryPressure) -> MemoryPressure {
+        MemoryPressure {
+            some_avg10: self.some_avg10.max(other.some_avg10),
+            full_avg10: self.full_avg10.max(other.full_avg10),
+        }
+    }
+
+    /// Whether pressure is high enough that measurements taken meanwhile
+    /// are likely to be noisy.
+    pub fn is_high(&self) -> bool {
+        self.some_avg10 >= HIGH_PRESSURE
+    }
+}
+
+/// PSI `some avg10` (percent) at or above which memory pressure counts as
+/// high.
+pub const HIGH_PRESSURE: f64 = 10.0;
+
+/// Reads the current system memory pressure.
+///
+/// # Returns
+/// * `None` if PSI is unavailable (non-Linux, or a kernel without `CONFIG_PSI`)
+pub fn read_memory_pressure() -> Option<MemoryPressure> {
+    #[cfg(target_os = "linux")]
+    {
+        let contents = std::fs::read_to_string("/proc/pressure/memory").ok()?;
+        parse_memory_pressure(&contents)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Parses the contents of a PSI file such as `/proc/pressure/memory`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_memory_pressure(contents: &str) -> Option<MemoryPressure> {
+    let avg10 = |kind: &str| -> Option<f64> {
+        let line = contents.lines().find(|l| l.starts_with(kind))?;
+        line.split_whitespace()
+            .find_map(|field| field.strip_prefix("avg10="))
+            .and_then(|value| value.parse().ok())
+    };
+
+    Some(MemoryPressure {
+        some_avg10: avg10("some ")?,
+        // Kernels before 5.x only report "some" for some resources.
+        full_avg10: avg10("full ").unwrap_or(0.0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_memory_pressure() {
+        let psi = "some avg10=12.50 avg60=3.10 avg300=0.80 total=123456\n\
+                   full avg10=4.25 avg60=1.00 avg300=0.20 total=65432\n";
+        let pressure = parse_memory_pressure(psi).unwrap();
+        assert_eq!(pressure.some_avg10, 12.5);
+        assert_eq!(pressure.full_avg10, 4.25);
+
+        let some_only = "some avg10=0.00 avg60=0.00 avg300=0.00 total=0\n";
+        assert_eq!(parse_memory_pressure(some_only).unwrap().full_avg10, 0.0);
+
+        assert!(parse_memory_pressure("").is_none());
+        assert!(parse_memory_pressure("some avg10=abc\n").is_none());
+    }
+
+    #[test]
+    fn test_memory_pressure_max() {
+        let a = MemoryPressure {
+            some_avg10: 12.0,
+            full_avg10: 1.0,
+        };
+        let b = MemoryPressure {
+            some_avg10: 3.0,
+            full_avg10: 2.5,
+        };
+        let max = a.max(&b);
+        assert_eq!(max.some_avg10, 12.0);
+        assert_eq!(max.full_avg10, 2.5);
+        assert!(max.is_high());
+        assert!(!b.is_high());
+    }
+}
@@ -0,0 +1,193 @@
+//! Bounding the timeline, for `--timeline-max-samples`.
+//!
+//! A long run at a short interval collects more samples than are worth
+//! keeping in memory or writing out, so the timeline can be capped. What
+//! happens to the samples beyond the cap is the retention policy:
+//!
+//! * `downsample` - the whole run is kept at a lower resolution: once the cap
+//!   is reached every other sample is dropped and only every second sample is
+//!   kept from then on (the default)
+//! * `ring` - only the most recent samples are kept
+//! * `truncate` - only the first samples are kept
+//!
+//! The policy applied and the samples it dropped are reported with the
+//! timeline, so tools reading it know it isn't every sample taken.
+
+use crate::types::{MemoryUsage, PeakMemError, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// What to do with samples beyond the cap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RetentionPolicy {
+    /// Halve the resolution each time the cap is reached.
+    #[default]
+    Downsample,
+    /// Keep the most recent samples.
+    Ring,
+    /// Keep the first samples.
+    Truncate,
+}
+
+impl FromStr for RetentionPolicy {
+    type Err = PeakMemError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "downsample" => Ok(RetentionPolicy::Downsample),
+            "ring" => Ok(RetentionPolicy::Ring),
+            "truncate" => Ok(RetentionPolicy::Truncate),
+            _ => Err(PeakMemError::InvalidArgument(format!(
+                "Invalid retention policy '{s}'. Use one of: downsample, ring, truncate"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for RetentionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RetentionPolicy::Downsample => write!(f, "downsample"),
+            RetentionPolicy::Ring => write!(f, "ring"),
+            RetentionPolicy::Truncate => write!(f, "truncate"),
+        }
+    }
+}
+
+/// How a capped timeline relates to the samples taken.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionSummary {
+    /// The retention policy applied.
+    pub policy: String,
+    /// The cap on the samples kept.
+    pub max_samples: u64,
+    /// Samples taken during the run.
+    pub original_samples: u64,
+    /// Samples in the timeline.
+    pub kept_samples: u64,
+    /// With `downsample`, the timeline holds every Nth sample taken.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_every: Option<u64>,
+}
+
+impl RetentionSummary {
+    /// Whether any sample was dropped.
+    pub fn dropped_samples(&self) -> bool {
+        self.kept_samples < self.original_samples
+    }
+}
+
+/// Applies a retention policy to a timeline as samples arrive.
+#[derive(Debug, Clone)]
+pub struct TimelineRetention {
+    policy: RetentionPolicy,
+    max_samples: usize,
+    /// Only every `stride`th sample is kept while downsampling.
+    stride: u64,
+    seen: u64,
+}
+
+impl TimelineRetention {
+    /// Caps a timeline at `max_samples`, which must be at least one.
+    pub fn new(policy: RetentionPolicy, max_samples: usize) -> Self {
+        Self {
+            policy,
+            max_samples: max_samples.max(1),
+            stride: 1,
+            seen: 0,
+        }
+    }
+
+    /// Adds a sample to the timeline, dropping samples as the policy
+    /// says once it's full.
+    pub fn push(&mut self, timeline: &mut Vec<MemoryUsage>, sample: MemoryUsage) {
+        let index = self.seen;
+        self.seen += 1;
+        match self.policy {
+            RetentionPolicy::Downsample => {
+                if !index.is_multiple_of(self.stride) {
+                    return;
+                }
+                timeline.push(sample);
+                if timeline.len() > self.max_samples {
+                    let mut position = 0u64;
+                    timeline.retain(|_| {
+                        position += 1;
+                        !position.is_multiple_of(2)
+                    });
+                    self.stride *= 2;
+                }
+            }
+            RetentionPolicy::Ring => {
+                if timeline.len() >= self.max_samples {
+                    timeline.remove(0);
+                }
+                timeline.push(sample);
+            }
+            RetentionPolicy::Truncate => {
+                if timeline.len() < self.max_samples {
+                    timeline.push(sample);
+                }
+            }
+        }
+    }
+
+    /// Summarizes the retention of a timeline of `kept` samples.
+    pub fn summary(&self, kept: usize) -> RetentionSummary {
+        RetentionSummary {
+            policy: self.policy.to_string(),
+            max_samples: self.max_samples as u64,
+            original_samples: self.seen,
+            kept_samples: kept as u64,
+            sample_every: (self.policy == RetentionPolicy::Downsample).then_some(self.stride),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Timestamp;
+
+    /// Pushes samples whose RSS is their index and returns the RSS kept.
+    fn retain(policy: RetentionPolicy, samples: u64) -> (Vec<u64>, RetentionSummary) {
+        let mut retention = TimelineRetention::new(policy, 4);
+        let mut timeline = Vec::new();
+        for rss in 0..samples {
+            retention.push(&mut timeline, MemoryUsage::new(rss, 0, Timestamp::now()));
+        }
+        let summary = retention.summary(timeline.len());
+        (timeline.iter().map(|s| s.rss_bytes).collect(), summary)
+    }
+
+    #[test]
+    fn test_policies() {
+        let (kept, summary) = retain(RetentionPolicy::Downsample, 10);
+        assert_eq!(kept, vec![0, 4, 8]);
+        assert_eq!(summary.sample_every, Some(4));
+        assert_eq!(summary.original_samples, 10);
+        assert!(summary.dropped_samples());
+
+        let (kept, summary) = retain(RetentionPolicy::Ring, 10);
+        assert_eq!(kept, vec![6, 7, 8, 9]);
+        assert_eq!(summary.sample_every, None);
+
+        let (kept, _) = retain(RetentionPolicy::Truncate, 10);
+        assert_eq!(kept, vec![0, 1, 2, 3]);
+
+        let (kept, summary) = retain(RetentionPolicy::Ring, 3);
+        assert_eq!(kept, vec![0, 1, 2]);
+        assert!(!summary.dropped_samples());
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "ring".parse::<RetentionPolicy>().unwrap(),
+            RetentionPolicy::Ring
+        );
+        assert!("fifo".parse::<RetentionPolicy>().is_err());
+        assert_eq!(RetentionPolicy::Downsample.to_string(), "downsample");
+    }
+}
@@ -0,0 +1,390 @@
+//! Managed-heap probes for `--runtime-heap`.
+//!
+//! The RSS of a garbage-collected program says little about its own
+//! allocations: a collector holds on to memory it has freed, and grows
+//! the heap ahead of need. Each runtime is asked instead how much of its
+//! heap is in use, which is recorded next to the RSS of each sample.
+//!
+//! * JVM: the heap's used spaces are read from the performance data each JVM
+//!   exports in `hsperfdata_<user>/<pid>` in the temp directory, the counters
+//!   `jstat -gc` reports, without starting `jstat` on every sample.
+//! * Python: peak-mem puts a `sitecustomize` module on the `PYTHONPATH` of the
+//!   command, which starts `tracemalloc` and reports its traced memory from a
+//!   thread. Any `sitecustomize` of the user's own is still run. What
+//!   `tracemalloc` keeps of each allocation adds to the process's RSS.
+//! * Go: the program reports `runtime.MemStats.HeapAlloc` itself, with a few
+//!   lines shown in the manual page.
+//!
+//! Agents report through files: each process writes the bytes in use to a
+//! file named after its PID in the directory in [`HEAP_DIR_VAR`], every
+//! [`HEAP_INTERVAL_VAR`] milliseconds, replacing it in one rename. Only the
+//! processes of the tree in a sample are counted, so exited ones drop out.
+
+use crate::types::{PeakMemError, ProcessMemoryInfo, Result};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Where agents write the heap of their process.
+pub const HEAP_DIR_VAR: &str = "PEAK_MEM_HEAP_DIR";
+
+/// How often agents should report, in milliseconds.
+pub const HEAP_INTERVAL_VAR: &str = "PEAK_MEM_HEAP_INTERVAL_MS";
+
+/// The agent put on the `PYTHONPATH` for `--runtime-heap python`.
+const PYTHON_AGENT: &str = r#"# Written by peak-mem for --runtime-heap python: traces the heap with
+# tracemalloc and reports the memory in use to peak-mem.
+import os
+import sys
+
+
+def _peak_mem_heap():
+    import threading
+    import time
+    import tracemalloc
+
+    directory = os.environ.get("PEAK_MEM_HEAP_DIR")
+    if not directory:
+        return
+    interval = int(os.environ.get("PEAK_MEM_HEAP_INTERVAL_MS", "100")) / 1000
+
+    def report():
+        path = os.path.join(directory, str(os.getpid()))
+        while True:
+            current, _ = tracemalloc.get_traced_memory()
+            try:
+                with open(path + ".tmp", "w") as f:
+                    f.write("%d\n" % current)
+                os.replace(path + ".tmp", path)
+            except OSError:
+                return
+            time.sleep(interval)
+
+    def start():
+        threading.Thread(target=report, name="peak-mem-heap", daemon=True).start()
+
+    tracemalloc.start()
+    start()
+    if hasattr(os, "register_at_fork"):
+        os.register_at_fork(after_in_child=start)
+
+
+_peak_mem_heap()
+
+# Run the sitecustomize this one shadows, if any
+_here = os.path.dirname(os.path.abspath(__file__))
+sys.path[:] = [p for p in sys.path if os.path.abspath(p or ".") != _here]
+sys.modules.pop("sitecustomize", None)
+try:
+    import sitecustomize  # noqa: F401
+except ImportError:
+    pass
+"#;
+
+/// A runtime whose managed heap can be probed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runtime {
+    /// Java and other JVM languages, through their performance data.
+    Jvm,
+    /// Go, through an agent in the program.
+    Go,
+    /// CPython, through `tracemalloc`.
+    Python,
+}
+
+impl FromStr for Runtime {
+    type Err = PeakMemError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "jvm" | "java" => Ok(Runtime::Jvm),
+            "go" => Ok(Runtime::Go),
+            "python" => Ok(Runtime::Python),
+            _ => Err(PeakMemError::InvalidArgument(format!(
+                "Invalid runtime '{s}'. Use one of: jvm, go, python"
+            ))),
+        }
+    }
+}
+
+/// Reads the managed heap of the processes of a tree.
+pub struct HeapProbe {
+    runtimes: Vec<Runtime>,
+    /// The directory of the agents' reports and the Python agent, removed
+    /// with the probe.
+    dir: PathBuf,
+    interval_ms: u64,
+}
+
+impl HeapProbe {
+    /// Sets up the probes of `runtimes` for agents reporting every
+    /// `interval_ms`.
+    ///
+    /// # Errors
+    /// * `PeakMemError::Io` - The directory for the agents couldn't be created
+    pub fn new(runtimes: Vec<Runtime>, interval_ms: u64) -> Result<Self> {
+        let dir = std::env::temp_dir().join(format!("peak-mem-heap-{}", std::process::id()));
+        // Left by an earlier peak-mem with the same PID that was killed
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("heaps"))?;
+        let probe = Self {
+            runtimes,
+            dir,
+            interval_ms,
+        };
+        if probe.runtimes.contains(&Runtime::Python) {
+            fs::create_dir(probe.python_dir())?;
+            fs::write(probe.python_dir().join("sitecustomize.py"), PYTHON_AGENT)?;
+        }
+        Ok(probe)
+    }
+
+    fn heaps_dir(&self) -> PathBuf {
+        self.dir.join("heaps")
+    }
+
+    fn python_dir(&self) -> PathBuf {
+        self.dir.join("python")
+    }
+
+    /// The variables to start the command with, so that its agents
+    /// report.
+    pub fn environment(&self) -> Vec<(OsString, OsString)> {
+        let mut vars = vec![
+            (HEAP_DIR_VAR.into(), self.heaps_dir().into_os_string()),
+            (
+                HEAP_INTERVAL_VAR.into(),
+                self.interval_ms.to_string().into(),
+            ),
+        ];
+        if self.runtimes.contains(&Runtime::Python) {
+            let mut paths = vec![self.python_dir()];
+            if let Some(existing) = std::env::var_os("PYTHONPATH") {
+                paths.extend(std::env::split_paths(&existing));
+            }
+            if let Ok(path) = std::env::join_paths(paths) {
+                vars.push(("PYTHONPATH".into(), path));
+            }
+        }
+        vars
+    }
+
+    /// The heap in use by the processes of a sample: those of `tree`, or
+    /// `pids` without one.
+    ///
+    /// # Returns
+    /// * The bytes in use, or `None` if no process reported its heap
+    pub fn sample(&self, tree: Option<&ProcessMemoryInfo>, pids: &[u32]) -> Option<u64> {
+        let mut processes = Vec::new();
+        match tree {
+            Some(tree) => collect_pids(tree, &mut processes),
+            None => processes.extend_from_slice(pids),
+        }
+
+        let jvm = self.runtimes.contains(&Runtime::Jvm);
+        let agents = self
+            .runtimes
+            .iter()
+            .any(|runtime| matches!(runtime, Runtime::Go | Runtime::Python));
+        let jvm_dirs = if jvm { perf_data_dirs() } else { Vec::new() };
+        let mut total = None;
+        for pid in processes {
+            let reported = if agents {
+                read_agent_report(&self.heaps_dir().join(pid.to_string()))
+            } else {
+                None
+            };
+            let heap = reported.or_else(|| {
+                jvm_dirs
+                    .iter()
+                    .find_map(|dir| fs::read(dir.join(pid.to_string())).ok())
+                    .and_then(|data| perf_data_heap(&data))
+            });
+            if let Some(bytes) = heap {
+                *total.get_or_insert(0) += bytes;
+            }
+        }
+        total
+    }
+}
+
+impl Drop for HeapProbe {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn collect_pids(tree: &ProcessMemoryInfo, pids: &mut Vec<u32>) {
+    pids.push(tree.pid);
+    for child in &tree.children {
+        collect_pids(child, pids);
+    }
+}
+
+fn read_agent_report(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// The `hsperfdata_<user>` directories JVMs export their performance
+/// data in.
+fn perf_data_dirs() -> Vec<PathBuf> {
+    // HotSpot uses /tmp on Linux whatever TMPDIR says
+    let temp = if cfg!(target_os = "macos") {
+        std::env::temp_dir()
+    } else {
+        PathBuf::from("/tmp")
+    };
+    let Ok(entries) = fs::read_dir(temp) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with("hsperfdata_")
+        })
+        .map(|entry| entry.path())
+        .collect()
+}
+
+/// Sums the used space of every generation of a JVM's heap from its
+/// performance data, the `sun.gc.generation.<n>.space.<m>.used`
+/// counters.
+///
+/// # Returns
+/// * The bytes in use, or `None` if the data isn't valid or not yet accessible
+pub fn perf_data_heap(data: &[u8]) -> Option<u64> {
+    const MAGIC: [u8; 4] = [0xca, 0xfe, 0xc0, 0xc0];
+    const HEADER: usize = 32;
+    const ENTRY_HEADER: usize = 20;
+
+    if data.get(..4)? != MAGIC {
+        return None;
+    }
+    let little_endian = *data.get(4)? == 1;
+    let u32_at = |offset: usize| -> Option<u32> {
+        let bytes: [u8; 4] = data.get(offset..offset.checked_add(4)?)?.try_into().ok()?;
+        Some(if little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    };
+    let u64_at = |offset: usize| -> Option<u64> {
+        let bytes: [u8; 8] = data.get(offset..offset.checked_add(8)?)?.try_into().ok()?;
+        Some(if little_endian {
+            u64::from_le_bytes(bytes)
+        } else {
+            u64::from_be_bytes(bytes)
+        })
+    };
+    // Not accessible until the JVM has created every counter
+    if data.len() < HEADER || data[7] == 0 {
+        return None;
+    }
+
+    let mut offset = u32_at(24)? as usize;
+    let entries = u32_at(28)?;
+    let mut used = 0;
+    for _ in 0..entries {
+        let length = u32_at(offset)? as usize;
+        if length < ENTRY_HEADER {
+            return None;
+        }
+        let name_start = offset + u32_at(offset + 4)? as usize;
+        let vector_length = u32_at(offset + 8)?;
+        let data_type = *data.get(offset + 12)?;
+        let value_offset = offset + u32_at(offset + 16)? as usize;
+
+        let name = data.get(name_start..)?;
+        let name = &name[..name.iter().position(|&b| b == 0)?];
+        if vector_length == 0 && data_type == b'J' && is_space_used(name) {
+            used += u64_at(value_offset)?;
+        }
+        offset += length;
+    }
+    Some(used)
+}
+
+fn is_space_used(name: &[u8]) -> bool {
+    let Some(rest) = std::str::from_utf8(name)
+        .ok()
+        .and_then(|name| name.strip_prefix("sun.gc.generation."))
+    else {
+        return false;
+    };
+    let parts: Vec<&str> = rest.split('.').collect();
+    let number = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    matches!(parts.as_slice(), [generation, "space", space, "used"]
+        if number(generation) && number(space))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lays out performance data with the given long counters.
+    fn perf_data(counters: &[(&str, u64)]) -> Vec<u8> {
+        let mut data = vec![0xca, 0xfe, 0xc0, 0xc0, 1, 2, 0, 1];
+        data.extend_from_slice(&[0; 16]);
+        data.extend_from_slice(&32u32.to_le_bytes());
+        data.extend_from_slice(&(counters.len() as u32).to_le_bytes());
+        for (name, value) in counters {
+            // The name, padded to 8 bytes, then the value
+            let name_length = (name.len() + 1).div_ceil(8) * 8;
+            let length = 24 + name_length + 8;
+            data.extend_from_slice(&(length as u32).to_le_bytes());
+            data.extend_from_slice(&24u32.to_le_bytes());
+            data.extend_from_slice(&0u32.to_le_bytes());
+            data.extend_from_slice(&[b'J', 0, 1, 3]);
+            data.extend_from_slice(&((24 + name_length) as u32).to_le_bytes());
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(name.as_bytes());
+            data.resize(data.len() + name_length - name.len(), 0);
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_perf_data_heap() {
+        let data = perf_data(&[
+            ("sun.gc.generation.0.space.0.used", 4096),
+            ("sun.gc.generation.0.space.0.capacity", 1 << 30),
+            ("sun.gc.generation.1.space.0.used", 1024),
+            ("sun.gc.metaspace.used", 512),
+        ]);
+        assert_eq!(perf_data_heap(&data), Some(5120));
+
+        let mut inaccessible = data.clone();
+        inaccessible[7] = 0;
+        assert_eq!(perf_data_heap(&inaccessible), None);
+        assert_eq!(perf_data_heap(&data[..40]), None);
+        assert_eq!(perf_data_heap(b"not perf data at all, not at all"), None);
+    }
+
+    #[test]
+    fn test_agent_reports() {
+        let probe = HeapProbe::new(vec![Runtime::Go, Runtime::Python], 50).unwrap();
+        let vars = probe.environment();
+        let heaps = PathBuf::from(&vars[0].1);
+        assert_eq!(vars[0].0, HEAP_DIR_VAR);
+        assert_eq!(vars[1].1, "50");
+        assert!(vars.iter().any(|(name, _)| name == "PYTHONPATH"));
+        assert!(probe.python_dir().join("sitecustomize.py").exists());
+
+        assert_eq!(probe.sample(None, &[1, 2]), None);
+        fs::write(heaps.join("1"), "1000\n").unwrap();
+        fs::write(heaps.join("2"), "24").unwrap();
+        fs::write(heaps.join("3"), "5000").unwrap();
+        // Process 3 isn't in the sample
+        assert_eq!(probe.sample(None, &[1, 2]), Some(1024));
+
+        let dir = probe.dir.clone();
+        drop(probe);
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,128 @@
+//! The RSS of each process over the run, for the cursor of `--report`.
+//!
+//! The tree is sampled as a whole anyway, so each sample's processes are
+//! kept in brief: PID, name and RSS. Like the timeline downsampled by
+//! `--timeline-max-samples`, the snapshots keep the whole run at a lower
+//! resolution once [`MAX_SNAPSHOTS`] is reached, so a long run with many
+//! processes doesn't grow without bound.
+
+use crate::query;
+use crate::types::{ProcessMemoryInfo, Timestamp};
+
+/// Snapshots kept at most.
+pub const MAX_SNAPSHOTS: usize = 1000;
+
+/// A process in a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessRss {
+    /// Process ID.
+    pub pid: u32,
+    /// Name of the process.
+    pub name: String,
+    /// Resident set size (in bytes).
+    pub rss_bytes: u64,
+}
+
+/// The processes of the tree in one sample.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessSnapshot {
+    /// When the tree was sampled.
+    pub timestamp: Timestamp,
+    /// The processes of the tree, parents before their children.
+    pub processes: Vec<ProcessRss>,
+}
+
+/// Collects the snapshots of a run.
+#[derive(Debug)]
+pub struct ProcessSnapshots {
+    snapshots: Vec<ProcessSnapshot>,
+    max_snapshots: usize,
+    /// Only every `stride`th sample is kept.
+    stride: u64,
+    seen: u64,
+}
+
+impl Default for ProcessSnapshots {
+    fn default() -> Self {
+        Self::new(MAX_SNAPSHOTS)
+    }
+}
+
+impl ProcessSnapshots {
+    /// Keeps at most `max_snapshots` snapshots, which must be at least one.
+    pub fn new(max_snapshots: usize) -> Self {
+        Self {
+            snapshots: Vec::new(),
+            max_snapshots: max_snapshots.max(1),
+            stride: 1,
+            seen: 0,
+        }
+    }
+
+    /// Records the processes of a sampled tree.
+    pub fn record(&mut self, tree: &ProcessMemoryInfo, timestamp: Timestamp) {
+        let index = self.seen;
+        self.seen += 1;
+        if !index.is_multiple_of(self.stride) {
+            return;
+        }
+
+        let processes = query::processes(tree)
+            .into_iter()
+            .map(|info| ProcessRss {
+                pid: info.pid,
+                name: info.name.clone(),
+                rss_bytes: info.memory.rss_bytes,
+            })
+            .collect();
+        self.snapshots.push(ProcessSnapshot {
+            timestamp,
+            processes,
+        });
+        if self.snapshots.len() > self.max_snapshots {
+            let mut position = 0u64;
+            self.snapshots.retain(|_| {
+                position += 1;
+                !position.is_multiple_of(2)
+            });
+            self.stride *= 2;
+        }
+    }
+
+    /// The snapshots kept, oldest first.
+    pub fn snapshots(&self) -> Vec<ProcessSnapshot> {
+        self.snapshots.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::MemoryUsage;
+
+    fn tree(rss: u64) -> ProcessMemoryInfo {
+        let process = |pid, name: &str, children| ProcessMemoryInfo {
+            pid,
+            name: name.to_string(),
+            memory: MemoryUsage::new(rss, 0, Timestamp::now()),
+            children,
+            namespace: None,
+            rss_stats: None,
+        };
+        process(1, "make", vec![process(2, "cc", vec![])])
+    }
+
+    #[test]
+    fn test_record() {
+        let mut snapshots = ProcessSnapshots::new(4);
+        for rss in 0..10 {
+            snapshots.record(&tree(rss), Timestamp::now());
+        }
+        let kept = snapshots.snapshots();
+        // Every fourth of the ten samples once the cap was reached twice
+        let rss: Vec<u64> = kept.iter().map(|s| s.processes[0].rss_bytes).collect();
+        assert_eq!(rss, vec![0, 4, 8]);
+        assert_eq!(kept[0].processes[1].name, "cc");
+        assert_eq!(kept[0].processes[1].pid, 2);
+    }
+}
@@ -0,0 +1,873 @@
+//! Where the tracker keeps its samples, for `--sample-store`.
+//!
+//! Every sample of the tracker goes into a [`SampleStore`], which decides
+//! how much of the run is kept and where. The stores trade off like this:
+//!
+//! * `memory` - a vector, capped by `--timeline-max-samples` as its retention
+//!   policy says (the default)
+//! * `ring-file` - a ring of the most recent `--timeline-max-samples` samples
+//!   in a file mapped into memory, so a long run at a high rate neither grows
+//!   the heap nor loses its samples if peak-mem is killed
+//! * `sqlite` - every sample as a row of a SQLite database, so a run of any
+//!   length is kept whole on disk and can be queried while it goes on (with the
+//!   `sqlite` feature)
+//!
+//! With `--timeline-delta`, either store only gets the samples whose RSS
+//! moved by more than the delta from the last one it got, and the latest
+//! sample, so that the timeline still reaches the end of the run.
+//!
+//! Under `--self-memory-cap`, a `memory` store without a cap moves its
+//! samples to a temporary file whenever peak-mem goes over the cap, and
+//! reads them back for the results.
+//!
+//! A ring file starts with a header of eight little-endian words: the
+//! magic `PKMEMRNG`, the format version and record size, the capacity
+//! in records, and the samples written so far; sample `n` is at record
+//! `n % capacity`. A record is ten words: RSS, VSZ, the Unix timestamp
+//! in microseconds, minor and major faults, threads, bytes read, bytes
+//! written, GPU memory and managed heap, the last two `u64::MAX` if not
+//! tracked.
+
+use crate::monitor::retention::{RetentionPolicy, RetentionSummary, TimelineRetention};
+use crate::types::{MemoryUsage, PeakMemError, Result, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// A store shared by the tracker and whoever reads its samples live.
+pub type SharedStore = Arc<RwLock<Box<dyn SampleStore>>>;
+
+/// Keeps the samples of a run.
+pub trait SampleStore: Send + Sync {
+    /// Adds a sample, dropping older ones if the store says so.
+    fn push(&mut self, sample: MemoryUsage);
+
+    /// Number of samples kept.
+    fn len(&self) -> usize;
+
+    /// The most recent sample, if any.
+    fn last(&self) -> Option<MemoryUsage>;
+
+    /// The samples kept, oldest first.
+    fn samples(&self) -> Vec<MemoryUsage>;
+
+    /// The `n` most recent samples kept, oldest first.
+    fn recent(&self, n: usize) -> Vec<MemoryUsage>;
+
+    /// How the samples kept relate to those taken, if any were dropped
+    /// by design.
+    fn retention(&self) -> Option<RetentionSummary>;
+
+    /// Moves the samples held in memory to disk, if the store keeps them
+    /// in memory without a cap.
+    ///
+    /// # Returns
+    /// * How many samples were moved
+    ///
+    /// # Errors
+    /// * `PeakMemError::Io` - The samples couldn't be written
+    fn spill(&mut self) -> Result<usize> {
+        Ok(0)
+    }
+
+    /// How the samples kept relate to those taken, if only changes were
+    /// kept.
+    fn delta(&self) -> Option<DeltaSummary> {
+        None
+    }
+}
+
+/// How a timeline of only the changes relates to the samples taken.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeltaSummary {
+    /// How far RSS had to move for a sample to be kept.
+    pub delta_bytes: u64,
+    /// Samples taken during the run.
+    pub original_samples: u64,
+    /// Samples in the timeline.
+    pub kept_samples: u64,
+}
+
+/// The kinds of store `--sample-store` selects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StoreKind {
+    /// [`MemoryStore`].
+    #[default]
+    Memory,
+    /// [`RingFileStore`].
+    RingFile,
+    /// `SqliteStore`, with the `sqlite` feature.
+    Sqlite,
+}
+
+impl FromStr for StoreKind {
+    type Err = PeakMemError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "memory" => Ok(StoreKind::Memory),
+            "ring-file" => Ok(StoreKind::RingFile),
+            "sqlite" => Ok(StoreKind::Sqlite),
+            _ => Err(PeakMemError::InvalidArgument(format!(
+                "Invalid sample store '{s}'. Use one of: memory, ring-file, sqlite"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for StoreKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreKind::Memory => write!(f, "memory"),
+            StoreKind::RingFile => write!(f, "ring-file"),
+            StoreKind::Sqlite => write!(f, "sqlite"),
+        }
+    }
+}
+
+/// Samples in a vector, optionally capped.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    samples: Vec<MemoryUsage>,
+    retention: Option<TimelineRetention>,
+    /// Samples older than those in the vector, once spilled.
+    spilled: Option<SpillFile>,
+}
+
+impl MemoryStore {
+    /// Keeps every sample.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Caps the samples, dropping those beyond the cap as `retention`
+    /// says.
+    pub fn with_retention(retention: TimelineRetention) -> Self {
+        Self {
+            samples: Vec::new(),
+            retention: Some(retention),
+            spilled: None,
+        }
+    }
+
+    fn spilled_len(&self) -> u64 {
+        self.spilled.as_ref().map_or(0, |spilled| spilled.records)
+    }
+}
+
+impl SampleStore for MemoryStore {
+    fn push(&mut self, sample: MemoryUsage) {
+        match &mut self.retention {
+            Some(retention) => retention.push(&mut self.samples, sample),
+            None => self.samples.push(sample),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.spilled_len() as usize + self.samples.len()
+    }
+
+    fn last(&self) -> Option<MemoryUsage> {
+        match (self.samples.last(), &self.spilled) {
+            (Some(last), _) => Some(last.clone()),
+            (None, Some(spilled)) => spilled.read_from(spilled.records - 1).pop(),
+            (None, None) => None,
+        }
+    }
+
+    fn samples(&self) -> Vec<MemoryUsage> {
+        self.recent(self.len())
+    }
+
+    fn recent(&self, n: usize) -> Vec<MemoryUsage> {
+        let in_memory = &self.samples[self.samples.len().saturating_sub(n)..];
+        let from_disk = (n - in_memory.len()) as u64;
+        let mut recent = match &self.spilled {
+            Some(spilled) if from_disk > 0 => {
+                spilled.read_from(spilled.records.saturating_sub(from_disk))
+            }
+            _ => Vec::new(),
+        };
+        recent.extend_from_slice(in_memory);
+        recent
+    }
+
+    fn retention(&self) -> Option<RetentionSummary> {
+        self.retention
+            .as_ref()
+            .map(|retention| retention.summary(self.samples.len()))
+    }
+
+    fn spill(&mut self) -> Result<usize> {
+        // A capped store is as small as it gets
+        if self.retention.is_some() || self.samples.is_empty() {
+            return Ok(0);
+        }
+        let spilled = match &mut self.spilled {
+            Some(spilled) => spilled,
+            None => self.spilled.insert(SpillFile::create()?),
+        };
+        spilled.append(&self.samples)?;
+        let moved = self.samples.len();
+        self.samples = Vec::new();
+        Ok(moved)
+    }
+}
+
+/// Passes a store only the samples whose RSS changed by more than a
+/// delta.
+pub struct DeltaStore {
+    inner: Box<dyn SampleStore>,
+    delta_bytes: u64,
+    /// RSS of the last sample passed on.
+    last_kept_rss: Option<u64>,
+    /// The latest sample, while it isn't passed on.
+    pending: Option<MemoryUsage>,
+    seen: u64,
+}
+
+impl DeltaStore {
+    /// Keeps the samples whose RSS moved by more than `delta_bytes` since
+    /// the last one kept in `inner`.
+    pub fn new(inner: Box<dyn SampleStore>, delta_bytes: u64) -> Self {
+        Self {
+            inner,
+            delta_bytes,
+            last_kept_rss: None,
+            pending: None,
+            seen: 0,
+        }
+    }
+}
+
+impl SampleStore for DeltaStore {
+    fn push(&mut self, sample: MemoryUsage) {
+        self.seen += 1;
+        let changed = self
+            .last_kept_rss
+            .is_none_or(|rss| rss.abs_diff(sample.rss_bytes) > self.delta_bytes);
+        if changed {
+            self.last_kept_rss = Some(sample.rss_bytes);
+            self.pending = None;
+            self.inner.push(sample);
+        } else {
+            self.pending = Some(sample);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len() + usize::from(self.pending.is_some())
+    }
+
+    fn last(&self) -> Option<MemoryUsage> {
+        self.pending.clone().or_else(|| self.inner.last())
+    }
+
+    fn samples(&self) -> Vec<MemoryUsage> {
+        let mut samples = self.inner.samples();
+        samples.extend(self.pending.clone());
+        samples
+    }
+
+    fn recent(&self, n: usize) -> Vec<MemoryUsage> {
+        let pending = usize::from(self.pending.is_some()).min(n);
+        let mut recent = self.inner.recent(n - pending);
+        recent.extend(self.pending.clone().filter(|_| pending > 0));
+        recent
+    }
+
+    fn retention(&self) -> Option<RetentionSummary> {
+        self.inner.retention()
+    }
+
+    fn spill(&mut self) -> Result<usize> {
+        self.inner.spill()
+    }
+
+    fn delta(&self) -> Option<DeltaSummary> {
+        Some(DeltaSummary {
+            delta_bytes: self.delta_bytes,
+            original_samples: self.seen,
+            kept_samples: self.len() as u64,
+        })
+    }
+}
+
+/// Samples moved out of memory, as records in a temporary file that is
+/// removed as soon as it is opened.
+#[derive(Debug)]
+struct SpillFile {
+    file: File,
+    records: u64,
+}
+
+impl SpillFile {
+    fn create() -> Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "peak-mem-spill-{}-{}",
+            std::process::id(),
+            Timestamp::now().unix_micros()
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        std::fs::remove_file(&path)?;
+        Ok(Self { file, records: 0 })
+    }
+
+    fn append(&mut self, samples: &[MemoryUsage]) -> Result<()> {
+        let bytes: Vec<u8> = samples
+            .iter()
+            .flat_map(encode)
+            .flat_map(u64::to_le_bytes)
+            .collect();
+        let offset = self.records * (RECORD_WORDS * WORD) as u64;
+        self.file.write_all_at(&bytes, offset)?;
+        self.records += samples.len() as u64;
+        Ok(())
+    }
+
+    /// Reads back the samples from record `first` on. The file is private
+    /// to this process, so reading it only fails if the disk does.
+    fn read_from(&self, first: u64) -> Vec<MemoryUsage> {
+        let record_len = RECORD_WORDS * WORD;
+        let mut bytes = vec![0; (self.records - first) as usize * record_len];
+        if let Err(e) = self
+            .file
+            .read_exact_at(&mut bytes, first * record_len as u64)
+        {
+            eprintln!("Warning: Failed to read spilled samples back: {e}");
+            return Vec::new();
+        }
+        bytes
+            .chunks_exact(record_len)
+            .map(|record| {
+                let mut words = [0; RECORD_WORDS];
+                for (word, bytes) in words.iter_mut().zip(record.chunks_exact(WORD)) {
+                    *word = u64::from_le_bytes(bytes.try_into().unwrap());
+                }
+                decode(words)
+            })
+            .collect()
+    }
+}
+
+/// Lays a sample out as a record of ring and spill files.
+fn encode(sample: &MemoryUsage) -> [u64; RECORD_WORDS] {
+    [
+        sample.rss_bytes,
+        sample.vsz_bytes,
+        sample.timestamp.unix_micros(),
+        sample.minor_faults,
+        sample.major_faults,
+        sample.thread_count,
+        sample.disk_read_bytes,
+        sample.disk_write_bytes,
+        sample.gpu_bytes.unwrap_or(u64::MAX),
+        sample.heap_bytes.unwrap_or(u64::MAX),
+    ]
+}
+
+fn decode(record: [u64; RECORD_WORDS]) -> MemoryUsage {
+    MemoryUsage {
+        minor_faults: record[3],
+        major_faults: record[4],
+        thread_count: record[5],
+        disk_read_bytes: record[6],
+        disk_write_bytes: record[7],
+        gpu_bytes: Some(record[8]).filter(|&gpu| gpu != u64::MAX),
+        heap_bytes: Some(record[9]).filter(|&heap| heap != u64::MAX),
+        ..MemoryUsage::new(record[0], record[1], Timestamp::from_unix_micros(record[2]))
+    }
+}
+
+const MAGIC: &[u8; 8] = b"PKMEMRNG";
+const VERSION: u64 = 2;
+const HEADER_WORDS: usize = 8;
+const RECORD_WORDS: usize = 10;
+const WORD: usize = std::mem::size_of::<u64>();
+
+/// The most samples a ring file holds, making it 8 GB.
+pub const MAX_RING_CAPACITY: usize = 100_000_000;
+/// Header word holding the samples written so far.
+const WRITTEN: usize = 4;
+
+/// The most recent samples in a ring in a memory-mapped file.
+pub struct RingFileStore {
+    map: *mut u8,
+    len: usize,
+    capacity: u64,
+    written: u64,
+}
+
+// The mapping is owned by the store and only reached through it
+unsafe impl Send for RingFileStore {}
+unsafe impl Sync for RingFileStore {}
+
+impl RingFileStore {
+    /// Creates the ring file at `path`, replacing any file there, with
+    /// room for `capacity` samples, which must be at least one.
+    ///
+    /// # Errors
+    /// * `PeakMemError::InvalidArgument` - `capacity` is over
+    ///   [`MAX_RING_CAPACITY`]
+    /// * `PeakMemError::Io` - The file couldn't be created or mapped
+    pub fn create(path: &Path, capacity: usize) -> Result<Self> {
+        let capacity = capacity.max(1);
+        let len = Some(capacity)
+            .filter(|&capacity| capacity <= MAX_RING_CAPACITY)
+            .and_then(|capacity| capacity.checked_mul(RECORD_WORDS))
+            .and_then(|words| words.checked_add(HEADER_WORDS))
+            .and_then(|words| words.checked_mul(WORD))
+            .ok_or_else(|| {
+                PeakMemError::InvalidArgument(format!(
+                    "A ring file holds at most {MAX_RING_CAPACITY} samples, not {capacity}"
+                ))
+            })?;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(len as u64)?;
+        let map = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if map == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        // The mapping stays valid once the file is closed
+        let mut store = Self {
+            map: map.cast(),
+            len,
+            capacity: capacity as u64,
+            written: 0,
+        };
+        let bytes = store.bytes_mut();
+        bytes[..WORD].copy_from_slice(MAGIC);
+        store.set_word(1, VERSION);
+        store.set_word(2, RECORD_WORDS as u64);
+        store.set_word(3, capacity as u64);
+        Ok(store)
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.map, self.len) }
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.map, self.len) }
+    }
+
+    fn word(&self, index: usize) -> u64 {
+        let start = index * WORD;
+        let mut word = [0; WORD];
+        word.copy_from_slice(&self.bytes()[start..start + WORD]);
+        u64::from_le_bytes(word)
+    }
+
+    fn set_word(&mut self, index: usize, value: u64) {
+        let start = index * WORD;
+        self.bytes_mut()[start..start + WORD].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Index of the first word of the record of sample `n`.
+    fn record(&self, n: u64) -> usize {
+        HEADER_WORDS + (n % self.capacity) as usize * RECORD_WORDS
+    }
+
+    fn read(&self, n: u64) -> MemoryUsage {
+        let record = self.record(n);
+        decode(std::array::from_fn(|field| self.word(record + field)))
+    }
+}
+
+impl SampleStore for RingFileStore {
+    fn push(&mut self, sample: MemoryUsage) {
+        let record = self.record(self.written);
+        for (field, value) in encode(&sample).into_iter().enumerate() {
+            self.set_word(record + field, value);
+        }
+        // Counted only once the record is complete
+        self.written += 1;
+        self.set_word(WRITTEN, self.written);
+    }
+
+    fn len(&self) -> usize {
+        self.written.min(self.capacity) as usize
+    }
+
+    fn last(&self) -> Option<MemoryUsage> {
+        self.written.checked_sub(1).map(|n| self.read(n))
+    }
+
+    fn samples(&self) -> Vec<MemoryUsage> {
+        self.recent(self.capacity as usize)
+    }
+
+    fn recent(&self, n: usize) -> Vec<MemoryUsage> {
+        let first = self.written.saturating_sub(self.capacity.min(n as u64));
+        (first..self.written).map(|n| self.read(n)).collect()
+    }
+
+    fn retention(&self) -> Option<RetentionSummary> {
+        Some(RetentionSummary {
+            policy: RetentionPolicy::Ring.to_string(),
+            max_samples: self.capacity,
+            original_samples: self.written,
+            kept_samples: self.len() as u64,
+            sample_every: None,
+        })
+    }
+}
+
+impl Drop for RingFileStore {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.map.cast(), self.len);
+        }
+    }
+}
+
+/// Every sample as a row of the `samples` table of a SQLite database.
+///
+/// The table's columns are those of a ring file's record, with NULL for
+/// GPU memory and managed heap if not tracked, after the sample's number
+/// `n`. Writes go to a write-ahead log without waiting for the disk, so
+/// sampling isn't held up by fsync; a crash of the machine may lose the
+/// last samples, one of peak-mem doesn't.
+#[cfg(feature = "sqlite")]
+pub struct SqliteStore {
+    db: std::sync::Mutex<rusqlite::Connection>,
+    written: usize,
+    last: Option<MemoryUsage>,
+    /// Whether a write has failed, after which the store is read-only.
+    failed: bool,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStore {
+    /// Opens the database at `path`, creating it if needed, and replaces
+    /// its `samples` table with an empty one.
+    ///
+    /// # Errors
+    /// * `PeakMemError::Runtime` - The file isn't a database or can't be
+    ///   created
+    pub fn create(path: &Path) -> Result<Self> {
+        let context = |e: rusqlite::Error| {
+            PeakMemError::Runtime(format!("Sample store {}: {e}", path.display()))
+        };
+        let db = rusqlite::Connection::open(path).map_err(context)?;
+        db.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA synchronous = NORMAL;
+             DROP TABLE IF EXISTS samples;
+             CREATE TABLE samples (
+                 n INTEGER PRIMARY KEY,
+                 rss_bytes INTEGER NOT NULL,
+                 vsz_bytes INTEGER NOT NULL,
+                 timestamp_us INTEGER NOT NULL,
+                 minor_faults INTEGER NOT NULL,
+                 major_faults INTEGER NOT NULL,
+                 thread_count INTEGER NOT NULL,
+                 disk_read_bytes INTEGER NOT NULL,
+                 disk_write_bytes INTEGER NOT NULL,
+                 gpu_bytes INTEGER,
+                 heap_bytes INTEGER
+             );",
+        )
+        .map_err(context)?;
+        Ok(Self {
+            db: std::sync::Mutex::new(db),
+            written: 0,
+            last: None,
+            failed: false,
+        })
+    }
+
+    /// The samples numbered from `first` on, oldest first.
+    fn read_from(&self, first: usize) -> rusqlite::Result<Vec<MemoryUsage>> {
+        let db = self.db.lock().unwrap_or_else(|e| e.into_inner());
+        let mut select = db.prepare_cached(
+            "SELECT rss_bytes, vsz_bytes, timestamp_us, minor_faults, major_faults,
+                    thread_count, disk_read_bytes, disk_write_bytes, gpu_bytes, heap_bytes
+             FROM samples WHERE n >= ? ORDER BY n",
+        )?;
+        let rows = select.query_map([first as i64], |row| {
+            let word = |column| row.get::<_, i64>(column).map(|value| value as u64);
+            let optional = |column| {
+                row.get::<_, Option<i64>>(column)
+                    .map(|value| value.map_or(u64::MAX, |value| value as u64))
+            };
+            Ok(decode([
+                word(0)?,
+                word(1)?,
+                word(2)?,
+                word(3)?,
+                word(4)?,
+                word(5)?,
+                word(6)?,
+                word(7)?,
+                optional(8)?,
+                optional(9)?,
+            ]))
+        })?;
+        rows.collect()
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl SampleStore for SqliteStore {
+    fn push(&mut self, sample: MemoryUsage) {
+        if !self.failed {
+            let record = encode(&sample).map(|value| value as i64);
+            let optional = |value: i64| (value != -1).then_some(value);
+            let inserted = self
+                .db
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .prepare_cached("INSERT INTO samples VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+                .and_then(|mut insert| {
+                    insert.execute(rusqlite::params![
+                        self.written as i64,
+                        record[0],
+                        record[1],
+                        record[2],
+                        record[3],
+                        record[4],
+                        record[5],
+                        record[6],
+                        record[7],
+                        optional(record[8]),
+                        optional(record[9]),
+                    ])
+                });
+            match inserted {
+                Ok(_) => self.written += 1,
+                Err(e) => {
+                    eprintln!("Warning: Failed to write to the sample store: {e}");
+                    self.failed = true;
+                }
+            }
+        }
+        self.last = Some(sample);
+    }
+
+    fn len(&self) -> usize {
+        self.written
+    }
+
+    fn last(&self) -> Option<MemoryUsage> {
+        self.last.clone()
+    }
+
+    fn samples(&self) -> Vec<MemoryUsage> {
+        self.recent(self.written)
+    }
+
+    fn recent(&self, n: usize) -> Vec<MemoryUsage> {
+        self.read_from(self.written.saturating_sub(n))
+            .unwrap_or_else(|e| {
+                eprintln!("Warning: Failed to read the sample store: {e}");
+                Vec::new()
+            })
+    }
+
+    fn retention(&self) -> Option<RetentionSummary> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(rss: u64) -> MemoryUsage {
+        MemoryUsage {
+            gpu_bytes: rss.is_multiple_of(2).then_some(rss * 10),
+            heap_bytes: Some(rss / 2),
+            ..MemoryUsage::new(rss, rss * 2, Timestamp::now())
+        }
+    }
+
+    #[test]
+    fn test_memory_store() {
+        let mut store =
+            MemoryStore::with_retention(TimelineRetention::new(RetentionPolicy::Ring, 2));
+        for rss in 0..5 {
+            store.push(sample(rss));
+        }
+        let kept: Vec<u64> = store.samples().iter().map(|s| s.rss_bytes).collect();
+        assert_eq!(kept, vec![3, 4]);
+        assert_eq!(store.last().unwrap().rss_bytes, 4);
+        assert_eq!(store.recent(5).len(), 2);
+        assert_eq!(store.retention().unwrap().original_samples, 5);
+        assert!(MemoryStore::new().retention().is_none());
+    }
+
+    #[test]
+    fn test_memory_store_spill() {
+        let mut store = MemoryStore::new();
+        assert_eq!(store.spill().unwrap(), 0);
+        for rss in 0..3 {
+            store.push(sample(rss));
+        }
+        assert_eq!(store.spill().unwrap(), 3);
+        assert_eq!(store.last().unwrap().heap_bytes, Some(1));
+        for rss in 3..5 {
+            store.push(sample(rss));
+        }
+        assert_eq!(store.spill().unwrap(), 2);
+        store.push(sample(5));
+
+        // Spilled samples read back as they went in, before the newer ones
+        assert_eq!(store.len(), 6);
+        let kept: Vec<u64> = store.samples().iter().map(|s| s.rss_bytes).collect();
+        assert_eq!(kept, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(store.samples()[2].gpu_bytes, Some(20));
+        assert_eq!(store.samples()[1].gpu_bytes, None);
+        let recent: Vec<u64> = store.recent(3).iter().map(|s| s.rss_bytes).collect();
+        assert_eq!(recent, vec![3, 4, 5]);
+
+        // A capped store doesn't spill
+        let mut capped =
+            MemoryStore::with_retention(TimelineRetention::new(RetentionPolicy::Ring, 2));
+        capped.push(sample(1));
+        assert_eq!(capped.spill().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_delta_store() {
+        let mut store = DeltaStore::new(Box::new(MemoryStore::new()), 10);
+        for rss in [100, 105, 111, 120, 122, 80, 85] {
+            store.push(sample(rss));
+        }
+        // The first, each move past 10 from the last kept, and the latest
+        let kept: Vec<u64> = store.samples().iter().map(|s| s.rss_bytes).collect();
+        assert_eq!(kept, vec![100, 111, 122, 80, 85]);
+        assert_eq!(store.last().unwrap().rss_bytes, 85);
+        let recent: Vec<u64> = store.recent(2).iter().map(|s| s.rss_bytes).collect();
+        assert_eq!(recent, vec![80, 85]);
+        assert_eq!(
+            store.delta(),
+            Some(DeltaSummary {
+                delta_bytes: 10,
+                original_samples: 7,
+                kept_samples: 5,
+            })
+        );
+
+        // A kept sample replaces the pending one
+        store.push(sample(200));
+        assert_eq!(store.len(), 5);
+        assert!(MemoryStore::new().delta().is_none());
+    }
+
+    #[test]
+    fn test_ring_file_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("samples.ring");
+        let mut store = RingFileStore::create(&path, 3).unwrap();
+        assert_eq!(store.len(), 0);
+        assert!(store.last().is_none());
+
+        let samples: Vec<MemoryUsage> = (0..5).map(sample).collect();
+        for sample in &samples {
+            store.push(sample.clone());
+        }
+        assert_eq!(store.len(), 3);
+        let kept = store.samples();
+        assert_eq!(kept[0].rss_bytes, 2);
+        assert_eq!(kept[2].vsz_bytes, 8);
+        assert_eq!(kept[1].gpu_bytes, None);
+        assert_eq!(kept[2].gpu_bytes, Some(40));
+        assert_eq!(kept[2].heap_bytes, Some(2));
+        assert_eq!(store.recent(2)[0].rss_bytes, 3);
+        assert_eq!(
+            kept[2].timestamp.unix_micros(),
+            samples[4].timestamp.unix_micros()
+        );
+        let summary = store.retention().unwrap();
+        assert_eq!((summary.max_samples, summary.original_samples), (3, 5));
+
+        // The samples are in the file as they arrive
+        let file = std::fs::read(&path).unwrap();
+        assert_eq!(&file[..8], MAGIC);
+        assert_eq!(file.len(), (HEADER_WORDS + 3 * RECORD_WORDS) * WORD);
+        assert_eq!(file[WRITTEN * WORD], 5);
+
+        // Too large a ring is refused before the file is touched
+        let too_large = dir.path().join("too-large.ring");
+        for capacity in [MAX_RING_CAPACITY + 1, 1 << 61] {
+            assert!(matches!(
+                RingFileStore::create(&too_large, capacity),
+                Err(PeakMemError::InvalidArgument(_))
+            ));
+        }
+        assert!(!too_large.exists());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("samples.db");
+        let mut store = SqliteStore::create(&path).unwrap();
+        assert_eq!(store.len(), 0);
+        assert!(store.samples().is_empty());
+
+        let samples: Vec<MemoryUsage> = (0..5).map(sample).collect();
+        for sample in &samples {
+            store.push(sample.clone());
+        }
+        assert_eq!(store.len(), 5);
+        assert_eq!(store.last().unwrap().rss_bytes, 4);
+        let kept = store.samples();
+        assert_eq!(kept.len(), 5);
+        assert_eq!(kept[1].gpu_bytes, None);
+        assert_eq!(kept[4].gpu_bytes, Some(40));
+        assert_eq!(kept[4].heap_bytes, Some(2));
+        assert_eq!(
+            kept[4].timestamp.unix_micros(),
+            samples[4].timestamp.unix_micros()
+        );
+        let recent: Vec<u64> = store.recent(2).iter().map(|s| s.rss_bytes).collect();
+        assert_eq!(recent, vec![3, 4]);
+        assert!(store.retention().is_none());
+
+        // The samples are in the database as they arrive, and a new store
+        // starts over
+        let db = rusqlite::Connection::open(&path).unwrap();
+        let count: i64 = db
+            .query_row("SELECT count(*) FROM samples", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 5);
+        assert_eq!(SqliteStore::create(&path).unwrap().samples().len(), 0);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "ring-file".parse::<StoreKind>().unwrap(),
+            StoreKind::RingFile
+        );
+        assert_eq!("sqlite".parse::<StoreKind>().unwrap(), StoreKind::Sqlite);
+        assert!("postgres".parse::<StoreKind>().is_err());
+    }
+}
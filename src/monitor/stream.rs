@@ -0,0 +1,403 @@
+//! Writing samples as they are taken, for `--stream` and `--stream-socket`.
+//!
+//! Each sample becomes a line of JSON, in the form of a `--timeline`
+//! sample, written and flushed as soon as it is taken. Another tool can
+//! follow the run as it goes, and a run that is killed still leaves
+//! every sample taken until then. [`StreamStore`] streams the samples
+//! pushed to the tracker's store; `--system` writes its own. With
+//! `--runs`, every run streams to the same place, one after another.
+//!
+//! A Unix domain socket sends the lines to every client connected at the
+//! time, from the sample after it connects. Sampling doesn't wait for
+//! clients: each has a queue of [`CLIENT_BACKLOG`] lines and is
+//! disconnected once it falls further behind.
+
+use crate::monitor::retention::RetentionSummary;
+use crate::monitor::store::{DeltaSummary, SampleStore};
+use crate::types::{MemoryUsage, Result, Timestamp, TimestampFormat};
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::UnixListener;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Lines queued for a socket client before it is disconnected.
+pub const CLIENT_BACKLOG: usize = 1024;
+
+/// How long the clients of a closed socket may take to read what's left.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+type Writer = Box<dyn Write + Send>;
+
+/// Where the samples go and how their timestamps are written. Clones
+/// write to the same place.
+#[derive(Clone)]
+pub struct SampleStream {
+    sink: Sink,
+    timestamps: Option<TimestampFormat>,
+    /// When streaming started, for relative timestamps.
+    start: Timestamp,
+}
+
+impl SampleStream {
+    /// Streams to standard output for `-`, or else to a file, replaced if
+    /// it exists.
+    ///
+    /// # Errors
+    /// * `PeakMemError::Io` - The file couldn't be created
+    pub fn open(target: &Path, timestamps: Option<TimestampFormat>) -> Result<Self> {
+        let writer: Writer = if target == Path::new("-") {
+            Box::new(io::stdout())
+        } else {
+            Box::new(File::create(target)?)
+        };
+        Ok(Self::new(writer, timestamps))
+    }
+
+    fn new(writer: Writer, timestamps: Option<TimestampFormat>) -> Self {
+        Self {
+            sink: Sink::Writer(Arc::new(Mutex::new(Some(writer)))),
+            timestamps,
+            start: Timestamp::now(),
+        }
+    }
+
+    /// Streams to the clients of a Unix domain socket at `path`. A socket
+    /// left there by a run that was killed is replaced.
+    ///
+    /// # Errors
+    /// * `PeakMemError::Io` - The socket couldn't be created, e.g. because
+    ///   something else is listening on it or a file is in the way
+    pub fn listen(path: &Path, timestamps: Option<TimestampFormat>) -> Result<Self> {
+        let stale = std::fs::symlink_metadata(path)
+            .is_ok_and(|metadata| metadata.file_type().is_socket())
+            && std::os::unix::net::UnixStream::connect(path).is_err();
+        if stale {
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let writers = Arc::new(Mutex::new(Vec::new()));
+        let accept = tokio::spawn(Socket::accept(
+            listener,
+            Arc::clone(&clients),
+            Arc::clone(&writers),
+        ));
+        Ok(Self {
+            sink: Sink::Socket(Arc::new(Socket {
+                file: Arc::new(SocketFile {
+                    path: path.to_path_buf(),
+                    removed: AtomicBool::new(false),
+                }),
+                clients,
+                writers,
+                accept,
+            })),
+            timestamps,
+            start: Timestamp::now(),
+        })
+    }
+
+    /// Writes a sample as a line of JSON. A failed write is reported and
+    /// ends the stream, not the run.
+    pub fn write(&self, sample: &MemoryUsage) {
+        let line = match self.line(sample) {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("Warning: Failed to stream samples: {e}");
+                return;
+            }
+        };
+        match &self.sink {
+            Sink::Writer(writer) => {
+                let mut writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+                let Some(active) = writer.as_mut() else {
+                    return;
+                };
+                if let Err(e) = Self::write_line(active.as_mut(), &line) {
+                    eprintln!("Warning: Failed to stream samples: {e}");
+                    *writer = None;
+                }
+            }
+            Sink::Socket(socket) => socket.send(line.into()),
+        }
+    }
+
+    /// Returns a guard that removes the socket when dropped, if streaming
+    /// to one. Clones of the stream can outlive the run, e.g. in a
+    /// tracker's store, so the socket isn't left to the last of them.
+    pub fn socket_guard(&self) -> Option<SocketGuard> {
+        match &self.sink {
+            Sink::Socket(socket) => Some(SocketGuard(Arc::clone(&socket.file))),
+            Sink::Writer(_) => None,
+        }
+    }
+
+    /// Ends the stream: a socket has its clients read what's left and is
+    /// removed.
+    pub async fn close(&self) {
+        if let Sink::Socket(socket) = &self.sink {
+            socket.close().await;
+        }
+    }
+
+    fn line(&self, sample: &MemoryUsage) -> Result<String> {
+        let mut json = serde_json::to_value(sample)?;
+        if let Some(format) = self.timestamps {
+            json["timestamp"] = format.to_json(sample.timestamp, self.start);
+        }
+        let mut line = serde_json::to_string(&json)?;
+        line.push('\n');
+        Ok(line)
+    }
+
+    fn write_line(writer: &mut dyn Write, line: &str) -> io::Result<()> {
+        writer.write_all(line.as_bytes())?;
+        writer.flush()
+    }
+}
+
+#[derive(Clone)]
+enum Sink {
+    /// `None` once a write failed, so it's only reported once.
+    Writer(Arc<Mutex<Option<Writer>>>),
+    Socket(Arc<Socket>),
+}
+
+/// The file of a listening socket.
+struct SocketFile {
+    path: PathBuf,
+    /// Whether the file was removed, so that a socket another process has
+    /// bound there since isn't.
+    removed: AtomicBool,
+}
+
+impl SocketFile {
+    fn remove(&self) {
+        if !self.removed.swap(true, Ordering::SeqCst) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Removes the file of a stream's socket when dropped.
+pub struct SocketGuard(Arc<SocketFile>);
+
+impl Drop for SocketGuard {
+    fn drop(&mut self) {
+        self.0.remove();
+    }
+}
+
+/// A listening socket and its clients.
+struct Socket {
+    file: Arc<SocketFile>,
+    /// The queue of each client.
+    clients: Arc<Mutex<Vec<mpsc::Sender<Arc<str>>>>>,
+    /// The tasks writing each client's queue to it.
+    writers: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    accept: JoinHandle<()>,
+}
+
+impl Socket {
+    async fn accept(
+        listener: UnixListener,
+        clients: Arc<Mutex<Vec<mpsc::Sender<Arc<str>>>>>,
+        writers: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    ) {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let (sender, mut queue) = mpsc::channel::<Arc<str>>(CLIENT_BACKLOG);
+            let writer = tokio::spawn(async move {
+                while let Some(line) = queue.recv().await {
+                    if stream.write_all(line.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            });
+            lock(&clients).push(sender);
+            // Those of clients that have gone have ended
+            let mut writers = lock(&writers);
+            writers.retain(|writer| !writer.is_finished());
+            writers.push(writer);
+        }
+    }
+
+    /// Queues a line for every client, disconnecting those that have
+    /// gone or fallen behind.
+    fn send(&self, line: Arc<str>) {
+        lock(&self.clients).retain(|client| client.try_send(Arc::clone(&line)).is_ok());
+    }
+
+    async fn close(&self) {
+        self.accept.abort();
+        // The writers end once their queues are empty
+        lock(&self.clients).clear();
+        let writers = std::mem::take(&mut *lock(&self.writers));
+        let _ = tokio::time::timeout(DRAIN_TIMEOUT, async {
+            for writer in writers {
+                let _ = writer.await;
+            }
+        })
+        .await;
+        self.file.remove();
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        self.accept.abort();
+        self.file.remove();
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A store that streams each sample before keeping it in another.
+pub struct StreamStore {
+    inner: Box<dyn SampleStore>,
+    streams: Vec<SampleStream>,
+}
+
+impl StreamStore {
+    /// Streams the samples pushed to `inner`.
+    pub fn new(inner: Box<dyn SampleStore>, streams: Vec<SampleStream>) -> Self {
+        Self { inner, streams }
+    }
+}
+
+impl SampleStore for StreamStore {
+    fn push(&mut self, sample: MemoryUsage) {
+        for stream in &self.streams {
+            stream.write(&sample);
+        }
+        self.inner.push(sample);
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn last(&self) -> Option<MemoryUsage> {
+        self.inner.last()
+    }
+
+    fn samples(&self) -> Vec<MemoryUsage> {
+        self.inner.samples()
+    }
+
+    fn recent(&self, n: usize) -> Vec<MemoryUsage> {
+        self.inner.recent(n)
+    }
+
+    fn retention(&self) -> Option<RetentionSummary> {
+        self.inner.retention()
+    }
+
+    fn spill(&mut self) -> Result<usize> {
+        self.inner.spill()
+    }
+
+    fn delta(&self) -> Option<DeltaSummary> {
+        self.inner.delta()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::store::MemoryStore;
+
+    /// Collects what is written, to be read while the stream holds it.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Write::write(&mut *self.0.lock().unwrap(), buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_stream_store() {
+        let written = Shared::default();
+        let stream = SampleStream::new(Box::new(written.clone()), Some(TimestampFormat::Unix));
+        let mut store = StreamStore::new(Box::new(MemoryStore::new()), vec![stream]);
+        for rss in [1024, 4096] {
+            store.push(MemoryUsage::new(
+                rss,
+                0,
+                Timestamp::from_unix_micros(1_500_000),
+            ));
+        }
+        assert_eq!(store.len(), 2);
+
+        let written = String::from_utf8(written.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> = written
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["rss_bytes"], 4096);
+        assert_eq!(lines[0]["timestamp"], 1.5);
+    }
+
+    #[tokio::test]
+    async fn test_socket() {
+        use tokio::io::AsyncBufReadExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("samples.sock");
+        let stream = SampleStream::listen(&path, None).unwrap();
+        let client = tokio::net::UnixStream::connect(&path).await.unwrap();
+        // Until the client is accepted, samples only go to the others
+        while lock(match &stream.sink {
+            Sink::Socket(socket) => &socket.clients,
+            Sink::Writer(_) => unreachable!(),
+        })
+        .is_empty()
+        {
+            tokio::task::yield_now().await;
+        }
+
+        for rss in [1024, 4096] {
+            stream.write(&MemoryUsage::new(rss, 0, Timestamp::now()));
+        }
+        stream.close().await;
+        assert!(!path.exists());
+
+        let mut lines = tokio::io::BufReader::new(client).lines();
+        let mut rss = Vec::new();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            let sample: serde_json::Value = serde_json::from_str(&line).unwrap();
+            rss.push(sample["rss_bytes"].as_u64().unwrap());
+        }
+        assert_eq!(rss, vec![1024, 4096]);
+    }
+
+    #[tokio::test]
+    async fn test_socket_guard() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("samples.sock");
+        let stream = SampleStream::listen(&path, None).unwrap();
+        assert!(path.exists());
+
+        // A clone of the stream is left, as in a tracker's store
+        let clone = stream.clone();
+        drop(stream.socket_guard().unwrap());
+        assert!(!path.exists());
+        drop((stream, clone));
+    }
+}
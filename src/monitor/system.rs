@@ -0,0 +1,141 @@
+//! System-wide memory readings for `--system`.
+//!
+//! On Linux, `/proc/meminfo` reports the machine's total, available and
+//! swap memory. Other platforms aren't supported yet, so readings are
+//! unavailable there.
+
+use serde::{Deserialize, Serialize};
+
+/// A snapshot of the machine's memory (in bytes).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SystemMemory {
+    /// Physical memory installed.
+    pub total_bytes: u64,
+    /// Memory available to new allocations without swapping, counting
+    /// reclaimable caches.
+    pub available_bytes: u64,
+    /// Swap space configured.
+    pub swap_total_bytes: u64,
+    /// Swap space in use.
+    pub swap_used_bytes: u64,
+}
+
+impl SystemMemory {
+    /// Memory in use: whatever isn't available.
+    pub fn used_bytes(&self) -> u64 {
+        self.total_bytes.saturating_sub(self.available_bytes)
+    }
+}
+
+/// Extremes of the machine's memory over a `--system` session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SystemPeaks {
+    /// Physical memory installed.
+    pub total_bytes: u64,
+    /// Highest memory in use.
+    pub peak_used_bytes: u64,
+    /// Lowest memory available.
+    pub min_available_bytes: u64,
+    /// Highest swap in use.
+    pub peak_swap_used_bytes: u64,
+}
+
+impl SystemPeaks {
+    /// Starts tracking from a first reading.
+    pub fn new(memory: &SystemMemory) -> Self {
+        Self {
+            total_bytes: memory.total_bytes,
+            peak_used_bytes: memory.used_bytes(),
+            min_available_bytes: memory.available_bytes,
+            peak_swap_used_bytes: memory.swap_used_bytes,
+        }
+    }
+
+    /// Takes another reading into account.
+    pub fn record(&mut self, memory: &SystemMemory) {
+        self.peak_used_bytes = self.peak_used_bytes.max(memory.used_bytes());
+        self.min_available_bytes = self.min_available_bytes.min(memory.available_bytes);
+        self.peak_swap_used_bytes = self.peak_swap_used_bytes.max(memory.swap_used_bytes);
+    }
+}
+
+/// Reads the machine's current memory.
+///
+/// # Returns
+/// * `None` if unavailable (non-Linux, or `/proc` isn't mounted)
+pub fn read_system_memory() -> Option<SystemMemory> {
+    #[cfg(target_os = "linux")]
+    {
+        let contents = std::fs::read_to_string("/proc/meminfo").ok()?;
+        parse_meminfo(&contents)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Parses the contents of `/proc/meminfo`, whose sizes are in KiB.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_meminfo(contents: &str) -> Option<SystemMemory> {
+    let field = |name: &str| -> Option<u64> {
+        contents.lines().find_map(|line| {
+            let value = line.strip_prefix(name)?.strip_prefix(':')?;
+            let kib: u64 = value.split_whitespace().next()?.parse().ok()?;
+            Some(kib * 1024)
+        })
+    };
+
+    let swap_total_bytes = field("SwapTotal").unwrap_or(0);
+    Some(SystemMemory {
+        total_bytes: field("MemTotal")?,
+        // Kernels before 3.14 don't estimate available memory
+        available_bytes: field("MemAvailable").or_else(|| field("MemFree"))?,
+        swap_total_bytes,
+        swap_used_bytes: swap_total_bytes.saturating_sub(field("SwapFree").unwrap_or(0)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_meminfo() {
+        let meminfo = "\
+MemTotal:       16000000 kB
+MemFree:         2000000 kB
+MemAvailable:    6000000 kB
+SwapTotal:       4000000 kB
+SwapFree:        3000000 kB
+";
+        let memory = parse_meminfo(meminfo).unwrap();
+        assert_eq!(memory.total_bytes, 16_000_000 * 1024);
+        assert_eq!(memory.used_bytes(), 10_000_000 * 1024);
+        assert_eq!(memory.swap_used_bytes, 1_000_000 * 1024);
+
+        // No MemAvailable, and no swap
+        let memory = parse_meminfo("MemTotal: 1000 kB\nMemFree: 400 kB\n").unwrap();
+        assert_eq!(memory.used_bytes(), 600 * 1024);
+        assert_eq!(memory.swap_total_bytes, 0);
+
+        assert_eq!(parse_meminfo("garbage"), None);
+    }
+
+    #[test]
+    fn test_system_peaks() {
+        let reading = |available_bytes, swap_used_bytes| SystemMemory {
+            total_bytes: 100,
+            available_bytes,
+            swap_total_bytes: 50,
+            swap_used_bytes,
+        };
+        let mut peaks = SystemPeaks::new(&reading(60, 5));
+        peaks.record(&reading(20, 1));
+        peaks.record(&reading(70, 9));
+        assert_eq!(peaks.peak_used_bytes, 80);
+        assert_eq!(peaks.min_available_bytes, 20);
+        assert_eq!(peaks.peak_swap_used_bytes, 9);
+    }
+}
@@ -0,0 +1,419 @@
+//! CPU frequency, thermal throttling and energy during a run, for
+//! `--thermal`.
+//!
+//! A CPU that overheats slows itself down, and a run that was throttled
+//! takes longer than one that wasn't without the code having changed, so
+//! its duration and CPU time shouldn't be compared as if it hadn't been.
+//! On Linux sysfs exposes what's needed to tell:
+//!
+//! * `cpufreq` - the current and highest frequency of each CPU, sampled during
+//!   the run
+//! * `thermal_throttle` - how often each core and package was throttled, read
+//!   at the start and the end (x86 only)
+//! * `thermal` zones - temperatures, sampled during the run
+//! * `powercap` - the energy RAPL domains consumed, read at the start and the
+//!   end; often only readable by root
+//!
+//! Whatever isn't available is left out. Other platforms expose none of
+//! it, so nothing is recorded there.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration};
+
+/// Sampling frequency and temperature more often than this doesn't tell
+/// more about throttling.
+const MIN_INTERVAL_MS: u64 = 100;
+
+/// CPU frequency, throttling and energy over a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ThermalContext {
+    /// Thermal throttling events the cores and packages counted during the
+    /// run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub throttle_events: Option<u64>,
+    /// Mean frequency of the CPUs over the run (in MHz).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mean_cpu_freq_mhz: Option<f64>,
+    /// Highest frequency the CPUs can run at (in MHz).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cpu_freq_mhz: Option<f64>,
+    /// Highest temperature of any thermal zone (in degrees Celsius).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peak_temperature_celsius: Option<f64>,
+    /// Energy the RAPL domains consumed during the run (in joules).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub energy_joules: Option<f64>,
+}
+
+impl ThermalContext {
+    /// Whether the CPUs were throttled during the run, making its duration
+    /// and CPU time less trustworthy.
+    pub fn throttled(&self) -> bool {
+        self.throttle_events.is_some_and(|events| events > 0)
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == ThermalContext::default()
+    }
+}
+
+/// The sysfs files read, below a root that tests can replace.
+#[derive(Debug, Clone)]
+struct Sysfs {
+    root: PathBuf,
+}
+
+/// An energy counter of a RAPL domain.
+#[derive(Debug, Clone, PartialEq)]
+struct EnergyCounter {
+    path: PathBuf,
+    energy_uj: u64,
+    /// The counter wraps around to zero after this.
+    max_energy_range_uj: u64,
+}
+
+impl Sysfs {
+    fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Entries of a directory whose names start with `prefix`, sorted.
+    fn entries(&self, dir: &str, prefix: &str) -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(self.root.join(dir)) else {
+            return Vec::new();
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(prefix))
+            .map(|entry| entry.path())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// The `cpuN` directories, leaving out `cpufreq`, `cpuidle` and the
+    /// like.
+    fn cpus(&self) -> Vec<PathBuf> {
+        self.entries("devices/system/cpu", "cpu")
+            .into_iter()
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str()?.strip_prefix("cpu"))
+                    .is_some_and(|id| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()))
+            })
+            .collect()
+    }
+
+    /// Throttling events so far: those of each core, and those of each
+    /// package once, although every CPU in it reports them.
+    fn throttle_count(&self) -> Option<u64> {
+        let mut total = None;
+        let mut packages = HashSet::new();
+        for cpu in self.cpus() {
+            let throttle = cpu.join("thermal_throttle");
+            if let Some(count) = read_u64(&throttle.join("core_throttle_count")) {
+                *total.get_or_insert(0) += count;
+            }
+            let package = read_u64(&cpu.join("topology/physical_package_id"));
+            if packages.insert(package) {
+                if let Some(count) = read_u64(&throttle.join("package_throttle_count")) {
+                    *total.get_or_insert(0) += count;
+                }
+            }
+        }
+        total
+    }
+
+    /// The mean current and the highest frequency of the CPUs, in kHz.
+    fn cpu_freq_khz(&self) -> Option<(f64, u64)> {
+        let (mut sum, mut count, mut max) = (0u64, 0u64, 0u64);
+        for cpu in self.cpus() {
+            let cpufreq = cpu.join("cpufreq");
+            if let Some(current) = read_u64(&cpufreq.join("scaling_cur_freq")) {
+                sum += current;
+                count += 1;
+            }
+            if let Some(highest) = read_u64(&cpufreq.join("cpuinfo_max_freq")) {
+                max = max.max(highest);
+            }
+        }
+        (count > 0).then(|| (sum as f64 / count as f64, max))
+    }
+
+    /// The highest temperature of any thermal zone, in millidegrees
+    /// Celsius.
+    fn temperature(&self) -> Option<i64> {
+        self.entries("class/thermal", "thermal_zone")
+            .iter()
+            .filter_map(|zone| {
+                fs::read_to_string(zone.join("temp"))
+                    .ok()?
+                    .trim()
+                    .parse()
+                    .ok()
+            })
+            .max()
+    }
+
+    /// The counters of the top-level RAPL domains, such as
+    /// `intel-rapl:0`; their subdomains (`intel-rapl:0:0`) are part of
+    /// them.
+    fn energy_counters(&self) -> Vec<EnergyCounter> {
+        self.entries("class/powercap", "intel-rapl:")
+            .into_iter()
+            .filter(|path| {
+                path.file_name()
+                    .is_some_and(|name| name.to_string_lossy().matches(':').count() == 1)
+            })
+            .filter_map(|path| {
+                Some(EnergyCounter {
+                    energy_uj: read_u64(&path.join("energy_uj"))?,
+                    max_energy_range_uj: read_u64(&path.join("max_energy_range_uj"))
+                        .unwrap_or(u64::MAX),
+                    path,
+                })
+            })
+            .collect()
+    }
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Energy consumed between two readings of the same counters, in joules.
+fn energy_between(start: &[EnergyCounter], end: &[EnergyCounter]) -> Option<f64> {
+    if start.is_empty() {
+        return None;
+    }
+    let microjoules: u64 = start
+        .iter()
+        .filter_map(|before| {
+            let after = end.iter().find(|after| after.path == before.path)?;
+            Some(if after.energy_uj >= before.energy_uj {
+                after.energy_uj - before.energy_uj
+            } else {
+                // Wrapped around
+                before.max_energy_range_uj - before.energy_uj + after.energy_uj
+            })
+        })
+        .sum();
+    Some(microjoules as f64 / 1e6)
+}
+
+/// What the samples taken during the run saw.
+#[derive(Debug, Default)]
+struct Samples {
+    freq_khz_sum: f64,
+    freq_samples: u64,
+    max_freq_khz: u64,
+    peak_temperature: Option<i64>,
+}
+
+impl Samples {
+    fn take(&mut self, sysfs: &Sysfs) {
+        if let Some((mean, max)) = sysfs.cpu_freq_khz() {
+            self.freq_khz_sum += mean;
+            self.freq_samples += 1;
+            self.max_freq_khz = self.max_freq_khz.max(max);
+        }
+        if let Some(temperature) = sysfs.temperature() {
+            self.peak_temperature = Some(
+                self.peak_temperature
+                    .map_or(temperature, |peak| peak.max(temperature)),
+            );
+        }
+    }
+}
+
+/// Records the thermal context of a run from its start until
+/// [`ThermalRecorder::finish`].
+pub struct ThermalRecorder {
+    sysfs: Sysfs,
+    throttle_start: Option<u64>,
+    energy_start: Vec<EnergyCounter>,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<Samples>,
+}
+
+impl ThermalRecorder {
+    /// Reads the counters and starts sampling every `interval_ms`, or
+    /// every 100ms if that's longer.
+    pub fn start(interval_ms: u64) -> Self {
+        Self::start_at(Sysfs::new("/sys"), interval_ms)
+    }
+
+    fn start_at(sysfs: Sysfs, interval_ms: u64) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let (sysfs, stop) = (sysfs.clone(), Arc::clone(&stop));
+            tokio::spawn(async move {
+                let mut samples = Samples::default();
+                let mut interval =
+                    time::interval(Duration::from_millis(interval_ms.max(MIN_INTERVAL_MS)));
+                interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+                loop {
+                    interval.tick().await;
+                    samples.take(&sysfs);
+                    if stop.load(Ordering::SeqCst) {
+                        return samples;
+                    }
+                }
+            })
+        };
+        Self {
+            throttle_start: sysfs.throttle_count(),
+            energy_start: sysfs.energy_counters(),
+            sysfs,
+            stop,
+            handle,
+        }
+    }
+
+    /// Stops sampling and reads the counters again.
+    ///
+    /// # Returns
+    /// * `None` if nothing could be read on this machine
+    pub async fn finish(self) -> Option<ThermalContext> {
+        self.stop.store(true, Ordering::SeqCst);
+        // The sampling task doesn't panic; if it did, only its samples
+        // are lost
+        let samples = self.handle.await.unwrap_or_default();
+
+        let throttle_events = match (self.throttle_start, self.sysfs.throttle_count()) {
+            (Some(start), Some(end)) => Some(end.saturating_sub(start)),
+            _ => None,
+        };
+        let context = ThermalContext {
+            throttle_events,
+            mean_cpu_freq_mhz: (samples.freq_samples > 0)
+                .then(|| samples.freq_khz_sum / samples.freq_samples as f64 / 1000.0),
+            max_cpu_freq_mhz: (samples.max_freq_khz > 0)
+                .then(|| samples.max_freq_khz as f64 / 1000.0),
+            peak_temperature_celsius: samples
+                .peak_temperature
+                .map(|millidegrees| millidegrees as f64 / 1000.0),
+            energy_joules: energy_between(&self.energy_start, &self.sysfs.energy_counters()),
+        };
+        (!context.is_empty()).then_some(context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(root: &Path, path: &str, contents: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    /// Two CPUs in one package, each throttled `core` times, and the
+    /// package `package` times.
+    fn fake_sysfs(root: &Path, core: u64, package: u64, energy_uj: u64) {
+        for cpu in ["cpu0", "cpu1"] {
+            let dir = format!("devices/system/cpu/{cpu}");
+            write(
+                root,
+                &format!("{dir}/cpufreq/scaling_cur_freq"),
+                "2000000\n",
+            );
+            write(
+                root,
+                &format!("{dir}/cpufreq/cpuinfo_max_freq"),
+                "4000000\n",
+            );
+            write(root, &format!("{dir}/topology/physical_package_id"), "0\n");
+            let throttle = format!("{dir}/thermal_throttle");
+            write(
+                root,
+                &format!("{throttle}/core_throttle_count"),
+                &core.to_string(),
+            );
+            write(
+                root,
+                &format!("{throttle}/package_throttle_count"),
+                &package.to_string(),
+            );
+        }
+        fs::create_dir_all(root.join("devices/system/cpu/cpufreq")).unwrap();
+        write(root, "class/thermal/thermal_zone0/temp", "45000\n");
+        write(root, "class/thermal/thermal_zone1/temp", "71500\n");
+        write(
+            root,
+            "class/powercap/intel-rapl:0/energy_uj",
+            &energy_uj.to_string(),
+        );
+        write(
+            root,
+            "class/powercap/intel-rapl:0/max_energy_range_uj",
+            "1000000000",
+        );
+        write(root, "class/powercap/intel-rapl:0:0/energy_uj", "5");
+    }
+
+    #[test]
+    fn test_sysfs_readings() {
+        let dir = tempfile::tempdir().unwrap();
+        fake_sysfs(dir.path(), 3, 2, 100);
+        let sysfs = Sysfs::new(dir.path());
+
+        assert_eq!(sysfs.cpus().len(), 2);
+        // The package is counted once
+        assert_eq!(sysfs.throttle_count(), Some(8));
+        assert_eq!(sysfs.cpu_freq_khz(), Some((2_000_000.0, 4_000_000)));
+        assert_eq!(sysfs.temperature(), Some(71_500));
+        let counters = sysfs.energy_counters();
+        assert_eq!(counters.len(), 1);
+        assert_eq!(counters[0].energy_uj, 100);
+
+        let empty = Sysfs::new(dir.path().join("missing"));
+        assert_eq!(empty.throttle_count(), None);
+        assert_eq!(empty.cpu_freq_khz(), None);
+        assert!(empty.energy_counters().is_empty());
+    }
+
+    #[test]
+    fn test_energy_wraps_around() {
+        let counter = |energy_uj| EnergyCounter {
+            path: PathBuf::from("intel-rapl:0"),
+            energy_uj,
+            max_energy_range_uj: 1_000_000,
+        };
+        assert_eq!(
+            energy_between(&[counter(200_000)], &[counter(700_000)]),
+            Some(0.5)
+        );
+        assert_eq!(
+            energy_between(&[counter(900_000)], &[counter(400_000)]),
+            Some(0.5)
+        );
+        assert_eq!(energy_between(&[], &[counter(1)]), None);
+    }
+
+    #[tokio::test]
+    async fn test_recorder() {
+        let dir = tempfile::tempdir().unwrap();
+        fake_sysfs(dir.path(), 3, 2, 1_000_000);
+        let recorder = ThermalRecorder::start_at(Sysfs::new(dir.path()), 10);
+        fake_sysfs(dir.path(), 4, 3, 3_500_000);
+        let context = recorder.finish().await.unwrap();
+
+        assert_eq!(context.throttle_events, Some(3));
+        assert!(context.throttled());
+        assert_eq!(context.mean_cpu_freq_mhz, Some(2000.0));
+        assert_eq!(context.max_cpu_freq_mhz, Some(4000.0));
+        assert_eq!(context.peak_temperature_celsius, Some(71.5));
+        assert_eq!(context.energy_joules, Some(2.5));
+
+        let recorder = ThermalRecorder::start_at(Sysfs::new(dir.path().join("missing")), 10);
+        assert_eq!(recorder.finish().await, None);
+    }
+}
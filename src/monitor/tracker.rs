@@ -1,32 +1,129 @@
 //! Continuous memory tracking with peak detection.
 //!
 //! This module provides the `MemoryTracker` which continuously monitors
-//! a process's memory usage and maintains peak values.
+//! a process's memory usage and maintains peak values. A tracker may watch
+//! several independent processes at once; their samples are summed, so
+//! the peak is that of everything together.
+//!
+//! peak-mem leaves itself out: attached to an ancestor of its own, such as
+//! the shell it runs in, it would otherwise count its own memory and that
+//! of the command it runs twice.
 
+use crate::monitor::aggregate::Aggregate;
+use crate::monitor::cgroup::CgroupMemory;
+use crate::monitor::fds::{self, FdSummary};
+use crate::monitor::gpu::{self, GpuMonitor};
+use crate::monitor::jobs::{JobKind, JobPeak, JobPeaks};
+use crate::monitor::lifetimes::ProcessLifetimes;
+use crate::monitor::pause::{PauseSwitch, SamplingPause};
+use crate::monitor::pool::{PoolStats, PoolSummary};
+use crate::monitor::pressure::{self, MemoryPressure};
+use crate::monitor::retention::RetentionSummary;
+use crate::monitor::runtime::HeapProbe;
+use crate::monitor::snapshots::{ProcessSnapshot, ProcessSnapshots};
+use crate::monitor::store::{DeltaSummary, MemoryStore, SampleStore, SharedStore};
+use crate::monitor::system::{self, SystemMemory};
+use crate::monitor::warnings::Warnings;
 use crate::monitor::{MemoryMonitor, SharedMonitor};
-use crate::types::{MemoryUsage, ProcessMemoryInfo, Result};
+use crate::types::{
+    ByteSize, IntervalAdjustment, MemoryUsage, PeakMemError, ProcessMemoryInfo, Result,
+    SamplingDiagnostics, TargetPeak, Timestamp,
+};
+use std::collections::{BTreeSet, HashSet};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::RwLock;
 use tokio::time;
 
+/// A process tree shared by the tracker and whoever reads it live.
+pub type SharedTree = Arc<RwLock<Option<ProcessMemoryInfo>>>;
+
+/// PSI `some avg10` (percent) below which the normal interval resumes.
+/// Kept well below [`pressure::HIGH_PRESSURE`] so the interval doesn't
+/// flap.
+const PRESSURE_LOW: f64 = 5.0;
+/// How much the sampling interval is stretched while under pressure.
+const BACKOFF_FACTOR: u64 = 4;
+
 /// Tracks memory usage over time for a process and its children.
 ///
 /// The tracker runs in a background task, periodically sampling memory usage
 /// and updating peak values using lock-free atomic operations.
 pub struct MemoryTracker {
     monitor: SharedMonitor,
-    pid: u32,
+    /// Processes to track, each with its tree unless children are
+    /// excluded. The first is the main one.
+    pids: Vec<u32>,
+    /// Peaks of each tracked process on its own, in the order of `pids`.
+    target_peaks: Arc<Vec<PeakPair>>,
     /// Peak RSS value observed (in bytes), updated atomically.
     pub peak_rss: Arc<AtomicU64>,
     /// Peak VSZ value observed (in bytes), updated atomically.
     pub peak_vsz: Arc<AtomicU64>,
-    timeline: Arc<RwLock<Vec<MemoryUsage>>>,
+    /// RSS of the latest sample (in bytes), whether the timeline kept it
+    /// or not.
+    pub current_rss: Arc<AtomicU64>,
+    /// VSZ of the latest sample (in bytes).
+    pub current_vsz: Arc<AtomicU64>,
+    /// Where the timeline is kept.
+    timeline: SharedStore,
     running: Arc<AtomicBool>,
     track_children: bool,
+    /// How the memory of each tracked tree is combined.
+    aggregate: Aggregate,
     sample_count: Arc<AtomicU64>,
+    /// Ticks skipped because a sample took longer than the interval.
+    skipped_ticks: Arc<AtomicU64>,
+    /// Samples that left out a process that couldn't be read.
+    unreadable_samples: Arc<AtomicU64>,
     peak_process_tree: Arc<RwLock<Option<ProcessMemoryInfo>>>,
+    /// The RSS of each process of the main tree over the run.
+    lifetimes: Arc<RwLock<ProcessLifetimes>>,
+    counters: Arc<SampleCounters>,
+    /// Whether to sample less often under system memory pressure.
+    pressure_backoff: bool,
+    /// Interval adjustments made by pressure backoff.
+    diagnostics: Arc<RwLock<SamplingDiagnostics>>,
+    /// Highest system memory pressure observed, if PSI is available.
+    peak_pressure: Arc<RwLock<Option<MemoryPressure>>>,
+    /// The machine's memory when the least of it was available, if known.
+    lowest_system: Arc<RwLock<Option<SystemMemory>>>,
+    /// GPU memory source, if GPU tracking is enabled.
+    gpu: Option<Arc<GpuMonitor>>,
+    /// Peak GPU memory observed for the tree (in bytes).
+    peak_gpu: Arc<AtomicU64>,
+    /// Managed-heap probes, if `--runtime-heap` was given.
+    heap: Option<Arc<HeapProbe>>,
+    /// Peak managed heap reported by the tree (in bytes).
+    peak_heap: Arc<RwLock<Option<u64>>>,
+    /// Names of every process seen in the tree.
+    observed_processes: Arc<RwLock<BTreeSet<String>>>,
+    /// Whether sampling is paused, and the pauses so far.
+    pause: PauseSwitch,
+    /// Whether to count open file descriptors at each new peak.
+    fd_summary: bool,
+    /// Open file descriptors of the tree at peak RSS.
+    peak_fds: Arc<RwLock<Option<FdSummary>>>,
+    /// Cgroup whose memory charge replaces the summed RSS, if any.
+    cgroup: Option<Arc<CgroupMemory>>,
+    /// Peak RSS of each compiler job, for each kind of job attributed.
+    job_peaks: Arc<RwLock<Vec<JobPeaks>>>,
+    /// Footprint of a worker pool in the tree, if one is measured.
+    pool: Option<Arc<RwLock<PoolStats>>>,
+    /// The processes of the tree in each sample, if kept.
+    process_snapshots: Option<Arc<RwLock<ProcessSnapshots>>>,
+    /// The main tree of the latest sample, if kept.
+    live_tree: Option<SharedTree>,
+    /// Rate limits the warnings raised on each sample.
+    warnings: Arc<Warnings>,
+    /// Whether peak-mem was found in a tracked tree, so that it's only
+    /// reported once.
+    found_self: Arc<AtomicBool>,
+    /// How much memory peak-mem itself may use, if capped.
+    self_memory_cap: Option<ByteSize>,
+    /// Whether to stop after the first sample.
+    single_sample: bool,
 }
 
 impl MemoryTracker {
@@ -39,15 +136,153 @@ impl MemoryTracker {
     pub fn new(monitor: Box<dyn MemoryMonitor>, pid: u32, track_children: bool) -> Self {
         Self {
             monitor: Arc::new(tokio::sync::Mutex::new(monitor)),
-            pid,
+            pids: vec![pid],
+            target_peaks: Arc::new(vec![PeakPair::default()]),
             peak_rss: Arc::new(AtomicU64::new(0)),
             peak_vsz: Arc::new(AtomicU64::new(0)),
-            timeline: Arc::new(RwLock::new(Vec::new())),
+            current_rss: Arc::new(AtomicU64::new(0)),
+            current_vsz: Arc::new(AtomicU64::new(0)),
+            timeline: Arc::new(RwLock::new(Box::new(MemoryStore::new()))),
             running: Arc::new(AtomicBool::new(false)),
             track_children,
+            aggregate: Aggregate::default(),
             sample_count: Arc::new(AtomicU64::new(0)),
+            skipped_ticks: Arc::new(AtomicU64::new(0)),
+            unreadable_samples: Arc::new(AtomicU64::new(0)),
             peak_process_tree: Arc::new(RwLock::new(None)),
+            lifetimes: Arc::new(RwLock::new(ProcessLifetimes::default())),
+            counters: Arc::new(SampleCounters::default()),
+            pressure_backoff: false,
+            diagnostics: Arc::new(RwLock::new(SamplingDiagnostics::default())),
+            peak_pressure: Arc::new(RwLock::new(None)),
+            lowest_system: Arc::new(RwLock::new(None)),
+            gpu: None,
+            peak_gpu: Arc::new(AtomicU64::new(0)),
+            heap: None,
+            peak_heap: Arc::new(RwLock::new(None)),
+            observed_processes: Arc::new(RwLock::new(BTreeSet::new())),
+            pause: PauseSwitch::default(),
+            fd_summary: false,
+            peak_fds: Arc::new(RwLock::new(None)),
+            cgroup: None,
+            job_peaks: Arc::new(RwLock::new(Vec::new())),
+            pool: None,
+            process_snapshots: None,
+            live_tree: None,
+            warnings: Arc::new(Warnings::default()),
+            found_self: Arc::new(AtomicBool::new(false)),
+            self_memory_cap: None,
+            single_sample: false,
+        }
+    }
+
+    /// Also tracks other, unrelated processes alongside the main one.
+    ///
+    /// Sampling continues until every tracked process has exited or
+    /// `stop()` is called.
+    pub fn with_additional_pids(mut self, pids: &[u32]) -> Self {
+        self.pids.extend_from_slice(pids);
+        self.target_peaks = Arc::new(self.pids.iter().map(|_| PeakPair::default()).collect());
+        self
+    }
+
+    /// Combines the memory of each tracked tree in another way than
+    /// summing it. Has no effect without child tracking.
+    pub fn with_aggregate(mut self, aggregate: Aggregate) -> Self {
+        self.aggregate = aggregate;
+        self
+    }
+
+    /// Enables backing off the sampling rate under system memory pressure.
+    ///
+    /// While PSI reports high memory pressure, the interval is stretched so
+    /// that peak-mem adds as little as possible to the contention it is
+    /// measuring. It returns to normal once pressure subsides. Has no
+    /// effect where PSI is unavailable.
+    pub fn with_pressure_backoff(mut self, enabled: bool) -> Self {
+        self.pressure_backoff = enabled;
+        self
+    }
+
+    /// Enables GPU memory tracking: each sample also records the GPU
+    /// memory used by the processes it covers.
+    pub fn with_gpu(mut self, gpu: GpuMonitor) -> Self {
+        self.gpu = Some(Arc::new(gpu));
+        self
+    }
+
+    /// Also records the managed heap of the tree's runtimes on each
+    /// sample.
+    pub fn with_heap(mut self, heap: Arc<HeapProbe>) -> Self {
+        self.heap = Some(heap);
+        self
+    }
+
+    /// Enables counting the tree's open file descriptors by type whenever
+    /// a new peak RSS is reached.
+    pub fn with_fd_summary(mut self, enabled: bool) -> Self {
+        self.fd_summary = enabled;
+        self
+    }
+
+    /// Enables recording the peak RSS of each job of a kind (crate or
+    /// source file) compiled by compiler processes in the tree. Needs
+    /// child tracking.
+    pub fn with_job_attribution(mut self, kind: JobKind) -> Self {
+        // Not yet shared with the sampling task
+        if let Some(jobs) = Arc::get_mut(&mut self.job_peaks) {
+            jobs.get_mut().push(JobPeaks::new(kind));
         }
+        self
+    }
+
+    /// Enables measuring the worker pool matched by `stats` in the main
+    /// tree separately before and after it settles. Needs child tracking.
+    pub fn with_pool(mut self, stats: PoolStats) -> Self {
+        self.pool = Some(Arc::new(RwLock::new(stats)));
+        self
+    }
+
+    /// Keeps the RSS of each process of the main tree in each sample.
+    pub fn with_process_snapshots(mut self) -> Self {
+        self.process_snapshots = Some(Arc::new(RwLock::new(ProcessSnapshots::default())));
+        self
+    }
+
+    /// Keeps the main tree of the latest sample, for a live view of it.
+    pub fn with_live_tree(mut self) -> Self {
+        self.live_tree = Some(Arc::new(RwLock::new(None)));
+        self
+    }
+
+    /// Keeps the timeline in `store` instead of an uncapped vector.
+    pub fn with_sample_store(mut self, store: Box<dyn SampleStore>) -> Self {
+        self.timeline = Arc::new(RwLock::new(store));
+        self
+    }
+
+    /// Measures memory by a cgroup's charge instead of the summed RSS of
+    /// the tracked processes, e.g. for a container. The per-process peaks
+    /// are still RSS.
+    pub fn with_cgroup(mut self, cgroup: CgroupMemory) -> Self {
+        self.cgroup = Some(Arc::new(cgroup));
+        self
+    }
+
+    /// Keeps peak-mem's own RSS under `cap`: whenever a sample finds it
+    /// over, the timeline is moved to disk and the processes that have
+    /// exited are forgotten, with a warning.
+    pub fn with_self_memory_cap(mut self, cap: ByteSize) -> Self {
+        self.self_memory_cap = Some(cap);
+        self
+    }
+
+    /// Takes the first sample only, for `--once`. The first tick of the
+    /// sampling interval fires right away, so stopping the tracker after
+    /// it has started would race a second sample.
+    pub fn with_single_sample(mut self) -> Self {
+        self.single_sample = true;
+        self
     }
 
     /// Starts the background tracking task.
@@ -62,113 +297,222 @@ impl MemoryTracker {
     /// * `JoinHandle` for the spawned tracking task
     pub async fn start(&self, interval_ms: u64) -> tokio::task::JoinHandle<()> {
         let monitor = Arc::clone(&self.monitor);
-        let pid = self.pid;
+        let pids = self.pids.clone();
+        let target_peaks = Arc::clone(&self.target_peaks);
         let peak_rss = Arc::clone(&self.peak_rss);
         let peak_vsz = Arc::clone(&self.peak_vsz);
         let timeline = Arc::clone(&self.timeline);
         let running = Arc::clone(&self.running);
         let track_children = self.track_children;
+        let aggregate = self.aggregate.clone();
         let sample_count = Arc::clone(&self.sample_count);
+        let current_rss = Arc::clone(&self.current_rss);
+        let current_vsz = Arc::clone(&self.current_vsz);
+        let skipped_ticks = Arc::clone(&self.skipped_ticks);
+        let unreadable_samples = Arc::clone(&self.unreadable_samples);
         let peak_process_tree = Arc::clone(&self.peak_process_tree);
+        let lifetimes = Arc::clone(&self.lifetimes);
+        let counters = Arc::clone(&self.counters);
+        let pressure_backoff = self.pressure_backoff;
+        let diagnostics = Arc::clone(&self.diagnostics);
+        let peak_pressure = Arc::clone(&self.peak_pressure);
+        let lowest_system = Arc::clone(&self.lowest_system);
+        let gpu = self.gpu.clone();
+        let peak_gpu = Arc::clone(&self.peak_gpu);
+        let heap = self.heap.clone();
+        let peak_heap = Arc::clone(&self.peak_heap);
+        let observed_processes = Arc::clone(&self.observed_processes);
+        let pause = self.pause.clone();
+        let fd_summary = self.fd_summary;
+        let peak_fds = Arc::clone(&self.peak_fds);
+        let cgroup = self.cgroup.clone();
+        let warnings = Arc::clone(&self.warnings);
+        let found_self = Arc::clone(&self.found_self);
+        let self_memory_cap = self.self_memory_cap;
+        let attribute_jobs = !self.job_peaks.read().await.is_empty();
+        let job_peaks = Arc::clone(&self.job_peaks);
+        let pool = self.pool.clone();
+        let process_snapshots = self.process_snapshots.clone();
+        let live_tree = self.live_tree.clone();
+        let single_sample = self.single_sample;
+        let started = std::time::Instant::now();
 
         running.store(true, Ordering::SeqCst);
+        diagnostics.write().await.base_interval_ms = interval_ms;
 
         tokio::spawn(async move {
-            let mut interval = time::interval(Duration::from_millis(interval_ms));
-            interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
-
-            // Sample immediately
-            let monitor_guard = monitor.lock().await;
-            if track_children {
-                if let Ok(tree) = monitor_guard.get_process_tree(pid).await {
-                    let mut total_rss = 0u64;
-                    let mut total_vsz = 0u64;
-                    Self::sum_tree_memory(&tree, &mut total_rss, &mut total_vsz);
-
-                    peak_rss.store(total_rss, Ordering::SeqCst);
-                    peak_vsz.store(total_vsz, Ordering::SeqCst);
-                    sample_count.fetch_add(1, Ordering::SeqCst);
-
-                    // Store initial process tree
-                    let mut pt = peak_process_tree.write().await;
-                    *pt = Some(tree.clone());
-
-                    let mut tl = timeline.write().await;
-                    tl.push(MemoryUsage {
-                        rss_bytes: total_rss,
-                        vsz_bytes: total_vsz,
-                        timestamp: tree.memory.timestamp,
-                    });
+            let mut interval = Self::sampling_interval(interval_ms, false);
+            let mut current_ms = interval_ms;
+            let mut last_tick: Option<time::Instant> = None;
+            // Sample immediately, then on every tick
+            let mut first = true;
+            loop {
+                if !first {
+                    if single_sample || !running.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    // A late tick moves the next one to the following
+                    // multiple of the interval; those in between are lost
+                    let tick = interval.tick().await;
+                    if let Some(last) = last_tick.replace(tick) {
+                        let ticks = tick.duration_since(last).as_millis() as u64 / current_ms;
+                        skipped_ticks.fetch_add(ticks.saturating_sub(1), Ordering::SeqCst);
+                    }
+                    if pause.is_paused() {
+                        continue;
+                    }
                 }
-            } else if let Ok(usage) = monitor_guard.get_memory_usage(pid).await {
-                peak_rss.store(usage.rss_bytes, Ordering::SeqCst);
-                peak_vsz.store(usage.vsz_bytes, Ordering::SeqCst);
-                sample_count.fetch_add(1, Ordering::SeqCst);
 
-                let mut tl = timeline.write().await;
-                tl.push(usage);
-            }
-            drop(monitor_guard);
+                let monitor = monitor.lock().await;
+                let sample = Self::sample_targets(
+                    monitor.as_ref(),
+                    &pids,
+                    track_children,
+                    &aggregate,
+                    &observed_processes,
+                    &warnings,
+                    &found_self,
+                )
+                .await;
+                let own_rss = match self_memory_cap {
+                    Some(_) => monitor
+                        .get_memory_usage(std::process::id())
+                        .await
+                        .ok()
+                        .map(|own| own.rss_bytes),
+                    None => None,
+                };
+                drop(monitor);
 
-            while running.load(Ordering::SeqCst) {
-                interval.tick().await;
+                let Some(mut sample) = sample else {
+                    if first {
+                        first = false;
+                        continue;
+                    }
+                    // Every tracked process has likely terminated
+                    break;
+                };
+                first = false;
+                if sample.unreadable {
+                    unreadable_samples.fetch_add(1, Ordering::SeqCst);
+                }
 
-                let monitor = monitor.lock().await;
-                if track_children {
-                    match monitor.get_process_tree(pid).await {
-                        Ok(tree) => {
-                            let mut total_rss = 0u64;
-                            let mut total_vsz = 0u64;
-                            Self::sum_tree_memory(&tree, &mut total_rss, &mut total_vsz);
-
-                            // Check if this is a new peak
-                            let old_peak = peak_rss.load(Ordering::SeqCst);
-                            if total_rss > old_peak {
-                                peak_rss.store(total_rss, Ordering::SeqCst);
-                                peak_vsz.store(total_vsz, Ordering::SeqCst);
-
-                                // Update peak process tree
-                                let mut pt = peak_process_tree.write().await;
-                                *pt = Some(tree.clone());
-                            } else {
-                                peak_rss.fetch_max(total_rss, Ordering::SeqCst);
-                                peak_vsz.fetch_max(total_vsz, Ordering::SeqCst);
-                            }
-
-                            sample_count.fetch_add(1, Ordering::SeqCst);
-
-                            let mut tl = timeline.write().await;
-                            tl.push(MemoryUsage {
-                                rss_bytes: total_rss,
-                                vsz_bytes: total_vsz,
-                                timestamp: tree.memory.timestamp,
-                            });
-                        }
-                        Err(_) => {
-                            // Process likely terminated
-                            break;
-                        }
+                Self::record_gpu_for_pids(
+                    gpu.as_deref(),
+                    &warnings,
+                    &peak_gpu,
+                    &mut sample.total,
+                    sample.pids.iter().copied(),
+                );
+                if let Some(heap) = &heap {
+                    sample.total.heap_bytes = heap.sample(sample.main_tree.as_ref(), &sample.pids);
+                    if let Some(bytes) = sample.total.heap_bytes {
+                        let mut peak = peak_heap.write().await;
+                        *peak = Some(peak.map_or(bytes, |peak| peak.max(bytes)));
+                    }
+                }
+                for (peaks, usage) in target_peaks.iter().zip(&sample.targets) {
+                    if let Some(usage) = usage {
+                        peaks.record(usage);
+                    }
+                }
+                if let Some(current) = cgroup.as_deref().and_then(CgroupMemory::current) {
+                    sample.total.rss_bytes = current;
+                }
+
+                if let (true, Some(tree)) = (attribute_jobs, &sample.main_tree) {
+                    for jobs in job_peaks.write().await.iter_mut() {
+                        jobs.record(tree);
+                    }
+                }
+                if let (Some(pool), Some(tree)) = (&pool, &sample.main_tree) {
+                    pool.write().await.record(tree, started.elapsed());
+                }
+                if let (Some(snapshots), Some(tree)) = (&process_snapshots, &sample.main_tree) {
+                    snapshots.write().await.record(tree, sample.total.timestamp);
+                }
+                if let Some(tree) = &sample.main_tree {
+                    lifetimes.write().await.record(tree);
+                }
+                if let (Some(live_tree), Some(tree)) = (&live_tree, &sample.main_tree) {
+                    *live_tree.write().await = Some(tree.clone());
+                }
+
+                // With children, a new peak RSS also resets the peak VSZ to
+                // that sample's, along with the peak process tree
+                let total = sample.total;
+                let new_peak = sample_count.load(Ordering::SeqCst) == 0
+                    || total.rss_bytes > peak_rss.load(Ordering::SeqCst);
+                if new_peak {
+                    peak_rss.fetch_max(total.rss_bytes, Ordering::SeqCst);
+                    if track_children {
+                        peak_vsz.store(total.vsz_bytes, Ordering::SeqCst);
+                    } else {
+                        peak_vsz.fetch_max(total.vsz_bytes, Ordering::SeqCst);
+                    }
+                    if let Some(tree) = sample.main_tree {
+                        *peak_process_tree.write().await = Some(tree);
+                    }
+                    if fd_summary {
+                        *peak_fds.write().await = fds::summarize(sample.pids.iter().copied());
                     }
                 } else {
-                    match monitor.get_memory_usage(pid).await {
-                        Ok(usage) => {
-                            // Update peaks
-                            peak_rss.fetch_max(usage.rss_bytes, Ordering::SeqCst);
-                            peak_vsz.fetch_max(usage.vsz_bytes, Ordering::SeqCst);
-                            sample_count.fetch_add(1, Ordering::SeqCst);
-
-                            // Add to timeline
-                            let mut tl = timeline.write().await;
-                            tl.push(usage);
-                        }
-                        Err(_) => {
-                            // Process likely terminated
-                            break;
+                    peak_vsz.fetch_max(total.vsz_bytes, Ordering::SeqCst);
+                }
+                // The kernel's own peak also catches spikes between samples,
+                // once it is beyond the cgroup's peak from before the run
+                if let Some(peak) = cgroup.as_deref().and_then(CgroupMemory::peak) {
+                    peak_rss.fetch_max(peak, Ordering::SeqCst);
+                }
+
+                counters.record(&total);
+                current_rss.store(total.rss_bytes, Ordering::SeqCst);
+                current_vsz.store(total.vsz_bytes, Ordering::SeqCst);
+                sample_count.fetch_add(1, Ordering::SeqCst);
+                timeline.write().await.push(total);
+
+                if let (Some(cap), Some(own_rss)) = (self_memory_cap, own_rss) {
+                    if own_rss > cap.as_u64() {
+                        let mut pids: HashSet<u32> = sample.pids.iter().copied().collect();
+                        if let Some(peak) = peak_process_tree.read().await.as_ref() {
+                            let mut peak_pids = Vec::new();
+                            Self::collect_pids(peak, &mut peak_pids);
+                            pids.extend(peak_pids);
                         }
+                        Self::shed_own_memory(cap, &timeline, &lifetimes, &pids, &warnings).await;
+                    }
+                }
+
+                if let Some(memory) = system::read_system_memory() {
+                    let mut lowest = lowest_system.write().await;
+                    if lowest.is_none_or(|lowest| memory.available_bytes < lowest.available_bytes) {
+                        *lowest = Some(memory);
+                    }
+                }
+
+                let Some(pressure) = pressure::read_memory_pressure() else {
+                    continue;
+                };
+                Self::record_pressure(&peak_pressure, pressure).await;
+
+                if pressure_backoff {
+                    let next_ms = Self::backoff_interval(current_ms, interval_ms, &pressure);
+                    if next_ms != current_ms {
+                        current_ms = next_ms;
+                        interval = Self::sampling_interval(current_ms, true);
+                        diagnostics
+                            .write()
+                            .await
+                            .adjustments
+                            .push(IntervalAdjustment {
+                                timestamp: Timestamp::now(),
+                                interval_ms: current_ms,
+                                pressure_some_avg10: pressure.some_avg10,
+                            });
                     }
                 }
-                drop(monitor);
             }
+            warnings.flush();
         })
     }
 
@@ -187,52 +531,516 @@ impl MemoryTracker {
         self.peak_vsz.load(Ordering::SeqCst)
     }
 
+    /// Returns the peaks of each tracked process on its own, main process
+    /// first. The peaks of different processes need not coincide, so they
+    /// can add up to more than the combined peak.
+    pub fn target_peaks(&self) -> Vec<TargetPeak> {
+        self.pids
+            .iter()
+            .zip(self.target_peaks.iter())
+            .map(|(&pid, peaks)| TargetPeak {
+                pid,
+                command: None,
+                peak_rss_bytes: peaks.rss.load(Ordering::SeqCst),
+                peak_vsz_bytes: peaks.vsz.load(Ordering::SeqCst),
+            })
+            .collect()
+    }
+
+    /// Returns the highest cumulative minor page fault count observed.
+    ///
+    /// Counters of processes that exited between samples drop out of the
+    /// tree sum, so for short-lived children this is a lower bound.
+    pub fn minor_faults(&self) -> u64 {
+        self.counters.minor_faults.load(Ordering::SeqCst)
+    }
+
+    /// Returns the highest cumulative major page fault count observed.
+    ///
+    /// Subject to the same lower-bound caveat as
+    /// [`MemoryTracker::minor_faults`].
+    pub fn major_faults(&self) -> u64 {
+        self.counters.major_faults.load(Ordering::SeqCst)
+    }
+
+    /// Returns the highest number of threads observed alive at once.
+    pub fn peak_thread_count(&self) -> u64 {
+        self.counters.peak_thread_count.load(Ordering::SeqCst)
+    }
+
+    /// Returns the highest cumulative bytes read from storage observed.
+    ///
+    /// Subject to the same lower-bound caveat as
+    /// [`MemoryTracker::minor_faults`].
+    pub fn disk_read_bytes(&self) -> u64 {
+        self.counters.disk_read_bytes.load(Ordering::SeqCst)
+    }
+
+    /// Returns the highest cumulative bytes written to storage observed.
+    ///
+    /// Subject to the same lower-bound caveat as
+    /// [`MemoryTracker::minor_faults`].
+    pub fn disk_write_bytes(&self) -> u64 {
+        self.counters.disk_write_bytes.load(Ordering::SeqCst)
+    }
+
     /// Returns a copy of the collected timeline data.
     pub async fn timeline(&self) -> Vec<MemoryUsage> {
-        self.timeline.read().await.clone()
+        self.timeline.read().await.samples()
     }
 
     /// Returns a shared handle to the collected timeline samples.
     ///
     /// Allows other tasks (e.g. the watch-mode display) to observe the
     /// most recent sample without querying the platform monitor again.
-    pub fn timeline_handle(&self) -> Arc<RwLock<Vec<MemoryUsage>>> {
+    pub fn timeline_handle(&self) -> SharedStore {
         Arc::clone(&self.timeline)
     }
 
+    /// Returns a shared handle to the main tree of the latest sample, if
+    /// kept.
+    pub fn live_tree_handle(&self) -> Option<SharedTree> {
+        self.live_tree.clone()
+    }
+
+    /// Returns the sampling diagnostics, if pressure backoff was enabled.
+    pub async fn sampling_diagnostics(&self) -> Option<SamplingDiagnostics> {
+        if self.pressure_backoff {
+            Some(self.diagnostics.read().await.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the highest system memory pressure observed during the
+    /// run, or `None` where PSI is unavailable.
+    pub async fn peak_pressure(&self) -> Option<MemoryPressure> {
+        *self.peak_pressure.read().await
+    }
+
+    /// Returns the machine's memory at the sample with the least of it
+    /// available, or `None` where that can't be read.
+    pub async fn lowest_system_memory(&self) -> Option<SystemMemory> {
+        *self.lowest_system.read().await
+    }
+
+    /// Returns the peak GPU memory observed, if GPU tracking is enabled.
+    pub fn peak_gpu(&self) -> Option<u64> {
+        self.gpu
+            .as_ref()
+            .map(|_| self.peak_gpu.load(Ordering::SeqCst))
+    }
+
+    /// Returns the peak managed heap, if any runtime of the tree reported
+    /// one.
+    pub async fn peak_heap(&self) -> Option<u64> {
+        *self.peak_heap.read().await
+    }
+
+    /// Returns the tree's open file descriptors at peak RSS, if counting
+    /// was enabled and is supported on this platform.
+    pub async fn peak_fds(&self) -> Option<FdSummary> {
+        *self.peak_fds.read().await
+    }
+
+    /// Returns the footprint of the worker pool, if one was measured and
+    /// any of its processes seen.
+    pub async fn pool_summary(&self) -> Option<PoolSummary> {
+        match &self.pool {
+            Some(pool) => pool.read().await.summary(),
+            None => None,
+        }
+    }
+
+    /// Returns the processes of the tree in each sample, if they were
+    /// kept.
+    pub async fn process_snapshots(&self) -> Option<Vec<ProcessSnapshot>> {
+        match &self.process_snapshots {
+            Some(snapshots) => Some(snapshots.read().await.snapshots()),
+            None => None,
+        }
+    }
+
+    /// Returns how the timeline was capped, if it was.
+    pub async fn timeline_retention(&self) -> Option<RetentionSummary> {
+        self.timeline.read().await.retention()
+    }
+
+    /// Returns how the timeline was thinned to its changes, if it was.
+    pub async fn timeline_delta(&self) -> Option<DeltaSummary> {
+        self.timeline.read().await.delta()
+    }
+
+    /// Returns the peak RSS of each job of a kind compiled, highest
+    /// first. Empty unless that kind is attributed.
+    pub async fn job_peaks(&self, kind: JobKind) -> Vec<JobPeak> {
+        self.job_peaks
+            .read()
+            .await
+            .iter()
+            .find(|jobs| jobs.kind() == kind)
+            .map_or_else(Vec::new, JobPeaks::sorted)
+    }
+
+    /// Returns a handle to pause and resume sampling with.
+    pub fn pause_switch(&self) -> PauseSwitch {
+        self.pause.clone()
+    }
+
+    /// Returns the pauses in sampling so far.
+    pub fn pauses(&self) -> Vec<SamplingPause> {
+        self.pause.pauses()
+    }
+
+    /// Returns the names of all processes seen in the tree, sorted. Empty
+    /// when children are not tracked.
+    pub async fn observed_processes(&self) -> Vec<String> {
+        self.observed_processes
+            .read()
+            .await
+            .iter()
+            .cloned()
+            .collect()
+    }
+
     /// Returns the number of samples collected.
     pub fn sample_count(&self) -> u64 {
         self.sample_count.load(Ordering::SeqCst)
     }
 
-    /// Returns the process tree captured at peak memory usage.
+    /// Returns a shared handle to the number of samples taken, which,
+    /// unlike the timeline's length, never goes down.
+    pub fn sample_count_handle(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.sample_count)
+    }
+
+    /// Returns the number of sampling ticks skipped because sampling fell
+    /// behind.
+    pub fn skipped_ticks(&self) -> u64 {
+        self.skipped_ticks.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of samples that left out a process that
+    /// couldn't be read.
+    pub fn unreadable_samples(&self) -> u64 {
+        self.unreadable_samples.load(Ordering::SeqCst)
+    }
+
+    /// Returns the process tree captured at peak memory usage, each
+    /// process with its RSS over the whole run.
     ///
     /// # Returns
     /// * `Ok(ProcessMemoryInfo)` - Process tree at peak
     /// * `Err` - If no process tree has been captured yet
     pub async fn get_process_tree(&self) -> Result<crate::types::ProcessMemoryInfo> {
         let tree_lock = self.peak_process_tree.read().await;
-        tree_lock.clone().ok_or_else(|| {
+        let mut tree = tree_lock.clone().ok_or_else(|| {
             crate::types::PeakMemError::ProcessSpawn("No process tree available".to_string())
+        })?;
+        self.lifetimes.read().await.annotate(&mut tree);
+        Ok(tree)
+    }
+
+    /// Samples every tracked process (or tree) once.
+    ///
+    /// # Returns
+    /// * The summed sample, or `None` if none of the processes could be read,
+    ///   which usually means they have all exited
+    async fn sample_targets(
+        monitor: &dyn MemoryMonitor,
+        pids: &[u32],
+        track_children: bool,
+        aggregate: &Aggregate,
+        observed_processes: &RwLock<BTreeSet<String>>,
+        warnings: &Warnings,
+        found_self: &AtomicBool,
+    ) -> Option<TargetsSample> {
+        let mut total: Option<MemoryUsage> = None;
+        let mut targets = Vec::with_capacity(pids.len());
+        let mut covered = Vec::new();
+        let mut main_tree = None;
+        let mut unreadable = false;
+
+        for (index, &pid) in pids.iter().enumerate() {
+            let usage = if track_children {
+                let unreadable_before = monitor.unreadable_processes();
+                let tree = monitor.get_process_tree(pid).await;
+                if monitor.unreadable_processes() > unreadable_before {
+                    warnings.warn(&format!(
+                        "Some processes in the tree of PID {pid} can't be read and are left out"
+                    ));
+                    unreadable = true;
+                }
+                match tree {
+                    Ok(tree) if Self::exited(&tree.memory) => None,
+                    Ok(mut tree) => {
+                        if Self::prune(&mut tree, std::process::id())
+                            && !found_self.swap(true, Ordering::SeqCst)
+                        {
+                            eprintln!(
+                                "Warning: peak-mem is in the process tree of PID {pid}; \
+                                 leaving out its own memory and that of what it runs"
+                            );
+                        }
+                        Self::record_names(&mut *observed_processes.write().await, &tree);
+                        Self::collect_pids(&tree, &mut covered);
+                        let mut usage = Self::sum_tree_memory(&tree);
+                        if *aggregate != Aggregate::Sum {
+                            (usage.rss_bytes, usage.vsz_bytes) = aggregate.memory(&tree);
+                        }
+                        if index == 0 {
+                            main_tree = Some(tree);
+                        }
+                        Some(usage)
+                    }
+                    Err(e) => Self::sample_failed(warnings, e, &mut unreadable),
+                }
+            } else {
+                covered.push(pid);
+                match monitor.get_memory_usage(pid).await {
+                    Ok(usage) if Self::exited(&usage) => None,
+                    Ok(usage) => Some(usage),
+                    Err(e) => Self::sample_failed(warnings, e, &mut unreadable),
+                }
+            };
+
+            if let Some(usage) = &usage {
+                match &mut total {
+                    Some(total) => total.accumulate(usage),
+                    None => total = Some(usage.clone()),
+                }
+            }
+            targets.push(usage);
+        }
+
+        Some(TargetsSample {
+            total: total?,
+            targets,
+            pids: covered,
+            main_tree,
+            unreadable,
         })
     }
 
+    /// Whether a process has exited and only waits to be reaped: a zombie
+    /// has no address space left, while any live process maps something.
+    fn exited(usage: &MemoryUsage) -> bool {
+        usage.vsz_bytes == 0
+    }
+
+    /// Warns about a process that exists but can't be read. Any other
+    /// failure usually means the process has exited.
+    fn sample_failed(
+        warnings: &Warnings,
+        error: PeakMemError,
+        unreadable: &mut bool,
+    ) -> Option<MemoryUsage> {
+        if let PeakMemError::PermissionDenied(_) = error {
+            warnings.warn(&error.to_string());
+            *unreadable = true;
+        }
+        None
+    }
+
     /// Recursively sums memory usage across a process tree.
     ///
     /// # Arguments
     /// * `info` - Root of process tree
-    /// * `rss` - Accumulator for RSS bytes
-    /// * `vsz` - Accumulator for VSZ bytes
-    fn sum_tree_memory(info: &crate::types::ProcessMemoryInfo, rss: &mut u64, vsz: &mut u64) {
-        *rss += info.memory.rss_bytes;
-        *vsz += info.memory.vsz_bytes;
+    ///
+    /// # Returns
+    /// * A single sample holding the tree totals, stamped with the root's
+    ///   timestamp
+    fn sum_tree_memory(info: &ProcessMemoryInfo) -> MemoryUsage {
+        let mut total = info.memory.clone();
 
         for child in &info.children {
-            Self::sum_tree_memory(child, rss, vsz);
+            total.accumulate(&Self::sum_tree_memory(child));
+        }
+
+        total
+    }
+
+    /// Records the GPU memory used by the given processes on a sample and
+    /// raises the peak. A failed NVML query leaves the sample without GPU
+    /// data, with a warning, rather than ending the run.
+    fn record_gpu_for_pids(
+        gpu: Option<&GpuMonitor>,
+        warnings: &Warnings,
+        peak: &AtomicU64,
+        usage: &mut MemoryUsage,
+        pids: impl IntoIterator<Item = u32>,
+    ) {
+        let Some(gpu) = gpu else {
+            return;
+        };
+        match gpu.usage_by_pid() {
+            Ok(by_pid) => {
+                let bytes = gpu::usage_for_pids(&by_pid, pids);
+                usage.gpu_bytes = Some(bytes);
+                peak.fetch_max(bytes, Ordering::SeqCst);
+            }
+            Err(e) => warnings.warn(&e.to_string()),
+        }
+    }
+
+    fn record_names(names: &mut BTreeSet<String>, info: &ProcessMemoryInfo) {
+        if !names.contains(&info.name) {
+            names.insert(info.name.clone());
+        }
+        for child in &info.children {
+            Self::record_names(names, child);
+        }
+    }
+
+    /// Removes a process and its descendants from a tree.
+    ///
+    /// # Returns
+    /// * Whether the process was below the root; the root itself stays
+    fn prune(tree: &mut ProcessMemoryInfo, pid: u32) -> bool {
+        let before = tree.children.len();
+        tree.children.retain(|child| child.pid != pid);
+        let mut pruned = tree.children.len() != before;
+        for child in &mut tree.children {
+            pruned |= Self::prune(child, pid);
+        }
+        pruned
+    }
+
+    fn collect_pids(info: &ProcessMemoryInfo, pids: &mut Vec<u32>) {
+        pids.push(info.pid);
+        for child in &info.children {
+            Self::collect_pids(child, pids);
+        }
+    }
+
+    /// Frees what memory peak-mem can once it is over its cap: the
+    /// timeline goes to disk, and the lifetimes of processes not in
+    /// `pids` are dropped.
+    async fn shed_own_memory(
+        cap: ByteSize,
+        timeline: &SharedStore,
+        lifetimes: &RwLock<ProcessLifetimes>,
+        pids: &HashSet<u32>,
+        warnings: &Warnings,
+    ) {
+        warnings.warn(&format!(
+            "peak-mem is over its --self-memory-cap of {cap}; moving the timeline to disk"
+        ));
+        let spilled = match timeline.write().await.spill() {
+            Ok(spilled) => spilled,
+            Err(e) => {
+                warnings.warn(&format!("Failed to move the timeline to disk: {e}"));
+                0
+            }
+        };
+        let forgotten = lifetimes.write().await.retain(pids);
+        if spilled == 0 && forgotten == 0 {
+            warnings.warn(&format!(
+                "peak-mem is still over its --self-memory-cap of {cap} with nothing left to move to disk"
+            ));
+        }
+    }
+
+    /// Raises the stored peak pressure to a new reading.
+    async fn record_pressure(peak: &RwLock<Option<MemoryPressure>>, pressure: MemoryPressure) {
+        let mut peak = peak.write().await;
+        *peak = Some(match *peak {
+            Some(old) => old.max(&pressure),
+            None => pressure,
+        });
+    }
+
+    /// Creates a sampling interval timer.
+    ///
+    /// A replacement timer (`delayed`) waits a full period before its first
+    /// tick instead of firing immediately.
+    fn sampling_interval(interval_ms: u64, delayed: bool) -> time::Interval {
+        let period = Duration::from_millis(interval_ms);
+        let mut interval = if delayed {
+            time::interval_at(time::Instant::now() + period, period)
+        } else {
+            time::interval(period)
+        };
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+        interval
+    }
+
+    /// Picks the sampling interval for the current memory pressure.
+    ///
+    /// # Arguments
+    /// * `current_ms` - Interval currently in use
+    /// * `base_ms` - Interval requested by the user
+    /// * `pressure` - Latest pressure reading
+    fn backoff_interval(current_ms: u64, base_ms: u64, pressure: &MemoryPressure) -> u64 {
+        let backed_off = current_ms != base_ms;
+        if !backed_off && pressure.is_high() {
+            base_ms.saturating_mul(BACKOFF_FACTOR)
+        } else if backed_off && pressure.some_avg10 < PRESSURE_LOW {
+            base_ms
+        } else {
+            current_ms
         }
     }
 }
 
+/// Peak RSS and VSZ of a single tracked process.
+#[derive(Default)]
+struct PeakPair {
+    rss: AtomicU64,
+    vsz: AtomicU64,
+}
+
+impl PeakPair {
+    fn record(&self, usage: &MemoryUsage) {
+        self.rss.fetch_max(usage.rss_bytes, Ordering::SeqCst);
+        self.vsz.fetch_max(usage.vsz_bytes, Ordering::SeqCst);
+    }
+}
+
+/// One sample of every tracked process.
+struct TargetsSample {
+    /// Sum over all processes that could be read.
+    total: MemoryUsage,
+    /// Each tracked process's own sample, in tracking order; `None` once
+    /// it has exited.
+    targets: Vec<Option<MemoryUsage>>,
+    /// Every process covered, including children.
+    pids: Vec<u32>,
+    /// The main process's tree, if children are tracked.
+    main_tree: Option<ProcessMemoryInfo>,
+    /// Whether a process couldn't be read and was left out.
+    unreadable: bool,
+}
+
+/// Highest values of the per-sample counters observed for the tree.
+///
+/// Fault and I/O counters are cumulative, so their highest sum is the best
+/// estimate of the tree's total; the thread count is a plain peak.
+#[derive(Default)]
+struct SampleCounters {
+    minor_faults: AtomicU64,
+    major_faults: AtomicU64,
+    peak_thread_count: AtomicU64,
+    disk_read_bytes: AtomicU64,
+    disk_write_bytes: AtomicU64,
+}
+
+impl SampleCounters {
+    /// Raises the stored counters to those of a new sample.
+    fn record(&self, usage: &MemoryUsage) {
+        self.minor_faults
+            .fetch_max(usage.minor_faults, Ordering::SeqCst);
+        self.major_faults
+            .fetch_max(usage.major_faults, Ordering::SeqCst);
+        self.peak_thread_count
+            .fetch_max(usage.thread_count, Ordering::SeqCst);
+        self.disk_read_bytes
+            .fetch_max(usage.disk_read_bytes, Ordering::SeqCst);
+        self.disk_write_bytes
+            .fetch_max(usage.disk_write_bytes, Ordering::SeqCst);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -270,6 +1078,65 @@ mod tests {
         assert!(!timeline.is_empty(), "Timeline should not be empty");
     }
 
+    #[test]
+    fn test_backoff_interval() {
+        let pressure = |some_avg10| MemoryPressure {
+            some_avg10,
+            full_avg10: 0.0,
+        };
+
+        assert_eq!(
+            MemoryTracker::backoff_interval(100, 100, &pressure(2.0)),
+            100
+        );
+        assert_eq!(
+            MemoryTracker::backoff_interval(100, 100, &pressure(15.0)),
+            400
+        );
+        // Between the two marks the current interval is kept either way
+        assert_eq!(
+            MemoryTracker::backoff_interval(100, 100, &pressure(7.0)),
+            100
+        );
+        assert_eq!(
+            MemoryTracker::backoff_interval(400, 100, &pressure(7.0)),
+            400
+        );
+        assert_eq!(
+            MemoryTracker::backoff_interval(400, 100, &pressure(30.0)),
+            400
+        );
+        assert_eq!(
+            MemoryTracker::backoff_interval(400, 100, &pressure(1.0)),
+            100
+        );
+    }
+
+    #[test]
+    fn test_prune() {
+        let process = |pid, children| ProcessMemoryInfo {
+            pid,
+            name: format!("p{pid}"),
+            memory: MemoryUsage::new(1024, 0, Timestamp::now()),
+            children,
+            namespace: None,
+            rss_stats: None,
+        };
+        // A shell running peak-mem (3) and something else, peak-mem
+        // running the command
+        let mut tree = process(
+            1,
+            vec![process(2, vec![]), process(3, vec![process(4, vec![])])],
+        );
+        assert!(MemoryTracker::prune(&mut tree, 3));
+        let mut pids = Vec::new();
+        MemoryTracker::collect_pids(&tree, &mut pids);
+        assert_eq!(pids, vec![1, 2]);
+
+        assert!(!MemoryTracker::prune(&mut tree, 1));
+        assert_eq!(tree.pid, 1);
+    }
+
     #[tokio::test]
     async fn test_process_tree_capture() {
         let monitor = create_monitor().unwrap();
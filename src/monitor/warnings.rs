@@ -0,0 +1,154 @@
+//! Deduplication of warnings raised while sampling.
+//!
+//! A problem that affects every sample, such as a process whose memory
+//! can't be read or a failing GPU query, would otherwise print a line per
+//! sample and bury everything else on stderr during long runs. Each
+//! distinct warning is printed the first time it occurs; repeats are
+//! counted and summarized at most once per interval, and whatever is still
+//! pending is summarized when sampling ends.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often a repeating warning is summarized.
+pub const SUMMARY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Rate-limits warnings by their message.
+#[derive(Debug)]
+pub struct Warnings {
+    interval: Duration,
+    seen: Mutex<HashMap<String, Repeats>>,
+}
+
+/// Repeats of one warning since it was last printed.
+#[derive(Debug)]
+struct Repeats {
+    printed_at: Instant,
+    suppressed: u64,
+}
+
+impl Default for Warnings {
+    fn default() -> Self {
+        Self::new(SUMMARY_INTERVAL)
+    }
+}
+
+impl Warnings {
+    /// Creates a warning manager that summarizes repeats every `interval`.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Prints a warning to stderr unless it was printed within the
+    /// interval, in which case it is only counted.
+    pub fn warn(&self, message: &str) {
+        if let Some(line) = self.record(message, Instant::now()) {
+            eprintln!("Warning: {line}");
+        }
+    }
+
+    /// Prints a summary of every warning repeated since it was last
+    /// printed.
+    pub fn flush(&self) {
+        for line in self.pending() {
+            eprintln!("Warning: {line}");
+        }
+    }
+
+    /// Counts an occurrence of a warning.
+    ///
+    /// # Returns
+    /// * The line to print, or `None` if the warning is suppressed
+    fn record(&self, message: &str, now: Instant) -> Option<String> {
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(repeats) = seen.get_mut(message) else {
+            seen.insert(
+                message.to_string(),
+                Repeats {
+                    printed_at: now,
+                    suppressed: 0,
+                },
+            );
+            return Some(message.to_string());
+        };
+
+        if now.duration_since(repeats.printed_at) < self.interval {
+            repeats.suppressed += 1;
+            return None;
+        }
+        let line = Self::summarize(message, repeats.suppressed + 1, self.interval);
+        repeats.printed_at = now;
+        repeats.suppressed = 0;
+        Some(line)
+    }
+
+    /// Takes the summaries of the warnings with uncounted repeats.
+    fn pending(&self) -> Vec<String> {
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        let mut lines: Vec<String> = seen
+            .iter_mut()
+            .filter(|(_, repeats)| repeats.suppressed > 0)
+            .map(|(message, repeats)| {
+                let since = repeats.printed_at.elapsed();
+                let line = Self::summarize(message, repeats.suppressed, since);
+                repeats.suppressed = 0;
+                line
+            })
+            .collect();
+        lines.sort();
+        lines
+    }
+
+    fn summarize(message: &str, times: u64, over: Duration) -> String {
+        let times = if times == 1 {
+            "once".to_string()
+        } else {
+            format!("{times} times")
+        };
+        format!(
+            "{message} (repeated {times} in the last {}s)",
+            over.as_secs().max(1)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeats_are_summarized_once_per_interval() {
+        let warnings = Warnings::new(Duration::from_secs(60));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(
+            warnings
+                .record("Permission denied: pid 7", at(0))
+                .as_deref(),
+            Some("Permission denied: pid 7")
+        );
+        assert_eq!(warnings.record("Permission denied: pid 7", at(1)), None);
+        assert_eq!(warnings.record("Permission denied: pid 7", at(30)), None);
+        // A different warning isn't held back
+        assert!(warnings.record("GPU query failed", at(30)).is_some());
+
+        assert_eq!(
+            warnings
+                .record("Permission denied: pid 7", at(61))
+                .as_deref(),
+            Some("Permission denied: pid 7 (repeated 3 times in the last 60s)")
+        );
+        assert_eq!(warnings.record("Permission denied: pid 7", at(62)), None);
+
+        // Only the pending repeat is left to summarize, and only once
+        let pending = warnings.pending();
+        assert_eq!(pending.len(), 1);
+        assert!(pending[0].starts_with("Permission denied: pid 7 (repeated once in the last"));
+        assert!(warnings.pending().is_empty());
+    }
+}
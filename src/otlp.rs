@@ -0,0 +1,163 @@
+//! Exporting results as OpenTelemetry metrics for `--otlp-endpoint`.
+//!
+//! The run is sent once it ends, as OTLP over HTTP with the JSON encoding,
+//! which any OpenTelemetry Collector accepts on its `otlphttp` receiver:
+//!
+//! * `peak_mem.rss` and `peak_mem.vsz` - gauges with a data point per timeline
+//!   sample
+//! * `peak_mem.peak_rss` - a summary of the run's RSS, whose 1.0 quantile is
+//!   the peak
+//!
+//! The resource carries the command, host and baseline name as attributes.
+//! Like the rest of [`crate::http`], only `http://` endpoints are supported.
+
+use crate::http::{self, HttpUrl};
+use crate::types::{MemoryUsage, MonitorResult, Result, Timestamp};
+use serde_json::{json, Value};
+
+/// Path of the metrics signal below an endpoint's base path.
+const METRICS_PATH: &str = "/v1/metrics";
+
+/// An OTLP/HTTP metrics endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OtlpExporter {
+    url: HttpUrl,
+}
+
+impl OtlpExporter {
+    /// Sends to a collector at `endpoint`, e.g. `http://localhost:4318`.
+    /// As with `OTEL_EXPORTER_OTLP_ENDPOINT`, `/v1/metrics` is appended
+    /// unless the URL already ends with it.
+    pub fn new(endpoint: &HttpUrl) -> Self {
+        let mut url = endpoint.clone();
+        if !url.path.ends_with(METRICS_PATH) {
+            url.path = format!("{}{METRICS_PATH}", url.path.trim_end_matches('/'));
+        }
+        Self { url }
+    }
+
+    /// Exports a run's metrics.
+    ///
+    /// # Errors
+    /// * Connection, I/O or timeout failures, and non-2xx responses
+    pub async fn export(&self, payload: &Value) -> Result<()> {
+        let body = serde_json::to_vec(payload)?;
+        http::send("POST", &self.url, "application/json", &body).await?;
+        Ok(())
+    }
+}
+
+/// Builds the `ExportMetricsServiceRequest` for a run.
+///
+/// # Arguments
+/// * `result` - The run's results, with its timeline if it was kept
+/// * `host` - Host name for the `host.name` attribute
+/// * `baseline` - Name of the baseline compared against or saved, if any
+pub fn metrics_request(result: &MonitorResult, host: &str, baseline: Option<&str>) -> Value {
+    let mut attributes = vec![
+        attribute("service.name", "peak-mem"),
+        attribute("process.command_line", &result.command),
+        attribute("host.name", host),
+    ];
+    if let Some(baseline) = baseline {
+        attributes.push(attribute("peak_mem.baseline", baseline));
+    }
+
+    let start = nanos(result.started_at());
+    let end = nanos(result.timestamp);
+    let timeline = result.timeline.as_deref().unwrap_or_default();
+    let mut metrics = Vec::new();
+    if !timeline.is_empty() {
+        metrics.push(gauge(
+            "peak_mem.rss",
+            "Resident set size of the process tree",
+            timeline,
+            |sample| sample.rss_bytes,
+        ));
+        metrics.push(gauge(
+            "peak_mem.vsz",
+            "Virtual size of the process tree",
+            timeline,
+            |sample| sample.vsz_bytes,
+        ));
+    }
+
+    let min_rss = timeline
+        .iter()
+        .map(|sample| sample.rss_bytes)
+        .min()
+        .unwrap_or(result.peak_rss_bytes);
+    let sum: u64 = timeline.iter().map(|sample| sample.rss_bytes).sum();
+    metrics.push(json!({
+        "name": "peak_mem.peak_rss",
+        "description": "Resident set size of the process tree over the run; the 1.0 quantile is the peak",
+        "unit": "By",
+        "summary": {
+            "dataPoints": [{
+                "startTimeUnixNano": start,
+                "timeUnixNano": end,
+                "count": timeline.len().to_string(),
+                "sum": sum as f64,
+                "quantileValues": [
+                    { "quantile": 0.0, "value": min_rss as f64 },
+                    { "quantile": 1.0, "value": result.peak_rss_bytes as f64 },
+                ],
+            }],
+        },
+    }));
+
+    json!({
+        "resourceMetrics": [{
+            "resource": { "attributes": attributes },
+            "scopeMetrics": [{
+                "scope": { "name": "peak-mem", "version": env!("CARGO_PKG_VERSION") },
+                "metrics": metrics,
+            }],
+        }],
+    })
+}
+
+fn attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+fn gauge(
+    name: &str,
+    description: &str,
+    timeline: &[MemoryUsage],
+    value: impl Fn(&MemoryUsage) -> u64,
+) -> Value {
+    // 64-bit integers are strings in OTLP's JSON encoding
+    let points: Vec<Value> = timeline
+        .iter()
+        .map(|sample| {
+            json!({
+                "timeUnixNano": nanos(sample.timestamp),
+                "asInt": value(sample).to_string(),
+            })
+        })
+        .collect();
+    json!({
+        "name": name,
+        "description": description,
+        "unit": "By",
+        "gauge": { "dataPoints": points },
+    })
+}
+
+fn nanos(timestamp: Timestamp) -> String {
+    (u128::from(timestamp.unix_micros()) * 1000).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_path() {
+        let base = HttpUrl::parse("http://localhost:4318").unwrap();
+        assert_eq!(OtlpExporter::new(&base).url.path, "/v1/metrics");
+        let full = HttpUrl::parse("http://collector/otlp/v1/metrics").unwrap();
+        assert_eq!(OtlpExporter::new(&full).url.path, "/otlp/v1/metrics");
+    }
+}
@@ -0,0 +1,99 @@
+//! SVG status badges for `--badge`.
+//!
+//! A badge in the look of shields.io ("peak mem | 1.2 GiB") that CI can
+//! publish as an artifact for a README to embed. It is green when a
+//! comparison found no regression, red when it found one and blue when
+//! nothing was compared.
+
+use super::{format_bytes, html::escape};
+use crate::baseline::ComparisonResult;
+use crate::cli::MemoryUnit;
+use crate::types::MonitorResult;
+
+const LABEL: &str = "peak mem";
+const GREEN: &str = "#4c1";
+const RED: &str = "#e05d44";
+const BLUE: &str = "#007ec6";
+
+/// Renders the badge for a run.
+///
+/// # Arguments
+/// * `result` - The run's results
+/// * `comparison` - The comparison against a baseline, if one was made; adds
+///   the change in peak RSS and picks the color
+/// * `units` - Fixed unit for the peak, or automatic
+pub fn badge(
+    result: &MonitorResult,
+    comparison: Option<&ComparisonResult>,
+    units: Option<MemoryUnit>,
+) -> String {
+    let peak = format_bytes(result.peak_rss_bytes, units);
+    let (value, color) = match comparison {
+        Some(comparison) => (
+            format!("{peak} ({:+.1}%)", comparison.rss_diff_percent),
+            if comparison.regression_detected {
+                RED
+            } else {
+                GREEN
+            },
+        ),
+        None => (peak, BLUE),
+    };
+    render(LABEL, &value, color)
+}
+
+/// Lays out a two-part flat badge. Widths are estimated from the glyphs
+/// of 11px Verdana, which shields.io uses.
+fn render(label: &str, value: &str, color: &str) -> String {
+    const PADDING: u32 = 10;
+    let label_width = text_width(label) + PADDING;
+    let value_width = text_width(value) + PADDING;
+    let width = label_width + value_width;
+    let label_x = label_width / 2;
+    let value_x = label_width + value_width / 2;
+    let (label, value) = (escape(label), escape(value));
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {value}">
+<title>{label}: {value}</title>
+<linearGradient id="s" x2="0" y2="100%"><stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/></linearGradient>
+<clipPath id="r"><rect width="{width}" height="20" rx="3" fill="#fff"/></clipPath>
+<g clip-path="url(#r)"><rect width="{label_width}" height="20" fill="#555"/><rect x="{label_width}" width="{value_width}" height="20" fill="{color}"/><rect width="{width}" height="20" fill="url(#s)"/></g>
+<g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">
+<text x="{label_x}" y="15" fill="#010101" fill-opacity=".3">{label}</text><text x="{label_x}" y="14">{label}</text>
+<text x="{value_x}" y="15" fill="#010101" fill-opacity=".3">{value}</text><text x="{value_x}" y="14">{value}</text>
+</g>
+</svg>
+"##
+    )
+}
+
+/// Approximate width of text in 11px Verdana, in pixels.
+fn text_width(text: &str) -> u32 {
+    text.chars()
+        .map(|c| match c {
+            'i' | 'l' | 'j' | '.' | ',' | ':' | '\'' | '|' | '!' => 3,
+            ' ' | 'f' | 't' | 'r' | 'I' | '(' | ')' | '[' | ']' | '-' => 4,
+            'm' | 'w' | 'M' | 'W' | '%' => 10,
+            'A'..='Z' => 8,
+            _ => 7,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let svg = render("peak mem", "1.2 GiB", GREEN);
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.contains(r#"aria-label="peak mem: 1.2 GiB""#));
+        assert!(svg.contains(r##"fill="#4c1""##));
+        // Both parts are wide enough for their text
+        assert!(svg.contains(r##"<rect width="69" height="20" fill="#555"/>"##));
+
+        let svg = render("peak mem", "<&>", BLUE);
+        assert!(svg.contains("&lt;&amp;&gt;"));
+    }
+}
@@ -0,0 +1,473 @@
+//! Charts of the timeline, for `--chart` and `--chart-term`.
+//!
+//! `--chart` writes a standalone SVG image of RSS and VSZ over the run,
+//! with the peak marked and the threshold drawn in, for docs and
+//! dashboards to embed as is. Unlike the chart of `--report`, it is drawn
+//! here rather than by a script, so it shows the same in a browser, an
+//! image viewer or a Markdown preview. `--chart-term` draws RSS over the
+//! run in block characters, to see its shape right after the run, and
+//! the human output sums it up in a sparkline.
+
+use super::html::escape;
+use super::{format_bytes, writeln_str};
+use crate::cli::MemoryUnit;
+use crate::types::{ByteSize, MemoryUsage, MonitorResult};
+
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 300.0;
+const LEFT: f64 = 70.0;
+const RIGHT: f64 = 15.0;
+const TOP: f64 = 25.0;
+const BOTTOM: f64 = 30.0;
+const RSS_COLOR: &str = "#1f6feb";
+const VSZ_COLOR: &str = "#9bb";
+const PEAK_COLOR: &str = "#c00";
+/// The RSS of a timeline compared against.
+const OTHER_COLOR: &str = "#e69f00";
+/// Rows of the terminal chart; each holds eight levels.
+const TERM_ROWS: usize = 8;
+pub(super) const BLOCKS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+/// Characters in the sparkline of the human output.
+pub const SPARKLINE_WIDTH: usize = 20;
+
+/// A sample as charted: milliseconds into the run, RSS and VSZ.
+pub type ChartPoint = (u64, u64, u64);
+
+/// Renders the chart of a run's timeline.
+///
+/// # Arguments
+/// * `result` - The run's results, with its timeline
+/// * `threshold` - The `--threshold`, if one was set, drawn as a dashed line
+/// * `units` - Optional fixed memory unit to use for the labels
+pub fn svg_chart(
+    result: &MonitorResult,
+    threshold: Option<ByteSize>,
+    units: Option<MemoryUnit>,
+) -> String {
+    svg_timeline(
+        &result.command,
+        &chart_points(result),
+        None,
+        threshold,
+        units,
+    )
+}
+
+/// Renders the chart of a timeline, with the RSS of another one drawn in
+/// too if given, under its label.
+///
+/// # Arguments
+/// * `title` - What was measured, for the image's title
+/// * `points` - The timeline
+/// * `other` - The label and timeline of another run to compare with
+/// * `threshold` - A threshold to draw as a dashed line
+/// * `units` - Optional fixed memory unit to use for the labels
+pub fn svg_timeline(
+    title: &str,
+    points: &[ChartPoint],
+    other: Option<(&str, &[ChartPoint])>,
+    threshold: Option<ByteSize>,
+    units: Option<MemoryUnit>,
+) -> String {
+    let seconds = |ms: f64| format!("{:.*}s", if ms < 10_000.0 { 2 } else { 1 }, ms / 1000.0);
+
+    let mut svg = String::new();
+    writeln_str!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" height=\"{HEIGHT}\" \
+         viewBox=\"0 0 {WIDTH} {HEIGHT}\" font-family=\"sans-serif\" font-size=\"11\">\n\
+         <title>Memory usage of {}</title>\n\
+         <rect width=\"{WIDTH}\" height=\"{HEIGHT}\" fill=\"#fff\"/>",
+        escape(title)
+    );
+
+    let to_f64 = |points: &[ChartPoint]| -> Vec<(f64, u64, u64)> {
+        points
+            .iter()
+            .map(|&(ms, rss, vsz)| (ms as f64, rss, vsz))
+            .collect()
+    };
+    let samples = to_f64(points);
+    let other = other.map(|(label, points)| (label, to_f64(points)));
+    if samples.is_empty() {
+        writeln_str!(
+            svg,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\" fill=\"#666\">No samples were recorded</text>\n</svg>",
+            WIDTH / 2.0,
+            HEIGHT / 2.0
+        );
+        return svg;
+    }
+
+    let others = other.as_ref().map_or(&[][..], |(_, samples)| samples);
+    let max_ms = samples
+        .iter()
+        .chain(others)
+        .map(|sample| sample.0)
+        .fold(1.0, f64::max);
+    let max_bytes = samples
+        .iter()
+        .map(|&(_, rss, vsz)| rss.max(vsz))
+        .chain(others.iter().map(|&(_, rss, _)| rss))
+        .chain(threshold.map(|t| t.as_u64()))
+        .max()
+        .unwrap_or(0)
+        .max(1) as f64
+        * 1.05;
+    let x = |ms: f64| LEFT + (WIDTH - LEFT - RIGHT) * ms / max_ms;
+    let y = |bytes: f64| TOP + (HEIGHT - TOP - BOTTOM) * (1.0 - bytes / max_bytes);
+
+    for i in 0..=4 {
+        let bytes = max_bytes * f64::from(i) / 4.0;
+        let ms = max_ms * f64::from(i) / 4.0;
+        writeln_str!(
+            svg,
+            "<line x1=\"{LEFT}\" x2=\"{}\" y1=\"{y:.1}\" y2=\"{y:.1}\" stroke=\"#eee\"/>\
+             <text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\" fill=\"#666\">{}</text>\
+             <text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\" fill=\"#666\">{}</text>",
+            WIDTH - RIGHT,
+            LEFT - 5.0,
+            y(bytes) + 4.0,
+            format_bytes(bytes.round() as u64, units),
+            x(ms),
+            HEIGHT - 10.0,
+            seconds(ms),
+            y = y(bytes),
+        );
+    }
+
+    let mut line = |samples: &[(f64, u64, u64)],
+                    color: &str,
+                    dashes: &str,
+                    bytes: &dyn Fn(&(f64, u64, u64)) -> u64| {
+        let points: Vec<String> = samples
+            .iter()
+            .map(|sample| format!("{:.1},{:.1}", x(sample.0), y(bytes(sample) as f64)))
+            .collect();
+        writeln_str!(
+            svg,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"{color}\" stroke-width=\"1.5\"{dashes}/>",
+            points.join(" ")
+        );
+    };
+    line(&samples, VSZ_COLOR, "", &|sample| sample.2);
+    if let Some((_, others)) = &other {
+        line(
+            others,
+            OTHER_COLOR,
+            " stroke-dasharray=\"5 3\"",
+            &|sample| sample.1,
+        );
+    }
+    line(&samples, RSS_COLOR, "", &|sample| sample.1);
+
+    if let Some(threshold) = threshold {
+        writeln_str!(
+            svg,
+            "<line x1=\"{LEFT}\" x2=\"{}\" y1=\"{y:.1}\" y2=\"{y:.1}\" stroke=\"{PEAK_COLOR}\" \
+             stroke-dasharray=\"4 3\"/>\
+             <text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\" fill=\"{PEAK_COLOR}\">threshold {}</text>",
+            WIDTH - RIGHT,
+            WIDTH - RIGHT,
+            y(threshold.as_u64() as f64) - 4.0,
+            format_bytes(threshold.as_u64(), units),
+            y = y(threshold.as_u64() as f64),
+        );
+    }
+
+    // The first sample at the peak, when it was reached
+    if let Some(&(ms, rss, _)) = samples.iter().rev().max_by_key(|sample| sample.1) {
+        let (cx, cy) = (x(ms), y(rss as f64));
+        // Keep the label inside the chart when the peak is near its end
+        let (label_x, anchor) = if cx > WIDTH * 0.7 {
+            (cx - 7.0, "end")
+        } else {
+            (cx + 7.0, "start")
+        };
+        writeln_str!(
+            svg,
+            "<circle cx=\"{cx:.1}\" cy=\"{cy:.1}\" r=\"4\" fill=\"{PEAK_COLOR}\"/>\
+             <text x=\"{label_x:.1}\" y=\"{:.1}\" text-anchor=\"{anchor}\" fill=\"{PEAK_COLOR}\">\
+             peak {} at {}</text>",
+            cy + 14.0,
+            format_bytes(rss, units),
+            seconds(ms)
+        );
+    }
+
+    writeln_str!(
+        svg,
+        "<rect x=\"{LEFT}\" y=\"8\" width=\"10\" height=\"3\" fill=\"{RSS_COLOR}\"/>\
+         <text x=\"{}\" y=\"13\" fill=\"#666\">RSS</text>\
+         <rect x=\"{}\" y=\"8\" width=\"10\" height=\"3\" fill=\"{VSZ_COLOR}\"/>\
+         <text x=\"{}\" y=\"13\" fill=\"#666\">VSZ</text>",
+        LEFT + 14.0,
+        LEFT + 50.0,
+        LEFT + 64.0
+    );
+    if let Some((label, _)) = &other {
+        writeln_str!(
+            svg,
+            "<rect x=\"{}\" y=\"8\" width=\"10\" height=\"3\" fill=\"{OTHER_COLOR}\"/>\
+             <text x=\"{}\" y=\"13\" fill=\"#666\">RSS of {}</text>",
+            LEFT + 100.0,
+            LEFT + 114.0,
+            escape(label)
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// The timeline of a run, as charted.
+fn chart_points(result: &MonitorResult) -> Vec<ChartPoint> {
+    let start = result.started_at();
+    result
+        .timeline
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .map(|sample| {
+            (
+                sample.timestamp.duration_since(start).as_millis() as u64,
+                sample.rss_bytes,
+                sample.vsz_bytes,
+            )
+        })
+        .collect()
+}
+
+/// Renders RSS over a run in block characters, `width` columns wide
+/// including the axis labels.
+///
+/// Each column shows the highest RSS sampled in its share of the run; a
+/// column with no sample in it continues the one before.
+///
+/// # Arguments
+/// * `result` - The run's results, with its timeline
+/// * `width` - Width of the terminal
+/// * `units` - Optional fixed memory unit to use for the labels
+pub fn terminal_chart(result: &MonitorResult, width: usize, units: Option<MemoryUnit>) -> String {
+    terminal_timeline(&chart_points(result), width, units, None)
+}
+
+/// Renders RSS over a timeline in block characters, as
+/// [`terminal_chart`] does for a run.
+///
+/// # Arguments
+/// * `points` - The timeline
+/// * `width` - Width of the terminal
+/// * `units` - Optional fixed memory unit to use for the labels
+/// * `scale` - The length of the time axis (in milliseconds) and the top of the
+///   RSS axis, for charts drawn to be compared; the timeline's own otherwise
+pub fn terminal_timeline(
+    points: &[ChartPoint],
+    width: usize,
+    units: Option<MemoryUnit>,
+    scale: Option<(u64, u64)>,
+) -> String {
+    let seconds = |ms: u64| format!("{:.2}s", ms as f64 / 1000.0);
+
+    let samples: Vec<(u64, u64)> = points.iter().map(|&(ms, rss, _)| (ms, rss)).collect();
+    let Some(&(peak_ms, peak)) = samples.iter().rev().max_by_key(|sample| sample.1) else {
+        return "No samples were recorded\n".to_string();
+    };
+    let (end_ms, top) = scale.unwrap_or((samples.last().map_or(0, |sample| sample.0), peak));
+
+    let top_label = format_bytes(top, units);
+    let bottom_label = format_bytes(0, units);
+    let label_width = top_label.chars().count().max(bottom_label.chars().count());
+    let columns = width.saturating_sub(label_width + 2).max(10);
+
+    let mut levels = vec![None; columns];
+    let column = |ms: u64| (ms * columns as u64 / end_ms.max(1)).min(columns as u64 - 1) as usize;
+    for &(ms, rss) in &samples {
+        levels[column(ms)] = levels[column(ms)].max(Some(rss));
+    }
+    // On a longer time axis, nothing is drawn past the last sample
+    let last_column = samples.last().map_or(0, |sample| column(sample.0));
+    let mut previous = 0;
+    let heights: Vec<usize> = levels
+        .into_iter()
+        .enumerate()
+        .map(|(index, rss)| {
+            if index > last_column {
+                return 0;
+            }
+            let rss = rss.unwrap_or(previous);
+            previous = rss;
+            // Any memory at all shows
+            (rss as f64 / top.max(1) as f64 * (TERM_ROWS * 8) as f64).ceil() as usize
+        })
+        .collect();
+
+    let mut chart = format!(
+        "RSS over time (peak {} at {}):\n",
+        format_bytes(peak, units),
+        seconds(peak_ms)
+    );
+    for row in (0..TERM_ROWS).rev() {
+        let label = match row {
+            _ if row == TERM_ROWS - 1 => &top_label,
+            0 => &bottom_label,
+            _ => "",
+        };
+        let bars: String = heights
+            .iter()
+            .map(|&height| BLOCKS[height.saturating_sub(row * 8).min(8)])
+            .collect();
+        writeln_str!(chart, "{label:>label_width$} ┤{}", bars.trim_end());
+    }
+    writeln_str!(chart, "{:label_width$} └{}", "", "─".repeat(columns));
+    let (first, last) = (seconds(0), seconds(end_ms));
+    writeln_str!(
+        chart,
+        "{:label_width$}  {first}{last:>gap$}",
+        "",
+        gap = columns.saturating_sub(first.len()).max(last.len() + 1)
+    );
+    chart
+}
+
+/// Sums up RSS over a timeline in at most `width` block characters,
+/// from the lowest RSS sampled to the highest.
+///
+/// # Returns
+/// * `None` - Fewer than two samples were taken
+pub fn sparkline(timeline: &[MemoryUsage], width: usize) -> Option<String> {
+    let (first, last) = (timeline.first()?, timeline.last()?);
+    if timeline.len() < 2 || width == 0 {
+        return None;
+    }
+    let span = last
+        .timestamp
+        .duration_since(first.timestamp)
+        .as_micros()
+        .max(1);
+    let columns = width.min(timeline.len());
+
+    // The highest RSS in each column's share of the run
+    let mut levels = vec![None; columns];
+    for sample in timeline {
+        let offset = sample.timestamp.duration_since(first.timestamp).as_micros();
+        let column = (offset * columns as u128 / span).min(columns as u128 - 1) as usize;
+        levels[column] = levels[column].max(Some(sample.rss_bytes));
+    }
+    let low = timeline.iter().map(|s| s.rss_bytes).min().unwrap_or(0);
+    let high = timeline.iter().map(|s| s.rss_bytes).max().unwrap_or(0);
+
+    let mut previous = first.rss_bytes;
+    Some(
+        levels
+            .into_iter()
+            .map(|rss| {
+                let rss = rss.unwrap_or(previous);
+                previous = rss;
+                block(rss, low, high)
+            })
+            .collect(),
+    )
+}
+
+/// The block character of `value` on a scale from `low` to `high`.
+pub(super) fn block(value: u64, low: u64, high: u64) -> char {
+    let level = value.saturating_sub(low) as f64 / high.saturating_sub(low).max(1) as f64;
+    BLOCKS[1 + (level.min(1.0) * 7.0).round() as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Timestamp;
+
+    #[test]
+    fn test_svg_chart() {
+        let result: MonitorResult = serde_json::from_str(
+            r#"{"command": "./app <x>", "peak_rss_bytes": 2048,
+                "peak_vsz_bytes": 4096, "duration_ms": 1000, "exit_code": 0,
+                "threshold_exceeded": false, "timestamp": "1970-01-01T00:00:02Z",
+                "timeline": [
+                    {"rss_bytes": 1024, "vsz_bytes": 4096, "timestamp": "1970-01-01T00:00:01Z"},
+                    {"rss_bytes": 2048, "vsz_bytes": 4096, "timestamp": "1970-01-01T00:00:01.500Z"},
+                    {"rss_bytes": 2048, "vsz_bytes": 4096, "timestamp": "1970-01-01T00:00:02Z"}
+                ]}"#,
+        )
+        .unwrap();
+
+        let svg = svg_chart(&result, Some(ByteSize::b(3072)), None);
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert!(svg.contains("<title>Memory usage of ./app &lt;x&gt;</title>"));
+        // The peak is annotated where it was first reached
+        assert!(svg.contains("peak 2.0 KiB at 0.50s"));
+        assert!(svg.contains("threshold 3.0 KiB"));
+        assert_eq!(svg.matches("<polyline").count(), 2);
+
+        let other = [(0, 1024, 0), (2000, 4096, 0)];
+        let svg = svg_timeline(
+            "run",
+            &chart_points(&result),
+            Some(("b.json", &other)),
+            None,
+            None,
+        );
+        assert!(svg.contains("RSS of b.json"));
+        assert_eq!(svg.matches("<polyline").count(), 3);
+
+        let empty = MonitorResult {
+            timeline: None,
+            ..result
+        };
+        assert!(svg_chart(&empty, None, None).contains("No samples were recorded"));
+    }
+
+    #[test]
+    fn test_sparkline() {
+        let timeline: Vec<MemoryUsage> = [10, 20, 80, 80, 50, 10]
+            .iter()
+            .enumerate()
+            .map(|(i, &rss)| {
+                MemoryUsage::new(rss, 0, Timestamp::from_unix_micros(i as u64 * 100_000))
+            })
+            .collect();
+        assert_eq!(sparkline(&timeline, 20).unwrap(), "▁▂██▅▁");
+        // Three columns of two samples each
+        assert_eq!(sparkline(&timeline, 3).unwrap(), "▂█▅");
+        assert_eq!(sparkline(&timeline[..1], 20), None);
+    }
+
+    #[test]
+    fn test_terminal_chart() {
+        let result: MonitorResult = serde_json::from_str(
+            r#"{"command": "./app", "peak_rss_bytes": 2048,
+                "peak_vsz_bytes": 4096, "duration_ms": 1000, "exit_code": 0,
+                "threshold_exceeded": false, "timestamp": "1970-01-01T00:00:02Z",
+                "timeline": [
+                    {"rss_bytes": 1024, "vsz_bytes": 4096, "timestamp": "1970-01-01T00:00:01Z"},
+                    {"rss_bytes": 2048, "vsz_bytes": 4096, "timestamp": "1970-01-01T00:00:01.500Z"},
+                    {"rss_bytes": 0, "vsz_bytes": 4096, "timestamp": "1970-01-01T00:00:02Z"}
+                ]}"#,
+        )
+        .unwrap();
+
+        let chart = terminal_chart(&result, 20, None);
+        let lines: Vec<&str> = chart.lines().collect();
+        assert_eq!(lines[0], "RSS over time (peak 2.0 KiB at 0.50s):");
+        // Half the peak until 0.5s, the peak until the last sample at zero
+        assert_eq!(lines[1], "2.0 KiB ┤     █████");
+        assert_eq!(lines[4], "        ┤     █████");
+        assert_eq!(lines[5], "        ┤██████████");
+        assert_eq!(lines[8], "    0 B ┤██████████");
+        assert_eq!(lines[9], "        └───────────");
+        assert_eq!(lines[10], "         0.00s 1.00s");
+
+        let empty = MonitorResult {
+            timeline: None,
+            ..result
+        };
+        assert_eq!(
+            terminal_chart(&empty, 80, None),
+            "No samples were recorded\n"
+        );
+    }
+}
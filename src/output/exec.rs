@@ -0,0 +1,98 @@
+//! Report formats provided by another program, for `--format exec:CMD`.
+//!
+//! The command is run with `sh -c` once the results are in, gets them on
+//! stdin as `--json` prints them, and whatever it writes to stdout is the
+//! output, so a custom format needs no change to peak-mem. Its stderr is
+//! peak-mem's.
+
+use crate::types::{PeakMemError, Result};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+/// An external formatter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecFormat {
+    command: String,
+}
+
+impl ExecFormat {
+    /// Runs the formatter on the results.
+    ///
+    /// # Arguments
+    /// * `json` - The results, as `--json` prints them
+    ///
+    /// # Returns
+    /// * What the command wrote to stdout
+    ///
+    /// # Errors
+    /// * `PeakMemError::ProcessSpawn` - The shell couldn't be started
+    /// * `PeakMemError::Runtime` - The command failed
+    pub fn run(&self, json: &[u8]) -> Result<Vec<u8>> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                PeakMemError::ProcessSpawn(format!("--format command '{}': {e}", self.command))
+            })?;
+
+        // Written from another thread, so that a formatter that writes
+        // before reading everything can't block both sides
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let input = json.to_vec();
+        let writer = std::thread::spawn(move || {
+            // A formatter may stop reading early; that is its business
+            let _ = stdin.write_all(&input);
+        });
+        let output = child.wait_with_output()?;
+        let _ = writer.join();
+
+        if !output.status.success() {
+            return Err(PeakMemError::Runtime(format!(
+                "--format command '{}' failed ({})",
+                self.command, output.status
+            )));
+        }
+        Ok(output.stdout)
+    }
+}
+
+impl FromStr for ExecFormat {
+    type Err = PeakMemError;
+
+    /// Parses `exec:COMMAND`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            Some(("exec", command)) if !command.trim().is_empty() => Ok(Self {
+                command: command.to_string(),
+            }),
+            _ => Err(PeakMemError::InvalidArgument(format!(
+                "Invalid format '{s}'. Use exec:COMMAND"
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert!("exec:jq .peak_rss_bytes".parse::<ExecFormat>().is_ok());
+        assert!("exec:".parse::<ExecFormat>().is_err());
+        assert!("json".parse::<ExecFormat>().is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        let format: ExecFormat = "exec:tr a-z A-Z".parse().unwrap();
+        assert_eq!(format.run(b"{\"a\": 1}").unwrap(), b"{\"A\": 1}");
+
+        let failing: ExecFormat = "exec:cat >/dev/null; exit 3".parse().unwrap();
+        assert!(failing.run(b"{}").is_err());
+    }
+}
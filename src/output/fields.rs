@@ -0,0 +1,132 @@
+//! Selectable result fields for `--fields` output.
+//!
+//! `--fields` prints chosen values as one tab-separated line in the order
+//! given, for shell pipelines that need more than `--quiet` but don't want
+//! to parse JSON. Names follow the JSON fields, with `peak_rss` and
+//! `peak_vsz` accepted as short forms.
+
+use crate::types::{MonitorResult, PeakMemError, Result, TimestampFormat};
+use std::str::FromStr;
+
+/// A single value that can be selected with `--fields`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Command,
+    PeakRss,
+    PeakVsz,
+    DurationMs,
+    ExitCode,
+    ThresholdExceeded,
+    MinorFaults,
+    MajorFaults,
+    PeakThreadCount,
+    UserTimeMs,
+    SystemTimeMs,
+    CpuUtilizationPercent,
+    DiskReadBytes,
+    DiskWriteBytes,
+    PeakGpuBytes,
+    Timestamp,
+}
+
+impl Field {
+    /// Every field with its canonical name, in documentation order.
+    const ALL: [(Field, &'static str); 16] = [
+        (Field::Command, "command"),
+        (Field::PeakRss, "peak_rss_bytes"),
+        (Field::PeakVsz, "peak_vsz_bytes"),
+        (Field::DurationMs, "duration_ms"),
+        (Field::ExitCode, "exit_code"),
+        (Field::ThresholdExceeded, "threshold_exceeded"),
+        (Field::MinorFaults, "minor_faults"),
+        (Field::MajorFaults, "major_faults"),
+        (Field::PeakThreadCount, "peak_thread_count"),
+        (Field::UserTimeMs, "user_time_ms"),
+        (Field::SystemTimeMs, "system_time_ms"),
+        (Field::CpuUtilizationPercent, "cpu_utilization_percent"),
+        (Field::DiskReadBytes, "disk_read_bytes"),
+        (Field::DiskWriteBytes, "disk_write_bytes"),
+        (Field::PeakGpuBytes, "peak_gpu_bytes"),
+        (Field::Timestamp, "timestamp"),
+    ];
+
+    /// Returns the field's value as printed by `--fields`. Values the run
+    /// doesn't have, such as the exit code of a signalled command, are
+    /// empty.
+    pub fn value(&self, result: &MonitorResult, timestamps: TimestampFormat) -> String {
+        match self {
+            // Tabs and newlines would break the line apart
+            Field::Command => result.command.replace(['\t', '\n'], " "),
+            Field::PeakRss => result.peak_rss_bytes.to_string(),
+            Field::PeakVsz => result.peak_vsz_bytes.to_string(),
+            Field::DurationMs => result.duration_ms.to_string(),
+            Field::ExitCode => result.exit_code.map_or(String::new(), |c| c.to_string()),
+            Field::ThresholdExceeded => result.threshold_exceeded.to_string(),
+            Field::MinorFaults => result.minor_faults.to_string(),
+            Field::MajorFaults => result.major_faults.to_string(),
+            Field::PeakThreadCount => result.peak_thread_count.to_string(),
+            Field::UserTimeMs => result.user_time_ms.to_string(),
+            Field::SystemTimeMs => result.system_time_ms.to_string(),
+            Field::CpuUtilizationPercent => format!("{:.1}", result.cpu_utilization_percent),
+            Field::DiskReadBytes => result.disk_read_bytes.to_string(),
+            Field::DiskWriteBytes => result.disk_write_bytes.to_string(),
+            Field::PeakGpuBytes => result
+                .peak_gpu_bytes
+                .map_or(String::new(), |b| b.to_string()),
+            Field::Timestamp => timestamps.render(result.timestamp, result.started_at()),
+        }
+    }
+
+    /// Formats the selected fields of a result as one tab-separated line.
+    pub fn line(fields: &[Field], result: &MonitorResult, timestamps: TimestampFormat) -> String {
+        fields
+            .iter()
+            .map(|field| field.value(result, timestamps))
+            .collect::<Vec<_>>()
+            .join("\t")
+    }
+}
+
+impl FromStr for Field {
+    type Err = PeakMemError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let name = match s.trim() {
+            "peak_rss" => "peak_rss_bytes",
+            "peak_vsz" => "peak_vsz_bytes",
+            name => name,
+        };
+
+        Field::ALL
+            .iter()
+            .find(|(_, candidate)| *candidate == name)
+            .map(|(field, _)| *field)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Field::ALL.iter().map(|(_, name)| *name).collect();
+                PeakMemError::InvalidArgument(format!(
+                    "Unknown field '{s}'. Use one of: {}",
+                    names.join(", ")
+                ))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_field() {
+        assert_eq!("peak_rss".parse::<Field>().unwrap(), Field::PeakRss);
+        assert_eq!("peak_rss_bytes".parse::<Field>().unwrap(), Field::PeakRss);
+        assert_eq!("exit_code".parse::<Field>().unwrap(), Field::ExitCode);
+        assert!("peak".parse::<Field>().is_err());
+    }
+
+    #[test]
+    fn test_every_field_parses_by_name() {
+        for (field, name) in Field::ALL {
+            assert_eq!(name.parse::<Field>().unwrap(), field);
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! GitHub Actions integration, for `--github`.
+//!
+//! A tripped threshold or a regression becomes an `::error` workflow
+//! command, which the runner shows as an annotation on the run, and a
+//! comparison made in a noisy environment or on a throttled CPU a
+//! `::warning`. The results are
+//! also tabulated in Markdown for the job summary, next to the baseline if
+//! one was compared.
+
+use super::report::{comparison_table, regressed_metrics};
+use super::{format_bytes, writeln_str};
+use crate::baseline::ComparisonResult;
+use crate::cli::MemoryUnit;
+use crate::types::{ByteSize, MonitorResult};
+
+/// Formats the workflow commands for a run, one per line; empty if
+/// nothing needs an annotation.
+///
+/// # Arguments
+/// * `result` - The run's results
+/// * `threshold` - The `--threshold`, if one was set
+/// * `comparison` - The baseline's name and the comparison against it, if one
+///   was made
+/// * `units` - Optional fixed memory unit to use for display
+pub fn github_annotations(
+    result: &MonitorResult,
+    threshold: Option<ByteSize>,
+    comparison: Option<(&str, &ComparisonResult)>,
+    units: Option<MemoryUnit>,
+) -> String {
+    let mut commands = String::new();
+    let mut command = |level: &str, title: &str, message: String| {
+        writeln_str!(
+            commands,
+            "::{level} title={}::{}",
+            escape_property(title),
+            escape_data(&message)
+        );
+    };
+    if let (Some(threshold), true) = (threshold, result.threshold_exceeded) {
+        command(
+            "error",
+            "Memory threshold exceeded",
+            format!(
+                "Peak RSS of {} was {}, above the threshold of {}",
+                result.command,
+                format_bytes(result.peak_rss_bytes, units),
+                format_bytes(threshold.as_u64(), units)
+            ),
+        );
+    }
+    if let Some((name, comparison)) = comparison {
+        if comparison.regression_detected {
+            command(
+                "error",
+                "Memory regression",
+                format!(
+                    "{} regressed in {} against baseline {name} (peak RSS {} to {}, {:+.1}%)",
+                    result.command,
+                    regressed_metrics(comparison).join(", "),
+                    format_bytes(comparison.baseline.peak_rss_bytes, units),
+                    format_bytes(result.peak_rss_bytes, units),
+                    comparison.rss_diff_percent
+                ),
+            );
+        }
+        if comparison.noisy_environment {
+            command(
+                "warning",
+                "Noisy environment",
+                format!(
+                    "High system memory pressure during the run of {} or its baseline {name}; \
+                     the comparison may be noisy",
+                    result.command
+                ),
+            );
+        }
+        if comparison.throttled {
+            command(
+                "warning",
+                "CPU throttled",
+                format!(
+                    "The CPU was throttled during the run of {} or its baseline {name}; \
+                     duration and CPU time may not be comparable",
+                    result.command
+                ),
+            );
+        }
+        if let Some(other) = &comparison.other_platform {
+            command(
+                "warning",
+                "Other platform",
+                format!(
+                    "Baseline {name} of {} was recorded on {other}; \
+                     the comparison measures the platform as much as the code",
+                    result.command
+                ),
+            );
+        }
+    }
+    commands
+}
+
+/// Formats the results as Markdown for `$GITHUB_STEP_SUMMARY`.
+///
+/// # Arguments
+/// * `result` - The run's results
+/// * `comparison` - The baseline's name and the comparison against it, if one
+///   was made, which replace the results table with one against the baseline
+/// * `units` - Optional fixed memory unit to use for display
+pub fn github_summary(
+    result: &MonitorResult,
+    comparison: Option<(&str, &ComparisonResult)>,
+    units: Option<MemoryUnit>,
+) -> String {
+    let mut summary = String::new();
+    writeln_str!(summary, "### Memory usage of `{}`\n", result.command);
+    match comparison {
+        Some((name, comparison)) => {
+            let verdict = if comparison.regression_detected {
+                format!(
+                    ":x: Regressed in {}",
+                    regressed_metrics(comparison).join(", ")
+                )
+            } else {
+                ":white_check_mark: No regression".to_string()
+            };
+            writeln_str!(summary, "{verdict} against baseline `{name}`.\n");
+            summary.push_str(&comparison_table(comparison, units));
+        }
+        None => {
+            writeln_str!(summary, "| Metric | Value |");
+            writeln_str!(summary, "|---|---|");
+            let mut row = |metric: &str, value: String| {
+                writeln_str!(summary, "| {metric} | {value} |");
+            };
+            let seconds = |ms: u64| format!("{:.2}s", ms as f64 / 1000.0);
+            row("Peak RSS", format_bytes(result.peak_rss_bytes, units));
+            row("Peak VSZ", format_bytes(result.peak_vsz_bytes, units));
+            row("Duration", seconds(result.duration_ms));
+            row("CPU time", seconds(result.cpu_time_ms()));
+            if let Some(gpu) = result.peak_gpu_bytes {
+                row("Peak GPU memory", format_bytes(gpu, units));
+            }
+        }
+    }
+    if result.threshold_exceeded {
+        writeln_str!(summary, "\n:x: The memory threshold was exceeded.");
+    }
+    summary
+}
+
+/// Escapes the message of a workflow command.
+fn escape_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a property of a workflow command, such as its title.
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::baseline::{Baseline, RegressionThresholds};
+
+    #[test]
+    fn test_github_annotations() {
+        let current = MonitorResult {
+            threshold_exceeded: true,
+            ..MonitorResult::fixture("./app", 2048)
+        };
+        let annotations = github_annotations(&current, Some(ByteSize::b(1024)), None, None);
+        assert_eq!(
+            annotations,
+            "::error title=Memory threshold exceeded::\
+             Peak RSS of ./app was 2.0 KiB, above the threshold of 1.0 KiB\n"
+        );
+        assert!(github_annotations(
+            &MonitorResult::fixture("./app", 512),
+            Some(ByteSize::b(1024)),
+            None,
+            None
+        )
+        .is_empty());
+
+        let baseline = Baseline::from(&MonitorResult::fixture("./app", 1024));
+        let comparison = ComparisonResult::new(baseline, current, RegressionThresholds::rss(10.0));
+        let annotations =
+            github_annotations(&comparison.current, None, Some(("main", &comparison)), None);
+        assert!(annotations.starts_with("::error title=Memory regression::"));
+        assert!(annotations.contains("in peak RSS against baseline main"));
+    }
+
+    #[test]
+    fn test_github_summary() {
+        let summary = github_summary(
+            &MonitorResult {
+                duration_ms: 1500,
+                ..MonitorResult::fixture("./app", 2048)
+            },
+            None,
+            None,
+        );
+        assert!(summary.starts_with("### Memory usage of `./app`"));
+        assert!(summary.contains("| Peak RSS | 2.0 KiB |"));
+        assert!(summary.contains("| Duration | 1.50s |"));
+        let summary = github_summary(
+            &MonitorResult::fixture("./app", 2048),
+            None,
+            Some(MemoryUnit::Bytes),
+        );
+        assert!(summary.contains("| Peak RSS | 2048 B |"));
+
+        let baseline = Baseline::from(&MonitorResult::fixture("./app", 1024));
+        let comparison = ComparisonResult::new(
+            baseline,
+            MonitorResult::fixture("./app", 2048),
+            RegressionThresholds::rss(10.0),
+        );
+        let summary = github_summary(&comparison.current, Some(("main", &comparison)), None);
+        assert!(summary.contains(":x: Regressed in peak RSS against baseline `main`."));
+        assert!(summary.contains("| Peak RSS | 1.0 KiB | 2.0 KiB | +100.0% |"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape_data("100%\nnext"), "100%25%0Anext");
+        assert_eq!(escape_property("a: b, c"), "a%3A b%2C c");
+    }
+}
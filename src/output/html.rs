@@ -0,0 +1,480 @@
+//! Self-contained HTML reports, for `--report`.
+//!
+//! One file that opens in any browser and can be attached to an issue or
+//! a chat: a summary of the run, a chart of its timeline with the peak
+//! marked and the threshold and baseline peak drawn in, and the process
+//! tree at the peak. The chart is drawn by a short script embedded in the
+//! page from the samples embedded next to it, so nothing is loaded from
+//! elsewhere.
+
+use super::{format_bytes, writeln_str};
+use crate::baseline::ComparisonResult;
+use crate::cli::MemoryUnit;
+use crate::types::{ByteSize, MonitorResult, ProcessMemoryInfo};
+
+const STYLE: &str = r#"
+body { font: 14px/1.4 system-ui, sans-serif; margin: 2em auto; max-width: 60em; padding: 0 1em; color: #222; }
+h1 { font-size: 1.4em; } h1 code { font-size: 0.9em; }
+table.summary td { padding: 0.15em 1.5em 0.15em 0; }
+table.summary td:first-child { color: #666; }
+#chart { width: 100%; height: 320px; }
+#chart text { font-size: 11px; fill: #666; }
+#cursor { width: 100%; }
+table.processes th, table.processes td { padding: 0.1em 1.5em 0.1em 0; text-align: left; }
+table.processes td:last-child { text-align: right; }
+#tooltip { position: absolute; display: none; background: #fff; border: 1px solid #ccc; padding: 0.3em 0.5em; font-size: 12px; pointer-events: none; }
+.tree ul { list-style: none; padding-left: 1.2em; margin: 0; }
+.tree > ul { padding-left: 0; }
+.tree .rss { color: #666; margin-left: 0.5em; }
+.regressed { color: #c00; } .passed { color: #080; }
+"#;
+
+/// Draws the chart into `#chart` from the JSON in `#data`: the samples as
+/// `[ms, rss, vsz]`, the peak, the optional threshold and baseline peak,
+/// the baseline's samples as `[ms, rss]` and the processes of each sample
+/// as `[ms, [[pid, name, rss], ...]]`. The cursor, moved by dragging it or
+/// the slider, picks the processes listed in `#processes`.
+const SCRIPT: &str = r##"
+const data = JSON.parse(document.getElementById("data").textContent);
+const svg = document.getElementById("chart");
+const tooltip = document.getElementById("tooltip");
+const slider = document.getElementById("cursor");
+const ns = "http://www.w3.org/2000/svg";
+const units = ["B", "KiB", "MiB", "GiB", "TiB"];
+const s = data.samples, base = data.baseline_samples || [];
+let cursorMs = data.peak ? data.peak[0] : 0, dragging = false;
+function bytes(n) {
+  let i = 0;
+  while (n >= 1024 && i < units.length - 1) { n /= 1024; i++; }
+  return (i ? n.toFixed(1) : n) + " " + units[i];
+}
+function seconds(ms) { return (ms / 1000).toFixed(ms < 10000 ? 2 : 1) + "s"; }
+function nearest(points, ms) {
+  let best = points[0];
+  for (const p of points) if (Math.abs(p[0] - ms) < Math.abs(best[0] - ms)) best = p;
+  return best;
+}
+function el(name, attrs, text) {
+  const e = document.createElementNS(ns, name);
+  for (const k in attrs) e.setAttribute(k, attrs[k]);
+  if (text !== undefined) e.textContent = text;
+  svg.appendChild(e);
+  return e;
+}
+const maxMs = Math.max(1, s.length ? s[s.length - 1][0] : 0, base.length ? base[base.length - 1][0] : 0);
+const left = 70, right = 10, top = 10, bottom = 25;
+let x = ms => ms, msAt = px => px;
+function draw() {
+  svg.replaceChildren();
+  const showVsz = document.getElementById("vsz").checked;
+  const w = svg.clientWidth, h = svg.clientHeight;
+  if (!s.length) {
+    el("text", { x: w / 2, y: h / 2, "text-anchor": "middle" }, "No samples were recorded");
+    return;
+  }
+  let maxBytes = Math.max(...s.map(p => showVsz ? Math.max(p[1], p[2]) : p[1]), ...base.map(p => p[1]));
+  if (data.threshold) maxBytes = Math.max(maxBytes, data.threshold);
+  if (data.baseline) maxBytes = Math.max(maxBytes, data.baseline);
+  maxBytes = Math.max(1, maxBytes * 1.05);
+  x = ms => left + (w - left - right) * ms / maxMs;
+  msAt = px => Math.min(maxMs, Math.max(0, (px - left) / (w - left - right) * maxMs));
+  const y = b => top + (h - top - bottom) * (1 - b / maxBytes);
+  for (let i = 0; i <= 4; i++) {
+    const b = maxBytes * i / 4;
+    el("line", { x1: left, x2: w - right, y1: y(b), y2: y(b), stroke: "#eee" });
+    el("text", { x: left - 5, y: y(b) + 4, "text-anchor": "end" }, bytes(Math.round(b)));
+    const ms = maxMs * i / 4;
+    el("text", { x: x(ms), y: h - 8, "text-anchor": "middle" }, seconds(ms));
+  }
+  const line = (points, index, color, dash) => el("polyline", {
+    points: points.map(p => x(p[0]) + "," + y(p[index])).join(" "),
+    fill: "none", stroke: color, "stroke-width": 1.5, "stroke-dasharray": dash || "none",
+  });
+  if (showVsz) line(s, 2, "#9bb");
+  if (base.length) line(base, 1, "#888", "5 3");
+  line(s, 1, "#1f6feb");
+  const level = (bytes_, color, label) => {
+    el("line", { x1: left, x2: w - right, y1: y(bytes_), y2: y(bytes_), stroke: color, "stroke-dasharray": "4 3" });
+    el("text", { x: w - right, y: y(bytes_) - 4, "text-anchor": "end", style: "fill:" + color }, label + " " + bytes(bytes_));
+  };
+  if (data.threshold) level(data.threshold, "#c00", "threshold");
+  if (data.baseline) level(data.baseline, "#888", "baseline peak");
+  if (data.peak) {
+    el("circle", { cx: x(data.peak[0]), cy: y(data.peak[1]), r: 4, fill: "#c00" });
+    el("text", { x: x(data.peak[0]) + 6, y: y(data.peak[1]) + 12 }, "peak " + bytes(data.peak[1]));
+  }
+  const hover = el("line", { y1: top, y2: h - bottom, stroke: "#ccc", visibility: "hidden" });
+  el("line", { x1: x(cursorMs), x2: x(cursorMs), y1: top, y2: h - bottom, stroke: "#444", "stroke-width": 2, style: "cursor:ew-resize" });
+  svg.onmousemove = event => {
+    const ms = msAt(event.clientX - svg.getBoundingClientRect().left);
+    if (dragging) { moveCursor(ms); return; }
+    const current = nearest(s, ms);
+    hover.setAttribute("x1", x(current[0]));
+    hover.setAttribute("x2", x(current[0]));
+    hover.setAttribute("visibility", "visible");
+    let text = seconds(current[0]) + ": RSS " + bytes(current[1]) + ", VSZ " + bytes(current[2]);
+    if (base.length) text += "; baseline RSS " + bytes(nearest(base, ms)[1]);
+    tooltip.style.display = "block";
+    tooltip.style.left = (event.pageX + 12) + "px";
+    tooltip.style.top = (event.pageY + 12) + "px";
+    tooltip.textContent = text;
+  };
+  svg.onmouseleave = () => {
+    hover.setAttribute("visibility", "hidden");
+    tooltip.style.display = "none";
+  };
+}
+function moveCursor(ms) {
+  cursorMs = ms;
+  slider.value = ms;
+  draw();
+  table();
+}
+function table() {
+  const body = document.getElementById("processes");
+  if (!body || !data.processes.length) return;
+  const [ms, processes] = nearest(data.processes, cursorMs);
+  document.getElementById("instant").textContent = seconds(ms);
+  body.replaceChildren();
+  for (const [pid, name, rss] of [...processes].sort((a, b) => b[2] - a[2])) {
+    const row = body.insertRow();
+    row.insertCell().textContent = pid;
+    row.insertCell().textContent = name;
+    row.insertCell().textContent = bytes(rss);
+  }
+}
+svg.onmousedown = event => {
+  dragging = true;
+  tooltip.style.display = "none";
+  moveCursor(msAt(event.clientX - svg.getBoundingClientRect().left));
+};
+window.onmouseup = () => { dragging = false; };
+slider.max = maxMs;
+slider.value = cursorMs;
+slider.oninput = () => moveCursor(Number(slider.value));
+document.getElementById("vsz").onchange = draw;
+window.onresize = draw;
+draw();
+table();
+"##;
+
+/// Renders the report for a run.
+///
+/// # Arguments
+/// * `result` - The run's results, with its timeline and process tree if they
+///   were kept
+/// * `comparison` - The baseline's name and the comparison against it, if one
+///   was made, which add the baseline's peak to the chart and summary
+/// * `threshold` - The `--threshold`, if one was set, drawn into the chart
+/// * `units` - Optional fixed memory unit to use for display
+pub fn html_report(
+    result: &MonitorResult,
+    comparison: Option<(&str, &ComparisonResult)>,
+    threshold: Option<ByteSize>,
+    units: Option<MemoryUnit>,
+) -> String {
+    let mut html = String::new();
+    writeln_str!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>peak-mem: {}</title>\n<style>{STYLE}</style>\n</head>\n<body>",
+        escape(&result.command)
+    );
+    writeln_str!(
+        html,
+        "<h1>Memory usage of <code>{}</code></h1>",
+        escape(&result.command)
+    );
+
+    writeln_str!(html, "<table class=\"summary\">");
+    let mut row = |label: &str, value: String| {
+        writeln_str!(html, "<tr><td>{label}</td><td>{value}</td></tr>");
+    };
+    row("Peak RSS", format_bytes(result.peak_rss_bytes, units));
+    row("Peak VSZ", format_bytes(result.peak_vsz_bytes, units));
+    row(
+        "Duration",
+        format!("{:.2}s", result.duration().as_secs_f64()),
+    );
+    row(
+        "CPU time",
+        format!(
+            "{:.2}s user / {:.2}s system",
+            result.user_time_ms as f64 / 1000.0,
+            result.system_time_ms as f64 / 1000.0
+        ),
+    );
+    if let Some(code) = result.exit_code {
+        row("Exit code", code.to_string());
+    }
+    row(
+        "Recorded",
+        format!("{} UTC", result.started_at().format_datetime()),
+    );
+    if let Some(threshold) = threshold {
+        let (class, verdict) = if result.threshold_exceeded {
+            ("regressed", "exceeded")
+        } else {
+            ("passed", "not exceeded")
+        };
+        row(
+            "Threshold",
+            format!(
+                "{} <span class=\"{class}\">({verdict})</span>",
+                format_bytes(threshold.as_u64(), units)
+            ),
+        );
+    }
+    if let Some((name, comparison)) = comparison {
+        let (class, verdict) = if comparison.regression_detected {
+            ("regressed", "regression")
+        } else {
+            ("passed", "no regression")
+        };
+        row(
+            &format!("Baseline <code>{}</code>", escape(name)),
+            format!(
+                "{} peak RSS, {:+.1}% <span class=\"{class}\">({verdict})</span>",
+                format_bytes(comparison.baseline.peak_rss_bytes, units),
+                comparison.rss_diff_percent
+            ),
+        );
+    }
+    if let Some(retention) = &result.timeline_retention {
+        if retention.dropped_samples() {
+            row(
+                "Timeline",
+                format!(
+                    "{} of {} samples kept ({})",
+                    retention.kept_samples, retention.original_samples, retention.policy
+                ),
+            );
+        }
+    }
+    if let Some(delta) = &result.timeline_delta {
+        row(
+            "Timeline",
+            format!(
+                "{} of {} samples kept (RSS changes over {})",
+                delta.kept_samples,
+                delta.original_samples,
+                format_bytes(delta.delta_bytes, units)
+            ),
+        );
+    }
+    writeln_str!(html, "</table>");
+
+    writeln_str!(html, "<h2>Timeline</h2>");
+    writeln_str!(
+        html,
+        "<label><input type=\"checkbox\" id=\"vsz\"> Show VSZ</label>\n\
+         <svg id=\"chart\"></svg>\n<input type=\"range\" id=\"cursor\" min=\"0\" \
+         aria-label=\"Time\">\n<div id=\"tooltip\"></div>"
+    );
+    if result.process_snapshots.is_some() {
+        writeln_str!(
+            html,
+            "<h2>Processes at <span id=\"instant\"></span></h2>\n\
+             <table class=\"processes\"><thead><tr><th>PID</th><th>Process</th>\
+             <th>RSS</th></tr></thead><tbody id=\"processes\"></tbody></table>"
+        );
+    }
+    writeln_str!(
+        html,
+        "<script type=\"application/json\" id=\"data\">{}</script>",
+        chart_data(result, comparison.map(|(_, c)| c), threshold)
+    );
+    writeln_str!(html, "<script>{SCRIPT}</script>");
+
+    if let Some(tree) = &result.process_tree {
+        writeln_str!(
+            html,
+            "<h2>Process tree at peak</h2>\n<div class=\"tree\"><ul>"
+        );
+        write_tree(&mut html, tree, units);
+        writeln_str!(html, "</ul></div>");
+    }
+
+    writeln_str!(
+        html,
+        "<p><small>Generated by peak-mem {}</small></p>\n</body>\n</html>",
+        env!("CARGO_PKG_VERSION")
+    );
+    html
+}
+
+/// The chart's data as JSON, to embed in a `<script>` element.
+fn chart_data(
+    result: &MonitorResult,
+    comparison: Option<&ComparisonResult>,
+    threshold: Option<ByteSize>,
+) -> String {
+    let start = result.started_at();
+    let samples: Vec<[u64; 3]> = result
+        .timeline
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .map(|sample| {
+            [
+                sample.timestamp.duration_since(start).as_millis() as u64,
+                sample.rss_bytes,
+                sample.vsz_bytes,
+            ]
+        })
+        .collect();
+    // The first sample at the peak, when it was reached
+    let peak = samples
+        .iter()
+        .rev()
+        .max_by_key(|sample| sample[1])
+        .map(|sample| [sample[0], sample[1]]);
+    // Each from its own start
+    let baseline_samples =
+        comparison
+            .and_then(|c| c.baseline.timeline.as_deref())
+            .map(|timeline| {
+                let start = timeline.first().map(|sample| sample.timestamp);
+                timeline
+                    .iter()
+                    .map(|sample| {
+                        let ms = start.map_or(0, |start| {
+                            sample.timestamp.duration_since(start).as_millis() as u64
+                        });
+                        [ms, sample.rss_bytes]
+                    })
+                    .collect::<Vec<_>>()
+            });
+    let processes: Vec<serde_json::Value> = result
+        .process_snapshots
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .map(|snapshot| {
+            let processes: Vec<serde_json::Value> = snapshot
+                .processes
+                .iter()
+                .map(|p| serde_json::json!([p.pid, p.name, p.rss_bytes]))
+                .collect();
+            serde_json::json!([
+                snapshot.timestamp.duration_since(start).as_millis() as u64,
+                processes
+            ])
+        })
+        .collect();
+    serde_json::json!({
+        "samples": samples,
+        "peak": peak,
+        "threshold": threshold.map(|t| t.as_u64()),
+        "baseline": comparison.map(|c| c.baseline.peak_rss_bytes),
+        "baseline_samples": baseline_samples,
+        "processes": processes,
+    })
+    .to_string()
+    // A process name mustn't end the element
+    .replace('<', "\\u003c")
+}
+
+fn write_tree(html: &mut String, info: &ProcessMemoryInfo, units: Option<MemoryUnit>) {
+    let label = format!(
+        "{} <span class=\"rss\">pid {} &middot; {}</span>",
+        escape(&info.name),
+        info.pid,
+        format_bytes(info.memory.rss_bytes, units)
+    );
+    if info.children.is_empty() {
+        writeln_str!(html, "<li>{label}</li>");
+        return;
+    }
+    writeln_str!(html, "<li><details open><summary>{label}</summary><ul>");
+    for child in &info.children {
+        write_tree(html, child, units);
+    }
+    writeln_str!(html, "</ul></details></li>");
+}
+
+/// Escapes text for HTML.
+pub(super) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::baseline::{Baseline, RegressionThresholds};
+    use crate::monitor::snapshots::{ProcessRss, ProcessSnapshot};
+    use crate::types::{MemoryUsage, Timestamp};
+
+    #[test]
+    fn test_html_report() {
+        let result: MonitorResult = serde_json::from_str(
+            r#"{"command": "./app --name '<b>'", "peak_rss_bytes": 2048,
+                "peak_vsz_bytes": 4096, "duration_ms": 1000, "exit_code": 0,
+                "threshold_exceeded": false, "timestamp": "1970-01-01T00:00:02Z",
+                "timeline": [
+                    {"rss_bytes": 1024, "vsz_bytes": 4096, "timestamp": "1970-01-01T00:00:01Z"},
+                    {"rss_bytes": 2048, "vsz_bytes": 4096, "timestamp": "1970-01-01T00:00:01.500Z"}
+                ],
+                "process_tree": {"pid": 7, "name": "app", "children": [],
+                    "memory": {"rss_bytes": 2048, "vsz_bytes": 4096,
+                               "timestamp": "1970-01-01T00:00:01.500Z"}}}"#,
+        )
+        .unwrap();
+
+        let html = html_report(&result, None, Some(ByteSize::b(4096)), None);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<code>./app --name &#39;&lt;b&gt;&#39;</code>"));
+        assert!(html.contains("<td>Peak RSS</td><td>2.0 KiB</td>"));
+        assert!(html.contains("4.0 KiB <span class=\"passed\">(not exceeded)</span>"));
+        assert!(html.contains("<li>app <span class=\"rss\">pid 7 &middot; 2.0 KiB</span></li>"));
+        // Offsets from the start, one second before the end
+        assert!(html.contains(
+            r#"{"baseline":null,"baseline_samples":null,"peak":[500,2048],"processes":[],"samples":[[0,1024,4096],[500,2048,4096]],"threshold":4096}"#
+        ));
+        assert!(!html.contains("Processes at"));
+    }
+
+    #[test]
+    fn test_html_report_comparison() {
+        let mut result: MonitorResult = serde_json::from_str(
+            r#"{"command": "./app", "peak_rss_bytes": 2048,
+                "peak_vsz_bytes": 4096, "duration_ms": 1000, "exit_code": 0,
+                "threshold_exceeded": false, "timestamp": "1970-01-01T00:00:02Z",
+                "timeline": [
+                    {"rss_bytes": 2048, "vsz_bytes": 4096, "timestamp": "1970-01-01T00:00:01.500Z"}
+                ]}"#,
+        )
+        .unwrap();
+        let mut baseline = Baseline::from(&result);
+        baseline.timeline = Some(vec![
+            MemoryUsage::new(512, 0, Timestamp::from_unix_micros(5_000_000)),
+            MemoryUsage::new(1024, 0, Timestamp::from_unix_micros(5_250_000)),
+        ]);
+        result.process_snapshots = Some(vec![ProcessSnapshot {
+            timestamp: Timestamp::from_unix_micros(1_500_000),
+            processes: vec![ProcessRss {
+                pid: 7,
+                name: "</script>".to_string(),
+                rss_bytes: 2048,
+            }],
+        }]);
+        let comparison = ComparisonResult::new(baseline, result, RegressionThresholds::rss(10.0));
+
+        let html = html_report(&comparison.current, Some(("main", &comparison)), None, None);
+        assert!(html.contains("<h2>Processes at <span id=\"instant\"></span></h2>"));
+        // The baseline from its own start
+        assert!(html.contains(r#""baseline_samples":[[0,512],[250,1024]]"#));
+        assert!(html.contains(r#""processes":[[500,[[7,"\u003c/script>",2048]]]]"#));
+    }
+}
@@ -0,0 +1,112 @@
+//! InfluxDB line protocol, for `--influx` and `--influx-file`.
+//!
+//! Each timeline sample becomes a `peak_mem_sample` point and the run
+//! itself a `peak_mem` point at the time it ended, both tagged with the
+//! command, so the output can be piped into `influx write` or read by
+//! telegraf's `file` and `exec` inputs to graph runs over time.
+
+use super::{write_str, writeln_str};
+use crate::baseline::ComparisonResult;
+use crate::types::{MonitorResult, Timestamp};
+
+/// Formats a run as line protocol, one point per line.
+///
+/// # Arguments
+/// * `result` - The run's results, with its timeline if it was kept
+/// * `comparison` - The comparison against a baseline, if one was made, whose
+///   verdict is added to the `peak_mem` point
+pub fn influx_lines(result: &MonitorResult, comparison: Option<&ComparisonResult>) -> String {
+    let tags = if result.command.is_empty() {
+        String::new()
+    } else {
+        format!(",command={}", escape_tag(&result.command))
+    };
+
+    let mut lines = String::new();
+    for sample in result.timeline.as_deref().unwrap_or_default() {
+        writeln_str!(
+            lines,
+            "peak_mem_sample{tags} rss_bytes={}i,vsz_bytes={}i {}",
+            sample.rss_bytes,
+            sample.vsz_bytes,
+            nanos(sample.timestamp)
+        );
+    }
+
+    let mut fields = format!(
+        "peak_rss_bytes={}i,peak_vsz_bytes={}i,duration_ms={}i,threshold_exceeded={}",
+        result.peak_rss_bytes, result.peak_vsz_bytes, result.duration_ms, result.threshold_exceeded
+    );
+    if let Some(code) = result.exit_code {
+        write_str!(fields, ",exit_code={code}i");
+    }
+    if let Some(comparison) = comparison {
+        write_str!(
+            fields,
+            ",baseline_peak_rss_bytes={}i,rss_diff_percent={},regression_detected={}",
+            comparison.baseline.peak_rss_bytes,
+            comparison.rss_diff_percent,
+            comparison.regression_detected
+        );
+    }
+    writeln_str!(lines, "peak_mem{tags} {fields} {}", nanos(result.timestamp));
+    lines
+}
+
+/// Escapes a tag value: commas, equals signs and spaces are
+/// backslash-escaped, and newlines, which can't be escaped, become spaces.
+fn escape_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            ',' | '=' | ' ' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' | '\r' => escaped.push_str("\\ "),
+            _ => escaped.push(c),
+        }
+    }
+    // A trailing backslash would escape the separator after the tag
+    if escaped.ends_with('\\') {
+        escaped.push('\\');
+    }
+    escaped
+}
+
+fn nanos(timestamp: Timestamp) -> u128 {
+    u128::from(timestamp.unix_micros()) * 1000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_influx_lines() {
+        let result: MonitorResult = serde_json::from_str(
+            r#"{"command": "make -j8 CC=clang", "peak_rss_bytes": 2048,
+                "peak_vsz_bytes": 4096, "duration_ms": 1500, "exit_code": 0,
+                "threshold_exceeded": false, "timestamp": "1970-01-01T00:00:02Z",
+                "timeline": [{"rss_bytes": 1024, "vsz_bytes": 2048,
+                              "timestamp": "1970-01-01T00:00:01Z"}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            influx_lines(&result, None),
+            "peak_mem_sample,command=make\\ -j8\\ CC\\=clang \
+             rss_bytes=1024i,vsz_bytes=2048i 1000000000\n\
+             peak_mem,command=make\\ -j8\\ CC\\=clang \
+             peak_rss_bytes=2048i,peak_vsz_bytes=4096i,duration_ms=1500i,\
+             threshold_exceeded=false,exit_code=0i 2000000000\n"
+        );
+    }
+
+    #[test]
+    fn test_escape_tag() {
+        assert_eq!(escape_tag("a,b=c d"), "a\\,b\\=c\\ d");
+        assert_eq!(escape_tag("echo\nhi"), "echo\\ hi");
+        assert_eq!(escape_tag("dir\\"), "dir\\\\");
+    }
+}
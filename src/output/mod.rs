@@ -3,11 +3,96 @@
 //! This module provides formatters for different output formats including
 //! human-readable, JSON, CSV, and quiet modes.
 
-use crate::baseline::ComparisonResult;
+use crate::baseline::{Baseline, BaselineSummary, ComparisonResult, Trend};
+use crate::bench::{BenchResult, INTERVALS_MS};
 use crate::cli::{MemoryUnit, OutputFormat};
-use crate::types::{ByteSize, MonitorResult, ProcessMemoryInfo, Result};
+use crate::git::GitInfo;
+#[cfg(feature = "sqlite")]
+use crate::history::HistoryRun;
+use crate::merge::{MergedRuns, Stats};
+use crate::monitor::fds::FdSummary;
+use crate::monitor::jobs::JobPeak;
+use crate::monitor::pause::SamplingPause;
+use crate::monitor::perf::AllocationCounts;
+use crate::monitor::thermal::ThermalContext;
+use crate::treediff::{DiffStatus, TreeDiff};
+use crate::types::{
+    ByteSize, MonitorResult, PeakMemError, ProcessMemoryInfo, Result, Timestamp, TimestampFormat,
+};
+use std::collections::VecDeque;
 use std::io::{self, Write};
 
+mod badge;
+mod chart;
+mod exec;
+mod fields;
+mod github;
+mod html;
+mod influx;
+mod prometheus;
+mod report;
+mod style;
+mod tap;
+mod template;
+mod tui;
+pub use badge::badge;
+pub use chart::{
+    sparkline, svg_chart, svg_timeline, terminal_chart, terminal_timeline, ChartPoint,
+    SPARKLINE_WIDTH,
+};
+pub use exec::ExecFormat;
+pub use fields::Field;
+pub use github::{github_annotations, github_summary};
+pub use html::html_report;
+pub use influx::influx_lines;
+pub use prometheus::{prometheus_exposition, prometheus_textfile};
+pub use report::regression_report;
+pub use style::{ColorChoice, Style};
+pub use tap::tap_report;
+pub use template::Template;
+pub use tui::{TuiDisplay, WatchState};
+
+/// How many crates or translation units `--cargo` and `--compile-units`
+/// list in the default human output.
+const HUMAN_JOBS: usize = 5;
+
+/// Formats a size in the `--units` unit, or the most readable one.
+pub fn format_bytes(bytes: u64, units: Option<MemoryUnit>) -> String {
+    match units {
+        Some(unit) => unit.format(bytes),
+        None => ByteSize::b(bytes).to_string(),
+    }
+}
+
+/// `write!` to a `String`, which can't fail.
+macro_rules! write_str {
+    ($text:expr, $($arg:tt)*) => {{
+        use std::fmt::Write as _;
+        let _ = write!($text, $($arg)*);
+    }};
+}
+
+/// `writeln!` to a `String`, which can't fail.
+macro_rules! writeln_str {
+    ($text:expr) => {
+        $text.push('\n')
+    };
+    ($text:expr, $($arg:tt)*) => {{
+        use std::fmt::Write as _;
+        let _ = writeln!($text, $($arg)*);
+    }};
+}
+
+pub(crate) use {write_str, writeln_str};
+
+/// The error for a format option that `what` has no output in.
+pub fn unsupported_format(option: &str, what: &str) -> PeakMemError {
+    PeakMemError::InvalidArgument(format!("{option} can't be used with {what}"))
+}
+
+/// Samples of RSS the watch display keeps for its history row.
+pub const WATCH_HISTORY: usize = 120;
+
 /// Simple CSV writer that handles escaping
 struct CsvWriter<W: Write> {
     writer: W,
@@ -56,34 +141,54 @@ impl OutputFormatter {
     /// Formats monitoring results according to the specified format.
     ///
     /// # Arguments
+    /// * `stdout` - Where to write, standard output unless `--output` was given
     /// * `result` - The monitoring results to format
     /// * `format` - The output format to use
     /// * `verbose` - Whether to include verbose information
     /// * `units` - Optional fixed memory unit to use for display
+    /// * `timestamps` - Optional timestamp format for human and CSV output
+    /// * `style` - Colors for human output
     pub fn format(
+        stdout: &mut dyn Write,
         result: &MonitorResult,
         format: OutputFormat,
         verbose: bool,
         units: Option<MemoryUnit>,
+        timestamps: Option<TimestampFormat>,
+        style: Style,
     ) -> Result<()> {
         match format {
             OutputFormat::Human => {
                 if verbose {
-                    Self::format_verbose(result, units)
+                    Self::format_verbose(stdout, result, units, timestamps, style)
                 } else {
-                    Self::format_human(result, units)
+                    Self::format_human(stdout, result, units, style)
                 }
             }
-            OutputFormat::Json => Self::format_json(result),
-            OutputFormat::Csv => Self::format_csv(result),
-            OutputFormat::Quiet => Self::format_quiet(result),
+            OutputFormat::Json => Self::format_json(stdout, result),
+            OutputFormat::Csv => Self::format_csv(stdout, result, timestamps),
+            OutputFormat::Quiet => Self::format_quiet(stdout, result),
+            OutputFormat::Influx => {
+                write!(stdout, "{}", influx_lines(result, None))?;
+                Ok(())
+            }
         }
     }
 
-    fn format_human(result: &MonitorResult, units: Option<MemoryUnit>) -> Result<()> {
-        let mut stdout = io::stdout();
-
-        writeln!(stdout, "Command: {}", result.command)?;
+    fn format_human(
+        stdout: &mut dyn Write,
+        result: &MonitorResult,
+        units: Option<MemoryUnit>,
+        style: Style,
+    ) -> Result<()> {
+        writeln!(
+            stdout,
+            "{}",
+            style.dim(&format!("Command: {}", result.command))
+        )?;
+        Self::print_cgroup_owner(stdout, result)?;
+        Self::print_system(stdout, result, units)?;
+        Self::print_runs(stdout, result, units)?;
 
         if let Some(unit) = units {
             write!(
@@ -91,34 +196,374 @@ impl OutputFormatter {
                 "Peak memory usage: {} (RSS)",
                 unit.format(result.peak_rss_bytes)
             )?;
-            writeln!(stdout, " / {} (VSZ)", unit.format(result.peak_vsz_bytes))?;
+            write!(stdout, " / {} (VSZ)", unit.format(result.peak_vsz_bytes))?;
         } else {
             write!(stdout, "Peak memory usage: {} (RSS)", result.peak_rss())?;
-            writeln!(stdout, " / {} (VSZ)", result.peak_vsz())?;
+            write!(stdout, " / {} (VSZ)", result.peak_vsz())?;
+        }
+        match &result.rss_sparkline {
+            Some(sparkline) => writeln!(stdout, "  {sparkline}")?,
+            None => writeln!(stdout)?,
+        }
+        Self::print_targets(stdout, result, units, "  ")?;
+        Self::print_jobs(stdout, "Crates", &result.crates, units, Some(HUMAN_JOBS))?;
+        Self::print_jobs(
+            stdout,
+            "Translation units",
+            &result.translation_units,
+            units,
+            Some(HUMAN_JOBS),
+        )?;
+        Self::print_pool(stdout, result, units)?;
+        if let Some(reason) = &result.degraded_mode {
+            writeln!(
+                stdout,
+                "Degraded mode: {reason} (RSS from rusage, VSZ unavailable)"
+            )?;
+        }
+
+        if let Some(gpu_bytes) = result.peak_gpu_bytes {
+            match units {
+                Some(unit) => writeln!(stdout, "Peak GPU memory: {}", unit.format(gpu_bytes))?,
+                None => writeln!(stdout, "Peak GPU memory: {}", ByteSize::b(gpu_bytes))?,
+            }
+        }
+        if let Some(heap_bytes) = result.peak_heap_bytes {
+            match units {
+                Some(unit) => writeln!(stdout, "Peak managed heap: {}", unit.format(heap_bytes))?,
+                None => writeln!(stdout, "Peak managed heap: {}", ByteSize::b(heap_bytes))?,
+            }
+        }
+
+        writeln!(
+            stdout,
+            "Page faults: {} major / {} minor",
+            result.major_faults, result.minor_faults
+        )?;
+        if let Some(counts) = &result.allocation_counts {
+            writeln!(stdout, "Allocations: {}", Self::allocation_summary(counts))?;
+        }
+        if let Some(pressure) = &result.peak_memory_pressure {
+            writeln!(
+                stdout,
+                "Memory pressure: {:.1}% some / {:.1}% full (peak 10s avg)",
+                pressure.some_avg10, pressure.full_avg10
+            )?;
+        }
+        if !result.sampling_pauses.is_empty() {
+            writeln!(stdout, "{}", Self::pause_summary(&result.sampling_pauses))?;
+        }
+        if let Some(memory) = &result.lowest_system_memory {
+            writeln!(
+                stdout,
+                "Lowest system memory available: {} of {}",
+                format_bytes(memory.available_bytes, units),
+                format_bytes(memory.total_bytes, units)
+            )?;
+        }
+        if let Some(thermal) = &result.thermal {
+            writeln!(stdout, "Thermal: {}", Self::thermal_summary(thermal))?;
         }
 
         if let Some(exit_code) = result.exit_code {
             writeln!(stdout, "Exit code: {exit_code}")?;
         }
 
-        writeln!(stdout, "Duration: {:.1}s", result.duration().as_secs_f64())?;
+        let duration = format!("Duration: {:.1}s", result.duration().as_secs_f64());
+        writeln!(stdout, "{}", style.dim(&duration))?;
+        let cpu_time = format!(
+            "CPU time: {:.1}s user / {:.1}s system ({:.0}% CPU)",
+            result.user_time_ms as f64 / 1000.0,
+            result.system_time_ms as f64 / 1000.0,
+            result.cpu_utilization_percent
+        );
+        writeln!(stdout, "{}", style.dim(&cpu_time))?;
+        writeln!(
+            stdout,
+            "Disk I/O: {} read / {} written",
+            format_bytes(result.disk_read_bytes, units),
+            format_bytes(result.disk_write_bytes, units)
+        )?;
 
         if result.threshold_exceeded {
-            writeln!(stdout, "\n⚠️  THRESHOLD EXCEEDED")?;
+            writeln!(stdout, "\n{}", style.bad("⚠️  THRESHOLD EXCEEDED"))?;
+            Self::print_threshold_event(stdout, result, units, style)?;
         }
 
         stdout.flush()?;
         Ok(())
     }
 
-    fn format_json(result: &MonitorResult) -> Result<()> {
+    /// Prints which container or systemd unit was monitored, if any.
+    fn print_cgroup_owner(stdout: &mut dyn Write, result: &MonitorResult) -> Result<()> {
+        if let Some(container) = &result.container {
+            writeln!(
+                stdout,
+                "Container: {} ({}, init PID {}; RSS is the cgroup's memory charge)",
+                container.name, container.runtime, container.pid
+            )?;
+        }
+        if let Some(unit) = &result.unit {
+            writeln!(
+                stdout,
+                "Unit: {} (main PID {}; RSS is the cgroup's memory charge)",
+                unit.name, unit.main_pid
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Formats a timestamp of the run for human output: in UTC to the
+    /// second unless `--timestamp-format` was given.
+    fn human_time(
+        timestamp: Timestamp,
+        start: Timestamp,
+        timestamps: Option<TimestampFormat>,
+    ) -> String {
+        match timestamps {
+            None => format!("{} UTC", timestamp.format_datetime()),
+            Some(TimestampFormat::Relative) => {
+                format!("+{}s", TimestampFormat::Relative.render(timestamp, start))
+            }
+            Some(format) => format.render(timestamp, start),
+        }
+    }
+
+    /// Describes the pauses in sampling, e.g. `Sampling paused: 2 times,
+    /// 3.1s in all (SIGUSR1)`.
+    fn pause_summary(pauses: &[SamplingPause]) -> String {
+        let paused_ms: u64 = pauses.iter().map(SamplingPause::duration_ms).sum();
+        format!(
+            "Sampling paused: {} {}, {:.1}s in all (SIGUSR1)",
+            pauses.len(),
+            if pauses.len() == 1 { "time" } else { "times" },
+            paused_ms as f64 / 1000.0
+        )
+    }
+
+    /// Prints the machine's memory extremes, with `--system`.
+    fn print_system(
+        stdout: &mut dyn Write,
+        result: &MonitorResult,
+        units: Option<MemoryUnit>,
+    ) -> Result<()> {
+        let Some(system) = &result.system else {
+            return Ok(());
+        };
+        writeln!(
+            stdout,
+            "System: {} total; lowest available {}, peak swap used {} (RSS is the memory in use)",
+            format_bytes(system.total_bytes, units),
+            format_bytes(system.min_available_bytes, units),
+            format_bytes(system.peak_swap_used_bytes, units)
+        )?;
+        Ok(())
+    }
+
+    /// Prints the range of peaks over all runs, if there were several.
+    fn print_runs(
+        stdout: &mut dyn Write,
+        result: &MonitorResult,
+        units: Option<MemoryUnit>,
+    ) -> Result<()> {
+        let peaks = result.runs.iter().map(|run| run.peak_rss_bytes);
+        let (Some(lowest), Some(highest)) = (peaks.clone().min(), peaks.max()) else {
+            return Ok(());
+        };
+        writeln!(
+            stdout,
+            "Runs: {} (peak RSS {} to {})",
+            result.runs.len(),
+            format_bytes(lowest, units),
+            format_bytes(highest, units)
+        )?;
+        Ok(())
+    }
+
+    /// Prints the peak of each process monitored together, one per line.
+    fn print_targets(
+        stdout: &mut dyn Write,
+        result: &MonitorResult,
+        units: Option<MemoryUnit>,
+        indent: &str,
+    ) -> Result<()> {
+        for target in &result.targets {
+            let label = match &target.command {
+                Some(command) => format!("pid {} ({command})", target.pid),
+                None => format!("pid {}", target.pid),
+            };
+            writeln!(
+                stdout,
+                "{indent}{label}: {} (RSS) / {} (VSZ)",
+                format_bytes(target.peak_rss_bytes, units),
+                format_bytes(target.peak_vsz_bytes, units)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Prints the worker pool's footprint during warmup and once settled,
+    /// such as "Pool (gunicorn): 4 workers; warmup peak 1.2 GiB, steady
+    /// 800.0 MiB (200.0 MiB per worker) after 30.0s".
+    fn print_pool(
+        stdout: &mut dyn Write,
+        result: &MonitorResult,
+        units: Option<MemoryUnit>,
+    ) -> Result<()> {
+        let Some(pool) = &result.pool else {
+            return Ok(());
+        };
+        let steady_after = pool.steady_after_ms as f64 / 1000.0;
+        write!(
+            stdout,
+            "Pool ({}): {} workers; warmup peak {}, ",
+            pool.pattern,
+            pool.workers,
+            format_bytes(pool.warmup_peak_rss_bytes, units)
+        )?;
+        match pool.steady_peak_rss_bytes {
+            Some(steady) => {
+                write!(stdout, "steady {}", format_bytes(steady, units))?;
+                if let Some(per_worker) = pool.steady_rss_per_worker_bytes {
+                    write!(stdout, " ({} per worker)", format_bytes(per_worker, units))?;
+                }
+                writeln!(stdout, " after {steady_after:.1}s")?;
+            }
+            None => writeln!(stdout, "never settled (ran less than {steady_after:.1}s)")?,
+        }
+        Ok(())
+    }
+
+    /// Prints the peak RSS of each compiler job, highest first, up to
+    /// `limit` of them.
+    fn print_jobs(
+        stdout: &mut dyn Write,
+        title: &str,
+        jobs: &[JobPeak],
+        units: Option<MemoryUnit>,
+        limit: Option<usize>,
+    ) -> Result<()> {
+        if jobs.is_empty() {
+            return Ok(());
+        }
+        writeln!(stdout, "{title}: ({} compiled, by peak RSS)", jobs.len())?;
+        let shown = limit.unwrap_or(jobs.len());
+        for job in jobs.iter().take(shown) {
+            writeln!(
+                stdout,
+                "  {}: {}",
+                job.name,
+                format_bytes(job.peak_rss_bytes, units)
+            )?;
+        }
+        if jobs.len() > shown {
+            writeln!(
+                stdout,
+                "  ... and {} more (see -v or --json)",
+                jobs.len() - shown
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Describes perf allocation counts, such as
+    /// "120 page faults / 35 mmap / 4 brk (perf)".
+    fn allocation_summary(counts: &AllocationCounts) -> String {
+        let mut parts = vec![format!("{} page faults", counts.page_faults)];
+        if let Some(mmap) = counts.mmap_calls {
+            parts.push(format!("{mmap} mmap"));
+        }
+        if let Some(brk) = counts.brk_calls {
+            parts.push(format!("{brk} brk"));
+        }
+        format!("{} (perf)", parts.join(" / "))
+    }
+
+    /// Describes the thermal context of a run, such as "3 throttle events /
+    /// CPU 2.1 of 4.5 GHz (mean) / peak 78.0°C / 1234.5 J". Readings that
+    /// weren't available are left out.
+    fn thermal_summary(thermal: &ThermalContext) -> String {
+        let mut parts = Vec::new();
+        if let Some(events) = thermal.throttle_events {
+            parts.push(format!("{events} throttle events"));
+        }
+        match (thermal.mean_cpu_freq_mhz, thermal.max_cpu_freq_mhz) {
+            (Some(mean), Some(max)) => parts.push(format!(
+                "CPU {:.1} of {:.1} GHz (mean)",
+                mean / 1000.0,
+                max / 1000.0
+            )),
+            (Some(mean), None) => parts.push(format!("CPU {:.1} GHz (mean)", mean / 1000.0)),
+            _ => {}
+        }
+        if let Some(celsius) = thermal.peak_temperature_celsius {
+            parts.push(format!("peak {celsius:.1}°C"));
+        }
+        if let Some(joules) = thermal.energy_joules {
+            parts.push(format!("{joules:.1} J"));
+        }
+        parts.join(" / ")
+    }
+
+    /// Describes open file descriptors by type, such as
+    /// "14 (3 files, 8 sockets, 3 pipes)". Types with none open are left
+    /// out.
+    fn fd_breakdown(fds: &FdSummary) -> String {
+        let parts: Vec<String> = [
+            (fds.files, "files"),
+            (fds.sockets, "sockets"),
+            (fds.pipes, "pipes"),
+            (fds.devices, "devices"),
+            (fds.anon_inodes, "anon inodes"),
+            (fds.other, "other"),
+        ]
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, kind)| format!("{count} {kind}"))
+        .collect();
+
+        if parts.is_empty() {
+            "0".to_string()
+        } else {
+            format!("{} ({})", fds.total(), parts.join(", "))
+        }
+    }
+
+    /// Prints when the threshold was crossed, if it was annotated.
+    fn print_threshold_event(
+        stdout: &mut dyn Write,
+        result: &MonitorResult,
+        units: Option<MemoryUnit>,
+        style: Style,
+    ) -> Result<()> {
+        if let Some(event) = &result.threshold_event {
+            let rss = if let Some(unit) = units {
+                unit.format(event.rss_bytes)
+            } else {
+                ByteSize::b(event.rss_bytes).to_string()
+            };
+            let crossed = format!(
+                "Threshold crossed after {:.1}s (RSS: {})",
+                event.elapsed_ms as f64 / 1000.0,
+                rss
+            );
+            writeln!(stdout, "{}", style.bad(&crossed))?;
+        }
+        Ok(())
+    }
+
+    fn format_json(stdout: &mut dyn Write, result: &MonitorResult) -> Result<()> {
         let json = serde_json::to_string_pretty(result)?;
-        println!("{json}");
+        writeln!(stdout, "{json}")?;
         Ok(())
     }
 
-    fn format_csv(result: &MonitorResult) -> Result<()> {
-        let mut wtr = CsvWriter::new(io::stdout());
+    fn format_csv(
+        stdout: &mut dyn Write,
+        result: &MonitorResult,
+        timestamps: Option<TimestampFormat>,
+    ) -> Result<()> {
+        let mut wtr = CsvWriter::new(stdout);
 
         wtr.write_record(&[
             "command",
@@ -128,9 +573,36 @@ impl OutputFormatter {
             "exit_code",
             "threshold_exceeded",
             "timestamp",
+            "minor_faults",
+            "major_faults",
+            "user_time_ms",
+            "system_time_ms",
+            "cpu_utilization_percent",
+            "disk_read_bytes",
+            "disk_write_bytes",
+            "pressure_some_avg10",
+            "pressure_full_avg10",
+            "peak_gpu_bytes",
+            "degraded_mode",
+            "perf_page_faults",
+            "mmap_calls",
+            "brk_calls",
+            "throttle_events",
+            "energy_joules",
         ])?;
 
         let exit_code_str = result.exit_code.map_or(String::new(), |c| c.to_string());
+        let counts = result.allocation_counts;
+        let thermal = result.thermal;
+        let (pressure_some, pressure_full) =
+            result
+                .peak_memory_pressure
+                .map_or((String::new(), String::new()), |p| {
+                    (
+                        format!("{:.2}", p.some_avg10),
+                        format!("{:.2}", p.full_avg10),
+                    )
+                });
         wtr.write_record(&[
             &result.command,
             &result.peak_rss_bytes.to_string(),
@@ -138,28 +610,128 @@ impl OutputFormatter {
             &result.duration_ms.to_string(),
             &exit_code_str,
             &result.threshold_exceeded.to_string(),
-            &result.timestamp.to_rfc3339(),
+            &timestamps
+                .unwrap_or(TimestampFormat::Rfc3339)
+                .render(result.timestamp, result.started_at()),
+            &result.minor_faults.to_string(),
+            &result.major_faults.to_string(),
+            &result.user_time_ms.to_string(),
+            &result.system_time_ms.to_string(),
+            &format!("{:.1}", result.cpu_utilization_percent),
+            &result.disk_read_bytes.to_string(),
+            &result.disk_write_bytes.to_string(),
+            &pressure_some,
+            &pressure_full,
+            &result
+                .peak_gpu_bytes
+                .map_or(String::new(), |b| b.to_string()),
+            result.degraded_mode.as_deref().unwrap_or(""),
+            &counts.map_or(String::new(), |c| c.page_faults.to_string()),
+            &counts
+                .and_then(|c| c.mmap_calls)
+                .map_or(String::new(), |n| n.to_string()),
+            &counts
+                .and_then(|c| c.brk_calls)
+                .map_or(String::new(), |n| n.to_string()),
+            &thermal
+                .and_then(|t| t.throttle_events)
+                .map_or(String::new(), |n| n.to_string()),
+            &thermal
+                .and_then(|t| t.energy_joules)
+                .map_or(String::new(), |j| format!("{j:.3}")),
         ])?;
 
         wtr.flush()?;
         Ok(())
     }
 
-    fn format_quiet(result: &MonitorResult) -> Result<()> {
-        println!("{}", result.peak_rss_bytes);
+    /// Prints the selected fields as one tab-separated line.
+    pub fn format_fields(
+        stdout: &mut dyn Write,
+        result: &MonitorResult,
+        fields: &[Field],
+        timestamps: Option<TimestampFormat>,
+    ) -> Result<()> {
+        writeln!(
+            stdout,
+            "{}",
+            Field::line(
+                fields,
+                result,
+                timestamps.unwrap_or(TimestampFormat::Rfc3339)
+            )
+        )?;
+        Ok(())
+    }
+
+    /// Formats the results with a `--format exec:` command, which is given
+    /// the JSON of the comparison if one was made.
+    pub fn format_exec(
+        stdout: &mut dyn Write,
+        result: &MonitorResult,
+        comparison: Option<&ComparisonResult>,
+        format: &ExecFormat,
+    ) -> Result<()> {
+        let mut json = Vec::new();
+        match comparison {
+            Some(comparison) => Self::format_comparison(
+                &mut json,
+                comparison,
+                OutputFormat::Json,
+                None,
+                Style::default(),
+            )?,
+            None => Self::format_json(&mut json, result)?,
+        }
+        stdout.write_all(&format.run(&json)?)?;
         Ok(())
     }
 
-    fn format_verbose(result: &MonitorResult, units: Option<MemoryUnit>) -> Result<()> {
-        let mut stdout = io::stdout();
+    /// Formats a result with a `--template`, ending it with a newline if
+    /// the template doesn't.
+    pub fn format_template(
+        stdout: &mut dyn Write,
+        result: &MonitorResult,
+        template: &Template,
+    ) -> Result<()> {
+        let line = template.render(result);
+        write!(stdout, "{line}")?;
+        if !line.ends_with('\n') {
+            writeln!(stdout)?;
+        }
+        Ok(())
+    }
+
+    fn format_quiet(stdout: &mut dyn Write, result: &MonitorResult) -> Result<()> {
+        writeln!(stdout, "{}", result.peak_rss_bytes)?;
+        Ok(())
+    }
 
+    fn format_verbose(
+        stdout: &mut dyn Write,
+        result: &MonitorResult,
+        units: Option<MemoryUnit>,
+        timestamps: Option<TimestampFormat>,
+        style: Style,
+    ) -> Result<()> {
         // Header
-        writeln!(stdout, "Command: {}", result.command)?;
+        writeln!(
+            stdout,
+            "{}",
+            style.dim(&format!("Command: {}", result.command))
+        )?;
+        Self::print_cgroup_owner(stdout, result)?;
+        Self::print_system(stdout, result, units)?;
+        Self::print_runs(stdout, result, units)?;
         if let Some(start_time) = result.start_time {
-            writeln!(stdout, "Started: {} UTC", start_time.format_datetime())?;
+            let started = format!(
+                "Started: {}",
+                Self::human_time(start_time, start_time, timestamps)
+            );
+            writeln!(stdout, "{}", style.dim(&started))?;
         }
         if let Some(pid) = result.main_pid {
-            writeln!(stdout, "Process ID: {pid}")?;
+            writeln!(stdout, "{}", style.dim(&format!("Process ID: {pid}")))?;
         }
         writeln!(stdout)?;
 
@@ -192,8 +764,82 @@ impl OutputFormatter {
                 result.peak_vsz_bytes
             )?;
         }
+        if let Some(aggregate) = &result.aggregate {
+            writeln!(stdout, "  Tree aggregated by: {aggregate}")?;
+        }
+        if !result.targets.is_empty() {
+            writeln!(stdout, "  Per process:")?;
+            Self::print_targets(stdout, result, units, "    ")?;
+        }
+        if let Some(reason) = &result.degraded_mode {
+            writeln!(
+                stdout,
+                "  Degraded mode: {reason} (RSS from rusage, VSZ unavailable)"
+            )?;
+        }
+        if let Some(gpu_bytes) = result.peak_gpu_bytes {
+            writeln!(
+                stdout,
+                "  Peak GPU memory: {} ({gpu_bytes} bytes)",
+                format_bytes(gpu_bytes, units)
+            )?;
+        }
+        if let Some(heap_bytes) = result.peak_heap_bytes {
+            writeln!(
+                stdout,
+                "  Peak managed heap: {} ({heap_bytes} bytes)",
+                format_bytes(heap_bytes, units)
+            )?;
+        }
+        writeln!(stdout, "  Major page faults: {}", result.major_faults)?;
+        writeln!(stdout, "  Minor page faults: {}", result.minor_faults)?;
+        writeln!(stdout, "  Peak threads: {}", result.peak_thread_count)?;
+        if let Some(counts) = &result.allocation_counts {
+            writeln!(
+                stdout,
+                "  Allocations: {}",
+                Self::allocation_summary(counts)
+            )?;
+        }
+        if let Some(fds) = &result.fd_summary {
+            writeln!(stdout, "  Open FDs at peak: {}", Self::fd_breakdown(fds))?;
+        }
+        if let Some(pressure) = &result.peak_memory_pressure {
+            writeln!(
+                stdout,
+                "  Peak system pressure: {:.1}% some / {:.1}% full",
+                pressure.some_avg10, pressure.full_avg10
+            )?;
+        }
+        if !result.sampling_pauses.is_empty() {
+            writeln!(stdout, "  {}", Self::pause_summary(&result.sampling_pauses))?;
+        }
+        if let Some(memory) = &result.lowest_system_memory {
+            writeln!(
+                stdout,
+                "  Lowest system memory available: {} of {} ({} bytes)",
+                format_bytes(memory.available_bytes, units),
+                format_bytes(memory.total_bytes, units),
+                memory.available_bytes
+            )?;
+        }
         writeln!(stdout)?;
 
+        if result.pool.is_some() {
+            Self::print_pool(stdout, result, units)?;
+            writeln!(stdout)?;
+        }
+
+        for (title, jobs) in [
+            ("Crates", &result.crates),
+            ("Translation units", &result.translation_units),
+        ] {
+            if !jobs.is_empty() {
+                Self::print_jobs(stdout, title, jobs, units, None)?;
+                writeln!(stdout)?;
+            }
+        }
+
         // Process Tree Section
         if let Some(tree) = &result.process_tree {
             let process_count = Self::count_processes(tree);
@@ -201,14 +847,15 @@ impl OutputFormatter {
                 stdout,
                 "Process Tree: ({process_count} processes monitored)"
             )?;
-            Self::print_process_tree(&mut stdout, tree, "", true, units)?;
-        } else {
+            Self::print_process_tree(stdout, tree, "", true, units)?;
+            writeln!(stdout)?;
+        } else if result.system.is_none() {
             writeln!(
                 stdout,
                 "Process Tree: (monitoring disabled with --no-children)"
             )?;
+            writeln!(stdout)?;
         }
-        writeln!(stdout)?;
 
         // Performance Section
         writeln!(stdout, "Performance:")?;
@@ -220,13 +867,96 @@ impl OutputFormatter {
         if let Some(sample_count) = result.sample_count {
             writeln!(stdout, "  Samples collected: {sample_count}")?;
         }
+        if let Some(retention) = result
+            .timeline_retention
+            .as_ref()
+            .filter(|retention| retention.dropped_samples())
+        {
+            write!(
+                stdout,
+                "  Timeline: {} of {} samples kept ({}",
+                retention.kept_samples, retention.original_samples, retention.policy
+            )?;
+            match retention.sample_every {
+                Some(every) => writeln!(stdout, ", 1 in {every})")?,
+                None => writeln!(stdout, ")")?,
+            }
+        }
+        if let Some(delta) = &result.timeline_delta {
+            writeln!(
+                stdout,
+                "  Timeline: {} of {} samples kept (RSS changes over {})",
+                delta.kept_samples,
+                delta.original_samples,
+                format_bytes(delta.delta_bytes, units)
+            )?;
+        }
         writeln!(
             stdout,
             "  Effective sample interval: {}ms",
             result.duration_ms / result.sample_count.unwrap_or(1).max(1)
         )?;
+        writeln!(
+            stdout,
+            "  CPU time: {:.3}s user / {:.3}s system",
+            result.user_time_ms as f64 / 1000.0,
+            result.system_time_ms as f64 / 1000.0
+        )?;
+        writeln!(
+            stdout,
+            "  CPU utilization: {:.1}%",
+            result.cpu_utilization_percent
+        )?;
+        writeln!(
+            stdout,
+            "  Disk read: {} ({} bytes)",
+            format_bytes(result.disk_read_bytes, units),
+            result.disk_read_bytes
+        )?;
+        writeln!(
+            stdout,
+            "  Disk written: {} ({} bytes)",
+            format_bytes(result.disk_write_bytes, units),
+            result.disk_write_bytes
+        )?;
+        if let Some(diagnostics) = &result.sampling_diagnostics {
+            if diagnostics.adjustments.is_empty() {
+                writeln!(stdout, "  Pressure backoff: no adjustments")?;
+            } else {
+                writeln!(stdout, "  Pressure backoff adjustments:")?;
+                for adjustment in &diagnostics.adjustments {
+                    let reason = if adjustment.interval_ms > diagnostics.base_interval_ms {
+                        "backed off"
+                    } else {
+                        "restored"
+                    };
+                    writeln!(
+                        stdout,
+                        "    {}: {reason} to {}ms (PSI some avg10 {:.1}%)",
+                        Self::human_time(adjustment.timestamp, result.started_at(), timestamps),
+                        adjustment.interval_ms,
+                        adjustment.pressure_some_avg10
+                    )?;
+                }
+            }
+        }
         writeln!(stdout)?;
 
+        // Environment Section
+        if let Some(fingerprint) = &result.fingerprint {
+            writeln!(stdout, "Environment:")?;
+            writeln!(
+                stdout,
+                "  Fingerprint: {} ({} variables)",
+                fingerprint.env_hash,
+                fingerprint.env.len()
+            )?;
+            for (command, output) in &fingerprint.probes {
+                writeln!(stdout, "  {command}: {output}")?;
+            }
+            writeln!(stdout)?;
+        }
+
         // Exit Status
         if let Some(exit_code) = result.exit_code {
             writeln!(
@@ -239,7 +969,8 @@ impl OutputFormatter {
 
         // Threshold Status
         if result.threshold_exceeded {
-            writeln!(stdout, "\n⚠️  THRESHOLD EXCEEDED")?;
+            writeln!(stdout, "\n{}", style.bad("⚠️  THRESHOLD EXCEEDED"))?;
+            Self::print_threshold_event(stdout, result, units, style)?;
         }
 
         stdout.flush()?;
@@ -269,25 +1000,59 @@ impl OutputFormatter {
             tree.name.clone()
         };
 
-        let memory_str = if let Some(unit) = units {
-            unit.format(tree.memory.rss_bytes)
-        } else {
-            ByteSize::b(tree.memory.rss_bytes).to_string()
+        // The process's own peak, which may not be when the tree peaked
+        let memory_str = match tree.rss_stats {
+            Some(stats) => format!(
+                "{} (min {}, avg {}, {} at the tree's peak)",
+                format_bytes(stats.peak_bytes, units),
+                format_bytes(stats.min_bytes, units),
+                format_bytes(stats.avg_bytes, units),
+                format_bytes(tree.memory.rss_bytes, units)
+            ),
+            None => format_bytes(tree.memory.rss_bytes, units),
+        };
+
+        let threads_str = match tree.memory.thread_count {
+            0 => String::new(),
+            1 => ", 1 thread".to_string(),
+            n => format!(", {n} threads"),
+        };
+
+        let namespace_str = match tree.namespace {
+            Some(ns) => format!(
+                ", {} in PID namespace {}{}",
+                ns.pid,
+                ns.inode,
+                if ns.via_cgroup {
+                    ", found by cgroup"
+                } else {
+                    ""
+                }
+            ),
+            None => String::new(),
         };
 
         writeln!(
             stdout,
-            "{}{}{} (PID: {}) - Peak: {}",
+            "{}{}{} (PID: {}{}) - Peak: {}{}",
             prefix,
             if prefix.is_empty() { "" } else { connector },
             name,
             tree.pid,
-            memory_str
+            namespace_str,
+            memory_str,
+            threads_str
         )?;
 
         // Sort children by peak RSS (descending)
         let mut children = tree.children.clone();
-        children.sort_by_key(|child| std::cmp::Reverse(child.memory.rss_bytes));
+        children.sort_by_key(|child| {
+            std::cmp::Reverse(
+                child
+                    .rss_stats
+                    .map_or(child.memory.rss_bytes, |stats| stats.peak_bytes),
+            )
+        });
 
         // Print children with proper tree structure
         let child_prefix = format!(
@@ -313,188 +1078,1075 @@ impl OutputFormatter {
     /// Formats baseline comparison results.
     ///
     /// # Arguments
-    /// * `comparison` - The comparison results
-    /// * `format` - The output format to use
+    /// * `stdout` - Where to write, standard output unless `--output` was given
+    /// * `comparison` - The comparison results
+    /// * `format` - The output format to use
+    /// * `units` - Optional fixed memory unit to use for display
+    pub fn format_comparison(
+        stdout: &mut dyn Write,
+        comparison: &ComparisonResult,
+        format: OutputFormat,
+        units: Option<MemoryUnit>,
+        style: Style,
+    ) -> Result<()> {
+        match format {
+            OutputFormat::Human => Self::format_comparison_human(stdout, comparison, units, style),
+            OutputFormat::Json => Self::format_comparison_json(stdout, comparison),
+            OutputFormat::Csv => Self::format_comparison_csv(stdout, comparison),
+            OutputFormat::Quiet => Self::format_comparison_quiet(stdout, comparison),
+            OutputFormat::Influx => {
+                write!(
+                    stdout,
+                    "{}",
+                    influx_lines(&comparison.current, Some(comparison))
+                )?;
+                Ok(())
+            }
+        }
+    }
+
+    fn format_comparison_human(
+        stdout: &mut dyn Write,
+        comparison: &ComparisonResult,
+        units: Option<MemoryUnit>,
+        style: Style,
+    ) -> Result<()> {
+        writeln!(
+            stdout,
+            "{}",
+            style.dim(&format!("Command: {}", comparison.current.command))
+        )?;
+        let baseline_git = comparison.baseline.git();
+        if baseline_git.is_some() || comparison.current.git.is_some() {
+            let describe = |git: Option<&GitInfo>| {
+                git.map_or_else(|| "unknown".to_string(), |git| git.to_string())
+            };
+            writeln!(
+                stdout,
+                "{}",
+                style.dim(&format!(
+                    "Commit: {} → {}",
+                    describe(baseline_git.as_ref()),
+                    describe(comparison.current.git.as_ref())
+                ))
+            )?;
+        }
+        writeln!(stdout)?;
+
+        writeln!(stdout, "Baseline vs Current:")?;
+        if let Some(unit) = units {
+            writeln!(
+                stdout,
+                "  Peak RSS: {} → {} ({:+.1}%)",
+                unit.format(comparison.baseline.peak_rss_bytes),
+                unit.format(comparison.current.peak_rss_bytes),
+                comparison.rss_diff_percent
+            )?;
+        } else {
+            writeln!(
+                stdout,
+                "  Peak RSS: {} → {} ({:+.1}%)",
+                ByteSize::b(comparison.baseline.peak_rss_bytes),
+                comparison.current.peak_rss(),
+                comparison.rss_diff_percent
+            )?;
+        }
+
+        if comparison.rss_diff_bytes > 0 {
+            if let Some(unit) = units {
+                writeln!(
+                    stdout,
+                    "  Absolute increase: {}",
+                    unit.format(comparison.rss_diff_bytes as u64)
+                )?;
+            } else {
+                writeln!(
+                    stdout,
+                    "  Absolute increase: {}",
+                    ByteSize::b(comparison.rss_diff_bytes as u64)
+                )?;
+            }
+        } else if comparison.rss_diff_bytes < 0 {
+            if let Some(unit) = units {
+                writeln!(
+                    stdout,
+                    "  Absolute decrease: {}",
+                    unit.format((-comparison.rss_diff_bytes) as u64)
+                )?;
+            } else {
+                writeln!(
+                    stdout,
+                    "  Absolute decrease: {}",
+                    ByteSize::b((-comparison.rss_diff_bytes) as u64)
+                )?;
+            }
+        }
+        if let Some(floor) = comparison.ignored_below_bytes {
+            let floor = format_bytes(floor, units);
+            writeln!(stdout, "  Memory differences below {floor} ignored")?;
+        }
+        if let Some(distribution) = &comparison.rss_distribution {
+            write!(
+                stdout,
+                "  Baseline of {} runs: mean {}, σ {}, {} to {}",
+                distribution.runs,
+                format_bytes(distribution.mean_bytes, units),
+                format_bytes(distribution.stddev_bytes, units),
+                format_bytes(distribution.min_bytes, units),
+                format_bytes(distribution.max_bytes, units)
+            )?;
+            match (comparison.rss_z_score, comparison.noise_sigmas) {
+                (Some(z), Some(limit)) => {
+                    writeln!(stdout, " ({z:+.1}σ, regression above {limit:+.1}σ)")?
+                }
+                (Some(z), None) => writeln!(stdout, " ({z:+.1}σ)")?,
+                _ => writeln!(stdout)?,
+            }
+        }
+
+        writeln!(stdout)?;
+        if let Some(unit) = units {
+            writeln!(
+                stdout,
+                "  Peak VSZ: {} → {} ({:+.1}%)",
+                unit.format(comparison.baseline.peak_vsz_bytes),
+                unit.format(comparison.current.peak_vsz_bytes),
+                comparison.vsz_diff_percent
+            )?;
+        } else {
+            writeln!(
+                stdout,
+                "  Peak VSZ: {} → {} ({:+.1}%)",
+                ByteSize::b(comparison.baseline.peak_vsz_bytes),
+                comparison.current.peak_vsz(),
+                comparison.vsz_diff_percent
+            )?;
+        }
+
+        writeln!(stdout)?;
+        writeln!(
+            stdout,
+            "  Duration: {:.1}s → {:.1}s ({:+.1}%)",
+            comparison.baseline.duration_ms as f64 / 1000.0,
+            comparison.current.duration().as_secs_f64(),
+            comparison.duration_diff_percent
+        )?;
+
+        writeln!(stdout)?;
+        writeln!(
+            stdout,
+            "  Major faults: {} → {} ({:+.1}%)",
+            comparison.baseline.major_faults,
+            comparison.current.major_faults,
+            comparison.major_faults_diff_percent
+        )?;
+        writeln!(
+            stdout,
+            "  Minor faults: {} → {} ({:+.1}%)",
+            comparison.baseline.minor_faults,
+            comparison.current.minor_faults,
+            comparison.minor_faults_diff_percent
+        )?;
+
+        writeln!(stdout)?;
+        writeln!(
+            stdout,
+            "  CPU time: {:.1}s → {:.1}s ({:+.1}%)",
+            comparison.baseline.cpu_time_ms() as f64 / 1000.0,
+            comparison.current.cpu_time_ms() as f64 / 1000.0,
+            comparison.cpu_time_diff_percent
+        )?;
+
+        if let (Some(baseline_gpu), Some(current_gpu), Some(percent)) = (
+            comparison.baseline.peak_gpu_bytes,
+            comparison.current.peak_gpu_bytes,
+            comparison.gpu_diff_percent,
+        ) {
+            writeln!(stdout)?;
+            writeln!(
+                stdout,
+                "  Peak GPU memory: {} → {} ({percent:+.1}%)",
+                format_bytes(baseline_gpu, units),
+                format_bytes(current_gpu, units)
+            )?;
+        }
+
+        if let Some(changes) = &comparison.fingerprint_changes {
+            writeln!(stdout)?;
+            if comparison.fingerprint_key_changed {
+                writeln!(
+                    stdout,
+                    "  Environment: not comparable (different fingerprint key)"
+                )?;
+                for change in changes {
+                    writeln!(stdout, "    {change}")?;
+                }
+            } else if changes.is_empty() {
+                writeln!(stdout, "  Environment: unchanged")?;
+            } else {
+                writeln!(stdout, "  Environment changed:")?;
+                for change in changes {
+                    writeln!(stdout, "    {change}")?;
+                }
+            }
+        }
+
+        writeln!(stdout)?;
+        if comparison.regression_detected {
+            let mut regressed = |what: &str, percent: f64| {
+                let line = format!("❌ REGRESSION DETECTED: {what} increased by {percent:.1}%");
+                writeln!(stdout, "{}", style.bad(&line))
+            };
+            if comparison.memory_regression_detected {
+                regressed("Memory usage", comparison.rss_diff_percent)?;
+            }
+            if comparison.fault_regression_detected {
+                regressed("Major page faults", comparison.major_faults_diff_percent)?;
+            }
+            if comparison.cpu_regression_detected {
+                regressed("CPU time", comparison.cpu_time_diff_percent)?;
+            }
+            if comparison.gpu_regression_detected {
+                regressed(
+                    "GPU memory",
+                    comparison.gpu_diff_percent.unwrap_or_default(),
+                )?;
+            }
+        } else {
+            writeln!(stdout, "{}", style.ok("✅ No regression detected"))?;
+        }
+        if comparison.noisy_environment {
+            writeln!(
+                stdout,
+                "⚠️  High memory pressure during one of the runs; results may be noisy"
+            )?;
+        }
+        if comparison.throttled {
+            writeln!(
+                stdout,
+                "⚠️  CPU throttled during one of the runs; duration and CPU time may not be comparable"
+            )?;
+        }
+        if let Some(other) = &comparison.other_platform {
+            writeln!(
+                stdout,
+                "⚠️  Baseline recorded on {other}; the numbers measure the platform as much as the code"
+            )?;
+        }
+
+        stdout.flush()?;
+        Ok(())
+    }
+
+    fn format_comparison_json(stdout: &mut dyn Write, comparison: &ComparisonResult) -> Result<()> {
+        let json = serde_json::to_string_pretty(comparison)?;
+        writeln!(stdout, "{json}")?;
+        Ok(())
+    }
+
+    fn format_comparison_csv(stdout: &mut dyn Write, comparison: &ComparisonResult) -> Result<()> {
+        let mut wtr = CsvWriter::new(stdout);
+
+        wtr.write_record(&[
+            "baseline_command",
+            "baseline_rss_bytes",
+            "baseline_vsz_bytes",
+            "baseline_duration_ms",
+            "current_command",
+            "current_rss_bytes",
+            "current_vsz_bytes",
+            "current_duration_ms",
+            "rss_diff_bytes",
+            "rss_diff_percent",
+            "vsz_diff_bytes",
+            "vsz_diff_percent",
+            "duration_diff_ms",
+            "duration_diff_percent",
+            "minor_faults_diff",
+            "major_faults_diff",
+            "major_faults_diff_percent",
+            "cpu_time_diff_ms",
+            "cpu_time_diff_percent",
+            "gpu_diff_bytes",
+            "gpu_diff_percent",
+            "regression_detected",
+            "noisy_environment",
+            "throttled",
+        ])?;
+
+        wtr.write_record(&[
+            &comparison.baseline.command,
+            &comparison.baseline.peak_rss_bytes.to_string(),
+            &comparison.baseline.peak_vsz_bytes.to_string(),
+            &comparison.baseline.duration_ms.to_string(),
+            &comparison.current.command,
+            &comparison.current.peak_rss_bytes.to_string(),
+            &comparison.current.peak_vsz_bytes.to_string(),
+            &comparison.current.duration_ms.to_string(),
+            &comparison.rss_diff_bytes.to_string(),
+            &comparison.rss_diff_percent.to_string(),
+            &comparison.vsz_diff_bytes.to_string(),
+            &comparison.vsz_diff_percent.to_string(),
+            &comparison.duration_diff_ms.to_string(),
+            &comparison.duration_diff_percent.to_string(),
+            &comparison.minor_faults_diff.to_string(),
+            &comparison.major_faults_diff.to_string(),
+            &comparison.major_faults_diff_percent.to_string(),
+            &comparison.cpu_time_diff_ms.to_string(),
+            &comparison.cpu_time_diff_percent.to_string(),
+            &comparison
+                .gpu_diff_bytes
+                .map_or(String::new(), |d| d.to_string()),
+            &comparison
+                .gpu_diff_percent
+                .map_or(String::new(), |p| p.to_string()),
+            &comparison.regression_detected.to_string(),
+            &comparison.noisy_environment.to_string(),
+            &comparison.throttled.to_string(),
+        ])?;
+
+        wtr.flush()?;
+        Ok(())
+    }
+
+    fn format_comparison_quiet(
+        stdout: &mut dyn Write,
+        comparison: &ComparisonResult,
+    ) -> Result<()> {
+        if comparison.regression_detected {
+            writeln!(stdout, "regression")?;
+        } else {
+            writeln!(stdout, "ok")?;
+        }
+        Ok(())
+    }
+
+    /// Formats the diff of two peak process trees.
+    ///
+    /// # Arguments
+    /// * `diff` - The aligned trees
+    /// * `format` - The output format to use; quiet prints the change in the
+    ///   whole tree's RSS
+    /// * `units` - Optional fixed memory unit to use for display
+    pub fn format_tree_diff(
+        diff: &TreeDiff,
+        format: OutputFormat,
+        units: Option<MemoryUnit>,
+    ) -> Result<()> {
+        match format {
+            OutputFormat::Human => Self::format_tree_diff_human(diff, units),
+            OutputFormat::Influx => Err(unsupported_format("--influx", "--diff-trees")),
+            OutputFormat::Json => {
+                println!("{}", serde_json::to_string_pretty(diff)?);
+                Ok(())
+            }
+            OutputFormat::Csv => {
+                let mut wtr = CsvWriter::new(io::stdout());
+                wtr.write_record(&[
+                    "process",
+                    "status",
+                    "before_rss_bytes",
+                    "after_rss_bytes",
+                    "rss_diff_bytes",
+                ])?;
+                Self::write_tree_diff_csv(&mut wtr, diff, "")?;
+                wtr.flush()
+            }
+            OutputFormat::Quiet => {
+                println!("{}", diff.total_diff_bytes());
+                Ok(())
+            }
+        }
+    }
+
+    fn format_tree_diff_human(diff: &TreeDiff, units: Option<MemoryUnit>) -> Result<()> {
+        let mut stdout = io::stdout();
+        let signed = |diff: i64| {
+            let sign = if diff < 0 { "-" } else { "+" };
+            format!("{sign}{}", format_bytes(diff.unsigned_abs(), units))
+        };
+
+        writeln!(stdout, "Process tree diff (peak RSS of each process):")?;
+        Self::print_tree_diff(&mut stdout, diff, "", true, units, &signed)?;
+        writeln!(stdout)?;
+        writeln!(stdout, "Whole tree: {}", signed(diff.total_diff_bytes()))?;
+        if let Some((path, growth)) = diff.largest_growth() {
+            writeln!(
+                stdout,
+                "Largest growth: {} ({})",
+                path.join(" > "),
+                signed(growth)
+            )?;
+        }
+        for (status, label) in [
+            (DiffStatus::Added, "Added"),
+            (DiffStatus::Removed, "Removed"),
+        ] {
+            let names = diff.names_with_status(status);
+            if !names.is_empty() {
+                writeln!(stdout, "{label}: {}", names.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn print_tree_diff(
+        stdout: &mut dyn Write,
+        diff: &TreeDiff,
+        prefix: &str,
+        is_last: bool,
+        units: Option<MemoryUnit>,
+        signed: &dyn Fn(i64) -> String,
+    ) -> Result<()> {
+        let connector = if is_last { "└── " } else { "├── " };
+        let change = match (diff.before_rss_bytes, diff.after_rss_bytes) {
+            (Some(before), Some(after)) => format!(
+                "{} → {} ({})",
+                format_bytes(before, units),
+                format_bytes(after, units),
+                signed(diff.rss_diff_bytes)
+            ),
+            (None, Some(after)) => format!("added ({})", format_bytes(after, units)),
+            (Some(before), None) => format!("removed (was {})", format_bytes(before, units)),
+            (None, None) => String::new(),
+        };
+        writeln!(
+            stdout,
+            "{}{}{}: {}",
+            prefix,
+            if prefix.is_empty() { "" } else { connector },
+            diff.name,
+            change
+        )?;
+
+        let child_prefix = if prefix.is_empty() {
+            "  ".to_string()
+        } else if is_last {
+            format!("{prefix}    ")
+        } else {
+            format!("{prefix}│   ")
+        };
+        for (i, child) in diff.children.iter().enumerate() {
+            let is_last_child = i == diff.children.len() - 1;
+            Self::print_tree_diff(stdout, child, &child_prefix, is_last_child, units, signed)?;
+        }
+        Ok(())
+    }
+
+    /// Formats the saved baselines, for `--list-baselines`.
+    ///
+    /// # Arguments
+    /// * `baselines` - The baselines, by name
+    /// * `format` - The output format to use; quiet prints only the names
+    /// * `units` - Optional fixed memory unit to use for display
+    pub fn format_baseline_list(
+        baselines: &[BaselineSummary],
+        format: OutputFormat,
+        units: Option<MemoryUnit>,
+    ) -> Result<()> {
+        let mut stdout = io::stdout();
+        match format {
+            OutputFormat::Json => {
+                writeln!(stdout, "{}", serde_json::to_string_pretty(baselines)?)?;
+            }
+            OutputFormat::Csv => {
+                let mut wtr = CsvWriter::new(stdout);
+                wtr.write_record(&[
+                    "name",
+                    "command",
+                    "peak_rss_bytes",
+                    "duration_ms",
+                    "platform",
+                    "created_at",
+                ])?;
+                for baseline in baselines {
+                    wtr.write_record(&[
+                        &baseline.name,
+                        &baseline.command,
+                        &baseline.peak_rss_bytes.to_string(),
+                        &baseline.duration_ms.to_string(),
+                        baseline.platform.as_deref().unwrap_or(""),
+                        &baseline.created_at.to_rfc3339(),
+                    ])?;
+                }
+                wtr.flush()?;
+            }
+            OutputFormat::Quiet => {
+                for baseline in baselines {
+                    writeln!(stdout, "{}", baseline.name)?;
+                }
+            }
+            OutputFormat::Influx => return Err(unsupported_format("--influx", "--list-baselines")),
+            OutputFormat::Human => {
+                if baselines.is_empty() {
+                    writeln!(stdout, "No baselines found.")?;
+                    return Ok(());
+                }
+                let mut rows = vec![[
+                    "NAME".to_string(),
+                    "COMMAND".to_string(),
+                    "PEAK RSS".to_string(),
+                    "DURATION".to_string(),
+                    "PLATFORM".to_string(),
+                    "CREATED".to_string(),
+                ]];
+                rows.extend(baselines.iter().map(|baseline| {
+                    [
+                        baseline.name.clone(),
+                        truncate(&baseline.command, 40),
+                        format_bytes(baseline.peak_rss_bytes, units),
+                        format!("{:.1}s", baseline.duration_ms as f64 / 1000.0),
+                        baseline.platform.clone().unwrap_or_else(|| "-".to_string()),
+                        baseline.created_at.format_datetime(),
+                    ]
+                }));
+                let mut widths = [0; 6];
+                for row in &rows {
+                    for (width, cell) in widths.iter_mut().zip(row) {
+                        *width = (*width).max(cell.chars().count());
+                    }
+                }
+
+                writeln!(stdout, "Saved baselines:")?;
+                for row in &rows {
+                    let cells: Vec<String> = row
+                        .iter()
+                        .zip(widths)
+                        .map(|(cell, width)| format!("{cell:width$}"))
+                        .collect();
+                    writeln!(stdout, "  {}", cells.join("  ").trim_end())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Formats how a baseline evolved over its saves, for `--trend`.
+    ///
+    /// # Arguments
+    /// * `trend` - The baseline's most recent saves
+    /// * `format` - The output format to use; quiet prints the peak RSS of each
+    ///   save
+    /// * `units` - Optional fixed memory unit to use for display
+    pub fn format_trend(
+        trend: &Trend,
+        format: OutputFormat,
+        units: Option<MemoryUnit>,
+    ) -> Result<()> {
+        let mut stdout = io::stdout();
+        match format {
+            OutputFormat::Json => {
+                writeln!(stdout, "{}", serde_json::to_string_pretty(trend)?)?;
+            }
+            OutputFormat::Csv => {
+                let mut wtr = CsvWriter::new(stdout);
+                wtr.write_record(&["recorded_at", "peak_rss_bytes", "duration_ms"])?;
+                for run in &trend.runs {
+                    wtr.write_record(&[
+                        &run.recorded_at.to_rfc3339(),
+                        &run.peak_rss_bytes.to_string(),
+                        &run.duration_ms.to_string(),
+                    ])?;
+                }
+                wtr.flush()?;
+            }
+            OutputFormat::Quiet => {
+                for run in &trend.runs {
+                    writeln!(stdout, "{}", run.peak_rss_bytes)?;
+                }
+            }
+            OutputFormat::Influx => return Err(unsupported_format("--influx", "--trend")),
+            OutputFormat::Human => {
+                let format_duration = |ms: u64| format!("{:.2}s", ms as f64 / 1000.0);
+
+                writeln!(
+                    stdout,
+                    "Trend of baseline '{}': {}",
+                    trend.name, trend.command
+                )?;
+                writeln!(
+                    stdout,
+                    "Last {} of {} saves:",
+                    trend.runs.len(),
+                    trend.total_runs
+                )?;
+                writeln!(stdout)?;
+                let rss_width = trend
+                    .runs
+                    .iter()
+                    .map(|run| format_bytes(run.peak_rss_bytes, units).len())
+                    .max()
+                    .unwrap_or(0)
+                    .max("PEAK RSS".len());
+                writeln!(
+                    stdout,
+                    "  {:19}  {:>rss_width$}  DURATION",
+                    "RECORDED", "PEAK RSS"
+                )?;
+                for run in &trend.runs {
+                    writeln!(
+                        stdout,
+                        "  {:19}  {:>rss_width$}  {:>8}",
+                        run.recorded_at.format_datetime(),
+                        format_bytes(run.peak_rss_bytes, units),
+                        format_duration(run.duration_ms)
+                    )?;
+                }
+
+                writeln!(stdout)?;
+                let rss: Vec<u64> = trend.runs.iter().map(|run| run.peak_rss_bytes).collect();
+                let durations: Vec<u64> = trend.runs.iter().map(|run| run.duration_ms).collect();
+                writeln!(
+                    stdout,
+                    "  Peak RSS  {}",
+                    trend_line(&rss, |bytes| format_bytes(bytes, units))
+                )?;
+                writeln!(
+                    stdout,
+                    "  Duration  {}",
+                    trend_line(&durations, format_duration)
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Formats runs recorded in a history database, for
+    /// `--query-history`.
+    ///
+    /// # Arguments
+    /// * `runs` - The matching runs, oldest first
+    /// * `format` - The output format to use; quiet prints the peak RSS of each
+    ///   run
     /// * `units` - Optional fixed memory unit to use for display
-    pub fn format_comparison(
-        comparison: &ComparisonResult,
+    #[cfg(feature = "sqlite")]
+    pub fn format_history(
+        runs: &[HistoryRun],
         format: OutputFormat,
         units: Option<MemoryUnit>,
     ) -> Result<()> {
+        let mut stdout = io::stdout();
         match format {
-            OutputFormat::Human => Self::format_comparison_human(comparison, units),
-            OutputFormat::Json => Self::format_comparison_json(comparison),
-            OutputFormat::Csv => Self::format_comparison_csv(comparison),
-            OutputFormat::Quiet => Self::format_comparison_quiet(comparison),
+            OutputFormat::Json => {
+                writeln!(stdout, "{}", serde_json::to_string_pretty(runs)?)?;
+            }
+            OutputFormat::Csv => {
+                let mut wtr = CsvWriter::new(stdout);
+                wtr.write_record(&[
+                    "id",
+                    "recorded_at",
+                    "command",
+                    "peak_rss_bytes",
+                    "duration_ms",
+                    "exit_code",
+                    "git_commit",
+                ])?;
+                for run in runs {
+                    wtr.write_record(&[
+                        &run.id.to_string(),
+                        &run.recorded_at.to_rfc3339(),
+                        &run.command,
+                        &run.peak_rss_bytes.to_string(),
+                        &run.duration_ms.to_string(),
+                        &run.exit_code.map_or(String::new(), |code| code.to_string()),
+                        run.git_commit.as_deref().unwrap_or(""),
+                    ])?;
+                }
+                wtr.flush()?;
+            }
+            OutputFormat::Quiet => {
+                for run in runs {
+                    writeln!(stdout, "{}", run.peak_rss_bytes)?;
+                }
+            }
+            OutputFormat::Influx => return Err(unsupported_format("--influx", "--query-history")),
+            OutputFormat::Human => {
+                if runs.is_empty() {
+                    writeln!(stdout, "No recorded runs match.")?;
+                    return Ok(());
+                }
+                let rss_width = runs
+                    .iter()
+                    .map(|run| format_bytes(run.peak_rss_bytes, units).len())
+                    .max()
+                    .unwrap_or(0)
+                    .max("PEAK RSS".len());
+                writeln!(
+                    stdout,
+                    "{:19}  {:>rss_width$}  {:>8}  {:>4}  {:8}  COMMAND",
+                    "RECORDED", "PEAK RSS", "DURATION", "EXIT", "COMMIT"
+                )?;
+                for run in runs {
+                    let commit = run.git_commit.as_deref().unwrap_or("-");
+                    writeln!(
+                        stdout,
+                        "{:19}  {:>rss_width$}  {:>8}  {:>4}  {:8}  {}",
+                        run.recorded_at.format_datetime(),
+                        format_bytes(run.peak_rss_bytes, units),
+                        format!("{:.2}s", run.duration_ms as f64 / 1000.0),
+                        run.exit_code
+                            .map_or("-".to_string(), |code| code.to_string()),
+                        &commit[..commit.len().min(8)],
+                        run.command
+                    )?;
+                }
+                writeln!(stdout)?;
+                let plural = if runs.len() == 1 { "" } else { "s" };
+                writeln!(stdout, "{} run{plural}", runs.len())?;
+            }
         }
+        Ok(())
     }
 
-    fn format_comparison_human(
-        comparison: &ComparisonResult,
+    /// Formats a saved baseline, for `--show-baseline`.
+    ///
+    /// # Arguments
+    /// * `name` - The baseline's name
+    /// * `baseline` - The baseline
+    /// * `format` - The output format to use; JSON prints the baseline as
+    ///   stored, human the summary
+    /// * `units` - Optional fixed memory unit to use for display
+    pub fn format_baseline(
+        name: &str,
+        baseline: &Baseline,
+        format: OutputFormat,
         units: Option<MemoryUnit>,
     ) -> Result<()> {
         let mut stdout = io::stdout();
+        match format {
+            OutputFormat::Human => {}
+            OutputFormat::Json => {
+                writeln!(stdout, "{}", serde_json::to_string_pretty(baseline)?)?;
+                return Ok(());
+            }
+            OutputFormat::Csv => return Err(unsupported_format("--csv", "--show-baseline")),
+            OutputFormat::Quiet => return Err(unsupported_format("--quiet", "--show-baseline")),
+            OutputFormat::Influx => return Err(unsupported_format("--influx", "--show-baseline")),
+        }
 
-        writeln!(stdout, "Command: {}", comparison.current.command)?;
-        writeln!(stdout)?;
-
-        writeln!(stdout, "Baseline vs Current:")?;
-        if let Some(unit) = units {
-            writeln!(
-                stdout,
-                "  Peak RSS: {} → {} ({:+.1}%)",
-                unit.format(comparison.baseline.peak_rss_bytes),
-                unit.format(comparison.current.peak_rss_bytes),
-                comparison.rss_diff_percent
-            )?;
-        } else {
+        writeln!(stdout, "Baseline: {name}")?;
+        writeln!(stdout, "Command: {}", baseline.command)?;
+        writeln!(
+            stdout,
+            "Created: {} (peak-mem {})",
+            baseline.created_at.format_datetime(),
+            baseline.version
+        )?;
+        writeln!(
+            stdout,
+            "Peak memory usage: {} (RSS) / {} (VSZ)",
+            format_bytes(baseline.peak_rss_bytes, units),
+            format_bytes(baseline.peak_vsz_bytes, units)
+        )?;
+        if let Some(gpu) = baseline.peak_gpu_bytes {
+            writeln!(stdout, "Peak GPU memory: {}", format_bytes(gpu, units))?;
+        }
+        writeln!(
+            stdout,
+            "Page faults: {} major / {} minor",
+            baseline.major_faults, baseline.minor_faults
+        )?;
+        writeln!(
+            stdout,
+            "Duration: {:.1}s",
+            baseline.duration_ms as f64 / 1000.0
+        )?;
+        writeln!(
+            stdout,
+            "CPU time: {:.1}s user / {:.1}s system",
+            baseline.user_time_ms as f64 / 1000.0,
+            baseline.system_time_ms as f64 / 1000.0
+        )?;
+        if let Some(distribution) = baseline.rss_distribution() {
             writeln!(
                 stdout,
-                "  Peak RSS: {} → {} ({:+.1}%)",
-                ByteSize::b(comparison.baseline.peak_rss_bytes),
-                comparison.current.peak_rss(),
-                comparison.rss_diff_percent
+                "Runs: {}, averaged above (peak RSS {} to {}, σ {})",
+                distribution.runs,
+                format_bytes(distribution.min_bytes, units),
+                format_bytes(distribution.max_bytes, units),
+                format_bytes(distribution.stddev_bytes, units)
             )?;
         }
+        if baseline.timeline.is_some() {
+            writeln!(stdout, "Timeline: recorded")?;
+        }
+        if !baseline.metadata.is_empty() {
+            writeln!(stdout, "Metadata:")?;
+            let mut metadata: Vec<_> = baseline.metadata.iter().collect();
+            metadata.sort();
+            for (key, value) in metadata {
+                writeln!(stdout, "  {key}: {value}")?;
+            }
+        }
+        Ok(())
+    }
 
-        if comparison.rss_diff_bytes > 0 {
-            if let Some(unit) = units {
-                writeln!(
-                    stdout,
-                    "  Absolute increase: {}",
-                    unit.format(comparison.rss_diff_bytes as u64)
-                )?;
-            } else {
-                writeln!(
-                    stdout,
-                    "  Absolute increase: {}",
-                    ByteSize::b(comparison.rss_diff_bytes as u64)
-                )?;
+    /// Formats the statistics of merged runs.
+    ///
+    /// # Arguments
+    /// * `merged` - The merged runs
+    /// * `format` - The output format to use; quiet prints the mean peak RSS
+    /// * `units` - Optional fixed memory unit to use for display
+    pub fn format_merged(
+        merged: &MergedRuns,
+        format: OutputFormat,
+        units: Option<MemoryUnit>,
+    ) -> Result<()> {
+        let mut stdout = io::stdout();
+        match format {
+            OutputFormat::Json => {
+                writeln!(stdout, "{}", serde_json::to_string_pretty(merged)?)?;
             }
-        } else if comparison.rss_diff_bytes < 0 {
-            if let Some(unit) = units {
-                writeln!(
-                    stdout,
-                    "  Absolute decrease: {}",
-                    unit.format((-comparison.rss_diff_bytes) as u64)
-                )?;
-            } else {
+            OutputFormat::Csv => {
+                let mut wtr = CsvWriter::new(stdout);
+                wtr.write_record(&["metric", "runs", "min", "mean", "max", "stddev"])?;
+                for (metric, stats) in [
+                    ("peak_rss_bytes", merged.peak_rss_bytes),
+                    ("duration_ms", merged.duration_ms),
+                ] {
+                    wtr.write_record(&[
+                        metric,
+                        &merged.runs.to_string(),
+                        &stats.min.to_string(),
+                        &stats.mean.to_string(),
+                        &stats.max.to_string(),
+                        &stats.stddev.to_string(),
+                    ])?;
+                }
+                wtr.flush()?;
+            }
+            OutputFormat::Quiet => writeln!(stdout, "{}", merged.peak_rss_bytes.mean)?,
+            OutputFormat::Influx => return Err(unsupported_format("--influx", "--merge")),
+            OutputFormat::Human => {
+                let seconds = |ms: u64| format!("{:.2}s", ms as f64 / 1000.0);
+                writeln!(stdout, "Command: {}", merged.command)?;
+                if merged.failed_runs > 0 {
+                    writeln!(
+                        stdout,
+                        "Runs: {} ({} failed)",
+                        merged.runs, merged.failed_runs
+                    )?;
+                } else {
+                    writeln!(stdout, "Runs: {}", merged.runs)?;
+                }
+                let line = |label: &str, stats: Stats, format: &dyn Fn(u64) -> String| {
+                    format!(
+                        "{label}: {} ± {} (min {}, max {})",
+                        format(stats.mean),
+                        format(stats.stddev),
+                        format(stats.min),
+                        format(stats.max)
+                    )
+                };
                 writeln!(
                     stdout,
-                    "  Absolute decrease: {}",
-                    ByteSize::b((-comparison.rss_diff_bytes) as u64)
+                    "{}",
+                    line("Peak RSS", merged.peak_rss_bytes, &|bytes| {
+                        format_bytes(bytes, units)
+                    })
                 )?;
+                writeln!(stdout, "{}", line("Duration", merged.duration_ms, &seconds))?;
             }
         }
+        Ok(())
+    }
 
-        writeln!(stdout)?;
-        if let Some(unit) = units {
-            writeln!(
-                stdout,
-                "  Peak VSZ: {} → {} ({:+.1}%)",
-                unit.format(comparison.baseline.peak_vsz_bytes),
-                unit.format(comparison.current.peak_vsz_bytes),
-                comparison.vsz_diff_percent
-            )?;
-        } else {
-            writeln!(
-                stdout,
-                "  Peak VSZ: {} → {} ({:+.1}%)",
-                ByteSize::b(comparison.baseline.peak_vsz_bytes),
-                comparison.current.peak_vsz(),
-                comparison.vsz_diff_percent
-            )?;
+    /// Formats the results of `--bench-self`.
+    ///
+    /// # Arguments
+    /// * `results` - The cost of sampling each tree size
+    /// * `format` - The output format to use
+    pub fn format_self_bench(results: &[BenchResult], format: OutputFormat) -> Result<()> {
+        match format {
+            OutputFormat::Json => {
+                println!("{}", serde_json::to_string_pretty(results)?);
+                Ok(())
+            }
+            OutputFormat::Csv => {
+                let mut wtr = CsvWriter::new(io::stdout());
+                wtr.write_record(&[
+                    "processes",
+                    "samples",
+                    "wall_us_per_sample",
+                    "cpu_us_per_sample",
+                    "interval_ms",
+                    "cpu_percent",
+                ])?;
+                for result in results {
+                    for overhead in &result.overhead {
+                        wtr.write_record(&[
+                            &result.processes.to_string(),
+                            &result.samples.to_string(),
+                            &format!("{:.1}", result.wall_us_per_sample),
+                            &format!("{:.1}", result.cpu_us_per_sample),
+                            &overhead.interval_ms.to_string(),
+                            &format!("{:.3}", overhead.cpu_percent),
+                        ])?;
+                    }
+                }
+                wtr.flush()
+            }
+            OutputFormat::Human => Self::format_self_bench_human(results),
+            OutputFormat::Quiet => Err(unsupported_format("--quiet", "--bench-self")),
+            OutputFormat::Influx => Err(unsupported_format("--influx", "--bench-self")),
         }
+    }
 
-        writeln!(stdout)?;
+    fn format_self_bench_human(results: &[BenchResult]) -> Result<()> {
+        let mut stdout = io::stdout();
+
+        writeln!(stdout, "Mean cost of a sample on this machine:")?;
         writeln!(
             stdout,
-            "  Duration: {:.1}s → {:.1}s ({:+.1}%)",
-            comparison.baseline.duration_ms as f64 / 1000.0,
-            comparison.current.duration().as_secs_f64(),
-            comparison.duration_diff_percent
+            "  {:>9}  {:>7}  {:>12}  {:>12}",
+            "Processes", "Samples", "Wall", "CPU"
         )?;
-
-        writeln!(stdout)?;
-        if comparison.regression_detected {
+        for result in results {
             writeln!(
                 stdout,
-                "❌ REGRESSION DETECTED: Memory usage increased by {:.1}%",
-                comparison.rss_diff_percent
+                "  {:>9}  {:>7}  {:>9.0} µs  {:>9.0} µs",
+                result.processes,
+                result.samples,
+                result.wall_us_per_sample,
+                result.cpu_us_per_sample
             )?;
-        } else {
-            writeln!(stdout, "✅ No regression detected")?;
         }
+        writeln!(stdout)?;
 
-        stdout.flush()?;
-        Ok(())
-    }
-
-    fn format_comparison_json(comparison: &ComparisonResult) -> Result<()> {
-        let json = serde_json::to_string_pretty(comparison)?;
-        println!("{json}");
+        writeln!(stdout, "CPU overhead by interval (percent of one core):")?;
+        write!(stdout, "  {:>8}", "Interval")?;
+        for result in results {
+            write!(stdout, "  {:>9}", format!("{} proc", result.processes))?;
+        }
+        writeln!(stdout)?;
+        for (i, interval_ms) in INTERVALS_MS.iter().enumerate() {
+            write!(stdout, "  {:>8}", format!("{interval_ms}ms"))?;
+            for result in results {
+                write!(stdout, "  {:>8.2}%", result.overhead[i].cpu_percent)?;
+            }
+            writeln!(stdout)?;
+        }
         Ok(())
     }
 
-    fn format_comparison_csv(comparison: &ComparisonResult) -> Result<()> {
-        let mut wtr = CsvWriter::new(io::stdout());
-
-        wtr.write_record(&[
-            "baseline_command",
-            "baseline_rss_bytes",
-            "baseline_vsz_bytes",
-            "baseline_duration_ms",
-            "current_command",
-            "current_rss_bytes",
-            "current_vsz_bytes",
-            "current_duration_ms",
-            "rss_diff_bytes",
-            "rss_diff_percent",
-            "vsz_diff_bytes",
-            "vsz_diff_percent",
-            "duration_diff_ms",
-            "duration_diff_percent",
-            "regression_detected",
-        ])?;
-
+    fn write_tree_diff_csv<W: Write>(
+        wtr: &mut CsvWriter<W>,
+        diff: &TreeDiff,
+        parent: &str,
+    ) -> Result<()> {
+        let path = if parent.is_empty() {
+            diff.name.clone()
+        } else {
+            format!("{parent}/{}", diff.name)
+        };
+        let status = match diff.status {
+            DiffStatus::Changed => "changed",
+            DiffStatus::Added => "added",
+            DiffStatus::Removed => "removed",
+        };
+        let bytes = |bytes: Option<u64>| bytes.map(|b| b.to_string()).unwrap_or_default();
         wtr.write_record(&[
-            &comparison.baseline.command,
-            &comparison.baseline.peak_rss_bytes.to_string(),
-            &comparison.baseline.peak_vsz_bytes.to_string(),
-            &comparison.baseline.duration_ms.to_string(),
-            &comparison.current.command,
-            &comparison.current.peak_rss_bytes.to_string(),
-            &comparison.current.peak_vsz_bytes.to_string(),
-            &comparison.current.duration_ms.to_string(),
-            &comparison.rss_diff_bytes.to_string(),
-            &comparison.rss_diff_percent.to_string(),
-            &comparison.vsz_diff_bytes.to_string(),
-            &comparison.vsz_diff_percent.to_string(),
-            &comparison.duration_diff_ms.to_string(),
-            &comparison.duration_diff_percent.to_string(),
-            &comparison.regression_detected.to_string(),
+            &path,
+            status,
+            &bytes(diff.before_rss_bytes),
+            &bytes(diff.after_rss_bytes),
+            &diff.rss_diff_bytes.to_string(),
         ])?;
-
-        wtr.flush()?;
+        for child in &diff.children {
+            Self::write_tree_diff_csv(wtr, child, &path)?;
+        }
         Ok(())
     }
+}
 
-    fn format_comparison_quiet(comparison: &ComparisonResult) -> Result<()> {
-        if comparison.regression_detected {
-            println!("regression");
-        } else {
-            println!("ok");
-        }
-        Ok(())
+/// Draws values, oldest first, as a sparkline followed by the change
+/// from the first to the last, e.g. `▁▃█ 1.0 MiB → 2.0 MiB (+100.0%)`.
+fn trend_line(values: &[u64], format: impl Fn(u64) -> String) -> String {
+    let (Some(&first), Some(&last)) = (values.first(), values.last()) else {
+        return String::new();
+    };
+    let low = values.iter().copied().min().unwrap_or(0);
+    let high = values.iter().copied().max().unwrap_or(0);
+    let sparkline: String = values
+        .iter()
+        .map(|&value| chart::block(value, low, high))
+        .collect();
+    let change = if first == 0 {
+        String::new()
+    } else {
+        format!(
+            " ({:+.1}%)",
+            (last as f64 - first as f64) / first as f64 * 100.0
+        )
+    };
+    format!("{sparkline}  {} → {}{change}", format(first), format(last))
+}
+
+/// Cuts text to at most `width` characters, marking the cut with an
+/// ellipsis.
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
     }
+    let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+/// A line of progress for `--watch` when stdout isn't a terminal: the
+/// time, how long the run has taken so far, and the current and peak
+/// memory.
+pub fn progress_line(
+    elapsed: std::time::Duration,
+    usage: &crate::types::MemoryUsage,
+    peak_rss: u64,
+    peak_vsz: u64,
+    units: Option<MemoryUnit>,
+) -> String {
+    format!(
+        "[{} +{:.0}s] RSS {} (peak {}) | VSZ {} (peak {})",
+        usage.timestamp.to_rfc3339(),
+        elapsed.as_secs_f64(),
+        format_bytes(usage.rss_bytes, units),
+        format_bytes(peak_rss, units),
+        format_bytes(usage.vsz_bytes, units),
+        format_bytes(peak_vsz, units)
+    )
+}
+
+/// A progress event for `--watch` with `--json`: one line of JSON with
+/// the time, how long the run has taken so far in milliseconds, and the
+/// current and peak memory in bytes.
+pub fn progress_event(
+    elapsed: std::time::Duration,
+    usage: &crate::types::MemoryUsage,
+    peak_rss: u64,
+    peak_vsz: u64,
+) -> String {
+    serde_json::json!({
+        "event": "progress",
+        "timestamp": usage.timestamp,
+        "elapsed_ms": elapsed.as_millis() as u64,
+        "rss_bytes": usage.rss_bytes,
+        "peak_rss_bytes": peak_rss,
+        "vsz_bytes": usage.vsz_bytes,
+        "peak_vsz_bytes": peak_vsz,
+    })
+    .to_string()
 }
 
 /// Handles real-time display of memory usage in watch mode.
 ///
-/// Uses terminal control sequences to update the display in-place.
+/// Uses terminal control sequences to update the display in-place. A row
+/// below the totals scrolls through the RSS of the last [`WATCH_HISTORY`]
+/// updates, as far as the terminal is wide. When children are tracked,
+/// the process tree of the latest sample is shown below, each process
+/// with its current RSS.
 pub struct RealtimeDisplay {
     last_line_count: usize,
     units: Option<MemoryUnit>,
+    /// RSS at each update, oldest first.
+    history: VecDeque<u64>,
 }
 
 impl RealtimeDisplay {
@@ -503,6 +2155,7 @@ impl RealtimeDisplay {
         Self {
             last_line_count: 0,
             units,
+            history: VecDeque::with_capacity(WATCH_HISTORY),
         }
     }
 
@@ -515,12 +2168,14 @@ impl RealtimeDisplay {
     /// * `peak_rss` - Peak RSS value observed
     /// * `current_vsz` - Current VSZ value
     /// * `peak_vsz` - Peak VSZ value observed
+    /// * `tree` - The process tree of the latest sample, if tracked
     pub fn update(
         &mut self,
         current_rss: ByteSize,
         peak_rss: ByteSize,
         current_vsz: ByteSize,
         peak_vsz: ByteSize,
+        tree: Option<&ProcessMemoryInfo>,
     ) -> Result<()> {
         use crossterm::{cursor, terminal, ExecutableCommand};
         let mut stdout = io::stdout();
@@ -549,12 +2204,108 @@ impl RealtimeDisplay {
             writeln!(stdout, "Current RSS: {current_rss} | Peak RSS: {peak_rss}")?;
             writeln!(stdout, "Current VSZ: {current_vsz} | Peak VSZ: {peak_vsz}")?;
         }
-        stdout.flush()?;
-
         self.last_line_count = 2;
+
+        if self.history.len() == WATCH_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(current_rss.as_u64());
+        let width = terminal::size().map_or(80, |(columns, _)| columns as usize);
+        writeln!(stdout, "{}", self.history_line(width))?;
+        self.last_line_count += 1;
+
+        if let Some(tree) = tree {
+            // Lines that scroll off the screen can't be cleared again
+            let rows = terminal::size().map_or(24, |(_, rows)| rows as usize);
+            let lines = Self::tree_lines(tree, self.units, rows.saturating_sub(3));
+            for line in &lines {
+                writeln!(stdout, "{line}")?;
+            }
+            self.last_line_count += lines.len();
+        }
+        stdout.flush()?;
         Ok(())
     }
 
+    /// Lays out the history row in at most `width` characters: the most
+    /// recent updates, scaled from the lowest RSS shown to the highest.
+    fn history_line(&self, width: usize) -> String {
+        let range = |values: &[u64]| {
+            let low = values.iter().copied().min().unwrap_or(0);
+            let high = values.iter().copied().max().unwrap_or(0);
+            (
+                low,
+                high,
+                format!(
+                    "  {} to {}",
+                    format_bytes(low, self.units),
+                    format_bytes(high, self.units)
+                ),
+            )
+        };
+        let label = "RSS history: ";
+        let history: Vec<u64> = self.history.iter().copied().collect();
+
+        // The range of all of the history is at least as wide as that of
+        // the part shown, give or take a digit
+        let (_, _, widest) = range(&history);
+        let room = width.saturating_sub(label.len() + widest.len() + 2).max(1);
+        let shown = &history[history.len().saturating_sub(room)..];
+        let (low, high, range) = range(shown);
+        let graph: String = shown
+            .iter()
+            .map(|&rss| chart::block(rss, low, high))
+            .collect();
+        format!("{label}{graph}{range}")
+    }
+
+    /// Lays out a process tree, heaviest children first, in at most
+    /// `max_lines` lines; the last says how many processes didn't fit.
+    fn tree_lines(
+        tree: &ProcessMemoryInfo,
+        units: Option<MemoryUnit>,
+        max_lines: usize,
+    ) -> Vec<String> {
+        fn walk(
+            tree: &ProcessMemoryInfo,
+            prefix: &str,
+            connector: &str,
+            units: Option<MemoryUnit>,
+            lines: &mut Vec<String>,
+        ) {
+            let rss = format_bytes(tree.memory.rss_bytes, units);
+            lines.push(format!(
+                "{prefix}{connector}{} (PID: {}) - RSS: {rss}",
+                tree.name, tree.pid
+            ));
+            let child_prefix = match connector {
+                "" => String::new(),
+                "└── " => format!("{prefix}    "),
+                _ => format!("{prefix}│   "),
+            };
+            let mut children: Vec<_> = tree.children.iter().collect();
+            children.sort_by_key(|child| std::cmp::Reverse(child.memory.rss_bytes));
+            for (i, child) in children.iter().enumerate() {
+                let connector = if i == children.len() - 1 {
+                    "└── "
+                } else {
+                    "├── "
+                };
+                walk(child, &child_prefix, connector, units, lines);
+            }
+        }
+
+        let mut lines = Vec::new();
+        walk(tree, "", "", units, &mut lines);
+        if lines.len() > max_lines {
+            let shown = max_lines.saturating_sub(1);
+            let hidden = lines.len() - shown;
+            lines.truncate(shown);
+            lines.push(format!("... and {hidden} more processes"));
+        }
+        lines
+    }
+
     /// Clears the real-time display.
     ///
     /// Removes all lines written by the display.
@@ -576,6 +2327,7 @@ impl RealtimeDisplay {
 mod tests {
     use super::*;
     use crate::types::{MemoryUsage, Timestamp};
+    use std::collections::HashMap;
 
     #[test]
     fn test_format_quiet() {
@@ -592,10 +2344,60 @@ mod tests {
             start_time: None,
             sample_count: None,
             main_pid: None,
+            minor_faults: 0,
+            major_faults: 0,
+            peak_thread_count: 0,
+            user_time_ms: 0,
+            system_time_ms: 0,
+            cpu_utilization_percent: 0.0,
+            threshold_event: None,
+            sampling_diagnostics: None,
+            disk_read_bytes: 0,
+            disk_write_bytes: 0,
+            fingerprint: None,
+            peak_memory_pressure: None,
+            lowest_system_memory: None,
+            peak_gpu_bytes: None,
+            peak_heap_bytes: None,
+            sampling_pauses: Vec::new(),
+            observed_processes: Vec::new(),
+            degraded_mode: None,
+            allocation_counts: None,
+            targets: Vec::new(),
+            fd_summary: None,
+            container: None,
+            unit: None,
+            runs: Vec::new(),
+            system: None,
+            crates: Vec::new(),
+            translation_units: Vec::new(),
+            aggregate: None,
+            pool: None,
+            timeline_retention: None,
+            timeline_delta: None,
+            git: None,
+            metadata: HashMap::new(),
+            thermal: None,
+            process_snapshots: None,
+            rss_sparkline: None,
         };
 
         // Quiet format should just print the RSS bytes
-        OutputFormatter::format(&result, OutputFormat::Quiet, false, None).unwrap();
+        let mut stdout = Vec::new();
+        OutputFormatter::format(
+            &mut stdout,
+            &result,
+            OutputFormat::Quiet,
+            false,
+            None,
+            None,
+            Style::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(stdout).unwrap(),
+            format!("{}\n", result.peak_rss_bytes)
+        );
     }
 
     #[test]
@@ -606,44 +2408,36 @@ mod tests {
         let child_process = ProcessMemoryInfo {
             pid: 12346,
             name: "rustc".to_string(),
-            memory: MemoryUsage {
-                rss_bytes: 442_123_456,
-                vsz_bytes: 512_123_456,
-                timestamp: now,
-            },
+            memory: MemoryUsage::new(442_123_456, 512_123_456, now),
             children: vec![
                 ProcessMemoryInfo {
                     pid: 12347,
                     name: "cc".to_string(),
-                    memory: MemoryUsage {
-                        rss_bytes: 23_456_789,
-                        vsz_bytes: 45_678_901,
-                        timestamp: now,
-                    },
+                    memory: MemoryUsage::new(23_456_789, 45_678_901, now),
                     children: vec![],
+                    namespace: None,
+                    rss_stats: None,
                 },
                 ProcessMemoryInfo {
                     pid: 12348,
                     name: "ld".to_string(),
-                    memory: MemoryUsage {
-                        rss_bytes: 89_123_456,
-                        vsz_bytes: 123_456_789,
-                        timestamp: now,
-                    },
+                    memory: MemoryUsage::new(89_123_456, 123_456_789, now),
                     children: vec![],
+                    namespace: None,
+                    rss_stats: None,
                 },
             ],
+            namespace: None,
+            rss_stats: None,
         };
 
         let root_process = ProcessMemoryInfo {
             pid: 12345,
             name: "cargo".to_string(),
-            memory: MemoryUsage {
-                rss_bytes: 45_234_567,
-                vsz_bytes: 78_901_234,
-                timestamp: now,
-            },
+            memory: MemoryUsage::new(45_234_567, 78_901_234, now),
             children: vec![child_process],
+            namespace: None,
+            rss_stats: None,
         };
 
         let result = MonitorResult {
@@ -659,10 +2453,76 @@ mod tests {
             start_time: Some(now),
             sample_count: Some(142),
             main_pid: Some(12345),
+            minor_faults: 0,
+            major_faults: 0,
+            peak_thread_count: 0,
+            user_time_ms: 0,
+            system_time_ms: 0,
+            cpu_utilization_percent: 0.0,
+            threshold_event: None,
+            sampling_diagnostics: None,
+            disk_read_bytes: 0,
+            disk_write_bytes: 0,
+            fingerprint: None,
+            peak_memory_pressure: None,
+            lowest_system_memory: None,
+            peak_gpu_bytes: None,
+            peak_heap_bytes: None,
+            sampling_pauses: Vec::new(),
+            observed_processes: Vec::new(),
+            degraded_mode: None,
+            allocation_counts: None,
+            targets: Vec::new(),
+            fd_summary: None,
+            container: None,
+            unit: None,
+            runs: Vec::new(),
+            system: None,
+            crates: Vec::new(),
+            translation_units: Vec::new(),
+            aggregate: None,
+            pool: None,
+            timeline_retention: None,
+            timeline_delta: None,
+            git: None,
+            metadata: HashMap::new(),
+            thermal: None,
+            process_snapshots: None,
+            rss_sparkline: None,
         };
 
         // Test verbose format - should not panic
-        OutputFormatter::format(&result, OutputFormat::Human, true, None).unwrap();
+        OutputFormatter::format(
+            &mut Vec::new(),
+            &result,
+            OutputFormat::Human,
+            true,
+            None,
+            None,
+            Style::default(),
+        )
+        .unwrap();
+
+        // Every size in the unit asked for
+        let mut output = Vec::new();
+        let result = MonitorResult {
+            disk_read_bytes: 2_097_152,
+            peak_gpu_bytes: Some(1_048_576),
+            ..result
+        };
+        OutputFormatter::format(
+            &mut output,
+            &result,
+            OutputFormat::Human,
+            true,
+            Some(MemoryUnit::Mebibytes),
+            None,
+            Style::default(),
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Disk read: 2.0 MiB (2097152 bytes)"));
+        assert!(output.contains("Peak GPU memory: 1.0 MiB (1048576 bytes)"));
     }
 
     #[test]
@@ -682,10 +2542,55 @@ mod tests {
             start_time: Some(now),
             sample_count: Some(1),
             main_pid: Some(99999),
+            minor_faults: 0,
+            major_faults: 0,
+            peak_thread_count: 0,
+            user_time_ms: 0,
+            system_time_ms: 0,
+            cpu_utilization_percent: 0.0,
+            threshold_event: None,
+            sampling_diagnostics: None,
+            disk_read_bytes: 0,
+            disk_write_bytes: 0,
+            fingerprint: None,
+            peak_memory_pressure: None,
+            lowest_system_memory: None,
+            peak_gpu_bytes: None,
+            peak_heap_bytes: None,
+            sampling_pauses: Vec::new(),
+            observed_processes: Vec::new(),
+            degraded_mode: None,
+            allocation_counts: None,
+            targets: Vec::new(),
+            fd_summary: None,
+            container: None,
+            unit: None,
+            runs: Vec::new(),
+            system: None,
+            crates: Vec::new(),
+            translation_units: Vec::new(),
+            aggregate: None,
+            pool: None,
+            timeline_retention: None,
+            timeline_delta: None,
+            git: None,
+            metadata: HashMap::new(),
+            thermal: None,
+            process_snapshots: None,
+            rss_sparkline: None,
         };
 
         // Test verbose format without process tree
-        OutputFormatter::format(&result, OutputFormat::Human, true, None).unwrap();
+        OutputFormatter::format(
+            &mut Vec::new(),
+            &result,
+            OutputFormat::Human,
+            true,
+            None,
+            None,
+            Style::default(),
+        )
+        .unwrap();
     }
 
     #[test]
@@ -694,44 +2599,141 @@ mod tests {
         let tree = ProcessMemoryInfo {
             pid: 1,
             name: "root".to_string(),
-            memory: MemoryUsage {
-                rss_bytes: 1000,
-                vsz_bytes: 2000,
-                timestamp: now,
-            },
+            memory: MemoryUsage::new(1000, 2000, now),
             children: vec![
                 ProcessMemoryInfo {
                     pid: 2,
                     name: "child1".to_string(),
-                    memory: MemoryUsage {
-                        rss_bytes: 100,
-                        vsz_bytes: 200,
-                        timestamp: now,
-                    },
+                    memory: MemoryUsage::new(100, 200, now),
                     children: vec![],
+                    namespace: None,
+                    rss_stats: None,
                 },
                 ProcessMemoryInfo {
                     pid: 3,
                     name: "child2".to_string(),
-                    memory: MemoryUsage {
-                        rss_bytes: 200,
-                        vsz_bytes: 400,
-                        timestamp: now,
-                    },
+                    memory: MemoryUsage::new(200, 400, now),
                     children: vec![ProcessMemoryInfo {
                         pid: 4,
                         name: "grandchild".to_string(),
-                        memory: MemoryUsage {
-                            rss_bytes: 50,
-                            vsz_bytes: 100,
-                            timestamp: now,
-                        },
+                        memory: MemoryUsage::new(50, 100, now),
                         children: vec![],
+                        namespace: None,
+                        rss_stats: None,
                     }],
+                    namespace: None,
+                    rss_stats: None,
                 },
             ],
+            namespace: None,
+            rss_stats: None,
         };
 
         assert_eq!(OutputFormatter::count_processes(&tree), 4);
     }
+
+    #[test]
+    fn test_progress_line() {
+        let usage = MemoryUsage::new(2048, 8192, Timestamp::from_unix_micros(1_000_000));
+        let line = progress_line(
+            std::time::Duration::from_millis(12_400),
+            &usage,
+            4096,
+            8192,
+            Some(MemoryUnit::Bytes),
+        );
+        assert_eq!(
+            line,
+            "[1970-01-01T00:00:01.000000+00:00 +12s] RSS 2048 B (peak 4096 B) | VSZ 8192 B (peak 8192 B)"
+        );
+    }
+
+    #[test]
+    fn test_progress_event() {
+        let usage = MemoryUsage::new(2048, 8192, Timestamp::from_unix_micros(1_000_000));
+        let event = progress_event(std::time::Duration::from_millis(12_400), &usage, 4096, 8192);
+        assert!(!event.contains('\n'));
+        let event: serde_json::Value = serde_json::from_str(&event).unwrap();
+        assert_eq!(
+            event,
+            serde_json::json!({
+                "event": "progress",
+                "timestamp": "1970-01-01T00:00:01.000000+00:00",
+                "elapsed_ms": 12_400,
+                "rss_bytes": 2048,
+                "peak_rss_bytes": 4096,
+                "vsz_bytes": 8192,
+                "peak_vsz_bytes": 8192,
+            })
+        );
+    }
+
+    #[test]
+    fn test_realtime_history_line() {
+        let mut display = RealtimeDisplay::new(Some(MemoryUnit::Bytes));
+        display.history.extend([100, 900, 500, 100, 300]);
+        assert_eq!(
+            display.history_line(80),
+            "RSS history: ▁█▅▁▃  100 B to 900 B"
+        );
+        // The oldest updates scroll out first
+        assert_eq!(display.history_line(34), "RSS history: █▁▅  100 B to 500 B");
+    }
+
+    #[test]
+    fn test_realtime_tree_lines() {
+        let now = Timestamp::now();
+        let process = |pid, name: &str, rss, children| ProcessMemoryInfo {
+            pid,
+            name: name.to_string(),
+            memory: MemoryUsage::new(rss, 0, now),
+            children,
+            namespace: None,
+            rss_stats: None,
+        };
+        let tree = process(
+            1,
+            "make",
+            1024,
+            vec![
+                process(2, "cc1", 1024, vec![]),
+                process(3, "ld", 4096, vec![process(4, "lto", 2048, vec![])]),
+            ],
+        );
+        let lines = RealtimeDisplay::tree_lines(&tree, Some(MemoryUnit::Bytes), 10);
+        assert_eq!(
+            lines,
+            vec![
+                "make (PID: 1) - RSS: 1024 B",
+                "├── ld (PID: 3) - RSS: 4096 B",
+                "│   └── lto (PID: 4) - RSS: 2048 B",
+                "└── cc1 (PID: 2) - RSS: 1024 B",
+            ]
+        );
+
+        let lines = RealtimeDisplay::tree_lines(&tree, Some(MemoryUnit::Bytes), 3);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2], "... and 2 more processes");
+    }
+
+    #[test]
+    fn test_fd_breakdown() {
+        let fds = FdSummary {
+            files: 3,
+            sockets: 8,
+            pipes: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            OutputFormatter::fd_breakdown(&fds),
+            "13 (3 files, 8 sockets, 2 pipes)"
+        );
+        assert_eq!(OutputFormatter::fd_breakdown(&FdSummary::default()), "0");
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("cargo build", 11), "cargo build");
+        assert_eq!(truncate("cargo build", 8), "cargo b…");
+    }
 }
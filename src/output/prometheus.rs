@@ -0,0 +1,130 @@
+//! Prometheus text exposition format, for `--prom-file` and
+//! `--serve-metrics`.
+//!
+//! `--prom-file` writes the final results of a run for the node_exporter
+//! textfile collector, so that memory of scheduled jobs or CI builds can be
+//! graphed and alerted on next to everything else a machine reports.
+
+use super::writeln_str;
+use crate::baseline::ComparisonResult;
+use crate::types::MonitorResult;
+
+/// One metric: name suffix after `peak_mem_`, type, help text and value.
+pub type Metric<'a> = (&'a str, &'a str, &'a str, f64);
+
+/// Formats `peak_mem_*` metrics, each with the same labels.
+///
+/// # Arguments
+/// * `labels` - Label names and values, e.g. `("command", "make")`
+/// * `metrics` - The metrics to write
+pub fn prometheus_exposition(labels: &[(&str, &str)], metrics: &[Metric]) -> String {
+    let labels = labels
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{name}=\"{value}\"")
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    let mut text = String::new();
+    for (name, kind, help, value) in metrics {
+        writeln_str!(text, "# HELP peak_mem_{name} {help}.");
+        writeln_str!(text, "# TYPE peak_mem_{name} {kind}");
+        writeln_str!(text, "peak_mem_{name}{{{labels}}} {value}");
+    }
+    text
+}
+
+/// Formats the final results of a run for the textfile collector.
+///
+/// # Arguments
+/// * `result` - The run's results
+/// * `baseline` - Name of the baseline compared against or saved, added as the
+///   `baseline` label
+/// * `comparison` - The comparison against that baseline, if one was made
+pub fn prometheus_textfile(
+    result: &MonitorResult,
+    baseline: Option<&str>,
+    comparison: Option<&ComparisonResult>,
+) -> String {
+    let mut labels = vec![("command", result.command.as_str())];
+    if let Some(baseline) = baseline {
+        labels.push(("baseline", baseline));
+    }
+
+    let mut metrics: Vec<Metric> = vec![
+        (
+            "peak_rss_bytes",
+            "gauge",
+            "Highest resident set size of the run",
+            result.peak_rss_bytes as f64,
+        ),
+        (
+            "peak_vsz_bytes",
+            "gauge",
+            "Peak virtual size of the run",
+            result.peak_vsz_bytes as f64,
+        ),
+        (
+            "duration_seconds",
+            "gauge",
+            "How long the run took",
+            result.duration_ms as f64 / 1000.0,
+        ),
+        (
+            "last_run_timestamp_seconds",
+            "gauge",
+            "When the run finished, in seconds since the Unix epoch",
+            result.timestamp.unix_micros() as f64 / 1_000_000.0,
+        ),
+    ];
+    if let Some(code) = result.exit_code {
+        metrics.push((
+            "exit_code",
+            "gauge",
+            "Exit code of the command",
+            f64::from(code),
+        ));
+    }
+    if let Some(comparison) = comparison {
+        metrics.push((
+            "baseline_peak_rss_bytes",
+            "gauge",
+            "Highest resident set size of the baseline",
+            comparison.baseline.peak_rss_bytes as f64,
+        ));
+        metrics.push((
+            "regression_detected",
+            "gauge",
+            "Whether the run regressed from the baseline (1) or not (0)",
+            if comparison.regression_detected {
+                1.0
+            } else {
+                0.0
+            },
+        ));
+    }
+    prometheus_exposition(&labels, &metrics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exposition() {
+        let text = prometheus_exposition(
+            &[("command", "sh -c \"echo hi\""), ("baseline", "main")],
+            &[("rss_bytes", "gauge", "Resident set size", 1024.0)],
+        );
+        assert_eq!(
+            text,
+            "# HELP peak_mem_rss_bytes Resident set size.\n\
+             # TYPE peak_mem_rss_bytes gauge\n\
+             peak_mem_rss_bytes{command=\"sh -c \\\"echo hi\\\"\",baseline=\"main\"} 1024\n"
+        );
+    }
+}
@@ -0,0 +1,260 @@
+//! Markdown regression reports for `--regression-report`.
+//!
+//! When a comparison finds a regression, CI can file the report as an
+//! issue as is: it names the command and baseline, and says what changed
+//! in a table, what the environment was, which processes used the most
+//! memory at the peak and where the timeline was saved.
+
+use super::{format_bytes, writeln_str};
+use crate::baseline::ComparisonResult;
+use crate::cli::MemoryUnit;
+use crate::git::GitInfo;
+use crate::query;
+use std::path::Path;
+
+/// How many processes to list under "Top consumers".
+const TOP_CONSUMERS: usize = 5;
+
+/// Writes the report for a regressed comparison.
+///
+/// # Arguments
+/// * `comparison` - The comparison that detected a regression
+/// * `baseline_name` - Name of the baseline compared against
+/// * `timeline` - Where the current run's timeline was saved, if it was
+/// * `units` - Optional fixed memory unit to use for display
+pub fn regression_report(
+    comparison: &ComparisonResult,
+    baseline_name: &str,
+    timeline: Option<&Path>,
+    units: Option<MemoryUnit>,
+) -> String {
+    let baseline = &comparison.baseline;
+    let current = &comparison.current;
+    let mut report = String::new();
+
+    writeln_str!(report, "# Memory regression in `{}`\n", current.command);
+    writeln_str!(
+        report,
+        "Compared against baseline `{baseline_name}`, recorded {} UTC. Regressed: {}.\n",
+        baseline.created_at.format_datetime(),
+        regressed_metrics(comparison).join(", ")
+    );
+    report.push_str(&comparison_table(comparison, units));
+
+    writeln_str!(report, "\n## Environment\n");
+    let metadata = |key: &str| {
+        baseline
+            .metadata
+            .get(key)
+            .map_or("unknown", String::as_str)
+            .to_string()
+    };
+    writeln_str!(
+        report,
+        "- Platform: {} {} (baseline: {} {})",
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        metadata("platform"),
+        metadata("arch")
+    );
+    let baseline_git = baseline.git();
+    if baseline_git.is_some() || current.git.is_some() {
+        let describe = |git: Option<&GitInfo>| {
+            git.map_or_else(|| "unknown".to_string(), |git| format!("`{git}`"))
+        };
+        writeln_str!(
+            report,
+            "- Commit: {} (baseline: {})",
+            describe(current.git.as_ref()),
+            describe(baseline_git.as_ref())
+        );
+    }
+    writeln_str!(
+        report,
+        "- peak-mem: {} (baseline: {})",
+        env!("CARGO_PKG_VERSION"),
+        baseline.version
+    );
+    if comparison.noisy_environment {
+        writeln_str!(
+            report,
+            "- High system memory pressure during one of the runs; results may be noisy"
+        );
+    }
+    if comparison.throttled {
+        writeln_str!(
+            report,
+            "- CPU throttled during one of the runs; duration and CPU time may not be comparable"
+        );
+    }
+    match &comparison.fingerprint_changes {
+        Some(changes) if comparison.fingerprint_key_changed => {
+            writeln_str!(
+                report,
+                "- Environment fingerprint not comparable (different fingerprint key)"
+            );
+            for change in changes {
+                writeln_str!(report, "  - {change}");
+            }
+        }
+        Some(changes) if changes.is_empty() => {
+            writeln_str!(report, "- Environment fingerprint unchanged");
+        }
+        Some(changes) => {
+            writeln_str!(report, "- Environment changes since the baseline:");
+            for change in changes {
+                writeln_str!(report, "  - {change}");
+            }
+        }
+        None => {}
+    }
+
+    if let Some(tree) = &current.process_tree {
+        writeln_str!(report, "\n## Top consumers at peak\n");
+        writeln_str!(report, "| Process | PID | RSS |");
+        writeln_str!(report, "|---|---|---|");
+        for process in query::top_processes(tree, TOP_CONSUMERS) {
+            writeln_str!(
+                report,
+                "| {} | {} | {} |",
+                process.name,
+                process.pid,
+                format_bytes(process.memory.rss_bytes, units)
+            );
+        }
+    }
+
+    if let Some(path) = timeline {
+        writeln_str!(report, "\n## Timeline\n");
+        writeln_str!(
+            report,
+            "The memory timeline of this run was saved to `{}`.",
+            path.display()
+        );
+    }
+
+    report
+}
+
+/// Tabulates the metrics of a comparison, baseline next to current.
+pub(super) fn comparison_table(comparison: &ComparisonResult, units: Option<MemoryUnit>) -> String {
+    let baseline = &comparison.baseline;
+    let current = &comparison.current;
+    let mut table = String::new();
+    writeln_str!(table, "| Metric | Baseline | Current | Change |");
+    writeln_str!(table, "|---|---|---|---|");
+    let mut row = |metric: &str, before: String, after: String, percent: f64| {
+        writeln_str!(table, "| {metric} | {before} | {after} | {percent:+.1}% |");
+    };
+    let bytes = |b: u64| format_bytes(b, units);
+    let seconds = |ms: u64| format!("{:.2}s", ms as f64 / 1000.0);
+    row(
+        "Peak RSS",
+        bytes(baseline.peak_rss_bytes),
+        bytes(current.peak_rss_bytes),
+        comparison.rss_diff_percent,
+    );
+    row(
+        "Peak VSZ",
+        bytes(baseline.peak_vsz_bytes),
+        bytes(current.peak_vsz_bytes),
+        comparison.vsz_diff_percent,
+    );
+    row(
+        "Duration",
+        seconds(baseline.duration_ms),
+        seconds(current.duration_ms),
+        comparison.duration_diff_percent,
+    );
+    row(
+        "CPU time",
+        seconds(baseline.cpu_time_ms()),
+        seconds(current.cpu_time_ms()),
+        comparison.cpu_time_diff_percent,
+    );
+    row(
+        "Major page faults",
+        baseline.major_faults.to_string(),
+        current.major_faults.to_string(),
+        comparison.major_faults_diff_percent,
+    );
+    row(
+        "Minor page faults",
+        baseline.minor_faults.to_string(),
+        current.minor_faults.to_string(),
+        comparison.minor_faults_diff_percent,
+    );
+    if let (Some(before), Some(after), Some(percent)) = (
+        baseline.peak_gpu_bytes,
+        current.peak_gpu_bytes,
+        comparison.gpu_diff_percent,
+    ) {
+        row("Peak GPU memory", bytes(before), bytes(after), percent);
+    }
+    table
+}
+
+/// Names the metrics that exceeded their regression thresholds.
+pub(super) fn regressed_metrics(comparison: &ComparisonResult) -> Vec<&'static str> {
+    [
+        (comparison.memory_regression_detected, "peak RSS"),
+        (comparison.fault_regression_detected, "major page faults"),
+        (comparison.cpu_regression_detected, "CPU time"),
+        (comparison.gpu_regression_detected, "peak GPU memory"),
+    ]
+    .into_iter()
+    .filter_map(|(regressed, metric)| regressed.then_some(metric))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::baseline::{Baseline, RegressionThresholds};
+    use crate::types::{MemoryUsage, MonitorResult, ProcessMemoryInfo, Timestamp};
+
+    #[test]
+    fn test_regression_report() {
+        let mut before = MonitorResult::fixture("./app", 100_000_000);
+        before.git = Some(GitInfo {
+            commit: "1a2b3c4d5e6f".to_string(),
+            branch: Some("main".to_string()),
+            dirty: false,
+        });
+        let baseline = Baseline::from(&before);
+        let mut current = MonitorResult::fixture("./app", 150_000_000);
+        let now = Timestamp::now();
+        current.process_tree = Some(ProcessMemoryInfo {
+            pid: 10,
+            name: "app".to_string(),
+            memory: MemoryUsage::new(50_000_000, 0, now),
+            children: vec![ProcessMemoryInfo {
+                pid: 11,
+                name: "worker".to_string(),
+                memory: MemoryUsage::new(100_000_000, 0, now),
+                children: vec![],
+                namespace: None,
+                rss_stats: None,
+            }],
+            namespace: None,
+            rss_stats: None,
+        });
+        let comparison = ComparisonResult::new(baseline, current, RegressionThresholds::rss(10.0));
+
+        let report = regression_report(&comparison, "main", Some(Path::new("mem.json")), None);
+        assert!(report.starts_with("# Memory regression in `./app`"));
+        assert!(report.contains("baseline `main`"));
+        assert!(report.contains("Regressed: peak RSS."));
+        assert!(report.contains("- Commit: unknown (baseline: `1a2b3c4d (main)`)"));
+        assert!(report.contains("| Peak RSS | 95.4 MiB | 143.1 MiB | +50.0% |"));
+        // Highest first
+        let worker = report.find("| worker | 11 |").unwrap();
+        let app = report.find("| app | 10 |").unwrap();
+        assert!(worker < app);
+        assert!(report.contains("saved to `mem.json`"));
+
+        let report = regression_report(&comparison, "main", None, Some(MemoryUnit::Megabytes));
+        assert!(report.contains("| Peak RSS | 100.0 MB | 150.0 MB | +50.0% |"));
+        assert!(report.contains("| worker | 11 | 100.0 MB |"));
+    }
+}
@@ -0,0 +1,119 @@
+//! Colors for the human output, for `--color`.
+//!
+//! Results that passed are green, a crossed threshold and regressions are
+//! red, and the lines about the run rather than its memory are dimmed.
+//! Colors are only used on a terminal unless asked for, and never when
+//! the `NO_COLOR` variable is set to anything (see no-color.org), short of
+//! `--color always`.
+
+use crate::types::{PeakMemError, Result};
+use std::ffi::OsStr;
+use std::fmt;
+use std::str::FromStr;
+
+/// When `--color` colors the output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// On a terminal, unless `NO_COLOR` is set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether to color output going to a terminal or not, `no_color`
+    /// being the value of `NO_COLOR`.
+    pub fn enabled(self, terminal: bool, no_color: Option<&OsStr>) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => terminal && no_color.is_none_or(OsStr::is_empty),
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = PeakMemError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(PeakMemError::InvalidArgument(format!(
+                "Invalid color choice '{s}'. Use one of: auto, always, never"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ColorChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorChoice::Auto => write!(f, "auto"),
+            ColorChoice::Always => write!(f, "always"),
+            ColorChoice::Never => write!(f, "never"),
+        }
+    }
+}
+
+/// Colors text with ANSI escapes, or leaves it as it is, as by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    enabled: bool,
+}
+
+impl Style {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+
+    /// For a result that passed.
+    pub fn ok(self, text: &str) -> String {
+        self.paint("32", text)
+    }
+
+    /// For a crossed threshold or a regression.
+    pub fn bad(self, text: &str) -> String {
+        self.paint("31", text)
+    }
+
+    /// For details of the run.
+    pub fn dim(self, text: &str) -> String {
+        self.paint("2", text)
+    }
+
+    fn paint(self, code: &str, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_choice() {
+        let auto = ColorChoice::Auto;
+        assert!(auto.enabled(true, None));
+        assert!(!auto.enabled(false, None));
+        assert!(!auto.enabled(true, Some(OsStr::new("1"))));
+        // Set but empty doesn't count
+        assert!(auto.enabled(true, Some(OsStr::new(""))));
+        assert!(ColorChoice::Always.enabled(false, Some(OsStr::new("1"))));
+        assert!(!ColorChoice::Never.enabled(true, None));
+        assert_eq!("never".parse::<ColorChoice>().unwrap(), ColorChoice::Never);
+        assert!("yes".parse::<ColorChoice>().is_err());
+    }
+
+    #[test]
+    fn test_style() {
+        assert_eq!(Style::new(true).bad("x"), "\x1b[31mx\x1b[0m");
+        assert_eq!(Style::default().ok("x"), "x");
+    }
+}
@@ -0,0 +1,119 @@
+//! Test Anything Protocol output, for `--tap`.
+//!
+//! Each check a run is held to becomes a test point: the command's exit
+//! status, the `--threshold` if one was set and the baseline comparison
+//! if one was made. `prove` and other TAP harnesses then report a memory
+//! budget next to the tests it guards.
+
+use super::report::regressed_metrics;
+use super::{format_bytes, writeln_str};
+use crate::baseline::ComparisonResult;
+use crate::cli::MemoryUnit;
+use crate::types::{ByteSize, MonitorResult};
+
+/// Formats a run's checks as a TAP version 13 stream.
+///
+/// # Arguments
+/// * `result` - The run's results
+/// * `started_command` - Whether a command was started, whose exit status is
+///   then checked
+/// * `threshold` - The `--threshold`, if one was set
+/// * `comparison` - The baseline's name and the comparison against it, if one
+///   was made
+/// * `units` - Optional fixed memory unit to use for display
+pub fn tap_report(
+    result: &MonitorResult,
+    started_command: bool,
+    threshold: Option<ByteSize>,
+    comparison: Option<(&str, &ComparisonResult)>,
+    units: Option<MemoryUnit>,
+) -> String {
+    let mut points: Vec<(bool, String)> = Vec::new();
+    if started_command {
+        points.push(match result.exit_code {
+            Some(code) => (
+                code == 0,
+                format!("{} exited with status {code}", result.command),
+            ),
+            None => (false, format!("{} was killed by a signal", result.command)),
+        });
+    }
+    if let Some(threshold) = threshold {
+        let peak = format_bytes(result.peak_rss_bytes, units);
+        let limit = format_bytes(threshold.as_u64(), units);
+        points.push(if result.threshold_exceeded {
+            (false, format!("peak RSS {peak} exceeds threshold {limit}"))
+        } else {
+            (true, format!("peak RSS {peak} is within threshold {limit}"))
+        });
+    }
+    if let Some((name, comparison)) = comparison {
+        let change = format!("peak RSS {:+.1}%", comparison.rss_diff_percent);
+        points.push(if comparison.regression_detected {
+            (
+                false,
+                format!(
+                    "regression against baseline {name} in {} ({change})",
+                    regressed_metrics(comparison).join(", ")
+                ),
+            )
+        } else {
+            (
+                true,
+                format!("no regression against baseline {name} ({change})"),
+            )
+        });
+    }
+
+    let mut tap = String::from("TAP version 13\n");
+    if points.is_empty() {
+        writeln_str!(
+            tap,
+            "1..0 # SKIP no threshold, baseline or command to check"
+        );
+    } else {
+        writeln_str!(tap, "1..{}", points.len());
+    }
+    for (number, (passed, description)) in points.iter().enumerate() {
+        let status = if *passed { "ok" } else { "not ok" };
+        // A # would start a directive
+        let description = description.replace('#', "\\#");
+        writeln_str!(tap, "{status} {} - {description}", number + 1);
+    }
+    writeln_str!(
+        tap,
+        "# Peak memory usage: {} (RSS) / {} (VSZ) in {:.3}s",
+        format_bytes(result.peak_rss_bytes, units),
+        format_bytes(result.peak_vsz_bytes, units),
+        result.duration().as_secs_f64()
+    );
+    tap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tap_report() {
+        let result = MonitorResult {
+            peak_vsz_bytes: 4096,
+            duration_ms: 1500,
+            threshold_exceeded: true,
+            ..MonitorResult::fixture("./app #1", 2048)
+        };
+
+        let tap = tap_report(&result, true, Some(ByteSize::b(1024)), None, None);
+        assert_eq!(
+            tap,
+            "TAP version 13\n\
+             1..2\n\
+             ok 1 - ./app \\#1 exited with status 0\n\
+             not ok 2 - peak RSS 2.0 KiB exceeds threshold 1.0 KiB\n\
+             # Peak memory usage: 2.0 KiB (RSS) / 4.0 KiB (VSZ) in 1.500s\n"
+        );
+
+        let tap = tap_report(&result, false, None, None, None);
+        assert!(tap.contains("1..0 # SKIP"));
+    }
+}
@@ -0,0 +1,179 @@
+//! User-defined output for `--template`.
+//!
+//! A template is text with `{{name}}` placeholders, each replaced by the
+//! value of that JSON field of the results, so a script can get exactly
+//! the line it expects. Nested fields and list items are reached with
+//! dots (`{{process_tree.name}}`, `{{runs.0.peak_rss_bytes}}`). Any field
+//! in bytes can also be asked for in another unit by swapping the
+//! `_bytes` suffix for `_kb`, `_mb`, `_gb`, `_kib`, `_mib` or `_gib`, and
+//! any in milliseconds in seconds with `_s` for `_ms`, e.g.
+//! `{{peak_rss_mib}} MiB in {{duration_s}}s`. Values the run doesn't
+//! have, like those of `--fields`, are empty.
+
+use crate::types::{MonitorResult, PeakMemError, Result};
+use serde_json::Value;
+use std::str::FromStr;
+
+/// Units a field in bytes can be converted to, by suffix.
+const BYTE_UNITS: [(&str, f64); 6] = [
+    ("_kb", 1_000.0),
+    ("_mb", 1_000_000.0),
+    ("_gb", 1_000_000_000.0),
+    ("_kib", 1_024.0),
+    ("_mib", 1_048_576.0),
+    ("_gib", 1_073_741_824.0),
+];
+
+/// A parsed `--template`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    /// The dotted path of a field.
+    Field(Vec<String>),
+}
+
+impl Template {
+    /// Reads the template given to `--template`: the argument itself if
+    /// it has a placeholder, or else the file it names.
+    ///
+    /// # Errors
+    /// * `PeakMemError::InvalidArgument` - The file couldn't be read, or the
+    ///   template has a malformed placeholder
+    pub fn load(arg: &str) -> Result<Self> {
+        if arg.contains("{{") {
+            return arg.parse();
+        }
+        std::fs::read_to_string(arg)
+            .map_err(|e| {
+                PeakMemError::InvalidArgument(format!(
+                    "'{arg}' has no {{{{field}}}} placeholder and can't be read as a template file: {e}"
+                ))
+            })?
+            .parse()
+    }
+
+    /// Fills in the placeholders from a run's results.
+    pub fn render(&self, result: &MonitorResult) -> String {
+        let json = serde_json::to_value(result).unwrap_or(Value::Null);
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Field(path) => Self::value(&json, path),
+            })
+            .collect()
+    }
+
+    fn value(json: &Value, path: &[String]) -> String {
+        if let Some(value) = Self::lookup(json, path) {
+            return match value {
+                Value::Null => String::new(),
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+        }
+
+        // A field converted to another unit
+        let (last, parents) = path.split_last().expect("paths aren't empty");
+        let convert = |stem: &str, suffix: &str, divisor: f64| {
+            let mut path = parents.to_vec();
+            path.push(format!("{stem}{suffix}"));
+            Self::lookup(json, &path)
+                .and_then(Value::as_f64)
+                .map(|value| format!("{:.1}", value / divisor))
+        };
+        let converted = match last.strip_suffix("_s") {
+            Some(stem) => convert(stem, "_ms", 1000.0),
+            None => BYTE_UNITS.iter().find_map(|(suffix, divisor)| {
+                convert(last.strip_suffix(suffix)?, "_bytes", *divisor)
+            }),
+        };
+        converted.unwrap_or_default()
+    }
+
+    fn lookup<'a>(json: &'a Value, path: &[String]) -> Option<&'a Value> {
+        path.iter().try_fold(json, |value, key| match value {
+            Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+            _ => value.get(key),
+        })
+    }
+}
+
+impl FromStr for Template {
+    type Err = PeakMemError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let inner = &rest[start + 2..];
+            let end = inner.find("}}").ok_or_else(|| {
+                PeakMemError::InvalidArgument(format!("Unclosed '{{{{' in template: {s}"))
+            })?;
+            let name = inner[..end].trim();
+            if name.is_empty() || name.split('.').any(str::is_empty) {
+                return Err(PeakMemError::InvalidArgument(format!(
+                    "Invalid field '{name}' in template"
+                )));
+            }
+            parts.push(Part::Field(name.split('.').map(str::to_string).collect()));
+            rest = &inner[end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::RunSummary;
+
+    fn result() -> MonitorResult {
+        MonitorResult {
+            duration_ms: 1500,
+            exit_code: None,
+            runs: vec![RunSummary {
+                peak_rss_bytes: 1000,
+                peak_vsz_bytes: 0,
+                duration_ms: 10,
+                exit_code: Some(0),
+            }],
+            ..MonitorResult::fixture("./app", 44040192)
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let template: Template = "{{ command }}: {{peak_rss_mib}} MiB in {{duration_s}}s"
+            .parse()
+            .unwrap();
+        assert_eq!(template.render(&result()), "./app: 42.0 MiB in 1.5s");
+
+        let template: Template = "{{runs.0.peak_rss_kb}}/{{exit_code}}/{{nothing}}"
+            .parse()
+            .unwrap();
+        assert_eq!(template.render(&result()), "1.0//");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("{{peak_rss_bytes".parse::<Template>().is_err());
+        assert!("{{}}".parse::<Template>().is_err());
+        assert!("{{runs..0}}".parse::<Template>().is_err());
+        assert_eq!(
+            "no fields".parse::<Template>().unwrap().render(&result()),
+            "no fields"
+        );
+    }
+}
@@ -0,0 +1,274 @@
+//! Full-screen watch mode for `--watch --tui`.
+//!
+//! Instead of the two lines of [`super::RealtimeDisplay`], the whole
+//! terminal shows the run: a graph each of RSS and VSZ over the most
+//! recent samples, one column per sample, with the peak at the top and
+//! marked where it falls in view, the elapsed time, and the processes of
+//! the tree using the most memory. The screen is drawn with crossterm on
+//! the alternate screen, which is left when monitoring ends so that the
+//! results print where the command's output was. Keys aren't read, as
+//! stdin belongs to the command.
+
+use super::chart::BLOCKS;
+use super::format_bytes;
+use crate::cli::MemoryUnit;
+use crate::query;
+use crate::types::{MemoryUsage, ProcessMemoryInfo, Result};
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Processes listed under the graphs at most.
+const TOP_PROCESSES: usize = 5;
+
+/// What the screen shows at one moment.
+pub struct WatchState<'a> {
+    /// The monitored command.
+    pub command: &'a str,
+    /// Time since monitoring started.
+    pub elapsed: Duration,
+    /// The most recent samples, oldest first.
+    pub recent: &'a [MemoryUsage],
+    /// Peak RSS so far (in bytes).
+    pub peak_rss: u64,
+    /// Peak VSZ so far (in bytes).
+    pub peak_vsz: u64,
+    /// The tree of the latest sample, if children are tracked.
+    pub tree: Option<&'a ProcessMemoryInfo>,
+}
+
+/// Lays out a screen of `width` by `height` characters.
+///
+/// # Returns
+/// * The lines of the screen, at most `height`
+pub fn watch_screen(
+    state: &WatchState,
+    width: usize,
+    height: usize,
+    units: Option<MemoryUnit>,
+) -> Vec<String> {
+    let top = state
+        .tree
+        .map(|tree| query::top_processes(tree, TOP_PROCESSES))
+        .unwrap_or_default();
+    // Title, the graphs' headers and marker rows, and the process list
+    let fixed = 7 + if top.is_empty() { 0 } else { top.len() + 2 };
+    let rows = (height.saturating_sub(fixed) / 2).max(1);
+
+    let elapsed = format!("{:.1}s", state.elapsed.as_secs_f64());
+    let title = format!("peak-mem: {}", state.command);
+    let mut lines = vec![fit(
+        &format!(
+            "{title}{elapsed:>gap$}",
+            gap = width
+                .saturating_sub(title.chars().count())
+                .max(elapsed.len() + 1)
+        ),
+        width,
+    )];
+
+    let current = state.recent.last();
+    for (name, value, peak) in [
+        ("RSS", rss as fn(&MemoryUsage) -> u64, state.peak_rss),
+        ("VSZ", vsz, state.peak_vsz),
+    ] {
+        let values: Vec<u64> = state.recent.iter().map(value).collect();
+        // A cgroup's peak may be above any sample, a reset one below
+        let peak = values.iter().copied().max().unwrap_or(0).max(peak);
+        lines.push(String::new());
+        lines.push(fit(
+            &format!(
+                "{name} {}   peak {}",
+                format_bytes(current.map_or(0, value), units),
+                format_bytes(peak, units)
+            ),
+            width,
+        ));
+        lines.extend(graph(&values, peak, rows, width, units));
+    }
+
+    if !top.is_empty() {
+        lines.push(String::new());
+        lines.push(fit(&format!("{:>8}  {:>10}  NAME", "PID", "RSS"), width));
+        for process in top {
+            lines.push(fit(
+                &format!(
+                    "{:>8}  {:>10}  {}",
+                    process.pid,
+                    format_bytes(process.memory.rss_bytes, units),
+                    process.name
+                ),
+                width,
+            ));
+        }
+    }
+    lines.truncate(height);
+    lines
+}
+
+/// Draws `values` right-aligned in `rows` rows, scaled to `peak`, with a
+/// row below marking the samples at the peak.
+fn graph(
+    values: &[u64],
+    peak: u64,
+    rows: usize,
+    width: usize,
+    units: Option<MemoryUnit>,
+) -> Vec<String> {
+    let top_label = format_bytes(peak, units);
+    let bottom_label = format_bytes(0, units);
+    let label_width = top_label.chars().count().max(bottom_label.chars().count());
+    let columns = width.saturating_sub(label_width + 2).max(1);
+    let shown = &values[values.len().saturating_sub(columns)..];
+    let pad = columns - shown.len();
+
+    // Any memory at all shows
+    let heights: Vec<usize> = shown
+        .iter()
+        .map(|&bytes| (bytes as f64 / peak.max(1) as f64 * (rows * 8) as f64).ceil() as usize)
+        .collect();
+    let mut lines = Vec::with_capacity(rows + 1);
+    for row in (0..rows).rev() {
+        let label = match row {
+            _ if row == rows - 1 => &top_label,
+            0 => &bottom_label,
+            _ => "",
+        };
+        let bars: String = heights
+            .iter()
+            .map(|&height| BLOCKS[height.saturating_sub(row * 8).min(8)])
+            .collect();
+        lines.push(format!("{label:>label_width$} ┤{}{bars}", " ".repeat(pad)));
+    }
+    let markers: String = shown
+        .iter()
+        .map(|&bytes| {
+            if bytes == peak && peak > 0 {
+                '▲'
+            } else {
+                ' '
+            }
+        })
+        .collect();
+    lines.push(
+        format!("{:label_width$}  {}{markers}", "", " ".repeat(pad))
+            .trim_end()
+            .to_string(),
+    );
+    lines
+}
+
+fn rss(usage: &MemoryUsage) -> u64 {
+    usage.rss_bytes
+}
+
+fn vsz(usage: &MemoryUsage) -> u64 {
+    usage.vsz_bytes
+}
+
+/// Cuts a line to the width of the screen.
+fn fit(line: &str, width: usize) -> String {
+    line.chars().take(width).collect()
+}
+
+/// The full-screen watch display, on the alternate screen while it lives.
+pub struct TuiDisplay {
+    units: Option<MemoryUnit>,
+}
+
+impl TuiDisplay {
+    /// Switches to the alternate screen.
+    pub fn enter(units: Option<MemoryUnit>) -> Result<Self> {
+        use crossterm::{cursor, terminal, ExecutableCommand};
+        let mut stdout = io::stdout();
+        stdout.execute(terminal::EnterAlternateScreen)?;
+        stdout.execute(cursor::Hide)?;
+        Ok(Self { units })
+    }
+
+    /// Redraws the screen.
+    pub fn draw(&mut self, state: &WatchState) -> Result<()> {
+        use crossterm::{cursor, terminal, QueueableCommand};
+        let (width, height) = terminal::size().unwrap_or((80, 24));
+        let lines = watch_screen(state, width as usize, height as usize, self.units);
+
+        let mut stdout = io::stdout();
+        stdout.queue(terminal::Clear(terminal::ClearType::All))?;
+        for (row, line) in lines.iter().enumerate() {
+            stdout.queue(cursor::MoveTo(0, row as u16))?;
+            write!(stdout, "{line}")?;
+        }
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for TuiDisplay {
+    fn drop(&mut self) {
+        use crossterm::{cursor, terminal, ExecutableCommand};
+        let mut stdout = io::stdout();
+        let _ = stdout.execute(cursor::Show);
+        let _ = stdout.execute(terminal::LeaveAlternateScreen);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Timestamp;
+
+    #[test]
+    fn test_watch_screen() {
+        let mib = |n: u64| n * 1024 * 1024;
+        let recent: Vec<MemoryUsage> = [1, 4, 8, 2]
+            .iter()
+            .map(|&n| MemoryUsage::new(mib(n), mib(n * 10), Timestamp::now()))
+            .collect();
+        let process = |pid, name: &str, rss, children| ProcessMemoryInfo {
+            pid,
+            name: name.to_string(),
+            memory: MemoryUsage::new(mib(rss), 0, Timestamp::now()),
+            children,
+            namespace: None,
+            rss_stats: None,
+        };
+        let tree = process(10, "make", 1, vec![process(11, "cc1", 3, vec![])]);
+        let state = WatchState {
+            command: "make",
+            elapsed: Duration::from_millis(2500),
+            recent: &recent,
+            peak_rss: mib(8),
+            peak_vsz: mib(80),
+            tree: Some(&tree),
+        };
+        let lines = watch_screen(&state, 40, 24, Some(MemoryUnit::Mebibytes));
+
+        assert!(lines[0].starts_with("peak-mem: make"));
+        assert!(lines[0].ends_with(" 2.5s"));
+        assert!(lines.contains(&"RSS 2.0 MiB   peak 8.0 MiB".to_string()));
+        assert!(lines.contains(&"VSZ 20.0 MiB   peak 80.0 MiB".to_string()));
+        // The samples end at the right edge, the peak marked under its column
+        let rss_top = lines
+            .iter()
+            .position(|line| line.starts_with("8.0 MiB ┤"))
+            .unwrap();
+        assert!(lines[rss_top].ends_with("█ "));
+        let markers = lines[rss_top..]
+            .iter()
+            .find(|line| line.contains('▲'))
+            .unwrap();
+        assert_eq!(markers.chars().count(), 39);
+        // Heaviest first
+        let cc1 = lines
+            .iter()
+            .position(|line| line.ends_with("  cc1"))
+            .unwrap();
+        let make = lines
+            .iter()
+            .position(|line| line.ends_with("  make"))
+            .unwrap();
+        assert!(lines[cc1].trim_start().starts_with("11 "));
+        assert!(cc1 < make);
+        assert!(lines.len() <= 24);
+        assert!(lines.iter().all(|line| line.chars().count() <= 40));
+    }
+}
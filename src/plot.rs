@@ -0,0 +1,211 @@
+//! Charting saved timelines, for `--plot`.
+//!
+//! A `--timeline` file, or a `--json` result with a timeline, is drawn as
+//! it was when the run ended: RSS over time in the terminal, or with
+//! `--chart` as an SVG image with the peak marked. Given a second file,
+//! its RSS is drawn on the same scale for comparison, and the change in
+//! peak RSS from the first to the second is reported.
+//!
+//! Timestamps may be in any `--timestamp-format`, as only the time
+//! between samples is used; normalized timelines have no sizes left to
+//! draw.
+
+use crate::cli::MemoryUnit;
+use crate::output::{format_bytes, terminal_timeline, writeln_str, ChartPoint};
+use crate::types::{PeakMemError, Result, Timestamp};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// A timeline read from a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedTimeline {
+    /// The file's name, to tell timelines apart.
+    pub label: String,
+    pub points: Vec<ChartPoint>,
+}
+
+impl SavedTimeline {
+    /// The highest RSS sampled.
+    pub fn peak_rss(&self) -> u64 {
+        self.points
+            .iter()
+            .map(|&(_, rss, _)| rss)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn end_ms(&self) -> u64 {
+        self.points.last().map_or(0, |&(ms, _, _)| ms)
+    }
+}
+
+/// Reads a timeline saved with `--timeline`, or the one in a `--json`
+/// result or comparison.
+///
+/// # Errors
+/// * `PeakMemError::Io` / `PeakMemError::Json` - File unreadable or not JSON
+/// * `PeakMemError::InvalidArgument` - No timeline in the file, or one without
+///   sizes or timestamps
+pub fn load_timeline(path: &Path) -> Result<SavedTimeline> {
+    let json: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let samples = match &json {
+        Value::Array(samples) => Some(samples),
+        _ => json
+            .get("samples")
+            .or_else(|| json.get("timeline"))
+            .or_else(|| json.get("current")?.get("timeline"))
+            .and_then(Value::as_array),
+    }
+    .ok_or_else(|| {
+        PeakMemError::InvalidArgument(format!(
+            "{} has no timeline (was it written with --timeline?)",
+            path.display()
+        ))
+    })?;
+
+    let mut start = None;
+    let points = samples
+        .iter()
+        .map(|sample| point(sample, &mut start))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| {
+            PeakMemError::InvalidArgument(format!(
+                "{} has samples without rss_bytes or a timestamp \
+                 (normalized timelines can't be plotted)",
+                path.display()
+            ))
+        })?;
+    Ok(SavedTimeline {
+        label: path.file_name().map_or_else(
+            || path.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        ),
+        points,
+    })
+}
+
+/// Reads a sample, timed from `start`, the first sample's time in
+/// seconds.
+fn point(sample: &Value, start: &mut Option<f64>) -> Option<ChartPoint> {
+    let rss = sample.get("rss_bytes")?.as_u64()?;
+    let vsz = sample.get("vsz_bytes").and_then(Value::as_u64).unwrap_or(0);
+    let seconds = match sample.get("timestamp")? {
+        timestamp @ Value::String(_) => {
+            let timestamp: Timestamp = serde_json::from_value(timestamp.clone()).ok()?;
+            timestamp.unix_micros() as f64 / 1_000_000.0
+        }
+        // Unix or relative seconds
+        timestamp => timestamp.as_f64()?,
+    };
+    let start = *start.get_or_insert(seconds);
+    Some((((seconds - start).max(0.0) * 1000.0) as u64, rss, vsz))
+}
+
+/// Draws a timeline in the terminal, above another for comparison if
+/// given, both on the same scale.
+///
+/// # Arguments
+/// * `timeline` - The timeline to draw
+/// * `other` - A timeline to compare with
+/// * `width` - Width of the terminal
+/// * `units` - Optional fixed memory unit to use for display
+pub fn plot_terminal(
+    timeline: &SavedTimeline,
+    other: Option<&SavedTimeline>,
+    width: usize,
+    units: Option<MemoryUnit>,
+) -> String {
+    let Some(other) = other else {
+        return terminal_timeline(&timeline.points, width, units, None);
+    };
+
+    let scale = Some((
+        timeline.end_ms().max(other.end_ms()),
+        timeline.peak_rss().max(other.peak_rss()),
+    ));
+    let mut plot = String::new();
+    for timeline in [timeline, other] {
+        writeln_str!(plot, "{}:", timeline.label);
+        plot.push_str(&terminal_timeline(&timeline.points, width, units, scale));
+        plot.push('\n');
+    }
+    let (before, after) = (timeline.peak_rss(), other.peak_rss());
+    let change = if before == 0 {
+        String::new()
+    } else {
+        format!(
+            " ({:+.1}%)",
+            (after as f64 - before as f64) / before as f64 * 100.0
+        )
+    };
+    writeln_str!(
+        plot,
+        "Peak RSS: {} → {}{change}",
+        format_bytes(before, units),
+        format_bytes(after, units)
+    );
+    plot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_timeline() {
+        let dir = tempfile::tempdir().unwrap();
+        let saved = dir.path().join("run.json");
+        fs::write(
+            &saved,
+            r#"[{"rss_bytes": 1024, "vsz_bytes": 4096, "timestamp": "2026-01-01T00:00:00Z"},
+                {"rss_bytes": 2048, "vsz_bytes": 4096, "timestamp": "2026-01-01T00:00:01.5Z"}]"#,
+        )
+        .unwrap();
+        let timeline = load_timeline(&saved).unwrap();
+        assert_eq!(timeline.label, "run.json");
+        assert_eq!(timeline.points, vec![(0, 1024, 4096), (1500, 2048, 4096)]);
+
+        // Relative timestamps, ahead of a retention summary
+        let capped = dir.path().join("capped.json");
+        fs::write(
+            &capped,
+            r#"{"samples": [{"rss_bytes": 1, "timestamp": 0.25},
+                            {"rss_bytes": 3, "timestamp": 0.75}],
+                "retention": {}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            load_timeline(&capped).unwrap().points,
+            vec![(0, 1, 0), (500, 3, 0)]
+        );
+
+        let normalized = dir.path().join("normalized.json");
+        fs::write(
+            &normalized,
+            r#"[{"time_percent": 0.0, "rss_percent": 100.0, "vsz_percent": 0.0}]"#,
+        )
+        .unwrap();
+        assert!(load_timeline(&normalized).is_err());
+        let result = dir.path().join("result.json");
+        fs::write(&result, r#"{"command": "true", "peak_rss_bytes": 1}"#).unwrap();
+        assert!(load_timeline(&result).is_err());
+    }
+
+    #[test]
+    fn test_plot_terminal() {
+        let timeline = |label: &str, points| SavedTimeline {
+            label: label.to_string(),
+            points,
+        };
+        let before = timeline("before.json", vec![(0, 1024, 0), (1000, 2048, 0)]);
+        let after = timeline("after.json", vec![(0, 1024, 0), (2000, 3072, 0)]);
+
+        let plot = plot_terminal(&before, Some(&after), 30, None);
+        assert!(plot.starts_with("before.json:\nRSS over time (peak 2.0 KiB at 1.00s):\n"));
+        assert!(plot.contains("after.json:\nRSS over time (peak 3.0 KiB at 2.00s):\n"));
+        // Both are drawn up to the higher peak
+        assert_eq!(plot.matches("3.0 KiB ┤").count(), 2);
+        assert!(plot.ends_with("Peak RSS: 2.0 KiB → 3.0 KiB (+50.0%)\n"));
+    }
+}
@@ -1,15 +1,30 @@
 //! Process spawning and management.
 //!
 //! This module handles spawning the target process and managing its lifecycle,
-//! including signal forwarding on Unix systems.
+//! including signal forwarding on Unix systems, as well as waiting for
+//! already running processes given with `--pid`.
+
+#[cfg(target_os = "linux")]
+mod sandbox;
+
+#[cfg(target_os = "linux")]
+pub use sandbox::Sandbox;
 
 use crate::types::{PeakMemError, Result};
+use std::ffi::OsString;
 use std::process::Stdio;
+use std::time::Duration;
 use tokio::process::Command;
 
 /// Handles spawning and running the target process.
 pub struct ProcessRunner {
-    command: Vec<String>,
+    /// Arguments are kept as the OS gave them, which needn't be UTF-8.
+    command: Vec<OsString>,
+    /// Variables set for the process on top of peak-mem's environment.
+    env: Vec<(OsString, OsString)>,
+    /// The sandbox to run the process in, for `--sandbox`.
+    #[cfg(target_os = "linux")]
+    sandbox: Option<std::sync::Arc<Sandbox>>,
 }
 
 impl ProcessRunner {
@@ -20,14 +35,32 @@ impl ProcessRunner {
     ///
     /// # Errors
     /// * Returns error if command is empty
-    pub fn new(command: Vec<String>) -> Result<Self> {
+    pub fn new(command: Vec<OsString>) -> Result<Self> {
         if command.is_empty() {
             return Err(PeakMemError::ProcessSpawn(
                 "No command provided".to_string(),
             ));
         }
 
-        Ok(Self { command })
+        Ok(Self {
+            command,
+            env: Vec::new(),
+            #[cfg(target_os = "linux")]
+            sandbox: None,
+        })
+    }
+
+    /// Starts the process with these variables set as well.
+    pub fn with_env(mut self, vars: Vec<(OsString, OsString)>) -> Self {
+        self.env.extend(vars);
+        self
+    }
+
+    /// Starts the process in this sandbox.
+    #[cfg(target_os = "linux")]
+    pub fn with_sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(std::sync::Arc::new(sandbox));
+        self
     }
 
     /// Spawns the configured process.
@@ -36,19 +69,68 @@ impl ProcessRunner {
     ///
     /// # Returns
     /// * `ProcessHandle` for managing the spawned process
+    ///
+    /// # Errors
+    /// * `PeakMemError::CommandNotFound` - There is no such command
+    /// * `PeakMemError::CommandNotExecutable` - The command isn't executable,
+    ///   or not in a format the OS can execute
+    /// * `PeakMemError::ProcessSpawn` - The process couldn't be started
+    ///   otherwise
     pub async fn spawn(&self) -> Result<ProcessHandle> {
         let program = &self.command[0];
         let args = &self.command[1..];
 
         let mut cmd = Command::new(program);
         cmd.args(args)
+            .envs(self.env.iter().map(|(name, value)| (name, value)))
             .stdin(Stdio::inherit())
             .stdout(Stdio::inherit())
             .stderr(Stdio::inherit());
 
-        let child = cmd
-            .spawn()
-            .map_err(|e| PeakMemError::ProcessSpawn(format!("Failed to spawn '{program}': {e}")))?;
+        #[cfg(target_os = "linux")]
+        let status = match &self.sandbox {
+            Some(sandbox) => {
+                let (read, write) = Sandbox::status_pipe()?;
+                let sandbox = std::sync::Arc::clone(sandbox);
+                let status = std::os::fd::AsRawFd::as_raw_fd(&write);
+                // SAFETY: entering the sandbox only makes system calls
+                unsafe {
+                    cmd.pre_exec(move || sandbox.enter(status));
+                }
+                Some((read, write))
+            }
+            None => None,
+        };
+
+        let spawned = cmd.spawn();
+        #[cfg(target_os = "linux")]
+        if let (Err(e), Some((read, write))) = (&spawned, status) {
+            // Only the child's end is left, so this doesn't wait for data
+            // that never comes
+            drop(write);
+            if let Some(step) = Sandbox::failed_step(&read) {
+                return Err(PeakMemError::ProcessSpawn(format!(
+                    "Failed to set up the sandbox ({step}): {e}"
+                )));
+            }
+        }
+
+        let child = spawned.map_err(|e| {
+            let program = program.to_string_lossy();
+            match e.kind() {
+                std::io::ErrorKind::NotFound => {
+                    PeakMemError::CommandNotFound(format!("'{program}': {e}"))
+                }
+                std::io::ErrorKind::PermissionDenied => {
+                    PeakMemError::CommandNotExecutable(format!("'{program}': {e}"))
+                }
+                #[cfg(unix)]
+                _ if e.raw_os_error() == Some(libc::ENOEXEC) => {
+                    PeakMemError::CommandNotExecutable(format!("'{program}': {e}"))
+                }
+                _ => PeakMemError::ProcessSpawn(format!("Failed to spawn '{program}': {e}")),
+            }
+        })?;
 
         let pid = child
             .id()
@@ -57,12 +139,27 @@ impl ProcessRunner {
         Ok(ProcessHandle { child, pid })
     }
 
-    /// Returns the command as a single string for display.
+    /// Returns the command as a single string for display, with any
+    /// invalid UTF-8 replaced.
     pub fn command_string(&self) -> String {
-        self.command.join(" ")
+        self.command
+            .iter()
+            .map(|arg| arg.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(" ")
     }
 }
 
+/// How a spawned process ended.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChildExit {
+    /// Exit code, or `None` if the process was killed by a signal.
+    pub code: Option<i32>,
+    /// Peak RSS of the process itself, without the children it didn't
+    /// wait for (zero where rusage isn't available).
+    pub max_rss_bytes: u64,
+}
+
 /// Handle to a spawned process.
 ///
 /// Provides methods for waiting on the process and forwarding signals.
@@ -79,12 +176,14 @@ impl ProcessHandle {
 
     /// Waits for the process to complete while forwarding signals on Unix.
     ///
-    /// Forwards SIGINT and SIGTERM to the child process.
+    /// Forwards SIGINT and SIGTERM to the child process. The child is
+    /// reaped with `wait4`, whose rusage is the child's own, unlike the
+    /// totals of all reaped children.
     ///
     /// # Returns
-    /// * Exit code of the process
+    /// * Exit code and peak RSS of the process
     #[cfg(unix)]
-    pub async fn wait_with_signal_forwarding(mut self) -> Result<Option<i32>> {
+    pub async fn wait_with_signal_forwarding(self) -> Result<ChildExit> {
         use nix::sys::signal::{self, Signal};
         use nix::unistd::Pid;
         use tokio::signal::unix::{signal, SignalKind};
@@ -95,29 +194,32 @@ impl ProcessHandle {
         let mut sigint_stream = signal(SignalKind::interrupt())?;
         let mut sigterm_stream = signal(SignalKind::terminate())?;
 
-        // Wait for either the child to exit or a signal
-        tokio::select! {
+        // Reaping blocks, so it runs on a thread of its own. Tokio's Child
+        // is never waited for.
+        let pid = self.pid;
+        let mut reaped = tokio::task::spawn_blocking(move || wait4(pid));
+        let exit = tokio::select! {
             // Child process exited
-            status = self.child.wait() => {
-                Ok(status?.code())
-            }
+            exit = &mut reaped => exit,
             // SIGINT received (Ctrl+C)
             _ = sigint_stream.recv() => {
-                // Forward SIGINT to child
+                // Forward SIGINT to child and wait for it to exit
                 let _ = signal::kill(child_pid, Signal::SIGINT);
-                // Wait for child to exit
-                let status = self.child.wait().await?;
-                Ok(status.code())
+                reaped.await
             }
             // SIGTERM received
             _ = sigterm_stream.recv() => {
-                // Forward SIGTERM to child
+                // Forward SIGTERM to child and wait for it to exit
                 let _ = signal::kill(child_pid, Signal::SIGTERM);
-                // Wait for child to exit
-                let status = self.child.wait().await?;
-                Ok(status.code())
+                reaped.await
             }
-        }
+        };
+        let exit = exit??;
+        // Once reaped, the Child must not be dropped: tokio would queue it
+        // to be reaped, and could reap whatever process gets the PID next.
+        // Its stdio is inherited, so nothing is leaked.
+        std::mem::forget(self.child);
+        Ok(exit)
     }
 
     /// Waits for the process to complete on Windows.
@@ -128,27 +230,159 @@ impl ProcessHandle {
     /// # Returns
     /// * Exit code of the process
     #[cfg(windows)]
-    pub async fn wait_with_signal_forwarding(mut self) -> Result<Option<i32>> {
+    pub async fn wait_with_signal_forwarding(mut self) -> Result<ChildExit> {
         // On Windows, Ctrl+C is automatically forwarded to child processes
         // in the same console, so we just wait normally
         let status = self.child.wait().await?;
-        Ok(status.code())
+        Ok(ChildExit {
+            code: status.code(),
+            max_rss_bytes: 0,
+        })
+    }
+}
+
+/// Reaps a child process, retrying when interrupted by a signal.
+#[cfg(unix)]
+fn wait4(pid: u32) -> std::io::Result<ChildExit> {
+    let mut status = 0;
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    while unsafe { libc::wait4(pid as libc::pid_t, &mut status, 0, &mut usage) } == -1 {
+        let e = std::io::Error::last_os_error();
+        if e.kind() != std::io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+
+    Ok(ChildExit {
+        code: libc::WIFEXITED(status).then(|| libc::WEXITSTATUS(status)),
+        max_rss_bytes: crate::monitor::max_rss_bytes(&usage),
+    })
+}
+
+/// Checks that a process to attach to exists and isn't peak-mem itself,
+/// which would wait for itself to exit.
+///
+/// # Errors
+/// * Returns error if there is no process with the given ID
+/// * `PeakMemError::InvalidArgument` - The process is peak-mem
+#[cfg(unix)]
+pub fn check_attachable(pid: u32) -> Result<()> {
+    if pid == std::process::id() {
+        Err(PeakMemError::InvalidArgument(format!(
+            "PID {pid} is peak-mem itself"
+        )))
+    } else if is_running(pid) {
+        Ok(())
+    } else {
+        Err(PeakMemError::Monitor(format!("No process with PID {pid}")))
     }
 }
 
+/// Waits for processes that peak-mem didn't start to exit.
+///
+/// They aren't our children and can't be waited on, so they are polled.
+/// SIGINT and SIGTERM end the wait without being forwarded: the processes
+/// belong to someone else.
+#[cfg(unix)]
+pub async fn wait_for_attached(pids: &[u32], poll: Duration) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigint_stream = signal(SignalKind::interrupt())?;
+    let mut sigterm_stream = signal(SignalKind::terminate())?;
+    let mut interval = tokio::time::interval(poll);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if !pids.iter().any(|&pid| is_running(pid)) {
+                    return Ok(());
+                }
+            }
+            _ = sigint_stream.recv() => return Ok(()),
+            _ = sigterm_stream.recv() => return Ok(()),
+        }
+    }
+}
+
+/// Whether a process exists. One owned by another user still counts.
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    use nix::errno::Errno;
+    use nix::sys::signal;
+    use nix::unistd::Pid;
+
+    signal::kill(Pid::from_raw(pid as i32), None) != Err(Errno::ESRCH)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[tokio::test]
     async fn test_process_runner() {
-        let runner = ProcessRunner::new(vec!["echo".to_string(), "test".to_string()]).unwrap();
+        let runner = ProcessRunner::new(vec!["echo".into(), "test".into()]).unwrap();
         let handle = runner.spawn().await.unwrap();
         let pid = handle.pid();
         assert!(pid > 0);
 
-        let exit_code = handle.wait_with_signal_forwarding().await.unwrap();
-        assert_eq!(exit_code, Some(0));
+        let exit = handle.wait_with_signal_forwarding().await.unwrap();
+        assert_eq!(exit.code, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_exit_of_the_child_itself() {
+        // A shell that grows and kills itself; the rusage is its own
+        let runner = ProcessRunner::new(vec![
+            "sh".into(),
+            "-c".into(),
+            "x=$(head -c 2000000 /dev/zero | tr '\\0' x); kill -9 $$".into(),
+        ])
+        .unwrap();
+        let exit = runner
+            .spawn()
+            .await
+            .unwrap()
+            .wait_with_signal_forwarding()
+            .await
+            .unwrap();
+        assert_eq!(exit.code, None);
+        assert!(exit.max_rss_bytes > 2_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_spawn_failures() {
+        for (program, code) in [("peak-mem-no-such-command", 127), ("/", 126)] {
+            let runner = ProcessRunner::new(vec![program.into()]).unwrap();
+            let error = runner.spawn().await.err().unwrap();
+            assert_eq!(error.shell_exit_code(), Some(code), "{program}");
+        }
+    }
+
+    #[test]
+    fn test_check_attachable() {
+        assert!(check_attachable(std::os::unix::process::parent_id()).is_ok());
+        assert!(matches!(
+            check_attachable(std::process::id()),
+            Err(PeakMemError::InvalidArgument(_))
+        ));
+        // Above the largest PID Linux, macOS or the BSDs hand out
+        assert!(check_attachable(i32::MAX as u32).is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_non_utf8_arguments() {
+        use std::os::unix::ffi::OsStringExt;
+
+        let arg = OsString::from_vec(b"caf\xe9".to_vec());
+        let runner = ProcessRunner::new(vec!["true".into(), arg]).unwrap();
+        assert_eq!(runner.command_string(), "true caf\u{fffd}");
+
+        let handle = runner.spawn().await.unwrap();
+        assert_eq!(
+            handle.wait_with_signal_forwarding().await.unwrap().code,
+            Some(0)
+        );
     }
 
     #[test]
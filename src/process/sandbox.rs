@@ -0,0 +1,380 @@
+//! Running the command in a sandbox, for `--sandbox` (Linux only).
+//!
+//! Between fork and exec, the child moves into new user, mount and network
+//! namespaces. Its network namespace has nothing but a loopback device
+//! that is down, so it can't reach the network. Its view of the
+//! filesystem is made read-only, except for the working directory and
+//! /tmp, which are bound over themselves writable, and /run is hidden
+//! under an empty tmpfs, as the sockets of the host's daemons (Docker's,
+//! D-Bus's) are there and a read-only socket can still be connected to;
+//! the host's mounts aren't touched. Last, a seccomp filter fails the system
+//! calls a build or a test has no business making, such as `ptrace`, `mount` or
+//! loading kernel modules, with `EPERM`.
+//!
+//! The child stays in peak-mem's PID namespace, so it is monitored like
+//! any other. Everything the child runs is prepared beforehand, as only
+//! async-signal-safe calls may be made after fork. If a step fails, the
+//! child writes which one on a pipe, and the spawn fails with it.
+
+use crate::types::{PeakMemError, Result};
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+
+/// System calls failed by the seccomp filter: tracing other processes,
+/// changing mounts and namespaces, the kernel, its modules, keyrings and
+/// the clock, and the interfaces most often used to attack it.
+const DENIED_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_open_tree,
+    libc::SYS_move_mount,
+    libc::SYS_fsopen,
+    libc::SYS_fsconfig,
+    libc::SYS_fsmount,
+    libc::SYS_fspick,
+    libc::SYS_mount_setattr,
+    libc::SYS_unshare,
+    libc::SYS_setns,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_reboot,
+    libc::SYS_kexec_load,
+    libc::SYS_kexec_file_load,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_acct,
+    libc::SYS_quotactl,
+    libc::SYS_syslog,
+    libc::SYS_settimeofday,
+    libc::SYS_clock_settime,
+    libc::SYS_clock_adjtime,
+    libc::SYS_adjtimex,
+    libc::SYS_keyctl,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_userfaultfd,
+    libc::SYS_open_by_handle_at,
+    libc::SYS_name_to_handle_at,
+];
+
+/// The `AUDIT_ARCH_*` value seccomp reports for native system calls.
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: Option<u32> = Some(0xC000_003E);
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: Option<u32> = Some(0xC000_00B7);
+#[cfg(target_arch = "riscv64")]
+const AUDIT_ARCH: Option<u32> = Some(0xC000_00F3);
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+)))]
+const AUDIT_ARCH: Option<u32> = None;
+
+/// The steps of entering the sandbox, by the byte the child reports a
+/// failure with.
+const STEPS: &[&str] = &[
+    "creating namespaces",
+    "mapping the user and group",
+    "making mounts private",
+    "making the filesystem read-only",
+    "hiding /run",
+    "changing to the working directory",
+    "installing the seccomp filter",
+];
+
+/// Everything the child needs to enter the sandbox, prepared before fork.
+pub struct Sandbox {
+    uid_map: CString,
+    gid_map: CString,
+    /// The working directory and /tmp.
+    writable: Vec<CString>,
+    /// /run and /var/run, unless they hold the working directory.
+    hidden: Vec<CString>,
+    working_dir: CString,
+    filter: Vec<libc::sock_filter>,
+}
+
+// The filter is plain data; the raw pointer to it is only made in the
+// child
+unsafe impl Send for Sandbox {}
+unsafe impl Sync for Sandbox {}
+
+impl Sandbox {
+    /// Prepares a sandbox for a command run from the working directory.
+    ///
+    /// # Errors
+    /// * `PeakMemError::InvalidArgument` - There is no seccomp filter for this
+    ///   architecture
+    /// * `PeakMemError::Io` - The working directory couldn't be read
+    pub fn new() -> Result<Self> {
+        let Some(arch) = AUDIT_ARCH else {
+            return Err(PeakMemError::InvalidArgument(format!(
+                "--sandbox has no seccomp filter for {}",
+                std::env::consts::ARCH
+            )));
+        };
+        let cstring = |bytes: &[u8]| CString::new(bytes).map_err(io::Error::from);
+        let working_dir = std::env::current_dir()?;
+        // Binding / writable over itself would undo the rest
+        let mut writable = Vec::new();
+        if working_dir.parent().is_some() {
+            writable.push(cstring(working_dir.as_os_str().as_bytes())?);
+        }
+        if working_dir != std::path::Path::new("/tmp") {
+            writable.push(cstring(b"/tmp")?);
+        }
+        // /var/run is most often a link to /run
+        let mut hidden = Vec::new();
+        for dir in ["/run", "/var/run"] {
+            let Ok(dir) = std::fs::canonicalize(dir) else {
+                continue;
+            };
+            let dir = cstring(dir.as_os_str().as_bytes())?;
+            if !working_dir.starts_with(std::ffi::OsStr::from_bytes(dir.as_bytes()))
+                && !hidden.contains(&dir)
+            {
+                hidden.push(dir);
+            }
+        }
+        // Only peak-mem's own IDs can be mapped without privileges
+        let uid = unsafe { libc::getuid() };
+        let gid = unsafe { libc::getgid() };
+        Ok(Self {
+            uid_map: cstring(format!("{uid} {uid} 1\n").as_bytes())?,
+            gid_map: cstring(format!("{gid} {gid} 1\n").as_bytes())?,
+            writable,
+            hidden,
+            working_dir: cstring(working_dir.as_os_str().as_bytes())?,
+            filter: seccomp_filter(arch),
+        })
+    }
+
+    /// Makes the pipe the child reports a failed step on. Both ends are
+    /// closed on exec.
+    pub fn status_pipe() -> Result<(OwnedFd, OwnedFd)> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+    }
+
+    /// Reads which step failed from the pipe, once the spawn has failed.
+    pub fn failed_step(status: &OwnedFd) -> Option<&'static str> {
+        let mut step = 0u8;
+        let read = unsafe { libc::read(status.as_raw_fd(), (&mut step as *mut u8).cast(), 1) };
+        (read == 1).then(|| STEPS.get(step as usize).copied())?
+    }
+
+    /// Enters the sandbox. Runs in the child between fork and exec, so it
+    /// only makes system calls, and reports a failure on `status`.
+    pub fn enter(&self, status: RawFd) -> io::Result<()> {
+        let fail = |step: u8| {
+            let error = io::Error::last_os_error();
+            unsafe { libc::write(status, (&step as *const u8).cast(), 1) };
+            Err(error)
+        };
+
+        let namespaces = libc::CLONE_NEWUSER | libc::CLONE_NEWNS | libc::CLONE_NEWNET;
+        if unsafe { libc::unshare(namespaces) } != 0 {
+            return fail(0);
+        }
+        let mapped = write_file(c"/proc/self/setgroups", c"deny")
+            && write_file(c"/proc/self/uid_map", &self.uid_map)
+            && write_file(c"/proc/self/gid_map", &self.gid_map);
+        if !mapped {
+            return fail(1);
+        }
+
+        // Nothing done here may reach the host's mounts
+        let private = unsafe {
+            libc::mount(
+                std::ptr::null(),
+                c"/".as_ptr(),
+                std::ptr::null(),
+                libc::MS_REC | libc::MS_PRIVATE,
+                std::ptr::null(),
+            )
+        };
+        if private != 0 {
+            return fail(2);
+        }
+        if !set_read_only(c"/", true) {
+            return fail(3);
+        }
+        // A directory that was read-only to begin with stays so
+        for path in &self.writable {
+            let bound = unsafe {
+                libc::mount(
+                    path.as_ptr(),
+                    path.as_ptr(),
+                    std::ptr::null(),
+                    libc::MS_BIND | libc::MS_REC,
+                    std::ptr::null(),
+                )
+            };
+            if bound == 0 {
+                set_read_only(path, false);
+            }
+        }
+        for path in &self.hidden {
+            let mounted = unsafe {
+                libc::mount(
+                    c"tmpfs".as_ptr(),
+                    path.as_ptr(),
+                    c"tmpfs".as_ptr(),
+                    libc::MS_NOSUID | libc::MS_NODEV,
+                    c"mode=755".as_ptr().cast(),
+                )
+            };
+            if mounted != 0 {
+                return fail(4);
+            }
+        }
+        // The old working directory is the read-only one underneath
+        if unsafe { libc::chdir(self.working_dir.as_ptr()) } != 0 {
+            return fail(5);
+        }
+
+        let program = libc::sock_fprog {
+            len: self.filter.len() as libc::c_ushort,
+            filter: self.filter.as_ptr().cast_mut(),
+        };
+        let filtered = unsafe {
+            libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) == 0
+                && libc::prctl(
+                    libc::PR_SET_SECCOMP,
+                    libc::SECCOMP_MODE_FILTER,
+                    &program as *const libc::sock_fprog,
+                ) == 0
+        };
+        if !filtered {
+            return fail(6);
+        }
+        Ok(())
+    }
+}
+
+/// Writes `contents` to the file at `path`, as the ID maps must be
+/// written: in a single write.
+fn write_file(path: &std::ffi::CStr, contents: &std::ffi::CStr) -> bool {
+    let fd = unsafe { libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) };
+    if fd < 0 {
+        return false;
+    }
+    let bytes = contents.to_bytes();
+    let written = unsafe { libc::write(fd, bytes.as_ptr().cast(), bytes.len()) };
+    unsafe { libc::close(fd) };
+    written == bytes.len() as isize
+}
+
+/// Makes the mount at `path` and every mount below it read-only, or
+/// writable again.
+fn set_read_only(path: &std::ffi::CStr, read_only: bool) -> bool {
+    let attr = libc::mount_attr {
+        attr_set: if read_only {
+            libc::MOUNT_ATTR_RDONLY
+        } else {
+            0
+        },
+        attr_clr: if read_only {
+            0
+        } else {
+            libc::MOUNT_ATTR_RDONLY
+        },
+        propagation: 0,
+        userns_fd: 0,
+    };
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_mount_setattr,
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            libc::AT_RECURSIVE,
+            &attr as *const libc::mount_attr,
+            std::mem::size_of::<libc::mount_attr>(),
+        )
+    };
+    result == 0
+}
+
+/// Builds the seccomp program: system calls of another architecture are
+/// refused, those in [`DENIED_SYSCALLS`] fail with `EPERM`, and the rest
+/// are allowed.
+fn seccomp_filter(arch: u32) -> Vec<libc::sock_filter> {
+    let instruction = |code: u32, k: u32, jt: u8, jf: u8| libc::sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    };
+    let load = |offset: u32| instruction(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, offset, 0, 0);
+    let ret = |action: u32| instruction(libc::BPF_RET | libc::BPF_K, action, 0, 0);
+    let jump_if = |test: u32, value: u32, jt: u8, jf: u8| {
+        instruction(libc::BPF_JMP | test | libc::BPF_K, value, jt, jf)
+    };
+    let errno = |errno: i32| libc::SECCOMP_RET_ERRNO | (errno as u32 & libc::SECCOMP_RET_DATA);
+
+    // Offsets into struct seccomp_data
+    let mut filter = vec![
+        load(4),
+        jump_if(libc::BPF_JEQ, arch, 1, 0),
+        ret(errno(libc::ENOSYS)),
+        load(0),
+    ];
+    // The x32 ABI shares the architecture, with its own numbers
+    if cfg!(target_arch = "x86_64") {
+        filter.push(jump_if(libc::BPF_JGE, 0x4000_0000, 0, 1));
+        filter.push(ret(errno(libc::ENOSYS)));
+    }
+    for &syscall in DENIED_SYSCALLS {
+        filter.push(jump_if(libc::BPF_JEQ, syscall as u32, 0, 1));
+        filter.push(ret(errno(libc::EPERM)));
+    }
+    filter.push(ret(libc::SECCOMP_RET_ALLOW));
+    filter
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seccomp_filter() {
+        let filter = seccomp_filter(0xC000_003E);
+        let ret_allow = (libc::BPF_RET | libc::BPF_K) as u16;
+        let last = filter.last().unwrap();
+        assert_eq!((last.code, last.k), (ret_allow, libc::SECCOMP_RET_ALLOW));
+        // Each denied call is a test and a return
+        let header = if cfg!(target_arch = "x86_64") { 6 } else { 4 };
+        assert_eq!(filter.len(), header + 2 * DENIED_SYSCALLS.len() + 1);
+        let ptrace = filter
+            .iter()
+            .position(|instruction| instruction.k == libc::SYS_ptrace as u32)
+            .unwrap();
+        assert_eq!(
+            filter[ptrace + 1].k,
+            libc::SECCOMP_RET_ERRNO | libc::EPERM as u32
+        );
+        // No jump may leave the program
+        let jumps = filter
+            .iter()
+            .enumerate()
+            .filter(|(_, instruction)| instruction.code & 0x07 == libc::BPF_JMP as u16);
+        for (i, instruction) in jumps {
+            let target = i + 1 + instruction.jt.max(instruction.jf) as usize;
+            assert!(target < filter.len());
+        }
+    }
+}
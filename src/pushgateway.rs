@@ -0,0 +1,117 @@
+//! Pushing final results to a Prometheus Pushgateway for `--pushgateway`.
+//!
+//! Short-lived jobs such as CI builds are gone before Prometheus could
+//! scrape them, so their results are pushed instead: the metrics of
+//! `--prom-file` replace the group named by the job and instance labels.
+
+use crate::http::{self, HttpUrl};
+use crate::types::Result;
+
+/// Content type of the text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// A metric group on a Pushgateway.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pushgateway {
+    url: HttpUrl,
+}
+
+impl Pushgateway {
+    /// Addresses the group with the given labels on the Pushgateway at
+    /// `base`, e.g. `http://localhost:9091`.
+    pub fn new(base: &HttpUrl, job: &str, instance: &str) -> Self {
+        let mut url = base.clone();
+        url.path = format!(
+            "{}/metrics/{}/{}",
+            url.path.trim_end_matches('/'),
+            grouping_key("job", job),
+            grouping_key("instance", instance)
+        );
+        Self { url }
+    }
+
+    /// Replaces the group's metrics with the given text exposition.
+    ///
+    /// # Errors
+    /// * Connection, I/O or timeout failures, and non-2xx responses
+    pub async fn push(&self, metrics: &str) -> Result<()> {
+        http::send("PUT", &self.url, CONTENT_TYPE, metrics.as_bytes()).await?;
+        Ok(())
+    }
+}
+
+/// Encodes a label as a path segment pair. Values that are empty or
+/// contain a `/` can't be a path segment and are sent base64-encoded, as
+/// the Pushgateway allows.
+fn grouping_key(name: &str, value: &str) -> String {
+    if value.is_empty() || value.contains('/') {
+        format!("{name}@base64/{}", base64_url(value.as_bytes()))
+    } else {
+        format!("{name}/{}", percent_encode(value))
+    }
+}
+
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+/// Base64 with the URL-safe alphabet (RFC 4648 section 5), padded.
+fn base64_url(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+    if bytes.is_empty() {
+        return "=".to_string();
+    }
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (u32::from(b) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_url() {
+        let base = HttpUrl::parse("http://localhost:9091/").unwrap();
+        let gateway = Pushgateway::new(&base, "peak-mem", "ci runner");
+        assert_eq!(
+            gateway.url.path,
+            "/metrics/job/peak-mem/instance/ci%20runner"
+        );
+
+        let gateway = Pushgateway::new(&base, "build/nightly", "");
+        assert_eq!(
+            gateway.url.path,
+            "/metrics/job@base64/YnVpbGQvbmlnaHRseQ==/instance@base64/="
+        );
+    }
+
+    #[test]
+    fn test_base64_url() {
+        assert_eq!(base64_url(b"f"), "Zg==");
+        assert_eq!(base64_url(b"fo"), "Zm8=");
+        assert_eq!(base64_url(b"foo"), "Zm9v");
+        assert_eq!(base64_url(b"\xfb\xff"), "-_8=");
+    }
+}
@@ -0,0 +1,292 @@
+//! Queries over a run's process tree and timeline, for custom reports.
+//!
+//! The formatters use these too, so a report built on them sees a run the
+//! way `--verbose`, `--tui` and the regression report do:
+//!
+//! * [`processes`] and [`top_processes`] - the tree's processes, as a list
+//! * [`group_by_name`] - the processes added up by name, e.g. every `cc` of a
+//!   build
+//! * [`peak_attribution`] - each process's share of the peak RSS
+//! * [`windows`] - the timeline summed up over fixed spans of time
+
+use crate::types::{
+    MemoryUsage, MonitorResult, PeakMemError, ProcessMemoryInfo, Result, Timestamp,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Processes of the same name added up.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NameGroup {
+    pub name: String,
+    /// How many processes have the name.
+    pub processes: usize,
+    /// Their RSS added up (in bytes).
+    pub rss_bytes: u64,
+    /// Their VSZ added up (in bytes).
+    pub vsz_bytes: u64,
+}
+
+/// A process's part of the peak.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PeakShare {
+    pub pid: u32,
+    pub name: String,
+    /// The process's own RSS at the peak (in bytes).
+    pub rss_bytes: u64,
+    /// That RSS as a percentage of the tree's.
+    pub percent: f64,
+}
+
+/// The samples of a timeline in one span of time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Window {
+    /// When the span starts.
+    pub start: Timestamp,
+    /// Samples taken in the span, at least one.
+    pub samples: usize,
+    /// Highest RSS sampled (in bytes).
+    pub peak_rss_bytes: u64,
+    /// Mean RSS of the samples (in bytes).
+    pub mean_rss_bytes: u64,
+    /// Highest VSZ sampled (in bytes).
+    pub peak_vsz_bytes: u64,
+}
+
+/// Returns the processes of a tree, parents before their children.
+pub fn processes(tree: &ProcessMemoryInfo) -> Vec<&ProcessMemoryInfo> {
+    fn collect<'a>(info: &'a ProcessMemoryInfo, all: &mut Vec<&'a ProcessMemoryInfo>) {
+        all.push(info);
+        for child in &info.children {
+            collect(child, all);
+        }
+    }
+
+    let mut all = Vec::new();
+    collect(tree, &mut all);
+    all
+}
+
+/// Returns the `count` processes of a tree with the highest RSS, highest
+/// first.
+pub fn top_processes(tree: &ProcessMemoryInfo, count: usize) -> Vec<&ProcessMemoryInfo> {
+    let mut all = processes(tree);
+    all.sort_by_key(|info| std::cmp::Reverse(info.memory.rss_bytes));
+    all.truncate(count);
+    all
+}
+
+/// Adds up the processes of a tree by name, highest RSS first.
+pub fn group_by_name(tree: &ProcessMemoryInfo) -> Vec<NameGroup> {
+    let mut groups: HashMap<&str, NameGroup> = HashMap::new();
+    for info in processes(tree) {
+        let group = groups.entry(&info.name).or_insert_with(|| NameGroup {
+            name: info.name.clone(),
+            processes: 0,
+            rss_bytes: 0,
+            vsz_bytes: 0,
+        });
+        group.processes += 1;
+        group.rss_bytes += info.memory.rss_bytes;
+        group.vsz_bytes += info.memory.vsz_bytes;
+    }
+    let mut groups: Vec<NameGroup> = groups.into_values().collect();
+    groups.sort_by(|a, b| {
+        b.rss_bytes
+            .cmp(&a.rss_bytes)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    groups
+}
+
+/// Returns how much of the peak each process of the tree held, highest
+/// first.
+///
+/// # Returns
+/// * One share per process, or none if the run kept no process tree, which
+///   takes `--verbose`
+pub fn peak_attribution(result: &MonitorResult) -> Vec<PeakShare> {
+    let Some(tree) = &result.process_tree else {
+        return Vec::new();
+    };
+    let all = top_processes(tree, usize::MAX);
+    let total: u64 = all.iter().map(|info| info.memory.rss_bytes).sum();
+    all.into_iter()
+        .map(|info| PeakShare {
+            pid: info.pid,
+            name: info.name.clone(),
+            rss_bytes: info.memory.rss_bytes,
+            percent: if total == 0 {
+                0.0
+            } else {
+                info.memory.rss_bytes as f64 / total as f64 * 100.0
+            },
+        })
+        .collect()
+}
+
+/// Sums up a timeline over consecutive spans of `width`, from its first
+/// sample on. Spans without a sample are left out.
+///
+/// # Errors
+/// * `PeakMemError::InvalidArgument` - `width` is zero
+pub fn windows(timeline: &[MemoryUsage], width: Duration) -> Result<Vec<Window>> {
+    if width.is_zero() {
+        return Err(PeakMemError::InvalidArgument(
+            "Window width must be greater than zero".to_string(),
+        ));
+    }
+    let Some(first) = timeline.first().map(|sample| sample.timestamp) else {
+        return Ok(Vec::new());
+    };
+
+    let mut windows: Vec<(u128, Window, u128)> = Vec::new();
+    for sample in timeline {
+        let index = sample.timestamp.duration_since(first).as_nanos() / width.as_nanos();
+        match windows.last_mut() {
+            Some((last, window, rss_sum)) if *last == index => {
+                window.samples += 1;
+                window.peak_rss_bytes = window.peak_rss_bytes.max(sample.rss_bytes);
+                window.peak_vsz_bytes = window.peak_vsz_bytes.max(sample.vsz_bytes);
+                *rss_sum += u128::from(sample.rss_bytes);
+            }
+            _ => {
+                let offset_micros = (index * width.as_nanos() / 1000) as u64;
+                windows.push((
+                    index,
+                    Window {
+                        start: Timestamp::from_unix_micros(first.unix_micros() + offset_micros),
+                        samples: 1,
+                        peak_rss_bytes: sample.rss_bytes,
+                        mean_rss_bytes: 0,
+                        peak_vsz_bytes: sample.vsz_bytes,
+                    },
+                    u128::from(sample.rss_bytes),
+                ));
+            }
+        }
+    }
+    Ok(windows
+        .into_iter()
+        .map(|(_, window, rss_sum)| Window {
+            mean_rss_bytes: (rss_sum / window.samples as u128) as u64,
+            ..window
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(
+        pid: u32,
+        name: &str,
+        rss: u64,
+        children: Vec<ProcessMemoryInfo>,
+    ) -> ProcessMemoryInfo {
+        ProcessMemoryInfo {
+            pid,
+            name: name.to_string(),
+            memory: MemoryUsage::new(rss, rss * 2, Timestamp::now()),
+            children,
+            namespace: None,
+            rss_stats: None,
+        }
+    }
+
+    fn build() -> ProcessMemoryInfo {
+        process(
+            1,
+            "make",
+            100,
+            vec![
+                process(2, "cc", 300, vec![process(4, "as", 50, vec![])]),
+                process(3, "cc", 200, vec![]),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_processes() {
+        let tree = build();
+        let pids: Vec<u32> = processes(&tree).iter().map(|info| info.pid).collect();
+        assert_eq!(pids, vec![1, 2, 4, 3]);
+        let top: Vec<u32> = top_processes(&tree, 2)
+            .iter()
+            .map(|info| info.pid)
+            .collect();
+        assert_eq!(top, vec![2, 3]);
+    }
+
+    #[test]
+    fn test_group_by_name() {
+        let groups = group_by_name(&build());
+        assert_eq!(
+            groups[0],
+            NameGroup {
+                name: "cc".to_string(),
+                processes: 2,
+                rss_bytes: 500,
+                vsz_bytes: 1000,
+            }
+        );
+        let names: Vec<&str> = groups.iter().map(|group| group.name.as_str()).collect();
+        assert_eq!(names, vec!["cc", "make", "as"]);
+    }
+
+    #[test]
+    fn test_peak_attribution() {
+        let mut result = MonitorResult::fixture("make", 650);
+        assert!(peak_attribution(&result).is_empty());
+
+        result.process_tree = Some(build());
+        let shares = peak_attribution(&result);
+        assert_eq!(shares.len(), 4);
+        assert_eq!((shares[0].pid, shares[0].rss_bytes), (2, 300));
+        assert!((shares.iter().map(|share| share.percent).sum::<f64>() - 100.0).abs() < 1e-9);
+        assert!((shares[3].percent - 50.0 / 650.0 * 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_windows() {
+        let start = Timestamp::from_unix_micros(1_767_225_600_000_000);
+        let sample = |ms: u64, rss: u64| {
+            MemoryUsage::new(
+                rss,
+                rss,
+                Timestamp::from_unix_micros(start.unix_micros() + ms * 1000),
+            )
+        };
+        let timeline = [
+            sample(0, 100),
+            sample(400, 300),
+            sample(1200, 50),
+            sample(3100, 80),
+        ];
+
+        let windows = windows(&timeline, Duration::from_secs(1)).unwrap();
+        let summary: Vec<(u64, usize, u64, u64)> = windows
+            .iter()
+            .map(|window| {
+                (
+                    window.start.duration_since(start).as_millis() as u64,
+                    window.samples,
+                    window.peak_rss_bytes,
+                    window.mean_rss_bytes,
+                )
+            })
+            .collect();
+        // Nothing was sampled in the third second
+        assert_eq!(
+            summary,
+            vec![(0, 2, 300, 200), (1000, 1, 50, 50), (3000, 1, 80, 80)]
+        );
+
+        assert!(super::windows(&[], Duration::from_secs(1))
+            .unwrap()
+            .is_empty());
+        assert!(super::windows(&timeline, Duration::ZERO).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+//! Failing a run whose numbers can't be trusted, for `--strict`.
+//!
+//! Without it, partial data only raises a warning: a child whose memory
+//! couldn't be read is left out of the totals, ticks the sampler fell
+//! behind on are skipped, and a run that can't be sampled at all falls
+//! back to rusage. In CI it is often better to fail than to gate on such
+//! numbers, so each problem gets its own exit code, just below the 126
+//! and 127 a shell uses for a command that couldn't be run.
+
+use std::fmt;
+
+/// Why a run's data is incomplete.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataProblem {
+    /// Sampling was impossible and rusage stood in for it.
+    Degraded(String),
+    /// Not a single sample was taken.
+    NoSamples,
+    /// Some samples left out a process that couldn't be read.
+    PermissionDenied { samples: u64 },
+    /// The sampler fell behind on more ticks than allowed.
+    SkippedTicks { skipped: u64, ticks: u64 },
+}
+
+impl DataProblem {
+    /// The exit code `--strict` fails with.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            DataProblem::Degraded(_) => 120,
+            DataProblem::NoSamples => 121,
+            DataProblem::PermissionDenied { .. } => 122,
+            DataProblem::SkippedTicks { .. } => 123,
+        }
+    }
+}
+
+impl fmt::Display for DataProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataProblem::Degraded(reason) => write!(f, "sampling was degraded: {reason}"),
+            DataProblem::NoSamples => write!(f, "no samples were taken"),
+            DataProblem::PermissionDenied { samples } => write!(
+                f,
+                "{samples} sample(s) left out a process that couldn't be read"
+            ),
+            DataProblem::SkippedTicks { skipped, ticks } => write!(
+                f,
+                "{skipped} of {ticks} sampling ticks were skipped ({:.1}%)",
+                skipped_percent(*skipped, *ticks)
+            ),
+        }
+    }
+}
+
+/// How completely a run was sampled.
+#[derive(Debug, Clone, Default)]
+pub struct SamplingQuality {
+    /// Samples taken.
+    pub samples: u64,
+    /// Ticks skipped because sampling fell behind.
+    pub skipped_ticks: u64,
+    /// Samples that left out a process that couldn't be read.
+    pub unreadable_samples: u64,
+    /// Why sampling fell back to rusage, if it did.
+    pub degraded: Option<String>,
+}
+
+impl SamplingQuality {
+    /// Finds the first problem with the data, most severe first.
+    ///
+    /// # Arguments
+    /// * `max_skipped_percent` - Share of the ticks that may be skipped
+    pub fn check(&self, max_skipped_percent: f64) -> Option<DataProblem> {
+        if let Some(reason) = &self.degraded {
+            return Some(DataProblem::Degraded(reason.clone()));
+        }
+        if self.samples == 0 {
+            return Some(DataProblem::NoSamples);
+        }
+        if self.unreadable_samples > 0 {
+            return Some(DataProblem::PermissionDenied {
+                samples: self.unreadable_samples,
+            });
+        }
+        let ticks = self.samples + self.skipped_ticks;
+        if skipped_percent(self.skipped_ticks, ticks) > max_skipped_percent {
+            return Some(DataProblem::SkippedTicks {
+                skipped: self.skipped_ticks,
+                ticks,
+            });
+        }
+        None
+    }
+}
+
+fn skipped_percent(skipped: u64, ticks: u64) -> f64 {
+    if ticks == 0 {
+        0.0
+    } else {
+        skipped as f64 * 100.0 / ticks as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let clean = SamplingQuality {
+            samples: 90,
+            skipped_ticks: 10,
+            ..Default::default()
+        };
+        assert_eq!(clean.check(10.0), None);
+        assert_eq!(
+            clean.check(5.0),
+            Some(DataProblem::SkippedTicks {
+                skipped: 10,
+                ticks: 100
+            })
+        );
+
+        let denied = SamplingQuality {
+            unreadable_samples: 3,
+            ..clean.clone()
+        };
+        assert_eq!(
+            denied.check(10.0),
+            Some(DataProblem::PermissionDenied { samples: 3 })
+        );
+        assert_eq!(
+            SamplingQuality::default().check(10.0),
+            Some(DataProblem::NoSamples)
+        );
+        // Degraded runs take no samples, but that isn't the problem
+        let degraded = SamplingQuality {
+            degraded: Some("ptrace is restricted".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(degraded.check(10.0).unwrap().exit_code(), 120);
+    }
+}
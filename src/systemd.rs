@@ -0,0 +1,86 @@
+//! Finding the processes of a systemd unit.
+//!
+//! `--unit` takes a unit name, asks systemd for the unit's main process and
+//! cgroup, and monitors that process's tree and the memory charged to the
+//! cgroup, which covers every process of the service.
+
+use crate::types::{PeakMemError, Result};
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+/// An active systemd unit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Unit {
+    /// The unit's name, e.g. `nginx.service`.
+    pub name: String,
+    /// PID of the unit's main process.
+    pub main_pid: u32,
+    /// The unit's cgroup, e.g. `/system.slice/nginx.service`.
+    pub control_group: String,
+}
+
+/// Looks up a running unit.
+///
+/// # Errors
+/// * `PeakMemError::InvalidArgument` - systemctl isn't available, the unit
+///   doesn't exist, or it has no running main process
+pub fn resolve(name: &str) -> Result<Unit> {
+    let output = Command::new("systemctl")
+        .args(["show", "--property=MainPID,ControlGroup", "--"])
+        .arg(name)
+        .output()
+        .map_err(|e| PeakMemError::InvalidArgument(format!("--unit needs systemctl: {e}")))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(PeakMemError::InvalidArgument(format!(
+            "Cannot look up unit '{name}': {}",
+            stderr.trim()
+        )));
+    }
+
+    let (main_pid, control_group) = parse_show(&String::from_utf8_lossy(&output.stdout));
+    match (main_pid, control_group) {
+        (Some(main_pid), Some(control_group)) if main_pid > 0 => Ok(Unit {
+            name: name.to_string(),
+            main_pid,
+            control_group,
+        }),
+        _ => Err(PeakMemError::InvalidArgument(format!(
+            "Unit '{name}' is not running"
+        ))),
+    }
+}
+
+/// Reads the main PID and cgroup from `systemctl show` output.
+fn parse_show(output: &str) -> (Option<u32>, Option<String>) {
+    let mut main_pid = None;
+    let mut control_group = None;
+    for line in output.lines() {
+        match line.split_once('=') {
+            Some(("MainPID", pid)) => main_pid = pid.trim().parse().ok(),
+            Some(("ControlGroup", path)) if !path.trim().is_empty() => {
+                control_group = Some(path.trim().to_string())
+            }
+            _ => {}
+        }
+    }
+    (main_pid, control_group)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_show() {
+        let running = "MainPID=812\nControlGroup=/system.slice/nginx.service\n";
+        assert_eq!(
+            parse_show(running),
+            (Some(812), Some("/system.slice/nginx.service".to_string()))
+        );
+
+        // A stopped unit has no main process or cgroup
+        assert_eq!(parse_show("MainPID=0\nControlGroup=\n"), (Some(0), None));
+    }
+}
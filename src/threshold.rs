@@ -0,0 +1,301 @@
+//! Reactions to the memory threshold being crossed during a run.
+//!
+//! Without any `--on-threshold` actions the threshold is only checked once
+//! the command has finished. Configuring actions starts a
+//! [`ThresholdWatcher`] that checks the peak as samples come in and runs
+//! the actions, in the order given, the first time the threshold is
+//! crossed.
+
+use crate::http::{self, HttpUrl};
+use crate::types::{ByteSize, PeakMemError, Result, ThresholdEvent, Timestamp};
+use nix::sys::signal::Signal;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+/// A single reaction to the threshold being crossed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThresholdAction {
+    /// Print a warning to stderr.
+    Warn,
+    /// Record when and at what RSS the threshold was crossed in the result.
+    Annotate,
+    /// Run a shell command with details in `PEAK_MEM_*` environment
+    /// variables.
+    Exec(String),
+    /// Send a signal to the monitored process.
+    Signal(Signal),
+    /// Kill the monitored process with SIGKILL.
+    Kill,
+    /// POST the event as JSON to an `http://` URL.
+    Webhook(HttpUrl),
+}
+
+impl ThresholdAction {
+    /// Whether this action targets the monitored process, and so only makes
+    /// sense while it is still running.
+    fn targets_child(&self) -> bool {
+        matches!(self, ThresholdAction::Signal(_) | ThresholdAction::Kill)
+    }
+}
+
+impl FromStr for ThresholdAction {
+    type Err = PeakMemError;
+
+    /// Parses `warn`, `annotate`, `kill`, `exec:COMMAND`, `signal:NAME` or
+    /// `webhook:URL`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, arg) = match s.split_once(':') {
+            Some((kind, arg)) => (kind, Some(arg)),
+            None => (s, None),
+        };
+
+        match (kind, arg) {
+            ("warn", None) => Ok(ThresholdAction::Warn),
+            ("annotate", None) => Ok(ThresholdAction::Annotate),
+            ("kill", None) => Ok(ThresholdAction::Kill),
+            ("exec", Some(cmd)) if !cmd.trim().is_empty() => {
+                Ok(ThresholdAction::Exec(cmd.to_string()))
+            }
+            ("signal", Some(name)) => parse_signal(name).map(ThresholdAction::Signal),
+            ("webhook", Some(url)) => HttpUrl::parse(url).map(ThresholdAction::Webhook),
+            _ => Err(PeakMemError::InvalidArgument(format!(
+                "Invalid threshold action '{s}'. Use one of: warn, annotate, kill, \
+                 exec:COMMAND, signal:NAME, webhook:URL"
+            ))),
+        }
+    }
+}
+
+/// Parses a signal name with or without the `SIG` prefix, e.g. `USR1` or
+/// `SIGTERM`.
+fn parse_signal(name: &str) -> Result<Signal> {
+    let upper = name.trim().to_uppercase();
+    let full = if upper.starts_with("SIG") {
+        upper
+    } else {
+        format!("SIG{upper}")
+    };
+
+    Signal::from_str(&full)
+        .map_err(|_| PeakMemError::InvalidArgument(format!("Unknown signal: '{name}'")))
+}
+
+/// Watches the peak RSS during a run and fires actions when it crosses
+/// the threshold.
+pub struct ThresholdWatcher {
+    threshold: ByteSize,
+    actions: Vec<ThresholdAction>,
+    pid: u32,
+    command: String,
+}
+
+impl ThresholdWatcher {
+    /// Creates a watcher for the given threshold and actions.
+    ///
+    /// # Arguments
+    /// * `threshold` - RSS above which the actions fire
+    /// * `actions` - Actions to run, in order
+    /// * `pid` - Process ID of the monitored process
+    /// * `command` - Command string, passed on to hooks
+    pub fn new(
+        threshold: ByteSize,
+        actions: Vec<ThresholdAction>,
+        pid: u32,
+        command: String,
+    ) -> Self {
+        Self {
+            threshold,
+            actions,
+            pid,
+            command,
+        }
+    }
+
+    /// Starts checking the tracker's peak RSS at the given interval.
+    ///
+    /// The task ends once the actions have fired or `stop` is set. When
+    /// stopped it checks one final time, since the sample that crossed the
+    /// threshold may have been the last one. Actions that target the
+    /// process are skipped once `child_exited` is set, as soon as it has
+    /// been reaped, since its PID may then belong to another process.
+    ///
+    /// # Returns
+    /// * `JoinHandle` resolving to the crossing event, if the threshold was
+    ///   crossed
+    pub fn spawn(
+        self,
+        peak_rss: Arc<AtomicU64>,
+        interval_ms: u64,
+        start: Instant,
+        stop: Arc<AtomicBool>,
+        child_exited: Arc<AtomicBool>,
+    ) -> JoinHandle<Option<ThresholdEvent>> {
+        tokio::spawn(async move {
+            let mut interval = time::interval(Duration::from_millis(interval_ms));
+            interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+
+            loop {
+                interval.tick().await;
+                let stopped = stop.load(Ordering::SeqCst);
+
+                let rss_bytes = peak_rss.load(Ordering::SeqCst);
+                if ByteSize::b(rss_bytes) > self.threshold {
+                    let event = ThresholdEvent {
+                        timestamp: Timestamp::now(),
+                        elapsed_ms: start.elapsed().as_millis() as u64,
+                        rss_bytes,
+                        threshold_bytes: self.threshold.as_u64(),
+                    };
+                    self.fire(&event, &child_exited).await;
+                    return Some(event);
+                }
+
+                if stopped {
+                    return None;
+                }
+            }
+        })
+    }
+
+    /// Runs every configured action for a crossing event.
+    async fn fire(&self, event: &ThresholdEvent, child_exited: &AtomicBool) {
+        for action in &self.actions {
+            // Checked before each, as an earlier action may take a while
+            if action.targets_child() && child_exited.load(Ordering::SeqCst) {
+                continue;
+            }
+            if let Err(e) = self.run_action(action, event).await {
+                eprintln!("Warning: Threshold action failed: {e}");
+            }
+        }
+    }
+
+    async fn run_action(&self, action: &ThresholdAction, event: &ThresholdEvent) -> Result<()> {
+        match action {
+            ThresholdAction::Warn => {
+                eprintln!(
+                    "Warning: Memory threshold {} exceeded after {:.1}s (RSS: {})",
+                    self.threshold,
+                    event.elapsed_ms as f64 / 1000.0,
+                    ByteSize::b(event.rss_bytes)
+                );
+                Ok(())
+            }
+            // The event itself is the annotation; it is attached to the
+            // result by the caller.
+            ThresholdAction::Annotate => Ok(()),
+            ThresholdAction::Exec(cmd) => {
+                let status = Command::new("sh")
+                    .arg("-c")
+                    .arg(cmd)
+                    .env("PEAK_MEM_PID", self.pid.to_string())
+                    .env("PEAK_MEM_COMMAND", &self.command)
+                    .env("PEAK_MEM_RSS_BYTES", event.rss_bytes.to_string())
+                    .env(
+                        "PEAK_MEM_THRESHOLD_BYTES",
+                        event.threshold_bytes.to_string(),
+                    )
+                    .env("PEAK_MEM_ELAPSED_MS", event.elapsed_ms.to_string())
+                    .status()
+                    .await?;
+                if !status.success() {
+                    return Err(PeakMemError::Runtime(format!(
+                        "hook '{cmd}' exited with {status}"
+                    )));
+                }
+                Ok(())
+            }
+            ThresholdAction::Signal(signal) => self.send_signal(*signal),
+            ThresholdAction::Kill => self.send_signal(Signal::SIGKILL),
+            ThresholdAction::Webhook(url) => {
+                let body = serde_json::json!({
+                    "event": "threshold_exceeded",
+                    "command": self.command,
+                    "pid": self.pid,
+                    "rss_bytes": event.rss_bytes,
+                    "threshold_bytes": event.threshold_bytes,
+                    "elapsed_ms": event.elapsed_ms,
+                    "timestamp": event.timestamp,
+                });
+                http::send("POST", url, "application/json", body.to_string().as_bytes()).await?;
+                Ok(())
+            }
+        }
+    }
+
+    fn send_signal(&self, signal: Signal) -> Result<()> {
+        nix::sys::signal::kill(nix::unistd::Pid::from_raw(self.pid as i32), signal).map_err(|e| {
+            PeakMemError::Runtime(format!("failed to send {signal} to {}: {e}", self.pid))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_threshold_action() {
+        assert_eq!(
+            "warn".parse::<ThresholdAction>().unwrap(),
+            ThresholdAction::Warn
+        );
+        assert_eq!(
+            "kill".parse::<ThresholdAction>().unwrap(),
+            ThresholdAction::Kill
+        );
+        assert_eq!(
+            "exec:echo hi".parse::<ThresholdAction>().unwrap(),
+            ThresholdAction::Exec("echo hi".to_string())
+        );
+        assert_eq!(
+            "signal:usr1".parse::<ThresholdAction>().unwrap(),
+            ThresholdAction::Signal(Signal::SIGUSR1)
+        );
+        assert_eq!(
+            "signal:SIGTERM".parse::<ThresholdAction>().unwrap(),
+            ThresholdAction::Signal(Signal::SIGTERM)
+        );
+        assert!(matches!(
+            "webhook:http://localhost:8080/hook".parse::<ThresholdAction>(),
+            Ok(ThresholdAction::Webhook(_))
+        ));
+
+        assert!("exec:".parse::<ThresholdAction>().is_err());
+        assert!("signal:NOPE".parse::<ThresholdAction>().is_err());
+        assert!("warn:loudly".parse::<ThresholdAction>().is_err());
+        assert!("reboot".parse::<ThresholdAction>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_no_kill_after_exit() {
+        let mut child = Command::new("sleep").arg("5").spawn().unwrap();
+        let watcher = ThresholdWatcher::new(
+            ByteSize::b(1),
+            vec![ThresholdAction::Kill],
+            child.id().unwrap(),
+            "sleep 5".to_string(),
+        );
+
+        // Flagged as exited, so the PID stands for a process we don't own
+        let event = watcher
+            .spawn(
+                Arc::new(AtomicU64::new(1024)),
+                10,
+                Instant::now(),
+                Arc::new(AtomicBool::new(false)),
+                Arc::new(AtomicBool::new(true)),
+            )
+            .await
+            .unwrap();
+        assert_eq!(event.map(|event| event.rss_bytes), Some(1024));
+        assert!(child.try_wait().unwrap().is_none());
+
+        child.kill().await.unwrap();
+    }
+}
@@ -0,0 +1,236 @@
+//! Comparing the peak process trees of two runs, for `--diff-trees`.
+//!
+//! A baseline comparison says that a tree grew, not where. The trees
+//! recorded at peak RSS by two `--json --verbose` results are aligned
+//! process by process instead: the roots with each other, and below them
+//! children of the same name in the order they were recorded, so the
+//! first `cc1` under `make` is paired with the first `cc1` under `make`
+//! in the other run. Each process is compared by its own RSS; processes
+//! only one of the runs had are added or removed.
+
+use crate::types::{PeakMemError, ProcessMemoryInfo, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+/// How a process of one tree relates to the other tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffStatus {
+    /// In both trees.
+    Changed,
+    /// Only in the second tree.
+    Added,
+    /// Only in the first tree.
+    Removed,
+}
+
+/// A process in either tree, with its RSS in both.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TreeDiff {
+    /// Process name.
+    pub name: String,
+    /// Whether the process is in both trees.
+    pub status: DiffStatus,
+    /// RSS of the process in the first tree (in bytes).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before_rss_bytes: Option<u64>,
+    /// RSS of the process in the second tree (in bytes).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after_rss_bytes: Option<u64>,
+    /// Change in the process's RSS, counting a missing side as zero.
+    pub rss_diff_bytes: i64,
+    /// Children, largest change first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TreeDiff>,
+}
+
+impl TreeDiff {
+    /// Aligns two trees, whose roots are paired whatever their names.
+    pub fn new(before: &ProcessMemoryInfo, after: &ProcessMemoryInfo) -> Self {
+        let mut unmatched: Vec<Option<&ProcessMemoryInfo>> =
+            after.children.iter().map(Some).collect();
+        let mut children: Vec<TreeDiff> = before
+            .children
+            .iter()
+            .map(|child| {
+                let pair = unmatched
+                    .iter_mut()
+                    .find(|other| other.is_some_and(|other| other.name == child.name))
+                    .and_then(Option::take);
+                match pair {
+                    Some(other) => TreeDiff::new(child, other),
+                    None => TreeDiff::one_sided(child, DiffStatus::Removed),
+                }
+            })
+            .collect();
+        children.extend(
+            unmatched
+                .into_iter()
+                .flatten()
+                .map(|child| TreeDiff::one_sided(child, DiffStatus::Added)),
+        );
+        children.sort_by_key(|child| std::cmp::Reverse(child.rss_diff_bytes.unsigned_abs()));
+
+        let (before_rss, after_rss) = (before.memory.rss_bytes, after.memory.rss_bytes);
+        Self {
+            name: after.name.clone(),
+            status: DiffStatus::Changed,
+            before_rss_bytes: Some(before_rss),
+            after_rss_bytes: Some(after_rss),
+            rss_diff_bytes: after_rss as i64 - before_rss as i64,
+            children,
+        }
+    }
+
+    /// A process, and everything below it, that only one tree has.
+    fn one_sided(info: &ProcessMemoryInfo, status: DiffStatus) -> Self {
+        let rss = info.memory.rss_bytes;
+        let mut children: Vec<TreeDiff> = info
+            .children
+            .iter()
+            .map(|child| TreeDiff::one_sided(child, status))
+            .collect();
+        children.sort_by_key(|child| std::cmp::Reverse(child.rss_diff_bytes.unsigned_abs()));
+        Self {
+            name: info.name.clone(),
+            status,
+            before_rss_bytes: (status == DiffStatus::Removed).then_some(rss),
+            after_rss_bytes: (status == DiffStatus::Added).then_some(rss),
+            rss_diff_bytes: if status == DiffStatus::Added {
+                rss as i64
+            } else {
+                -(rss as i64)
+            },
+            children,
+        }
+    }
+
+    /// Change in the RSS of the whole tree below and including this
+    /// process.
+    pub fn total_diff_bytes(&self) -> i64 {
+        self.rss_diff_bytes
+            + self
+                .children
+                .iter()
+                .map(TreeDiff::total_diff_bytes)
+                .sum::<i64>()
+    }
+
+    /// The process whose RSS grew the most, as the names on the way to it
+    /// from the root, with its growth. `None` if nothing grew.
+    pub fn largest_growth(&self) -> Option<(Vec<&str>, i64)> {
+        let mut largest: Option<(Vec<&str>, i64)> = None;
+        self.visit(&mut Vec::new(), &mut |path, node| {
+            if node.rss_diff_bytes > largest.as_ref().map_or(0, |(_, diff)| *diff) {
+                largest = Some((path.to_vec(), node.rss_diff_bytes));
+            }
+        });
+        largest
+    }
+
+    /// Names of the processes that were added or removed, in tree order.
+    pub fn names_with_status(&self, status: DiffStatus) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.visit(&mut Vec::new(), &mut |_, node| {
+            if node.status == status {
+                names.push(node.name.as_str());
+            }
+        });
+        names
+    }
+
+    fn visit<'a>(&'a self, path: &mut Vec<&'a str>, f: &mut dyn FnMut(&[&'a str], &'a TreeDiff)) {
+        path.push(&self.name);
+        f(path, self);
+        for child in &self.children {
+            child.visit(path, f);
+        }
+        path.pop();
+    }
+}
+
+/// Reads the peak process tree recorded in a `--json --verbose` result.
+///
+/// Comparison output is accepted too, in which case the tree of its
+/// current run is used.
+///
+/// # Errors
+/// * `PeakMemError::Io` / `PeakMemError::Json` - File unreadable or not JSON
+/// * `PeakMemError::InvalidArgument` - No process tree recorded
+pub fn load_tree(path: &Path) -> Result<ProcessMemoryInfo> {
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let tree = json
+        .get("process_tree")
+        .or_else(|| json.get("current")?.get("process_tree"))
+        .filter(|tree| !tree.is_null())
+        .ok_or_else(|| {
+            PeakMemError::InvalidArgument(format!(
+                "{} has no process_tree (was it written with --json --verbose?)",
+                path.display()
+            ))
+        })?;
+    Ok(serde_json::from_value(tree.clone())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{MemoryUsage, Timestamp};
+
+    fn process(name: &str, rss: u64, children: Vec<ProcessMemoryInfo>) -> ProcessMemoryInfo {
+        ProcessMemoryInfo {
+            pid: 1,
+            name: name.to_string(),
+            memory: MemoryUsage::new(rss, 0, Timestamp::now()),
+            children,
+            namespace: None,
+            rss_stats: None,
+        }
+    }
+
+    #[test]
+    fn test_tree_diff() {
+        let before = process(
+            "make",
+            10,
+            vec![
+                process("cc1", 100, vec![]),
+                process("cc1", 200, vec![]),
+                process("ld", 50, vec![]),
+            ],
+        );
+        let after = process(
+            "make",
+            10,
+            vec![
+                process("cc1", 100, vec![]),
+                process("cc1", 260, vec![]),
+                process("lld", 40, vec![]),
+            ],
+        );
+
+        let diff = TreeDiff::new(&before, &after);
+        assert_eq!(diff.rss_diff_bytes, 0);
+        assert_eq!(diff.total_diff_bytes(), 50);
+        // Largest change first
+        assert_eq!(diff.children[0].name, "cc1");
+        assert_eq!(diff.children[0].rss_diff_bytes, 60);
+        assert_eq!(diff.children[1].status, DiffStatus::Removed);
+        assert_eq!(diff.children[1].rss_diff_bytes, -50);
+        assert_eq!(diff.children[2].status, DiffStatus::Added);
+        assert_eq!(diff.children[3].rss_diff_bytes, 0);
+
+        assert_eq!(diff.largest_growth(), Some((vec!["make", "cc1"], 60)));
+        assert_eq!(diff.names_with_status(DiffStatus::Added), vec!["lld"]);
+        assert_eq!(diff.names_with_status(DiffStatus::Removed), vec!["ld"]);
+    }
+
+    #[test]
+    fn test_nothing_grew() {
+        let tree = process("app", 10, vec![process("worker", 5, vec![])]);
+        let diff = TreeDiff::new(&tree, &process("app", 8, vec![]));
+        assert_eq!(diff.largest_growth(), None);
+        assert_eq!(diff.total_diff_bytes(), -7);
+    }
+}
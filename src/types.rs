@@ -3,7 +3,24 @@
 //! This module defines the fundamental types used throughout the application
 //! for tracking memory usage, process information, and monitoring results.
 
+use crate::container::Container;
+use crate::fingerprint::EnvFingerprint;
+use crate::git::GitInfo;
+use crate::monitor::fds::FdSummary;
+use crate::monitor::jobs::JobPeak;
+use crate::monitor::pause::SamplingPause;
+use crate::monitor::perf::AllocationCounts;
+use crate::monitor::pool::PoolSummary;
+use crate::monitor::pressure::MemoryPressure;
+use crate::monitor::retention::RetentionSummary;
+use crate::monitor::snapshots::ProcessSnapshot;
+use crate::monitor::store::DeltaSummary;
+use crate::monitor::system::{SystemMemory, SystemPeaks};
+use crate::monitor::thermal::ThermalContext;
+use crate::strict::DataProblem;
+use crate::systemd::Unit;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt;
 use std::str::FromStr;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
@@ -112,6 +129,33 @@ impl Timestamp {
         Timestamp(SystemTime::now())
     }
 
+    /// Returns the time elapsed since an earlier timestamp, or zero if
+    /// `earlier` is actually later.
+    pub fn duration_since(self, earlier: Timestamp) -> Duration {
+        self.0.duration_since(earlier.0).unwrap_or_default()
+    }
+
+    /// Returns the timestamp that lies `duration` before this one, or the
+    /// Unix epoch if that is earlier.
+    pub fn saturating_sub(self, duration: Duration) -> Self {
+        Timestamp(
+            self.0
+                .checked_sub(duration)
+                .unwrap_or(UNIX_EPOCH)
+                .max(UNIX_EPOCH),
+        )
+    }
+
+    /// Returns the microseconds since the Unix epoch.
+    pub fn unix_micros(self) -> u64 {
+        self.duration_since(Timestamp(UNIX_EPOCH)).as_micros() as u64
+    }
+
+    /// Creates a timestamp from the microseconds since the Unix epoch.
+    pub fn from_unix_micros(micros: u64) -> Self {
+        Timestamp(UNIX_EPOCH + Duration::from_micros(micros))
+    }
+
     /// Convert to RFC3339 string format.
     pub fn to_rfc3339(self) -> String {
         let duration = self
@@ -167,7 +211,7 @@ impl Timestamp {
     ///
     /// Accepts an optional fractional-seconds part and either `Z` or a
     /// numeric UTC offset, which is normalized away.
-    fn parse_rfc3339(s: &str) -> Result<Self> {
+    pub fn parse_rfc3339(s: &str) -> Result<Self> {
         let invalid = || PeakMemError::Parse(format!("invalid RFC3339 timestamp: '{s}'"));
 
         let b = s.as_bytes();
@@ -275,6 +319,61 @@ fn civil_from_days(days: i64) -> (i64, u32, u32) {
     (if month <= 2 { year + 1 } else { year }, month, day)
 }
 
+/// How timestamps are written by `--timestamp-format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampFormat {
+    /// RFC3339 in UTC, e.g. `2024-06-01T12:34:56.500000+00:00`.
+    Rfc3339,
+    /// Seconds since the Unix epoch, with microseconds.
+    Unix,
+    /// Seconds since monitoring started, with milliseconds.
+    Relative,
+}
+
+impl FromStr for TimestampFormat {
+    type Err = PeakMemError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "rfc3339" => Ok(TimestampFormat::Rfc3339),
+            "unix" => Ok(TimestampFormat::Unix),
+            "relative" => Ok(TimestampFormat::Relative),
+            _ => Err(PeakMemError::InvalidArgument(format!(
+                "Invalid timestamp format '{s}'. Use one of: rfc3339, unix, relative"
+            ))),
+        }
+    }
+}
+
+impl TimestampFormat {
+    /// Writes a timestamp of a run that started at `start`.
+    pub fn render(self, timestamp: Timestamp, start: Timestamp) -> String {
+        match self {
+            TimestampFormat::Rfc3339 => timestamp.to_rfc3339(),
+            TimestampFormat::Unix => {
+                let micros = timestamp.unix_micros();
+                format!("{}.{:06}", micros / 1_000_000, micros % 1_000_000)
+            }
+            TimestampFormat::Relative => {
+                let millis = timestamp.duration_since(start).as_millis();
+                format!("{}.{:03}", millis / 1000, millis % 1000)
+            }
+        }
+    }
+
+    /// Like [`TimestampFormat::render`], but as a JSON value: a string
+    /// for RFC3339, otherwise a number.
+    pub fn to_json(self, timestamp: Timestamp, start: Timestamp) -> serde_json::Value {
+        let rendered = self.render(timestamp, start);
+        match self {
+            TimestampFormat::Rfc3339 => serde_json::Value::String(rendered),
+            TimestampFormat::Unix | TimestampFormat::Relative => {
+                serde_json::from_str(&rendered).unwrap_or(serde_json::Value::String(rendered))
+            }
+        }
+    }
+}
+
 /// Converts a (year, month, day) civil date to days since the Unix epoch.
 ///
 /// Inverse of [`civil_from_days`], from the same source.
@@ -319,6 +418,254 @@ pub struct MemoryUsage {
     pub vsz_bytes: u64,
     /// When this measurement was taken.
     pub timestamp: Timestamp,
+    /// Minor (soft) page faults incurred so far (cumulative).
+    #[serde(default)]
+    pub minor_faults: u64,
+    /// Major (hard) page faults incurred so far, i.e. faults that
+    /// required I/O (cumulative).
+    #[serde(default)]
+    pub major_faults: u64,
+    /// Number of threads alive when this measurement was taken.
+    #[serde(default)]
+    pub thread_count: u64,
+    /// Bytes read from storage so far (cumulative).
+    #[serde(default)]
+    pub disk_read_bytes: u64,
+    /// Bytes written to storage so far (cumulative).
+    #[serde(default)]
+    pub disk_write_bytes: u64,
+    /// GPU memory in use (in bytes), if GPU tracking is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gpu_bytes: Option<u64>,
+    /// Managed heap in use by the runtimes of the tree (in bytes), if
+    /// `--runtime-heap` was given and any of them reported it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heap_bytes: Option<u64>,
+    /// The machine's memory, on samples taken with `--system`, whose RSS
+    /// is the memory in use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<SystemMemory>,
+}
+
+impl MemoryUsage {
+    /// Creates a sample with the given memory sizes and all counters
+    /// zeroed.
+    pub fn new(rss_bytes: u64, vsz_bytes: u64, timestamp: Timestamp) -> Self {
+        Self {
+            rss_bytes,
+            vsz_bytes,
+            timestamp,
+            minor_faults: 0,
+            major_faults: 0,
+            thread_count: 0,
+            disk_read_bytes: 0,
+            disk_write_bytes: 0,
+            gpu_bytes: None,
+            heap_bytes: None,
+            system: None,
+        }
+    }
+
+    /// Adds another sample's sizes and counters to this one, keeping
+    /// this sample's timestamp. Used to aggregate a process tree.
+    pub fn accumulate(&mut self, other: &MemoryUsage) {
+        self.rss_bytes += other.rss_bytes;
+        self.vsz_bytes += other.vsz_bytes;
+        self.minor_faults += other.minor_faults;
+        self.major_faults += other.major_faults;
+        self.thread_count += other.thread_count;
+        self.disk_read_bytes += other.disk_read_bytes;
+        self.disk_write_bytes += other.disk_write_bytes;
+    }
+}
+
+/// A timeline sample scaled relative to the run, so runs of different
+/// size and length can be overlaid.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NormalizedSample {
+    /// Time since the first sample as a percentage of the timeline's span.
+    pub time_percent: f64,
+    /// RSS as a percentage of the timeline's peak RSS.
+    pub rss_percent: f64,
+    /// VSZ as a percentage of the timeline's peak VSZ.
+    pub vsz_percent: f64,
+}
+
+impl NormalizedSample {
+    /// Scales every sample in a timeline to percent-of-peak and
+    /// percent-of-duration.
+    ///
+    /// Percentages are 0.0 where the span or peak they are relative to is
+    /// zero, as for a single-sample timeline.
+    pub fn normalize(timeline: &[MemoryUsage]) -> Vec<NormalizedSample> {
+        let Some(first) = timeline.first() else {
+            return Vec::new();
+        };
+        let span = timeline
+            .last()
+            .map_or(Duration::ZERO, |last| {
+                last.timestamp.duration_since(first.timestamp)
+            })
+            .as_secs_f64();
+        let peak_rss = timeline.iter().map(|s| s.rss_bytes).max().unwrap_or(0);
+        let peak_vsz = timeline.iter().map(|s| s.vsz_bytes).max().unwrap_or(0);
+
+        let percent = |value: f64, of: f64| {
+            if of > 0.0 {
+                value / of * 100.0
+            } else {
+                0.0
+            }
+        };
+
+        timeline
+            .iter()
+            .map(|sample| NormalizedSample {
+                time_percent: percent(
+                    sample
+                        .timestamp
+                        .duration_since(first.timestamp)
+                        .as_secs_f64(),
+                    span,
+                ),
+                rss_percent: percent(sample.rss_bytes as f64, peak_rss as f64),
+                vsz_percent: percent(sample.vsz_bytes as f64, peak_vsz as f64),
+            })
+            .collect()
+    }
+}
+
+/// The spread of RSS across several runs at one point of their
+/// duration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvelopeSample {
+    /// Point in the runs as a percentage of each run's timeline span.
+    pub time_percent: f64,
+    /// Lowest RSS of any run at this point (in bytes).
+    pub rss_min_bytes: u64,
+    /// Median RSS of the runs at this point (in bytes).
+    pub rss_median_bytes: u64,
+    /// Highest RSS of any run at this point (in bytes).
+    pub rss_max_bytes: u64,
+}
+
+impl EnvelopeSample {
+    /// Number of evenly spaced points an envelope has, 0% to 100%.
+    pub const POINTS: usize = 101;
+
+    /// Builds the min/median/max envelope of several runs' timelines.
+    ///
+    /// Runs last different lengths of time, so each timeline is stretched
+    /// over 0-100% of its own span and its RSS interpolated linearly
+    /// between samples at [`EnvelopeSample::POINTS`] shared points. Empty
+    /// timelines are left out.
+    pub fn envelope(timelines: &[Vec<MemoryUsage>]) -> Vec<EnvelopeSample> {
+        let curves: Vec<Vec<(f64, f64)>> = timelines
+            .iter()
+            .filter(|timeline| !timeline.is_empty())
+            .map(|timeline| {
+                NormalizedSample::normalize(timeline)
+                    .iter()
+                    .zip(timeline)
+                    .map(|(normalized, sample)| (normalized.time_percent, sample.rss_bytes as f64))
+                    .collect()
+            })
+            .collect();
+        if curves.is_empty() {
+            return Vec::new();
+        }
+
+        (0..Self::POINTS)
+            .map(|point| {
+                let time_percent = point as f64 * 100.0 / (Self::POINTS - 1) as f64;
+                let mut values: Vec<u64> = curves
+                    .iter()
+                    .map(|curve| interpolate(curve, time_percent).round() as u64)
+                    .collect();
+                values.sort_unstable();
+
+                let middle = values.len() / 2;
+                let median = if values.len().is_multiple_of(2) {
+                    (values[middle - 1] + values[middle]) / 2
+                } else {
+                    values[middle]
+                };
+                EnvelopeSample {
+                    time_percent,
+                    rss_min_bytes: values[0],
+                    rss_median_bytes: median,
+                    rss_max_bytes: values[values.len() - 1],
+                }
+            })
+            .collect()
+    }
+}
+
+/// Returns the value of a curve of (time, value) points, sorted by time,
+/// at a given time, interpolating linearly between points and holding the
+/// first or last value outside them.
+fn interpolate(curve: &[(f64, f64)], time: f64) -> f64 {
+    let after = curve.partition_point(|&(t, _)| t < time);
+    if after == 0 {
+        return curve[0].1;
+    }
+    let Some(&(t1, v1)) = curve.get(after) else {
+        return curve[curve.len() - 1].1;
+    };
+    let (t0, v0) = curve[after - 1];
+    if t1 > t0 {
+        v0 + (v1 - v0) * (time - t0) / (t1 - t0)
+    } else {
+        v1
+    }
+}
+
+/// Summary of one of several runs of the command (with `--runs`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunSummary {
+    /// Peak RSS of the run (in bytes).
+    pub peak_rss_bytes: u64,
+    /// Peak VSZ of the run (in bytes).
+    pub peak_vsz_bytes: u64,
+    /// Duration of the run (in milliseconds).
+    pub duration_ms: u64,
+    /// Exit code of the command in that run.
+    pub exit_code: Option<i32>,
+}
+
+/// Records the moment the memory threshold was first crossed during a
+/// run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThresholdEvent {
+    /// When the crossing was detected.
+    pub timestamp: Timestamp,
+    /// Time since the command was started (in milliseconds).
+    pub elapsed_ms: u64,
+    /// Peak RSS at the time of detection (in bytes).
+    pub rss_bytes: u64,
+    /// The configured threshold (in bytes).
+    pub threshold_bytes: u64,
+}
+
+/// A change to the sampling interval made during a run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntervalAdjustment {
+    /// When the interval was changed.
+    pub timestamp: Timestamp,
+    /// The new sampling interval (in milliseconds).
+    pub interval_ms: u64,
+    /// System memory pressure (PSI `some avg10`, percent) that prompted the
+    /// change.
+    pub pressure_some_avg10: f64,
+}
+
+/// Diagnostics about how sampling was carried out during a run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SamplingDiagnostics {
+    /// The requested sampling interval (in milliseconds).
+    pub base_interval_ms: u64,
+    /// Interval changes made by pressure backoff, in order.
+    pub adjustments: Vec<IntervalAdjustment>,
 }
 
 /// Hierarchical representation of a process and its children's memory usage.
@@ -336,6 +683,55 @@ pub struct ProcessMemoryInfo {
     pub memory: MemoryUsage,
     /// List of child processes and their memory information.
     pub children: Vec<ProcessMemoryInfo>,
+    /// The PID namespace this process entered, if it isn't its parent's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<PidNamespace>,
+    /// This process's own RSS over every sample it was seen in, which
+    /// `memory` only shows at the moment of the tree's peak.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rss_stats: Option<RssStats>,
+}
+
+/// The RSS of one process over its lifetime, as far as it was sampled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RssStats {
+    /// Lowest RSS sampled (in bytes).
+    pub min_bytes: u64,
+    /// Mean RSS over the samples (in bytes).
+    pub avg_bytes: u64,
+    /// Highest RSS sampled (in bytes).
+    pub peak_bytes: u64,
+    /// Samples the process was seen in.
+    pub samples: u64,
+}
+
+/// A PID namespace boundary in a process tree, where a process such as a
+/// container's init runs in a PID namespace of its own (Linux).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PidNamespace {
+    /// Inode of the namespace, as in `/proc/<pid>/ns/pid`.
+    pub inode: u64,
+    /// PID of the process inside the namespace.
+    pub pid: u32,
+    /// Whether the process was found by sharing the cgroup of a process
+    /// at a boundary rather than as a descendant of one.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub via_cgroup: bool,
+}
+
+/// The peak of one of several processes monitored together.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetPeak {
+    /// Process ID of the monitored process.
+    pub pid: u32,
+    /// The command, if peak-mem started the process rather than attaching
+    /// to it with `--pid`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Peak RSS of this process (and its children) alone (in bytes).
+    pub peak_rss_bytes: u64,
+    /// Peak VSZ of this process (and its children) alone (in bytes).
+    pub peak_vsz_bytes: u64,
 }
 
 /// Complete results from monitoring a process's memory usage.
@@ -374,6 +770,138 @@ pub struct MonitorResult {
     /// Process ID of the main monitored process.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub main_pid: Option<u32>,
+    /// Total minor page faults incurred by the process tree.
+    #[serde(default)]
+    pub minor_faults: u64,
+    /// Total major page faults incurred by the process tree.
+    #[serde(default)]
+    pub major_faults: u64,
+    /// Highest number of threads alive across the process tree at once.
+    #[serde(default)]
+    pub peak_thread_count: u64,
+    /// CPU time the process tree spent in user mode (in milliseconds).
+    #[serde(default)]
+    pub user_time_ms: u64,
+    /// CPU time the process tree spent in kernel mode (in milliseconds).
+    #[serde(default)]
+    pub system_time_ms: u64,
+    /// Total CPU time as a percentage of wall-clock duration. Exceeds 100
+    /// when the tree kept more than one core busy.
+    #[serde(default)]
+    pub cpu_utilization_percent: f64,
+    /// Total bytes the process tree read from storage.
+    #[serde(default)]
+    pub disk_read_bytes: u64,
+    /// Total bytes the process tree wrote to storage.
+    #[serde(default)]
+    pub disk_write_bytes: u64,
+    /// When the threshold was crossed (if the `annotate` threshold action
+    /// was configured).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold_event: Option<ThresholdEvent>,
+    /// How sampling was adjusted during the run (if `--pressure-backoff`
+    /// was enabled).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampling_diagnostics: Option<SamplingDiagnostics>,
+    /// The environment the command ran in (if `--fingerprint` or
+    /// `--probe` was given).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<EnvFingerprint>,
+    /// Highest system memory pressure seen during the run (Linux PSI).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peak_memory_pressure: Option<MemoryPressure>,
+    /// The machine's memory when the least of it was available during the
+    /// run, to show how close the host came to running out whatever the
+    /// tree's own usage (Linux only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lowest_system_memory: Option<SystemMemory>,
+    /// Peak GPU memory used by the process tree (if `--gpu` was given).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peak_gpu_bytes: Option<u64>,
+    /// Peak managed heap in use by the runtimes of the tree (if
+    /// `--runtime-heap` was given and any of them reported it).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peak_heap_bytes: Option<u64>,
+    /// When sampling was paused with SIGUSR1, in order. The peaks and the
+    /// timeline leave these stretches out.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sampling_pauses: Vec<SamplingPause>,
+    /// Names of all processes seen in the tree during the run, sorted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub observed_processes: Vec<String>,
+    /// Why process memory couldn't be sampled, if it couldn't. Peak RSS
+    /// then comes from rusage instead and peak VSZ is zero.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub degraded_mode: Option<String>,
+    /// Page fault and `mmap`/`brk` counts from perf counters (if `--perf`
+    /// was given).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allocation_counts: Option<AllocationCounts>,
+    /// Per-process peaks when several processes were monitored together
+    /// (if `--pid` was given). The peaks above are then those of all of
+    /// them combined.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<TargetPeak>,
+    /// Open file descriptors of the tree at peak RSS, by type (if
+    /// `--fd-summary` was given, Linux only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fd_summary: Option<FdSummary>,
+    /// The container monitored (if `--container` was given). The peaks
+    /// above are then its cgroup's memory charge.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<Container>,
+    /// The systemd unit monitored (if `--unit` was given). The peaks
+    /// above are then its cgroup's memory charge.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<Unit>,
+    /// Every run, in order, if the command was run more than once with
+    /// `--runs`. The rest of the result is that of the run with the
+    /// highest peak RSS.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub runs: Vec<RunSummary>,
+    /// Extremes of the machine's memory, with `--system`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<SystemPeaks>,
+    /// Peak RSS of each crate compiled, highest first, with `--cargo`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub crates: Vec<JobPeak>,
+    /// Peak RSS of each C or C++ source file compiled, highest first, with
+    /// `--compile-units`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub translation_units: Vec<JobPeak>,
+    /// How the tree's memory was combined, with an `--aggregate` other
+    /// than `sum`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregate: Option<String>,
+    /// Footprint of the worker pool before and after it settled, with
+    /// `--pool-process`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool: Option<PoolSummary>,
+    /// How the timeline was capped, with `--timeline-max-samples`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeline_retention: Option<RetentionSummary>,
+    /// How the timeline was thinned to its changes, with
+    /// `--timeline-delta`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeline_delta: Option<DeltaSummary>,
+    /// CPU frequency, throttling and energy during the run, with
+    /// `--thermal`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thermal: Option<ThermalContext>,
+    /// The commit measured at, if run inside a git repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<GitInfo>,
+    /// Tags given with `--meta`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, String>,
+    /// The processes of the tree in each sample, kept for the process
+    /// table of `--report` and not written out.
+    #[serde(skip)]
+    pub process_snapshots: Option<Vec<ProcessSnapshot>>,
+    /// RSS over the run in block characters, for the human output, which
+    /// shows it whether or not the timeline is kept.
+    #[serde(skip)]
+    pub rss_sparkline: Option<String>,
 }
 
 impl MonitorResult {
@@ -391,6 +919,18 @@ impl MonitorResult {
     pub fn duration(&self) -> Duration {
         Duration::from_millis(self.duration_ms)
     }
+
+    /// Returns when monitoring started: the recorded start time, or else
+    /// the end time less the duration.
+    pub fn started_at(&self) -> Timestamp {
+        self.start_time
+            .unwrap_or_else(|| self.timestamp.saturating_sub(self.duration()))
+    }
+
+    /// Returns the total (user + system) CPU time in milliseconds.
+    pub fn cpu_time_ms(&self) -> u64 {
+        self.user_time_ms + self.system_time_ms
+    }
 }
 
 /// Error types that can occur during memory monitoring operations.
@@ -402,6 +942,12 @@ pub enum PeakMemError {
     /// Failed to spawn the target process.
     ProcessSpawn(String),
 
+    /// The command to run doesn't exist.
+    CommandNotFound(String),
+
+    /// The command to run exists but can't be executed.
+    CommandNotExecutable(String),
+
     /// Error occurred during memory monitoring.
     #[allow(dead_code)]
     Monitor(String),
@@ -428,12 +974,19 @@ pub enum PeakMemError {
 
     /// Runtime error.
     Runtime(String),
+
+    /// The run's data is incomplete and `--strict` was given.
+    DataQuality(DataProblem),
 }
 
 impl fmt::Display for PeakMemError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             PeakMemError::ProcessSpawn(msg) => write!(f, "Failed to spawn process: {}", msg),
+            PeakMemError::CommandNotFound(msg) => write!(f, "Command not found: {}", msg),
+            PeakMemError::CommandNotExecutable(msg) => {
+                write!(f, "Command not executable: {}", msg)
+            }
             PeakMemError::Monitor(msg) => write!(f, "Failed to monitor process: {}", msg),
             PeakMemError::UnsupportedPlatform(platform) => {
                 write!(f, "Platform not supported: {}", platform)
@@ -444,6 +997,29 @@ impl fmt::Display for PeakMemError {
             PeakMemError::InvalidArgument(msg) => write!(f, "Invalid argument: {}", msg),
             PeakMemError::Json(msg) => write!(f, "JSON error: {}", msg),
             PeakMemError::Runtime(msg) => write!(f, "Runtime error: {}", msg),
+            PeakMemError::DataQuality(problem) => write!(f, "Incomplete data: {problem}"),
+        }
+    }
+}
+
+impl PeakMemError {
+    /// The exit code a shell uses for the same failure, if there is one:
+    /// 127 for a command that doesn't exist and 126 for one that can't be
+    /// executed.
+    pub fn shell_exit_code(&self) -> Option<i32> {
+        match self {
+            PeakMemError::CommandNotFound(_) => Some(127),
+            PeakMemError::CommandNotExecutable(_) => Some(126),
+            _ => None,
+        }
+    }
+
+    /// The exit code to fail with, if there is a particular one: the
+    /// shell's, or that of a problem `--strict` found with the data.
+    pub fn exit_code(&self) -> Option<i32> {
+        match self {
+            PeakMemError::DataQuality(problem) => Some(problem.exit_code()),
+            _ => self.shell_exit_code(),
         }
     }
 }
@@ -484,6 +1060,25 @@ impl From<tokio::task::JoinError> for PeakMemError {
 /// Type alias for Results that may contain PeakMemError.
 pub type Result<T> = std::result::Result<T, PeakMemError>;
 
+#[cfg(test)]
+impl MonitorResult {
+    /// A result for tests: `command`, peaking at `peak_rss_bytes`, ran for
+    /// a second and exited with 0 at 2026-01-01T00:00:00Z, with nothing
+    /// else recorded. Tests set the fields they need on top.
+    pub fn fixture(command: &str, peak_rss_bytes: u64) -> Self {
+        // Deserialized, so that every other field has its default
+        let mut result: Self = serde_json::from_str(
+            r#"{"command": "", "peak_rss_bytes": 0, "peak_vsz_bytes": 0,
+                "duration_ms": 1000, "exit_code": 0, "threshold_exceeded": false,
+                "timestamp": "2026-01-01T00:00:00Z"}"#,
+        )
+        .unwrap();
+        result.command = command.to_string();
+        result.peak_rss_bytes = peak_rss_bytes;
+        result
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -549,6 +1144,28 @@ mod tests {
         assert_eq!(epoch.to_rfc3339(), "1970-01-01T00:00:00.000000+00:00");
     }
 
+    #[test]
+    fn test_timestamp_format() {
+        let start = Timestamp(UNIX_EPOCH + Duration::new(1_717_245_296, 500_000_000));
+        let ts = Timestamp(UNIX_EPOCH + Duration::new(1_717_245_298, 750_000));
+
+        assert_eq!(
+            TimestampFormat::Rfc3339.render(ts, start),
+            "2024-06-01T12:34:58.000750+00:00"
+        );
+        assert_eq!(TimestampFormat::Unix.render(ts, start), "1717245298.000750");
+        assert_eq!(TimestampFormat::Relative.render(ts, start), "1.500");
+        // Before the start clamps to zero
+        assert_eq!(TimestampFormat::Relative.render(start, ts), "0.000");
+
+        assert_eq!(
+            TimestampFormat::Relative.to_json(ts, start),
+            serde_json::json!(1.5)
+        );
+        assert!(TimestampFormat::Rfc3339.to_json(ts, start).is_string());
+        assert!("iso".parse::<TimestampFormat>().is_err());
+    }
+
     #[test]
     fn test_days_from_civil() {
         for days in [-1, 0, 11016, 11017, 19875, -141428] {
@@ -594,16 +1211,92 @@ mod tests {
 
     #[test]
     fn test_memory_usage_creation() {
-        let usage = MemoryUsage {
-            rss_bytes: 1024 * 1024,
-            vsz_bytes: 2048 * 1024,
-            timestamp: Timestamp::now(),
-        };
+        let usage = MemoryUsage::new(1024 * 1024, 2048 * 1024, Timestamp::now());
 
         assert_eq!(usage.rss_bytes, 1024 * 1024);
         assert_eq!(usage.vsz_bytes, 2048 * 1024);
     }
 
+    #[test]
+    fn test_normalize_timeline() {
+        let start = Timestamp::now();
+        let at = |ms: u64, rss: u64, vsz: u64| {
+            MemoryUsage::new(rss, vsz, Timestamp(start.0 + Duration::from_millis(ms)))
+        };
+        let timeline = vec![at(0, 50, 100), at(500, 200, 400), at(2000, 100, 400)];
+
+        let normalized = NormalizedSample::normalize(&timeline);
+        assert_eq!(normalized.len(), 3);
+        assert_eq!(normalized[0].time_percent, 0.0);
+        assert_eq!(normalized[0].rss_percent, 25.0);
+        assert_eq!(normalized[0].vsz_percent, 25.0);
+        assert_eq!(normalized[1].time_percent, 25.0);
+        assert_eq!(normalized[1].rss_percent, 100.0);
+        assert_eq!(normalized[2].time_percent, 100.0);
+        assert_eq!(normalized[2].rss_percent, 50.0);
+
+        let single = NormalizedSample::normalize(&[at(0, 10, 10)]);
+        assert_eq!(single[0].time_percent, 0.0);
+        assert_eq!(single[0].rss_percent, 100.0);
+        assert!(NormalizedSample::normalize(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_envelope_across_runs() {
+        let start = Timestamp::now();
+        let at = |ms: u64, rss: u64| {
+            MemoryUsage::new(rss, 0, Timestamp(start.0 + Duration::from_millis(ms)))
+        };
+        // Runs of different lengths line up by share of their duration
+        let timelines = vec![
+            vec![at(0, 100), at(1000, 300)],
+            vec![at(0, 200), at(4000, 200)],
+            vec![at(0, 0), at(100, 600)],
+            Vec::new(),
+        ];
+
+        let envelope = EnvelopeSample::envelope(&timelines);
+        assert_eq!(envelope.len(), EnvelopeSample::POINTS);
+        assert_eq!(envelope[0].time_percent, 0.0);
+        assert_eq!(
+            (
+                envelope[0].rss_min_bytes,
+                envelope[0].rss_median_bytes,
+                envelope[0].rss_max_bytes
+            ),
+            (0, 100, 200)
+        );
+        assert_eq!(envelope[50].time_percent, 50.0);
+        assert_eq!(
+            (
+                envelope[50].rss_min_bytes,
+                envelope[50].rss_median_bytes,
+                envelope[50].rss_max_bytes
+            ),
+            (200, 200, 300)
+        );
+        assert_eq!(envelope[100].rss_max_bytes, 600);
+
+        assert!(EnvelopeSample::envelope(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_memory_usage_accumulate() {
+        let mut total = MemoryUsage::new(100, 200, Timestamp::now());
+        total.disk_read_bytes = 4096;
+        let mut child = MemoryUsage::new(10, 20, Timestamp::now());
+        child.thread_count = 3;
+        child.disk_read_bytes = 512;
+        child.disk_write_bytes = 1024;
+
+        total.accumulate(&child);
+        assert_eq!(total.rss_bytes, 110);
+        assert_eq!(total.vsz_bytes, 220);
+        assert_eq!(total.thread_count, 3);
+        assert_eq!(total.disk_read_bytes, 4608);
+        assert_eq!(total.disk_write_bytes, 1024);
+    }
+
     #[test]
     fn test_monitor_result_conversions() {
         let result = MonitorResult {
@@ -619,6 +1312,42 @@ mod tests {
             start_time: None,
             sample_count: None,
             main_pid: None,
+            minor_faults: 0,
+            major_faults: 0,
+            peak_thread_count: 0,
+            user_time_ms: 0,
+            system_time_ms: 0,
+            cpu_utilization_percent: 0.0,
+            threshold_event: None,
+            sampling_diagnostics: None,
+            disk_read_bytes: 0,
+            disk_write_bytes: 0,
+            fingerprint: None,
+            peak_memory_pressure: None,
+            lowest_system_memory: None,
+            peak_gpu_bytes: None,
+            peak_heap_bytes: None,
+            sampling_pauses: Vec::new(),
+            observed_processes: Vec::new(),
+            degraded_mode: None,
+            allocation_counts: None,
+            targets: Vec::new(),
+            fd_summary: None,
+            container: None,
+            unit: None,
+            runs: Vec::new(),
+            system: None,
+            crates: Vec::new(),
+            translation_units: Vec::new(),
+            aggregate: None,
+            pool: None,
+            timeline_retention: None,
+            timeline_delta: None,
+            git: None,
+            metadata: HashMap::new(),
+            thermal: None,
+            process_snapshots: None,
+            rss_sparkline: None,
         };
 
         assert_eq!(result.peak_rss().to_string(), "100.0 MiB");
@@ -8,8 +8,35 @@
 use assert_cmd::Command;
 use predicates::prelude::*;
 
+/// Variables that make peak-mem think it runs in CI, which the tests
+/// themselves may.
+const CI_VARS: [&str; 9] = [
+    "GITHUB_ACTIONS",
+    "GITLAB_CI",
+    "BUILDKITE",
+    "CIRCLECI",
+    "TF_BUILD",
+    "TRAVIS",
+    "JENKINS_URL",
+    "TEAMCITY_VERSION",
+    "CI",
+];
+
 fn peak_mem() -> Command {
-    Command::cargo_bin("peak-mem").unwrap()
+    let mut command = Command::cargo_bin("peak-mem").unwrap();
+    for var in CI_VARS {
+        command.env_remove(var);
+    }
+    command
+}
+
+/// The file a baseline of this name is saved to on this platform.
+fn baseline_file(dir: &std::path::Path, name: &str) -> std::path::PathBuf {
+    dir.join(format!(
+        "{name}.{}-{}.json",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    ))
 }
 
 #[test]
@@ -36,6 +63,100 @@ fn json_output_is_valid_and_complete() {
     assert!(json["peak_rss_bytes"].as_u64().unwrap() > 0);
     assert!(json["peak_vsz_bytes"].as_u64().unwrap() > 0);
     assert!(json["duration_ms"].as_u64().unwrap() >= 300);
+    assert!(json["disk_read_bytes"].is_u64());
+    assert!(json["disk_write_bytes"].is_u64());
+}
+
+#[test]
+fn output_writes_the_results_to_a_file_and_leaves_stdout_to_the_command() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("results.json");
+
+    let assert = peak_mem()
+        .args(["--json", "-o"])
+        .arg(&path)
+        .args(["--", "echo", "hello"])
+        .assert()
+        .success();
+    assert_eq!(
+        String::from_utf8(assert.get_output().stdout.clone()).unwrap(),
+        "hello\n"
+    );
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(json["command"], "echo hello");
+
+    let path = dir.path().join("results.csv");
+    peak_mem()
+        .args(["--csv", "--output"])
+        .arg(&path)
+        .args(["--", "true"])
+        .assert()
+        .success()
+        .stdout("");
+    let csv = std::fs::read_to_string(&path).unwrap();
+    assert!(csv.starts_with("command,peak_rss_bytes,"));
+    assert_eq!(csv.lines().count(), 2);
+}
+
+#[test]
+fn output_format_follows_the_extension_unless_a_flag_says_otherwise() {
+    let dir = tempfile::tempdir().unwrap();
+    let run = |file: &str, flags: &[&str]| {
+        let path = dir.path().join(file);
+        peak_mem()
+            .args(flags)
+            .arg("--output")
+            .arg(&path)
+            .args(["--", "sleep", "0.1"])
+            .assert()
+            .success()
+            .stdout("");
+        std::fs::read_to_string(&path).unwrap()
+    };
+
+    let json: serde_json::Value = serde_json::from_str(&run("results.json", &[])).unwrap();
+    assert_eq!(json["command"], "sleep 0.1");
+    assert!(run("results.CSV", &[]).starts_with("command,peak_rss_bytes,"));
+    let markdown = run("results.md", &[]);
+    assert!(markdown.starts_with("### Memory usage of `sleep 0.1`"));
+    assert!(markdown.contains("| Peak RSS |"));
+    let html = run("results.html", &[]);
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("sleep 0.1"));
+    assert!(run("results.txt", &[]).starts_with("Command: sleep 0.1"));
+
+    // A format flag wins over the extension
+    assert!(run("results.json", &["--csv"]).starts_with("command,"));
+    assert!(run("results.html", &["--quiet"])
+        .trim()
+        .parse::<u64>()
+        .is_ok());
+}
+
+#[test]
+fn append_log_adds_a_line_per_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("runs.jsonl");
+
+    for command in ["true", "sleep 0.1"] {
+        peak_mem()
+            .arg("--append-log")
+            .arg(&log)
+            .arg("--")
+            .args(command.split(' '))
+            .assert()
+            .success();
+    }
+    let log = std::fs::read_to_string(&log).unwrap();
+    let runs: Vec<serde_json::Value> = log
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(runs.len(), 2);
+    assert_eq!(runs[0]["command"], "true");
+    assert_eq!(runs[1]["command"], "sleep 0.1");
+    assert!(runs[1]["peak_rss_bytes"].as_u64().unwrap() > 0);
 }
 
 #[test]
@@ -53,75 +174,2644 @@ fn csv_output_has_header_and_row() {
 }
 
 #[test]
-fn quiet_outputs_only_rss_bytes() {
+fn normalized_timeline_is_relative_to_peak_and_duration() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("timeline.json");
+
+    peak_mem()
+        .args(["--quiet", "--interval", "20", "--timeline"])
+        .arg(&path)
+        .args(["--timeline-normalized", "--", "sleep", "0.2"])
+        .assert()
+        .success();
+
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let samples = json.as_array().unwrap();
+    assert!(samples.len() > 1);
+    assert_eq!(samples[0]["time_percent"], 0.0);
+    assert_eq!(samples.last().unwrap()["time_percent"], 100.0);
+    assert!(samples.iter().any(|s| s["rss_percent"] == 100.0));
+}
+
+#[test]
+fn capped_timeline_records_its_retention() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("timeline.json");
+
+    peak_mem()
+        .args(["--quiet", "--interval", "10", "--timeline"])
+        .arg(&path)
+        .args([
+            "--timeline-max-samples",
+            "4",
+            "--timeline-retention",
+            "ring",
+        ])
+        .args(["--", "sleep", "0.3"])
+        .assert()
+        .success();
+
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let retention = &json["retention"];
+    assert_eq!(retention["policy"], "ring");
+    assert_eq!(retention["kept_samples"], 4);
+    assert!(retention["original_samples"].as_u64().unwrap() > 4);
+    assert_eq!(json["samples"].as_array().unwrap().len(), 4);
+}
+
+#[test]
+fn ring_file_sample_store_keeps_recent_samples() {
+    let dir = tempfile::tempdir().unwrap();
+    let ring = dir.path().join("samples.ring");
+    let timeline = dir.path().join("timeline.json");
+
+    peak_mem()
+        .args(["--quiet", "--interval", "10", "--sample-store", "ring-file"])
+        .arg("--sample-store-file")
+        .arg(&ring)
+        .args(["--timeline-max-samples", "4", "--timeline"])
+        .arg(&timeline)
+        .args(["--", "sleep", "0.3"])
+        .assert()
+        .success();
+
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&timeline).unwrap()).unwrap();
+    assert_eq!(json["retention"]["policy"], "ring");
+    assert_eq!(json["samples"].as_array().unwrap().len(), 4);
+    assert!(std::fs::read(&ring).unwrap().starts_with(b"PKMEMRNG"));
+
+    // The ring needs a file and a size
+    peak_mem()
+        .args(["--sample-store", "ring-file", "--", "true"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--sample-store-file"));
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_sample_store_keeps_every_sample() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("samples.db");
+    let timeline = dir.path().join("timeline.json");
+
+    peak_mem()
+        .args(["--quiet", "--interval", "10", "--sample-store", "sqlite"])
+        .arg("--sample-store-file")
+        .arg(&db)
+        .arg("--timeline")
+        .arg(&timeline)
+        .args(["--", "sleep", "0.3"])
+        .assert()
+        .success();
+
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&timeline).unwrap()).unwrap();
+    let samples = json.as_array().unwrap();
+    assert!(samples.len() > 4);
+    assert!(std::fs::read(&db).unwrap().starts_with(b"SQLite format 3"));
+}
+
+#[test]
+fn diff_trees_reports_per_process_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let result = |cc1: u64, linker: &str| {
+        let memory = |rss| {
+            format!(
+                r#"{{"rss_bytes": {rss}, "vsz_bytes": 0, "timestamp": "2026-01-01T00:00:00Z"}}"#
+            )
+        };
+        format!(
+            r#"{{"process_tree": {{"pid": 1, "name": "make", "memory": {}, "children": [
+                {{"pid": 2, "name": "cc1", "memory": {}, "children": []}},
+                {{"pid": 3, "name": "{linker}", "memory": {}, "children": []}}]}}}}"#,
+            memory(1000),
+            memory(cc1),
+            memory(500)
+        )
+    };
+    let (before, after) = (dir.path().join("a.json"), dir.path().join("b.json"));
+    std::fs::write(&before, result(1_000_000, "ld")).unwrap();
+    std::fs::write(&after, result(3_000_000, "lld")).unwrap();
+
+    peak_mem()
+        .arg("--diff-trees")
+        .args([&before, &after])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Largest growth: make > cc1"))
+        .stdout(predicate::str::contains("Added: lld"))
+        .stdout(predicate::str::contains("Removed: ld"));
+
+    peak_mem()
+        .args(["--quiet", "--diff-trees"])
+        .args([&before, &after])
+        .assert()
+        .success()
+        .stdout("2000000\n");
+    peak_mem()
+        .args(["compare", "--trees"])
+        .args([&before, &after])
+        .arg("--quiet")
+        .assert()
+        .success()
+        .stdout("2000000\n");
+
+    // Results without a tree are rejected
+    std::fs::write(&after, "{}").unwrap();
+    peak_mem()
+        .arg("--diff-trees")
+        .args([&before, &after])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("has no process_tree"));
+}
+
+#[test]
+fn bench_self_measures_each_tree_size() {
     let assert = peak_mem()
-        .args(["--quiet", "--", "sleep", "0.3"])
+        .args(["--bench-self", "--json"])
         .assert()
         .success();
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    let results = json.as_array().unwrap();
+    let sizes: Vec<_> = results
+        .iter()
+        .map(|r| r["processes"].as_u64().unwrap())
+        .collect();
+    assert_eq!(sizes, vec![1, 10, 100]);
+    for result in results {
+        assert!(result["samples"].as_u64().unwrap() > 0);
+        assert!(result["wall_us_per_sample"].as_f64().unwrap() > 0.0);
+        assert_eq!(result["overhead"][2]["interval_ms"], 100);
+    }
+}
 
-    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
-    let rss: u64 = stdout
-        .trim()
-        .parse()
-        .expect("--quiet should output a single number");
-    assert!(rss > 0);
+#[test]
+fn thermal_records_context_or_warns() {
+    let assert = peak_mem()
+        .args(["--thermal", "--json", "--", "sleep", "0.2"])
+        .assert()
+        .success();
+    let output = assert.get_output();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    // Whether anything is readable depends on the machine
+    if json["thermal"].is_null() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("--thermal found no CPU frequency"));
+    } else {
+        assert!(json["thermal"].as_object().is_some_and(|t| !t.is_empty()));
+    }
 }
 
 #[test]
-fn exit_code_is_passed_through() {
+fn non_utf8_arguments_reach_the_command() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let arg = std::ffi::OsStr::from_bytes(b"caf\xe9");
+    let assert = peak_mem()
+        .args(["--json", "--", "printf", "%s\\n"])
+        .arg(arg)
+        .assert()
+        .success();
+    let stdout = &assert.get_output().stdout;
+    // The command's own output comes first, byte for byte
+    assert!(stdout.starts_with(b"caf\xe9\n"));
+    let json: serde_json::Value = serde_json::from_slice(&stdout[5..]).unwrap();
+    assert_eq!(json["command"], "printf %s\\n caf\u{fffd}");
+}
+
+#[test]
+fn report_writes_self_contained_html() {
+    let dir = tempfile::tempdir().unwrap();
+    let report = dir.path().join("report.html");
+
+    let assert = peak_mem()
+        .arg("--report")
+        .arg(&report)
+        .args(["--json", "--", "sh", "-c", "sleep 0.3"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Report written to:"));
+    // The timeline was only kept for the report
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert!(json.get("timeline").is_none());
+
+    let html = std::fs::read_to_string(&report).unwrap();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<code>sh -c sleep 0.3</code>"));
+    assert!(html.contains("\"samples\":[["));
+    assert!(html.contains("Process tree at peak"));
+    assert!(!html.contains("src=\"http"));
+}
+
+#[test]
+fn report_overlays_baseline_timeline() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir_arg = dir.path().to_str().unwrap();
+    let timeline = dir.path().join("timeline.json");
+    let report = dir.path().join("report.html");
+
     peak_mem()
-        .args(["--", "sh", "-c", "exit 7"])
+        .args([
+            "--quiet",
+            "--baseline-dir",
+            dir_arg,
+            "--save-baseline",
+            "main",
+        ])
+        .arg("--timeline")
+        .arg(&timeline)
+        .args(["--", "sh", "-c", "sleep 0.3"])
         .assert()
-        .code(7);
+        .success();
+    peak_mem()
+        .args([
+            "--quiet",
+            "--baseline-dir",
+            dir_arg,
+            "--compare-baseline",
+            "main",
+        ])
+        .arg("--report")
+        .arg(&report)
+        .args(["--", "sh", "-c", "sleep 0.3"])
+        .assert()
+        .success();
+
+    let html = std::fs::read_to_string(&report).unwrap();
+    assert!(html.contains("\"baseline_samples\":[[0,"));
+    // The first snapshot lands a few milliseconds in on a busy machine
+    assert!(predicate::str::is_match(r#""processes":\[\[\d+,\[\["#)
+        .unwrap()
+        .eval(&html));
+    assert!(html.contains("Processes at"));
 }
 
 #[test]
-fn threshold_exceeded_exits_with_one() {
+fn chart_writes_svg_of_timeline() {
+    let dir = tempfile::tempdir().unwrap();
+    let chart = dir.path().join("chart.svg");
+
+    let assert = peak_mem()
+        .arg("--chart")
+        .arg(&chart)
+        .args(["--json", "--", "sh", "-c", "sleep 0.3"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Chart written to:"));
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert!(json.get("timeline").is_none());
+
+    let svg = std::fs::read_to_string(&chart).unwrap();
+    assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+    assert!(svg.contains("<polyline points="));
+    assert!(svg.contains(">peak "));
+}
+
+#[test]
+fn chart_term_draws_rss_after_results() {
+    let assert = peak_mem()
+        .args(["--chart-term", "--json", "--", "sh", "-c", "sleep 0.3"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("RSS over time (peak "))
+        // Which block the first column gets depends on the samples taken
+        .stderr(predicate::str::contains("┤"))
+        .stderr(predicate::function(|stderr: &str| {
+            stderr.contains(['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'])
+        }));
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert!(json.get("timeline").is_none());
+}
+
+#[test]
+fn meta_tags_results_and_baselines() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir_arg = dir.path().to_str().unwrap();
+
+    let assert = peak_mem()
+        .args([
+            "--baseline-dir",
+            dir_arg,
+            "--save-baseline",
+            "tagged",
+            "--json",
+        ])
+        .args(["--meta", "profile=release", "--meta", "runner=arm64"])
+        .args(["--", "true"])
+        .assert()
+        .success();
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert_eq!(json["metadata"]["profile"], "release");
     peak_mem()
-        .args(["--threshold", "1", "--", "sleep", "0.3"])
+        .args([
+            "--baseline-dir",
+            dir_arg,
+            "--save-baseline",
+            "untagged",
+            "--",
+            "true",
+        ])
+        .assert()
+        .success();
+
+    let saved = std::fs::read_to_string(baseline_file(dir.path(), "tagged")).unwrap();
+    let saved: serde_json::Value = serde_json::from_str(&saved).unwrap();
+    assert_eq!(saved["metadata"]["runner"], "arm64");
+
+    peak_mem()
+        .args(["--baseline-dir", dir_arg, "--list-baselines", "--quiet"])
+        .args(["--meta", "profile=release"])
+        .assert()
+        .success()
+        .stdout("tagged\n");
+
+    peak_mem()
+        .args(["--meta", "platform=haiku", "--", "true"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("recorded by peak-mem itself"));
+}
+
+#[test]
+fn compare_result_uses_a_saved_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let saved = dir.path().join("run.json");
+    std::fs::write(
+        &saved,
+        r#"{"command": "sleep", "peak_rss_bytes": 1, "peak_vsz_bytes": 0,
+            "duration_ms": 1000, "exit_code": 0, "threshold_exceeded": false,
+            "timestamp": "2026-01-01T00:00:00Z"}"#,
+    )
+    .unwrap();
+
+    let report = dir.path().join("regression.md");
+    peak_mem()
+        .arg("--compare-result")
+        .arg(&saved)
+        .arg("--regression-report")
+        .arg(&report)
+        .args(["--", "sleep", "0.1"])
         .assert()
         .code(1)
-        .stdout(predicate::str::contains("THRESHOLD EXCEEDED"));
+        .stdout(predicate::str::contains("REGRESSION DETECTED"));
+    assert!(report.exists());
+
+    peak_mem()
+        .arg("--compare-result")
+        .arg(dir.path().join("missing.json"))
+        .args(["--", "sleep", "0.1"])
+        .assert()
+        .failure();
 }
 
 #[test]
-fn baseline_save_list_delete_round_trip() {
+fn diff_compares_saved_results() {
     let dir = tempfile::tempdir().unwrap();
-    let dir_arg = dir.path().to_str().unwrap();
+    let result = |rss: u64| {
+        format!(
+            r#"{{"command": "./app", "peak_rss_bytes": {rss}, "peak_vsz_bytes": 0,
+                "duration_ms": 1000, "exit_code": 0, "threshold_exceeded": false,
+                "timestamp": "2026-01-01T00:00:00Z"}}"#
+        )
+    };
+    let (before, after) = (dir.path().join("a.json"), dir.path().join("b.json"));
+    std::fs::write(&before, result(100_000_000)).unwrap();
+    std::fs::write(&after, result(150_000_000)).unwrap();
 
     peak_mem()
-        .args(["--baseline-dir", dir_arg, "--save-baseline", "ci"])
-        .args(["--", "sleep", "0.3"])
+        .arg("--diff")
+        .args([&before, &after])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("REGRESSION DETECTED"));
+
+    let assert = peak_mem()
+        .arg("--diff")
+        .args([&after, &before])
+        .arg("--json")
         .assert()
         .success();
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert_eq!(json["baseline"]["peak_rss_bytes"], 150_000_000);
+    assert_eq!(json["regression_detected"], false);
 
+    let summary = dir.path().join("diff.md");
     peak_mem()
-        .args(["--baseline-dir", dir_arg, "--list-baselines"])
+        .arg("--diff")
+        .args([&before, &after])
+        .arg("-o")
+        .arg(&summary)
+        .assert()
+        .code(1);
+    assert!(std::fs::read_to_string(&summary)
+        .unwrap()
+        .contains("| Peak RSS |"));
+}
+
+#[test]
+fn render_outputs_saved_results() {
+    let dir = tempfile::tempdir().unwrap();
+    let saved = dir.path().join("result.json");
+    let assert = peak_mem()
+        .args(["--json", "--", "sh", "-c", "sleep 0.1"])
+        .assert()
+        .success();
+    std::fs::write(&saved, &assert.get_output().stdout).unwrap();
+
+    peak_mem()
+        .arg("--render")
+        .arg(&saved)
+        .args(["--template", "{{command}}"])
         .assert()
         .success()
-        .stdout(predicate::str::contains("Saved baselines:"))
-        .stdout(predicate::str::contains("ci"));
+        .stdout("sh -c sleep 0.1\n");
 
+    let (summary, report) = (
+        dir.path().join("summary.md"),
+        dir.path().join("report.html"),
+    );
     peak_mem()
-        .args(["--baseline-dir", dir_arg, "--delete-baseline", "ci"])
+        .arg("--render")
+        .arg(&saved)
+        .arg("-o")
+        .arg(&summary)
+        .arg("--report")
+        .arg(&report)
         .assert()
         .success();
+    assert!(std::fs::read_to_string(&summary)
+        .unwrap()
+        .contains("### Memory usage of `sh -c sleep 0.1`"));
+    assert!(std::fs::read_to_string(&report)
+        .unwrap()
+        .starts_with("<!DOCTYPE html>"));
+}
+
+#[test]
+fn merge_sums_up_saved_runs() {
+    let dir = tempfile::tempdir().unwrap();
+    let result = |name: &str, rss: u64, exit_code: i32| {
+        let path = dir.path().join(name);
+        std::fs::write(
+            &path,
+            format!(
+                r#"{{"command": "./app", "peak_rss_bytes": {rss}, "peak_vsz_bytes": 0,
+                    "duration_ms": 1000, "exit_code": {exit_code},
+                    "threshold_exceeded": false, "timestamp": "2026-01-01T00:00:00Z"}}"#
+            ),
+        )
+        .unwrap();
+        path
+    };
+    let runs = [
+        result("1.json", 1024, 0),
+        result("2.json", 2048, 0),
+        result("3.json", 3072, 1),
+    ];
 
     peak_mem()
-        .args(["--baseline-dir", dir_arg, "--list-baselines"])
+        .arg("--merge")
+        .args(&runs)
         .assert()
         .success()
-        .stdout(predicate::str::contains("No baselines found"));
+        .stdout(predicate::str::contains("Runs: 3 (1 failed)"))
+        .stdout(predicate::str::contains(
+            "Peak RSS: 2.0 KiB ± 1.0 KiB (min 1.0 KiB, max 3.0 KiB)",
+        ))
+        .stdout(predicate::str::contains(
+            "Duration: 1.00s ± 0.00s (min 1.00s, max 1.00s)",
+        ));
+
+    peak_mem()
+        .arg("--merge")
+        .args(&runs)
+        .arg("--csv")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "peak_rss_bytes,3,1024,2048,3072,1024",
+        ));
 }
 
 #[test]
-fn invalid_baseline_name_is_rejected() {
+fn trend_shows_the_saves_kept_in_history() {
     let dir = tempfile::tempdir().unwrap();
+    let baselines = dir.path().to_str().unwrap();
+    for _ in 0..3 {
+        peak_mem()
+            .args(["--baseline-dir", baselines, "--save-baseline", "main"])
+            .args(["--keep-history", "--quiet", "--", "sleep", "0.1"])
+            .assert()
+            .success();
+    }
 
     peak_mem()
-        .args(["--baseline-dir", dir.path().to_str().unwrap()])
-        .args(["--save-baseline", "..", "--", "sleep", "0.1"])
+        .args([
+            "baseline",
+            "trend",
+            "main",
+            "2",
+            "--baseline-dir",
+            baselines,
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Trend of baseline 'main': sleep 0.1\nLast 2 of 3 saves:\n",
+        ))
+        .stdout(predicate::str::contains("  Peak RSS  "))
+        .stdout(predicate::str::contains("  Duration  "));
+
+    let assert = peak_mem()
+        .args(["--baseline-dir", baselines, "--trend", "main", "--json"])
+        .assert()
+        .success();
+    let trend: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert_eq!(trend["total_runs"], 3);
+    assert_eq!(trend["runs"].as_array().unwrap().len(), 3);
+
+    // A plain save replaces the history
+    peak_mem()
+        .args(["--baseline-dir", baselines, "--save-baseline", "main"])
+        .args(["--quiet", "--", "true"])
+        .assert()
+        .success();
+    peak_mem()
+        .args(["--baseline-dir", baselines, "--trend", "main"])
         .assert()
         .failure()
-        .stderr(predicate::str::contains("Invalid baseline name"));
+        .stderr(predicate::str::contains("has no history"));
+}
+
+#[test]
+fn subcommands_expand_into_flags() {
+    let dir = tempfile::tempdir().unwrap();
+    let baselines = dir.path().to_str().unwrap();
+
+    peak_mem()
+        .args([
+            "run",
+            "--baseline-dir",
+            baselines,
+            "--save-baseline",
+            "main",
+        ])
+        .args(["--quiet", "--", "sh", "-c", "sleep 0.1"])
+        .assert()
+        .success();
+    peak_mem()
+        .args(["baseline", "list", "--baseline-dir", baselines])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("  main"));
+    peak_mem()
+        .args(["baseline", "show", "main", "--baseline-dir", baselines])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Baseline: main\nCommand: sh -c sleep 0.1\n",
+        ))
+        .stdout(predicate::str::contains("Peak memory usage: "))
+        .stdout(predicate::str::contains("Metadata:\n"));
+    let assert = peak_mem()
+        .args([
+            "baseline",
+            "show",
+            "main",
+            "--json",
+            "--baseline-dir",
+            baselines,
+        ])
+        .assert()
+        .success();
+    let baseline: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert_eq!(baseline["command"], "sh -c sleep 0.1");
+    peak_mem()
+        .args([
+            "baseline",
+            "mv",
+            "main",
+            "release",
+            "--baseline-dir",
+            baselines,
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Baseline 'main' renamed to 'release'.",
+        ));
+    peak_mem()
+        .args(["baseline", "rm", "release", "--baseline-dir", baselines])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Baseline 'release' deleted."));
+
+    peak_mem()
+        .args(["baseline", "frob"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("unknown baseline action 'frob'"));
+    // Only the first argument can be a subcommand
+    peak_mem()
+        .args(["--", "run"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("CommandNotFound"));
+}
+
+#[test]
+fn plot_charts_saved_timelines() {
+    let dir = tempfile::tempdir().unwrap();
+    let timeline = dir.path().join("run.json");
+    peak_mem()
+        .arg("--timeline")
+        .arg(&timeline)
+        .args(["--quiet", "--", "sh", "-c", "sleep 0.3"])
+        .assert()
+        .success();
+
+    peak_mem()
+        .arg("--plot")
+        .args([&timeline, &timeline])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("run.json:\nRSS over time (peak "))
+        .stdout(predicate::str::contains("(+0.0%)"));
+
+    let chart = dir.path().join("chart.svg");
+    peak_mem()
+        .arg("--plot")
+        .arg(&timeline)
+        .arg("--chart")
+        .arg(&chart)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Chart written to:"));
+    assert!(std::fs::read_to_string(&chart)
+        .unwrap()
+        .contains("<polyline points="));
+
+    peak_mem()
+        .arg("--plot")
+        .arg(dir.path().join("missing.json"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn human_output_sums_up_rss_in_a_sparkline() {
+    let assert = peak_mem()
+        .args(["--", "sh", "-c", "sleep 0.3"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let peak = stdout
+        .lines()
+        .find(|line| line.starts_with("Peak memory usage:"))
+        .unwrap();
+    let (_, sparkline) = peak.split_once("(VSZ)  ").unwrap();
+    assert!(!sparkline.is_empty());
+    assert!(sparkline.chars().all(|c| ('▁'..='█').contains(&c)));
+}
+
+#[test]
+fn stream_writes_samples_as_json_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    let stream = dir.path().join("samples.ndjson");
+    peak_mem()
+        .arg(format!("--stream={}", stream.display()))
+        .args(["--interval", "50", "--", "sh", "-c", "sleep 0.3"])
+        .assert()
+        .success();
+
+    let written = std::fs::read_to_string(&stream).unwrap();
+    let samples: Vec<serde_json::Value> = written
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("each line should be JSON"))
+        .collect();
+    assert!(samples.len() >= 2);
+    assert!(samples.iter().all(|s| s["rss_bytes"].as_u64().unwrap() > 0));
+}
+
+#[test]
+fn stream_defaults_to_stdout_ahead_of_the_results() {
+    let assert = peak_mem()
+        .args(["--stream", "--quiet", "--", "sh", "-c", "sleep 0.2"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    let (peak, samples) = lines.split_last().unwrap();
+    assert!(!samples.is_empty());
+    for sample in samples {
+        let sample: serde_json::Value = serde_json::from_str(sample).unwrap();
+        assert!(sample["timestamp"].is_string());
+    }
+    assert!(peak.parse::<u64>().is_ok());
+}
+
+#[test]
+fn watch_prints_progress_lines_without_a_terminal() {
+    let assert = peak_mem()
+        .args(["--watch", "--tui", "--progress-every", "1"])
+        .args(["--", "sh", "-c", "sleep 1.5"])
+        .assert()
+        .success();
+    let output = assert.get_output();
+    let stdout = String::from_utf8(output.stdout.clone()).unwrap();
+    let stderr = String::from_utf8(output.stderr.clone()).unwrap();
+    assert!(!stdout.contains('\x1b'));
+    assert!(stdout.starts_with("Command: sh -c sleep 1.5"));
+    let progress: Vec<&str> = stderr
+        .lines()
+        .filter(|line| line.contains("] RSS "))
+        .collect();
+    assert_eq!(progress.len(), 1, "{stderr}");
+    assert!(progress[0].starts_with('['));
+    assert!(progress[0].contains(" +1s] RSS "));
+
+    peak_mem().args(["--tui", "--", "true"]).assert().failure();
+    peak_mem()
+        .args(["--progress-every", "5", "--", "true"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn watch_with_json_writes_progress_events_to_stderr() {
+    let assert = peak_mem()
+        .args(["--watch", "--json", "--interval", "50"])
+        .args(["--", "sleep", "0.5"])
+        .assert()
+        .success();
+    let output = assert.get_output();
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result["command"], "sleep 0.5");
+
+    let stderr = String::from_utf8(output.stderr.clone()).unwrap();
+    let events: Vec<serde_json::Value> = stderr
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert!(events.len() > 3, "{stderr}");
+    for event in &events {
+        assert_eq!(event["event"], "progress");
+        assert!(event["rss_bytes"].as_u64().unwrap() <= event["peak_rss_bytes"].as_u64().unwrap());
+    }
+    let peak = events.last().unwrap()["peak_rss_bytes"].as_u64().unwrap();
+    assert!(peak <= result["peak_rss_bytes"].as_u64().unwrap());
+
+    peak_mem()
+        .args(["--watch", "--tui", "--json", "--", "true"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn stream_socket_sends_samples_to_clients() {
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+    use std::time::{Duration, Instant};
+
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("samples.sock");
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("peak-mem"))
+        .arg("--stream-socket")
+        .arg(&socket)
+        .args(["--quiet", "--interval", "20", "--", "sleep", "0.5"])
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(2);
+    let client = loop {
+        match UnixStream::connect(&socket) {
+            Ok(client) => break client,
+            Err(_) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+            Err(e) => panic!("socket never came up: {e}"),
+        }
+    };
+    // The connection is closed when monitoring ends
+    let samples: Vec<serde_json::Value> = BufReader::new(client)
+        .lines()
+        .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+        .collect();
+    assert!(child.wait().unwrap().success());
+    assert!(!samples.is_empty());
+    assert!(samples.iter().all(|s| s["rss_bytes"].as_u64().unwrap() > 0));
+    assert!(!socket.exists());
+}
+
+#[test]
+fn sigusr1_pauses_and_resumes_sampling() {
+    use std::time::Duration;
+
+    let dir = tempfile::tempdir().unwrap();
+    let timeline = dir.path().join("timeline.json");
+    let child = std::process::Command::new(assert_cmd::cargo::cargo_bin("peak-mem"))
+        .args([
+            "--json",
+            "--interval",
+            "20",
+            "--timestamp-format",
+            "relative",
+        ])
+        .arg("--timeline")
+        .arg(&timeline)
+        .args(["--", "sleep", "2"])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let usr1 = || {
+        let status = std::process::Command::new("kill")
+            .args(["-USR1", &child.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+    std::thread::sleep(Duration::from_millis(500));
+    usr1();
+    std::thread::sleep(Duration::from_millis(700));
+    usr1();
+
+    // The command isn't touched, and runs to the end
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Sampling paused"), "{stderr}");
+    assert!(stderr.contains("Sampling resumed"), "{stderr}");
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["exit_code"], 0);
+    let pauses = json["sampling_pauses"].as_array().unwrap();
+    assert_eq!(pauses.len(), 1);
+
+    let timeline: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&timeline).unwrap()).unwrap();
+    assert_eq!(timeline["pauses"], json["sampling_pauses"]);
+    let samples = timeline["samples"].as_array().unwrap();
+    let longest_gap = samples
+        .windows(2)
+        .map(|pair| pair[1]["timestamp"].as_f64().unwrap() - pair[0]["timestamp"].as_f64().unwrap())
+        .fold(0.0, f64::max);
+    assert!(longest_gap > 0.5, "{longest_gap}");
+}
+
+#[test]
+fn quiet_outputs_only_rss_bytes() {
+    let assert = peak_mem()
+        .args(["--quiet", "--", "sleep", "0.3"])
+        .assert()
+        .success();
+
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let rss: u64 = stdout
+        .trim()
+        .parse()
+        .expect("--quiet should output a single number");
+    assert!(rss > 0);
+}
+
+#[test]
+fn fields_output_is_one_tab_separated_line_in_order() {
+    let assert = peak_mem()
+        .args([
+            "--fields",
+            "peak_rss,duration_ms,exit_code",
+            "--",
+            "sleep",
+            "0.3",
+        ])
+        .assert()
+        .success();
+
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let values: Vec<&str> = stdout.trim_end_matches('\n').split('\t').collect();
+    assert_eq!(values.len(), 3, "unexpected output: {stdout:?}");
+    assert!(values[0].parse::<u64>().unwrap() > 0);
+    assert!(values[1].parse::<u64>().unwrap() >= 300);
+    assert_eq!(values[2], "0");
+
+    peak_mem()
+        .args(["--fields", "peak_rss,bogus", "--", "true"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn template_output_fills_in_the_fields() {
+    peak_mem()
+        .args([
+            "--template",
+            "exit {{exit_code}} after {{duration_s}}s",
+            "--",
+            "sleep",
+            "0.3",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"^exit 0 after 0\.\ds\n$").unwrap());
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("line.tmpl");
+    std::fs::write(&path, "{{command}}: {{peak_rss_kib}} KiB\n").unwrap();
+    peak_mem()
+        .arg("--template")
+        .arg(&path)
+        .args(["--", "true"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"^true: [\d.]+ KiB\n$").unwrap());
+
+    peak_mem()
+        .args(["--template", "missing.tmpl", "--", "true"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("template file"));
+}
+
+#[test]
+fn format_takes_the_built_in_formats() {
+    let output = peak_mem()
+        .args(["--format", "json", "--", "true"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["command"], "true");
+
+    peak_mem()
+        .args(["--format", "tap", "--", "true"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("TAP version 13\n"));
+
+    peak_mem()
+        .args(["--format", "prometheus", "--", "true"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "peak_mem_peak_rss_bytes{command=\"true\"}",
+        ));
+
+    // As their flags would be
+    peak_mem()
+        .args(["--format", "quiet", "--verbose", "--", "true"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with '--verbose'"));
+    peak_mem()
+        .args(["--format", "yaml", "--", "true"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid format 'yaml'"));
+}
+
+#[test]
+fn format_exec_pipes_the_json_results_through_a_command() {
+    peak_mem()
+        .args([
+            "--format",
+            r#"exec:sed -n 's/.*"command": "\([^"]*\)".*/cmd=\1/p'"#,
+            "--",
+            "sleep",
+            "0.3",
+        ])
+        .assert()
+        .success()
+        .stdout("cmd=sleep 0.3\n");
+
+    peak_mem()
+        .args(["--format", "exec:cat >/dev/null; exit 4", "--", "true"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("exit status: 4"));
+}
+
+#[test]
+fn exit_code_is_passed_through() {
+    peak_mem()
+        .args(["--", "sh", "-c", "exit 7"])
+        .assert()
+        .code(7);
+}
+
+#[test]
+fn threshold_exceeded_exits_with_one() {
+    peak_mem()
+        .args(["--threshold", "1", "--", "sleep", "0.3"])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("THRESHOLD EXCEEDED"));
+}
+
+#[test]
+fn baseline_save_list_delete_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir_arg = dir.path().to_str().unwrap();
+
+    peak_mem()
+        .args(["--baseline-dir", dir_arg, "--save-baseline", "ci"])
+        .args(["--", "sleep", "0.3"])
+        .assert()
+        .success();
+
+    peak_mem()
+        .args(["--baseline-dir", dir_arg, "--list-baselines"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Saved baselines:"))
+        .stdout(predicate::str::contains("NAME  COMMAND    PEAK RSS"))
+        .stdout(predicate::str::contains("ci    sleep 0.3  "));
+
+    let assert = peak_mem()
+        .args(["--baseline-dir", dir_arg, "--list-baselines", "--json"])
+        .assert()
+        .success();
+    let list: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert_eq!(list[0]["name"], "ci");
+    assert_eq!(list[0]["command"], "sleep 0.3");
+    assert!(list[0]["peak_rss_bytes"].as_u64().unwrap() > 0);
+
+    // Rather than the human list in their place
+    for format in [&["--influx"][..], &["--format", "prometheus"]] {
+        peak_mem()
+            .args(["--baseline-dir", dir_arg, "--list-baselines"])
+            .args(format)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "can't be used with --list-baselines",
+            ));
+    }
+
+    peak_mem()
+        .args(["--baseline-dir", dir_arg, "--delete-baseline", "ci"])
+        .assert()
+        .success();
+
+    peak_mem()
+        .args(["--baseline-dir", dir_arg, "--list-baselines"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No baselines found"));
+}
+
+#[test]
+fn baseline_export_import_between_dirs() {
+    let dir = tempfile::tempdir().unwrap();
+    let (here, there) = (dir.path().join("here"), dir.path().join("there"));
+    let file = dir.path().join("main.json");
+
+    peak_mem()
+        .arg("--baseline-dir")
+        .arg(&here)
+        .args(["--save-baseline", "main", "--quiet", "--", "sleep", "0.1"])
+        .assert()
+        .success();
+    peak_mem()
+        .arg("--baseline-dir")
+        .arg(&here)
+        .args(["--export-baseline", "main"])
+        .arg(&file)
+        .assert()
+        .success();
+
+    peak_mem()
+        .arg("--baseline-dir")
+        .arg(&there)
+        .arg("--import-baseline")
+        .arg(&file)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Baseline 'main' imported from:"))
+        .stderr(predicate::str::is_empty());
+    peak_mem()
+        .arg("--baseline-dir")
+        .arg(&there)
+        .args([
+            "--compare-baseline",
+            "main",
+            "--regression-threshold",
+            "1000",
+        ])
+        .args(["--quiet", "--", "sleep", "0.1"])
+        .assert()
+        .success();
+    // Importing again would replace it
+    peak_mem()
+        .arg("--baseline-dir")
+        .arg(&there)
+        .arg("--import-baseline")
+        .arg(&file)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already exists"));
+}
+
+#[test]
+fn baseline_can_be_kept_in_a_file_anywhere() {
+    let dir = tempfile::tempdir().unwrap();
+    let baselines = dir.path().join("baselines");
+
+    peak_mem()
+        .current_dir(dir.path())
+        .arg("--baseline-dir")
+        .arg(&baselines)
+        .args(["--baseline-file", "bench/baseline.json"])
+        .args(["--save-baseline", "main", "--quiet"])
+        .args(["--", "sleep", "0.1"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("saved to: bench/baseline.json"));
+    assert!(dir.path().join("bench/baseline.json").exists());
+
+    peak_mem()
+        .current_dir(dir.path())
+        .arg("--baseline-dir")
+        .arg(&baselines)
+        .args(["--baseline-file", "bench/baseline.json"])
+        .args(["--compare-baseline", "main"])
+        .args(["--regression-threshold", "1000", "--", "sleep", "0.1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Baseline vs Current:"));
+    peak_mem()
+        .arg("--baseline-dir")
+        .arg(&baselines)
+        .arg("--list-baselines")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No baselines found"));
+}
+
+#[test]
+fn baseline_name_is_never_a_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let package = dir.path().join("package.json");
+    std::fs::write(&package, "{\"name\": \"app\"}").unwrap();
+
+    peak_mem()
+        .current_dir(dir.path())
+        .args([
+            "--baseline-dir",
+            "baselines",
+            "--delete-baseline",
+            "package.json",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--baseline-file"));
+    peak_mem()
+        .current_dir(dir.path())
+        .args([
+            "--baseline-file",
+            "package.json",
+            "--delete-baseline",
+            "main",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is not a baseline"));
+    peak_mem()
+        .current_dir(dir.path())
+        .args(["--baseline-file", "package.json", "--save-baseline", "main"])
+        .args(["--", "true"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is not a baseline"));
+    assert_eq!(
+        std::fs::read_to_string(&package).unwrap(),
+        "{\"name\": \"app\"}"
+    );
+}
+
+#[test]
+fn invalid_baseline_name_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+
+    peak_mem()
+        .args(["--baseline-dir", dir.path().to_str().unwrap()])
+        .args(["--save-baseline", "..", "--", "sleep", "0.1"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid baseline name"));
+}
+
+#[test]
+fn conditional_save_only_updates_baseline_without_regression() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir_arg = dir.path().to_str().unwrap();
+
+    peak_mem()
+        .args(["--baseline-dir", dir_arg, "--save-baseline", "ci"])
+        .args(["--", "sleep", "0.1"])
+        .assert()
+        .success();
+
+    // Any RSS change counts as a regression against a negative threshold
+    peak_mem()
+        .args(["--baseline-dir", dir_arg, "--compare-baseline", "ci"])
+        .args(["--regression-threshold=-1000", "--save-if-no-regression"])
+        .args(["--", "sleep", "0.1"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("Baseline 'ci' not saved"));
+
+    peak_mem()
+        .args(["--baseline-dir", dir_arg, "--compare-baseline", "ci"])
+        .args(["--regression-threshold", "1000", "--save-if-no-regression"])
+        .args(["--", "sleep", "0.1"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Baseline 'ci' saved to:"));
+}
+
+#[test]
+fn update_baseline_if_improved_allows_a_tolerance() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir_arg = dir.path().to_str().unwrap();
+
+    peak_mem()
+        .args(["--baseline-dir", dir_arg, "--save-baseline", "ci"])
+        .args(["--", "sleep", "0.1"])
+        .assert()
+        .success();
+
+    // Far above the baseline's peak RSS, but within tolerance
+    let baseline = baseline_file(dir.path(), "ci");
+    let mut saved: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&baseline).unwrap()).unwrap();
+    saved["peak_rss_bytes"] = 1.into();
+    std::fs::write(&baseline, saved.to_string()).unwrap();
+    peak_mem()
+        .args(["--baseline-dir", dir_arg, "--compare-baseline", "ci"])
+        .args([
+            "--update-baseline-if-improved",
+            "--regression-threshold=1e12",
+        ])
+        .args(["--", "sleep", "0.1"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Baseline 'ci' not saved"));
+    peak_mem()
+        .args(["--baseline-dir", dir_arg, "--compare-baseline", "ci"])
+        .args([
+            "--update-baseline-if-improved=1e12",
+            "--regression-threshold=1e12",
+        ])
+        .args(["--", "sleep", "0.1"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Baseline 'ci' saved to:"));
+
+    // The run replaced the edited baseline
+    let saved: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&baseline).unwrap()).unwrap();
+    assert!(saved["peak_rss_bytes"].as_u64().unwrap() > 1);
+}
+
+#[test]
+fn github_annotates_regression_and_appends_summary() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir_arg = dir.path().to_str().unwrap();
+    let summary = dir.path().join("summary.md");
+    std::fs::write(&summary, "Earlier step\n").unwrap();
+
+    peak_mem()
+        .args(["--baseline-dir", dir_arg, "--save-baseline", "ci"])
+        .args(["--", "sleep", "0.1"])
+        .assert()
+        .success();
+
+    peak_mem()
+        .env("GITHUB_STEP_SUMMARY", &summary)
+        .args([
+            "--baseline-dir",
+            dir_arg,
+            "--compare-baseline",
+            "ci",
+            "--json",
+        ])
+        .args([
+            "--github",
+            "--regression-threshold=-1000",
+            "--",
+            "sleep",
+            "0.1",
+        ])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "::error title=Memory regression::sleep 0.1 regressed in peak RSS",
+        ));
+
+    let summary = std::fs::read_to_string(&summary).unwrap();
+    assert!(summary.starts_with("Earlier step\n### Memory usage of `sleep 0.1`"));
+    assert!(summary.contains("against baseline `ci`."));
+    assert!(summary.contains("| Metric | Baseline | Current | Change |"));
+}
+
+#[test]
+fn ci_writes_json_file_next_to_human_output() {
+    let dir = tempfile::tempdir().unwrap();
+
+    peak_mem()
+        .current_dir(dir.path())
+        .env("GITLAB_CI", "true")
+        .args(["--", "sleep", "0.2"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Peak memory usage:"))
+        .stderr(predicate::str::contains(
+            "GitLab CI detected; results written to: peak-mem.json",
+        ));
+    let json = std::fs::read_to_string(dir.path().join("peak-mem.json")).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(json["command"], "sleep 0.2");
+    std::fs::remove_file(dir.path().join("peak-mem.json")).unwrap();
+
+    // Not with --json on the console, nor when disabled
+    for args in [["--json"], ["--no-json-file"]] {
+        peak_mem()
+            .current_dir(dir.path())
+            .env("CI", "true")
+            .args(args)
+            .args(["--", "sleep", "0.1"])
+            .assert()
+            .success();
+        assert!(!dir.path().join("peak-mem.json").exists());
+    }
+
+    let custom = dir.path().join("results.json");
+    peak_mem()
+        .arg("--json-file")
+        .arg(&custom)
+        .args(["--", "sleep", "0.1"])
+        .assert()
+        .success();
+    assert!(custom.exists());
+}
+
+#[test]
+fn append_to_baseline_accumulates_runs() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir_arg = dir.path().to_str().unwrap();
+
+    for runs in 1..=3 {
+        peak_mem()
+            .args(["--baseline-dir", dir_arg, "--append-to-baseline", "ci"])
+            .args(["--baseline-window", "2", "--", "sleep", "0.1"])
+            .assert()
+            .success()
+            .stderr(predicate::str::contains(format!("({} runs)", runs.min(2))));
+    }
+    let baseline: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(baseline_file(dir.path(), "ci")).unwrap())
+            .unwrap();
+    assert_eq!(baseline["runs"].as_array().unwrap().len(), 2);
+
+    peak_mem()
+        .args(["--baseline-dir", dir_arg, "--compare-baseline", "ci"])
+        .args(["--", "sleep", "0.1"])
+        .assert()
+        .stdout(predicate::str::contains("Baseline of 2 runs: mean "));
+}
+
+#[test]
+fn baseline_saved_from_runs_keeps_each_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir_arg = dir.path().to_str().unwrap();
+
+    peak_mem()
+        .args(["--baseline-dir", dir_arg, "--save-baseline", "ci"])
+        .args(["--runs", "3", "--", "sleep", "0.1"])
+        .assert()
+        .success();
+    let baseline: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(baseline_file(dir.path(), "ci")).unwrap())
+            .unwrap();
+    assert_eq!(baseline["runs"].as_array().unwrap().len(), 3);
+
+    let output = peak_mem()
+        .args(["--baseline-dir", dir_arg, "--compare-baseline", "ci"])
+        .args(["--noise-sigmas", "1000", "--json", "--", "sleep", "0.1"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["rss_distribution"]["runs"], 3);
+    // Unless all three peaked alike, which leaves no noise to allow for
+    if json["rss_z_score"].is_number() {
+        assert_eq!(json["noise_sigmas"], 1000.0);
+        assert_eq!(json["memory_regression_detected"], false);
+    }
+
+    for sigmas in ["0", "-1", "NaN", "inf"] {
+        peak_mem()
+            .args(["--baseline-dir", dir_arg, "--compare-baseline", "ci"])
+            .arg(format!("--noise-sigmas={sigmas}"))
+            .args(["--", "sleep", "0.1"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("greater than zero"));
+    }
+}
+
+#[test]
+fn save_and_compare_compares_against_previous_baseline() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir_arg = dir.path().to_str().unwrap();
+
+    peak_mem()
+        .args(["--baseline-dir", dir_arg, "--save-baseline", "ci"])
+        .args(["--", "sleep", "0.1"])
+        .assert()
+        .success();
+
+    // The comparison reads the old baseline, then the run replaces it
+    // even though it regressed.
+    let assert = peak_mem()
+        .args(["--baseline-dir", dir_arg, "--json"])
+        .args(["--compare-baseline", "ci", "--save-baseline", "ci"])
+        .args(["--regression-threshold=-1000", "--", "sleep", "0.3"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("Baseline 'ci' saved to:"));
+
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert!(json["baseline"]["duration_ms"].as_u64().unwrap() < 300);
+
+    let saved = std::fs::read_to_string(baseline_file(dir.path(), "ci")).unwrap();
+    let saved: serde_json::Value = serde_json::from_str(&saved).unwrap();
+    assert!(saved["duration_ms"].as_u64().unwrap() >= 300);
+}
+
+#[test]
+fn auto_baseline_follows_the_default_branch() {
+    let repo = tempfile::tempdir().unwrap();
+    let baselines = tempfile::tempdir().unwrap();
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(repo.path())
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {args:?} failed");
+    };
+    git(&["init", "--quiet", "--initial-branch", "main"]);
+    git(&["commit", "--quiet", "--allow-empty", "-m", "base"]);
+
+    let run = |args: &[&str]| {
+        peak_mem()
+            .current_dir(repo.path())
+            .arg("--baseline-dir")
+            .arg(baselines.path())
+            .args(["--baseline-scheme", "ci-{branch}"])
+            .args(args)
+            .args(["--", "sleep", "0.1"])
+            .assert()
+    };
+
+    // Nothing saved for the default branch yet
+    run(&["--compare-baseline", "auto"])
+        .failure()
+        .stderr(predicate::str::contains("ci-main"));
+
+    run(&["--save-baseline", "auto"])
+        .success()
+        .stderr(predicate::str::contains("Baseline 'ci-main' saved"));
+    let saved = std::fs::read_to_string(baseline_file(baselines.path(), "ci-main")).unwrap();
+    let saved: serde_json::Value = serde_json::from_str(&saved).unwrap();
+    assert_eq!(saved["metadata"]["git_branch"], "main");
+    assert_eq!(saved["metadata"]["git_dirty"], "false");
+
+    git(&["checkout", "--quiet", "-b", "feature"]);
+    git(&["commit", "--quiet", "--allow-empty", "-m", "change"]);
+    run(&[
+        "--compare-baseline",
+        "auto",
+        "--regression-threshold",
+        "1000",
+    ])
+    .success()
+    .stderr(predicate::str::contains(
+        "Comparing against baseline 'ci-main'",
+    ))
+    // Both commits are named, so the regression can be traced
+    .stdout(
+        predicate::str::is_match(r"Commit: [0-9a-f]{8} \(main\) → [0-9a-f]{8} \(feature\)")
+            .unwrap(),
+    );
+}
+
+#[test]
+fn fingerprint_changes_are_reported_in_comparison() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir_arg = dir.path().to_str().unwrap();
+    let cache = tempfile::tempdir().unwrap();
+
+    peak_mem()
+        .args(["--baseline-dir", dir_arg, "--save-baseline", "ci"])
+        .args(["--probe", "echo tool 1.0", "--", "true"])
+        .env("XDG_CACHE_HOME", cache.path())
+        .env("PEAK_MEM_TEST_SECRET", "hunter2")
+        .assert()
+        .success();
+
+    // The key the values are hashed with stays out of the baseline
+    // directory
+    let key = cache.path().join("peak-mem").join("fingerprint.key");
+    assert_eq!(std::fs::read(&key).unwrap().len(), 16);
+    assert!(!dir.path().join("fingerprint.key").exists());
+
+    let compare = |cache: &std::path::Path| {
+        peak_mem()
+            .args(["--baseline-dir", dir_arg, "--compare-baseline", "ci"])
+            .args(["--regression-threshold", "1000"])
+            .args(["--probe", "echo tool 2.0", "--", "true"])
+            .env("XDG_CACHE_HOME", cache)
+            .env("PEAK_MEM_TEST_SECRET", "hunter3")
+            .assert()
+            .success()
+    };
+    compare(cache.path())
+        .stdout(predicate::str::contains("Environment changed:"))
+        .stdout(predicate::str::contains("PEAK_MEM_TEST_SECRET changed"))
+        .stdout(predicate::str::contains(
+            "`echo tool 2.0`: (not probed) → 'tool 2.0'",
+        ));
+
+    // With another key, the values can't be compared
+    let elsewhere = tempfile::tempdir().unwrap();
+    compare(elsewhere.path())
+        .stdout(predicate::str::contains(
+            "Environment: not comparable (different fingerprint key)",
+        ))
+        .stdout(predicate::str::contains("PEAK_MEM_TEST_SECRET").not());
+}
+
+#[test]
+fn expect_processes_from_warns_about_skipped_processes() {
+    let dir = tempfile::tempdir().unwrap();
+    let previous = dir.path().join("previous.json");
+    std::fs::write(
+        &previous,
+        r#"{"observed_processes": ["sh", "sleep", "skipped-step"]}"#,
+    )
+    .unwrap();
+
+    peak_mem()
+        .arg("--expect-processes-from")
+        .arg(&previous)
+        .args(["--interval", "10", "--", "sh", "-c", "sleep 0.3; true"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("did not run: skipped-step"))
+        .stderr(predicate::str::contains("not seen in").not());
+}
+
+#[test]
+fn threshold_actions_fire_during_the_run() {
+    let assert = peak_mem()
+        .args(["--json", "--threshold", "1"])
+        .args(["--on-threshold", "warn", "--on-threshold", "annotate"])
+        .args(["--on-threshold", "kill", "--", "sleep", "10"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("Memory threshold"));
+
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert_eq!(json["threshold_exceeded"], true);
+    assert!(json["threshold_event"]["rss_bytes"].as_u64().unwrap() > 1);
+    // Killed long before sleep would have finished
+    assert!(json["duration_ms"].as_u64().unwrap() < 10_000);
+    assert!(json["exit_code"].is_null());
+}
+
+#[test]
+fn threshold_action_requires_threshold() {
+    peak_mem()
+        .args(["--on-threshold", "warn", "--", "sleep", "0.1"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--threshold"));
+}
+
+#[test]
+fn attached_pids_are_reported_with_a_combined_peak() {
+    let mut server = std::process::Command::new("sleep")
+        .arg("5")
+        .spawn()
+        .unwrap();
+    let server_pid = server.id().to_string();
+
+    let assert = peak_mem()
+        .args(["--json", "--interval", "10", "--pid", &server_pid])
+        .args(["--", "sleep", "0.3"])
+        .assert()
+        .success();
+    let _ = server.kill();
+    let _ = server.wait();
+
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    let targets = json["targets"].as_array().unwrap();
+    assert_eq!(targets.len(), 2);
+    assert_eq!(targets[0]["command"], "sleep 0.3");
+    assert_eq!(targets[1]["pid"].to_string(), server_pid);
+    assert!(targets[1].get("command").is_none());
+
+    let combined = json["peak_rss_bytes"].as_u64().unwrap();
+    for target in targets {
+        let peak = target["peak_rss_bytes"].as_u64().unwrap();
+        assert!(peak > 0);
+        assert!(peak <= combined);
+    }
+}
+
+#[test]
+fn spawn_failures_exit_like_a_shell() {
+    peak_mem()
+        .args(["--", "peak-mem-no-such-command"])
+        .assert()
+        .code(127)
+        .stderr(predicate::str::contains("CommandNotFound"));
+
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("script");
+    std::fs::write(&script, "#!/bin/sh\n").unwrap();
+    peak_mem()
+        .arg("--")
+        .arg(&script)
+        .assert()
+        .code(126)
+        .stderr(predicate::str::contains("CommandNotExecutable"));
+}
+
+#[test]
+fn strict_fails_on_incomplete_data() {
+    peak_mem()
+        .args(["--strict", "--json", "--", "sleep", "0.3"])
+        .assert()
+        .success();
+
+    // A zombie still has a PID but nothing left to sample
+    let mut child = std::process::Command::new("true").spawn().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    peak_mem()
+        .args(["--pid", &child.id().to_string(), "--once", "--strict"])
+        .assert()
+        .code(121)
+        .stderr(predicate::str::contains("NoSamples"));
+    child.wait().unwrap();
+
+    peak_mem()
+        .args(["--max-skipped-ticks", "5", "--", "true"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn unknown_pid_is_rejected() {
+    peak_mem()
+        .args(["--pid", &i32::MAX.to_string()])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No process with PID"));
+}
+
+#[test]
+fn own_pid_is_rejected() {
+    // exec hands the shell's PID to peak-mem
+    Command::new("sh")
+        .args(["-c", "exec \"$0\" --pid $$"])
+        .arg(assert_cmd::cargo::cargo_bin("peak-mem"))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is peak-mem itself"));
+}
+
+#[test]
+fn peak_mem_leaves_itself_out_of_an_attached_tree() {
+    // The trailing command keeps the shell from exec'ing peak-mem
+    let assert = Command::new("sh")
+        .args(["-c", "\"$0\" --pid $$ --json -- sleep 0.3; true"])
+        .arg(assert_cmd::cargo::cargo_bin("peak-mem"))
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "peak-mem is in the process tree of PID",
+        ));
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    let observed = json["observed_processes"].as_array().unwrap();
+    assert!(!observed.iter().any(|name| name == "peak-mem"));
+    // The command is counted once, as a target of its own
+    let shell = &json["targets"][1];
+    assert!(shell["peak_rss_bytes"].as_u64().unwrap() < json["peak_rss_bytes"].as_u64().unwrap());
+}
+
+#[test]
+fn container_cannot_be_combined_with_a_command() {
+    peak_mem()
+        .args(["--container", "web", "--", "sleep", "0.1"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn repeated_runs_write_an_envelope_and_report_every_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("envelope.json");
+
+    let assert = peak_mem()
+        .args(["--json", "--runs", "3", "--interval", "10"])
+        .arg("--timeline-envelope")
+        .arg(&path)
+        .args(["--", "sleep", "0.1"])
+        .assert()
+        .success();
+
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    let runs = json["runs"].as_array().unwrap();
+    assert_eq!(runs.len(), 3);
+    let highest = runs
+        .iter()
+        .map(|run| run["peak_rss_bytes"].as_u64().unwrap())
+        .max()
+        .unwrap();
+    assert_eq!(json["peak_rss_bytes"].as_u64().unwrap(), highest);
+    // Only kept for the envelope
+    assert!(json.get("timeline").is_none());
+
+    let envelope: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let points = envelope.as_array().unwrap();
+    assert_eq!(points.len(), 101);
+    for point in points {
+        let min = point["rss_min_bytes"].as_u64().unwrap();
+        let median = point["rss_median_bytes"].as_u64().unwrap();
+        let max = point["rss_max_bytes"].as_u64().unwrap();
+        assert!(min <= median && median <= max);
+    }
+}
+
+#[test]
+fn repeated_runs_stop_at_a_failing_run() {
+    let assert = peak_mem()
+        .args(["--json", "--runs", "3", "--", "false"])
+        .assert()
+        .code(1);
+
+    let json: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert_eq!(json["exit_code"], 1);
+    assert!(json.get("runs").is_none());
+}
+
+#[test]
+fn unit_cannot_be_combined_with_a_command() {
+    peak_mem()
+        .args(["--unit", "myapp.service", "--", "sleep", "0.1"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn regression_report_is_written_only_on_regression() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir_arg = dir.path().to_str().unwrap();
+    let report = dir.path().join("report.md");
+
+    peak_mem()
+        .args(["--baseline-dir", dir_arg, "--save-baseline", "ci"])
+        .args(["--", "sleep", "0.1"])
+        .assert()
+        .success();
+
+    peak_mem()
+        .args(["--baseline-dir", dir_arg, "--compare-baseline", "ci"])
+        .arg("--regression-report")
+        .arg(&report)
+        .args(["--regression-threshold", "1000", "--", "sleep", "0.1"])
+        .assert()
+        .success();
+    assert!(!report.exists());
+
+    peak_mem()
+        .args(["--baseline-dir", dir_arg, "--compare-baseline", "ci"])
+        .arg("--regression-report")
+        .arg(&report)
+        .args(["--regression-threshold=-1000", "--", "sleep", "0.1"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("Regression report written to:"));
+
+    let report = std::fs::read_to_string(&report).unwrap();
+    assert!(report.starts_with("# Memory regression in `sleep 0.1`"));
+    assert!(report.contains("| Peak RSS |"));
+    assert!(report.contains("## Top consumers at peak"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn system_mode_samples_machine_memory_for_a_duration() {
+    let dir = tempfile::tempdir().unwrap();
+    let timeline = dir.path().join("system.json");
+
+    let output = peak_mem()
+        .args(["--system", "--duration", "1", "--interval", "50", "--json"])
+        .arg("--timeline")
+        .arg(&timeline)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["command"], "system");
+    let system = &json["system"];
+    assert!(system["total_bytes"].as_u64().unwrap() > 0);
+    assert!(json["peak_rss_bytes"].as_u64().unwrap() <= system["total_bytes"].as_u64().unwrap());
+    assert!(json["duration_ms"].as_u64().unwrap() >= 1000);
+
+    let samples: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&timeline).unwrap()).unwrap();
+    let samples = samples.as_array().unwrap();
+    assert!(samples.len() > 5);
+    assert!(samples[0]["system"]["available_bytes"].is_u64());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn lowest_system_memory_available_is_recorded() {
+    let output = peak_mem()
+        .args(["--json", "--", "sleep", "0.3"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let lowest = &json["lowest_system_memory"];
+    let available = lowest["available_bytes"].as_u64().unwrap();
+    assert!(available > 0);
+    assert!(available <= lowest["total_bytes"].as_u64().unwrap());
+
+    peak_mem()
+        .args(["--", "sleep", "0.1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Lowest system memory available: "));
+}
+
+#[test]
+fn timestamp_format_applies_to_fields_and_timeline() {
+    let dir = tempfile::tempdir().unwrap();
+    let timeline = dir.path().join("timeline.json");
+
+    let output = peak_mem()
+        .args(["--timestamp-format", "relative", "--fields", "timestamp"])
+        .arg("--timeline")
+        .arg(&timeline)
+        .args(["--", "sleep", "0.3"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let end: f64 = String::from_utf8(output.stdout)
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap();
+    assert!((0.3..5.0).contains(&end));
+
+    let samples: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&timeline).unwrap()).unwrap();
+    let times: Vec<f64> = samples
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|sample| sample["timestamp"].as_f64().unwrap())
+        .collect();
+    assert!(times[0] < 0.1);
+    assert!(times.windows(2).all(|pair| pair[0] <= pair[1]));
+
+    let output = peak_mem()
+        .args(["--timestamp-format", "unix", "--fields", "timestamp"])
+        .args(["--", "true"])
+        .output()
+        .unwrap();
+    let unix: f64 = String::from_utf8(output.stdout)
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap();
+    assert!(unix > 1_700_000_000.0);
+
+    peak_mem()
+        .args(["--timestamp-format", "iso", "--", "true"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("rfc3339, unix, relative"));
+}
+
+#[test]
+fn metrics_are_served_during_the_run() {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::time::{Duration, Instant};
+
+    // Find a free port
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("peak-mem"))
+        .args(["--quiet", "--serve-metrics", &addr.to_string()])
+        .args(["--", "sleep", "2"])
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(2);
+    let mut stream = loop {
+        match TcpStream::connect(addr) {
+            Ok(stream) => break stream,
+            Err(_) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(50)),
+            Err(e) => panic!("metrics endpoint never came up: {e}"),
+        }
+    };
+    stream
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    child.wait().unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("# TYPE peak_mem_peak_rss_bytes gauge"));
+    assert!(response.contains("peak_mem_rss_bytes{command=\"sleep 2\"}"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn pid_namespace_boundaries_are_marked_in_tree() {
+    // Needs unprivileged user namespaces
+    let unshare = ["unshare", "--user", "--map-root-user", "--pid", "--fork"];
+    let supported = std::process::Command::new(unshare[0])
+        .args(&unshare[1..])
+        .arg("true")
+        .status()
+        .is_ok_and(|status| status.success());
+    if !supported {
+        return;
+    }
+
+    let output = peak_mem()
+        .args(["--json", "--verbose", "--interval", "20", "--"])
+        .args(unshare)
+        .args(["sh", "-c", "sleep 0.5"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let tree = &json["process_tree"];
+    assert!(tree.get("namespace").is_none());
+    let namespace = &tree["children"][0]["namespace"];
+    // The shell is init of the new namespace
+    assert_eq!(namespace["pid"], 1);
+    assert!(namespace["inode"].as_u64().unwrap() > 0);
+    assert!(namespace.get("via_cgroup").is_none());
+}
+
+#[test]
+fn runtime_heap_records_the_python_heap() {
+    let supported = std::process::Command::new("python3")
+        .args(["-c", "import tracemalloc"])
+        .status()
+        .is_ok_and(|status| status.success());
+    if !supported {
+        return;
+    }
+
+    let script = "import time\nheld = [bytearray(1 << 20) for _ in range(16)]\ntime.sleep(0.5)\n";
+    let output = peak_mem()
+        .args(["--json", "--runtime-heap", "python", "--interval", "20"])
+        .args(["--", "python3", "-c", script])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let heap = json["peak_heap_bytes"].as_u64().unwrap();
+    assert!(heap >= 16 << 20, "{heap}");
+    assert!(heap < json["peak_rss_bytes"].as_u64().unwrap());
+
+    peak_mem()
+        .args(["--runtime-heap", "ruby", "--", "true"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid runtime 'ruby'"));
+}
+
+#[test]
+fn verbose_tree_has_each_process_rss_over_the_run() {
+    let output = peak_mem()
+        .args(["--json", "--verbose", "--interval", "20", "--"])
+        .args(["sh", "-c", "sleep 0.3; true"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let tree = &json["process_tree"];
+    let stats = &tree["rss_stats"];
+    let (min, avg, peak) = (
+        stats["min_bytes"].as_u64().unwrap(),
+        stats["avg_bytes"].as_u64().unwrap(),
+        stats["peak_bytes"].as_u64().unwrap(),
+    );
+    assert!(min <= avg && avg <= peak);
+    assert!(peak >= tree["memory"]["rss_bytes"].as_u64().unwrap());
+    assert!(stats["samples"].as_u64().unwrap() > 1);
+
+    let output = peak_mem()
+        .args(["--verbose", "--", "sh", "-c", "sleep 0.1"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("at the tree's peak)"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn cargo_mode_attributes_memory_to_crates() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Stands in for rustc; a script's process is named after it
+    let dir = tempfile::tempdir().unwrap();
+    let rustc = dir.path().join("rustc");
+    std::fs::write(&rustc, "#!/bin/sh\nsleep 0.5\n").unwrap();
+    std::fs::set_permissions(&rustc, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = peak_mem()
+        .args(["--cargo", "--json", "--interval", "20", "--"])
+        .arg(&rustc)
+        .args(["--crate-name", "demo", "src/lib.rs"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let crates = json["crates"].as_array().unwrap();
+    assert_eq!(crates.len(), 1);
+    assert_eq!(crates[0]["name"], "demo");
+    assert!(crates[0]["peak_rss_bytes"].as_u64().unwrap() > 0);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn compile_units_attributes_memory_to_source_files() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Stands in for a C compiler run by make
+    let dir = tempfile::tempdir().unwrap();
+    let cc = dir.path().join("cc");
+    std::fs::write(&cc, "#!/bin/sh\nsleep 0.5\n").unwrap();
+    std::fs::set_permissions(&cc, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = peak_mem()
+        .args(["--compile-units", "--json", "--interval", "20", "--"])
+        .arg(&cc)
+        .args(["-O2", "-o", "demo.o", "-c", "demo.c"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let units = json["translation_units"].as_array().unwrap();
+    assert_eq!(units.len(), 1);
+    assert_eq!(units[0]["name"], "demo.c");
+    assert!(json.get("crates").is_none());
+}
+
+#[test]
+fn prom_file_writes_textfile_metrics() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("run.prom");
+
+    peak_mem()
+        .arg("--prom-file")
+        .arg(&path)
+        .args(["--quiet", "--", "sh", "-c", "exit 3"])
+        .assert()
+        .code(3);
+
+    let metrics = std::fs::read_to_string(&path).unwrap();
+    assert!(metrics.contains("# TYPE peak_mem_peak_rss_bytes gauge"));
+    assert!(metrics.contains("peak_mem_exit_code{command=\"sh -c exit 3\"} 3\n"));
+    assert!(!dir.path().join("run.prom.tmp").exists());
+}
+
+#[test]
+fn influx_outputs_line_protocol() {
+    let output = peak_mem()
+        .args(["--influx", "--", "sleep", "0.3"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("peak_mem_sample,command=sleep\\ 0.3 rss_bytes="));
+    let last = stdout.lines().last().unwrap();
+    assert!(last.starts_with("peak_mem,command=sleep\\ 0.3 peak_rss_bytes="));
+    assert!(last.contains(",exit_code=0i "));
+
+    // The timeline kept for the file isn't added to the JSON output
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("run.lp");
+    let output = peak_mem()
+        .arg("--influx-file")
+        .arg(&path)
+        .args(["--json", "--", "sleep", "0.3"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(json.get("timeline").is_none());
+    let lines = std::fs::read_to_string(&path).unwrap();
+    assert!(lines.contains("peak_mem_sample,"));
+}
+
+#[test]
+fn tap_reports_each_check() {
+    peak_mem()
+        .args([
+            "--tap",
+            "--threshold",
+            "1K",
+            "--",
+            "sh",
+            "-c",
+            "sleep 0.2; exit 2",
+        ])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::starts_with("TAP version 13\n1..2\n"))
+        .stdout(predicate::str::contains(
+            "not ok 1 - sh -c sleep 0.2; exit 2 exited with status 2\n",
+        ))
+        .stdout(predicate::str::contains("not ok 2 - peak RSS "));
+
+    let dir = tempfile::tempdir().unwrap();
+    let dir_arg = dir.path().to_str().unwrap();
+    peak_mem()
+        .args([
+            "--baseline-dir",
+            dir_arg,
+            "--save-baseline",
+            "ci",
+            "--quiet",
+        ])
+        .args(["--", "sleep", "0.1"])
+        .assert()
+        .success();
+    peak_mem()
+        .args([
+            "--baseline-dir",
+            dir_arg,
+            "--compare-baseline",
+            "ci",
+            "--tap",
+        ])
+        .args(["--regression-threshold=-1000", "--", "sleep", "0.1"])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains(
+            "ok 1 - sleep 0.1 exited with status 0\n",
+        ))
+        .stdout(predicate::str::contains(
+            "not ok 2 - regression against baseline ci in peak RSS",
+        ));
+}
+
+#[test]
+fn badge_turns_red_on_regression() {
+    let dir = tempfile::tempdir().unwrap();
+    let badge = dir.path().join("badge.svg");
+
+    peak_mem()
+        .args(["--baseline-dir", dir.path().to_str().unwrap()])
+        .args(["--save-baseline", "ci", "--quiet", "--", "sleep", "0.1"])
+        .assert()
+        .success();
+    peak_mem()
+        .args(["--baseline-dir", dir.path().to_str().unwrap()])
+        .args(["--compare-baseline", "ci", "--regression-threshold=-1000"])
+        .arg("--badge")
+        .arg(&badge)
+        .args(["--quiet", "--", "sleep", "0.1"])
+        .assert()
+        .code(1);
+
+    let svg = std::fs::read_to_string(&badge).unwrap();
+    assert!(svg.contains("peak mem: "));
+    assert!(svg.contains(r##"fill="#e05d44""##));
+}
+
+/// Stands in for an HTTP service, answering one request with 200 and
+/// returning it.
+fn accept_one_request() -> (std::net::SocketAddr, std::thread::JoinHandle<String>) {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length: usize = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Content-Length: "))
+                    .unwrap()
+                    .parse()
+                    .unwrap();
+                if n == 0 || body.len() >= length {
+                    break;
+                }
+            }
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+        String::from_utf8(request).unwrap()
+    });
+    (addr, server)
+}
+
+#[test]
+fn results_are_pushed_to_pushgateway() {
+    let (addr, gateway) = accept_one_request();
+
+    peak_mem()
+        .args(["--pushgateway", &format!("http://{addr}")])
+        .args(["--push-job", "ci", "--push-instance", "runner-1"])
+        .args(["--quiet", "--", "true"])
+        .assert()
+        .success()
+        .stderr("");
+
+    let request = gateway.join().unwrap();
+    assert!(request.starts_with("PUT /metrics/job/ci/instance/runner-1 HTTP/1.1\r\n"));
+    assert!(request.contains("peak_mem_peak_rss_bytes{command=\"true\"} "));
+}
+
+#[test]
+fn once_takes_a_single_sample_of_a_running_process() {
+    let mut sleeper = std::process::Command::new("sleep")
+        .arg("10")
+        .spawn()
+        .unwrap();
+    let pid = sleeper.id().to_string();
+
+    let output = peak_mem()
+        .args(["--once", "--pid", &pid, "--json", "--verbose"])
+        .timeout(std::time::Duration::from_secs(5))
+        .output()
+        .unwrap();
+    sleeper.kill().unwrap();
+    sleeper.wait().unwrap();
+    assert!(output.status.success());
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["command"], format!("pid {pid}"));
+    assert_eq!(json["sample_count"], 1);
+    assert!(json["peak_rss_bytes"].as_u64().unwrap() > 0);
+}
+
+#[test]
+fn sample_subcommand_samples_once() {
+    let mut sleeper = std::process::Command::new("sleep")
+        .arg("10")
+        .spawn()
+        .unwrap();
+    let pid = sleeper.id().to_string();
+
+    let output = peak_mem()
+        .args(["sample", &pid, "--json", "--verbose"])
+        .timeout(std::time::Duration::from_secs(5))
+        .output()
+        .unwrap();
+    sleeper.kill().unwrap();
+    sleeper.wait().unwrap();
+    assert!(output.status.success());
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["command"], format!("pid {pid}"));
+    assert_eq!(json["sample_count"], 1);
+}
+
+#[test]
+fn aggregate_is_recorded_in_json() {
+    let output = peak_mem()
+        .args(["--aggregate", "max-of-processes", "--json", "--"])
+        .args(["sh", "-c", "sleep 0.2 & sleep 0.2; wait"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["aggregate"], "max-of-processes");
+
+    // The default isn't recorded
+    let output = peak_mem().args(["--json", "--", "true"]).output().unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(json.get("aggregate").is_none());
+
+    peak_mem()
+        .args(["--aggregate", "root-only-plus-named:", "--", "true"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("needs process names"));
+}
+
+#[test]
+fn otlp_export_sends_timeline_and_peak() {
+    let (addr, collector) = accept_one_request();
+
+    let output = peak_mem()
+        .args(["--otlp-endpoint", &format!("http://{addr}")])
+        .args(["--json", "--", "sleep", "0.3"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    // The timeline is only kept for the export
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(json.get("timeline").is_none());
+
+    let request = collector.join().unwrap();
+    assert!(request.starts_with("POST /v1/metrics HTTP/1.1\r\n"));
+    let (_, body) = request.split_once("\r\n\r\n").unwrap();
+    let payload: serde_json::Value = serde_json::from_str(body).unwrap();
+    let resource = &payload["resourceMetrics"][0];
+    assert!(resource["resource"]["attributes"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!({
+            "key": "process.command_line",
+            "value": { "stringValue": "sleep 0.3" }
+        })));
+    let metrics = resource["scopeMetrics"][0]["metrics"].as_array().unwrap();
+    let names: Vec<_> = metrics
+        .iter()
+        .map(|m| m["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["peak_mem.rss", "peak_mem.vsz", "peak_mem.peak_rss"]);
+    assert!(!metrics[0]["gauge"]["dataPoints"]
+        .as_array()
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn pool_reports_steady_state_separately() {
+    let output = peak_mem()
+        .args(["--pool-process", "^sleep$", "--pool-steady-after", "200ms"])
+        .args(["--json", "--interval", "20", "--"])
+        .args(["sh", "-c", "sleep 0.8 & sleep 0.8 & wait"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let pool = &json["pool"];
+    assert_eq!(pool["pattern"], "^sleep$");
+    assert_eq!(pool["steady_after_ms"], 200);
+    assert_eq!(pool["workers"], 2);
+    assert!(pool["steady_peak_rss_bytes"].as_u64().unwrap() > 0);
+
+    peak_mem()
+        .args(["--pool-process", "(", "--", "true"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid pattern"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sandbox_cuts_off_the_network_and_writes_outside_the_working_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    let script = "touch /etc/peak-mem-sandbox && exit 1; \
+                  tail -n +3 /proc/net/dev | grep -v '^ *lo:' && exit 2; \
+                  ls -A /run /var/run 2>/dev/null | grep -v ':$' | grep . && exit 3; \
+                  echo ok > written && cat written >&2";
+    let output = peak_mem()
+        .current_dir(dir.path())
+        .args(["--sandbox", "--json", "--", "sh", "-c", script])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("Failed to set up the sandbox (creating namespaces)") {
+        eprintln!("Unprivileged user namespaces are turned off; skipping");
+        return;
+    }
+    assert!(output.status.success(), "{stderr}");
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(json["peak_rss_bytes"].as_u64().unwrap() > 0);
+    assert!(dir.path().join("written").exists());
+    assert!(!std::path::Path::new("/etc/peak-mem-sandbox").exists());
+}
+
+#[test]
+fn self_memory_cap_moves_the_timeline_to_disk_without_losing_samples() {
+    let dir = tempfile::tempdir().unwrap();
+    let timeline = dir.path().join("timeline.json");
+
+    // No peak-mem fits in a kilobyte, so every sample is spilled
+    let output = peak_mem()
+        .args([
+            "--self-memory-cap",
+            "1K",
+            "--json",
+            "--verbose",
+            "--interval",
+            "50",
+        ])
+        .arg("--timeline")
+        .arg(&timeline)
+        .args(["--", "sleep", "0.5"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("over its --self-memory-cap of 1000 B"));
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let samples: Vec<serde_json::Value> =
+        serde_json::from_str(&std::fs::read_to_string(&timeline).unwrap()).unwrap();
+    assert_eq!(samples.len() as u64, json["sample_count"].as_u64().unwrap());
+    assert!(samples
+        .iter()
+        .all(|sample| sample["rss_bytes"].as_u64() > Some(0)));
+}
+
+#[test]
+fn archive_keeps_each_run_under_its_command() {
+    let dir = tempfile::tempdir().unwrap();
+    for command in [["echo", "a"], ["echo", "a"], ["echo", "b"]] {
+        peak_mem()
+            .arg("--archive")
+            .arg(dir.path())
+            .arg("--")
+            .args(command)
+            .assert()
+            .success();
+    }
+
+    let mut commands: Vec<usize> = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| std::fs::read_dir(entry.unwrap().path()).unwrap().count())
+        .collect();
+    commands.sort();
+    assert_eq!(commands, vec![1, 2]);
+
+    let runs = std::fs::read_dir(dir.path()).unwrap().flat_map(|entry| {
+        std::fs::read_dir(entry.unwrap().path())
+            .unwrap()
+            .map(|run| run.unwrap().path())
+    });
+    for run in runs {
+        assert!(run.extension().is_some_and(|extension| extension == "json"));
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&run).unwrap()).unwrap();
+        assert!(json["peak_rss_bytes"].as_u64().is_some());
+    }
+}
+
+#[test]
+fn color_follows_the_flag_and_no_color() {
+    let red_threshold = "\u{1b}[31m⚠️  THRESHOLD EXCEEDED\u{1b}[0m";
+    peak_mem()
+        .args([
+            "--color",
+            "always",
+            "--threshold",
+            "1",
+            "--",
+            "sleep",
+            "0.2",
+        ])
+        .assert()
+        .stdout(predicate::str::contains(red_threshold));
+
+    // Not on a terminal, or told not to
+    peak_mem()
+        .args(["--threshold", "1", "--", "sleep", "0.2"])
+        .assert()
+        .stdout(predicate::str::contains("THRESHOLD EXCEEDED"))
+        .stdout(predicate::str::contains("\u{1b}[").not());
+    // Asking for color outweighs NO_COLOR
+    peak_mem()
+        .env("NO_COLOR", "1")
+        .args(["--color", "always", "--", "true"])
+        .assert()
+        .stdout(predicate::str::contains("\u{1b}[2mCommand: true"));
+    peak_mem()
+        .args(["--no-color", "--color", "always", "--", "true"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn timeline_delta_keeps_only_the_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let timeline = dir.path().join("timeline.json");
+
+    // Nothing moves a gigabyte, so only the first and latest samples stay
+    let output = peak_mem()
+        .args(["--timeline-delta", "1G", "--interval", "20", "--json"])
+        .arg("--timeline")
+        .arg(&timeline)
+        .args(["--", "sleep", "0.4"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let file: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&timeline).unwrap()).unwrap();
+    assert_eq!(file["samples"].as_array().unwrap().len(), 2);
+    let delta = &file["delta"];
+    assert_eq!(delta["delta_bytes"], 1_000_000_000);
+    assert_eq!(delta["kept_samples"], 2);
+    assert!(delta["original_samples"].as_u64().unwrap() > 2);
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["timeline_delta"], *delta);
+    assert!(json["peak_rss_bytes"].as_u64().unwrap() > 0);
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn history_db_records_runs_for_querying() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("history.db");
+    for (command, profile) in [("a", "release"), ("b", "debug"), ("a", "debug")] {
+        peak_mem()
+            .arg("--history-db")
+            .arg(&db)
+            .args(["--meta", &format!("profile={profile}"), "--quiet"])
+            .args(["--", "echo", command])
+            .assert()
+            .success();
+    }
+
+    let query = |args: &[&str]| {
+        let output = peak_mem()
+            .args(["history", "query", "--history-db"])
+            .arg(&db)
+            .args(args)
+            .args(["--json"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()
+    };
+    assert_eq!(query(&[]).as_array().unwrap().len(), 3);
+    let runs = query(&["--history-command", "echo a", "--meta", "profile=debug"]);
+    assert_eq!(runs.as_array().unwrap().len(), 1);
+    assert_eq!(runs[0]["command"], "echo a");
+    assert_eq!(runs[0]["metadata"]["profile"], "debug");
+    assert!(runs[0]["peak_rss_bytes"].as_u64().is_some());
+    assert_eq!(query(&["--until", "2000-01-01"]), serde_json::json!([]));
+
+    peak_mem()
+        .args(["history", "query", "--history-db"])
+        .arg(&db)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("echo b"))
+        .stdout(predicate::str::contains("3 runs"));
+    peak_mem()
+        .args(["--query-history", "--history-db"])
+        .arg(dir.path().join("missing.db"))
+        .assert()
+        .failure();
+}
+
+#[cfg(not(feature = "sqlite"))]
+#[test]
+fn history_db_needs_the_sqlite_feature() {
+    peak_mem()
+        .args(["--history-db", "history.db", "--", "true"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("sqlite feature"));
 }